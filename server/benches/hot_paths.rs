@@ -0,0 +1,108 @@
+//! Benchmarks for the code that runs on most requests. Apart from markdown rendering they need a
+//! database, fill it with `cargo run --release --bin lemmy_seed` first.
+use actix_rt::{System, SystemRunner};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  PgConnection,
+};
+use lemmy_db::{
+  comment_view::CommentQueryBuilder,
+  community::Community,
+  get_database_url_from_env,
+  post::Post,
+  post_view::PostQueryBuilder,
+  user::User_,
+  Crud,
+  DbPool,
+  SortType,
+};
+use lemmy_server::apub::ToApub;
+use lemmy_utils::utils::markdown_to_html;
+
+static MARKDOWN: &str = include_str!("../../docs/src/about_ranking.md");
+
+fn pool() -> DbPool {
+  let db_url = get_database_url_from_env().expect("LEMMY_DATABASE_URL has to be set");
+  Pool::builder()
+    .max_size(1)
+    .build(ConnectionManager::<PgConnection>::new(&db_url))
+    .expect("Error connecting to the database")
+}
+
+/// The most commented post of the seeded data, for a thread as large as it gets.
+fn biggest_post(conn: &PgConnection) -> Post {
+  let sort = SortType::TopAll;
+  let post_view = PostQueryBuilder::create(conn)
+    .sort(&sort)
+    .limit(50)
+    .list()
+    .unwrap()
+    .into_iter()
+    .max_by_key(|p| p.number_of_comments)
+    .expect("The database is empty, run lemmy_seed first");
+  Post::read(conn, post_view.id).unwrap()
+}
+
+fn markdown(c: &mut Criterion) {
+  c.bench_function("markdown_to_html", |b| {
+    b.iter(|| markdown_to_html(black_box(MARKDOWN)))
+  });
+}
+
+fn listings(c: &mut Criterion) {
+  let pool = pool();
+  let conn = pool.get().unwrap();
+  let post = biggest_post(&conn);
+
+  let mut group = c.benchmark_group("listings");
+  for sort in &[SortType::Hot, SortType::New, SortType::TopWeek] {
+    group.bench_function(format!("posts_{:?}", sort), |b| {
+      b.iter(|| PostQueryBuilder::create(&conn).sort(sort).list().unwrap())
+    });
+  }
+  let sort = SortType::New;
+  group.bench_function("comment_thread", |b| {
+    b.iter(|| {
+      CommentQueryBuilder::create(&conn)
+        .sort(&sort)
+        .for_post_id(post.id)
+        .limit(9999)
+        .list()
+        .unwrap()
+    })
+  });
+  group.finish();
+}
+
+fn apub(c: &mut Criterion) {
+  // block_on only takes 'static futures, so the data is leaked, it's needed until the end anyway
+  let pool: &'static DbPool = Box::leak(Box::new(pool()));
+  let conn = pool.get().unwrap();
+  let post: &'static Post = Box::leak(Box::new(biggest_post(&conn)));
+  let community: &'static Community =
+    Box::leak(Box::new(Community::read(&conn, post.community_id).unwrap()));
+  let user: &'static User_ = Box::leak(Box::new(User_::read(&conn, post.creator_id).unwrap()));
+  drop(conn);
+  let mut system: SystemRunner = System::new("bench");
+
+  let mut group = c.benchmark_group("apub");
+  group.bench_function("post_to_apub", |b| {
+    b.iter(|| system.block_on(post.to_apub(pool)).unwrap())
+  });
+  group.bench_function("community_to_apub", |b| {
+    b.iter(|| system.block_on(community.to_apub(pool)).unwrap())
+  });
+  let page = system.block_on(post.to_apub(pool)).unwrap();
+  group.bench_function("post_serialize", |b| {
+    b.iter(|| serde_json::to_string(black_box(&page)).unwrap())
+  });
+  let person = system.block_on(user.to_apub(pool)).unwrap();
+  group.bench_function("user_serialize", |b| {
+    b.iter(|| serde_json::to_string(black_box(&person)).unwrap())
+  });
+  group.finish();
+}
+
+criterion_group!(benches, markdown, listings, apub);
+criterion_main!(benches);
@@ -1,4 +1,7 @@
-use lemmy_db::comment_view::CommentView;
+use lemmy_db::{
+  comment_view::{CollapsibleCommentView, CommentView},
+  post_view::PostView,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
@@ -30,6 +33,28 @@ pub struct RemoveComment {
   pub edit_id: i32,
   pub removed: bool,
   pub reason: Option<String>,
+  pub reason_id: Option<i32>,
+  pub notify_author: Option<bool>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DistinguishComment {
+  pub edit_id: i32,
+  pub distinguished: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct PinComment {
+  pub edit_id: i32,
+  pub pinned: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct ApproveMentions {
+  pub comment_id: i32,
   pub auth: String,
 }
 
@@ -65,13 +90,38 @@ pub struct CreateCommentLike {
 pub struct GetComments {
   pub type_: String,
   pub sort: String,
+  pub comment_sort: Option<String>,
   pub page: Option<i64>,
   pub limit: Option<i64>,
   pub community_id: Option<i32>,
+  pub post_id: Option<i32>,
   pub auth: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct GetCommentsResponse {
-  pub comments: Vec<CommentView>,
+  pub comments: Vec<CollapsibleCommentView>,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommentContext {
+  pub comment_id: i32,
+  /// How many parent comments, 3 by default.
+  pub context: Option<i64>,
+  /// How many levels of replies, 3 by default.
+  pub depth: Option<i64>,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GetCommentContextResponse {
+  pub comment: CommentView,
+  /// From the top one down to the parent of the comment.
+  pub ancestors: Vec<CollapsibleCommentView>,
+  pub descendants: Vec<CollapsibleCommentView>,
+  pub post: PostView,
+  /// How often the comment was viewed through its context.
+  pub views: i64,
+  /// Estimated, within a few percent
+  pub unique_views: i64,
 }
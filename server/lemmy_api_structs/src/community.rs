@@ -1,6 +1,20 @@
 use lemmy_db::{
+  community::{CommunityAppearance, CommunitySettings},
+  community_digest::CommunityDigestSubscription,
+  community_join_request::{CommunityJoinRequest, CommunityJoinRequestView},
+  community_notification::{CommunityNotification, CommunityNotificationPost},
+  community_post_tag::CommunityPostTag,
+  community_stats::{CommunityContributor, CommunityStatsDaily},
   community_view::{CommunityFollowerView, CommunityModeratorView, CommunityView},
+  content_report::ContentReport,
+  moderator_views::ModBulkActionView,
+  post_view::PostView,
+  removal_reason::RemovalReason,
+  scheduled_post::ScheduledPost,
+  spam_score::SpamScore,
   user_view::UserView,
+  vote_burst::VoteBurst,
+  vote_instance::VoteOrigin,
 };
 use serde::{Deserialize, Serialize};
 
@@ -15,6 +29,9 @@ pub struct GetCommunity {
 pub struct GetCommunityResponse {
   pub community: CommunityView,
   pub moderators: Vec<CommunityModeratorView>,
+  pub settings: CommunitySettings,
+  pub appearance: CommunityAppearance,
+  pub join_request: Option<CommunityJoinRequest>,
   pub online: usize,
 }
 
@@ -48,6 +65,13 @@ pub struct ListCommunitiesResponse {
   pub communities: Vec<CommunityView>,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct GetRecommendedCommunities {
+  pub category_ids: Option<String>,
+  pub limit: Option<i64>,
+  pub auth: Option<String>,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct BanFromCommunity {
   pub community_id: i32,
@@ -87,6 +111,39 @@ pub struct EditCommunity {
   pub banner: Option<String>,
   pub category_id: i32,
   pub nsfw: bool,
+  pub require_alt_text: Option<bool>,
+  pub trust_min_account_age_days: Option<i32>,
+  pub trust_min_karma: Option<i32>,
+  pub allow_anonymous_posts: Option<bool>,
+  pub join_requires_approval: Option<bool>,
+  pub join_question: Option<String>,
+  pub private: Option<bool>,
+  pub local_only: Option<bool>,
+  pub allow_authors_close_comments: Option<bool>,
+  pub automod_comment: Option<String>,
+  pub welcome_message: Option<String>,
+  /// Lower than the instance limit, negative for the instance one
+  pub max_post_body_length: Option<i32>,
+  pub max_comment_length: Option<i32>,
+  /// A color like `#1e90ff`, or empty for none
+  pub accent_color: Option<String>,
+  /// A sort type, or empty for none
+  pub default_sort: Option<String>,
+  /// any, text_only or link_only
+  pub allowed_post_type: Option<String>,
+  pub require_post_tag: Option<bool>,
+  /// A regular expression for the titles of new posts, or empty for none
+  pub title_pattern: Option<String>,
+  pub clean_titles: Option<bool>,
+  /// none, capitalize_first or no_all_caps
+  pub title_capitalization: Option<String>,
+  pub allow_post_url_edits: Option<bool>,
+  /// ignore, post or post_or_comment
+  pub microblog_mentions: Option<String>,
+  /// The text of the weekly digest emails, or empty for the default one
+  pub digest_template: Option<String>,
+  /// Opt out of search, here and on other instances
+  pub discoverable: Option<bool>,
   pub auth: String,
 }
 
@@ -110,9 +167,214 @@ pub struct RemoveCommunity {
 pub struct FollowCommunity {
   pub community_id: i32,
   pub follow: bool,
+  pub application: Option<String>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityJoinRequests {
+  pub community_id: i32,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewCommunityJoinRequest {
+  pub join_request_id: i32,
+  pub approve: bool,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct CommunityJoinRequestsResponse {
+  pub join_requests: Vec<CommunityJoinRequestView>,
+}
+
+#[derive(Deserialize)]
+pub struct GetModInbox {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetModInboxResponse {
+  pub join_requests: Vec<CommunityJoinRequestView>,
+  pub reports: Vec<SpamScore>,
+  pub user_reports: Vec<ContentReport>,
+  pub vote_bursts: Vec<VoteBurst>,
+}
+
+/// Either the post or the comment is reported.
+#[derive(Deserialize)]
+pub struct CreateReport {
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: String,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveReport {
+  pub report_id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ReportResponse {
+  pub report: ContentReport,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveVoteBurst {
+  pub vote_burst_id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct VoteBurstResponse {
+  pub vote_burst: VoteBurst,
+}
+
+/// The votes of either the post or the comment, by the instance of the voters.
+#[derive(Deserialize)]
+pub struct GetVoteOrigins {
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetVoteOriginsResponse {
+  pub origins: Vec<VoteOrigin>,
+}
+
+#[derive(Deserialize)]
+pub struct GetModInboxCounts {
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ModInboxCountsResponse {
+  pub join_requests: i64,
+  pub reports: i64,
+  pub user_reports: i64,
+  pub vote_bursts: i64,
+  /// Posts linking to greylisted domains
+  pub pending_posts: i64,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityMembers {
+  pub community_id: i32,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveCommunityMember {
+  pub community_id: i32,
+  pub user_id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct CommunityMembersResponse {
+  pub members: Vec<CommunityFollowerView>,
+}
+
+#[derive(Deserialize)]
+pub struct GetRemovalReasons {
+  pub community_id: i32,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRemovalReason {
+  pub community_id: i32,
+  pub title: String,
+  pub reason: String,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteRemovalReason {
+  pub removal_reason_id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct RemovalReasonsResponse {
+  pub removal_reasons: Vec<RemovalReason>,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityPostTags {
+  pub community_id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCommunityPostTag {
+  pub community_id: i32,
+  pub name: String,
   pub auth: String,
 }
 
+#[derive(Deserialize)]
+pub struct DeleteCommunityPostTag {
+  pub tag_id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct CommunityPostTagsResponse {
+  pub tags: Vec<CommunityPostTag>,
+}
+
+#[derive(Deserialize)]
+pub struct BulkRemoveFromCommunity {
+  pub community_id: i32,
+  pub user_id: i32,
+  pub since: Option<i64>,
+  pub until: Option<i64>,
+  pub reason: Option<String>,
+  pub reason_id: Option<i32>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct BulkBanFromCommunity {
+  pub community_id: i32,
+  pub user_ids: Vec<i32>,
+  pub reason: Option<String>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetModBulkAction {
+  pub id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ModBulkActionResponse {
+  pub bulk_action: ModBulkActionView,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityStats {
+  pub community_id: i32,
+  pub since: Option<i64>,
+  pub until: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetCommunityStatsResponse {
+  pub community: CommunityView,
+  pub days: Vec<CommunityStatsDaily>,
+  pub top_contributors: Vec<CommunityContributor>,
+  /// Of all time, whatever the range
+  pub views: i64,
+  pub unique_views: i64,
+}
+
 #[derive(Deserialize)]
 pub struct GetFollowedCommunities {
   pub auth: String,
@@ -123,9 +385,109 @@ pub struct GetFollowedCommunitiesResponse {
   pub communities: Vec<CommunityFollowerView>,
 }
 
+#[derive(Deserialize)]
+pub struct SubscribeCommunityNotifications {
+  pub community_id: i32,
+  pub subscribe: bool,
+  pub mod_posts_only: bool,
+  pub send_email: bool,
+  pub send_push: Option<bool>,
+  pub email_digest: Option<bool>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct CommunityNotificationResponse {
+  pub community_notification: Option<CommunityNotification>,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityNotifications {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetCommunityNotificationsResponse {
+  pub community_notifications: Vec<CommunityNotification>,
+}
+
+#[derive(Deserialize)]
+pub struct SubscribeCommunityDigest {
+  pub community_id: i32,
+  pub subscribe: bool,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct CommunityDigestResponse {
+  pub community_digest: Option<CommunityDigestSubscription>,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityDigests {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetCommunityDigestsResponse {
+  pub community_digests: Vec<CommunityDigestSubscription>,
+}
+
+#[derive(Deserialize)]
+pub struct GetCommunityNotificationPosts {
+  pub unread_only: bool,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct GetCommunityNotificationPostsResponse {
+  pub notifications: Vec<CommunityNotificationPost>,
+  pub posts: Vec<PostView>,
+}
+
 #[derive(Deserialize)]
 pub struct TransferCommunity {
   pub community_id: i32,
   pub user_id: i32,
   pub auth: String,
 }
+
+#[derive(Deserialize)]
+pub struct ListScheduledPosts {
+  pub community_id: i32,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateScheduledPost {
+  pub community_id: i32,
+  pub title_template: String,
+  pub body: Option<String>,
+  pub schedule: String,
+  pub stickied: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct EditScheduledPost {
+  pub id: i32,
+  pub title_template: String,
+  pub body: Option<String>,
+  pub schedule: String,
+  pub stickied: bool,
+  pub enabled: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteScheduledPost {
+  pub id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ScheduledPostsResponse {
+  pub scheduled_posts: Vec<ScheduledPost>,
+}
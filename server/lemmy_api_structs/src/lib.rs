@@ -10,14 +10,25 @@ pub mod site;
 pub mod user;
 
 use lemmy_db::comment::Comment;
+use lemmy_db::email_outbox::{EmailOutbox, EmailOutboxForm};
+use lemmy_db::community::{hidden_private_communities, Community, CommunitySettings};
+use lemmy_db::community_notification::{
+  CommunityNotification,
+  CommunityNotificationPost,
+  CommunityNotificationPostForm,
+};
 use lemmy_db::user::User_;
 use lemmy_db::post::Post;
 use lemmy_db::user_mention::{UserMentionForm, UserMention};
+use lemmy_db::saved_search::{SavedSearch, SavedSearchMatch, SavedSearchMatchForm};
+use lemmy_db::spam_score::{SpamScore, SpamScoreForm, HELD_MENTIONS_REASON};
+use lemmy_db::thread_mute::ThreadMute;
+use lemmy_db::trust::{TrustThresholds, UserTrust};
 use log::error;
 use lemmy_db::{Crud, DbPool};
-use lemmy_utils::utils::MentionData;
-use lemmy_utils::settings::Settings;
-use lemmy_utils::email::send_email;
+use lemmy_utils::utils::{search_keywords, MentionData};
+use lemmy_utils::settings::{MentionLimitAction, Settings};
+use lemmy_utils::email::{reply_address, ReplyTarget};
 use diesel::PgConnection;
 use lemmy_utils::LemmyError;
 
@@ -37,6 +48,31 @@ pub async fn blocking<F, T>(pool: &DbPool, f: F) -> Result<T, LemmyError>
   Ok(res)
 }
 
+/// Adds the email to the outbox, which sends it in the background and retries it if it fails.
+pub fn queue_email(
+  conn: &PgConnection,
+  subject: &str,
+  to_email: &str,
+  to_username: &str,
+  html: &str,
+  reply_to: Option<String>,
+) {
+  if Settings::get().email.is_none() {
+    error!("no_email_setup");
+    return;
+  }
+  let form = EmailOutboxForm {
+    to_email: to_email.to_owned(),
+    to_name: to_username.to_owned(),
+    subject: subject.to_owned(),
+    html: html.to_owned(),
+    reply_to,
+  };
+  if let Err(e) = EmailOutbox::enqueue(conn, &form) {
+    error!("{}", e);
+  }
+}
+
 pub async fn send_local_notifs(
   mentions: Vec<MentionData>,
   comment: Comment,
@@ -47,13 +83,128 @@ pub async fn send_local_notifs(
 ) -> Result<Vec<i32>, LemmyError> {
   let user2 = user.clone();
   let ids = blocking(pool, move |conn| {
-    do_send_local_notifs(conn, &mentions, &comment, &user2, &post, do_send_email)
+    let allowed = limit_mentions(conn, mentions, &user2, post.community_id);
+    if allowed.needs_approval {
+      report_held_mentions(conn, &comment);
+    }
+    do_send_local_notifs(conn, &allowed.mentions, &comment, &user2, &post, do_send_email)
   })
     .await?;
 
   Ok(ids)
 }
 
+/// Sends the mention notifications of a comment whose mentions were held back for approval.
+pub async fn send_approved_mentions(
+  mentions: Vec<MentionData>,
+  comment: Comment,
+  user: &User_,
+  pool: &DbPool,
+) -> Result<Vec<i32>, LemmyError> {
+  let user2 = user.clone();
+  let ids = blocking(pool, move |conn| {
+    let muted = muted_user_ids(conn, &comment);
+    do_send_mention_notifs(conn, &mentions, &comment, &user2, &muted, true)
+  })
+    .await?;
+
+  Ok(ids)
+}
+
+pub struct AllowedMentions {
+  pub mentions: Vec<MentionData>,
+  /// The mentions were over the limit, and nobody is notified until an admin approves them
+  pub needs_approval: bool,
+}
+
+/// Trusted users skip spam scoring, the post limit of new users, the approval of greylisted domains
+/// and the mention limit. Admins are always trusted, and so are the mods of the given community.
+/// Otherwise it depends on the instance trust config, and on the overrides of the community.
+pub fn is_trusted(
+  conn: &PgConnection,
+  user: &User_,
+  community_id: Option<i32>,
+) -> Result<bool, diesel::result::Error> {
+  if user.admin {
+    return Ok(true);
+  }
+
+  let config = Settings::get().trust;
+  let instance_thresholds = TrustThresholds {
+    min_account_age_days: config.min_account_age_days,
+    min_karma: config.min_karma,
+    max_mod_actions: config.max_mod_actions,
+  };
+  let thresholds = match community_id {
+    Some(community_id) => {
+      if Community::is_mod_or_admin(conn, user.id, community_id) {
+        return Ok(true);
+      }
+      let settings = CommunitySettings::read_for_community(conn, community_id)?;
+      instance_thresholds.for_community(&settings)
+    }
+    None => instance_thresholds,
+  };
+  Ok(UserTrust::read(conn, user.id)?.is_trusted(&thresholds))
+}
+
+/// Applies the mention limit of the config, to prevent notification spam. Trusted users, which
+/// includes mods and admins in the communities they moderate, can mention as many users as they
+/// want.
+pub fn limit_mentions(
+  conn: &PgConnection,
+  mentions: Vec<MentionData>,
+  creator: &User_,
+  community_id: i32,
+) -> AllowedMentions {
+  let config = Settings::get().mentions;
+  if mentions.len() <= config.max_mentions
+    || is_trusted(conn, creator, Some(community_id)).unwrap_or(false)
+  {
+    return AllowedMentions { mentions, needs_approval: false };
+  }
+
+  match config.over_limit {
+    MentionLimitAction::Truncate => AllowedMentions {
+      mentions: mentions.into_iter().take(config.max_mentions).collect(),
+      needs_approval: false,
+    },
+    MentionLimitAction::Approve => AllowedMentions {
+      mentions: Vec::new(),
+      needs_approval: true,
+    },
+  }
+}
+
+/// Puts the comment into the admins' spam list, where they can approve the mentions.
+fn report_held_mentions(conn: &PgConnection, comment: &Comment) {
+  let form = SpamScoreForm {
+    post_id: None,
+    comment_id: Some(comment.id),
+    private_message_id: None,
+    creator_id: comment.creator_id,
+    score: 0.0,
+    reasons: vec![HELD_MENTIONS_REASON.to_string()],
+    content_hash: SpamScore::hash_content(&comment.content),
+    reported: true,
+    removed: false,
+  };
+  if let Err(e) = SpamScore::create(&conn, &form) {
+    error!("{}", e);
+  }
+}
+
+/// The users who muted the thread of the comment, and get neither reply nor mention notifications.
+fn muted_user_ids(conn: &PgConnection, comment: &Comment) -> Vec<i32> {
+  match ThreadMute::muted_user_ids(&conn, comment) {
+    Ok(ids) => ids,
+    Err(e) => {
+      error!("{}", e);
+      Vec::new()
+    }
+  }
+}
+
 fn do_send_local_notifs(
   conn: &PgConnection,
   mentions: &[MentionData],
@@ -61,6 +212,66 @@ fn do_send_local_notifs(
   user: &User_,
   post: &Post,
   do_send_email: bool,
+) -> Vec<i32> {
+  let muted = muted_user_ids(conn, comment);
+  let mut recipient_ids =
+    do_send_mention_notifs(conn, mentions, comment, user, &muted, do_send_email);
+  let hostname = &format!("https://{}", Settings::get().hostname);
+
+  // Send notifs to the parent commenter / poster
+  match comment.parent_id {
+    Some(parent_id) => {
+      if let Ok(parent_comment) = Comment::read(&conn, parent_id) {
+        if parent_comment.creator_id != user.id && !muted.contains(&parent_comment.creator_id) {
+          if let Ok(parent_user) = User_::read(&conn, parent_comment.creator_id) {
+            recipient_ids.push(parent_user.id);
+
+            if do_send_email && parent_user.send_notifications_to_email {
+              if let Some(comment_reply_email) = parent_user.email {
+                let subject = &format!("{} - Reply from {}", Settings::get().hostname, user.name,);
+                let html = &format!(
+                  "<h1>Comment Reply</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
+                  user.name, comment.content, hostname
+                );
+                let reply_to = reply_address(ReplyTarget::Comment(comment.id), parent_user.id);
+                queue_email(conn, subject, &comment_reply_email, &parent_user.name, html, reply_to);
+              }
+            }
+          }
+        }
+      }
+    }
+    // Its a post
+    None => {
+      if post.creator_id != user.id && !muted.contains(&post.creator_id) {
+        if let Ok(parent_user) = User_::read(&conn, post.creator_id) {
+          recipient_ids.push(parent_user.id);
+
+          if do_send_email && parent_user.send_notifications_to_email {
+            if let Some(post_reply_email) = parent_user.email {
+              let subject = &format!("{} - Reply from {}", Settings::get().hostname, user.name,);
+              let html = &format!(
+                "<h1>Post Reply</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
+                user.name, comment.content, hostname
+              );
+              let reply_to = reply_address(ReplyTarget::Comment(comment.id), parent_user.id);
+              queue_email(conn, subject, &post_reply_email, &parent_user.name, html, reply_to);
+            }
+          }
+        }
+      }
+    }
+  };
+  recipient_ids
+}
+
+fn do_send_mention_notifs(
+  conn: &PgConnection,
+  mentions: &[MentionData],
+  comment: &Comment,
+  user: &User_,
+  muted: &[i32],
+  do_send_email: bool,
 ) -> Vec<i32> {
   let mut recipient_ids = Vec::new();
   let hostname = &format!("https://{}", Settings::get().hostname);
@@ -72,6 +283,10 @@ fn do_send_local_notifs(
     .collect::<Vec<&MentionData>>()
   {
     if let Ok(mention_user) = User_::read_from_name(&conn, &mention.name) {
+      if muted.contains(&mention_user.id) {
+        continue;
+      }
+
       // TODO
       // At some point, make it so you can't tag the parent creator either
       // This can cause two notifications, one for reply and the other for mention
@@ -98,62 +313,185 @@ fn do_send_local_notifs(
             "<h1>User Mention</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
             user.name, comment.content, hostname
           );
-          match send_email(subject, &mention_email, &mention_user.name, html) {
-            Ok(_o) => _o,
-            Err(e) => error!("{}", e),
-          };
+          let reply_to = reply_address(ReplyTarget::Comment(comment.id), mention_user.id);
+          queue_email(conn, subject, &mention_email, &mention_user.name, html, reply_to);
         }
       }
     }
   }
+  recipient_ids
+}
 
-  // Send notifs to the parent commenter / poster
-  match comment.parent_id {
-    Some(parent_id) => {
-      if let Ok(parent_comment) = Comment::read(&conn, parent_id) {
-        if parent_comment.creator_id != user.id {
-          if let Ok(parent_user) = User_::read(&conn, parent_comment.creator_id) {
-            recipient_ids.push(parent_user.id);
+fn can_see_community(conn: &PgConnection, user_id: i32, community_id: i32) -> bool {
+  match hidden_private_communities(conn, Some(user_id)) {
+    Ok(hidden) => !hidden.contains(&community_id),
+    Err(e) => {
+      error!("{}", e);
+      false
+    }
+  }
+}
 
-            if do_send_email && parent_user.send_notifications_to_email {
-              if let Some(comment_reply_email) = parent_user.email {
-                let subject = &format!("{} - Reply from {}", Settings::get().hostname, user.name,);
-                let html = &format!(
-                  "<h1>Comment Reply</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
-                  user.name, comment.content, hostname
-                );
-                match send_email(subject, &comment_reply_email, &parent_user.name, html) {
-                  Ok(_o) => _o,
-                  Err(e) => error!("{}", e),
-                };
-              }
-            }
+/// Match a new post against everyone's saved searches, and notify the owners of the matches.
+/// Returns the recipient ids along with their new matches, for the websocket.
+pub async fn send_saved_search_notifs(
+  post: Post,
+  pool: &DbPool,
+  do_send_email: bool,
+) -> Result<Vec<(i32, SavedSearchMatch)>, LemmyError> {
+  let matches = blocking(pool, move |conn| {
+    do_send_saved_search_notifs(conn, &post, do_send_email)
+  })
+    .await?;
+
+  Ok(matches)
+}
+
+fn do_send_saved_search_notifs(
+  conn: &PgConnection,
+  post: &Post,
+  do_send_email: bool,
+) -> Vec<(i32, SavedSearchMatch)> {
+  let mut recipients = Vec::new();
+  let hostname = &format!("https://{}", Settings::get().hostname);
+
+  let text = format!("{} {}", post.name, post.body.to_owned().unwrap_or_default());
+  let saved_searches = match SavedSearch::matching_post(
+    &conn,
+    search_keywords(&text),
+    post.community_id,
+    post.creator_id,
+    post.anonymous,
+  ) {
+    Ok(s) => s,
+    Err(e) => {
+      error!("{}", e);
+      return recipients;
+    }
+  };
+
+  for saved_search in saved_searches {
+    if !can_see_community(conn, saved_search.user_id, post.community_id) {
+      continue;
+    }
+
+    let match_form = SavedSearchMatchForm {
+      saved_search_id: saved_search.id,
+      post_id: post.id,
+    };
+
+    // Already matched, eg for a federated post we have seen before
+    let saved_search_match = match SavedSearchMatch::create(&conn, &match_form) {
+      Ok(Some(m)) => m,
+      Ok(None) => continue,
+      Err(e) => {
+        error!("{}", e);
+        continue;
+      }
+    };
+
+    // Several searches of the same user can match, but one email is enough
+    let already_notified = recipients.iter().any(|(id, _)| *id == saved_search.user_id);
+    recipients.push((saved_search.user_id, saved_search_match));
+
+    if do_send_email && !already_notified {
+      if let Ok(search_user) = User_::read(&conn, saved_search.user_id) {
+        if search_user.send_notifications_to_email {
+          if let Some(search_email) = search_user.email {
+            let subject = &format!("{} - New post for \"{}\"", Settings::get().hostname, saved_search.query);
+            let html = &format!(
+              "<h1>Saved Search</h1><br><div>{}</div><br><a href={}/post/{}>link</a>",
+              post.name, hostname, post.id
+            );
+            let reply_to = reply_address(ReplyTarget::Post(post.id), search_user.id);
+            queue_email(conn, subject, &search_email, &search_user.name, html, reply_to);
           }
         }
       }
     }
-    // Its a post
-    None => {
-      if post.creator_id != user.id {
-        if let Ok(parent_user) = User_::read(&conn, post.creator_id) {
-          recipient_ids.push(parent_user.id);
+  }
+  recipients
+}
 
-          if do_send_email && parent_user.send_notifications_to_email {
-            if let Some(post_reply_email) = parent_user.email {
-              let subject = &format!("{} - Reply from {}", Settings::get().hostname, user.name,);
-              let html = &format!(
-                "<h1>Post Reply</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
-                user.name, comment.content, hostname
-              );
-              match send_email(subject, &post_reply_email, &parent_user.name, html) {
-                Ok(_o) => _o,
-                Err(e) => error!("{}", e),
-              };
-            }
-          }
-        }
+/// Notify the users who subscribed to notifications for the community of the new post.
+/// Returns the subscriptions along with their notifications, for the websocket and web push.
+/// Subscribers who want a digest get their emails later, from the digest job.
+pub async fn send_community_notifs(
+  post: Post,
+  pool: &DbPool,
+  do_send_email: bool,
+) -> Result<Vec<(CommunityNotification, CommunityNotificationPost)>, LemmyError> {
+  let notifications = blocking(pool, move |conn| {
+    do_send_community_notifs(conn, &post, do_send_email)
+  })
+    .await?;
+
+  Ok(notifications)
+}
+
+fn do_send_community_notifs(
+  conn: &PgConnection,
+  post: &Post,
+  do_send_email: bool,
+) -> Vec<(CommunityNotification, CommunityNotificationPost)> {
+  let mut recipients = Vec::new();
+  let hostname = &format!("https://{}", Settings::get().hostname);
+
+  // Anonymous posts don't give away that their creator is a mod
+  let by_mod = !post.anonymous && Community::is_mod_or_admin(&conn, post.creator_id, post.community_id);
+  let subscriptions =
+    match CommunityNotification::subscribers_for_post(&conn, post.community_id, post.creator_id, by_mod) {
+      Ok(s) => s,
+      Err(e) => {
+        error!("{}", e);
+        return recipients;
       }
+    };
+  let community_name = match Community::read(&conn, post.community_id) {
+    Ok(c) => c.name,
+    Err(e) => {
+      error!("{}", e);
+      return recipients;
     }
   };
-  recipient_ids
+
+  for subscription in subscriptions {
+    // Removed members of a private community may still be subscribed
+    if !can_see_community(conn, subscription.user_id, post.community_id) {
+      continue;
+    }
+
+    let send_email = do_send_email && subscription.send_email;
+    let notification_form = CommunityNotificationPostForm {
+      user_id: subscription.user_id,
+      post_id: post.id,
+      email_pending: send_email && subscription.email_digest,
+    };
+
+    // Already notified, eg for a federated post we have seen before
+    let notification = match CommunityNotificationPost::create(&conn, &notification_form) {
+      Ok(Some(n)) => n,
+      Ok(None) => continue,
+      Err(e) => {
+        error!("{}", e);
+        continue;
+      }
+    };
+
+    if send_email && !subscription.email_digest {
+      if let Ok(subscriber) = User_::read(&conn, subscription.user_id) {
+        if let Some(subscriber_email) = subscriber.email {
+          let subject = &format!("{} - New post in {}", Settings::get().hostname, community_name);
+          let html = &format!(
+            "<h1>Community Notification</h1><br><div>{}</div><br><a href={}/post/{}>link</a>",
+            post.name, hostname, post.id
+          );
+          let reply_to = reply_address(ReplyTarget::Post(post.id), subscriber.id);
+          queue_email(conn, subject, &subscriber_email, &subscriber.name, html, reply_to);
+        }
+      }
+    }
+    recipients.push((subscription, notification));
+  }
+  recipients
 }
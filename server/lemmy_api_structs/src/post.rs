@@ -1,10 +1,20 @@
 use lemmy_db::{
-  comment_view::CommentView,
+  comment_view::{CollapsibleCommentView, CommentView},
+  community_post_tag::CommunityPostTag,
   community_view::{CommunityModeratorView, CommunityView},
+  post_edit::PostEdit,
+  post_media::PostMedia,
   post_view::PostView,
 };
 use serde::{Deserialize, Serialize};
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct PostMediaItem {
+  pub url: String,
+  pub caption: Option<String>,
+  pub alt_text: Option<String>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CreatePost {
   pub name: String,
@@ -12,6 +22,11 @@ pub struct CreatePost {
   pub body: Option<String>,
   pub nsfw: bool,
   pub community_id: i32,
+  pub thumbnail_alt_text: Option<String>,
+  pub media: Option<Vec<PostMediaItem>>,
+  pub anonymous: Option<bool>,
+  /// One of the post tags of the community
+  pub tag_id: Option<i32>,
   pub auth: String,
 }
 
@@ -20,19 +35,33 @@ pub struct PostResponse {
   pub post: PostView,
 }
 
+#[derive(Serialize, Clone)]
+pub struct NewPostsAvailableResponse {
+  /// 0 for the front page
+  pub community_id: i32,
+  pub new_posts: i64,
+}
+
 #[derive(Deserialize)]
 pub struct GetPost {
   pub id: i32,
+  pub comment_sort: Option<String>,
   pub auth: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct GetPostResponse {
   pub post: PostView,
-  pub comments: Vec<CommentView>,
+  pub comments: Vec<CollapsibleCommentView>,
   pub community: CommunityView,
   pub moderators: Vec<CommunityModeratorView>,
+  pub media: Vec<PostMedia>,
+  pub tag: Option<CommunityPostTag>,
+  pub related: Vec<PostView>,
   pub online: usize,
+  pub views: i64,
+  /// Estimated, within a few percent
+  pub unique_views: i64,
 }
 
 #[derive(Deserialize, Debug)]
@@ -49,6 +78,102 @@ pub struct GetPosts {
 #[derive(Serialize, Debug)]
 pub struct GetPostsResponse {
   pub posts: Vec<PostView>,
+  /// The gallery media of the posts, ordered by post and position
+  pub media: Vec<PostMedia>,
+}
+
+/// The posts linking to greylisted domains which wait for the approval of the user.
+#[derive(Deserialize, Debug)]
+pub struct ListDomainPostApprovals {
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListDomainPostApprovalsResponse {
+  pub posts: Vec<PostView>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApproveDomainPost {
+  pub post_id: i32,
+  pub approved: bool,
+  pub auth: String,
+}
+
+/// The posts in all communities which link to the url, or to anything on the domain.
+#[derive(Deserialize, Debug)]
+pub struct GetPostsByUrl {
+  pub url: Option<String>,
+  pub domain: Option<String>,
+  pub sort: String,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GetPostsByUrlResponse {
+  pub posts: Vec<PostView>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GetSimilarPosts {
+  pub community_id: i32,
+  pub name: String,
+  pub url: Option<String>,
+  pub limit: Option<i64>,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GetSimilarPostsResponse {
+  pub posts: Vec<PostView>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GetPostEditHistory {
+  pub post_id: i32,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GetPostEditHistoryResponse {
+  /// The versions of the post before each edit, the most recent first.
+  pub edits: Vec<PostEdit>,
+}
+
+/// The copies of a post in other communities, which link to the same url, with the comments of all
+/// of them.
+#[derive(Deserialize, Debug)]
+pub struct GetPostCrossposts {
+  pub post_id: i32,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GetPostCrosspostsResponse {
+  /// The post itself first, then the copies the user can see, newest first.
+  pub posts: Vec<PostView>,
+  pub comment_count: i64,
+  pub community_count: usize,
+  pub top_comments: Vec<CommentView>,
+}
+
+/// Shows how a title is going to be cleaned up, and the recent posts of the community with nearly
+/// the same title, before submitting a post.
+#[derive(Deserialize, Debug)]
+pub struct CheckPostTitle {
+  pub community_id: i32,
+  pub name: String,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckPostTitleResponse {
+  pub name: String,
+  pub duplicates: Vec<PostView>,
 }
 
 #[derive(Deserialize)]
@@ -65,6 +190,10 @@ pub struct EditPost {
   pub url: Option<String>,
   pub body: Option<String>,
   pub nsfw: bool,
+  pub thumbnail_alt_text: Option<String>,
+  pub media: Option<Vec<PostMediaItem>>,
+  /// Left out keeps the tag, 0 removes it
+  pub tag_id: Option<i32>,
   pub auth: String,
 }
 
@@ -80,6 +209,8 @@ pub struct RemovePost {
   pub edit_id: i32,
   pub removed: bool,
   pub reason: Option<String>,
+  pub reason_id: Option<i32>,
+  pub notify_author: Option<bool>,
   pub auth: String,
 }
 
@@ -90,6 +221,13 @@ pub struct LockPost {
   pub auth: String,
 }
 
+#[derive(Deserialize)]
+pub struct ClosePostComments {
+  pub edit_id: i32,
+  pub closed: bool,
+  pub auth: String,
+}
+
 #[derive(Deserialize)]
 pub struct StickyPost {
   pub edit_id: i32,
@@ -97,6 +235,13 @@ pub struct StickyPost {
   pub auth: String,
 }
 
+#[derive(Deserialize)]
+pub struct DistinguishPost {
+  pub edit_id: i32,
+  pub distinguished: bool,
+  pub auth: String,
+}
+
 #[derive(Deserialize)]
 pub struct SavePost {
   pub post_id: i32,
@@ -1,13 +1,26 @@
 use lemmy_db::{
+  admin_impersonation::AdminImpersonation,
   category::*,
   comment_view::*,
   community_view::*,
+  csp_report::CspReport,
+  domain::Domain,
+  email_outbox::EmailOutbox,
+  feature_flag::{FeatureFlag, FeatureFlagUser},
+  instance::Instance,
+  instance_stats::{InstancePeerStatsDaily, InstanceStatsDaily},
   moderator_views::*,
+  plugin_hook::PluginHook,
   post_view::*,
+  sign_in_signal::DuplicateAccount,
+  site_announcement::SiteAnnouncement,
   site_view::*,
+  spam_score::SpamScore,
   user::*,
   user_view::*,
 };
+use lemmy_rate_limit::RateLimitAllowance;
+use lemmy_utils::{email::EmailError, settings::RateLimitConfig};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
@@ -18,6 +31,17 @@ pub struct ListCategoriesResponse {
   pub categories: Vec<Category>,
 }
 
+#[derive(Deserialize)]
+pub struct GetFederatedInstances {}
+
+#[derive(Serialize)]
+pub struct GetFederatedInstancesResponse {
+  pub linked: Vec<Instance>,
+  pub mode: String,
+  pub allowed: Vec<String>,
+  pub blocked: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Search {
   pub q: String,
@@ -26,6 +50,8 @@ pub struct Search {
   pub sort: String,
   pub page: Option<i64>,
   pub limit: Option<i64>,
+  /// For mods, to also find removed and deleted posts and comments in their communities.
+  pub include_removed: Option<bool>,
   pub auth: Option<String>,
 }
 
@@ -57,6 +83,7 @@ pub struct GetModlogResponse {
   pub banned: Vec<ModBanView>,
   pub added_to_community: Vec<ModAddCommunityView>,
   pub added: Vec<ModAddView>,
+  pub bulk_actions: Vec<ModBulkActionView>,
 }
 
 #[derive(Deserialize)]
@@ -68,6 +95,12 @@ pub struct CreateSite {
   pub enable_downvotes: bool,
   pub open_registration: bool,
   pub enable_nsfw: bool,
+  pub require_alt_text: Option<bool>,
+  pub score_hidden_minutes: Option<i32>,
+  pub vote_lock_days: Option<i32>,
+  pub ninja_edit_minutes: Option<i32>,
+  pub edit_limit_days: Option<i32>,
+  pub undelete_window_days: Option<i32>,
   pub auth: String,
 }
 
@@ -80,6 +113,12 @@ pub struct EditSite {
   pub enable_downvotes: bool,
   pub open_registration: bool,
   pub enable_nsfw: bool,
+  pub require_alt_text: Option<bool>,
+  pub score_hidden_minutes: Option<i32>,
+  pub vote_lock_days: Option<i32>,
+  pub ninja_edit_minutes: Option<i32>,
+  pub edit_limit_days: Option<i32>,
+  pub undelete_window_days: Option<i32>,
   pub auth: String,
 }
 
@@ -125,3 +164,436 @@ pub struct SaveSiteConfig {
   pub config_hjson: String,
   pub auth: String,
 }
+
+#[derive(Deserialize)]
+pub struct ListSpamScores {
+  pub reported_only: bool,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ListSpamScoresResponse {
+  pub spam_scores: Vec<SpamScore>,
+}
+
+#[derive(Deserialize)]
+pub struct ListDomains {
+  /// allowed, blocked or greylisted
+  pub policy: Option<String>,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ListDomainsResponse {
+  pub domains: Vec<Domain>,
+}
+
+#[derive(Deserialize)]
+pub struct GetDomain {
+  pub name: String,
+  pub sort: String,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GetDomainResponse {
+  pub domain: Domain,
+  pub posts: Vec<PostView>,
+}
+
+#[derive(Deserialize)]
+pub struct SetDomainPolicy {
+  pub name: String,
+  pub policy: String,
+  pub reason: Option<String>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct DomainResponse {
+  pub domain: Domain,
+}
+
+#[derive(Deserialize)]
+pub struct GetInstanceStats {
+  pub since: Option<i64>,
+  pub until: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetInstanceStatsResponse {
+  pub days: Vec<InstanceStatsDaily>,
+  pub peers: Vec<InstancePeerStatsDaily>,
+}
+
+#[derive(Deserialize)]
+pub struct SendTestEmail {
+  /// Defaults to the email of the admin
+  pub email: Option<String>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct SendTestEmailResponse {
+  pub sent: bool,
+  pub error: Option<EmailError>,
+}
+
+#[derive(Deserialize)]
+pub struct GetEmailOutbox {
+  pub failed_only: bool,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetEmailOutboxResponse {
+  pub emails: Vec<EmailOutbox>,
+}
+
+#[derive(Deserialize)]
+pub struct ListCspReports {
+  /// Like script-src
+  pub directive: Option<String>,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ListCspReportsResponse {
+  pub reports: Vec<CspReport>,
+}
+
+#[derive(Deserialize)]
+pub struct RotateActorKeys {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct RotateActorKeysResponse {
+  /// The local users and communities which get new keys in the background
+  pub users: usize,
+  pub communities: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ImpersonateUser {
+  pub user_id: i32,
+  /// Why the admin needs to see the site as the user, for the impersonation log
+  pub reason: String,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ImpersonateUserResponse {
+  /// Only works for reading, until the impersonation expires
+  pub jwt: String,
+  pub impersonation: AdminImpersonation,
+}
+
+#[derive(Deserialize)]
+pub struct GetImpersonationLog {
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetImpersonationLogResponse {
+  pub impersonations: Vec<AdminImpersonation>,
+}
+
+#[derive(Deserialize)]
+pub struct GetDuplicateAccounts {
+  pub user_id: i32,
+  pub days: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetDuplicateAccountsResponse {
+  pub accounts: Vec<DuplicateAccount>,
+}
+
+#[derive(Deserialize)]
+pub struct GetDefaultCommunities {
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct SaveDefaultCommunities {
+  pub community_ids: Vec<i32>,
+  pub apply_to_existing_users: bool,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct DefaultCommunitiesResponse {
+  pub communities: Vec<CommunityView>,
+}
+
+#[derive(Deserialize)]
+pub struct GetSiteBranding {}
+
+#[derive(Deserialize)]
+pub struct SaveSiteBranding {
+  pub logo: Option<String>,
+  pub banner: Option<String>,
+  pub favicon: Option<String>,
+  pub primary_color: Option<String>,
+  pub secondary_color: Option<String>,
+  pub background_color: Option<String>,
+  pub text_color: Option<String>,
+  pub default_theme: Option<String>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SiteBrandingResponse {
+  pub logo: Option<String>,
+  pub banner: Option<String>,
+  pub favicon: Option<String>,
+  pub primary_color: Option<String>,
+  pub secondary_color: Option<String>,
+  pub background_color: Option<String>,
+  pub text_color: Option<String>,
+  pub default_theme: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListAnnouncements {
+  pub include_inactive: Option<bool>,
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListAnnouncementsResponse {
+  pub announcements: Vec<SiteAnnouncement>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateAnnouncement {
+  pub content: String,
+  pub severity: String,
+  pub starts: Option<i64>,
+  pub ends: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct EditAnnouncement {
+  pub id: i32,
+  pub content: String,
+  pub severity: String,
+  pub starts: Option<i64>,
+  pub ends: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAnnouncement {
+  pub id: i32,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DismissAnnouncement {
+  pub id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AnnouncementResponse {
+  pub announcement: SiteAnnouncement,
+}
+
+#[derive(Deserialize)]
+pub struct ListFeatureFlags {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ListFeatureFlagsResponse {
+  pub flags: Vec<FeatureFlag>,
+}
+
+#[derive(Deserialize)]
+pub struct SaveFeatureFlag {
+  pub name: String,
+  pub description: Option<String>,
+  pub enabled: bool,
+  pub rollout_percentage: i16,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteFeatureFlag {
+  pub name: String,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetFeatureFlagUser {
+  pub name: String,
+  pub user_id: i32,
+  /// None lets the rollout percentage decide for the user again
+  pub enabled: Option<bool>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct FeatureFlagResponse {
+  pub flag: FeatureFlag,
+  pub users: Vec<FeatureFlagUser>,
+}
+
+#[derive(Deserialize)]
+pub struct GetEnabledFeatures {
+  pub auth: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GetEnabledFeaturesResponse {
+  pub features: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListPluginHooks {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ListPluginHooksResponse {
+  pub hooks: Vec<PluginHook>,
+}
+
+#[derive(Deserialize)]
+pub struct CreatePluginHook {
+  pub name: String,
+  pub url: String,
+  pub hook_point: String,
+  pub timeout_ms: Option<i32>,
+  pub failure_policy: Option<String>,
+  pub secret: Option<String>,
+  pub enabled: Option<bool>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct EditPluginHook {
+  pub id: i32,
+  pub name: String,
+  pub url: String,
+  pub hook_point: String,
+  pub timeout_ms: Option<i32>,
+  pub failure_policy: Option<String>,
+  /// None keeps the secret, an empty string removes it
+  pub secret: Option<String>,
+  pub enabled: Option<bool>,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeletePluginHook {
+  pub id: i32,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct PluginHookResponse {
+  pub hook: PluginHook,
+}
+
+#[derive(Serialize)]
+pub struct WasmPluginInfo {
+  pub name: String,
+  pub enabled: bool,
+  /// Whether the module file is in the plugin directory
+  pub in_directory: bool,
+  pub loaded: bool,
+  /// Why the plugin couldn't be loaded, if it's enabled
+  pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListWasmPlugins {
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetWasmPluginEnabled {
+  pub name: String,
+  pub enabled: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReloadWasmPlugins {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ListWasmPluginsResponse {
+  pub plugins: Vec<WasmPluginInfo>,
+}
+
+#[derive(Deserialize)]
+pub struct GetMaintenanceMode {}
+
+#[derive(Deserialize)]
+pub struct SaveMaintenanceMode {
+  pub read_only: bool,
+  pub message: Option<String>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MaintenanceModeResponse {
+  pub read_only: bool,
+  pub message: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct GetRateLimitConfig {}
+
+#[derive(Serialize)]
+pub struct GetRateLimitConfigResponse {
+  pub rate_limit: RateLimitConfig,
+}
+
+#[derive(Deserialize)]
+pub struct SaveRateLimitConfig {
+  pub rate_limit: RateLimitConfig,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetRateLimitAllowances {}
+
+#[derive(Serialize)]
+pub struct GetRateLimitAllowancesResponse {
+  pub allowances: Vec<RateLimitAllowance>,
+}
+
+#[derive(Deserialize)]
+pub struct Ack {
+  pub event_id: u64,
+}
+
+#[derive(Serialize)]
+pub struct SessionStartedResponse {
+  pub resume_token: String,
+  /// False for new sessions, and when the session couldn't be resumed, so everything has to be
+  /// fetched again
+  pub resumed: bool,
+  /// Resumed sessions continue after this one
+  pub last_event_id: u64,
+}
@@ -3,8 +3,14 @@ use lemmy_db::{
   community_view::{CommunityFollowerView, CommunityModeratorView},
   post_view::PostView,
   private_message_view::PrivateMessageView,
+  push_subscription::PushSubscription,
+  saved_search::{SavedSearch, SavedSearchMatch},
+  sign_in_signal::SignInSignal,
+  thread_mute::ThreadMute,
   user_mention_view::UserMentionView,
+  user_presence::UserPresence,
   user_view::UserView,
+  vote_instance::UserIgnoredVoteInstance,
 };
 use serde::{Deserialize, Serialize};
 
@@ -59,6 +65,17 @@ pub struct SaveUserSettings {
   pub old_password: Option<String>,
   pub show_avatars: bool,
   pub send_notifications_to_email: bool,
+  pub discoverable: Option<bool>,
+  pub receive_welcome_messages: Option<bool>,
+  pub show_presence: Option<bool>,
+  pub bot_account: Option<bool>,
+  /// Comments with a lower score are sent collapsed.
+  pub collapse_comments_below_score: Option<i32>,
+  /// False stops collapsing comments by their score.
+  pub collapse_comments_by_score: Option<bool>,
+  pub collapse_bot_comments: Option<bool>,
+  /// Apply the last comment sort picked for a post again when it's opened.
+  pub remember_comment_sort: Option<bool>,
   pub auth: String,
 }
 
@@ -82,6 +99,8 @@ pub struct GetUserDetails {
 #[derive(Serialize)]
 pub struct GetUserDetailsResponse {
   pub user: UserView,
+  pub presence: Option<UserPresence>,
+  pub online: Option<bool>,
   pub follows: Vec<CommunityFollowerView>,
   pub moderates: Vec<CommunityModeratorView>,
   pub comments: Vec<CommentView>,
@@ -237,3 +256,163 @@ pub struct UserJoin {
 pub struct UserJoinResponse {
   pub user_id: i32,
 }
+
+#[derive(Deserialize)]
+pub struct CreateSavedSearch {
+  pub query: String,
+  pub community_id: Option<i32>,
+  pub creator_id: Option<i32>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SavedSearchResponse {
+  pub saved_search: SavedSearch,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteSavedSearch {
+  pub id: i32,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetSavedSearches {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetSavedSearchesResponse {
+  pub saved_searches: Vec<SavedSearch>,
+}
+
+#[derive(Deserialize)]
+pub struct GetSavedSearchMatches {
+  pub unread_only: bool,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct GetSavedSearchMatchesResponse {
+  pub matches: Vec<SavedSearchMatch>,
+  pub posts: Vec<PostView>,
+}
+
+#[derive(Deserialize)]
+pub struct MuteThread {
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub mute: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetThreadMutes {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ThreadMutesResponse {
+  pub thread_mutes: Vec<ThreadMute>,
+}
+
+#[derive(Deserialize)]
+pub struct GetPushPublicKey {}
+
+#[derive(Serialize)]
+pub struct GetPushPublicKeyResponse {
+  /// None if the instance doesn't send web pushes
+  pub public_key: Option<String>,
+}
+
+/// The keys of a push subscription, as the browser gives them out.
+#[derive(Deserialize)]
+pub struct PushSubscriptionKeys {
+  pub p256dh: String,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct SavePushSubscription {
+  pub endpoint: String,
+  pub keys: PushSubscriptionKeys,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeletePushSubscription {
+  pub endpoint: String,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct PushSubscriptionResponse {
+  pub push_subscription: Option<PushSubscription>,
+}
+
+#[derive(Deserialize)]
+pub struct GetNotifications {
+  /// From the previous response, to only get newer notifications
+  pub cursor: Option<i64>,
+  /// Seconds to wait when there are no new notifications yet, at most 60
+  pub timeout: Option<u64>,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct GetNotificationsResponse {
+  pub replies: Vec<ReplyView>,
+  pub mentions: Vec<UserMentionView>,
+  pub messages: Vec<PrivateMessageView>,
+  pub community_posts: Vec<PostView>,
+  /// Logins from a new device or country, which the user didn't review yet
+  pub sign_ins: Vec<SignInSignal>,
+  /// Microseconds since the epoch, of the newest notification
+  pub cursor: i64,
+}
+
+#[derive(Deserialize)]
+pub struct IgnoreVoteInstance {
+  /// The domain of the instance, like "lemmy.ml"
+  pub instance: String,
+  pub ignore: bool,
+  pub auth: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetIgnoredVoteInstances {
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct IgnoredVoteInstancesResponse {
+  pub ignored_vote_instances: Vec<UserIgnoredVoteInstance>,
+}
+
+#[derive(Deserialize)]
+pub struct GetSignIns {
+  pub anomalous_only: Option<bool>,
+  pub auth: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct GetSignInsResponse {
+  pub sign_ins: Vec<SignInSignal>,
+  /// The sign in of the token of the request, unless it's an older token
+  pub current_sign_in_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewSignIn {
+  pub id: i32,
+  /// Logs out the clients which use the token of the sign in
+  pub revoke: bool,
+  pub auth: String,
+}
+
+#[derive(Serialize)]
+pub struct ReviewSignInResponse {
+  pub sign_in: SignInSignal,
+}
@@ -1,5 +1,10 @@
 use crate::{schema::activity, Crud};
-use diesel::{dsl::*, result::Error, *};
+use diesel::{
+  dsl::*,
+  result::Error,
+  sql_types::{Bool, Text},
+  *,
+};
 use log::debug;
 use serde::Serialize;
 use serde_json::Value;
@@ -17,6 +22,7 @@ pub struct Activity {
   pub local: bool,
   pub published: chrono::NaiveDateTime,
   pub updated: Option<chrono::NaiveDateTime>,
+  pub ap_id: Option<String>,
 }
 
 #[derive(Insertable, AsChangeset)]
@@ -26,6 +32,7 @@ pub struct ActivityForm {
   pub data: Value,
   pub local: bool,
   pub updated: Option<chrono::NaiveDateTime>,
+  pub ap_id: Option<String>,
 }
 
 impl Crud<ActivityForm> for Activity {
@@ -53,6 +60,55 @@ impl Crud<ActivityForm> for Activity {
   }
 }
 
+/// The activities which go in the outbox of an actor: those addressed to the public, except for
+/// votes, and those which announce or undo votes. The actor id is bound after this.
+const OUTBOX_ACTIVITY_SQL: &str = "(data->'to' ? 'https://www.w3.org/ns/activitystreams#Public' \
+  or data->'cc' ? 'https://www.w3.org/ns/activitystreams#Public') \
+  and coalesce(data->>'type', '') not in ('Like', 'Dislike') \
+  and coalesce(data->'object'->>'type', '') not in ('Like', 'Dislike') \
+  and coalesce(data->'object'->'object'->>'type', '') not in ('Like', 'Dislike') \
+  and data->>'actor' = ";
+
+impl Activity {
+  /// A local activity, by the id it was sent with.
+  pub fn read_local_from_apub_id(conn: &PgConnection, object_id: &str) -> Result<Self, Error> {
+    use crate::schema::activity::dsl::*;
+    activity
+      .filter(ap_id.eq(object_id))
+      .filter(local.eq(true))
+      .first::<Self>(conn)
+  }
+
+  /// The outbox activities of a local actor, newest first, older than `before_id` if it's given.
+  pub fn list_for_outbox(
+    conn: &PgConnection,
+    actor_id: &str,
+    before_id: Option<i32>,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::activity::dsl::*;
+    let mut query = activity
+      .filter(local.eq(true))
+      .filter(sql::<Bool>(OUTBOX_ACTIVITY_SQL).bind::<Text, _>(actor_id))
+      .into_boxed();
+
+    if let Some(before_id) = before_id {
+      query = query.filter(id.lt(before_id));
+    }
+
+    query.order_by(id.desc()).limit(limit).load::<Self>(conn)
+  }
+
+  pub fn count_for_outbox(conn: &PgConnection, actor_id: &str) -> Result<i64, Error> {
+    use crate::schema::activity::dsl::*;
+    activity
+      .filter(local.eq(true))
+      .filter(sql::<Bool>(OUTBOX_ACTIVITY_SQL).bind::<Text, _>(actor_id))
+      .count()
+      .get_result(conn)
+  }
+}
+
 pub fn do_insert_activity<T>(
   conn: &PgConnection,
   user_id: i32,
@@ -63,11 +119,14 @@ where
   T: Serialize + Debug,
 {
   debug!("inserting activity for user {}, data {:?}", user_id, &data);
+  let data = serde_json::to_value(&data)?;
+  let ap_id = data.get("id").and_then(Value::as_str).map(str::to_owned);
   let activity_form = ActivityForm {
     user_id,
-    data: serde_json::to_value(&data)?,
+    data,
     local,
     updated: None,
+    ap_id,
   };
   let result = Activity::create(&conn, &activity_form);
   match result {
@@ -89,7 +148,7 @@ mod tests {
     ListingType,
     SortType,
   };
-  use serde_json::Value;
+  use serde_json::{json, Value};
 
   #[test]
   fn test_crud() {
@@ -119,6 +178,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_creator = User_::create(&conn, &creator_form).unwrap();
@@ -137,6 +202,7 @@ mod tests {
       data: test_json.to_owned(),
       local: true,
       updated: None,
+      ap_id: Some("https://example.com/activities/8c1e1f96-activity-crud".into()),
     };
 
     let inserted_activity = Activity::create(&conn, &activity_form).unwrap();
@@ -148,12 +214,55 @@ mod tests {
       local: true,
       published: inserted_activity.published,
       updated: None,
+      ap_id: Some("https://example.com/activities/8c1e1f96-activity-crud".into()),
     };
 
     let read_activity = Activity::read(&conn, inserted_activity.id).unwrap();
+    let read_activity_by_apub_id = Activity::read_local_from_apub_id(
+      &conn,
+      "https://example.com/activities/8c1e1f96-activity-crud",
+    )
+    .unwrap();
+
+    let actor_id = "https://example.com/u/activity_creator_pm";
+    let public = "https://www.w3.org/ns/activitystreams#Public";
+    let outbox_forms = vec![
+      json!({"type": "Create", "actor": actor_id, "to": public, "object": {"type": "Note"}}),
+      json!({"type": "Like", "actor": actor_id, "to": public, "object": "https://example.com/post/1"}),
+      json!({"type": "Create", "actor": actor_id, "to": "https://example.com/u/friend"}),
+      json!({"type": "Delete", "actor": actor_id, "to": [public], "cc": ["https://example.com/c/main"]}),
+    ]
+    .into_iter()
+    .map(|data| ActivityForm {
+      user_id: inserted_creator.id,
+      data,
+      local: true,
+      updated: None,
+      ap_id: None,
+    })
+    .collect::<Vec<ActivityForm>>();
+    let outbox_activities = outbox_forms
+      .iter()
+      .map(|form| Activity::create(&conn, form).unwrap())
+      .collect::<Vec<Activity>>();
+    let outbox = Activity::list_for_outbox(&conn, actor_id, None, 10).unwrap();
+    let older =
+      Activity::list_for_outbox(&conn, actor_id, Some(outbox_activities[3].id), 10).unwrap();
+    let outbox_count = Activity::count_for_outbox(&conn, actor_id).unwrap();
+
     User_::delete(&conn, inserted_creator.id).unwrap();
 
     assert_eq!(expected_activity, read_activity);
+    assert_eq!(expected_activity, read_activity_by_apub_id);
     assert_eq!(expected_activity, inserted_activity);
+    assert_eq!(
+      vec![outbox_activities[3].id, outbox_activities[0].id],
+      outbox.iter().map(|a| a.id).collect::<Vec<i32>>()
+    );
+    assert_eq!(
+      vec![outbox_activities[0].id],
+      older.iter().map(|a| a.id).collect::<Vec<i32>>()
+    );
+    assert_eq!(2, outbox_count);
   }
 }
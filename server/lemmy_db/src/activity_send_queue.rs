@@ -0,0 +1,189 @@
+use crate::{naive_now, schema::activity_send_queue};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// After this many failed attempts, which take about a week, the activity is dropped.
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 16;
+
+/// An outgoing activity which wasn't delivered to the inbox yet.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "activity_send_queue"]
+pub struct ActivitySendQueue {
+  pub id: i32,
+  pub activity: String,
+  pub inbox: String,
+  pub actor_id: String,
+  pub published: chrono::NaiveDateTime,
+  pub attempts: i32,
+  pub next_attempt: chrono::NaiveDateTime,
+  pub last_error: Option<String>,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "activity_send_queue"]
+pub struct ActivitySendQueueForm {
+  pub activity: String,
+  pub inbox: String,
+  pub actor_id: String,
+}
+
+/// How long to wait after the given number of failed attempts: a minute, doubling each time, up to
+/// a day.
+pub fn retry_delay(attempts: i32) -> chrono::Duration {
+  let minutes = 1i64.checked_shl(attempts.max(0) as u32).unwrap_or(i64::MAX);
+  chrono::Duration::minutes(minutes.min(24 * 60))
+}
+
+impl ActivitySendQueue {
+  pub fn enqueue(conn: &PgConnection, forms: &[ActivitySendQueueForm]) -> Result<Vec<Self>, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    insert_into(activity_send_queue)
+      .values(forms)
+      .get_results::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection, queue_id: i32) -> Result<Option<Self>, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    activity_send_queue
+      .find(queue_id)
+      .first::<Self>(conn)
+      .optional()
+  }
+
+  /// The inboxes whose next activity can be sent now.
+  pub fn list_due_inboxes(conn: &PgConnection) -> Result<Vec<String>, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    activity_send_queue
+      .filter(next_attempt.le(naive_now()))
+      .select(inbox)
+      .distinct()
+      .load::<String>(conn)
+  }
+
+  /// Takes the oldest activity for the inbox if it's due, so that it isn't sent twice while it's
+  /// being delivered. If the delivery doesn't finish, it becomes due again at `lease_until`.
+  pub fn claim_next(
+    conn: &PgConnection,
+    for_inbox: &str,
+    lease_until: chrono::NaiveDateTime,
+  ) -> Result<Option<Self>, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    let head = activity_send_queue
+      .filter(inbox.eq(for_inbox))
+      .order_by(id.asc())
+      .select(id)
+      .first::<i32>(conn)
+      .optional()?;
+    let head = match head {
+      Some(h) => h,
+      None => return Ok(None),
+    };
+    diesel::update(
+      activity_send_queue
+        .find(head)
+        .filter(next_attempt.le(naive_now())),
+    )
+    .set(next_attempt.eq(lease_until))
+    .get_result::<Self>(conn)
+    .optional()
+  }
+
+  pub fn delivered(conn: &PgConnection, queue_id: i32) -> Result<usize, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    diesel::delete(activity_send_queue.find(queue_id)).execute(conn)
+  }
+
+  /// Schedules the next attempt with exponential backoff, or after retry_after if the inbox asked
+  /// to wait longer. Returns `None` if the activity was dropped instead, because it failed too
+  /// often.
+  pub fn failed(
+    conn: &PgConnection,
+    queue_id: i32,
+    error: &str,
+    retry_after: Option<chrono::Duration>,
+  ) -> Result<Option<Self>, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    let queued = activity_send_queue.find(queue_id).first::<Self>(conn)?;
+    let failed_attempts = queued.attempts + 1;
+    if failed_attempts >= MAX_DELIVERY_ATTEMPTS {
+      Self::delivered(conn, queue_id)?;
+      return Ok(None);
+    }
+    let delay =
+      retry_delay(failed_attempts).max(retry_after.unwrap_or_else(chrono::Duration::zero));
+    diesel::update(activity_send_queue.find(queue_id))
+      .set((
+        attempts.eq(failed_attempts),
+        next_attempt.eq(naive_now() + delay),
+        last_error.eq(error),
+      ))
+      .get_result::<Self>(conn)
+      .map(Some)
+  }
+
+  /// The number of activities waiting to be delivered.
+  pub fn count_pending(conn: &PgConnection) -> Result<i64, Error> {
+    use crate::schema::activity_send_queue::dsl::*;
+    activity_send_queue.count().get_result(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{activity_send_queue::*, naive_now, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_retry_delay() {
+    assert_eq!(chrono::Duration::minutes(1), retry_delay(0));
+    assert_eq!(chrono::Duration::minutes(8), retry_delay(3));
+    assert_eq!(chrono::Duration::days(1), retry_delay(11));
+    assert_eq!(chrono::Duration::days(1), retry_delay(100));
+  }
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let inbox = "https://queue-test.example/inbox".to_string();
+    let form = |activity: &str| ActivitySendQueueForm {
+      activity: activity.to_owned(),
+      inbox: inbox.to_owned(),
+      actor_id: "https://queue-test.example/u/sender".to_owned(),
+    };
+    let queued = ActivitySendQueue::enqueue(&conn, &[form("first"), form("second")]).unwrap();
+    let due = ActivitySendQueue::list_due_inboxes(&conn).unwrap();
+
+    // The inbox gets its activities in order, one at a time, and can ask to wait before retrying
+    let lease_until = naive_now() + chrono::Duration::minutes(10);
+    let claimed = ActivitySendQueue::claim_next(&conn, &inbox, lease_until)
+      .unwrap()
+      .unwrap();
+    let claimed_twice = ActivitySendQueue::claim_next(&conn, &inbox, lease_until).unwrap();
+
+    let failed = ActivitySendQueue::failed(
+      &conn,
+      claimed.id,
+      "timeout",
+      Some(chrono::Duration::hours(2)),
+    )
+    .unwrap()
+    .unwrap();
+    let while_waiting = ActivitySendQueue::claim_next(&conn, &inbox, lease_until).unwrap();
+
+    ActivitySendQueue::delivered(&conn, claimed.id).unwrap();
+    let next = ActivitySendQueue::claim_next(&conn, &inbox, lease_until)
+      .unwrap()
+      .unwrap();
+    ActivitySendQueue::delivered(&conn, next.id).unwrap();
+
+    assert_eq!(2, queued.len());
+    assert!(due.contains(&inbox));
+    assert_eq!("first", claimed.activity);
+    assert_eq!(None, claimed_twice);
+    assert_eq!(1, failed.attempts);
+    assert_eq!(Some("timeout".to_string()), failed.last_error);
+    assert!(failed.next_attempt > naive_now() + chrono::Duration::hours(1));
+    assert_eq!(None, while_waiting);
+    assert_eq!("second", next.activity);
+  }
+}
@@ -0,0 +1,159 @@
+use crate::{limit_and_offset, naive_now, schema::admin_impersonation};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// An admin viewing the site as another user. Every request made with the token counts towards
+/// `requests`, so that the log shows how it was used.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "admin_impersonation"]
+pub struct AdminImpersonation {
+  pub id: i32,
+  pub admin_id: i32,
+  pub user_id: i32,
+  pub reason: String,
+  pub published: chrono::NaiveDateTime,
+  pub expires: chrono::NaiveDateTime,
+  pub requests: i32,
+  pub last_request: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "admin_impersonation"]
+pub struct AdminImpersonationForm {
+  pub admin_id: i32,
+  pub user_id: i32,
+  pub reason: String,
+  pub expires: chrono::NaiveDateTime,
+}
+
+impl AdminImpersonation {
+  pub fn start(conn: &PgConnection, form: &AdminImpersonationForm) -> Result<Self, Error> {
+    use crate::schema::admin_impersonation::dsl::*;
+    insert_into(admin_impersonation)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection, impersonation_id: i32) -> Result<Self, Error> {
+    use crate::schema::admin_impersonation::dsl::*;
+    admin_impersonation
+      .find(impersonation_id)
+      .first::<Self>(conn)
+  }
+
+  /// Counts a request made with the token, unless it expired. Returns none if it did.
+  pub fn record_request(conn: &PgConnection, impersonation_id: i32) -> Result<Option<Self>, Error> {
+    use crate::schema::admin_impersonation::dsl::*;
+    let request_time = naive_now();
+    diesel::update(
+      admin_impersonation
+        .find(impersonation_id)
+        .filter(expires.gt(request_time)),
+    )
+    .set((requests.eq(requests + 1), last_request.eq(request_time)))
+    .get_result::<Self>(conn)
+    .optional()
+  }
+
+  /// Newest first.
+  pub fn list(
+    conn: &PgConnection,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::admin_impersonation::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    admin_impersonation
+      .order_by(published.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    admin_impersonation::*,
+    naive_now,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_admin = UserForm {
+      name: "impersonating_admin".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: true,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+    let inserted_admin = User_::create(&conn, &new_admin).unwrap();
+
+    let new_user = UserForm {
+      name: "impersonated_user".into(),
+      admin: false,
+      ..new_admin
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let form = AdminImpersonationForm {
+      admin_id: inserted_admin.id,
+      user_id: inserted_user.id,
+      reason: "Their front page is empty".into(),
+      expires: naive_now() + chrono::Duration::minutes(30),
+    };
+    let started = AdminImpersonation::start(&conn, &form).unwrap();
+    let recorded = AdminImpersonation::record_request(&conn, started.id).unwrap();
+
+    let expired_form = AdminImpersonationForm {
+      expires: naive_now() - chrono::Duration::minutes(1),
+      ..form
+    };
+    let expired = AdminImpersonation::start(&conn, &expired_form).unwrap();
+    let recorded_expired = AdminImpersonation::record_request(&conn, expired.id).unwrap();
+    let read_expired = AdminImpersonation::read(&conn, expired.id).unwrap();
+    let log = AdminImpersonation::list(&conn, None, None).unwrap();
+
+    User_::delete(&conn, inserted_admin.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(0, started.requests);
+    assert_eq!(1, recorded.as_ref().map(|r| r.requests).unwrap());
+    assert!(recorded.unwrap().last_request.is_some());
+    assert_eq!(None, recorded_expired);
+    assert_eq!(0, read_expired.requests);
+    assert!(log.iter().any(|i| i.id == started.id));
+    assert!(log.iter().any(|i| i.id == expired.id));
+  }
+}
@@ -1,7 +1,7 @@
 use super::post::Post;
 use crate::{
   naive_now,
-  schema::{comment, comment_like, comment_saved},
+  schema::{comment, comment_like, comment_saved, post},
   Crud,
   Likeable,
   Saveable,
@@ -32,6 +32,8 @@ pub struct Comment {
   pub deleted: bool,
   pub ap_id: String,
   pub local: bool,
+  pub distinguished: bool,
+  pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -48,6 +50,7 @@ pub struct CommentForm {
   pub deleted: Option<bool>,
   pub ap_id: Option<String>,
   pub local: bool,
+  pub distinguished: Option<bool>,
 }
 
 impl CommentForm {
@@ -118,17 +121,51 @@ impl Comment {
       .get_results::<Self>(conn)
   }
 
+  /// Attributes everything of a creator to another user, eg to the placeholder of deleted
+  /// accounts.
+  pub fn reassign_creator(
+    conn: &PgConnection,
+    from_creator_id: i32,
+    to_creator_id: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::comment::dsl::*;
+    diesel::update(comment.filter(creator_id.eq(from_creator_id)))
+      .set(creator_id.eq(to_creator_id))
+      .execute(conn)
+  }
+
   pub fn update_deleted(
     conn: &PgConnection,
     comment_id: i32,
     new_deleted: bool,
   ) -> Result<Self, Error> {
     use crate::schema::comment::dsl::*;
+    let deleted_time = naive_now();
     diesel::update(comment.find(comment_id))
-      .set((deleted.eq(new_deleted), updated.eq(naive_now())))
+      .set((
+        deleted.eq(new_deleted),
+        deleted_at.eq(if new_deleted { Some(deleted_time) } else { None }),
+        updated.eq(deleted_time),
+      ))
       .get_result::<Self>(conn)
   }
 
+  pub fn update_deleted_for_creator(
+    conn: &PgConnection,
+    for_creator_id: i32,
+    new_deleted: bool,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::comment::dsl::*;
+    let deleted_time = naive_now();
+    diesel::update(comment.filter(creator_id.eq(for_creator_id)))
+      .set((
+        deleted.eq(new_deleted),
+        deleted_at.eq(if new_deleted { Some(deleted_time) } else { None }),
+        updated.eq(deleted_time),
+      ))
+      .get_results::<Self>(conn)
+  }
+
   pub fn update_removed(
     conn: &PgConnection,
     comment_id: i32,
@@ -140,6 +177,17 @@ impl Comment {
       .get_result::<Self>(conn)
   }
 
+  pub fn update_distinguished(
+    conn: &PgConnection,
+    comment_id: i32,
+    new_distinguished: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::comment::dsl::*;
+    diesel::update(comment.find(comment_id))
+      .set(distinguished.eq(new_distinguished))
+      .get_result::<Self>(conn)
+  }
+
   pub fn update_removed_for_creator(
     conn: &PgConnection,
     for_creator_id: i32,
@@ -151,6 +199,34 @@ impl Comment {
       .get_results::<Self>(conn)
   }
 
+  /// The comments of a user in a community that aren't removed yet, published in the given time
+  /// window.
+  pub fn list_for_creator_in_community(
+    conn: &PgConnection,
+    for_creator_id: i32,
+    for_community_id: i32,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+  ) -> Result<Vec<Self>, Error> {
+    let mut query = comment::table
+      .inner_join(post::table)
+      .filter(comment::creator_id.eq(for_creator_id))
+      .filter(post::community_id.eq(for_community_id))
+      .filter(comment::removed.eq(false))
+      .select(comment::all_columns)
+      .into_boxed();
+
+    if let Some(since) = since {
+      query = query.filter(comment::published.ge(since));
+    }
+
+    if let Some(until) = until {
+      query = query.filter(comment::published.le(until));
+    }
+
+    query.order_by(comment::published.asc()).load::<Self>(conn)
+  }
+
   pub fn update_read(conn: &PgConnection, comment_id: i32, new_read: bool) -> Result<Self, Error> {
     use crate::schema::comment::dsl::*;
     diesel::update(comment.find(comment_id))
@@ -162,10 +238,11 @@ impl Comment {
     conn: &PgConnection,
     comment_id: i32,
     new_content: &str,
+    new_updated: Option<chrono::NaiveDateTime>,
   ) -> Result<Self, Error> {
     use crate::schema::comment::dsl::*;
     diesel::update(comment.find(comment_id))
-      .set((content.eq(new_content), updated.eq(naive_now())))
+      .set((content.eq(new_content), updated.eq(new_updated)))
       .get_result::<Self>(conn)
   }
 
@@ -190,6 +267,8 @@ pub struct CommentLike {
   pub post_id: i32,
   pub score: i16,
   pub published: chrono::NaiveDateTime,
+  /// The instance of remote voters
+  pub instance: Option<String>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -295,6 +374,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -337,6 +422,10 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
       ap_id: None,
       local: true,
       published: None,
@@ -356,6 +445,7 @@ mod tests {
       updated: None,
       ap_id: None,
       local: true,
+      distinguished: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
@@ -373,6 +463,8 @@ mod tests {
       updated: None,
       ap_id: inserted_comment.ap_id.to_owned(),
       local: true,
+      distinguished: false,
+      deleted_at: None,
     };
 
     let child_comment_form = CommentForm {
@@ -387,6 +479,7 @@ mod tests {
       updated: None,
       ap_id: None,
       local: true,
+      distinguished: None,
     };
 
     let inserted_child_comment = Comment::create(&conn, &child_comment_form).unwrap();
@@ -408,6 +501,7 @@ mod tests {
       user_id: inserted_user.id,
       published: inserted_comment_like.published,
       score: 1,
+      instance: None,
     };
 
     // Comment Saved
@@ -426,7 +520,19 @@ mod tests {
     };
 
     let read_comment = Comment::read(&conn, inserted_comment.id).unwrap();
+    let creator_comments = Comment::list_for_creator_in_community(
+      &conn,
+      inserted_user.id,
+      inserted_community.id,
+      None,
+      Some(inserted_comment.published),
+    )
+    .unwrap();
     let updated_comment = Comment::update(&conn, inserted_comment.id, &comment_form).unwrap();
+    let distinguished_comment =
+      Comment::update_distinguished(&conn, inserted_comment.id, true).unwrap();
+    let deleted_comment = Comment::update_deleted(&conn, inserted_comment.id, true).unwrap();
+    let restored_comment = Comment::update_deleted(&conn, inserted_comment.id, false).unwrap();
     let like_removed = CommentLike::remove(&conn, inserted_user.id, inserted_comment.id).unwrap();
     let saved_removed = CommentSaved::unsave(&conn, &comment_saved_form).unwrap();
     let num_deleted = Comment::delete(&conn, inserted_comment.id).unwrap();
@@ -436,8 +542,12 @@ mod tests {
     User_::delete(&conn, inserted_user.id).unwrap();
 
     assert_eq!(expected_comment, read_comment);
+    assert_eq!(Some(&expected_comment), creator_comments.first());
     assert_eq!(expected_comment, inserted_comment);
     assert_eq!(expected_comment, updated_comment);
+    assert!(distinguished_comment.distinguished);
+    assert!(deleted_comment.deleted_at.is_some());
+    assert_eq!(None, restored_comment.deleted_at);
     assert_eq!(expected_comment_like, inserted_comment_like);
     assert_eq!(expected_comment_saved, inserted_comment_saved);
     assert_eq!(
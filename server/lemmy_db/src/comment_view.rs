@@ -1,5 +1,17 @@
 // TODO, remove the cross join here, just join to user directly
-use crate::{fuzzy_search, limit_and_offset, ListingType, MaybeOptional, SortType};
+use crate::{
+  community::{hidden_private_communities, undiscoverable_communities},
+  fuzzy_search,
+  limit_and_offset,
+  post_view::{SHOWN_HOT_RANK_ACTIVE_SQL, SHOWN_HOT_RANK_SQL, SHOWN_SCORE_SQL},
+  schema::user_,
+  user::undiscoverable_users,
+  vote_instance::{comment_votes_from_instances, UserIgnoredVoteInstance},
+  CommentSortType,
+  ListingType,
+  MaybeOptional,
+  SortType,
+};
 use diesel::{dsl::*, pg::Pg, result::Error, *};
 use serde::{Deserialize, Serialize};
 
@@ -19,6 +31,7 @@ table! {
     deleted -> Bool,
     ap_id -> Text,
     local -> Bool,
+    distinguished -> Bool,
     community_id -> Int4,
     community_actor_id -> Text,
     community_local -> Bool,
@@ -41,6 +54,7 @@ table! {
     my_vote -> Nullable<Int4>,
     subscribed -> Nullable<Bool>,
     saved -> Nullable<Bool>,
+    score_hidden -> Bool,
   }
 }
 
@@ -59,6 +73,7 @@ table! {
     deleted -> Bool,
     ap_id -> Text,
     local -> Bool,
+    distinguished -> Bool,
     community_id -> Int4,
     community_actor_id -> Text,
     community_local -> Bool,
@@ -81,6 +96,7 @@ table! {
     my_vote -> Nullable<Int4>,
     subscribed -> Nullable<Bool>,
     saved -> Nullable<Bool>,
+    score_hidden -> Bool,
   }
 }
 
@@ -100,6 +116,7 @@ pub struct CommentView {
   pub deleted: bool,
   pub ap_id: String,
   pub local: bool,
+  pub distinguished: bool,
   pub community_id: i32,
   pub community_actor_id: String,
   pub community_local: bool,
@@ -122,6 +139,60 @@ pub struct CommentView {
   pub my_vote: Option<i32>,
   pub subscribed: Option<bool>,
   pub saved: Option<bool>,
+  pub score_hidden: bool,
+}
+
+/// A comment of a comment tree, with a hint for clients to show it collapsed.
+#[derive(PartialEq, Debug, Serialize, Clone)]
+pub struct CollapsibleCommentView {
+  #[serde(flatten)]
+  pub comment: CommentView,
+  pub collapsed: bool,
+}
+
+impl CollapsibleCommentView {
+  /// Collapses the comments with a score below the threshold of the user, and those of bots if the
+  /// user chose so. Comments whose score is hidden yet are only collapsed for being from a bot.
+  pub fn from_comments(
+    conn: &PgConnection,
+    comments: Vec<CommentView>,
+    my_user_id: Option<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    let (below_score, collapse_bots) = match my_user_id {
+      Some(my_user_id) => user_::table
+        .find(my_user_id)
+        .select((
+          user_::collapse_comments_below_score,
+          user_::collapse_bot_comments,
+        ))
+        .first::<(Option<i32>, bool)>(conn)?,
+      None => (None, false),
+    };
+
+    let bot_ids = if collapse_bots {
+      let creator_ids = comments.iter().map(|c| c.creator_id).collect::<Vec<i32>>();
+      user_::table
+        .filter(user_::id.eq_any(creator_ids))
+        .filter(user_::bot_account.eq(true))
+        .select(user_::id)
+        .load::<i32>(conn)?
+    } else {
+      Vec::new()
+    };
+
+    Ok(
+      comments
+        .into_iter()
+        .map(|comment| {
+          let downvoted = below_score.map_or(false, |below| {
+            !comment.score_hidden && comment.score < i64::from(below)
+          });
+          let collapsed = downvoted || bot_ids.contains(&comment.creator_id);
+          CollapsibleCommentView { comment, collapsed }
+        })
+        .collect(),
+    )
+  }
 }
 
 pub struct CommentQueryBuilder<'a> {
@@ -129,12 +200,16 @@ pub struct CommentQueryBuilder<'a> {
   query: super::comment_view::comment_fast_view::BoxedQuery<'a, Pg>,
   listing_type: ListingType,
   sort: &'a SortType,
+  comment_sort: Option<CommentSortType>,
   for_community_id: Option<i32>,
+  for_community_ids: Option<Vec<i32>>,
   for_post_id: Option<i32>,
+  for_post_ids: Option<Vec<i32>>,
   for_creator_id: Option<i32>,
   search_term: Option<String>,
   my_user_id: Option<i32>,
   saved_only: bool,
+  include_removed: bool,
   page: Option<i64>,
   limit: Option<i64>,
 }
@@ -150,12 +225,16 @@ impl<'a> CommentQueryBuilder<'a> {
       query,
       listing_type: ListingType::All,
       sort: &SortType::New,
+      comment_sort: None,
       for_community_id: None,
+      for_community_ids: None,
       for_post_id: None,
+      for_post_ids: None,
       for_creator_id: None,
       search_term: None,
       my_user_id: None,
       saved_only: false,
+      include_removed: false,
       page: None,
       limit: None,
     }
@@ -171,11 +250,23 @@ impl<'a> CommentQueryBuilder<'a> {
     self
   }
 
+  /// Takes precedence over `sort`.
+  pub fn comment_sort<T: MaybeOptional<CommentSortType>>(mut self, comment_sort: T) -> Self {
+    self.comment_sort = comment_sort.get_optional();
+    self
+  }
+
   pub fn for_post_id<T: MaybeOptional<i32>>(mut self, for_post_id: T) -> Self {
     self.for_post_id = for_post_id.get_optional();
     self
   }
 
+  /// Only the comments of these posts.
+  pub fn for_post_ids<T: MaybeOptional<Vec<i32>>>(mut self, for_post_ids: T) -> Self {
+    self.for_post_ids = for_post_ids.get_optional();
+    self
+  }
+
   pub fn for_creator_id<T: MaybeOptional<i32>>(mut self, for_creator_id: T) -> Self {
     self.for_creator_id = for_creator_id.get_optional();
     self
@@ -186,6 +277,12 @@ impl<'a> CommentQueryBuilder<'a> {
     self
   }
 
+  /// Only the comments of these communities.
+  pub fn for_community_ids<T: MaybeOptional<Vec<i32>>>(mut self, for_community_ids: T) -> Self {
+    self.for_community_ids = for_community_ids.get_optional();
+    self
+  }
+
   pub fn search_term<T: MaybeOptional<String>>(mut self, search_term: T) -> Self {
     self.search_term = search_term.get_optional();
     self
@@ -201,6 +298,13 @@ impl<'a> CommentQueryBuilder<'a> {
     self
   }
 
+  /// Searches also find removed and deleted comments, and the comments of users who opted out of
+  /// search. Only for mods, together with `for_community_ids`.
+  pub fn include_removed(mut self, include_removed: bool) -> Self {
+    self.include_removed = include_removed;
+    self
+  }
+
   pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
     self.page = page.get_optional();
     self
@@ -213,6 +317,7 @@ impl<'a> CommentQueryBuilder<'a> {
 
   pub fn list(self) -> Result<Vec<CommentView>, Error> {
     use super::comment_view::comment_fast_view::dsl::*;
+    use diesel::sql_types::{BigInt, Bool, Integer};
 
     let mut query = self.query;
 
@@ -231,12 +336,28 @@ impl<'a> CommentQueryBuilder<'a> {
       query = query.filter(community_id.eq(for_community_id));
     }
 
+    if let Some(for_community_ids) = self.for_community_ids {
+      query = query.filter(community_id.eq_any(for_community_ids));
+    }
+
     if let Some(for_post_id) = self.for_post_id {
       query = query.filter(post_id.eq(for_post_id));
     };
 
+    if let Some(for_post_ids) = self.for_post_ids {
+      query = query.filter(post_id.eq_any(for_post_ids));
+    }
+
     if let Some(search_term) = self.search_term {
       query = query.filter(content.ilike(fuzzy_search(&search_term)));
+      // Unlike in threads, removed comments can't be shown as placeholders in search results
+      if !self.include_removed {
+        query = query
+          .filter(creator_id.ne_all(undiscoverable_users(self.conn)?))
+          .filter(community_id.ne_all(undiscoverable_communities(self.conn)?))
+          .filter(removed.eq(false))
+          .filter(deleted.eq(false));
+      }
     };
 
     query = match self.listing_type {
@@ -249,41 +370,156 @@ impl<'a> CommentQueryBuilder<'a> {
       query = query.filter(saved.eq(true));
     }
 
-    query = match self.sort {
-      SortType::Hot => query
-        .order_by(hot_rank.desc())
-        .then_order_by(published.desc()),
-      SortType::Active => query
-        .order_by(hot_rank_active.desc())
-        .then_order_by(published.desc()),
-      SortType::New => query.order_by(published.desc()),
-      SortType::TopAll => query.order_by(score.desc()),
-      SortType::TopYear => query
-        .filter(published.gt(now - 1.years()))
-        .order_by(score.desc()),
-      SortType::TopMonth => query
-        .filter(published.gt(now - 1.months()))
-        .order_by(score.desc()),
-      SortType::TopWeek => query
-        .filter(published.gt(now - 1.weeks()))
-        .order_by(score.desc()),
-      SortType::TopDay => query
-        .filter(published.gt(now - 1.days()))
-        .order_by(score.desc()),
-      // _ => query.order_by(published.desc()),
+    // Private communities only show their comments to members
+    let hidden_communities = hidden_private_communities(self.conn, self.my_user_id)?;
+    if !hidden_communities.is_empty() {
+      query = query.filter(not(community_id.eq_any(hidden_communities)));
+    }
+
+    // The pinned comment of a post comes first, whatever the sort
+    if let Some(for_post_id) = self.for_post_id {
+      query = query.order_by(
+        sql::<Bool>(&format!(
+          "comment_fast_view.id is not distinct from \
+            (select p.pinned_comment_id from post p where p.id = {})",
+          for_post_id
+        ))
+        .desc(),
+      );
+    }
+
+    query = match self.comment_sort {
+      Some(comment_sort) => order_by_comment_sort(query, &comment_sort, self.for_post_id),
+      None => {
+        let shown_score = || sql::<BigInt>(SHOWN_SCORE_SQL).desc();
+        let query = match self.sort {
+          SortType::Hot => query.then_order_by(sql::<Integer>(SHOWN_HOT_RANK_SQL).desc()),
+          SortType::Active => query.then_order_by(sql::<Integer>(SHOWN_HOT_RANK_ACTIVE_SQL).desc()),
+          SortType::New => query,
+          SortType::TopAll => query.then_order_by(shown_score()),
+          SortType::TopYear => query
+            .filter(published.gt(now - 1.years()))
+            .then_order_by(shown_score()),
+          SortType::TopMonth => query
+            .filter(published.gt(now - 1.months()))
+            .then_order_by(shown_score()),
+          SortType::TopWeek => query
+            .filter(published.gt(now - 1.weeks()))
+            .then_order_by(shown_score()),
+          SortType::TopDay => query
+            .filter(published.gt(now - 1.days()))
+            .then_order_by(shown_score()),
+        };
+        query.then_order_by(published.desc())
+      }
     };
 
     let (limit, offset) = limit_and_offset(self.page, self.limit);
 
     // Note: deleted and removed comments are done on the front side
-    query
+    let comments = query
       .limit(limit)
       .offset(offset)
-      .load::<CommentView>(self.conn)
+      .load::<CommentView>(self.conn)?;
+    CommentView::hide_for_viewer(self.conn, comments, self.my_user_id)
+  }
+}
+
+/// Reddit's controversy: high when there are many votes, and about as many up as down.
+const CONTROVERSY_SQL: &str = "case when score_hidden or upvotes = 0 or downvotes = 0 then 0 \
+  else power(upvotes + downvotes, least(upvotes, downvotes)::float / greatest(upvotes, downvotes)) \
+  end";
+
+/// Only appends to the ordering, which starts with the pinned comment when listing a post.
+fn order_by_comment_sort<'a>(
+  query: comment_fast_view::BoxedQuery<'a, Pg>,
+  comment_sort: &CommentSortType,
+  for_post_id: Option<i32>,
+) -> comment_fast_view::BoxedQuery<'a, Pg> {
+  use super::comment_view::comment_fast_view::dsl::*;
+  use diesel::sql_types::{BigInt, Bool, Double, Integer};
+
+  match (comment_sort, for_post_id) {
+    (CommentSortType::Hot, _) => query
+      .then_order_by(sql::<Integer>(SHOWN_HOT_RANK_SQL).desc())
+      .then_order_by(published.desc()),
+    (CommentSortType::New, _) => query.then_order_by(published.desc()),
+    (CommentSortType::Old, _) => query.then_order_by(published.asc()),
+    (CommentSortType::Controversial, _) => query
+      .then_order_by(sql::<Double>(CONTROVERSY_SQL).desc())
+      .then_order_by(published.desc()),
+    (CommentSortType::QA, Some(for_post_id)) => {
+      let post_creator = format!(
+        "(select p.creator_id from post p where p.id = {})",
+        for_post_id
+      );
+      query
+        .then_order_by(
+          sql::<Bool>(&format!("comment_fast_view.creator_id = {}", post_creator)).desc(),
+        )
+        .then_order_by(
+          sql::<Bool>(&format!(
+            "exists (select 1 from comment c where c.parent_id = comment_fast_view.id \
+              and c.creator_id = {})",
+            post_creator
+          ))
+          .desc(),
+        )
+        .then_order_by(sql::<BigInt>(SHOWN_SCORE_SQL).desc())
+        .then_order_by(published.asc())
+    }
+    // Without a post there is no creator to boost
+    (CommentSortType::Top, _) | (CommentSortType::QA, None) => query
+      .then_order_by(sql::<BigInt>(SHOWN_SCORE_SQL).desc())
+      .then_order_by(published.desc()),
   }
 }
 
 impl CommentView {
+  /// Like for posts, young comments don't show their score yet.
+  fn hide_score(mut self) -> Self {
+    if self.score_hidden {
+      self.score = 0;
+      self.upvotes = 0;
+      self.downvotes = 0;
+    }
+    self
+  }
+
+  /// Hides the scores which aren't shown yet, and leaves the votes from the instances which the
+  /// viewer ignores out of the others.
+  fn hide_for_viewer(
+    conn: &PgConnection,
+    comments: Vec<Self>,
+    my_user_id: Option<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    let mut comments = comments
+      .into_iter()
+      .map(Self::hide_score)
+      .collect::<Vec<Self>>();
+    let instances = match my_user_id {
+      Some(my_user_id) => UserIgnoredVoteInstance::instances_for_user(conn, my_user_id)?,
+      None => return Ok(comments),
+    };
+    if instances.is_empty() {
+      return Ok(comments);
+    }
+    let comment_ids = comments
+      .iter()
+      .filter(|c| !c.score_hidden)
+      .map(|c| c.id)
+      .collect::<Vec<i32>>();
+    let ignored_votes = comment_votes_from_instances(conn, comment_ids, instances)?;
+    for comment in comments.iter_mut() {
+      if let Some((up, down)) = ignored_votes.get(&comment.id) {
+        comment.upvotes -= up;
+        comment.downvotes -= down;
+        comment.score -= up - down;
+      }
+    }
+    Ok(comments)
+  }
+
   pub fn read(
     conn: &PgConnection,
     from_comment_id: i32,
@@ -303,7 +539,32 @@ impl CommentView {
       .filter(id.eq(from_comment_id))
       .order_by(published.desc());
 
-    query.first::<Self>(conn)
+    let comment = query.first::<Self>(conn)?;
+    Ok(Self::hide_for_viewer(conn, vec![comment], my_user_id)?.remove(0))
+  }
+
+  /// The direct replies to these comments, oldest first.
+  pub fn list_replies(
+    conn: &PgConnection,
+    parent_ids: Vec<i32>,
+    my_user_id: Option<i32>,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use super::comment_view::comment_fast_view::dsl::*;
+    let mut query = comment_fast_view.into_boxed();
+
+    if let Some(my_user_id) = my_user_id {
+      query = query.filter(user_id.eq(my_user_id));
+    } else {
+      query = query.filter(user_id.is_null());
+    }
+
+    let comments = query
+      .filter(parent_id.eq_any(parent_ids))
+      .order_by(published.asc())
+      .limit(limit)
+      .load::<Self>(conn)?;
+    Self::hide_for_viewer(conn, comments, my_user_id)
   }
 }
 
@@ -323,6 +584,7 @@ table! {
     deleted -> Bool,
     ap_id -> Text,
     local -> Bool,
+    distinguished -> Bool,
     community_id -> Int4,
     community_actor_id -> Text,
     community_local -> Bool,
@@ -367,6 +629,7 @@ pub struct ReplyView {
   pub deleted: bool,
   pub ap_id: String,
   pub local: bool,
+  pub distinguished: bool,
   pub community_id: i32,
   pub community_actor_id: String,
   pub community_local: bool,
@@ -523,6 +786,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -565,6 +834,10 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
       ap_id: None,
       local: true,
       published: None,
@@ -584,6 +857,7 @@ mod tests {
       updated: None,
       ap_id: None,
       local: true,
+      distinguished: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
@@ -627,8 +901,10 @@ mod tests {
       my_vote: None,
       subscribed: None,
       saved: None,
+      score_hidden: false,
       ap_id: inserted_comment.ap_id.to_owned(),
       local: true,
+      distinguished: false,
       community_actor_id: inserted_community.actor_id.to_owned(),
       community_local: true,
       creator_actor_id: inserted_user.actor_id.to_owned(),
@@ -665,8 +941,10 @@ mod tests {
       my_vote: Some(1),
       subscribed: Some(false),
       saved: Some(false),
+      score_hidden: false,
       ap_id: inserted_comment.ap_id.to_owned(),
       local: true,
+      distinguished: false,
       community_actor_id: inserted_community.actor_id.to_owned(),
       community_local: true,
       creator_actor_id: inserted_user.actor_id.to_owned(),
@@ -688,6 +966,70 @@ mod tests {
     read_comment_views_with_user[0].hot_rank = 0;
     read_comment_views_with_user[0].hot_rank_active = 0;
 
+    // Every sort has to put the pinned comment first, also before a newer one without votes
+    let newer_comment_form = CommentForm {
+      content: "A newer comment".into(),
+      ..comment_form.to_owned()
+    };
+    let newer_comment = Comment::create(&conn, &newer_comment_form).unwrap();
+    let pinned_post =
+      Post::update_pinned_comment(&conn, inserted_post.id, Some(inserted_comment.id)).unwrap();
+    let sorted_comment_ids = [
+      CommentSortType::Hot,
+      CommentSortType::New,
+      CommentSortType::Old,
+      CommentSortType::Top,
+      CommentSortType::Controversial,
+      CommentSortType::QA,
+    ]
+    .iter()
+    .map(|comment_sort| {
+      CommentQueryBuilder::create(&conn)
+        .for_post_id(inserted_post.id)
+        .comment_sort(comment_sort.to_owned())
+        .list()
+        .unwrap()
+        .iter()
+        .map(|c| c.id)
+        .collect::<Vec<i32>>()
+    })
+    .collect::<Vec<Vec<i32>>>();
+    Comment::delete(&conn, newer_comment.id).unwrap();
+
+    // The comment has a score of 1, and its creator isn't a bot
+    let collapsing_viewer_form = UserForm {
+      name: "timmy_collapser".into(),
+      collapse_comments_below_score: Some(2),
+      collapse_bot_comments: true,
+      ..new_user
+    };
+    let collapsing_viewer = User_::create(&conn, &collapsing_viewer_form).unwrap();
+    let collapsed_for_viewer = CollapsibleCommentView::from_comments(
+      &conn,
+      read_comment_views_no_user.to_owned(),
+      Some(collapsing_viewer.id),
+    )
+    .unwrap();
+    let collapsed_for_creator = CollapsibleCommentView::from_comments(
+      &conn,
+      read_comment_views_no_user.to_owned(),
+      Some(inserted_user.id),
+    )
+    .unwrap();
+    let collapsed_no_user =
+      CollapsibleCommentView::from_comments(&conn, read_comment_views_no_user.to_owned(), None)
+        .unwrap();
+    User_::delete(&conn, collapsing_viewer.id).unwrap();
+
+    let reply_form = CommentForm {
+      content: "A test reply".into(),
+      parent_id: Some(inserted_comment.id),
+      ..comment_form
+    };
+    let inserted_reply = Comment::create(&conn, &reply_form).unwrap();
+    let replies = CommentView::list_replies(&conn, vec![inserted_comment.id], None, 10).unwrap();
+    Comment::delete(&conn, inserted_reply.id).unwrap();
+
     let like_removed = CommentLike::remove(&conn, inserted_user.id, inserted_comment.id).unwrap();
     let num_deleted = Comment::delete(&conn, inserted_comment.id).unwrap();
     Post::delete(&conn, inserted_post.id).unwrap();
@@ -699,7 +1041,203 @@ mod tests {
       expected_comment_view_with_user,
       read_comment_views_with_user[0]
     );
+    assert_eq!(Some(inserted_comment.id), pinned_post.pinned_comment_id);
+    assert_eq!(
+      vec![vec![inserted_comment.id, newer_comment.id]; 6],
+      sorted_comment_ids
+    );
+    assert!(collapsed_for_viewer[0].collapsed);
+    assert!(!collapsed_for_creator[0].collapsed);
+    assert!(!collapsed_no_user[0].collapsed);
+    assert_eq!(expected_comment_view_no_user, collapsed_no_user[0].comment);
+    assert_eq!(1, replies.len());
+    assert_eq!(Some(inserted_comment.id), replies[0].parent_id);
     assert_eq!(1, num_deleted);
     assert_eq!(1, like_removed);
   }
+
+  #[test]
+  fn test_comment_sorts() {
+    let conn = establish_unpooled_connection();
+
+    let user_form = UserForm {
+      name: "sort_op".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+    let op = User_::create(&conn, &user_form).unwrap();
+    let commenter = User_::create(
+      &conn,
+      &UserForm {
+        name: "sort_commenter".into(),
+        ..user_form.to_owned()
+      },
+    )
+    .unwrap();
+    let voter = User_::create(
+      &conn,
+      &UserForm {
+        name: "sort_voter".into(),
+        ..user_form.to_owned()
+      },
+    )
+    .unwrap();
+    let other_voter = User_::create(
+      &conn,
+      &UserForm {
+        name: "sort_other_voter".into(),
+        ..user_form
+      },
+    )
+    .unwrap();
+
+    let new_community = CommunityForm {
+      name: "comment_sorts".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: op.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A post to sort the comments of".into(),
+      creator_id: op.id,
+      url: None,
+      body: None,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let test_start = naive_now();
+    let create_comment = |creator_id: i32, parent_id: Option<i32>, hours_ago: i64| {
+      let form = CommentForm {
+        content: "A comment to sort".into(),
+        creator_id,
+        post_id: inserted_post.id,
+        parent_id,
+        removed: None,
+        deleted: None,
+        read: None,
+        published: Some(test_start - chrono::Duration::hours(hours_ago)),
+        updated: None,
+        ap_id: None,
+        local: true,
+        distinguished: None,
+      };
+      Comment::create(&conn, &form).unwrap().id
+    };
+    let vote = |user_id: i32, comment_id: i32, score: i16| {
+      let form = CommentLikeForm {
+        comment_id,
+        post_id: inserted_post.id,
+        user_id,
+        score,
+      };
+      CommentLike::like(&conn, &form).unwrap();
+    };
+
+    // Score 2, not controversial
+    let liked = create_comment(commenter.id, None, 5);
+    vote(voter.id, liked, 1);
+    vote(other_voter.id, liked, 1);
+    // Score 0, but one vote in each direction
+    let disputed = create_comment(commenter.id, None, 4);
+    vote(voter.id, disputed, 1);
+    vote(other_voter.id, disputed, -1);
+    // Score 0, by the post creator
+    let by_op = create_comment(op.id, None, 3);
+    // Score -1, answered by the post creator
+    let answered = create_comment(commenter.id, None, 2);
+    vote(voter.id, answered, -1);
+    let answer = create_comment(op.id, Some(answered), 1);
+
+    let sorted_comment_ids = |comment_sort: CommentSortType| {
+      CommentQueryBuilder::create(&conn)
+        .for_post_id(inserted_post.id)
+        .comment_sort(comment_sort)
+        .list()
+        .unwrap()
+        .iter()
+        .map(|c| c.id)
+        .collect::<Vec<i32>>()
+    };
+    let new = sorted_comment_ids(CommentSortType::New);
+    let old = sorted_comment_ids(CommentSortType::Old);
+    let top = sorted_comment_ids(CommentSortType::Top);
+    let controversial = sorted_comment_ids(CommentSortType::Controversial);
+    let qa = sorted_comment_ids(CommentSortType::QA);
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    for user in &[op, commenter, voter, other_voter] {
+      User_::delete(&conn, user.id).unwrap();
+    }
+
+    assert_eq!(vec![answer, answered, by_op, disputed, liked], new);
+    assert_eq!(vec![liked, disputed, by_op, answered, answer], old);
+    // Ties by score are broken by the newest comment
+    assert_eq!(vec![liked, answer, by_op, disputed, answered], top);
+    assert_eq!(
+      vec![disputed, answer, answered, by_op, liked],
+      controversial
+    );
+    // The comments of the post creator, oldest first, then what they answered, then by score
+    assert_eq!(vec![by_op, answer, answered, liked, disputed], qa);
+  }
 }
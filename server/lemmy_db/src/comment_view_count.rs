@@ -0,0 +1,193 @@
+use crate::schema::comment_view_count;
+use diesel::{dsl::*, result::Error, *};
+
+/// The views of a comment through its context, kept like `PostViewCount`.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
+#[table_name = "comment_view_count"]
+pub struct CommentViewCount {
+  pub id: i32,
+  pub comment_id: i32,
+  pub views: i64,
+  pub visitors: Vec<u8>,
+  pub unique_views: i64,
+  pub updated: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Debug)]
+#[table_name = "comment_view_count"]
+pub struct CommentViewCountForm {
+  pub comment_id: i32,
+  pub views: i64,
+  pub visitors: Vec<u8>,
+  pub unique_views: i64,
+  pub updated: chrono::NaiveDateTime,
+}
+
+impl CommentViewCount {
+  pub fn read_for_comment(conn: &PgConnection, for_comment_id: i32) -> Result<Option<Self>, Error> {
+    use crate::schema::comment_view_count::dsl::*;
+    comment_view_count
+      .filter(comment_id.eq(for_comment_id))
+      .first::<Self>(conn)
+      .optional()
+  }
+
+  /// Replaces the counts of the comment, which the caller merged with the kept ones.
+  pub fn upsert(conn: &PgConnection, form: &CommentViewCountForm) -> Result<Self, Error> {
+    use crate::schema::comment_view_count::dsl::*;
+    insert_into(comment_view_count)
+      .values(form)
+      .on_conflict(comment_id)
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    comment::*,
+    comment_view_count::*,
+    community::*,
+    naive_now,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "viewed_commenter".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_viewed_comments".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A post with viewed comments".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A viewed comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      parent_id: None,
+      removed: None,
+      deleted: None,
+      read: None,
+      published: None,
+      updated: None,
+      ap_id: None,
+      local: true,
+      distinguished: None,
+    };
+
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let form = |views: i64, unique_views: i64| CommentViewCountForm {
+      comment_id: inserted_comment.id,
+      views,
+      visitors: vec![0, 1, 2],
+      unique_views,
+      updated: naive_now(),
+    };
+    let not_viewed = CommentViewCount::read_for_comment(&conn, inserted_comment.id).unwrap();
+    let inserted = CommentViewCount::upsert(&conn, &form(5, 2)).unwrap();
+    let updated = CommentViewCount::upsert(&conn, &form(8, 3)).unwrap();
+    let read = CommentViewCount::read_for_comment(&conn, inserted_comment.id).unwrap();
+
+    // The counts go with the comment
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    let after_delete = CommentViewCount::read_for_comment(&conn, inserted_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(None, not_viewed);
+    assert_eq!(inserted.id, updated.id);
+    assert_eq!(8, updated.views);
+    assert_eq!(3, updated.unique_views);
+    assert_eq!(vec![0, 1, 2], updated.visitors);
+    assert_eq!(Some(updated), read);
+    assert_eq!(None, after_delete);
+  }
+}
@@ -1,12 +1,23 @@
 use crate::{
   naive_now,
-  schema::{community, community_follower, community_moderator, community_user_ban},
+  schema::{
+    community,
+    community_appearance,
+    community_follower,
+    community_join_request,
+    community_moderator,
+    community_settings,
+    community_user_ban,
+    user_,
+  },
+  user::{User_, DELETED_USER_NAME},
   Bannable,
   Crud,
   Followable,
   Joinable,
 };
 use diesel::{dsl::*, result::Error, *};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Queryable, Identifiable, PartialEq, Debug)]
 #[table_name = "community"]
@@ -98,6 +109,77 @@ impl Community {
       .first::<Self>(conn)
   }
 
+  /// Remote communities that weren't refetched since the given time, the oldest first.
+  pub fn list_stale_remote(
+    conn: &PgConnection,
+    refreshed_before: chrono::NaiveDateTime,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::community::dsl::*;
+    community
+      .filter(local.eq(false))
+      .filter(deleted.eq(false))
+      .filter(last_refreshed_at.lt(refreshed_before))
+      .order_by(last_refreshed_at.asc())
+      .limit(limit)
+      .load::<Self>(conn)
+  }
+
+  /// The remote communities which local users follow or asked to join, see `CommunityFollower`.
+  pub fn list_remote_with_local_followers(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    let local_users = || user_::table.filter(user_::local.eq(true)).select(user_::id);
+    let followed = community_follower::table
+      .filter(community_follower::user_id.eq_any(local_users()))
+      .select(community_follower::community_id);
+    let requested = community_join_request::table
+      .filter(community_join_request::user_id.eq_any(local_users()))
+      .select(community_join_request::community_id);
+    community::table
+      .filter(community::local.eq(false))
+      .filter(community::deleted.eq(false))
+      .filter(
+        community::id
+          .eq_any(followed)
+          .or(community::id.eq_any(requested)),
+      )
+      .order_by(community::id)
+      .load::<Self>(conn)
+  }
+
+  /// Moves the community to the back of the refetch queue, eg if its instance is unreachable.
+  pub fn mark_refreshed(conn: &PgConnection, community_id: i32) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set(last_refreshed_at.eq(naive_now()))
+      .get_result::<Self>(conn)
+  }
+
+  /// The local communities whose keys sign activities, that is those which weren't deleted.
+  pub fn list_local_ids(conn: &PgConnection) -> Result<Vec<i32>, Error> {
+    use crate::schema::community::dsl::*;
+    community
+      .filter(local.eq(true))
+      .filter(deleted.eq(false))
+      .select(id)
+      .order_by(id)
+      .load::<i32>(conn)
+  }
+
+  pub fn update_keys(
+    conn: &PgConnection,
+    community_id: i32,
+    new_private_key: &str,
+    new_public_key: &str,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set((
+        private_key.eq(new_private_key),
+        public_key.eq(new_public_key),
+      ))
+      .get_result::<Self>(conn)
+  }
+
   pub fn update_deleted(
     conn: &PgConnection,
     community_id: i32,
@@ -303,6 +385,377 @@ impl Followable<CommunityFollowerForm> for CommunityFollower {
   }
 }
 
+impl CommunityFollower {
+  pub fn is_follower(
+    conn: &PgConnection,
+    for_community_id: i32,
+    for_user_id: i32,
+  ) -> Result<bool, Error> {
+    use crate::schema::community_follower::dsl::*;
+    select(exists(
+      community_follower
+        .filter(community_id.eq(for_community_id))
+        .filter(user_id.eq(for_user_id)),
+    ))
+    .get_result(conn)
+  }
+
+  /// The number of users who followed the community since the given time, and still follow it.
+  pub fn count_since(
+    conn: &PgConnection,
+    for_community_id: i32,
+    since: chrono::NaiveDateTime,
+  ) -> Result<i64, Error> {
+    use crate::schema::community_follower::dsl::*;
+    community_follower
+      .filter(community_id.eq(for_community_id))
+      .filter(published.gt(since))
+      .count()
+      .get_result(conn)
+  }
+
+  /// The ids of the follows of the community with the actor ids of the followers, newest first, for
+  /// the pages of its followers collection. Only the followers from the instance, if given, like
+  /// `lemmy.ml`.
+  pub fn list_actor_ids(
+    conn: &PgConnection,
+    for_community_id: i32,
+    instance: Option<&str>,
+    before: Option<i32>,
+    limit: i64,
+  ) -> Result<Vec<(i32, String)>, Error> {
+    let mut query = community_follower::table
+      .inner_join(user_::table)
+      .filter(community_follower::community_id.eq(for_community_id))
+      .select((community_follower::id, user_::actor_id))
+      .into_boxed();
+    if let Some(instance) = instance {
+      let instance = instance
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+      query = query.filter(user_::actor_id.like(format!("%://{}/%", instance)));
+    }
+    if let Some(before) = before {
+      query = query.filter(community_follower::id.lt(before));
+    }
+    query
+      .order_by(community_follower::id.desc())
+      .limit(limit)
+      .load::<(i32, String)>(conn)
+  }
+
+  pub fn list_local_followers(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<Vec<User_>, Error> {
+    community_follower::table
+      .inner_join(user_::table)
+      .filter(community_follower::community_id.eq(for_community_id))
+      .filter(user_::local.eq(true))
+      .select(user_::all_columns)
+      .load::<User_>(conn)
+  }
+
+  /// Subscribes every local user who isn't banned, and returns the number of new subscriptions.
+  /// Bots, like the automod users, and the placeholder of deleted users are left out.
+  pub fn follow_all_local_users(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<usize, Error> {
+    sql_query(
+      "insert into community_follower (community_id, user_id) \
+       select $1, id from user_ where local and not banned and not deleted and not bot_account \
+       and name <> $2 \
+       on conflict do nothing",
+    )
+    .bind::<diesel::sql_types::Integer, _>(for_community_id)
+    .bind::<diesel::sql_types::Text, _>(DELETED_USER_NAME)
+    .execute(conn)
+  }
+}
+
+/// The ids of communities which opted out of search, which the search queries leave out.
+pub fn undiscoverable_communities(conn: &PgConnection) -> Result<Vec<i32>, Error> {
+  community_settings::table
+    .select(community_settings::community_id)
+    .filter(community_settings::discoverable.eq(false))
+    .load::<i32>(conn)
+}
+
+/// The ids of the private communities whose content this user can't see, because they are
+/// neither a member nor a mod of them. Logged out users can't see any, admins see all of them.
+pub fn hidden_private_communities(
+  conn: &PgConnection,
+  viewer_id: Option<i32>,
+) -> Result<Vec<i32>, Error> {
+  let private_communities = community_settings::table
+    .filter(community_settings::private.eq(true))
+    .select(community_settings::community_id);
+  let viewer_id = match viewer_id {
+    Some(viewer_id) => viewer_id,
+    None => return private_communities.load::<i32>(conn),
+  };
+
+  let is_admin = user_::table
+    .find(viewer_id)
+    .select(user_::admin)
+    .first::<bool>(conn)
+    .optional()?
+    .unwrap_or(false);
+  if is_admin {
+    return Ok(Vec::new());
+  }
+
+  let followed = community_follower::table
+    .filter(community_follower::user_id.eq(viewer_id))
+    .select(community_follower::community_id)
+    .load::<i32>(conn)?;
+  let moderated = community_moderator::table
+    .filter(community_moderator::user_id.eq(viewer_id))
+    .select(community_moderator::community_id)
+    .load::<i32>(conn)?;
+  Ok(
+    private_communities
+      .load::<i32>(conn)?
+      .into_iter()
+      .filter(|id| !followed.contains(id) && !moderated.contains(id))
+      .collect(),
+  )
+}
+
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Clone)]
+#[belongs_to(Community)]
+#[table_name = "community_settings"]
+pub struct CommunitySettings {
+  pub id: i32,
+  pub community_id: i32,
+  pub require_alt_text: bool,
+  pub trust_min_account_age_days: Option<i32>,
+  pub trust_min_karma: Option<i32>,
+  pub allow_anonymous_posts: bool,
+  pub join_requires_approval: bool,
+  pub join_question: Option<String>,
+  pub private: bool,
+  pub local_only: bool,
+  pub allow_authors_close_comments: bool,
+  pub automod_comment: Option<String>,
+  pub automod_user_id: Option<i32>,
+  pub welcome_message: Option<String>,
+  pub max_post_body_length: Option<i32>,
+  pub max_comment_length: Option<i32>,
+  pub allowed_post_type: String,
+  pub require_post_tag: bool,
+  pub title_pattern: Option<String>,
+  pub clean_titles: bool,
+  pub title_capitalization: String,
+  pub allow_post_url_edits: bool,
+  pub microblog_mentions: String,
+  pub digest_template: Option<String>,
+  pub discoverable: bool,
+}
+
+/// Which kinds of posts a community accepts.
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+pub enum AllowedPostType {
+  Any,
+  /// Posts without a url
+  TextOnly,
+  /// Posts with a url
+  LinkOnly,
+}
+
+/// How a community changes the capitalization of new post titles.
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+pub enum TitleCapitalization {
+  None,
+  /// Makes the first letter uppercase
+  CapitalizeFirst,
+  /// Titles in all caps are lowercased, except for their first letter
+  NoAllCaps,
+}
+
+/// What a local community does with public notes from microblogs, like Mastodon, which mention it.
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+pub enum MicroblogMentions {
+  Ignore,
+  /// Each note becomes a post
+  Post,
+  /// Notes linking to a post of the community become comments on it, the others become posts
+  PostOrComment,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_settings"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CommunitySettingsForm {
+  pub community_id: i32,
+  pub require_alt_text: bool,
+  pub trust_min_account_age_days: Option<i32>,
+  pub trust_min_karma: Option<i32>,
+  pub allow_anonymous_posts: bool,
+  pub join_requires_approval: bool,
+  pub join_question: Option<String>,
+  pub private: bool,
+  pub local_only: bool,
+  pub allow_authors_close_comments: bool,
+  pub automod_comment: Option<String>,
+  pub automod_user_id: Option<i32>,
+  pub welcome_message: Option<String>,
+  pub max_post_body_length: Option<i32>,
+  pub max_comment_length: Option<i32>,
+  pub allowed_post_type: String,
+  pub require_post_tag: bool,
+  pub title_pattern: Option<String>,
+  pub clean_titles: bool,
+  pub title_capitalization: String,
+  pub allow_post_url_edits: bool,
+  pub microblog_mentions: String,
+  pub digest_template: Option<String>,
+  pub discoverable: bool,
+}
+
+impl CommunitySettings {
+  /// Communities that never saved any settings get the defaults.
+  pub fn read_for_community(conn: &PgConnection, for_community_id: i32) -> Result<Self, Error> {
+    use crate::schema::community_settings::dsl::*;
+    let settings = community_settings
+      .filter(community_id.eq(for_community_id))
+      .first::<Self>(conn)
+      .optional()?;
+    Ok(settings.unwrap_or(CommunitySettings {
+      id: 0,
+      community_id: for_community_id,
+      require_alt_text: false,
+      trust_min_account_age_days: None,
+      trust_min_karma: None,
+      allow_anonymous_posts: false,
+      join_requires_approval: false,
+      join_question: None,
+      private: false,
+      local_only: false,
+      allow_authors_close_comments: false,
+      automod_comment: None,
+      automod_user_id: None,
+      welcome_message: None,
+      max_post_body_length: None,
+      max_comment_length: None,
+      allowed_post_type: AllowedPostType::Any.to_string(),
+      require_post_tag: false,
+      title_pattern: None,
+      clean_titles: false,
+      title_capitalization: TitleCapitalization::None.to_string(),
+      allow_post_url_edits: true,
+      microblog_mentions: MicroblogMentions::Ignore.to_string(),
+      digest_template: None,
+      discoverable: true,
+    }))
+  }
+
+  pub fn parsed_allowed_post_type(&self) -> AllowedPostType {
+    self
+      .allowed_post_type
+      .parse()
+      .unwrap_or(AllowedPostType::Any)
+  }
+
+  pub fn parsed_title_capitalization(&self) -> TitleCapitalization {
+    self
+      .title_capitalization
+      .parse()
+      .unwrap_or(TitleCapitalization::None)
+  }
+
+  pub fn parsed_microblog_mentions(&self) -> MicroblogMentions {
+    self
+      .microblog_mentions
+      .parse()
+      .unwrap_or(MicroblogMentions::Ignore)
+  }
+
+  /// Only members can post in communities which require approval to join, or which are private.
+  pub fn requires_membership(&self) -> bool {
+    self.join_requires_approval || self.private
+  }
+
+  pub fn upsert(conn: &PgConnection, form: &CommunitySettingsForm) -> Result<Self, Error> {
+    use crate::schema::community_settings::dsl::*;
+    insert_into(community_settings)
+      .values(form)
+      .on_conflict(community_id)
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// For remote communities, which only have the flag their instance sent.
+  pub fn update_discoverable(
+    conn: &PgConnection,
+    for_community_id: i32,
+    new_discoverable: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::community_settings::dsl::*;
+    insert_into(community_settings)
+      .values((
+        community_id.eq(for_community_id),
+        discoverable.eq(new_discoverable),
+      ))
+      .on_conflict(community_id)
+      .do_update()
+      .set(discoverable.eq(new_discoverable))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Clone)]
+#[belongs_to(Community)]
+#[table_name = "community_appearance"]
+pub struct CommunityAppearance {
+  pub id: i32,
+  pub community_id: i32,
+  pub accent_color: Option<String>,
+  pub default_sort: Option<String>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_appearance"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CommunityAppearanceForm {
+  pub community_id: i32,
+  pub accent_color: Option<String>,
+  pub default_sort: Option<String>,
+}
+
+impl CommunityAppearance {
+  /// Communities without one look like the rest of the site.
+  pub fn read_for_community(conn: &PgConnection, for_community_id: i32) -> Result<Self, Error> {
+    use crate::schema::community_appearance::dsl::*;
+    let appearance = community_appearance
+      .filter(community_id.eq(for_community_id))
+      .first::<Self>(conn)
+      .optional()?;
+    Ok(appearance.unwrap_or(CommunityAppearance {
+      id: 0,
+      community_id: for_community_id,
+      accent_color: None,
+      default_sort: None,
+    }))
+  }
+
+  pub fn upsert(conn: &PgConnection, form: &CommunityAppearanceForm) -> Result<Self, Error> {
+    use crate::schema::community_appearance::dsl::*;
+    insert_into(community_appearance)
+      .values(form)
+      .on_conflict(community_id)
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use crate::{community::*, tests::establish_unpooled_connection, user::*, ListingType, SortType};
@@ -335,6 +788,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -426,9 +885,115 @@ mod tests {
       published: inserted_community_user_ban.published,
     };
 
+    let default_settings =
+      CommunitySettings::read_for_community(&conn, inserted_community.id).unwrap();
+
+    let community_settings_form = CommunitySettingsForm {
+      community_id: inserted_community.id,
+      require_alt_text: true,
+      trust_min_account_age_days: Some(7),
+      trust_min_karma: None,
+      allow_anonymous_posts: true,
+      join_requires_approval: true,
+      join_question: Some("Why do you want to join?".into()),
+      private: true,
+      local_only: true,
+      allow_authors_close_comments: true,
+      automod_comment: Some("Please read the rules.".into()),
+      automod_user_id: None,
+      welcome_message: Some("Welcome!".into()),
+      max_post_body_length: Some(1000),
+      max_comment_length: None,
+      allowed_post_type: AllowedPostType::TextOnly.to_string(),
+      require_post_tag: true,
+      title_pattern: Some("^\\[[A-Z]+\\]".into()),
+      clean_titles: true,
+      title_capitalization: TitleCapitalization::NoAllCaps.to_string(),
+      allow_post_url_edits: false,
+      microblog_mentions: MicroblogMentions::PostOrComment.to_string(),
+      digest_template: Some("This week in {community}:\n\n{top_posts}".into()),
+      discoverable: false,
+    };
+
+    CommunitySettings::upsert(&conn, &community_settings_form).unwrap();
+    let upserted_settings = CommunitySettings::upsert(&conn, &community_settings_form).unwrap();
+
+    let expected_settings = CommunitySettings {
+      id: upserted_settings.id,
+      community_id: inserted_community.id,
+      require_alt_text: true,
+      trust_min_account_age_days: Some(7),
+      trust_min_karma: None,
+      allow_anonymous_posts: true,
+      join_requires_approval: true,
+      join_question: Some("Why do you want to join?".into()),
+      private: true,
+      local_only: true,
+      allow_authors_close_comments: true,
+      automod_comment: Some("Please read the rules.".into()),
+      automod_user_id: None,
+      welcome_message: Some("Welcome!".into()),
+      max_post_body_length: Some(1000),
+      max_comment_length: None,
+      allowed_post_type: AllowedPostType::TextOnly.to_string(),
+      require_post_tag: true,
+      title_pattern: Some("^\\[[A-Z]+\\]".into()),
+      clean_titles: true,
+      title_capitalization: TitleCapitalization::NoAllCaps.to_string(),
+      allow_post_url_edits: false,
+      microblog_mentions: MicroblogMentions::PostOrComment.to_string(),
+      digest_template: Some("This week in {community}:\n\n{top_posts}".into()),
+      discoverable: false,
+    };
+
+    let read_settings =
+      CommunitySettings::read_for_community(&conn, inserted_community.id).unwrap();
+
+    let default_appearance =
+      CommunityAppearance::read_for_community(&conn, inserted_community.id).unwrap();
+    let appearance_form = CommunityAppearanceForm {
+      community_id: inserted_community.id,
+      accent_color: Some("#1e90ff".into()),
+      default_sort: Some("New".into()),
+    };
+    CommunityAppearance::upsert(&conn, &appearance_form).unwrap();
+    let upserted_appearance = CommunityAppearance::upsert(&conn, &appearance_form).unwrap();
+    let read_appearance =
+      CommunityAppearance::read_for_community(&conn, inserted_community.id).unwrap();
+    let hidden_from_guests = hidden_private_communities(&conn, None).unwrap();
+    let hidden_from_follower = hidden_private_communities(&conn, Some(inserted_user.id)).unwrap();
+
     let read_community = Community::read(&conn, inserted_community.id).unwrap();
     let updated_community =
       Community::update(&conn, inserted_community.id, &new_community).unwrap();
+    let is_follower =
+      CommunityFollower::is_follower(&conn, inserted_community.id, inserted_user.id).unwrap();
+    let follower_actor_ids =
+      CommunityFollower::list_actor_ids(&conn, inserted_community.id, None, None, 10).unwrap();
+    let other_instance_actor_ids = CommunityFollower::list_actor_ids(
+      &conn,
+      inserted_community.id,
+      Some("example.com"),
+      None,
+      10,
+    )
+    .unwrap();
+    let wildcard_actor_ids =
+      CommunityFollower::list_actor_ids(&conn, inserted_community.id, Some("%"), None, 10).unwrap();
+    let older_actor_ids = CommunityFollower::list_actor_ids(
+      &conn,
+      inserted_community.id,
+      None,
+      Some(inserted_community_follower.id),
+      10,
+    )
+    .unwrap();
+    let local_followers =
+      CommunityFollower::list_local_followers(&conn, inserted_community.id).unwrap();
+    let remote_followed = Community::list_remote_with_local_followers(&conn).unwrap();
+    let local_ids = Community::list_local_ids(&conn).unwrap();
+    let rotated_community =
+      Community::update_keys(&conn, inserted_community.id, "private", "public").unwrap();
     let ignored_community = CommunityFollower::unfollow(&conn, &community_follower_form).unwrap();
     let left_community = CommunityModerator::leave(&conn, &community_user_form).unwrap();
     let unban = CommunityUserBan::unban(&conn, &community_user_ban_form).unwrap();
@@ -439,8 +1004,57 @@ mod tests {
     assert_eq!(expected_community, inserted_community);
     assert_eq!(expected_community, updated_community);
     assert_eq!(expected_community_follower, inserted_community_follower);
+    assert!(is_follower);
+    assert_eq!(
+      vec![(
+        inserted_community_follower.id,
+        inserted_user.actor_id.to_owned()
+      )],
+      follower_actor_ids
+    );
+    assert!(other_instance_actor_ids.is_empty());
+    assert!(wildcard_actor_ids.is_empty());
+    assert!(older_actor_ids.is_empty());
+    assert_eq!(
+      vec![inserted_user.id],
+      local_followers.iter().map(|u| u.id).collect::<Vec<i32>>()
+    );
+    assert!(!remote_followed
+      .iter()
+      .any(|c| c.id == inserted_community.id));
+    assert!(local_ids.contains(&inserted_community.id));
+    assert_eq!(Some("private".into()), rotated_community.private_key);
+    assert_eq!(Some("public".into()), rotated_community.public_key);
+    assert!(hidden_from_guests.contains(&inserted_community.id));
+    assert!(!hidden_from_follower.contains(&inserted_community.id));
     assert_eq!(expected_community_user, inserted_community_user);
     assert_eq!(expected_community_user_ban, inserted_community_user_ban);
+    assert!(!default_settings.require_alt_text);
+    assert_eq!(
+      AllowedPostType::Any,
+      default_settings.parsed_allowed_post_type()
+    );
+    assert_eq!(
+      AllowedPostType::TextOnly,
+      read_settings.parsed_allowed_post_type()
+    );
+    assert_eq!(
+      TitleCapitalization::NoAllCaps,
+      read_settings.parsed_title_capitalization()
+    );
+    assert_eq!(
+      MicroblogMentions::Ignore,
+      default_settings.parsed_microblog_mentions()
+    );
+    assert_eq!(
+      MicroblogMentions::PostOrComment,
+      read_settings.parsed_microblog_mentions()
+    );
+    assert_eq!(expected_settings, upserted_settings);
+    assert_eq!(expected_settings, read_settings);
+    assert_eq!(None, default_appearance.accent_color);
+    assert_eq!(Some("#1e90ff".into()), upserted_appearance.accent_color);
+    assert_eq!(upserted_appearance, read_appearance);
     assert_eq!(1, ignored_community);
     assert_eq!(1, left_community);
     assert_eq!(1, unban);
@@ -0,0 +1,226 @@
+use crate::{
+  naive_now,
+  schema::{community_digest, community_digest_subscription, community_follower},
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// A follower of a community who gets its weekly digest by email.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_digest_subscription"]
+pub struct CommunityDigestSubscription {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_digest_subscription"]
+pub struct CommunityDigestSubscriptionForm {
+  pub community_id: i32,
+  pub user_id: i32,
+}
+
+impl CommunityDigestSubscription {
+  pub fn subscribe(
+    conn: &PgConnection,
+    form: &CommunityDigestSubscriptionForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::community_digest_subscription::dsl::*;
+    insert_into(community_digest_subscription)
+      .values(form)
+      .on_conflict((community_id, user_id))
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn unsubscribe(
+    conn: &PgConnection,
+    for_community_id: i32,
+    for_user_id: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::community_digest_subscription::dsl::*;
+    diesel::delete(
+      community_digest_subscription
+        .filter(community_id.eq(for_community_id))
+        .filter(user_id.eq(for_user_id)),
+    )
+    .execute(conn)
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_digest_subscription::dsl::*;
+    community_digest_subscription
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// The subscriptions of the users who still follow the community.
+  pub fn list_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_digest_subscription::dsl::*;
+    let followers = community_follower::table
+      .filter(community_follower::community_id.eq(for_community_id))
+      .select(community_follower::user_id);
+    community_digest_subscription
+      .filter(community_id.eq(for_community_id))
+      .filter(user_id.eq_any(followers))
+      .load::<Self>(conn)
+  }
+}
+
+/// When the digest of a community was last sent.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_digest"]
+pub struct CommunityDigest {
+  pub id: i32,
+  pub community_id: i32,
+  pub sent: chrono::NaiveDateTime,
+}
+
+impl CommunityDigest {
+  /// The communities with digest subscribers, whose digest wasn't sent since `sent_before`.
+  pub fn list_due(
+    conn: &PgConnection,
+    sent_before: chrono::NaiveDateTime,
+  ) -> Result<Vec<i32>, Error> {
+    let recently_sent = community_digest::table
+      .filter(community_digest::sent.ge(sent_before))
+      .select(community_digest::community_id);
+    community_digest_subscription::table
+      .filter(community_digest_subscription::community_id.ne_all(recently_sent))
+      .select(community_digest_subscription::community_id)
+      .distinct()
+      .load::<i32>(conn)
+  }
+
+  pub fn mark_sent(conn: &PgConnection, for_community_id: i32) -> Result<Self, Error> {
+    use crate::schema::community_digest::dsl::*;
+    insert_into(community_digest)
+      .values(community_id.eq(for_community_id))
+      .on_conflict(community_id)
+      .do_update()
+      .set(sent.eq(naive_now()))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    community_digest::*,
+    naive_now,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    Followable,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "digest_reader".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_digest".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let subscription_form = CommunityDigestSubscriptionForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+    };
+    let subscription = CommunityDigestSubscription::subscribe(&conn, &subscription_form).unwrap();
+    let listed = CommunityDigestSubscription::list_for_user(&conn, inserted_user.id).unwrap();
+
+    // Only followers get the digest
+    let not_following =
+      CommunityDigestSubscription::list_for_community(&conn, inserted_community.id).unwrap();
+    let follower_form = CommunityFollowerForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+    };
+    CommunityFollower::follow(&conn, &follower_form).unwrap();
+    let following =
+      CommunityDigestSubscription::list_for_community(&conn, inserted_community.id).unwrap();
+
+    let due = CommunityDigest::list_due(&conn, naive_now()).unwrap();
+    CommunityDigest::mark_sent(&conn, inserted_community.id).unwrap();
+    let sent = CommunityDigest::mark_sent(&conn, inserted_community.id).unwrap();
+    let due_after_sending =
+      CommunityDigest::list_due(&conn, naive_now() - chrono::Duration::days(7)).unwrap();
+
+    let unsubscribed =
+      CommunityDigestSubscription::unsubscribe(&conn, inserted_community.id, inserted_user.id)
+        .unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(vec![subscription.to_owned()], listed);
+    assert!(not_following.is_empty());
+    assert_eq!(vec![subscription], following);
+    assert!(due.contains(&inserted_community.id));
+    assert_eq!(inserted_community.id, sent.community_id);
+    assert!(!due_after_sending.contains(&inserted_community.id));
+    assert_eq!(1, unsubscribed);
+  }
+}
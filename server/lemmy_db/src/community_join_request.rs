@@ -0,0 +1,256 @@
+use crate::schema::community_join_request;
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+use serde_json::Value;
+
+table! {
+  community_join_request_view (id) {
+    id -> Int4,
+    community_id -> Int4,
+    user_id -> Int4,
+    application -> Nullable<Text>,
+    published -> Timestamp,
+    user_actor_id -> Text,
+    user_local -> Bool,
+    user_name -> Varchar,
+    user_preferred_username -> Nullable<Varchar>,
+    avatar -> Nullable<Text>,
+    community_actor_id -> Text,
+    community_local -> Bool,
+    community_name -> Varchar,
+    community_icon -> Nullable<Text>,
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_join_request"]
+pub struct CommunityJoinRequest {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub application: Option<String>,
+  pub published: chrono::NaiveDateTime,
+  /// The Follow activity of a remote applicant.
+  pub follow: Option<Value>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_join_request"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CommunityJoinRequestForm {
+  pub community_id: i32,
+  pub user_id: i32,
+  pub application: Option<String>,
+  pub follow: Option<Value>,
+}
+
+impl CommunityJoinRequest {
+  /// Applying again to the same community replaces the previous application.
+  pub fn apply(conn: &PgConnection, form: &CommunityJoinRequestForm) -> Result<Self, Error> {
+    use crate::schema::community_join_request::dsl::*;
+    insert_into(community_join_request)
+      .values(form)
+      .on_conflict((community_id, user_id))
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection, join_request_id: i32) -> Result<Self, Error> {
+    use crate::schema::community_join_request::dsl::*;
+    community_join_request
+      .find(join_request_id)
+      .first::<Self>(conn)
+  }
+
+  pub fn read_for_user(
+    conn: &PgConnection,
+    for_community_id: i32,
+    for_user_id: i32,
+  ) -> Result<Option<Self>, Error> {
+    use crate::schema::community_join_request::dsl::*;
+    community_join_request
+      .filter(community_id.eq(for_community_id))
+      .filter(user_id.eq(for_user_id))
+      .first::<Self>(conn)
+      .optional()
+  }
+
+  /// Removes the request once it was approved, rejected or withdrawn.
+  pub fn remove(
+    conn: &PgConnection,
+    for_community_id: i32,
+    for_user_id: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::community_join_request::dsl::*;
+    diesel::delete(
+      community_join_request
+        .filter(community_id.eq(for_community_id))
+        .filter(user_id.eq(for_user_id)),
+    )
+    .execute(conn)
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_join_request_view"]
+pub struct CommunityJoinRequestView {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub application: Option<String>,
+  pub published: chrono::NaiveDateTime,
+  pub user_actor_id: String,
+  pub user_local: bool,
+  pub user_name: String,
+  pub user_preferred_username: Option<String>,
+  pub avatar: Option<String>,
+  pub community_actor_id: String,
+  pub community_local: bool,
+  pub community_name: String,
+  pub community_icon: Option<String>,
+}
+
+impl CommunityJoinRequestView {
+  /// The pending requests of a community, oldest first.
+  pub fn for_community(conn: &PgConnection, for_community_id: i32) -> Result<Vec<Self>, Error> {
+    use super::community_join_request::community_join_request_view::dsl::*;
+    community_join_request_view
+      .filter(community_id.eq(for_community_id))
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+
+  /// The pending requests of several communities, oldest first.
+  pub fn for_communities(
+    conn: &PgConnection,
+    for_community_ids: Vec<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    use super::community_join_request::community_join_request_view::dsl::*;
+    community_join_request_view
+      .filter(community_id.eq_any(for_community_ids))
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    community_join_request::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "join_applicant".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_join_request".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let join_request_form = CommunityJoinRequestForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+      application: None,
+      follow: None,
+    };
+
+    let inserted_join_request = CommunityJoinRequest::apply(&conn, &join_request_form).unwrap();
+
+    let answered_form = CommunityJoinRequestForm {
+      application: Some("I'd like to help out".into()),
+      ..join_request_form
+    };
+
+    let updated_join_request = CommunityJoinRequest::apply(&conn, &answered_form).unwrap();
+
+    let expected_join_request = CommunityJoinRequest {
+      id: inserted_join_request.id,
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+      application: Some("I'd like to help out".into()),
+      published: inserted_join_request.published,
+      follow: None,
+    };
+
+    let read_join_request =
+      CommunityJoinRequest::read_for_user(&conn, inserted_community.id, inserted_user.id).unwrap();
+    let join_requests =
+      CommunityJoinRequestView::for_community(&conn, inserted_community.id).unwrap();
+    let join_requests_for_communities =
+      CommunityJoinRequestView::for_communities(&conn, vec![inserted_community.id]).unwrap();
+    let num_removed =
+      CommunityJoinRequest::remove(&conn, inserted_community.id, inserted_user.id).unwrap();
+    let read_after =
+      CommunityJoinRequest::read_for_user(&conn, inserted_community.id, inserted_user.id).unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(expected_join_request, updated_join_request);
+    assert_eq!(Some(expected_join_request), read_join_request);
+    assert_eq!(1, join_requests.len());
+    assert_eq!("join_applicant", join_requests[0].user_name);
+    assert_eq!(join_requests, join_requests_for_communities);
+    assert_eq!(1, num_removed);
+    assert_eq!(None, read_after);
+  }
+}
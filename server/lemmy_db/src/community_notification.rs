@@ -0,0 +1,410 @@
+use crate::{
+  limit_and_offset,
+  schema::{community_notification, community_notification_post},
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_notification"]
+pub struct CommunityNotification {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub mod_posts_only: bool,
+  pub send_email: bool,
+  pub published: chrono::NaiveDateTime,
+  pub send_push: bool,
+  pub email_digest: bool,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_notification"]
+pub struct CommunityNotificationForm {
+  pub community_id: i32,
+  pub user_id: i32,
+  pub mod_posts_only: bool,
+  pub send_email: bool,
+  pub send_push: bool,
+  pub email_digest: bool,
+}
+
+impl CommunityNotification {
+  /// Subscribing again to the same community only changes the preferences.
+  pub fn subscribe(conn: &PgConnection, form: &CommunityNotificationForm) -> Result<Self, Error> {
+    use crate::schema::community_notification::dsl::*;
+    insert_into(community_notification)
+      .values(form)
+      .on_conflict((community_id, user_id))
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn unsubscribe(
+    conn: &PgConnection,
+    for_community_id: i32,
+    for_user_id: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::community_notification::dsl::*;
+    diesel::delete(
+      community_notification
+        .filter(community_id.eq(for_community_id))
+        .filter(user_id.eq(for_user_id)),
+    )
+    .execute(conn)
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_notification::dsl::*;
+    community_notification
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// The subscriptions which a new post in the community should notify. Users who only want mod
+  /// announcements are left out, unless the post is by a mod.
+  pub fn subscribers_for_post(
+    conn: &PgConnection,
+    for_community_id: i32,
+    post_creator_id: i32,
+    by_mod: bool,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_notification::dsl::*;
+    let mut query = community_notification
+      .filter(community_id.eq(for_community_id))
+      .filter(user_id.ne(post_creator_id))
+      .into_boxed();
+
+    if !by_mod {
+      query = query.filter(mod_posts_only.eq(false));
+    }
+
+    query.load::<Self>(conn)
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_notification_post"]
+pub struct CommunityNotificationPost {
+  pub id: i32,
+  pub user_id: i32,
+  pub post_id: i32,
+  pub read: bool,
+  pub published: chrono::NaiveDateTime,
+  pub email_pending: bool,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_notification_post"]
+pub struct CommunityNotificationPostForm {
+  pub user_id: i32,
+  pub post_id: i32,
+  /// Goes into the next digest email of the user
+  pub email_pending: bool,
+}
+
+impl CommunityNotificationPost {
+  /// Returns None if the user was already notified about this post, eg when a federated post
+  /// arrives twice.
+  pub fn create(
+    conn: &PgConnection,
+    form: &CommunityNotificationPostForm,
+  ) -> Result<Option<Self>, Error> {
+    use crate::schema::community_notification_post::dsl::*;
+    insert_into(community_notification_post)
+      .values(form)
+      .on_conflict_do_nothing()
+      .get_result::<Self>(conn)
+      .optional()
+  }
+
+  pub fn list_for_user(
+    conn: &PgConnection,
+    for_user_id: i32,
+    unread_only: bool,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_notification_post::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = community_notification_post
+      .filter(user_id.eq(for_user_id))
+      .into_boxed();
+
+    if unread_only {
+      query = query.filter(read.eq(false));
+    }
+
+    query
+      .order_by(published.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  pub fn mark_all_as_read(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_notification_post::dsl::*;
+    diesel::update(
+      community_notification_post
+        .filter(user_id.eq(for_user_id))
+        .filter(read.eq(false)),
+    )
+    .set(read.eq(true))
+    .get_results::<Self>(conn)
+  }
+
+  /// The users with a notification which has waited for its digest email since before the given
+  /// time.
+  pub fn users_with_due_digest(
+    conn: &PgConnection,
+    pending_before: chrono::NaiveDateTime,
+  ) -> Result<Vec<i32>, Error> {
+    use crate::schema::community_notification_post::dsl::*;
+    community_notification_post
+      .filter(email_pending.eq(true))
+      .filter(published.lt(pending_before))
+      .select(user_id)
+      .distinct()
+      .load::<i32>(conn)
+  }
+
+  /// The notifications for the next digest email of the user, which aren't pending anymore after
+  /// this.
+  pub fn take_pending_emails(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_notification_post::dsl::*;
+    let mut taken = diesel::update(
+      community_notification_post
+        .filter(user_id.eq(for_user_id))
+        .filter(email_pending.eq(true)),
+    )
+    .set(email_pending.eq(false))
+    .get_results::<Self>(conn)?;
+    taken.sort_by_key(|n| n.published);
+    Ok(taken)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    community_notification::*,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "community_notified".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let poster_form = UserForm {
+      name: "notification_poster".into(),
+      ..new_user
+    };
+
+    let inserted_poster = User_::create(&conn, &poster_form).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_notification".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let notification_form = CommunityNotificationForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+      mod_posts_only: false,
+      send_email: true,
+      send_push: true,
+      email_digest: true,
+    };
+
+    let inserted_notification =
+      CommunityNotification::subscribe(&conn, &notification_form).unwrap();
+
+    let mod_posts_form = CommunityNotificationForm {
+      mod_posts_only: true,
+      ..notification_form
+    };
+
+    let updated_notification = CommunityNotification::subscribe(&conn, &mod_posts_form).unwrap();
+
+    let expected_notification = CommunityNotification {
+      id: inserted_notification.id,
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+      mod_posts_only: true,
+      send_email: true,
+      published: inserted_notification.published,
+      send_push: true,
+      email_digest: true,
+    };
+
+    let for_user_post = CommunityNotification::subscribers_for_post(
+      &conn,
+      inserted_community.id,
+      inserted_poster.id,
+      false,
+    )
+    .unwrap();
+    let for_mod_post = CommunityNotification::subscribers_for_post(
+      &conn,
+      inserted_community.id,
+      inserted_poster.id,
+      true,
+    )
+    .unwrap();
+    let for_own_post = CommunityNotification::subscribers_for_post(
+      &conn,
+      inserted_community.id,
+      inserted_user.id,
+      true,
+    )
+    .unwrap();
+
+    let new_post = PostForm {
+      name: "An announcement".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_poster.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let notification_post_form = CommunityNotificationPostForm {
+      user_id: inserted_user.id,
+      post_id: inserted_post.id,
+      email_pending: true,
+    };
+
+    let inserted_notification_post =
+      CommunityNotificationPost::create(&conn, &notification_post_form)
+        .unwrap()
+        .unwrap();
+    let duplicate_notification_post =
+      CommunityNotificationPost::create(&conn, &notification_post_form).unwrap();
+
+    let not_due =
+      CommunityNotificationPost::users_with_due_digest(&conn, inserted_notification_post.published)
+        .unwrap();
+    let due = CommunityNotificationPost::users_with_due_digest(
+      &conn,
+      inserted_notification_post.published + chrono::Duration::seconds(1),
+    )
+    .unwrap();
+    let pending_emails =
+      CommunityNotificationPost::take_pending_emails(&conn, inserted_user.id).unwrap();
+    let pending_after =
+      CommunityNotificationPost::take_pending_emails(&conn, inserted_user.id).unwrap();
+
+    let unread_posts =
+      CommunityNotificationPost::list_for_user(&conn, inserted_user.id, true, None, None).unwrap();
+    let marked_read = CommunityNotificationPost::mark_all_as_read(&conn, inserted_user.id).unwrap();
+    let unread_after =
+      CommunityNotificationPost::list_for_user(&conn, inserted_user.id, true, None, None).unwrap();
+
+    let num_unsubscribed =
+      CommunityNotification::unsubscribe(&conn, inserted_community.id, inserted_user.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+    User_::delete(&conn, inserted_poster.id).unwrap();
+
+    assert_eq!(expected_notification, updated_notification);
+    assert!(for_user_post.is_empty());
+    assert_eq!(vec![expected_notification], for_mod_post);
+    assert!(for_own_post.is_empty());
+    assert_eq!(None, duplicate_notification_post);
+    assert!(not_due.is_empty());
+    assert_eq!(vec![inserted_user.id], due);
+    assert_eq!(
+      vec![inserted_notification_post.id],
+      pending_emails.iter().map(|n| n.id).collect::<Vec<i32>>()
+    );
+    assert!(pending_after.is_empty());
+    assert_eq!(
+      vec![CommunityNotificationPost {
+        email_pending: false,
+        ..inserted_notification_post
+      }],
+      unread_posts
+    );
+    assert_eq!(1, marked_read.len());
+    assert!(unread_after.is_empty());
+    assert_eq!(1, num_unsubscribed);
+  }
+}
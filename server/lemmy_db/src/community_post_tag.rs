@@ -0,0 +1,270 @@
+use crate::{
+  schema::{community_post_tag, post_tag},
+  Crud,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_post_tag"]
+pub struct CommunityPostTag {
+  pub id: i32,
+  pub community_id: i32,
+  pub name: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_post_tag"]
+pub struct CommunityPostTagForm {
+  pub community_id: i32,
+  pub name: String,
+}
+
+impl Crud<CommunityPostTagForm> for CommunityPostTag {
+  fn read(conn: &PgConnection, tag_id: i32) -> Result<Self, Error> {
+    use crate::schema::community_post_tag::dsl::*;
+    community_post_tag.find(tag_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, tag_id: i32) -> Result<usize, Error> {
+    use crate::schema::community_post_tag::dsl::*;
+    diesel::delete(community_post_tag.find(tag_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &CommunityPostTagForm) -> Result<Self, Error> {
+    use crate::schema::community_post_tag::dsl::*;
+    insert_into(community_post_tag)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, tag_id: i32, form: &CommunityPostTagForm) -> Result<Self, Error> {
+    use crate::schema::community_post_tag::dsl::*;
+    diesel::update(community_post_tag.find(tag_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl CommunityPostTag {
+  /// The tags of a community, in the order they were added.
+  pub fn list_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_post_tag::dsl::*;
+    community_post_tag
+      .filter(community_id.eq(for_community_id))
+      .order_by(published.asc())
+      .then_order_by(id.asc())
+      .load::<Self>(conn)
+  }
+
+  pub fn count_for_community(conn: &PgConnection, for_community_id: i32) -> Result<i64, Error> {
+    use crate::schema::community_post_tag::dsl::*;
+    community_post_tag
+      .filter(community_id.eq(for_community_id))
+      .count()
+      .get_result::<i64>(conn)
+  }
+}
+
+#[derive(Queryable, Identifiable, Associations, PartialEq, Debug, Serialize, Clone)]
+#[belongs_to(CommunityPostTag, foreign_key = "tag_id")]
+#[table_name = "post_tag"]
+pub struct PostTag {
+  pub id: i32,
+  pub post_id: i32,
+  pub tag_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "post_tag"]
+pub struct PostTagForm {
+  pub post_id: i32,
+  pub tag_id: i32,
+}
+
+impl PostTag {
+  /// Replaces the tag of a post, None removes it.
+  pub fn set_for_post(
+    conn: &PgConnection,
+    for_post_id: i32,
+    for_tag_id: Option<i32>,
+  ) -> Result<Option<Self>, Error> {
+    use crate::schema::post_tag::dsl::*;
+    conn.transaction::<_, Error, _>(|| {
+      diesel::delete(post_tag.filter(post_id.eq(for_post_id))).execute(conn)?;
+      match for_tag_id {
+        Some(for_tag_id) => {
+          let form = PostTagForm {
+            post_id: for_post_id,
+            tag_id: for_tag_id,
+          };
+          insert_into(post_tag)
+            .values(&form)
+            .get_result::<Self>(conn)
+            .map(Some)
+        }
+        None => Ok(None),
+      }
+    })
+  }
+
+  /// The tag of a post, if it has one.
+  pub fn read_for_post(
+    conn: &PgConnection,
+    for_post_id: i32,
+  ) -> Result<Option<CommunityPostTag>, Error> {
+    post_tag::table
+      .inner_join(community_post_tag::table)
+      .filter(post_tag::post_id.eq(for_post_id))
+      .select(community_post_tag::all_columns)
+      .first::<CommunityPostTag>(conn)
+      .optional()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    community_post_tag::*,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "post_tag_mod".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_post_tag".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A tagged post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let tag_form = CommunityPostTagForm {
+      community_id: inserted_community.id,
+      name: "Question".into(),
+    };
+
+    let inserted_tag = CommunityPostTag::create(&conn, &tag_form).unwrap();
+
+    let expected_tag = CommunityPostTag {
+      id: inserted_tag.id,
+      community_id: inserted_community.id,
+      name: "Question".into(),
+      published: inserted_tag.published,
+    };
+
+    let read_tag = CommunityPostTag::read(&conn, inserted_tag.id).unwrap();
+    let community_tags =
+      CommunityPostTag::list_for_community(&conn, inserted_community.id).unwrap();
+    let num_community_tags =
+      CommunityPostTag::count_for_community(&conn, inserted_community.id).unwrap();
+
+    let untagged = PostTag::read_for_post(&conn, inserted_post.id).unwrap();
+    PostTag::set_for_post(&conn, inserted_post.id, Some(inserted_tag.id)).unwrap();
+    let tagged = PostTag::read_for_post(&conn, inserted_post.id).unwrap();
+    let cleared = PostTag::set_for_post(&conn, inserted_post.id, None).unwrap();
+    let untagged_again = PostTag::read_for_post(&conn, inserted_post.id).unwrap();
+
+    let num_deleted = CommunityPostTag::delete(&conn, inserted_tag.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(expected_tag, read_tag);
+    assert_eq!(expected_tag, inserted_tag);
+    assert_eq!(vec![expected_tag.to_owned()], community_tags);
+    assert_eq!(1, num_community_tags);
+    assert_eq!(None, untagged);
+    assert_eq!(Some(expected_tag), tagged);
+    assert_eq!(None, cleared);
+    assert_eq!(None, untagged_again);
+    assert_eq!(1, num_deleted);
+  }
+}
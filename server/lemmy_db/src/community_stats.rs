@@ -0,0 +1,288 @@
+use crate::schema::{comment_aggregates_fast, community_stats_daily, post_aggregates_fast};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The counts of one day in a community, which triggers keep up to date.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_stats_daily"]
+pub struct CommunityStatsDaily {
+  pub id: i32,
+  pub community_id: i32,
+  pub day: chrono::NaiveDate,
+  pub subscribers_gained: i32,
+  pub subscribers_lost: i32,
+  pub posts: i32,
+  pub comments: i32,
+  pub removals: i32,
+  pub reports: i32,
+}
+
+impl CommunityStatsDaily {
+  /// Days without any activity have no row.
+  pub fn list_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+    since: chrono::NaiveDate,
+    until: chrono::NaiveDate,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_stats_daily::dsl::*;
+    community_stats_daily
+      .filter(community_id.eq(for_community_id))
+      .filter(day.between(since, until))
+      .order_by(day.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[derive(PartialEq, Debug, Serialize, Clone)]
+pub struct CommunityContributor {
+  pub user_id: i32,
+  pub user_name: String,
+  pub posts: i64,
+  pub comments: i64,
+}
+
+impl CommunityContributor {
+  /// The users with the most posts and comments in the community in the given time, including
+  /// removed and deleted ones.
+  pub fn list_top(
+    conn: &PgConnection,
+    for_community_id: i32,
+    since: chrono::NaiveDateTime,
+    until: chrono::NaiveDateTime,
+    limit: usize,
+  ) -> Result<Vec<Self>, Error> {
+    use diesel::sql_types::BigInt;
+    let post_counts = post_aggregates_fast::table
+      .filter(post_aggregates_fast::community_id.eq(for_community_id))
+      .filter(post_aggregates_fast::published.between(since, until))
+      .group_by((
+        post_aggregates_fast::creator_id,
+        post_aggregates_fast::creator_name,
+      ))
+      .select((
+        post_aggregates_fast::creator_id,
+        post_aggregates_fast::creator_name,
+        sql::<BigInt>("count(*)"),
+      ))
+      .load::<(Option<i32>, Option<String>, i64)>(conn)?;
+
+    let comment_counts = comment_aggregates_fast::table
+      .filter(comment_aggregates_fast::community_id.eq(for_community_id))
+      .filter(comment_aggregates_fast::published.between(since, until))
+      .group_by((
+        comment_aggregates_fast::creator_id,
+        comment_aggregates_fast::creator_name,
+      ))
+      .select((
+        comment_aggregates_fast::creator_id,
+        comment_aggregates_fast::creator_name,
+        sql::<BigInt>("count(*)"),
+      ))
+      .load::<(Option<i32>, Option<String>, i64)>(conn)?;
+
+    let mut contributors: HashMap<i32, Self> = HashMap::new();
+    for (is_post, (creator_id, creator_name, count)) in post_counts
+      .into_iter()
+      .map(|c| (true, c))
+      .chain(comment_counts.into_iter().map(|c| (false, c)))
+    {
+      let (user_id, user_name) = match (creator_id, creator_name) {
+        (Some(user_id), Some(user_name)) => (user_id, user_name),
+        _ => continue,
+      };
+      let contributor = contributors.entry(user_id).or_insert(CommunityContributor {
+        user_id,
+        user_name,
+        posts: 0,
+        comments: 0,
+      });
+      if is_post {
+        contributor.posts += count;
+      } else {
+        contributor.comments += count;
+      }
+    }
+
+    let mut contributors: Vec<Self> = contributors.values().cloned().collect();
+    contributors.sort_by(|a, b| {
+      (b.posts + b.comments)
+        .cmp(&(a.posts + a.comments))
+        .then(a.user_id.cmp(&b.user_id))
+    });
+    contributors.truncate(limit);
+    Ok(contributors)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    comment::*,
+    community::*,
+    community_stats::*,
+    naive_now,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    Followable,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "community_stats_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_community_stats".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let community_follower_form = CommunityFollowerForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+    };
+
+    CommunityFollower::follow(&conn, &community_follower_form).unwrap();
+
+    let new_post = PostForm {
+      name: "A counted post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A counted comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      published: None,
+      updated: None,
+      ap_id: None,
+      local: true,
+      distinguished: None,
+    };
+
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+    let inserted_other_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    // The database might not be in UTC, so look around today
+    let since = naive_now() - chrono::Duration::days(1);
+    let until = naive_now() + chrono::Duration::days(1);
+    let stats = CommunityStatsDaily::list_for_community(
+      &conn,
+      inserted_community.id,
+      since.date(),
+      until.date(),
+    )
+    .unwrap();
+    let contributors =
+      CommunityContributor::list_top(&conn, inserted_community.id, since, until, 10).unwrap();
+
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Comment::delete(&conn, inserted_other_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let expected_stats = CommunityStatsDaily {
+      id: stats[0].id,
+      community_id: inserted_community.id,
+      day: stats[0].day,
+      subscribers_gained: 1,
+      subscribers_lost: 0,
+      posts: 1,
+      comments: 2,
+      removals: 0,
+      reports: 0,
+    };
+
+    let expected_contributor = CommunityContributor {
+      user_id: inserted_user.id,
+      user_name: "community_stats_user".into(),
+      posts: 1,
+      comments: 2,
+    };
+
+    assert_eq!(vec![expected_stats], stats);
+    assert_eq!(vec![expected_contributor], contributors);
+  }
+}
@@ -1,6 +1,12 @@
 use super::community_view::community_fast_view::BoxedQuery;
-use crate::{fuzzy_search, limit_and_offset, MaybeOptional, SortType};
-use diesel::{pg::Pg, result::Error, *};
+use crate::{
+  community::{hidden_private_communities, undiscoverable_communities},
+  fuzzy_search,
+  limit_and_offset,
+  MaybeOptional,
+  SortType,
+};
+use diesel::{dsl::not, pg::Pg, result::Error, *};
 use serde::{Deserialize, Serialize};
 
 table! {
@@ -225,7 +231,8 @@ impl<'a> CommunityQueryBuilder<'a> {
       query = query
         .filter(name.ilike(searcher.to_owned()))
         .or_filter(title.ilike(searcher.to_owned()))
-        .or_filter(description.ilike(searcher));
+        .or_filter(description.ilike(searcher))
+        .filter(id.ne_all(undiscoverable_communities(self.conn)?));
     };
 
     // The view lets you pass a null user_id, if you're not logged in
@@ -256,6 +263,12 @@ impl<'a> CommunityQueryBuilder<'a> {
       query = query.filter(nsfw.eq(false));
     };
 
+    // Private communities are unlisted, except for their own members
+    let hidden_communities = hidden_private_communities(self.conn, self.from_user_id)?;
+    if !hidden_communities.is_empty() {
+      query = query.filter(not(id.eq_any(hidden_communities)));
+    }
+
     let (limit, offset) = limit_and_offset(self.page, self.limit);
     query
       .limit(limit)
@@ -287,6 +300,47 @@ impl CommunityView {
 
     query.first::<Self>(conn)
   }
+
+  /// The most subscribed communities which the user doesn't follow yet, for onboarding. The ones
+  /// in the given categories come first.
+  pub fn list_recommended(
+    conn: &PgConnection,
+    from_user_id: Option<i32>,
+    for_category_ids: Vec<i32>,
+    show_nsfw: bool,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use super::community_view::community_fast_view::dsl::*;
+
+    let mut query = community_fast_view.into_boxed();
+
+    // The view lets you pass a null user_id, if you're not logged in
+    if let Some(from_user_id) = from_user_id {
+      query = query
+        .filter(user_id.eq(from_user_id))
+        .filter(subscribed.is_null().or(subscribed.eq(false)));
+    } else {
+      query = query.filter(user_id.is_null());
+    };
+
+    if !show_nsfw {
+      query = query.filter(nsfw.eq(false));
+    };
+
+    let hidden_communities = hidden_private_communities(conn, from_user_id)?;
+    if !hidden_communities.is_empty() {
+      query = query.filter(not(id.eq_any(hidden_communities)));
+    }
+
+    query
+      .filter(removed.eq(false))
+      .filter(deleted.eq(false))
+      .order_by(category_id.eq_any(for_category_ids).desc())
+      .then_order_by(number_of_subscribers.desc())
+      .then_order_by(hot_rank.desc())
+      .limit(limit)
+      .load::<Self>(conn)
+  }
 }
 
 #[derive(
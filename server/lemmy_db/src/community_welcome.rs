@@ -0,0 +1,145 @@
+use crate::schema::community_welcome;
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "community_welcome"]
+pub struct CommunityWelcome {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_welcome"]
+pub struct CommunityWelcomeForm {
+  pub community_id: i32,
+  pub user_id: i32,
+}
+
+impl CommunityWelcome {
+  /// Returns None if the user was already welcomed to the community.
+  pub fn record(conn: &PgConnection, form: &CommunityWelcomeForm) -> Result<Option<Self>, Error> {
+    use crate::schema::community_welcome::dsl::*;
+    insert_into(community_welcome)
+      .values(form)
+      .on_conflict_do_nothing()
+      .get_result::<Self>(conn)
+      .optional()
+  }
+
+  pub fn count_for_user_since(
+    conn: &PgConnection,
+    for_user_id: i32,
+    since: chrono::NaiveDateTime,
+  ) -> Result<i64, Error> {
+    use crate::schema::community_welcome::dsl::*;
+    community_welcome
+      .filter(user_id.eq(for_user_id))
+      .filter(published.gt(since))
+      .count()
+      .get_result::<i64>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    community_welcome::*,
+    naive_now,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "welcomed_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_welcome".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let welcome_form = CommunityWelcomeForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+    };
+
+    let before = naive_now() - chrono::Duration::days(1);
+    let inserted_welcome = CommunityWelcome::record(&conn, &welcome_form)
+      .unwrap()
+      .unwrap();
+    let second_welcome = CommunityWelcome::record(&conn, &welcome_form).unwrap();
+    let num_welcomes =
+      CommunityWelcome::count_for_user_since(&conn, inserted_user.id, before).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let expected_welcome = CommunityWelcome {
+      id: inserted_welcome.id,
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+      published: inserted_welcome.published,
+    };
+
+    assert_eq!(expected_welcome, inserted_welcome);
+    assert_eq!(None, second_welcome);
+    assert_eq!(1, num_welcomes);
+  }
+}
@@ -0,0 +1,266 @@
+use crate::schema::{comment, content_report, post};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// A post or comment which a user reported to the mods of its community.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "content_report"]
+pub struct ContentReport {
+  pub id: i32,
+  pub creator_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: String,
+  pub resolved: bool,
+  pub resolver_id: Option<i32>,
+  /// The id of the Flag activity, for reports by remote users
+  pub ap_id: Option<String>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "content_report"]
+pub struct ContentReportForm {
+  pub creator_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: String,
+  pub ap_id: Option<String>,
+}
+
+impl ContentReport {
+  /// Returns none if the Flag activity of the report was already received.
+  pub fn report(conn: &PgConnection, form: &ContentReportForm) -> Result<Option<Self>, Error> {
+    use crate::schema::content_report::dsl::*;
+    insert_into(content_report)
+      .values(form)
+      .on_conflict_do_nothing()
+      .get_result::<Self>(conn)
+      .optional()
+  }
+
+  pub fn read(conn: &PgConnection, report_id: i32) -> Result<Self, Error> {
+    use crate::schema::content_report::dsl::*;
+    content_report.find(report_id).first::<Self>(conn)
+  }
+
+  pub fn resolve(conn: &PgConnection, report_id: i32, by_user_id: i32) -> Result<Self, Error> {
+    use crate::schema::content_report::dsl::*;
+    diesel::update(content_report.find(report_id))
+      .set((resolved.eq(true), resolver_id.eq(by_user_id)))
+      .get_result::<Self>(conn)
+  }
+
+  /// The community of the reported post or comment.
+  pub fn community_id(&self, conn: &PgConnection) -> Result<i32, Error> {
+    match (self.post_id, self.comment_id) {
+      (Some(reported_post_id), _) => post::table
+        .find(reported_post_id)
+        .select(post::community_id)
+        .first::<i32>(conn),
+      (None, Some(reported_comment_id)) => comment::table
+        .inner_join(post::table)
+        .filter(comment::id.eq(reported_comment_id))
+        .select(post::community_id)
+        .first::<i32>(conn),
+      (None, None) => Err(Error::NotFound),
+    }
+  }
+
+  /// The unresolved reports of posts and comments in these communities, which weren't removed or
+  /// deleted yet, oldest first.
+  pub fn list_open_for_communities(
+    conn: &PgConnection,
+    community_ids: Vec<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::content_report::dsl::*;
+    let open_posts = post::table
+      .filter(post::community_id.eq_any(community_ids.to_owned()))
+      .filter(post::removed.eq(false))
+      .filter(post::deleted.eq(false))
+      .select(post::id.nullable());
+    let open_comments = comment::table
+      .inner_join(post::table)
+      .filter(post::community_id.eq_any(community_ids))
+      .filter(comment::removed.eq(false))
+      .filter(comment::deleted.eq(false))
+      .select(comment::id.nullable());
+
+    content_report
+      .filter(resolved.eq(false))
+      .filter(
+        post_id
+          .eq_any(open_posts)
+          .or(comment_id.eq_any(open_comments)),
+      )
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    comment::*,
+    community::*,
+    content_report::*,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "content_reporter".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_content_report".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A reported post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A reported comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      published: None,
+      updated: None,
+      ap_id: None,
+      local: true,
+      distinguished: None,
+    };
+
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let post_report_form = ContentReportForm {
+      creator_id: inserted_user.id,
+      post_id: Some(inserted_post.id),
+      comment_id: None,
+      reason: "Spam".into(),
+      ap_id: None,
+    };
+    let post_report = ContentReport::report(&conn, &post_report_form)
+      .unwrap()
+      .unwrap();
+
+    let flag_form = ContentReportForm {
+      post_id: None,
+      comment_id: Some(inserted_comment.id),
+      reason: "Harassment".into(),
+      ap_id: Some("https://example.com/activities/flag/1".into()),
+      ..post_report_form
+    };
+    let comment_report = ContentReport::report(&conn, &flag_form).unwrap();
+    let repeated_flag = ContentReport::report(&conn, &flag_form).unwrap();
+
+    let open =
+      ContentReport::list_open_for_communities(&conn, vec![inserted_community.id]).unwrap();
+    let post_community_id = post_report.community_id(&conn).unwrap();
+    let comment_community_id = comment_report
+      .as_ref()
+      .unwrap()
+      .community_id(&conn)
+      .unwrap();
+    let resolved = ContentReport::resolve(&conn, post_report.id, inserted_user.id).unwrap();
+    let open_after_resolving =
+      ContentReport::list_open_for_communities(&conn, vec![inserted_community.id]).unwrap();
+
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert!(comment_report.is_some());
+    assert_eq!(None, repeated_flag);
+    assert_eq!(2, open.len());
+    assert_eq!(inserted_community.id, post_community_id);
+    assert_eq!(inserted_community.id, comment_community_id);
+    assert!(resolved.resolved);
+    assert_eq!(Some(inserted_user.id), resolved.resolver_id);
+    assert_eq!(1, open_after_resolving.len());
+  }
+}
@@ -0,0 +1,107 @@
+use crate::{limit_and_offset, schema::csp_report};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// A content security policy violation which a browser reported.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "csp_report"]
+pub struct CspReport {
+  pub id: i32,
+  pub document_uri: String,
+  pub violated_directive: String,
+  pub blocked_uri: Option<String>,
+  pub source_file: Option<String>,
+  pub line_number: Option<i32>,
+  /// enforce or report
+  pub disposition: Option<String>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone, Debug, PartialEq)]
+#[table_name = "csp_report"]
+pub struct CspReportForm {
+  pub document_uri: String,
+  pub violated_directive: String,
+  pub blocked_uri: Option<String>,
+  pub source_file: Option<String>,
+  pub line_number: Option<i32>,
+  pub disposition: Option<String>,
+}
+
+impl CspReport {
+  pub fn create(conn: &PgConnection, form: &CspReportForm) -> Result<Self, Error> {
+    use crate::schema::csp_report::dsl::*;
+    insert_into(csp_report)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// The reports, or only those of one directive, newest first.
+  pub fn list(
+    conn: &PgConnection,
+    for_directive: Option<String>,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::csp_report::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = csp_report.into_boxed();
+
+    if let Some(for_directive) = for_directive {
+      query = query.filter(violated_directive.eq(for_directive));
+    }
+
+    query
+      .order_by(id.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  /// Browsers repeat the same reports over and over, so only the newest ones are kept.
+  pub fn delete_all_but_newest(conn: &PgConnection, keep: i32) -> Result<usize, Error> {
+    use crate::schema::csp_report::dsl::*;
+    let newest = csp_report.select(max(id)).first::<Option<i32>>(conn)?;
+    match newest {
+      Some(newest) => diesel::delete(csp_report.filter(id.le(newest - keep))).execute(conn),
+      None => Ok(0),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{csp_report::*, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let form = |directive: &str| CspReportForm {
+      document_uri: "https://lemmy.example.com/post/1".into(),
+      violated_directive: directive.into(),
+      blocked_uri: Some("https://tracker.example.com/script.js".into()),
+      source_file: None,
+      line_number: Some(12),
+      disposition: Some("enforce".into()),
+    };
+    let first = CspReport::create(&conn, &form("script-src")).unwrap();
+    let second = CspReport::create(&conn, &form("img-src")).unwrap();
+    let third = CspReport::create(&conn, &form("script-src")).unwrap();
+
+    let all = CspReport::list(&conn, None, None, Some(3)).unwrap();
+    let scripts = CspReport::list(&conn, Some("script-src".into()), None, Some(2)).unwrap();
+    let deleted = CspReport::delete_all_but_newest(&conn, 2).unwrap();
+    let kept = CspReport::list(&conn, None, None, None).unwrap();
+
+    CspReport::delete_all_but_newest(&conn, 0).unwrap();
+
+    assert_eq!(
+      vec![third.to_owned(), second.to_owned(), first.to_owned()],
+      all
+    );
+    assert_eq!(vec![third.to_owned(), first], scripts);
+    assert!(deleted >= 1);
+    assert_eq!(vec![third, second], kept);
+  }
+}
@@ -0,0 +1,140 @@
+use crate::schema::default_community;
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "default_community"]
+pub struct DefaultCommunity {
+  pub id: i32,
+  pub community_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "default_community"]
+pub struct DefaultCommunityForm {
+  pub community_id: i32,
+}
+
+impl DefaultCommunity {
+  pub fn list_community_ids(conn: &PgConnection) -> Result<Vec<i32>, Error> {
+    use crate::schema::default_community::dsl::*;
+    default_community
+      .select(community_id)
+      .order_by(id.asc())
+      .load::<i32>(conn)
+  }
+
+  /// Replaces the whole list of default communities.
+  pub fn replace(conn: &PgConnection, community_ids: &[i32]) -> Result<Vec<Self>, Error> {
+    use crate::schema::default_community::dsl::*;
+    let forms = community_ids
+      .iter()
+      .map(|c| DefaultCommunityForm { community_id: *c })
+      .collect::<Vec<DefaultCommunityForm>>();
+    conn.transaction(|| {
+      diesel::delete(default_community).execute(conn)?;
+      insert_into(default_community)
+        .values(&forms)
+        .on_conflict_do_nothing()
+        .get_results::<Self>(conn)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    default_community::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "default_admin".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: true,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_default".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let original_ids = DefaultCommunity::list_community_ids(&conn).unwrap();
+    let replaced =
+      DefaultCommunity::replace(&conn, &[inserted_community.id, inserted_community.id]).unwrap();
+    let replaced_ids = DefaultCommunity::list_community_ids(&conn).unwrap();
+    let num_followed =
+      CommunityFollower::follow_all_local_users(&conn, inserted_community.id).unwrap();
+    let is_follower =
+      CommunityFollower::is_follower(&conn, inserted_community.id, inserted_user.id).unwrap();
+    DefaultCommunity::replace(&conn, &original_ids).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let expected_default_community = DefaultCommunity {
+      id: replaced[0].id,
+      community_id: inserted_community.id,
+      published: replaced[0].published,
+    };
+
+    assert_eq!(vec![expected_default_community], replaced);
+    assert_eq!(vec![inserted_community.id], replaced_ids);
+    assert!(num_followed >= 1);
+    assert!(is_follower);
+  }
+}
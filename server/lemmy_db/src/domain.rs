@@ -0,0 +1,318 @@
+use crate::{
+  limit_and_offset,
+  naive_now,
+  schema::{domain, domain_post_approval, post},
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::{Deserialize, Serialize};
+
+/// What admins allow for posts linking to a domain.
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+pub enum DomainPolicy {
+  Allowed,
+  /// Posts linking to it are refused
+  Blocked,
+  /// Posts linking to it wait for the approval of a mod or admin
+  Greylisted,
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "domain"]
+pub struct Domain {
+  pub id: i32,
+  pub name: String,
+  pub post_count: i32,
+  pub last_post: Option<chrono::NaiveDateTime>,
+  pub policy: String,
+  pub policy_reason: Option<String>,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+/// The post counts are kept by a trigger, so only the policy is ever written.
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "domain"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct DomainPolicyForm {
+  pub name: String,
+  pub policy: String,
+  pub policy_reason: Option<String>,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+impl Domain {
+  pub fn read_by_name(conn: &PgConnection, for_name: &str) -> Result<Self, Error> {
+    use crate::schema::domain::dsl::*;
+    domain.filter(name.eq(for_name)).first::<Self>(conn)
+  }
+
+  /// None for domains which nobody linked to yet, and which admins didn't set a policy for.
+  pub fn find_by_name(conn: &PgConnection, for_name: &str) -> Result<Option<Self>, Error> {
+    Domain::read_by_name(conn, for_name).optional()
+  }
+
+  /// Domains which nobody linked to yet are allowed, unless an admin set a policy for them.
+  pub fn policy_for(conn: &PgConnection, for_name: &str) -> Result<DomainPolicy, Error> {
+    let found = Domain::find_by_name(conn, for_name)?;
+    Ok(found.map_or(DomainPolicy::Allowed, |d| d.parsed_policy()))
+  }
+
+  pub fn parsed_policy(&self) -> DomainPolicy {
+    self.policy.parse().unwrap_or(DomainPolicy::Allowed)
+  }
+
+  /// The most linked domains first.
+  pub fn list(
+    conn: &PgConnection,
+    for_policy: Option<String>,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::domain::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = domain.into_boxed();
+    if let Some(for_policy) = for_policy {
+      query = query.filter(policy.eq(for_policy));
+    }
+    query
+      .order_by(post_count.desc())
+      .then_order_by(name.asc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  pub fn set_policy(conn: &PgConnection, form: &DomainPolicyForm) -> Result<Self, Error> {
+    use crate::schema::domain::dsl::*;
+    let form = DomainPolicyForm {
+      updated: Some(naive_now()),
+      ..form.to_owned()
+    };
+    insert_into(domain)
+      .values(&form)
+      .on_conflict(name)
+      .do_update()
+      .set(&form)
+      .get_result::<Self>(conn)
+  }
+}
+
+#[derive(Queryable, Identifiable, Associations, PartialEq, Debug, Serialize, Clone)]
+#[belongs_to(Domain)]
+#[table_name = "domain_post_approval"]
+pub struct DomainPostApproval {
+  pub id: i32,
+  pub post_id: i32,
+  pub domain_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "domain_post_approval"]
+pub struct DomainPostApprovalForm {
+  pub post_id: i32,
+  pub domain_id: i32,
+}
+
+impl DomainPostApproval {
+  pub fn create(conn: &PgConnection, form: &DomainPostApprovalForm) -> Result<Self, Error> {
+    use crate::schema::domain_post_approval::dsl::*;
+    insert_into(domain_post_approval)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read_for_post(conn: &PgConnection, for_post_id: i32) -> Result<Self, Error> {
+    use crate::schema::domain_post_approval::dsl::*;
+    domain_post_approval
+      .filter(post_id.eq(for_post_id))
+      .first::<Self>(conn)
+  }
+
+  pub fn delete_for_post(conn: &PgConnection, for_post_id: i32) -> Result<usize, Error> {
+    use crate::schema::domain_post_approval::dsl::*;
+    diesel::delete(domain_post_approval.filter(post_id.eq(for_post_id))).execute(conn)
+  }
+
+  pub fn count_for_communities(conn: &PgConnection, community_ids: Vec<i32>) -> Result<i64, Error> {
+    domain_post_approval::table
+      .inner_join(post::table)
+      .filter(post::community_id.eq_any(community_ids))
+      .count()
+      .get_result::<i64>(conn)
+  }
+
+  /// The posts waiting for approval in these communities, or in all of them for admins, oldest
+  /// first.
+  pub fn list_for_communities(
+    conn: &PgConnection,
+    community_ids: Option<Vec<i32>>,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = domain_post_approval::table
+      .inner_join(post::table)
+      .select(domain_post_approval::all_columns)
+      .into_boxed();
+    if let Some(community_ids) = community_ids {
+      query = query.filter(post::community_id.eq_any(community_ids));
+    }
+    query
+      .order_by(domain_post_approval::published.asc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    domain::*,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "domain_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_domain".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A domain post".into(),
+      url: Some("https://www.Domain-Test.example/article".into()),
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+    let counted_domain = Domain::read_by_name(&conn, "domain-test.example").unwrap();
+
+    let policy_form = DomainPolicyForm {
+      name: "domain-test.example".into(),
+      policy: DomainPolicy::Greylisted.to_string(),
+      policy_reason: Some("Link farm".into()),
+      updated: None,
+    };
+    let greylisted_domain = Domain::set_policy(&conn, &policy_form).unwrap();
+    let policy = Domain::policy_for(&conn, "domain-test.example").unwrap();
+    let unknown_policy = Domain::policy_for(&conn, "unknown-domain.example").unwrap();
+    let greylisted = Domain::list(&conn, Some("greylisted".into()), None, Some(50)).unwrap();
+
+    let approval_form = DomainPostApprovalForm {
+      post_id: inserted_post.id,
+      domain_id: greylisted_domain.id,
+    };
+    let inserted_approval = DomainPostApproval::create(&conn, &approval_form).unwrap();
+    let read_approval = DomainPostApproval::read_for_post(&conn, inserted_post.id).unwrap();
+    let community_approvals = DomainPostApproval::list_for_communities(
+      &conn,
+      Some(vec![inserted_community.id]),
+      None,
+      None,
+    )
+    .unwrap();
+    let num_community_approvals =
+      DomainPostApproval::count_for_communities(&conn, vec![inserted_community.id]).unwrap();
+    let num_approvals_deleted =
+      DomainPostApproval::delete_for_post(&conn, inserted_post.id).unwrap();
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    let uncounted_domain = Domain::read_by_name(&conn, "domain-test.example").unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(1, counted_domain.post_count);
+    assert_eq!("allowed", counted_domain.policy);
+    assert_eq!(counted_domain.id, greylisted_domain.id);
+    assert_eq!("greylisted", greylisted_domain.policy);
+    assert_eq!(DomainPolicy::Greylisted, policy);
+    assert_eq!(DomainPolicy::Allowed, unknown_policy);
+    assert!(greylisted.iter().any(|d| d.id == greylisted_domain.id));
+    assert_eq!(inserted_approval, read_approval);
+    assert_eq!(vec![inserted_approval], community_approvals);
+    assert_eq!(1, num_community_approvals);
+    assert_eq!(1, num_approvals_deleted);
+    assert_eq!(0, uncounted_domain.post_count);
+  }
+}
@@ -0,0 +1,165 @@
+use crate::{activity_send_queue::retry_delay, limit_and_offset, naive_now, schema::email_outbox};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// After this many failed attempts, which take a few hours, the email is marked as failed.
+pub const MAX_EMAIL_ATTEMPTS: i32 = 8;
+
+/// An email which wasn't sent yet, or which failed.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "email_outbox"]
+pub struct EmailOutbox {
+  pub id: i32,
+  pub to_email: String,
+  pub to_name: String,
+  pub subject: String,
+  /// Not shown to admins, as it can contain tokens like the ones of password resets
+  #[serde(skip_serializing)]
+  pub html: String,
+  pub reply_to: Option<String>,
+  pub published: chrono::NaiveDateTime,
+  pub attempts: i32,
+  pub next_attempt: chrono::NaiveDateTime,
+  pub last_error: Option<String>,
+  pub failed: bool,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "email_outbox"]
+pub struct EmailOutboxForm {
+  pub to_email: String,
+  pub to_name: String,
+  pub subject: String,
+  pub html: String,
+  pub reply_to: Option<String>,
+}
+
+impl EmailOutbox {
+  pub fn enqueue(conn: &PgConnection, form: &EmailOutboxForm) -> Result<Self, Error> {
+    use crate::schema::email_outbox::dsl::*;
+    insert_into(email_outbox)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// The emails to send now, oldest first.
+  pub fn list_due(conn: &PgConnection, limit: i64) -> Result<Vec<Self>, Error> {
+    use crate::schema::email_outbox::dsl::*;
+    email_outbox
+      .filter(failed.eq(false))
+      .filter(next_attempt.le(naive_now()))
+      .order_by(id.asc())
+      .limit(limit)
+      .load::<Self>(conn)
+  }
+
+  /// The emails waiting to be sent or retried, or only those which failed, newest first.
+  pub fn list(
+    conn: &PgConnection,
+    failed_only: bool,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::email_outbox::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = email_outbox.into_boxed();
+
+    if failed_only {
+      query = query.filter(failed.eq(true));
+    }
+
+    query
+      .order_by(published.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  pub fn sent(conn: &PgConnection, email_id: i32) -> Result<usize, Error> {
+    use crate::schema::email_outbox::dsl::*;
+    diesel::delete(email_outbox.find(email_id)).execute(conn)
+  }
+
+  /// Schedules the next attempt with exponential backoff, or marks the email as failed if retrying
+  /// won't help or it failed too often.
+  pub fn failed(
+    conn: &PgConnection,
+    email_id: i32,
+    error: &str,
+    permanent: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::email_outbox::dsl::*;
+    let queued = email_outbox.find(email_id).first::<Self>(conn)?;
+    let failed_attempts = queued.attempts + 1;
+    diesel::update(email_outbox.find(email_id))
+      .set((
+        attempts.eq(failed_attempts),
+        next_attempt.eq(naive_now() + retry_delay(failed_attempts)),
+        last_error.eq(error),
+        failed.eq(permanent || failed_attempts >= MAX_EMAIL_ATTEMPTS),
+      ))
+      .get_result::<Self>(conn)
+  }
+
+  /// Removes the failed emails which are older than `published_before`.
+  pub fn prune_failed(
+    conn: &PgConnection,
+    published_before: chrono::NaiveDateTime,
+  ) -> Result<usize, Error> {
+    use crate::schema::email_outbox::dsl::*;
+    diesel::delete(
+      email_outbox
+        .filter(failed.eq(true))
+        .filter(published.lt(published_before)),
+    )
+    .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{email_outbox::*, naive_now, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let form = EmailOutboxForm {
+      to_email: "outbox@example.com".into(),
+      to_name: "outbox_user".into(),
+      subject: "Hello".into(),
+      html: "<h1>Hello</h1>".into(),
+      reply_to: None,
+    };
+    let queued = EmailOutbox::enqueue(&conn, &form).unwrap();
+    let due = EmailOutbox::list_due(&conn, 1000).unwrap();
+
+    let retried = EmailOutbox::failed(&conn, queued.id, "connection: refused", false).unwrap();
+    let due_while_waiting = EmailOutbox::list_due(&conn, 1000).unwrap();
+    let failed = EmailOutbox::failed(&conn, queued.id, "rejected 550: no such user", true).unwrap();
+    let failed_list = EmailOutbox::list(&conn, true, None, None).unwrap();
+
+    let pruned =
+      EmailOutbox::prune_failed(&conn, naive_now() + chrono::Duration::minutes(1)).unwrap();
+
+    let sent_form = EmailOutboxForm {
+      subject: "Sent".into(),
+      ..form
+    };
+    let to_send = EmailOutbox::enqueue(&conn, &sent_form).unwrap();
+    let num_sent = EmailOutbox::sent(&conn, to_send.id).unwrap();
+
+    assert!(due.iter().any(|e| e.id == queued.id));
+    assert_eq!(1, retried.attempts);
+    assert!(!retried.failed);
+    assert!(!due_while_waiting.iter().any(|e| e.id == queued.id));
+    assert!(failed.failed);
+    assert_eq!(
+      Some("rejected 550: no such user".to_string()),
+      failed.last_error
+    );
+    assert!(failed_list.iter().any(|e| e.id == queued.id));
+    assert!(pruned >= 1);
+    assert_eq!(1, num_sent);
+  }
+}
@@ -0,0 +1,346 @@
+use crate::{
+  naive_now,
+  schema::{feature_flag, feature_flag_user},
+  Crud,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "feature_flag"]
+pub struct FeatureFlag {
+  pub id: i32,
+  pub name: String,
+  pub description: Option<String>,
+  pub enabled: bool,
+  pub rollout_percentage: i16,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "feature_flag"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct FeatureFlagForm {
+  pub name: String,
+  pub description: Option<String>,
+  pub enabled: bool,
+  pub rollout_percentage: i16,
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "feature_flag_user"]
+pub struct FeatureFlagUser {
+  pub id: i32,
+  pub feature_flag_id: i32,
+  pub user_id: i32,
+  pub enabled: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "feature_flag_user"]
+pub struct FeatureFlagUserForm {
+  pub feature_flag_id: i32,
+  pub user_id: i32,
+  pub enabled: bool,
+}
+
+impl Crud<FeatureFlagForm> for FeatureFlag {
+  fn read(conn: &PgConnection, flag_id: i32) -> Result<Self, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    feature_flag.find(flag_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, flag_id: i32) -> Result<usize, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    diesel::delete(feature_flag.find(flag_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &FeatureFlagForm) -> Result<Self, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    insert_into(feature_flag)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, flag_id: i32, form: &FeatureFlagForm) -> Result<Self, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    diesel::update(feature_flag.find(flag_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl FeatureFlag {
+  pub fn read_from_name(conn: &PgConnection, flag_name: &str) -> Result<Self, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    feature_flag.filter(name.eq(flag_name)).first::<Self>(conn)
+  }
+
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    feature_flag.order_by(name.asc()).load::<Self>(conn)
+  }
+
+  /// Creates the flag, or replaces the one with the same name.
+  pub fn save(conn: &PgConnection, form: &FeatureFlagForm) -> Result<Self, Error> {
+    use crate::schema::feature_flag::dsl::*;
+    insert_into(feature_flag)
+      .values(form)
+      .on_conflict(name)
+      .do_update()
+      .set((form, updated.eq(naive_now())))
+      .get_result::<Self>(conn)
+  }
+
+  /// Whether the feature is on for the user. Flags which don't exist are off.
+  pub fn is_enabled(
+    conn: &PgConnection,
+    flag_name: &str,
+    for_user_id: Option<i32>,
+  ) -> Result<bool, Error> {
+    let flag = match FeatureFlag::read_from_name(conn, flag_name).optional()? {
+      Some(f) => f,
+      None => return Ok(false),
+    };
+    let user_setting = match for_user_id {
+      Some(for_user_id) => FeatureFlagUser::read_setting(conn, flag.id, for_user_id)?,
+      None => None,
+    };
+    Ok(flag.is_enabled_for(for_user_id, user_setting))
+  }
+
+  /// The names of all features which are on for the user, so that clients can show them.
+  pub fn list_enabled_names(
+    conn: &PgConnection,
+    for_user_id: Option<i32>,
+  ) -> Result<Vec<String>, Error> {
+    let flags = FeatureFlag::list_all(conn)?;
+    let user_settings = match for_user_id {
+      Some(for_user_id) => FeatureFlagUser::list_for_user(conn, for_user_id)?,
+      None => Vec::new(),
+    };
+    let enabled = flags
+      .into_iter()
+      .filter(|flag| {
+        let user_setting = user_settings
+          .iter()
+          .find(|s| s.feature_flag_id == flag.id)
+          .map(|s| s.enabled);
+        flag.is_enabled_for(for_user_id, user_setting)
+      })
+      .map(|flag| flag.name)
+      .collect();
+    Ok(enabled)
+  }
+
+  /// A disabled flag is off for everyone. Otherwise the setting for the user wins, and then the
+  /// rollout percentage. Users who aren't logged in only get fully rolled out features.
+  pub fn is_enabled_for(&self, for_user_id: Option<i32>, user_setting: Option<bool>) -> bool {
+    if !self.enabled {
+      return false;
+    }
+    if let Some(user_setting) = user_setting {
+      return user_setting;
+    }
+    match for_user_id {
+      Some(for_user_id) => rollout_bucket(&self.name, for_user_id) < self.rollout_percentage,
+      None => self.rollout_percentage >= 100,
+    }
+  }
+}
+
+/// A number from 0 to 99 for the user, which stays the same when the percentage is raised, so
+/// that users who already have the feature keep it. Every flag orders the users differently.
+pub fn rollout_bucket(flag_name: &str, for_user_id: i32) -> i16 {
+  let mut hasher = Sha256::new();
+  hasher.update(format!("{}:{}", flag_name, for_user_id));
+  let hash = hasher.finalize();
+  (u16::from_be_bytes([hash[0], hash[1]]) % 100) as i16
+}
+
+impl FeatureFlagUser {
+  pub fn read_setting(
+    conn: &PgConnection,
+    flag_id: i32,
+    for_user_id: i32,
+  ) -> Result<Option<bool>, Error> {
+    use crate::schema::feature_flag_user::dsl::*;
+    feature_flag_user
+      .filter(feature_flag_id.eq(flag_id))
+      .filter(user_id.eq(for_user_id))
+      .select(enabled)
+      .first::<bool>(conn)
+      .optional()
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::feature_flag_user::dsl::*;
+    feature_flag_user
+      .filter(user_id.eq(for_user_id))
+      .load::<Self>(conn)
+  }
+
+  pub fn list_for_flag(conn: &PgConnection, flag_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::feature_flag_user::dsl::*;
+    feature_flag_user
+      .filter(feature_flag_id.eq(flag_id))
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+
+  /// Turns the flag on or off for the user, replacing an earlier setting.
+  pub fn set(conn: &PgConnection, form: &FeatureFlagUserForm) -> Result<Self, Error> {
+    use crate::schema::feature_flag_user::dsl::*;
+    insert_into(feature_flag_user)
+      .values(form)
+      .on_conflict((feature_flag_id, user_id))
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// Lets the rollout percentage decide for the user again.
+  pub fn remove(conn: &PgConnection, flag_id: i32, for_user_id: i32) -> Result<usize, Error> {
+    use crate::schema::feature_flag_user::dsl::*;
+    diesel::delete(
+      feature_flag_user
+        .filter(feature_flag_id.eq(flag_id))
+        .filter(user_id.eq(for_user_id)),
+    )
+    .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    feature_flag::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "feature_flag_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let flag_form = FeatureFlagForm {
+      name: "test_flag_crud".into(),
+      description: Some("A flag for the tests".into()),
+      enabled: true,
+      rollout_percentage: 0,
+    };
+    let inserted_flag = FeatureFlag::save(&conn, &flag_form).unwrap();
+    let enabled_before =
+      FeatureFlag::is_enabled(&conn, "test_flag_crud", Some(inserted_user.id)).unwrap();
+
+    let user_form = FeatureFlagUserForm {
+      feature_flag_id: inserted_flag.id,
+      user_id: inserted_user.id,
+      enabled: true,
+    };
+    FeatureFlagUser::set(&conn, &user_form).unwrap();
+    let enabled_for_user =
+      FeatureFlag::is_enabled(&conn, "test_flag_crud", Some(inserted_user.id)).unwrap();
+    let enabled_anonymous = FeatureFlag::is_enabled(&conn, "test_flag_crud", None).unwrap();
+    let enabled_names = FeatureFlag::list_enabled_names(&conn, Some(inserted_user.id)).unwrap();
+    let missing_flag =
+      FeatureFlag::is_enabled(&conn, "test_flag_missing", Some(inserted_user.id)).unwrap();
+
+    let disabled_form = FeatureFlagForm {
+      enabled: false,
+      ..flag_form
+    };
+    let disabled_flag = FeatureFlag::save(&conn, &disabled_form).unwrap();
+    let enabled_when_disabled =
+      FeatureFlag::is_enabled(&conn, "test_flag_crud", Some(inserted_user.id)).unwrap();
+
+    let num_removed = FeatureFlagUser::remove(&conn, inserted_flag.id, inserted_user.id).unwrap();
+    let num_deleted = FeatureFlag::delete(&conn, inserted_flag.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(inserted_flag.id, disabled_flag.id);
+    assert!(!enabled_before);
+    assert!(enabled_for_user);
+    assert!(!enabled_anonymous);
+    assert!(enabled_names.contains(&"test_flag_crud".to_string()));
+    assert!(!missing_flag);
+    assert!(!enabled_when_disabled);
+    assert_eq!(1, num_removed);
+    assert_eq!(1, num_deleted);
+  }
+
+  #[test]
+  fn test_rollout() {
+    let flag = FeatureFlag {
+      id: 0,
+      name: "test_rollout".into(),
+      description: None,
+      enabled: true,
+      rollout_percentage: 30,
+      published: naive_now(),
+      updated: None,
+    };
+
+    let enabled = (0..1000)
+      .filter(|user_id| flag.is_enabled_for(Some(*user_id), None))
+      .count();
+    assert!(enabled > 200 && enabled < 400);
+
+    // Raising the percentage keeps the users who already have the feature
+    let raised = FeatureFlag {
+      rollout_percentage: 60,
+      ..flag.clone()
+    };
+    assert!((0..1000)
+      .filter(|user_id| flag.is_enabled_for(Some(*user_id), None))
+      .all(|user_id| raised.is_enabled_for(Some(user_id), None)));
+
+    assert_eq!(
+      rollout_bucket("test_rollout", 42),
+      rollout_bucket("test_rollout", 42)
+    );
+    assert!(!flag.is_enabled_for(None, None));
+    assert!(flag.is_enabled_for(Some(1), Some(true)));
+    assert!(!flag.is_enabled_for(Some(1), Some(false)));
+  }
+}
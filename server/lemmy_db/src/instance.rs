@@ -0,0 +1,221 @@
+use crate::{naive_now, schema::instance};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// Another instance we federate with. The software it runs is filled in by the instance crawler,
+/// from its nodeinfo.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "instance"]
+pub struct Instance {
+  pub id: i32,
+  pub domain: String,
+  pub software: Option<String>,
+  pub version: Option<String>,
+  pub published: chrono::NaiveDateTime,
+  pub crawled: Option<chrono::NaiveDateTime>,
+  pub supports_groups: bool,
+  pub supports_votes: bool,
+}
+
+/// The length of the software and version columns.
+const MAX_SOFTWARE_LENGTH: usize = 255;
+
+/// Software whose users can follow groups, and which understands announces from them.
+const GROUP_SOFTWARE: [&str; 6] = ["lemmy", "kbin", "mbin", "lotide", "piefed", "friendica"];
+
+/// Software which understands likes and dislikes as votes.
+const VOTE_SOFTWARE: [&str; 7] = [
+  "lemmy",
+  "kbin",
+  "mbin",
+  "lotide",
+  "piefed",
+  "friendica",
+  "peertube",
+];
+
+/// Whether the software supports groups and votes, going by its nodeinfo name.
+pub fn software_capabilities(software_name: &str) -> (bool, bool) {
+  let name = software_name.to_lowercase();
+  (
+    GROUP_SOFTWARE.contains(&name.as_str()),
+    VOTE_SOFTWARE.contains(&name.as_str()),
+  )
+}
+
+impl Instance {
+  /// Adds the instances of the remote users and communities which aren't known yet.
+  pub fn add_linked(conn: &PgConnection) -> Result<usize, Error> {
+    sql_query(
+      "insert into instance (domain)
+      select distinct lower(substring(actor_id from '^[a-z]+://([^/:]+)'))
+      from (
+        select actor_id from user_ where not local
+        union select actor_id from community where not local
+      ) actors
+      where substring(actor_id from '^[a-z]+://([^/:]+)') is not null
+      on conflict (domain) do nothing",
+    )
+    .execute(conn)
+  }
+
+  pub fn list(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::instance::dsl::*;
+    instance.order_by(domain.asc()).load::<Self>(conn)
+  }
+
+  /// The known instances among `domains`.
+  pub fn list_for_domains(conn: &PgConnection, domains: Vec<String>) -> Result<Vec<Self>, Error> {
+    use crate::schema::instance::dsl::*;
+    instance.filter(domain.eq_any(domains)).load::<Self>(conn)
+  }
+
+  /// The instances which were never crawled, or not since `crawled_before`, those never crawled
+  /// first.
+  pub fn list_uncrawled(
+    conn: &PgConnection,
+    crawled_before: chrono::NaiveDateTime,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::instance::dsl::*;
+    instance
+      .filter(crawled.is_null().or(crawled.lt(crawled_before)))
+      .order_by(crawled.is_not_null())
+      .then_order_by(crawled.asc())
+      .limit(limit)
+      .load::<Self>(conn)
+  }
+
+  /// The name and version come from the nodeinfo of the instance, so they are cut to the length of
+  /// their columns.
+  pub fn update_software(
+    conn: &PgConnection,
+    instance_id: i32,
+    new_software: &str,
+    new_version: &str,
+  ) -> Result<Self, Error> {
+    use crate::schema::instance::dsl::*;
+    let new_software = new_software
+      .chars()
+      .take(MAX_SOFTWARE_LENGTH)
+      .collect::<String>();
+    let new_version = new_version
+      .chars()
+      .take(MAX_SOFTWARE_LENGTH)
+      .collect::<String>();
+    let (groups, votes) = software_capabilities(&new_software);
+    diesel::update(instance.find(instance_id))
+      .set((
+        software.eq(new_software),
+        version.eq(new_version),
+        supports_groups.eq(groups),
+        supports_votes.eq(votes),
+        crawled.eq(naive_now()),
+      ))
+      .get_result::<Self>(conn)
+  }
+
+  /// Moves the instance to the back of the crawl queue, keeping the software found before, eg if it
+  /// is unreachable.
+  pub fn mark_crawled(conn: &PgConnection, instance_id: i32) -> Result<Self, Error> {
+    use crate::schema::instance::dsl::*;
+    diesel::update(instance.find(instance_id))
+      .set(crawled.eq(naive_now()))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    instance::*,
+    naive_now,
+    schema::instance,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+  use diesel::{QueryDsl, RunQueryDsl};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "instance_peer_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: Some("https://Peer-Instance.example:8536/u/instance_peer_user".into()),
+      bio: None,
+      local: false,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    Instance::add_linked(&conn).unwrap();
+    let linked = Instance::list(&conn)
+      .unwrap()
+      .into_iter()
+      .find(|i| i.domain == "peer-instance.example")
+      .unwrap();
+    let uncrawled = Instance::list_uncrawled(&conn, naive_now(), 10000).unwrap();
+    let crawled = Instance::update_software(&conn, linked.id, "lemmy", "0.8.0").unwrap();
+    let recrawled = Instance::mark_crawled(&conn, linked.id).unwrap();
+    let by_domain =
+      Instance::list_for_domains(&conn, vec!["peer-instance.example".to_string()]).unwrap();
+    let microblog = Instance::update_software(&conn, linked.id, "Mastodon", "3.2.1").unwrap();
+    let long_version =
+      Instance::update_software(&conn, linked.id, "lemmy", &"1".repeat(300)).unwrap();
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+    diesel::delete(instance::table.find(linked.id))
+      .execute(&conn)
+      .unwrap();
+
+    assert_eq!(None, linked.software);
+    assert!(uncrawled.iter().any(|i| i.id == linked.id));
+    assert_eq!(Some("lemmy".to_string()), crawled.software);
+    assert_eq!(Some("0.8.0".to_string()), crawled.version);
+    assert_eq!(Some("lemmy".to_string()), recrawled.software);
+    assert!(recrawled.crawled.is_some());
+    assert_eq!(vec![recrawled], by_domain);
+    assert!(linked.supports_groups && linked.supports_votes);
+    assert!(crawled.supports_groups && crawled.supports_votes);
+    assert!(!microblog.supports_groups && !microblog.supports_votes);
+    assert_eq!(Some("1".repeat(255)), long_version.version);
+  }
+
+  #[test]
+  fn test_software_capabilities() {
+    assert_eq!((true, true), software_capabilities("lemmy"));
+    assert_eq!((true, true), software_capabilities("Friendica"));
+    assert_eq!((false, true), software_capabilities("peertube"));
+    assert_eq!((false, false), software_capabilities("mastodon"));
+    assert_eq!((false, false), software_capabilities("pleroma"));
+  }
+}
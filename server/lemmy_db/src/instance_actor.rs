@@ -0,0 +1,86 @@
+use crate::schema::instance_actor;
+use diesel::{dsl::*, result::Error, *};
+
+/// The keys of the Application actor of the instance itself. There is only one, which is created by
+/// a code migration.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
+#[table_name = "instance_actor"]
+pub struct InstanceActor {
+  pub id: i32,
+  pub private_key: String,
+  pub public_key: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "instance_actor"]
+pub struct InstanceActorForm {
+  pub private_key: String,
+  pub public_key: String,
+}
+
+impl InstanceActor {
+  pub fn create(conn: &PgConnection, form: &InstanceActorForm) -> Result<Self, Error> {
+    use crate::schema::instance_actor::dsl::*;
+    insert_into(instance_actor)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection) -> Result<Self, Error> {
+    use crate::schema::instance_actor::dsl::*;
+    instance_actor.order_by(id.asc()).first::<Self>(conn)
+  }
+
+  pub fn update_keys(
+    conn: &PgConnection,
+    instance_actor_id: i32,
+    form: &InstanceActorForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::instance_actor::dsl::*;
+    diesel::update(instance_actor.find(instance_actor_id))
+      .set((
+        private_key.eq(&form.private_key),
+        public_key.eq(&form.public_key),
+      ))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{instance_actor::*, schema::instance_actor, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let before = InstanceActor::read(&conn).ok();
+
+    let form = InstanceActorForm {
+      private_key: "private".into(),
+      public_key: "public".into(),
+    };
+    let inserted = InstanceActor::create(&conn, &form).unwrap();
+    let read = InstanceActor::read(&conn).unwrap();
+    let rotated = InstanceActor::update_keys(
+      &conn,
+      inserted.id,
+      &InstanceActorForm {
+        private_key: "rotated private".into(),
+        public_key: "rotated public".into(),
+      },
+    )
+    .unwrap();
+
+    diesel::delete(instance_actor::table.find(inserted.id))
+      .execute(&conn)
+      .unwrap();
+
+    // A newer one doesn't replace the first
+    assert_eq!(before.unwrap_or_else(|| inserted.to_owned()), read);
+    assert_eq!("private", inserted.private_key);
+    assert_eq!("rotated private", rotated.private_key);
+    assert_eq!("rotated public", rotated.public_key);
+  }
+}
@@ -0,0 +1,186 @@
+use crate::schema::{instance_peer_stats_daily, instance_stats_daily};
+use diesel::{dsl::*, result::Error, sql_types::Date, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "instance_stats_daily"]
+pub struct InstanceStatsDaily {
+  pub id: i32,
+  pub day: chrono::NaiveDate,
+  pub registrations: i32,
+  pub active_users: i32,
+  pub activities_in: i32,
+  pub activities_out: i32,
+  pub queue_pending: i32,
+  pub queue_running: i32,
+  pub queue_failed: i32,
+  pub database_bytes: i64,
+  pub updated: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "instance_peer_stats_daily"]
+pub struct InstancePeerStatsDaily {
+  pub id: i32,
+  pub day: chrono::NaiveDate,
+  pub domain: String,
+  pub activities_in: i32,
+  pub activities_out: i32,
+  pub requests_retried: i32,
+  pub circuit_breaker_trips: i32,
+}
+
+impl InstanceStatsDaily {
+  /// Today in the time zone of the database, which the stats are counted in.
+  pub fn today(conn: &PgConnection) -> Result<chrono::NaiveDate, Error> {
+    select(date(now)).get_result::<chrono::NaiveDate>(conn)
+  }
+
+  /// Recounts the registrations, active users, incoming activities and storage of the day.
+  pub fn refresh(conn: &PgConnection, for_day: chrono::NaiveDate) -> Result<usize, Error> {
+    sql_query("select refresh_instance_stats_daily($1)")
+      .bind::<Date, _>(for_day)
+      .execute(conn)
+  }
+
+  pub fn update_queue(
+    conn: &PgConnection,
+    for_day: chrono::NaiveDate,
+    pending: i32,
+    running: i32,
+    failed: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::instance_stats_daily::dsl::*;
+    diesel::update(instance_stats_daily.filter(day.eq(for_day)))
+      .set((
+        queue_pending.eq(pending),
+        queue_running.eq(running),
+        queue_failed.eq(failed),
+      ))
+      .execute(conn)
+  }
+
+  pub fn list(
+    conn: &PgConnection,
+    since: chrono::NaiveDate,
+    until: chrono::NaiveDate,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::instance_stats_daily::dsl::*;
+    instance_stats_daily
+      .filter(day.between(since, until))
+      .order_by(day.asc())
+      .load::<Self>(conn)
+  }
+}
+
+impl InstancePeerStatsDaily {
+  /// Adds activities sent to another instance, to its stats and to the ones of the instance. The
+  /// day has to be refreshed first.
+  pub fn add_activities_out(
+    conn: &PgConnection,
+    for_day: chrono::NaiveDate,
+    for_domain: &str,
+    count: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::instance_peer_stats_daily::dsl::*;
+    insert_into(instance_peer_stats_daily)
+      .values((
+        day.eq(for_day),
+        domain.eq(for_domain),
+        activities_out.eq(count),
+      ))
+      .on_conflict((day, domain))
+      .do_update()
+      .set(activities_out.eq(activities_out + count))
+      .execute(conn)?;
+
+    diesel::update(instance_stats_daily::table.filter(instance_stats_daily::day.eq(for_day)))
+      .set(instance_stats_daily::activities_out.eq(instance_stats_daily::activities_out + count))
+      .execute(conn)
+  }
+
+  /// Adds retried requests to another instance, and how often its circuit breaker opened.
+  pub fn add_request_failures(
+    conn: &PgConnection,
+    for_day: chrono::NaiveDate,
+    for_domain: &str,
+    retried: i32,
+    trips: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::instance_peer_stats_daily::dsl::*;
+    insert_into(instance_peer_stats_daily)
+      .values((
+        day.eq(for_day),
+        domain.eq(for_domain),
+        requests_retried.eq(retried),
+        circuit_breaker_trips.eq(trips),
+      ))
+      .on_conflict((day, domain))
+      .do_update()
+      .set((
+        requests_retried.eq(requests_retried + retried),
+        circuit_breaker_trips.eq(circuit_breaker_trips + trips),
+      ))
+      .execute(conn)
+  }
+
+  pub fn list(
+    conn: &PgConnection,
+    since: chrono::NaiveDate,
+    until: chrono::NaiveDate,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::instance_peer_stats_daily::dsl::*;
+    instance_peer_stats_daily
+      .filter(day.between(since, until))
+      .order_by((day.asc(), domain.asc()))
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{instance_stats::*, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let today = InstanceStatsDaily::today(&conn).unwrap();
+    InstanceStatsDaily::refresh(&conn, today).unwrap();
+    InstanceStatsDaily::update_queue(&conn, today, 3, 1, 0).unwrap();
+    InstancePeerStatsDaily::add_activities_out(&conn, today, "stats.example.com", 2).unwrap();
+    InstancePeerStatsDaily::add_activities_out(&conn, today, "stats.example.com", 3).unwrap();
+    InstancePeerStatsDaily::add_request_failures(&conn, today, "stats.example.com", 4, 1).unwrap();
+
+    let stats = InstanceStatsDaily::list(&conn, today, today).unwrap();
+    let peer_stats = InstancePeerStatsDaily::list(&conn, today, today)
+      .unwrap()
+      .into_iter()
+      .filter(|p| p.domain == "stats.example.com")
+      .collect::<Vec<InstancePeerStatsDaily>>();
+
+    diesel::delete(
+      instance_peer_stats_daily::table
+        .filter(instance_peer_stats_daily::domain.eq("stats.example.com")),
+    )
+    .execute(&conn)
+    .unwrap();
+
+    let expected_peer_stats = InstancePeerStatsDaily {
+      id: peer_stats[0].id,
+      day: today,
+      domain: "stats.example.com".into(),
+      activities_in: 0,
+      activities_out: 5,
+      requests_retried: 4,
+      circuit_breaker_trips: 1,
+    };
+
+    assert_eq!(1, stats.len());
+    assert_eq!(3, stats[0].queue_pending);
+    assert_eq!(1, stats[0].queue_running);
+    assert!(stats[0].activities_out >= 5);
+    assert!(stats[0].database_bytes > 0);
+    assert_eq!(vec![expected_peer_stats], peer_stats);
+  }
+}
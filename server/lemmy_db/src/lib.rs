@@ -4,7 +4,6 @@ extern crate diesel;
 extern crate strum_macros;
 #[macro_use]
 extern crate lazy_static;
-extern crate bcrypt;
 extern crate chrono;
 extern crate log;
 extern crate regex;
@@ -20,25 +19,63 @@ use serde::{Deserialize, Serialize};
 use std::{env, env::VarError};
 
 pub mod activity;
+pub mod activity_send_queue;
+pub mod admin_impersonation;
 pub mod category;
 pub mod comment;
 pub mod comment_view;
+pub mod comment_view_count;
 pub mod community;
+pub mod community_digest;
+pub mod community_join_request;
+pub mod community_notification;
+pub mod community_post_tag;
+pub mod community_stats;
 pub mod community_view;
+pub mod community_welcome;
+pub mod content_report;
+pub mod csp_report;
+pub mod default_community;
+pub mod domain;
+pub mod email_outbox;
+pub mod feature_flag;
+pub mod instance;
+pub mod instance_actor;
+pub mod instance_stats;
 pub mod moderator;
 pub mod moderator_views;
 pub mod password_reset_request;
+pub mod plugin_hook;
 pub mod post;
+pub mod post_comment_sort;
+pub mod post_edit;
+pub mod post_media;
 pub mod post_view;
+pub mod post_view_count;
 pub mod private_message;
 pub mod private_message_view;
+pub mod push_subscription;
+pub mod received_activity;
+pub mod removal_reason;
+pub mod saved_search;
+pub mod scheduled_post;
 pub mod schema;
+pub mod sign_in_signal;
 pub mod site;
+pub mod site_announcement;
+pub mod site_branding;
 pub mod site_view;
+pub mod spam_score;
+pub mod thread_mute;
+pub mod trust;
 pub mod user;
 pub mod user_mention;
 pub mod user_mention_view;
+pub mod user_presence;
 pub mod user_view;
+pub mod vote_burst;
+pub mod vote_instance;
+pub mod wasm_plugin;
 
 pub type DbPool = diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<diesel::PgConnection>>;
 
@@ -146,6 +183,19 @@ pub enum SortType {
   TopAll,
 }
 
+/// Orders for the comments of a single post. Listings across posts use `SortType`.
+#[derive(EnumString, ToString, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CommentSortType {
+  Hot,
+  New,
+  Old,
+  Top,
+  /// Comments with many votes in both directions
+  Controversial,
+  /// Replies of the post creator first, then the comments they answered
+  QA,
+}
+
 #[derive(EnumString, ToString, Debug, Serialize, Deserialize)]
 pub enum ListingType {
   All,
@@ -4,6 +4,7 @@ use crate::{
     mod_add_community,
     mod_ban,
     mod_ban_from_community,
+    mod_bulk_action,
     mod_lock_post,
     mod_remove_comment,
     mod_remove_community,
@@ -55,6 +56,23 @@ impl Crud<ModRemovePostForm> for ModRemovePost {
   }
 }
 
+impl ModRemovePost {
+  /// Whether a mod removed or restored the post at or after `since`.
+  pub fn exists_for_post_since(
+    conn: &PgConnection,
+    for_post_id: i32,
+    since: chrono::NaiveDateTime,
+  ) -> Result<bool, Error> {
+    use crate::schema::mod_remove_post::dsl::*;
+    select(exists(
+      mod_remove_post
+        .filter(post_id.eq(for_post_id))
+        .filter(when_.ge(since)),
+    ))
+    .get_result(conn)
+  }
+}
+
 #[derive(Queryable, Identifiable, PartialEq, Debug)]
 #[table_name = "mod_lock_post"]
 pub struct ModLockPost {
@@ -270,6 +288,90 @@ impl Crud<ModBanFromCommunityForm> for ModBanFromCommunity {
   }
 }
 
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
+#[table_name = "mod_bulk_action"]
+pub struct ModBulkAction {
+  pub id: i32,
+  pub mod_user_id: i32,
+  pub community_id: i32,
+  pub other_user_ids: Vec<i32>,
+  pub banned: bool,
+  pub since: Option<chrono::NaiveDateTime>,
+  pub until: Option<chrono::NaiveDateTime>,
+  pub reason: Option<String>,
+  pub total: i32,
+  pub done: i32,
+  pub finished: bool,
+  pub when_: chrono::NaiveDateTime,
+  pub failed_post_ids: Vec<i32>,
+  pub failed_comment_ids: Vec<i32>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "mod_bulk_action"]
+pub struct ModBulkActionForm {
+  pub mod_user_id: i32,
+  pub community_id: i32,
+  pub other_user_ids: Vec<i32>,
+  pub banned: bool,
+  pub since: Option<chrono::NaiveDateTime>,
+  pub until: Option<chrono::NaiveDateTime>,
+  pub reason: Option<String>,
+  pub total: i32,
+}
+
+impl Crud<ModBulkActionForm> for ModBulkAction {
+  fn read(conn: &PgConnection, from_id: i32) -> Result<Self, Error> {
+    use crate::schema::mod_bulk_action::dsl::*;
+    mod_bulk_action.find(from_id).first::<Self>(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ModBulkActionForm) -> Result<Self, Error> {
+    use crate::schema::mod_bulk_action::dsl::*;
+    insert_into(mod_bulk_action)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, from_id: i32, form: &ModBulkActionForm) -> Result<Self, Error> {
+    use crate::schema::mod_bulk_action::dsl::*;
+    diesel::update(mod_bulk_action.find(from_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ModBulkAction {
+  pub fn update_progress(conn: &PgConnection, from_id: i32, new_done: i32) -> Result<Self, Error> {
+    use crate::schema::mod_bulk_action::dsl::*;
+    diesel::update(mod_bulk_action.find(from_id))
+      .set(done.eq(new_done))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_failed(
+    conn: &PgConnection,
+    from_id: i32,
+    new_failed_post_ids: Vec<i32>,
+    new_failed_comment_ids: Vec<i32>,
+  ) -> Result<Self, Error> {
+    use crate::schema::mod_bulk_action::dsl::*;
+    diesel::update(mod_bulk_action.find(from_id))
+      .set((
+        failed_post_ids.eq(new_failed_post_ids),
+        failed_comment_ids.eq(new_failed_comment_ids),
+      ))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn finish(conn: &PgConnection, from_id: i32) -> Result<Self, Error> {
+    use crate::schema::mod_bulk_action::dsl::*;
+    diesel::update(mod_bulk_action.find(from_id))
+      .set(finished.eq(true))
+      .get_result::<Self>(conn)
+  }
+}
+
 #[derive(Queryable, Identifiable, PartialEq, Debug)]
 #[table_name = "mod_ban"]
 pub struct ModBan {
@@ -431,6 +533,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_mod = User_::create(&conn, &new_mod).unwrap();
@@ -459,6 +567,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -501,6 +615,10 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
       ap_id: None,
       local: true,
       published: None,
@@ -520,6 +638,7 @@ mod tests {
       updated: None,
       ap_id: None,
       local: true,
+      distinguished: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
@@ -647,6 +766,47 @@ mod tests {
       when_: inserted_mod_ban_from_community.when_,
     };
 
+    // bulk action
+
+    let mod_bulk_action_form = ModBulkActionForm {
+      mod_user_id: inserted_mod.id,
+      community_id: inserted_community.id,
+      other_user_ids: vec![inserted_user.id],
+      banned: true,
+      since: None,
+      until: None,
+      reason: None,
+      total: 1,
+    };
+    let inserted_mod_bulk_action = ModBulkAction::create(&conn, &mod_bulk_action_form).unwrap();
+    let read_mod_bulk_action = ModBulkAction::read(&conn, inserted_mod_bulk_action.id).unwrap();
+    let expected_mod_bulk_action = ModBulkAction {
+      id: inserted_mod_bulk_action.id,
+      mod_user_id: inserted_mod.id,
+      community_id: inserted_community.id,
+      other_user_ids: vec![inserted_user.id],
+      banned: true,
+      since: None,
+      until: None,
+      reason: None,
+      total: 1,
+      done: 0,
+      finished: false,
+      when_: inserted_mod_bulk_action.when_,
+      failed_post_ids: vec![],
+      failed_comment_ids: vec![],
+    };
+    ModBulkAction::update_progress(&conn, inserted_mod_bulk_action.id, 1).unwrap();
+    ModBulkAction::update_failed(
+      &conn,
+      inserted_mod_bulk_action.id,
+      vec![],
+      vec![inserted_comment.id],
+    )
+    .unwrap();
+    let finished_mod_bulk_action =
+      ModBulkAction::finish(&conn, inserted_mod_bulk_action.id).unwrap();
+
     // ban
 
     let mod_ban_form = ModBanForm {
@@ -718,6 +878,13 @@ mod tests {
     assert_eq!(expected_mod_remove_comment, read_mod_remove_comment);
     assert_eq!(expected_mod_remove_community, read_mod_remove_community);
     assert_eq!(expected_mod_ban_from_community, read_mod_ban_from_community);
+    assert_eq!(expected_mod_bulk_action, read_mod_bulk_action);
+    assert_eq!(1, finished_mod_bulk_action.done);
+    assert_eq!(
+      vec![inserted_comment.id],
+      finished_mod_bulk_action.failed_comment_ids
+    );
+    assert!(finished_mod_bulk_action.finished);
     assert_eq!(expected_mod_ban, read_mod_ban);
     assert_eq!(expected_mod_add_community, read_mod_add_community);
     assert_eq!(expected_mod_add, read_mod_add);
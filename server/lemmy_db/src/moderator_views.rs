@@ -355,6 +355,82 @@ impl ModBanFromCommunityView {
   }
 }
 
+table! {
+  mod_bulk_action_view (id) {
+    id -> Int4,
+    mod_user_id -> Int4,
+    community_id -> Int4,
+    other_user_ids -> Array<Int4>,
+    banned -> Bool,
+    since -> Nullable<Timestamp>,
+    until -> Nullable<Timestamp>,
+    reason -> Nullable<Text>,
+    total -> Int4,
+    done -> Int4,
+    finished -> Bool,
+    when_ -> Timestamp,
+    failed_post_ids -> Array<Int4>,
+    failed_comment_ids -> Array<Int4>,
+    mod_user_name -> Varchar,
+    community_name -> Varchar,
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, QueryableByName, Clone)]
+#[table_name = "mod_bulk_action_view"]
+pub struct ModBulkActionView {
+  pub id: i32,
+  pub mod_user_id: i32,
+  pub community_id: i32,
+  pub other_user_ids: Vec<i32>,
+  pub banned: bool,
+  pub since: Option<chrono::NaiveDateTime>,
+  pub until: Option<chrono::NaiveDateTime>,
+  pub reason: Option<String>,
+  pub total: i32,
+  pub done: i32,
+  pub finished: bool,
+  pub when_: chrono::NaiveDateTime,
+  pub failed_post_ids: Vec<i32>,
+  pub failed_comment_ids: Vec<i32>,
+  pub mod_user_name: String,
+  pub community_name: String,
+}
+
+impl ModBulkActionView {
+  pub fn read(conn: &PgConnection, from_id: i32) -> Result<Self, Error> {
+    use super::moderator_views::mod_bulk_action_view::dsl::*;
+    mod_bulk_action_view.find(from_id).first::<Self>(conn)
+  }
+
+  pub fn list(
+    conn: &PgConnection,
+    from_community_id: Option<i32>,
+    from_mod_user_id: Option<i32>,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use super::moderator_views::mod_bulk_action_view::dsl::*;
+    let mut query = mod_bulk_action_view.into_boxed();
+
+    let (limit, offset) = limit_and_offset(page, limit);
+
+    if let Some(from_community_id) = from_community_id {
+      query = query.filter(community_id.eq(from_community_id));
+    };
+
+    if let Some(from_mod_user_id) = from_mod_user_id {
+      query = query.filter(mod_user_id.eq(from_mod_user_id));
+    };
+
+    query
+      .limit(limit)
+      .offset(offset)
+      .order_by(when_.desc())
+      .load::<Self>(conn)
+  }
+}
+
 table! {
   mod_ban_view (id) {
     id -> Int4,
@@ -0,0 +1,143 @@
+use crate::{schema::plugin_hook, Crud};
+use diesel::{dsl::*, result::Error, *};
+use serde::{Deserialize, Serialize};
+
+/// The points where plugins are called, before the thing happens.
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum HookPoint {
+  PrePostCreate,
+  PreRegister,
+  PreFederationSend,
+}
+
+/// What happens when a plugin can't be reached, times out or answers with nonsense.
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum HookFailurePolicy {
+  Allow,
+  Reject,
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "plugin_hook"]
+pub struct PluginHook {
+  pub id: i32,
+  pub name: String,
+  pub url: String,
+  pub hook_point: String,
+  pub timeout_ms: i32,
+  pub failure_policy: String,
+  #[serde(skip_serializing)]
+  pub secret: Option<String>,
+  pub enabled: bool,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "plugin_hook"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct PluginHookForm {
+  pub name: String,
+  pub url: String,
+  pub hook_point: String,
+  pub timeout_ms: i32,
+  pub failure_policy: String,
+  pub secret: Option<String>,
+  pub enabled: bool,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+impl Crud<PluginHookForm> for PluginHook {
+  fn read(conn: &PgConnection, hook_id: i32) -> Result<Self, Error> {
+    use crate::schema::plugin_hook::dsl::*;
+    plugin_hook.find(hook_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, hook_id: i32) -> Result<usize, Error> {
+    use crate::schema::plugin_hook::dsl::*;
+    diesel::delete(plugin_hook.find(hook_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &PluginHookForm) -> Result<Self, Error> {
+    use crate::schema::plugin_hook::dsl::*;
+    insert_into(plugin_hook)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, hook_id: i32, form: &PluginHookForm) -> Result<Self, Error> {
+    use crate::schema::plugin_hook::dsl::*;
+    diesel::update(plugin_hook.find(hook_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl PluginHook {
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::plugin_hook::dsl::*;
+    plugin_hook.order_by(id.asc()).load::<Self>(conn)
+  }
+
+  /// In the order they are called, which is the order they were registered in.
+  pub fn list_enabled(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::plugin_hook::dsl::*;
+    plugin_hook
+      .filter(enabled.eq(true))
+      .order_by(id.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{plugin_hook::*, tests::establish_unpooled_connection, Crud};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_hook = PluginHookForm {
+      name: "spam filter".into(),
+      url: "http://localhost:9000/hook".into(),
+      hook_point: HookPoint::PrePostCreate.to_string(),
+      timeout_ms: 500,
+      failure_policy: HookFailurePolicy::Allow.to_string(),
+      secret: Some("hunter2".into()),
+      enabled: true,
+      updated: None,
+    };
+
+    let inserted_hook = PluginHook::create(&conn, &new_hook).unwrap();
+
+    let expected_hook = PluginHook {
+      id: inserted_hook.id,
+      name: "spam filter".into(),
+      url: "http://localhost:9000/hook".into(),
+      hook_point: "PrePostCreate".into(),
+      timeout_ms: 500,
+      failure_policy: "Allow".into(),
+      secret: Some("hunter2".into()),
+      enabled: true,
+      published: inserted_hook.published,
+      updated: None,
+    };
+
+    let enabled_hooks = PluginHook::list_enabled(&conn).unwrap();
+    let disabled_form = PluginHookForm {
+      enabled: false,
+      ..new_hook
+    };
+    let updated_hook = PluginHook::update(&conn, inserted_hook.id, &disabled_form).unwrap();
+    let enabled_after_update = PluginHook::list_enabled(&conn).unwrap();
+    let read_hook = PluginHook::read(&conn, inserted_hook.id).unwrap();
+    let num_deleted = PluginHook::delete(&conn, inserted_hook.id).unwrap();
+
+    assert_eq!(expected_hook, inserted_hook);
+    assert!(enabled_hooks.contains(&inserted_hook));
+    assert!(!updated_hook.enabled);
+    assert!(!enabled_after_update.contains(&updated_hook));
+    assert_eq!(updated_hook, read_hook);
+    assert_eq!(1, num_deleted);
+  }
+}
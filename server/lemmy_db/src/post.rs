@@ -9,7 +9,7 @@ use crate::{
 use diesel::{dsl::*, result::Error, *};
 use url::{ParseError, Url};
 
-#[derive(Queryable, Identifiable, PartialEq, Debug)]
+#[derive(Clone, Queryable, Identifiable, PartialEq, Debug)]
 #[table_name = "post"]
 pub struct Post {
   pub id: i32,
@@ -31,6 +31,12 @@ pub struct Post {
   pub thumbnail_url: Option<String>,
   pub ap_id: String,
   pub local: bool,
+  pub thumbnail_alt_text: Option<String>,
+  pub distinguished: bool,
+  pub pinned_comment_id: Option<i32>,
+  pub anonymous: bool,
+  pub comments_closed: bool,
+  pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Insertable, AsChangeset)]
@@ -54,6 +60,10 @@ pub struct PostForm {
   pub thumbnail_url: Option<String>,
   pub ap_id: Option<String>,
   pub local: bool,
+  pub thumbnail_alt_text: Option<String>,
+  pub distinguished: Option<bool>,
+  pub anonymous: Option<bool>,
+  pub comments_closed: Option<bool>,
 }
 
 impl PostForm {
@@ -75,12 +85,23 @@ impl Post {
     use crate::schema::post::dsl::*;
     post
       .filter(community_id.eq(the_community_id))
+      .filter(anonymous.eq(false))
       .then_order_by(published.desc())
       .then_order_by(stickied.desc())
       .limit(20)
       .load::<Self>(conn)
   }
 
+  /// How many posts the user created during the last hour.
+  pub fn count_recent_for_creator(conn: &PgConnection, for_creator_id: i32) -> Result<i64, Error> {
+    use crate::schema::post::dsl::*;
+    post
+      .filter(creator_id.eq(for_creator_id))
+      .filter(published.gt(now - 1.hours()))
+      .count()
+      .get_result(conn)
+  }
+
   pub fn read_from_apub_id(conn: &PgConnection, object_id: &str) -> Result<Self, Error> {
     use crate::schema::post::dsl::*;
     post.filter(ap_id.eq(object_id)).first::<Self>(conn)
@@ -114,17 +135,51 @@ impl Post {
       .get_results::<Self>(conn)
   }
 
+  /// Attributes everything of a creator to another user, eg to the placeholder of deleted
+  /// accounts.
+  pub fn reassign_creator(
+    conn: &PgConnection,
+    from_creator_id: i32,
+    to_creator_id: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.filter(creator_id.eq(from_creator_id)))
+      .set(creator_id.eq(to_creator_id))
+      .execute(conn)
+  }
+
   pub fn update_deleted(
     conn: &PgConnection,
     post_id: i32,
     new_deleted: bool,
   ) -> Result<Self, Error> {
     use crate::schema::post::dsl::*;
+    let deleted_time = naive_now();
     diesel::update(post.find(post_id))
-      .set((deleted.eq(new_deleted), updated.eq(naive_now())))
+      .set((
+        deleted.eq(new_deleted),
+        deleted_at.eq(if new_deleted { Some(deleted_time) } else { None }),
+        updated.eq(deleted_time),
+      ))
       .get_result::<Self>(conn)
   }
 
+  pub fn update_deleted_for_creator(
+    conn: &PgConnection,
+    for_creator_id: i32,
+    new_deleted: bool,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::post::dsl::*;
+    let deleted_time = naive_now();
+    diesel::update(post.filter(creator_id.eq(for_creator_id)))
+      .set((
+        deleted.eq(new_deleted),
+        deleted_at.eq(if new_deleted { Some(deleted_time) } else { None }),
+        updated.eq(deleted_time),
+      ))
+      .get_results::<Self>(conn)
+  }
+
   pub fn update_removed(
     conn: &PgConnection,
     post_id: i32,
@@ -156,6 +211,33 @@ impl Post {
       .get_results::<Self>(conn)
   }
 
+  /// The posts of a user in a community that aren't removed yet, published in the given time
+  /// window.
+  pub fn list_for_creator_in_community(
+    conn: &PgConnection,
+    for_creator_id: i32,
+    for_community_id: i32,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::post::dsl::*;
+    let mut query = post
+      .filter(creator_id.eq(for_creator_id))
+      .filter(community_id.eq(for_community_id))
+      .filter(removed.eq(false))
+      .into_boxed();
+
+    if let Some(since) = since {
+      query = query.filter(published.ge(since));
+    }
+
+    if let Some(until) = until {
+      query = query.filter(published.le(until));
+    }
+
+    query.order_by(published.asc()).load::<Self>(conn)
+  }
+
   pub fn update_locked(conn: &PgConnection, post_id: i32, new_locked: bool) -> Result<Self, Error> {
     use crate::schema::post::dsl::*;
     diesel::update(post.find(post_id))
@@ -163,6 +245,17 @@ impl Post {
       .get_result::<Self>(conn)
   }
 
+  pub fn update_comments_closed(
+    conn: &PgConnection,
+    post_id: i32,
+    new_comments_closed: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(comments_closed.eq(new_comments_closed))
+      .get_result::<Self>(conn)
+  }
+
   pub fn update_stickied(
     conn: &PgConnection,
     post_id: i32,
@@ -174,10 +267,39 @@ impl Post {
       .get_result::<Self>(conn)
   }
 
+  pub fn update_distinguished(
+    conn: &PgConnection,
+    post_id: i32,
+    new_distinguished: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(distinguished.eq(new_distinguished))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_pinned_comment(
+    conn: &PgConnection,
+    post_id: i32,
+    new_pinned_comment_id: Option<i32>,
+  ) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(pinned_comment_id.eq(new_pinned_comment_id))
+      .get_result::<Self>(conn)
+  }
+
   pub fn is_post_creator(user_id: i32, post_creator_id: i32) -> bool {
     user_id == post_creator_id
   }
 
+  /// Whether votes on the post and its comments are locked, given the vote_lock_days of the site.
+  pub fn is_archived(&self, vote_lock_days: Option<i32>) -> bool {
+    vote_lock_days
+      .map(|days| self.published < naive_now() - chrono::Duration::days(days as i64))
+      .unwrap_or(false)
+  }
+
   pub fn upsert(conn: &PgConnection, post_form: &PostForm) -> Result<Post, Error> {
     use crate::schema::post::dsl::*;
     insert_into(post)
@@ -222,6 +344,8 @@ pub struct PostLike {
   pub user_id: i32,
   pub score: i16,
   pub published: chrono::NaiveDateTime,
+  /// The instance of remote voters
+  pub instance: Option<String>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -364,6 +488,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -406,6 +536,10 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
       ap_id: None,
       local: true,
       published: None,
@@ -431,6 +565,12 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: false,
+      pinned_comment_id: None,
+      anonymous: false,
+      comments_closed: false,
+      deleted_at: None,
       ap_id: inserted_post.ap_id.to_owned(),
       local: true,
     };
@@ -450,6 +590,7 @@ mod tests {
       user_id: inserted_user.id,
       published: inserted_post_like.published,
       score: 1,
+      instance: None,
     };
 
     // Post Save
@@ -483,7 +624,26 @@ mod tests {
     };
 
     let read_post = Post::read(&conn, inserted_post.id).unwrap();
+    let creator_posts = Post::list_for_creator_in_community(
+      &conn,
+      inserted_user.id,
+      inserted_community.id,
+      Some(inserted_post.published),
+      None,
+    )
+    .unwrap();
+    let later_posts = Post::list_for_creator_in_community(
+      &conn,
+      inserted_user.id,
+      inserted_community.id,
+      Some(inserted_post.published + chrono::Duration::seconds(1)),
+      None,
+    )
+    .unwrap();
     let updated_post = Post::update(&conn, inserted_post.id, &new_post).unwrap();
+    let closed_post = Post::update_comments_closed(&conn, inserted_post.id, true).unwrap();
+    let deleted_post = Post::update_deleted(&conn, inserted_post.id, true).unwrap();
+    let restored_post = Post::update_deleted(&conn, inserted_post.id, false).unwrap();
     let like_removed = PostLike::remove(&conn, inserted_user.id, inserted_post.id).unwrap();
     let saved_removed = PostSaved::unsave(&conn, &post_saved_form).unwrap();
     let read_removed = PostRead::mark_as_unread(&conn, &post_read_form).unwrap();
@@ -492,8 +652,14 @@ mod tests {
     User_::delete(&conn, inserted_user.id).unwrap();
 
     assert_eq!(expected_post, read_post);
+    assert_eq!(vec![expected_post.to_owned()], creator_posts);
+    assert!(later_posts.is_empty());
     assert_eq!(expected_post, inserted_post);
     assert_eq!(expected_post, updated_post);
+    assert!(closed_post.comments_closed);
+    assert!(!closed_post.locked);
+    assert!(deleted_post.deleted_at.is_some());
+    assert_eq!(None, restored_post.deleted_at);
     assert_eq!(expected_post_like, inserted_post_like);
     assert_eq!(expected_post_saved, inserted_post_saved);
     assert_eq!(expected_post_read, inserted_post_read);
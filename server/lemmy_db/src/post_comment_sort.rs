@@ -0,0 +1,205 @@
+use crate::{naive_now, schema::post_comment_sort, CommentSortType};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// How long the comment sort picked for a post is remembered.
+pub const COMMENT_SORT_MEMORY_DAYS: i32 = 30;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "post_comment_sort"]
+pub struct PostCommentSort {
+  pub id: i32,
+  pub user_id: i32,
+  pub post_id: i32,
+  pub comment_sort: String,
+  pub updated: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "post_comment_sort"]
+pub struct PostCommentSortForm {
+  pub user_id: i32,
+  pub post_id: i32,
+  pub comment_sort: String,
+  pub updated: chrono::NaiveDateTime,
+}
+
+impl PostCommentSort {
+  /// Remembers the sort for the post, and forgets the expired ones of the user.
+  pub fn remember(
+    conn: &PgConnection,
+    for_user_id: i32,
+    for_post_id: i32,
+    for_comment_sort: CommentSortType,
+  ) -> Result<Self, Error> {
+    use crate::schema::post_comment_sort::dsl::*;
+    let form = PostCommentSortForm {
+      user_id: for_user_id,
+      post_id: for_post_id,
+      comment_sort: for_comment_sort.to_string(),
+      updated: naive_now(),
+    };
+    conn.transaction::<_, Error, _>(|| {
+      diesel::delete(
+        post_comment_sort
+          .filter(user_id.eq(for_user_id))
+          .filter(updated.lt(now - COMMENT_SORT_MEMORY_DAYS.days())),
+      )
+      .execute(conn)?;
+      insert_into(post_comment_sort)
+        .values(&form)
+        .on_conflict((user_id, post_id))
+        .do_update()
+        .set(&form)
+        .get_result::<Self>(conn)
+    })
+  }
+
+  /// The sort the user last picked for the post, unless it expired.
+  pub fn recall(
+    conn: &PgConnection,
+    for_user_id: i32,
+    for_post_id: i32,
+  ) -> Result<Option<CommentSortType>, Error> {
+    use crate::schema::post_comment_sort::dsl::*;
+    let remembered = post_comment_sort
+      .filter(user_id.eq(for_user_id))
+      .filter(post_id.eq(for_post_id))
+      .filter(updated.gt(now - COMMENT_SORT_MEMORY_DAYS.days()))
+      .select(comment_sort)
+      .first::<String>(conn)
+      .optional()?;
+    Ok(remembered.and_then(|s| s.parse().ok()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    post::*,
+    post_comment_sort::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    CommentSortType,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "comment_sorter".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: true,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_comment_sort".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A sorted post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let unknown = PostCommentSort::recall(&conn, inserted_user.id, inserted_post.id).unwrap();
+    PostCommentSort::remember(
+      &conn,
+      inserted_user.id,
+      inserted_post.id,
+      CommentSortType::Top,
+    )
+    .unwrap();
+    let remembered = PostCommentSort::recall(&conn, inserted_user.id, inserted_post.id).unwrap();
+    let changed = PostCommentSort::remember(
+      &conn,
+      inserted_user.id,
+      inserted_post.id,
+      CommentSortType::QA,
+    )
+    .unwrap();
+    let remembered_again =
+      PostCommentSort::recall(&conn, inserted_user.id, inserted_post.id).unwrap();
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(None, unknown);
+    assert_eq!(Some(CommentSortType::Top), remembered);
+    assert_eq!("QA", changed.comment_sort);
+    assert_eq!(Some(CommentSortType::QA), remembered_again);
+  }
+}
@@ -0,0 +1,200 @@
+use crate::{
+  post::{Post, PostForm},
+  schema::post_edit,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// The version of a post before one of its edits.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "post_edit"]
+pub struct PostEdit {
+  pub id: i32,
+  pub post_id: i32,
+  pub editor_id: i32,
+  pub name: String,
+  pub url: Option<String>,
+  pub body: Option<String>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "post_edit"]
+pub struct PostEditForm {
+  pub post_id: i32,
+  pub editor_id: i32,
+  pub name: String,
+  pub url: Option<String>,
+  pub body: Option<String>,
+}
+
+impl PostEdit {
+  /// Keeps the version of the post before the edit, if the edit changes its title, url or body.
+  pub fn record(
+    conn: &PgConnection,
+    before: &Post,
+    after: &PostForm,
+    for_editor_id: i32,
+  ) -> Result<Option<Self>, Error> {
+    use crate::schema::post_edit::dsl::*;
+    if before.name == after.name && before.url == after.url && before.body == after.body {
+      return Ok(None);
+    }
+    let form = PostEditForm {
+      post_id: before.id,
+      editor_id: for_editor_id,
+      name: before.name.to_owned(),
+      url: before.url.to_owned(),
+      body: before.body.to_owned(),
+    };
+    insert_into(post_edit)
+      .values(&form)
+      .get_result::<Self>(conn)
+      .map(Some)
+  }
+
+  /// Replaces the editor with a placeholder, for anonymous posts, like `PostView::hide_creator`.
+  pub fn hide_editor(mut self) -> Self {
+    self.editor_id = 0;
+    self
+  }
+
+  /// The earlier versions of a post, the most recent first.
+  pub fn list_for_post(conn: &PgConnection, for_post_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::post_edit::dsl::*;
+    post_edit
+      .filter(post_id.eq(for_post_id))
+      .order_by(published.desc())
+      .then_order_by(id.desc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    post::*,
+    post_edit::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "post_editor".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_post_edit".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A linked post".into(),
+      url: Some("https://example.com/first".into()),
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let unchanged = PostEdit::record(&conn, &inserted_post, &new_post, inserted_user.id).unwrap();
+    let moved_post = PostForm {
+      url: Some("https://example.com/second".into()),
+      ..new_post
+    };
+    let recorded_edit =
+      PostEdit::record(&conn, &inserted_post, &moved_post, inserted_user.id).unwrap();
+    let edits = PostEdit::list_for_post(&conn, inserted_post.id).unwrap();
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let expected_edit = PostEdit {
+      id: edits[0].id,
+      post_id: inserted_post.id,
+      editor_id: inserted_user.id,
+      name: "A linked post".into(),
+      url: Some("https://example.com/first".into()),
+      body: None,
+      published: edits[0].published,
+    };
+
+    assert_eq!(None, unchanged);
+    assert_eq!(Some(expected_edit.to_owned()), recorded_edit);
+    assert_eq!(vec![expected_edit], edits);
+  }
+}
@@ -0,0 +1,246 @@
+use crate::{
+  schema::{post_media, post_media::dsl::*},
+  Crud,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// The maximum number of images that can be attached to a single post.
+pub const MAX_POST_MEDIA: usize = 20;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "post_media"]
+pub struct PostMedia {
+  pub id: i32,
+  pub post_id: i32,
+  pub position: i32,
+  pub url: String,
+  pub caption: Option<String>,
+  pub alt_text: Option<String>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "post_media"]
+pub struct PostMediaForm {
+  pub post_id: i32,
+  pub position: i32,
+  pub url: String,
+  pub caption: Option<String>,
+  pub alt_text: Option<String>,
+}
+
+impl Crud<PostMediaForm> for PostMedia {
+  fn read(conn: &PgConnection, post_media_id: i32) -> Result<Self, Error> {
+    post_media.find(post_media_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, post_media_id: i32) -> Result<usize, Error> {
+    diesel::delete(post_media.find(post_media_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &PostMediaForm) -> Result<Self, Error> {
+    insert_into(post_media)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, post_media_id: i32, form: &PostMediaForm) -> Result<Self, Error> {
+    diesel::update(post_media.find(post_media_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl PostMedia {
+  pub fn list_for_post(conn: &PgConnection, for_post_id: i32) -> Result<Vec<Self>, Error> {
+    post_media
+      .filter(post_id.eq(for_post_id))
+      .order_by(position.asc())
+      .load::<Self>(conn)
+  }
+
+  /// The galleries of a page of posts, ordered by post and position.
+  pub fn list_for_posts(conn: &PgConnection, post_ids: &[i32]) -> Result<Vec<Self>, Error> {
+    post_media
+      .filter(post_id.eq_any(post_ids))
+      .order_by((post_id.asc(), position.asc()))
+      .load::<Self>(conn)
+  }
+
+  /// Replaces the whole gallery of a post. Positions are taken from the order of `forms`.
+  pub fn replace_for_post(
+    conn: &PgConnection,
+    for_post_id: i32,
+    forms: &[PostMediaForm],
+  ) -> Result<Vec<Self>, Error> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::delete(post_media.filter(post_id.eq(for_post_id))).execute(conn)?;
+
+      let forms = forms
+        .iter()
+        .enumerate()
+        .map(|(i, f)| PostMediaForm {
+          post_id: for_post_id,
+          position: i as i32,
+          ..f.to_owned()
+        })
+        .collect::<Vec<PostMediaForm>>();
+
+      insert_into(post_media)
+        .values(&forms)
+        .get_results::<Self>(conn)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    post::*,
+    post_media::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "gallery_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_gallery".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A gallery post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let media_forms = vec![
+      PostMediaForm {
+        post_id: inserted_post.id,
+        position: 0,
+        url: "https://example.com/a.png".into(),
+        caption: Some("first".into()),
+        alt_text: Some("a cat".into()),
+      },
+      PostMediaForm {
+        post_id: inserted_post.id,
+        position: 0,
+        url: "https://example.com/b.png".into(),
+        caption: None,
+        alt_text: None,
+      },
+    ];
+
+    let inserted_media =
+      PostMedia::replace_for_post(&conn, inserted_post.id, &media_forms).unwrap();
+
+    let expected_media = PostMedia {
+      id: inserted_media[1].id,
+      post_id: inserted_post.id,
+      position: 1,
+      url: "https://example.com/b.png".into(),
+      caption: None,
+      alt_text: None,
+      published: inserted_media[1].published,
+    };
+
+    let read_media = PostMedia::list_for_post(&conn, inserted_post.id).unwrap();
+    let read_for_posts = PostMedia::list_for_posts(&conn, &[inserted_post.id]).unwrap();
+
+    // Replacing drops the old gallery
+    let replaced_media =
+      PostMedia::replace_for_post(&conn, inserted_post.id, &media_forms[..1]).unwrap();
+    let read_replaced = PostMedia::list_for_post(&conn, inserted_post.id).unwrap();
+
+    let num_deleted = PostMedia::delete(&conn, replaced_media[0].id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(2, read_media.len());
+    assert_eq!(expected_media, read_media[1]);
+    assert_eq!(inserted_media, read_media);
+    assert_eq!(read_media, read_for_posts);
+    assert_eq!(1, read_replaced.len());
+    assert_eq!(1, num_deleted);
+  }
+}
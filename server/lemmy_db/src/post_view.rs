@@ -1,7 +1,49 @@
 use super::post_view::post_fast_view::BoxedQuery;
-use crate::{fuzzy_search, limit_and_offset, ListingType, MaybeOptional, SortType};
-use diesel::{dsl::*, pg::Pg, result::Error, *};
+use crate::{
+  community::{hidden_private_communities, undiscoverable_communities, Community},
+  fuzzy_search,
+  limit_and_offset,
+  user::undiscoverable_users,
+  vote_instance::{post_votes_from_instances, UserIgnoredVoteInstance},
+  ListingType,
+  MaybeOptional,
+  SortType,
+};
+use diesel::{
+  dsl::*,
+  pg::Pg,
+  result::Error,
+  sql_types::{Float, Nullable, Text},
+  *,
+};
 use serde::Serialize;
+use std::collections::HashMap;
+
+/// The creator name which anonymous posts are shown with.
+pub const ANONYMOUS_CREATOR_NAME: &str = "anonymous";
+
+/// While their score is hidden, posts and comments are sorted as if nobody voted on them yet, which
+/// orders them by time, so that their place in the listing doesn't give the score away.
+pub(crate) const SHOWN_SCORE_SQL: &str = "case when score_hidden then 0 else score end";
+pub(crate) const SHOWN_HOT_RANK_SQL: &str =
+  "case when score_hidden then hot_rank(1, published) else hot_rank end";
+pub(crate) const SHOWN_HOT_RANK_ACTIVE_SQL: &str =
+  "case when score_hidden then hot_rank(1, published) else hot_rank_active end";
+
+/// How similar (by pg_trgm) a post title has to be to another one to be suggested as similar.
+/// It's used as pg_trgm.similarity_threshold, so that the trigram index can find these titles.
+pub const POST_SIMILARITY_THRESHOLD: f32 = 0.4;
+/// How similar a post title has to be to another one to count as nearly the same title.
+pub const DUPLICATE_TITLE_THRESHOLD: f32 = 0.8;
+/// How far back posts with nearly the same title are looked for, in days.
+pub const DUPLICATE_TITLE_DAYS: i32 = 7;
+
+sql_function!(fn similarity(a: Text, b: Text) -> Float);
+// True if the similarity is at least pg_trgm.similarity_threshold. Unlike a comparison with
+// similarity(), it can use the trigram index.
+diesel_infix_operator!(TrigramSimilar, " % ");
+// The host of the url, lowercased and without www.
+sql_function!(fn url_domain(url: Nullable<Text>) -> Nullable<Text>);
 
 // The faked schema since diesel doesn't do views
 table! {
@@ -25,6 +67,11 @@ table! {
     thumbnail_url -> Nullable<Text>,
     ap_id -> Text,
     local -> Bool,
+    thumbnail_alt_text -> Nullable<Text>,
+    distinguished -> Bool,
+    pinned_comment_id -> Nullable<Int4>,
+    anonymous -> Bool,
+    comments_closed -> Bool,
     creator_actor_id -> Text,
     creator_local -> Bool,
     creator_name -> Varchar,
@@ -52,6 +99,7 @@ table! {
     subscribed -> Nullable<Bool>,
     read -> Nullable<Bool>,
     saved -> Nullable<Bool>,
+    score_hidden -> Bool,
   }
 }
 
@@ -76,6 +124,11 @@ table! {
     thumbnail_url -> Nullable<Text>,
     ap_id -> Text,
     local -> Bool,
+    thumbnail_alt_text -> Nullable<Text>,
+    distinguished -> Bool,
+    pinned_comment_id -> Nullable<Int4>,
+    anonymous -> Bool,
+    comments_closed -> Bool,
     creator_actor_id -> Text,
     creator_local -> Bool,
     creator_name -> Varchar,
@@ -103,6 +156,7 @@ table! {
     subscribed -> Nullable<Bool>,
     read -> Nullable<Bool>,
     saved -> Nullable<Bool>,
+    score_hidden -> Bool,
   }
 }
 
@@ -128,6 +182,11 @@ pub struct PostView {
   pub thumbnail_url: Option<String>,
   pub ap_id: String,
   pub local: bool,
+  pub thumbnail_alt_text: Option<String>,
+  pub distinguished: bool,
+  pub pinned_comment_id: Option<i32>,
+  pub anonymous: bool,
+  pub comments_closed: bool,
   pub creator_actor_id: String,
   pub creator_local: bool,
   pub creator_name: String,
@@ -155,6 +214,7 @@ pub struct PostView {
   pub subscribed: Option<bool>,
   pub read: Option<bool>,
   pub saved: Option<bool>,
+  pub score_hidden: bool,
 }
 
 pub struct PostQueryBuilder<'a> {
@@ -166,11 +226,14 @@ pub struct PostQueryBuilder<'a> {
   for_creator_id: Option<i32>,
   for_community_id: Option<i32>,
   for_community_name: Option<String>,
+  for_community_ids: Option<Vec<i32>>,
   search_term: Option<String>,
   url_search: Option<String>,
+  for_domain: Option<String>,
   show_nsfw: bool,
   saved_only: bool,
   unread_only: bool,
+  include_removed: bool,
   page: Option<i64>,
   limit: Option<i64>,
 }
@@ -190,11 +253,14 @@ impl<'a> PostQueryBuilder<'a> {
       for_creator_id: None,
       for_community_id: None,
       for_community_name: None,
+      for_community_ids: None,
       search_term: None,
       url_search: None,
+      for_domain: None,
       show_nsfw: true,
       saved_only: false,
       unread_only: false,
+      include_removed: false,
       page: None,
       limit: None,
     }
@@ -225,6 +291,12 @@ impl<'a> PostQueryBuilder<'a> {
     self
   }
 
+  /// Only the posts of these communities.
+  pub fn for_community_ids<T: MaybeOptional<Vec<i32>>>(mut self, for_community_ids: T) -> Self {
+    self.for_community_ids = for_community_ids.get_optional();
+    self
+  }
+
   pub fn search_term<T: MaybeOptional<String>>(mut self, search_term: T) -> Self {
     self.search_term = search_term.get_optional();
     self
@@ -235,6 +307,14 @@ impl<'a> PostQueryBuilder<'a> {
     self
   }
 
+  /// Like example.com, which also finds the links to www.example.com.
+  pub fn for_domain<T: MaybeOptional<String>>(mut self, for_domain: T) -> Self {
+    self.for_domain = for_domain
+      .get_optional()
+      .map(|d| d.to_lowercase().trim_start_matches("www.").to_owned());
+    self
+  }
+
   pub fn my_user_id<T: MaybeOptional<i32>>(mut self, my_user_id: T) -> Self {
     self.my_user_id = my_user_id.get_optional();
     self
@@ -250,6 +330,13 @@ impl<'a> PostQueryBuilder<'a> {
     self
   }
 
+  /// Also shows removed and deleted posts, and the posts of users who opted out of search. Only
+  /// for mods, together with `for_community_ids`.
+  pub fn include_removed(mut self, include_removed: bool) -> Self {
+    self.include_removed = include_removed;
+    self
+  }
+
   pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
     self.page = page.get_optional();
     self
@@ -262,6 +349,7 @@ impl<'a> PostQueryBuilder<'a> {
 
   pub fn list(self) -> Result<Vec<PostView>, Error> {
     use super::post_view::post_fast_view::dsl::*;
+    use diesel::sql_types::{BigInt, Integer};
 
     let mut query = self.query;
 
@@ -281,39 +369,51 @@ impl<'a> PostQueryBuilder<'a> {
       query = query.then_order_by(stickied.desc());
     }
 
+    if let Some(for_community_ids) = self.for_community_ids {
+      query = query.filter(community_id.eq_any(for_community_ids));
+    }
+
     if let Some(url_search) = self.url_search {
       query = query.filter(url.eq(url_search));
     }
 
+    if let Some(for_domain) = self.for_domain {
+      query = query.filter(url_domain(url).eq(for_domain));
+    }
+
     if let Some(search_term) = self.search_term {
       let searcher = fuzzy_search(&search_term);
-      query = query
-        .filter(name.ilike(searcher.to_owned()))
-        .or_filter(body.ilike(searcher));
+      // Not or_filter, which would skip the filters before it for posts whose body matches
+      query = query.filter(name.ilike(searcher.to_owned()).or(body.ilike(searcher)));
+      // Respect the search opt-out of the post creator and of the community
+      if !self.include_removed {
+        query = query
+          .filter(creator_id.ne_all(undiscoverable_users(self.conn)?))
+          .filter(community_id.ne_all(undiscoverable_communities(self.conn)?));
+      }
     }
 
+    let shown_score = || sql::<BigInt>(SHOWN_SCORE_SQL).desc();
     query = match self.sort {
-      SortType::Active => query
-        .then_order_by(hot_rank_active.desc())
-        .then_order_by(published.desc()),
-      SortType::Hot => query
-        .then_order_by(hot_rank.desc())
-        .then_order_by(published.desc()),
-      SortType::New => query.then_order_by(published.desc()),
-      SortType::TopAll => query.then_order_by(score.desc()),
+      SortType::Active => query.then_order_by(sql::<Integer>(SHOWN_HOT_RANK_ACTIVE_SQL).desc()),
+      SortType::Hot => query.then_order_by(sql::<Integer>(SHOWN_HOT_RANK_SQL).desc()),
+      SortType::New => query,
+      SortType::TopAll => query.then_order_by(shown_score()),
       SortType::TopYear => query
         .filter(published.gt(now - 1.years()))
-        .then_order_by(score.desc()),
+        .then_order_by(shown_score()),
       SortType::TopMonth => query
         .filter(published.gt(now - 1.months()))
-        .then_order_by(score.desc()),
+        .then_order_by(shown_score()),
       SortType::TopWeek => query
         .filter(published.gt(now - 1.weeks()))
-        .then_order_by(score.desc()),
+        .then_order_by(shown_score()),
       SortType::TopDay => query
         .filter(published.gt(now - 1.days()))
-        .then_order_by(score.desc()),
+        .then_order_by(shown_score()),
     };
+    // Newest first among equals, like the posts whose score is hidden
+    query = query.then_order_by(published.desc());
 
     // The view lets you pass a null user_id, if you're not logged in
     query = if let Some(my_user_id) = self.my_user_id {
@@ -325,7 +425,11 @@ impl<'a> PostQueryBuilder<'a> {
     // If its for a specific user, show the removed / deleted
     if let Some(for_creator_id) = self.for_creator_id {
       query = query.filter(creator_id.eq(for_creator_id));
-    } else {
+      // Only the creator gets to see their anonymous posts on their profile
+      if self.my_user_id != Some(for_creator_id) {
+        query = query.filter(anonymous.eq(false));
+      }
+    } else if !self.include_removed {
       query = query
         .filter(removed.eq(false))
         .filter(deleted.eq(false))
@@ -348,20 +452,114 @@ impl<'a> PostQueryBuilder<'a> {
       query = query.filter(read.eq(false));
     };
 
+    // Private communities only show their posts to members
+    let hidden_communities = hidden_private_communities(self.conn, self.my_user_id)?;
+    if !hidden_communities.is_empty() {
+      query = query.filter(not(community_id.eq_any(hidden_communities)));
+    }
+
+    if !self.include_removed {
+      query = query
+        .filter(removed.eq(false))
+        .filter(deleted.eq(false))
+        .filter(community_removed.eq(false))
+        .filter(community_deleted.eq(false));
+    }
+
     let (limit, offset) = limit_and_offset(self.page, self.limit);
-    query = query
-      .limit(limit)
-      .offset(offset)
-      .filter(removed.eq(false))
-      .filter(deleted.eq(false))
-      .filter(community_removed.eq(false))
-      .filter(community_deleted.eq(false));
+    query = query.limit(limit).offset(offset);
 
-    query.load::<PostView>(self.conn)
+    let posts = query.load::<PostView>(self.conn)?;
+    PostView::hide_for_viewer(self.conn, posts, self.my_user_id)
   }
 }
 
 impl PostView {
+  /// Posts younger than the score_hidden_minutes of the site don't show their score yet, to
+  /// reduce bandwagon voting.
+  fn hide_score(mut self) -> Self {
+    if self.score_hidden {
+      self.score = 0;
+      self.upvotes = 0;
+      self.downvotes = 0;
+    }
+    self
+  }
+
+  /// Replaces the creator of an anonymous post with a placeholder.
+  pub fn hide_creator(mut self) -> Self {
+    if self.anonymous {
+      self.creator_id = 0;
+      self.creator_actor_id = String::new();
+      self.creator_local = true;
+      self.creator_name = ANONYMOUS_CREATOR_NAME.to_owned();
+      self.creator_preferred_username = None;
+      self.creator_published = self.published;
+      self.creator_avatar = None;
+      self.banned = false;
+      self.banned_from_community = false;
+    }
+    self
+  }
+
+  /// Leaves the votes from the instances which the viewer ignores out of the scores.
+  fn exclude_ignored_votes(
+    conn: &PgConnection,
+    mut posts: Vec<Self>,
+    my_user_id: i32,
+  ) -> Result<Vec<Self>, Error> {
+    let instances = UserIgnoredVoteInstance::instances_for_user(conn, my_user_id)?;
+    if instances.is_empty() {
+      return Ok(posts);
+    }
+    let post_ids = posts.iter().map(|p| p.id).collect::<Vec<i32>>();
+    let ignored_votes = post_votes_from_instances(conn, post_ids, instances)?;
+    for post in posts.iter_mut() {
+      if let Some((up, down)) = ignored_votes.get(&post.id) {
+        post.upvotes -= up;
+        post.downvotes -= down;
+        post.score -= up - down;
+      }
+    }
+    Ok(posts)
+  }
+
+  /// Hides the scores which aren't shown yet, and the creators of anonymous posts, unless the
+  /// viewer created the post or moderates its community. Votes from the instances the viewer
+  /// ignores aren't counted.
+  fn hide_for_viewer(
+    conn: &PgConnection,
+    posts: Vec<Self>,
+    my_user_id: Option<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    let posts = match my_user_id {
+      Some(my_user_id) => Self::exclude_ignored_votes(conn, posts, my_user_id)?,
+      None => posts,
+    };
+    let mut moderates = HashMap::new();
+    let posts = posts
+      .into_iter()
+      .map(|post| {
+        let post = post.hide_score();
+        if !post.anonymous || my_user_id == Some(post.creator_id) {
+          return post;
+        }
+        let can_see_creator = match my_user_id {
+          Some(my_user_id) => *moderates
+            .entry(post.community_id)
+            .or_insert_with(|| Community::is_mod_or_admin(conn, my_user_id, post.community_id)),
+          None => false,
+        };
+        if can_see_creator {
+          post
+        } else {
+          post.hide_creator()
+        }
+      })
+      .collect();
+    Ok(posts)
+  }
+
   pub fn read(
     conn: &PgConnection,
     from_post_id: i32,
@@ -380,7 +578,100 @@ impl PostView {
       query = query.filter(user_id.is_null());
     };
 
-    query.first::<Self>(conn)
+    let post = query.first::<Self>(conn)?;
+    Ok(Self::hide_for_viewer(conn, vec![post], my_user_id)?.remove(0))
+  }
+
+  /// Lists the posts of a community whose title is similar to `title`, or that link to the same
+  /// url, most similar first.
+  pub fn list_similar(
+    conn: &PgConnection,
+    for_community_id: i32,
+    title: &str,
+    url_search: Option<String>,
+    exclude_post_id: Option<i32>,
+    my_user_id: Option<i32>,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use super::post_view::post_fast_view::dsl::*;
+
+    let mut query = post_fast_view
+      .filter(TrigramSimilar::new(
+        name,
+        title.to_owned().into_sql::<sql_types::Text>(),
+      ))
+      .into_boxed();
+
+    if let Some(url_search) = url_search {
+      query = query.or_filter(url.eq(url_search));
+    }
+
+    if let Some(exclude_post_id) = exclude_post_id {
+      query = query.filter(id.ne(exclude_post_id));
+    }
+
+    // The view lets you pass a null user_id, if you're not logged in
+    query = if let Some(my_user_id) = my_user_id {
+      query.filter(user_id.eq(my_user_id))
+    } else {
+      query.filter(user_id.is_null())
+    };
+
+    if hidden_private_communities(conn, my_user_id)?.contains(&for_community_id) {
+      return Ok(Vec::new());
+    }
+
+    let posts = conn.transaction(|| {
+      // Only for this transaction, since the connection goes back to the pool
+      sql_query("select set_config('pg_trgm.similarity_threshold', $1, true)")
+        .bind::<sql_types::Text, _>(POST_SIMILARITY_THRESHOLD.to_string())
+        .execute(conn)?;
+      query
+        .filter(community_id.eq(for_community_id))
+        .filter(removed.eq(false))
+        .filter(deleted.eq(false))
+        .order_by(similarity(name, title).desc())
+        .then_order_by(published.desc())
+        .limit(limit)
+        .load::<Self>(conn)
+    })?;
+    Self::hide_for_viewer(conn, posts, my_user_id)
+  }
+
+  /// Lists the recent posts of a community with nearly the same title, newest first.
+  pub fn list_duplicate_titles(
+    conn: &PgConnection,
+    for_community_id: i32,
+    title: &str,
+    my_user_id: Option<i32>,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    use super::post_view::post_fast_view::dsl::*;
+
+    if hidden_private_communities(conn, my_user_id)?.contains(&for_community_id) {
+      return Ok(Vec::new());
+    }
+
+    let mut query = post_fast_view
+      .filter(community_id.eq(for_community_id))
+      .filter(similarity(name, title).gt(DUPLICATE_TITLE_THRESHOLD))
+      .filter(published.gt(now - DUPLICATE_TITLE_DAYS.days()))
+      .filter(removed.eq(false))
+      .filter(deleted.eq(false))
+      .into_boxed();
+
+    // The view lets you pass a null user_id, if you're not logged in
+    query = if let Some(my_user_id) = my_user_id {
+      query.filter(user_id.eq(my_user_id))
+    } else {
+      query.filter(user_id.is_null())
+    };
+
+    query
+      .order_by(published.desc())
+      .limit(limit)
+      .load::<Self>(conn)
+      .and_then(|posts| Self::hide_for_viewer(conn, posts, my_user_id))
   }
 }
 
@@ -429,6 +720,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -455,9 +752,10 @@ mod tests {
 
     let inserted_community = Community::create(&conn, &new_community).unwrap();
 
+    let post_url = "https://www.example.com/article?id=1";
     let new_post = PostForm {
       name: post_name.to_owned(),
-      url: None,
+      url: Some(post_url.to_owned()),
       body: None,
       creator_id: inserted_user.id,
       community_id: inserted_community.id,
@@ -471,6 +769,10 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
       ap_id: None,
       local: true,
       published: None,
@@ -492,6 +794,7 @@ mod tests {
       user_id: inserted_user.id,
       published: inserted_post_like.published,
       score: 1,
+      instance: None,
     };
 
     let read_post_listings_with_user = PostQueryBuilder::create(&conn)
@@ -513,13 +816,55 @@ mod tests {
     let read_post_listing_with_user =
       PostView::read(&conn, inserted_post.id, Some(inserted_user.id)).unwrap();
 
+    let similar_posts = PostView::list_similar(
+      &conn,
+      inserted_community.id,
+      "test post three",
+      None,
+      None,
+      None,
+      10,
+    )
+    .unwrap();
+    let similar_posts_excluded = PostView::list_similar(
+      &conn,
+      inserted_community.id,
+      "test post three",
+      None,
+      Some(inserted_post.id),
+      None,
+      10,
+    )
+    .unwrap();
+    let dissimilar_posts = PostView::list_similar(
+      &conn,
+      inserted_community.id,
+      "something else entirely",
+      None,
+      None,
+      None,
+      10,
+    )
+    .unwrap();
+    let duplicate_titles =
+      PostView::list_duplicate_titles(&conn, inserted_community.id, "Test Post 3", None, 10)
+        .unwrap();
+    let no_duplicate_titles = PostView::list_duplicate_titles(
+      &conn,
+      inserted_community.id,
+      "something else entirely",
+      None,
+      10,
+    )
+    .unwrap();
+
     // the non user version
     let expected_post_listing_no_user = PostView {
       user_id: None,
       my_vote: None,
       id: inserted_post.id,
       name: post_name.to_owned(),
-      url: None,
+      url: Some(post_url.to_owned()),
       body: None,
       creator_id: inserted_user.id,
       creator_name: user_name.to_owned(),
@@ -550,11 +895,17 @@ mod tests {
       subscribed: None,
       read: None,
       saved: None,
+      score_hidden: false,
       nsfw: false,
       embed_title: None,
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: false,
+      pinned_comment_id: None,
+      anonymous: false,
+      comments_closed: false,
       ap_id: inserted_post.ap_id.to_owned(),
       local: true,
       creator_actor_id: inserted_user.actor_id.to_owned(),
@@ -567,8 +918,8 @@ mod tests {
       user_id: Some(inserted_user.id),
       my_vote: Some(1),
       id: inserted_post.id,
-      name: post_name,
-      url: None,
+      name: post_name.to_owned(),
+      url: Some(post_url.to_owned()),
       body: None,
       removed: false,
       deleted: false,
@@ -599,11 +950,17 @@ mod tests {
       subscribed: Some(false),
       read: Some(false),
       saved: Some(false),
+      score_hidden: false,
       nsfw: false,
       embed_title: None,
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: false,
+      pinned_comment_id: None,
+      anonymous: false,
+      comments_closed: false,
       ap_id: inserted_post.ap_id.to_owned(),
       local: true,
       creator_actor_id: inserted_user.actor_id.to_owned(),
@@ -612,6 +969,36 @@ mod tests {
       community_local: true,
     };
 
+    let url_posts = PostQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .url_search(post_url.to_owned())
+      .list()
+      .unwrap();
+    let domain_posts = PostQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .for_domain("Example.com".to_owned())
+      .list()
+      .unwrap();
+    let other_domain_posts = PostQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .for_domain("example.org".to_owned())
+      .list()
+      .unwrap();
+
+    // Removed posts are only found by mod searches
+    Post::update_removed(&conn, inserted_post.id, true).unwrap();
+    let search_removed = PostQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .search_term(post_name.to_owned())
+      .list()
+      .unwrap();
+    let mod_search_removed = PostQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .include_removed(true)
+      .search_term(post_name.to_owned())
+      .list()
+      .unwrap();
+
     let like_removed = PostLike::remove(&conn, inserted_user.id, inserted_post.id).unwrap();
     let num_deleted = Post::delete(&conn, inserted_post.id).unwrap();
     Community::delete(&conn, inserted_community.id).unwrap();
@@ -630,6 +1017,25 @@ mod tests {
     assert_eq!(expected_post_listing_no_user, read_post_listing_no_user);
     assert_eq!(1, read_post_listings_no_user.len());
 
+    // Posts linking to the url or its domain
+    assert_eq!(vec![expected_post_listing_no_user.to_owned()], url_posts);
+    assert_eq!(1, domain_posts.len());
+    assert!(other_domain_posts.is_empty());
+
+    // Similar posts
+    assert_eq!(vec![expected_post_listing_no_user], similar_posts);
+    assert!(similar_posts_excluded.is_empty());
+    assert!(dissimilar_posts.is_empty());
+    assert_eq!(
+      vec![inserted_post.id],
+      duplicate_titles.iter().map(|p| p.id).collect::<Vec<i32>>()
+    );
+    assert!(no_duplicate_titles.is_empty());
+
+    assert!(search_removed.is_empty());
+    assert_eq!(1, mod_search_removed.len());
+    assert!(mod_search_removed[0].removed);
+
     // assert_eq!(expected_post, inserted_post);
     // assert_eq!(expected_post, updated_post);
     assert_eq!(expected_post_like, inserted_post_like);
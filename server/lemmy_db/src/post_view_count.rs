@@ -0,0 +1,199 @@
+use crate::schema::{post, post_view_count};
+use diesel::{dsl::*, result::Error, sql_types::BigInt, *};
+
+/// The views of a post. The visitors are the registers of a HyperLogLog sketch, and the unique
+/// views are its estimate, kept so that they don't need to be estimated again when read.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
+#[table_name = "post_view_count"]
+pub struct PostViewCount {
+  pub id: i32,
+  pub post_id: i32,
+  pub views: i64,
+  pub visitors: Vec<u8>,
+  pub unique_views: i64,
+  pub updated: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Debug)]
+#[table_name = "post_view_count"]
+pub struct PostViewCountForm {
+  pub post_id: i32,
+  pub views: i64,
+  pub visitors: Vec<u8>,
+  pub unique_views: i64,
+  pub updated: chrono::NaiveDateTime,
+}
+
+impl PostViewCount {
+  pub fn read_for_post(conn: &PgConnection, for_post_id: i32) -> Result<Option<Self>, Error> {
+    use crate::schema::post_view_count::dsl::*;
+    post_view_count
+      .filter(post_id.eq(for_post_id))
+      .first::<Self>(conn)
+      .optional()
+  }
+
+  /// Replaces the counts of the post, which the caller merged with the kept ones.
+  pub fn upsert(conn: &PgConnection, form: &PostViewCountForm) -> Result<Self, Error> {
+    use crate::schema::post_view_count::dsl::*;
+    insert_into(post_view_count)
+      .values(form)
+      .on_conflict(post_id)
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// The views and unique views of all the posts of the community, removed and deleted ones
+  /// included. Visitors of several posts count once for each of them.
+  pub fn totals_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<(i64, i64), Error> {
+    post_view_count::table
+      .inner_join(post::table)
+      .filter(post::community_id.eq(for_community_id))
+      .select((
+        sql::<BigInt>("coalesce(sum(post_view_count.views), 0)::bigint"),
+        sql::<BigInt>("coalesce(sum(post_view_count.unique_views), 0)::bigint"),
+      ))
+      .first::<(i64, i64)>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    naive_now,
+    post::*,
+    post_view_count::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "viewed_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_viewed".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = |name: &str| PostForm {
+      name: name.into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let first_post = Post::create(&conn, &new_post("A viewed post")).unwrap();
+    let second_post = Post::create(&conn, &new_post("Another viewed post")).unwrap();
+
+    let form = |post_id: i32, views: i64, unique_views: i64| PostViewCountForm {
+      post_id,
+      views,
+      visitors: vec![0, 1, 2],
+      unique_views,
+      updated: naive_now(),
+    };
+    let not_viewed = PostViewCount::read_for_post(&conn, first_post.id).unwrap();
+    let inserted = PostViewCount::upsert(&conn, &form(first_post.id, 5, 2)).unwrap();
+    let updated = PostViewCount::upsert(&conn, &form(first_post.id, 8, 3)).unwrap();
+    PostViewCount::upsert(&conn, &form(second_post.id, 4, 4)).unwrap();
+    let read = PostViewCount::read_for_post(&conn, first_post.id).unwrap();
+    let totals = PostViewCount::totals_for_community(&conn, inserted_community.id).unwrap();
+
+    // The counts go with the post
+    Post::delete(&conn, first_post.id).unwrap();
+    let after_delete = PostViewCount::read_for_post(&conn, first_post.id).unwrap();
+    Post::delete(&conn, second_post.id).unwrap();
+    let totals_without_posts =
+      PostViewCount::totals_for_community(&conn, inserted_community.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(None, not_viewed);
+    assert_eq!(inserted.id, updated.id);
+    assert_eq!(8, updated.views);
+    assert_eq!(3, updated.unique_views);
+    assert_eq!(vec![0, 1, 2], updated.visitors);
+    assert_eq!(Some(updated), read);
+    assert_eq!((12, 7), totals);
+    assert_eq!(None, after_delete);
+    assert_eq!((0, 0), totals_without_posts);
+  }
+}
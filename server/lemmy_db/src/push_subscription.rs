@@ -0,0 +1,172 @@
+use crate::schema::push_subscription;
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "push_subscription"]
+pub struct PushSubscription {
+  pub id: i32,
+  pub user_id: i32,
+  pub endpoint: String,
+  pub p256dh: String,
+  pub auth: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "push_subscription"]
+pub struct PushSubscriptionForm {
+  pub user_id: i32,
+  pub endpoint: String,
+  pub p256dh: String,
+  pub auth: String,
+}
+
+impl PushSubscription {
+  /// An endpoint belongs to one browser, so saving it again, eg after logging in as another user,
+  /// replaces the old subscription.
+  pub fn subscribe(conn: &PgConnection, form: &PushSubscriptionForm) -> Result<Self, Error> {
+    use crate::schema::push_subscription::dsl::*;
+    insert_into(push_subscription)
+      .values(form)
+      .on_conflict(endpoint)
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn unsubscribe(
+    conn: &PgConnection,
+    for_endpoint: &str,
+    for_user_id: i32,
+  ) -> Result<usize, Error> {
+    use crate::schema::push_subscription::dsl::*;
+    diesel::delete(
+      push_subscription
+        .filter(endpoint.eq(for_endpoint))
+        .filter(user_id.eq(for_user_id)),
+    )
+    .execute(conn)
+  }
+
+  /// For subscriptions which the push service says don't exist anymore.
+  pub fn delete_for_endpoint(conn: &PgConnection, for_endpoint: &str) -> Result<usize, Error> {
+    use crate::schema::push_subscription::dsl::*;
+    diesel::delete(push_subscription.filter(endpoint.eq(for_endpoint))).execute(conn)
+  }
+
+  pub fn list_for_users(conn: &PgConnection, for_user_ids: Vec<i32>) -> Result<Vec<Self>, Error> {
+    use crate::schema::push_subscription::dsl::*;
+    push_subscription
+      .filter(user_id.eq_any(for_user_ids))
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    push_subscription::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "push_subscriber".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let other_form = UserForm {
+      name: "other_subscriber".into(),
+      ..new_user
+    };
+
+    let inserted_other = User_::create(&conn, &other_form).unwrap();
+
+    let subscription_form = PushSubscriptionForm {
+      user_id: inserted_user.id,
+      endpoint: "https://push.example.com/abc".into(),
+      p256dh: "key".into(),
+      auth: "secret".into(),
+    };
+
+    let inserted_subscription = PushSubscription::subscribe(&conn, &subscription_form).unwrap();
+
+    let expected_subscription = PushSubscription {
+      id: inserted_subscription.id,
+      user_id: inserted_user.id,
+      endpoint: "https://push.example.com/abc".into(),
+      p256dh: "key".into(),
+      auth: "secret".into(),
+      published: inserted_subscription.published,
+    };
+
+    let listed = PushSubscription::list_for_users(&conn, vec![inserted_user.id]).unwrap();
+
+    // The same browser, logged in as the other user
+    let other_subscription_form = PushSubscriptionForm {
+      user_id: inserted_other.id,
+      ..subscription_form
+    };
+    let moved_subscription = PushSubscription::subscribe(&conn, &other_subscription_form).unwrap();
+    let listed_after_move =
+      PushSubscription::list_for_users(&conn, vec![inserted_user.id]).unwrap();
+    let not_unsubscribed =
+      PushSubscription::unsubscribe(&conn, "https://push.example.com/abc", inserted_user.id)
+        .unwrap();
+    let num_unsubscribed =
+      PushSubscription::unsubscribe(&conn, "https://push.example.com/abc", inserted_other.id)
+        .unwrap();
+
+    PushSubscription::subscribe(&conn, &other_subscription_form).unwrap();
+    let num_deleted =
+      PushSubscription::delete_for_endpoint(&conn, "https://push.example.com/abc").unwrap();
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+    User_::delete(&conn, inserted_other.id).unwrap();
+
+    assert_eq!(vec![expected_subscription], listed);
+    assert_eq!(inserted_subscription.id, moved_subscription.id);
+    assert_eq!(inserted_other.id, moved_subscription.user_id);
+    assert!(listed_after_move.is_empty());
+    assert_eq!(0, not_unsubscribed);
+    assert_eq!(1, num_unsubscribed);
+    assert_eq!(1, num_deleted);
+  }
+}
@@ -0,0 +1,82 @@
+use crate::schema::received_activity;
+use diesel::{dsl::*, result::Error, *};
+
+/// The id of an activity which one of the inboxes received.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
+#[table_name = "received_activity"]
+pub struct ReceivedActivity {
+  pub id: i32,
+  pub inbox: String,
+  pub ap_id: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+impl ReceivedActivity {
+  /// Keeps the id, and returns false if it was kept already, that is if the inbox received the
+  /// activity before.
+  pub fn insert(conn: &PgConnection, for_inbox: &str, activity_id: &str) -> Result<bool, Error> {
+    use crate::schema::received_activity::dsl::*;
+    insert_into(received_activity)
+      .values((inbox.eq(for_inbox), ap_id.eq(activity_id)))
+      .on_conflict_do_nothing()
+      .execute(conn)
+      .map(|inserted| inserted == 1)
+  }
+
+  /// Forgets the id, so that the activity is handled if the inbox receives it again.
+  pub fn remove(conn: &PgConnection, for_inbox: &str, activity_id: &str) -> Result<usize, Error> {
+    use crate::schema::received_activity::dsl::*;
+    diesel::delete(
+      received_activity
+        .filter(inbox.eq(for_inbox))
+        .filter(ap_id.eq(activity_id)),
+    )
+    .execute(conn)
+  }
+
+  pub fn delete_older_than(
+    conn: &PgConnection,
+    before: chrono::NaiveDateTime,
+  ) -> Result<usize, Error> {
+    use crate::schema::received_activity::dsl::*;
+    diesel::delete(received_activity.filter(published.lt(before))).execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{naive_now, received_activity::*, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let shared_inbox = "/inbox";
+    let community_inbox = "/c/main/inbox";
+    let first_id = "https://enterprise.lemmy.ml/activities/create/received-1";
+    let second_id = "https://enterprise.lemmy.ml/activities/like/received-2";
+    let first_received = ReceivedActivity::insert(&conn, shared_inbox, first_id).unwrap();
+    let first_received_again = ReceivedActivity::insert(&conn, shared_inbox, first_id).unwrap();
+    let first_received_by_community =
+      ReceivedActivity::insert(&conn, community_inbox, first_id).unwrap();
+    let second_received = ReceivedActivity::insert(&conn, shared_inbox, second_id).unwrap();
+
+    let removed = ReceivedActivity::remove(&conn, shared_inbox, second_id).unwrap();
+    let second_received_after_removal =
+      ReceivedActivity::insert(&conn, shared_inbox, second_id).unwrap();
+
+    let deleted =
+      ReceivedActivity::delete_older_than(&conn, naive_now() - chrono::Duration::days(1)).unwrap();
+    ReceivedActivity::remove(&conn, shared_inbox, first_id).unwrap();
+    ReceivedActivity::remove(&conn, community_inbox, first_id).unwrap();
+    ReceivedActivity::remove(&conn, shared_inbox, second_id).unwrap();
+
+    assert!(first_received);
+    assert!(!first_received_again);
+    assert!(first_received_by_community);
+    assert!(second_received);
+    assert_eq!(1, removed);
+    assert!(second_received_after_removal);
+    assert_eq!(0, deleted);
+  }
+}
@@ -0,0 +1,166 @@
+use crate::{schema::removal_reason, Crud};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "removal_reason"]
+pub struct RemovalReason {
+  pub id: i32,
+  pub community_id: i32,
+  pub title: String,
+  pub reason: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "removal_reason"]
+pub struct RemovalReasonForm {
+  pub community_id: i32,
+  pub title: String,
+  pub reason: String,
+}
+
+impl Crud<RemovalReasonForm> for RemovalReason {
+  fn read(conn: &PgConnection, removal_reason_id: i32) -> Result<Self, Error> {
+    use crate::schema::removal_reason::dsl::*;
+    removal_reason.find(removal_reason_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, removal_reason_id: i32) -> Result<usize, Error> {
+    use crate::schema::removal_reason::dsl::*;
+    diesel::delete(removal_reason.find(removal_reason_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &RemovalReasonForm) -> Result<Self, Error> {
+    use crate::schema::removal_reason::dsl::*;
+    insert_into(removal_reason)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    removal_reason_id: i32,
+    form: &RemovalReasonForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::removal_reason::dsl::*;
+    diesel::update(removal_reason.find(removal_reason_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl RemovalReason {
+  /// The removal reasons of a community, in the order they were added.
+  pub fn list_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::removal_reason::dsl::*;
+    removal_reason
+      .filter(community_id.eq(for_community_id))
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    removal_reason::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "removal_reason_mod".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_removal_reason".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let removal_reason_form = RemovalReasonForm {
+      community_id: inserted_community.id,
+      title: "Off topic".into(),
+      reason: "Posts have to be about the topic of the community.".into(),
+    };
+
+    let inserted_removal_reason = RemovalReason::create(&conn, &removal_reason_form).unwrap();
+
+    let expected_removal_reason = RemovalReason {
+      id: inserted_removal_reason.id,
+      community_id: inserted_community.id,
+      title: "Off topic".into(),
+      reason: "Posts have to be about the topic of the community.".into(),
+      published: inserted_removal_reason.published,
+    };
+
+    let read_removal_reason = RemovalReason::read(&conn, inserted_removal_reason.id).unwrap();
+    let community_reasons =
+      RemovalReason::list_for_community(&conn, inserted_community.id).unwrap();
+    let num_deleted = RemovalReason::delete(&conn, inserted_removal_reason.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(expected_removal_reason, read_removal_reason);
+    assert_eq!(expected_removal_reason, inserted_removal_reason);
+    assert_eq!(vec![expected_removal_reason], community_reasons);
+    assert_eq!(1, num_deleted);
+  }
+}
@@ -0,0 +1,381 @@
+use crate::{
+  limit_and_offset,
+  schema::{saved_search, saved_search_match},
+  Crud,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "saved_search"]
+pub struct SavedSearch {
+  pub id: i32,
+  pub user_id: i32,
+  pub query: String,
+  pub keywords: Vec<String>,
+  pub community_id: Option<i32>,
+  pub creator_id: Option<i32>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "saved_search"]
+pub struct SavedSearchForm {
+  pub user_id: i32,
+  pub query: String,
+  pub keywords: Vec<String>,
+  pub community_id: Option<i32>,
+  pub creator_id: Option<i32>,
+}
+
+impl Crud<SavedSearchForm> for SavedSearch {
+  fn read(conn: &PgConnection, saved_search_id: i32) -> Result<Self, Error> {
+    use crate::schema::saved_search::dsl::*;
+    saved_search.find(saved_search_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, saved_search_id: i32) -> Result<usize, Error> {
+    use crate::schema::saved_search::dsl::*;
+    diesel::delete(saved_search.find(saved_search_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &SavedSearchForm) -> Result<Self, Error> {
+    use crate::schema::saved_search::dsl::*;
+    insert_into(saved_search)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    saved_search_id: i32,
+    form: &SavedSearchForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::saved_search::dsl::*;
+    diesel::update(saved_search.find(saved_search_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl SavedSearch {
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::saved_search::dsl::*;
+    saved_search
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// Find the saved searches of other users that a new post matches. A search matches if all of
+  /// its keywords appear in the post, which the gin index on keywords can answer directly.
+  /// Searches filtered by creator never match anonymous posts, as that would reveal the creator.
+  pub fn matching_post(
+    conn: &PgConnection,
+    post_keywords: Vec<String>,
+    post_community_id: i32,
+    post_creator_id: i32,
+    post_anonymous: bool,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::saved_search::dsl::*;
+    let mut matching = saved_search
+      .filter(keywords.is_contained_by(post_keywords))
+      .filter(
+        community_id
+          .is_null()
+          .or(community_id.eq(post_community_id)),
+      )
+      .filter(user_id.ne(post_creator_id))
+      .into_boxed();
+
+    matching = if post_anonymous {
+      matching.filter(creator_id.is_null())
+    } else {
+      matching.filter(creator_id.is_null().or(creator_id.eq(post_creator_id)))
+    };
+
+    matching.load::<Self>(conn)
+  }
+}
+
+#[derive(Queryable, Identifiable, Associations, PartialEq, Debug, Serialize, Clone)]
+#[belongs_to(SavedSearch)]
+#[table_name = "saved_search_match"]
+pub struct SavedSearchMatch {
+  pub id: i32,
+  pub saved_search_id: i32,
+  pub post_id: i32,
+  pub read: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "saved_search_match"]
+pub struct SavedSearchMatchForm {
+  pub saved_search_id: i32,
+  pub post_id: i32,
+}
+
+impl SavedSearchMatch {
+  /// Returns None if the post was already matched by this search, eg when a federated post
+  /// arrives twice.
+  pub fn create(conn: &PgConnection, form: &SavedSearchMatchForm) -> Result<Option<Self>, Error> {
+    use crate::schema::saved_search_match::dsl::*;
+    insert_into(saved_search_match)
+      .values(form)
+      .on_conflict_do_nothing()
+      .get_result::<Self>(conn)
+      .optional()
+  }
+
+  pub fn list_for_user(
+    conn: &PgConnection,
+    for_user_id: i32,
+    unread_only: bool,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = saved_search_match::table
+      .inner_join(saved_search::table)
+      .filter(saved_search::user_id.eq(for_user_id))
+      .select(saved_search_match::all_columns)
+      .into_boxed();
+
+    if unread_only {
+      query = query.filter(saved_search_match::read.eq(false));
+    }
+
+    query
+      .order_by(saved_search_match::published.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  pub fn mark_all_as_read(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::saved_search_match::dsl::*;
+    let user_searches = saved_search::table
+      .select(saved_search::id)
+      .filter(saved_search::user_id.eq(for_user_id));
+    diesel::update(
+      saved_search_match
+        .filter(saved_search_id.eq_any(user_searches))
+        .filter(read.eq(false)),
+    )
+    .set(read.eq(true))
+    .get_results::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    post::*,
+    saved_search::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "saved_searcher".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let poster_form = UserForm {
+      name: "saved_search_poster".into(),
+      ..new_user
+    };
+
+    let inserted_poster = User_::create(&conn, &poster_form).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_saved_search".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let saved_search_form = SavedSearchForm {
+      user_id: inserted_user.id,
+      query: "Rust async".into(),
+      keywords: vec!["rust".into(), "async".into()],
+      community_id: Some(inserted_community.id),
+      creator_id: None,
+    };
+
+    let inserted_saved_search = SavedSearch::create(&conn, &saved_search_form).unwrap();
+
+    let expected_saved_search = SavedSearch {
+      id: inserted_saved_search.id,
+      user_id: inserted_user.id,
+      query: "Rust async".into(),
+      keywords: vec!["rust".into(), "async".into()],
+      community_id: Some(inserted_community.id),
+      creator_id: None,
+      published: inserted_saved_search.published,
+    };
+
+    let post_keywords = vec!["async".into(), "in".into(), "rust".into()];
+    let matching = SavedSearch::matching_post(
+      &conn,
+      post_keywords.to_owned(),
+      inserted_community.id,
+      inserted_poster.id,
+      false,
+    )
+    .unwrap();
+    let not_matching = SavedSearch::matching_post(
+      &conn,
+      vec!["rust".into()],
+      inserted_community.id,
+      inserted_poster.id,
+      false,
+    )
+    .unwrap();
+    let own_post = SavedSearch::matching_post(
+      &conn,
+      post_keywords.to_owned(),
+      inserted_community.id,
+      inserted_user.id,
+      false,
+    )
+    .unwrap();
+
+    let creator_search_form = SavedSearchForm {
+      query: "Rust async by poster".into(),
+      creator_id: Some(inserted_poster.id),
+      ..saved_search_form.to_owned()
+    };
+    let creator_search = SavedSearch::create(&conn, &creator_search_form).unwrap();
+    let creator_matching = SavedSearch::matching_post(
+      &conn,
+      post_keywords.to_owned(),
+      inserted_community.id,
+      inserted_poster.id,
+      false,
+    )
+    .unwrap();
+    let anonymous_matching = SavedSearch::matching_post(
+      &conn,
+      post_keywords,
+      inserted_community.id,
+      inserted_poster.id,
+      true,
+    )
+    .unwrap();
+    SavedSearch::delete(&conn, creator_search.id).unwrap();
+
+    let new_post = PostForm {
+      name: "Async in Rust".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_poster.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let match_form = SavedSearchMatchForm {
+      saved_search_id: inserted_saved_search.id,
+      post_id: inserted_post.id,
+    };
+
+    let inserted_match = SavedSearchMatch::create(&conn, &match_form)
+      .unwrap()
+      .unwrap();
+    let duplicate_match = SavedSearchMatch::create(&conn, &match_form).unwrap();
+
+    let unread_matches =
+      SavedSearchMatch::list_for_user(&conn, inserted_user.id, true, None, None).unwrap();
+    let marked_read = SavedSearchMatch::mark_all_as_read(&conn, inserted_user.id).unwrap();
+    let unread_after =
+      SavedSearchMatch::list_for_user(&conn, inserted_user.id, true, None, None).unwrap();
+
+    let num_deleted = SavedSearch::delete(&conn, inserted_saved_search.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+    User_::delete(&conn, inserted_poster.id).unwrap();
+
+    assert_eq!(expected_saved_search, inserted_saved_search);
+    assert_eq!(vec![expected_saved_search.to_owned()], matching);
+    assert_eq!(2, creator_matching.len());
+    assert_eq!(vec![expected_saved_search], anonymous_matching);
+    assert!(not_matching.is_empty());
+    assert!(own_post.is_empty());
+    assert_eq!(None, duplicate_match);
+    assert_eq!(vec![inserted_match], unread_matches);
+    assert_eq!(1, marked_read.len());
+    assert!(unread_after.is_empty());
+    assert_eq!(1, num_deleted);
+  }
+}
@@ -0,0 +1,278 @@
+use crate::{naive_now, schema::scheduled_post, Crud};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "scheduled_post"]
+pub struct ScheduledPost {
+  pub id: i32,
+  pub community_id: i32,
+  pub creator_id: i32,
+  pub title_template: String,
+  pub body: Option<String>,
+  pub schedule: String,
+  pub stickied: bool,
+  pub enabled: bool,
+  pub next_run: chrono::NaiveDateTime,
+  pub last_post_id: Option<i32>,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "scheduled_post"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct ScheduledPostForm {
+  pub community_id: i32,
+  pub creator_id: i32,
+  pub title_template: String,
+  pub body: Option<String>,
+  pub schedule: String,
+  pub stickied: bool,
+  pub enabled: bool,
+  pub next_run: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+impl Crud<ScheduledPostForm> for ScheduledPost {
+  fn read(conn: &PgConnection, scheduled_post_id: i32) -> Result<Self, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    scheduled_post.find(scheduled_post_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, scheduled_post_id: i32) -> Result<usize, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    diesel::delete(scheduled_post.find(scheduled_post_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ScheduledPostForm) -> Result<Self, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    insert_into(scheduled_post)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    scheduled_post_id: i32,
+    form: &ScheduledPostForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    diesel::update(scheduled_post.find(scheduled_post_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ScheduledPost {
+  pub fn list_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    scheduled_post
+      .filter(community_id.eq(for_community_id))
+      .order_by(next_run.asc())
+      .load::<Self>(conn)
+  }
+
+  /// The enabled ones whose time has come.
+  pub fn list_due(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    scheduled_post
+      .filter(enabled.eq(true))
+      .filter(next_run.le(naive_now()))
+      .order_by(next_run.asc())
+      .load::<Self>(conn)
+  }
+
+  /// Moves it to the next time. Disables it if there is no next time.
+  pub fn update_next_run(
+    conn: &PgConnection,
+    scheduled_post_id: i32,
+    new_next_run: Option<chrono::NaiveDateTime>,
+  ) -> Result<Self, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    let target = scheduled_post.find(scheduled_post_id);
+    match new_next_run {
+      Some(new_next_run) => diesel::update(target)
+        .set(next_run.eq(new_next_run))
+        .get_result::<Self>(conn),
+      None => diesel::update(target)
+        .set(enabled.eq(false))
+        .get_result::<Self>(conn),
+    }
+  }
+
+  pub fn update_last_post(
+    conn: &PgConnection,
+    scheduled_post_id: i32,
+    post_id: i32,
+  ) -> Result<Self, Error> {
+    use crate::schema::scheduled_post::dsl::*;
+    diesel::update(scheduled_post.find(scheduled_post_id))
+      .set(last_post_id.eq(post_id))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    naive_now,
+    post::*,
+    scheduled_post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "scheduled_poster".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_scheduled_post".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "Weekly discussion".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let scheduled_post_form = ScheduledPostForm {
+      community_id: inserted_community.id,
+      creator_id: inserted_user.id,
+      title_template: "Weekly discussion {date}".into(),
+      body: None,
+      schedule: "0 12 * * 1".into(),
+      stickied: true,
+      enabled: true,
+      next_run: naive_now() - chrono::Duration::minutes(1),
+      updated: None,
+    };
+
+    let inserted_scheduled_post = ScheduledPost::create(&conn, &scheduled_post_form).unwrap();
+
+    let expected_scheduled_post = ScheduledPost {
+      id: inserted_scheduled_post.id,
+      community_id: inserted_community.id,
+      creator_id: inserted_user.id,
+      title_template: "Weekly discussion {date}".into(),
+      body: None,
+      schedule: "0 12 * * 1".into(),
+      stickied: true,
+      enabled: true,
+      next_run: inserted_scheduled_post.next_run,
+      last_post_id: None,
+      published: inserted_scheduled_post.published,
+      updated: None,
+    };
+
+    let due = ScheduledPost::list_due(&conn).unwrap();
+    let moved = ScheduledPost::update_next_run(
+      &conn,
+      inserted_scheduled_post.id,
+      Some(naive_now() + chrono::Duration::days(7)),
+    )
+    .unwrap();
+    let due_after_move = ScheduledPost::list_due(&conn).unwrap();
+    let with_post =
+      ScheduledPost::update_last_post(&conn, inserted_scheduled_post.id, inserted_post.id).unwrap();
+    let disabled = ScheduledPost::update_next_run(&conn, inserted_scheduled_post.id, None).unwrap();
+    let for_community = ScheduledPost::list_for_community(&conn, inserted_community.id).unwrap();
+
+    // Deleting the post keeps the scheduled post
+    Post::delete(&conn, inserted_post.id).unwrap();
+    let after_post_delete = ScheduledPost::read(&conn, inserted_scheduled_post.id).unwrap();
+    let num_deleted = ScheduledPost::delete(&conn, inserted_scheduled_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(expected_scheduled_post, inserted_scheduled_post);
+    assert!(due.contains(&inserted_scheduled_post));
+    assert!(!due_after_move.iter().any(|s| s.id == moved.id));
+    assert_eq!(Some(inserted_post.id), with_post.last_post_id);
+    assert!(!disabled.enabled);
+    assert_eq!(vec![disabled], for_community);
+    assert_eq!(None, after_post_delete.last_post_id);
+    assert_eq!(1, num_deleted);
+  }
+}
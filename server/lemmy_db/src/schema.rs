@@ -6,6 +6,33 @@ table! {
         local -> Bool,
         published -> Timestamp,
         updated -> Nullable<Timestamp>,
+        ap_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    activity_send_queue (id) {
+        id -> Int4,
+        activity -> Text,
+        inbox -> Text,
+        actor_id -> Text,
+        published -> Timestamp,
+        attempts -> Int4,
+        next_attempt -> Timestamp,
+        last_error -> Nullable<Text>,
+    }
+}
+
+table! {
+    admin_impersonation (id) {
+        id -> Int4,
+        admin_id -> Int4,
+        user_id -> Int4,
+        reason -> Text,
+        published -> Timestamp,
+        expires -> Timestamp,
+        requests -> Int4,
+        last_request -> Nullable<Timestamp>,
     }
 }
 
@@ -30,6 +57,8 @@ table! {
         deleted -> Bool,
         ap_id -> Varchar,
         local -> Bool,
+        distinguished -> Bool,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
@@ -47,6 +76,7 @@ table! {
         deleted -> Nullable<Bool>,
         ap_id -> Nullable<Varchar>,
         local -> Nullable<Bool>,
+        distinguished -> Nullable<Bool>,
         post_name -> Nullable<Varchar>,
         community_id -> Nullable<Int4>,
         community_actor_id -> Nullable<Varchar>,
@@ -77,6 +107,7 @@ table! {
         post_id -> Int4,
         score -> Int2,
         published -> Timestamp,
+        instance -> Nullable<Varchar>,
     }
 }
 
@@ -89,6 +120,17 @@ table! {
     }
 }
 
+table! {
+    comment_view_count (id) {
+        id -> Int4,
+        comment_id -> Int4,
+        views -> Int8,
+        visitors -> Bytea,
+        unique_views -> Int8,
+        updated -> Timestamp,
+    }
+}
+
 table! {
     community (id) {
         id -> Int4,
@@ -143,6 +185,32 @@ table! {
     }
 }
 
+table! {
+    community_appearance (id) {
+        id -> Int4,
+        community_id -> Int4,
+        accent_color -> Nullable<Varchar>,
+        default_sort -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    community_digest (id) {
+        id -> Int4,
+        community_id -> Int4,
+        sent -> Timestamp,
+    }
+}
+
+table! {
+    community_digest_subscription (id) {
+        id -> Int4,
+        community_id -> Int4,
+        user_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
 table! {
     community_follower (id) {
         id -> Int4,
@@ -152,6 +220,17 @@ table! {
     }
 }
 
+table! {
+    community_join_request (id) {
+        id -> Int4,
+        community_id -> Int4,
+        user_id -> Int4,
+        application -> Nullable<Text>,
+        published -> Timestamp,
+        follow -> Nullable<Jsonb>,
+    }
+}
+
 table! {
     community_moderator (id) {
         id -> Int4,
@@ -161,6 +240,83 @@ table! {
     }
 }
 
+table! {
+    community_notification (id) {
+        id -> Int4,
+        community_id -> Int4,
+        user_id -> Int4,
+        mod_posts_only -> Bool,
+        send_email -> Bool,
+        published -> Timestamp,
+        send_push -> Bool,
+        email_digest -> Bool,
+    }
+}
+
+table! {
+    community_notification_post (id) {
+        id -> Int4,
+        user_id -> Int4,
+        post_id -> Int4,
+        read -> Bool,
+        published -> Timestamp,
+        email_pending -> Bool,
+    }
+}
+
+table! {
+    community_post_tag (id) {
+        id -> Int4,
+        community_id -> Int4,
+        name -> Varchar,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    community_settings (id) {
+        id -> Int4,
+        community_id -> Int4,
+        require_alt_text -> Bool,
+        trust_min_account_age_days -> Nullable<Int4>,
+        trust_min_karma -> Nullable<Int4>,
+        allow_anonymous_posts -> Bool,
+        join_requires_approval -> Bool,
+        join_question -> Nullable<Text>,
+        private -> Bool,
+        local_only -> Bool,
+        allow_authors_close_comments -> Bool,
+        automod_comment -> Nullable<Text>,
+        automod_user_id -> Nullable<Int4>,
+        welcome_message -> Nullable<Text>,
+        max_post_body_length -> Nullable<Int4>,
+        max_comment_length -> Nullable<Int4>,
+        allowed_post_type -> Varchar,
+        require_post_tag -> Bool,
+        title_pattern -> Nullable<Text>,
+        clean_titles -> Bool,
+        title_capitalization -> Varchar,
+        allow_post_url_edits -> Bool,
+        microblog_mentions -> Varchar,
+        digest_template -> Nullable<Text>,
+        discoverable -> Bool,
+    }
+}
+
+table! {
+    community_stats_daily (id) {
+        id -> Int4,
+        community_id -> Int4,
+        day -> Date,
+        subscribers_gained -> Int4,
+        subscribers_lost -> Int4,
+        posts -> Int4,
+        comments -> Int4,
+        removals -> Int4,
+        reports -> Int4,
+    }
+}
+
 table! {
     community_user_ban (id) {
         id -> Int4,
@@ -170,6 +326,160 @@ table! {
     }
 }
 
+table! {
+    community_welcome (id) {
+        id -> Int4,
+        community_id -> Int4,
+        user_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    content_report (id) {
+        id -> Int4,
+        creator_id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        reason -> Text,
+        resolved -> Bool,
+        resolver_id -> Nullable<Int4>,
+        ap_id -> Nullable<Text>,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    csp_report (id) {
+        id -> Int4,
+        document_uri -> Text,
+        violated_directive -> Text,
+        blocked_uri -> Nullable<Text>,
+        source_file -> Nullable<Text>,
+        line_number -> Nullable<Int4>,
+        disposition -> Nullable<Varchar>,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    default_community (id) {
+        id -> Int4,
+        community_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    domain (id) {
+        id -> Int4,
+        name -> Text,
+        post_count -> Int4,
+        last_post -> Nullable<Timestamp>,
+        policy -> Varchar,
+        policy_reason -> Nullable<Text>,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    domain_post_approval (id) {
+        id -> Int4,
+        post_id -> Int4,
+        domain_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    email_outbox (id) {
+        id -> Int4,
+        to_email -> Text,
+        to_name -> Text,
+        subject -> Text,
+        html -> Text,
+        reply_to -> Nullable<Text>,
+        published -> Timestamp,
+        attempts -> Int4,
+        next_attempt -> Timestamp,
+        last_error -> Nullable<Text>,
+        failed -> Bool,
+    }
+}
+
+table! {
+    feature_flag (id) {
+        id -> Int4,
+        name -> Varchar,
+        description -> Nullable<Text>,
+        enabled -> Bool,
+        rollout_percentage -> Int2,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    feature_flag_user (id) {
+        id -> Int4,
+        feature_flag_id -> Int4,
+        user_id -> Int4,
+        enabled -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    instance (id) {
+        id -> Int4,
+        domain -> Varchar,
+        software -> Nullable<Varchar>,
+        version -> Nullable<Varchar>,
+        published -> Timestamp,
+        crawled -> Nullable<Timestamp>,
+        supports_groups -> Bool,
+        supports_votes -> Bool,
+    }
+}
+
+table! {
+    instance_actor (id) {
+        id -> Int4,
+        private_key -> Text,
+        public_key -> Text,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    instance_peer_stats_daily (id) {
+        id -> Int4,
+        day -> Date,
+        domain -> Text,
+        activities_in -> Int4,
+        activities_out -> Int4,
+        requests_retried -> Int4,
+        circuit_breaker_trips -> Int4,
+    }
+}
+
+table! {
+    instance_stats_daily (id) {
+        id -> Int4,
+        day -> Date,
+        registrations -> Int4,
+        active_users -> Int4,
+        activities_in -> Int4,
+        activities_out -> Int4,
+        queue_pending -> Int4,
+        queue_running -> Int4,
+        queue_failed -> Int4,
+        database_bytes -> Int8,
+        updated -> Timestamp,
+    }
+}
+
 table! {
     mod_add (id) {
         id -> Int4,
@@ -216,6 +526,25 @@ table! {
     }
 }
 
+table! {
+    mod_bulk_action (id) {
+        id -> Int4,
+        mod_user_id -> Int4,
+        community_id -> Int4,
+        other_user_ids -> Array<Int4>,
+        banned -> Bool,
+        since -> Nullable<Timestamp>,
+        until -> Nullable<Timestamp>,
+        reason -> Nullable<Text>,
+        total -> Int4,
+        done -> Int4,
+        finished -> Bool,
+        when_ -> Timestamp,
+        failed_post_ids -> Array<Int4>,
+        failed_comment_ids -> Array<Int4>,
+    }
+}
+
 table! {
     mod_lock_post (id) {
         id -> Int4,
@@ -279,6 +608,21 @@ table! {
     }
 }
 
+table! {
+    plugin_hook (id) {
+        id -> Int4,
+        name -> Varchar,
+        url -> Text,
+        hook_point -> Text,
+        timeout_ms -> Int4,
+        failure_policy -> Text,
+        secret -> Nullable<Text>,
+        enabled -> Bool,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     post (id) {
         id -> Int4,
@@ -300,6 +644,12 @@ table! {
         thumbnail_url -> Nullable<Text>,
         ap_id -> Varchar,
         local -> Bool,
+        thumbnail_alt_text -> Nullable<Text>,
+        distinguished -> Bool,
+        pinned_comment_id -> Nullable<Int4>,
+        anonymous -> Bool,
+        comments_closed -> Bool,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
@@ -324,6 +674,11 @@ table! {
         thumbnail_url -> Nullable<Text>,
         ap_id -> Nullable<Varchar>,
         local -> Nullable<Bool>,
+        thumbnail_alt_text -> Nullable<Text>,
+        distinguished -> Nullable<Bool>,
+        pinned_comment_id -> Nullable<Int4>,
+        anonymous -> Bool,
+        comments_closed -> Bool,
         creator_actor_id -> Nullable<Varchar>,
         creator_local -> Nullable<Bool>,
         creator_name -> Nullable<Varchar>,
@@ -349,6 +704,28 @@ table! {
     }
 }
 
+table! {
+    post_comment_sort (id) {
+        id -> Int4,
+        user_id -> Int4,
+        post_id -> Int4,
+        comment_sort -> Varchar,
+        updated -> Timestamp,
+    }
+}
+
+table! {
+    post_edit (id) {
+        id -> Int4,
+        post_id -> Int4,
+        editor_id -> Int4,
+        name -> Varchar,
+        url -> Nullable<Text>,
+        body -> Nullable<Text>,
+        published -> Timestamp,
+    }
+}
+
 table! {
     post_like (id) {
         id -> Int4,
@@ -356,6 +733,19 @@ table! {
         user_id -> Int4,
         score -> Int2,
         published -> Timestamp,
+        instance -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    post_media (id) {
+        id -> Int4,
+        post_id -> Int4,
+        position -> Int4,
+        url -> Text,
+        caption -> Nullable<Text>,
+        alt_text -> Nullable<Text>,
+        published -> Timestamp,
     }
 }
 
@@ -377,6 +767,26 @@ table! {
     }
 }
 
+table! {
+    post_tag (id) {
+        id -> Int4,
+        post_id -> Int4,
+        tag_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    post_view_count (id) {
+        id -> Int4,
+        post_id -> Int4,
+        views -> Int8,
+        visitors -> Bytea,
+        unique_views -> Int8,
+        updated -> Timestamp,
+    }
+}
+
 table! {
     private_message (id) {
         id -> Int4,
@@ -392,6 +802,90 @@ table! {
     }
 }
 
+table! {
+    push_subscription (id) {
+        id -> Int4,
+        user_id -> Int4,
+        endpoint -> Text,
+        p256dh -> Text,
+        auth -> Text,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    received_activity (id) {
+        id -> Int4,
+        inbox -> Text,
+        ap_id -> Text,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    removal_reason (id) {
+        id -> Int4,
+        community_id -> Int4,
+        title -> Varchar,
+        reason -> Text,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    saved_search (id) {
+        id -> Int4,
+        user_id -> Int4,
+        query -> Text,
+        keywords -> Array<Text>,
+        community_id -> Nullable<Int4>,
+        creator_id -> Nullable<Int4>,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    saved_search_match (id) {
+        id -> Int4,
+        saved_search_id -> Int4,
+        post_id -> Int4,
+        read -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    scheduled_post (id) {
+        id -> Int4,
+        community_id -> Int4,
+        creator_id -> Int4,
+        title_template -> Varchar,
+        body -> Nullable<Text>,
+        schedule -> Text,
+        stickied -> Bool,
+        enabled -> Bool,
+        next_run -> Timestamp,
+        last_post_id -> Nullable<Int4>,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    sign_in_signal (id) {
+        id -> Int4,
+        user_id -> Int4,
+        ip_range -> Nullable<Varchar>,
+        fingerprint -> Nullable<Varchar>,
+        registration -> Bool,
+        published -> Timestamp,
+        country -> Nullable<Varchar>,
+        anomalous -> Bool,
+        reviewed -> Bool,
+        revoked -> Bool,
+    }
+}
+
 table! {
     site (id) {
         id -> Int4,
@@ -405,6 +899,74 @@ table! {
         enable_nsfw -> Bool,
         icon -> Nullable<Text>,
         banner -> Nullable<Text>,
+        require_alt_text -> Bool,
+        score_hidden_minutes -> Nullable<Int4>,
+        vote_lock_days -> Nullable<Int4>,
+        ninja_edit_minutes -> Nullable<Int4>,
+        edit_limit_days -> Nullable<Int4>,
+        undelete_window_days -> Nullable<Int4>,
+    }
+}
+
+table! {
+    site_announcement (id) {
+        id -> Int4,
+        creator_id -> Int4,
+        content -> Text,
+        severity -> Text,
+        starts -> Timestamp,
+        ends -> Nullable<Timestamp>,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+        pushed -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    site_announcement_dismissal (id) {
+        id -> Int4,
+        announcement_id -> Int4,
+        user_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    site_branding (id) {
+        id -> Int4,
+        favicon -> Nullable<Text>,
+        primary_color -> Nullable<Text>,
+        secondary_color -> Nullable<Text>,
+        background_color -> Nullable<Text>,
+        text_color -> Nullable<Text>,
+        default_theme -> Nullable<Text>,
+        updated -> Timestamp,
+    }
+}
+
+table! {
+    spam_score (id) {
+        id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        private_message_id -> Nullable<Int4>,
+        creator_id -> Int4,
+        score -> Float4,
+        reasons -> Array<Text>,
+        content_hash -> Text,
+        reported -> Bool,
+        removed -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    thread_mute (id) {
+        id -> Int4,
+        user_id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        published -> Timestamp,
     }
 }
 
@@ -435,6 +997,14 @@ table! {
         public_key -> Nullable<Text>,
         last_refreshed_at -> Timestamp,
         banner -> Nullable<Text>,
+        discoverable -> Bool,
+        receive_welcome_messages -> Bool,
+        deleted -> Bool,
+        bot_account -> Bool,
+        collapse_comments_below_score -> Nullable<Int4>,
+        collapse_bot_comments -> Bool,
+        remember_comment_sort -> Bool,
+        sessionless_tokens_revoked -> Bool,
     }
 }
 
@@ -470,6 +1040,15 @@ table! {
     }
 }
 
+table! {
+    user_ignored_vote_instance (id) {
+        id -> Int4,
+        user_id -> Int4,
+        instance -> Varchar,
+        published -> Timestamp,
+    }
+}
+
 table! {
     user_mention (id) {
         id -> Int4,
@@ -480,6 +1059,36 @@ table! {
     }
 }
 
+table! {
+    user_presence (id) {
+        id -> Int4,
+        user_id -> Int4,
+        last_active -> Timestamp,
+        show_presence -> Bool,
+    }
+}
+
+table! {
+    vote_burst (id) {
+        id -> Int4,
+        post_id -> Int4,
+        instance -> Varchar,
+        votes -> Int4,
+        resolved -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    wasm_plugin (id) {
+        id -> Int4,
+        name -> Varchar,
+        enabled -> Bool,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
 joinable!(activity -> user_ (user_id));
 joinable!(comment -> post (post_id));
 joinable!(comment -> user_ (creator_id));
@@ -488,16 +1097,42 @@ joinable!(comment_like -> post (post_id));
 joinable!(comment_like -> user_ (user_id));
 joinable!(comment_saved -> comment (comment_id));
 joinable!(comment_saved -> user_ (user_id));
+joinable!(comment_view_count -> comment (comment_id));
 joinable!(community -> category (category_id));
 joinable!(community -> user_ (creator_id));
+joinable!(community_appearance -> community (community_id));
+joinable!(community_digest -> community (community_id));
+joinable!(community_digest_subscription -> community (community_id));
+joinable!(community_digest_subscription -> user_ (user_id));
 joinable!(community_follower -> community (community_id));
 joinable!(community_follower -> user_ (user_id));
+joinable!(community_join_request -> community (community_id));
+joinable!(community_join_request -> user_ (user_id));
 joinable!(community_moderator -> community (community_id));
 joinable!(community_moderator -> user_ (user_id));
+joinable!(community_notification -> community (community_id));
+joinable!(community_notification -> user_ (user_id));
+joinable!(community_notification_post -> post (post_id));
+joinable!(community_notification_post -> user_ (user_id));
+joinable!(community_post_tag -> community (community_id));
+joinable!(community_settings -> community (community_id));
+joinable!(community_settings -> user_ (automod_user_id));
+joinable!(community_stats_daily -> community (community_id));
 joinable!(community_user_ban -> community (community_id));
 joinable!(community_user_ban -> user_ (user_id));
+joinable!(community_welcome -> community (community_id));
+joinable!(community_welcome -> user_ (user_id));
+joinable!(content_report -> comment (comment_id));
+joinable!(content_report -> post (post_id));
+joinable!(default_community -> community (community_id));
+joinable!(domain_post_approval -> domain (domain_id));
+joinable!(domain_post_approval -> post (post_id));
+joinable!(feature_flag_user -> feature_flag (feature_flag_id));
+joinable!(feature_flag_user -> user_ (user_id));
 joinable!(mod_add_community -> community (community_id));
 joinable!(mod_ban_from_community -> community (community_id));
+joinable!(mod_bulk_action -> community (community_id));
+joinable!(mod_bulk_action -> user_ (mod_user_id));
 joinable!(mod_lock_post -> post (post_id));
 joinable!(mod_lock_post -> user_ (mod_user_id));
 joinable!(mod_remove_comment -> comment (comment_id));
@@ -511,48 +1146,126 @@ joinable!(mod_sticky_post -> user_ (mod_user_id));
 joinable!(password_reset_request -> user_ (user_id));
 joinable!(post -> community (community_id));
 joinable!(post -> user_ (creator_id));
+joinable!(post_comment_sort -> post (post_id));
+joinable!(post_comment_sort -> user_ (user_id));
+joinable!(post_edit -> post (post_id));
+joinable!(post_edit -> user_ (editor_id));
 joinable!(post_like -> post (post_id));
 joinable!(post_like -> user_ (user_id));
+joinable!(post_media -> post (post_id));
 joinable!(post_read -> post (post_id));
 joinable!(post_read -> user_ (user_id));
 joinable!(post_saved -> post (post_id));
 joinable!(post_saved -> user_ (user_id));
+joinable!(post_tag -> community_post_tag (tag_id));
+joinable!(post_tag -> post (post_id));
+joinable!(post_view_count -> post (post_id));
+joinable!(push_subscription -> user_ (user_id));
+joinable!(removal_reason -> community (community_id));
+joinable!(saved_search -> community (community_id));
+joinable!(saved_search_match -> post (post_id));
+joinable!(saved_search_match -> saved_search (saved_search_id));
+joinable!(scheduled_post -> community (community_id));
+joinable!(scheduled_post -> post (last_post_id));
+joinable!(scheduled_post -> user_ (creator_id));
+joinable!(sign_in_signal -> user_ (user_id));
 joinable!(site -> user_ (creator_id));
+joinable!(site_announcement -> user_ (creator_id));
+joinable!(site_announcement_dismissal -> site_announcement (announcement_id));
+joinable!(site_announcement_dismissal -> user_ (user_id));
+joinable!(spam_score -> comment (comment_id));
+joinable!(spam_score -> post (post_id));
+joinable!(spam_score -> private_message (private_message_id));
+joinable!(spam_score -> user_ (creator_id));
+joinable!(thread_mute -> comment (comment_id));
+joinable!(thread_mute -> post (post_id));
+joinable!(thread_mute -> user_ (user_id));
 joinable!(user_ban -> user_ (user_id));
+joinable!(user_ignored_vote_instance -> user_ (user_id));
 joinable!(user_mention -> comment (comment_id));
 joinable!(user_mention -> user_ (recipient_id));
+joinable!(user_presence -> user_ (user_id));
+joinable!(vote_burst -> post (post_id));
 
 allow_tables_to_appear_in_same_query!(
     activity,
+    activity_send_queue,
+    admin_impersonation,
     category,
     comment,
     comment_aggregates_fast,
     comment_like,
     comment_saved,
+    comment_view_count,
     community,
     community_aggregates_fast,
+    community_appearance,
+    community_digest,
+    community_digest_subscription,
     community_follower,
+    community_join_request,
     community_moderator,
+    community_notification,
+    community_notification_post,
+    community_post_tag,
+    community_settings,
+    community_stats_daily,
     community_user_ban,
+    community_welcome,
+    content_report,
+    csp_report,
+    default_community,
+    domain,
+    domain_post_approval,
+    email_outbox,
+    feature_flag,
+    feature_flag_user,
+    instance,
+    instance_actor,
+    instance_peer_stats_daily,
+    instance_stats_daily,
     mod_add,
     mod_add_community,
     mod_ban,
     mod_ban_from_community,
+    mod_bulk_action,
     mod_lock_post,
     mod_remove_comment,
     mod_remove_community,
     mod_remove_post,
     mod_sticky_post,
     password_reset_request,
+    plugin_hook,
     post,
     post_aggregates_fast,
+    post_comment_sort,
+    post_edit,
     post_like,
+    post_media,
     post_read,
     post_saved,
+    post_tag,
+    post_view_count,
     private_message,
+    push_subscription,
+    received_activity,
+    removal_reason,
+    saved_search,
+    saved_search_match,
+    scheduled_post,
+    sign_in_signal,
     site,
+    site_announcement,
+    site_announcement_dismissal,
+    site_branding,
+    spam_score,
+    thread_mute,
     user_,
     user_ban,
     user_fast,
+    user_ignored_vote_instance,
     user_mention,
+    user_presence,
+    vote_burst,
+    wasm_plugin,
 );
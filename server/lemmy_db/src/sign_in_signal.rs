@@ -0,0 +1,473 @@
+use crate::schema::{sign_in_signal, user_};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// How much each kind of shared signal adds to the confidence that two accounts are run by the
+/// same person. Address ranges are shared by neighbours and mobile networks, and fingerprints by
+/// everyone with the same browser, so none of them is proof on its own.
+pub const SHARED_IP_RANGE_WEIGHT: f64 = 0.5;
+pub const SHARED_FINGERPRINT_WEIGHT: f64 = 0.4;
+pub const SHARED_EMAIL_DOMAIN_WEIGHT: f64 = 0.3;
+
+/// Email domains of more local users than this, like those of the big providers, aren't a signal.
+pub const COMMON_EMAIL_DOMAIN_USERS: i64 = 25;
+
+const MAX_DUPLICATE_ACCOUNTS: usize = 50;
+
+/// The address range and client fingerprint of a local user when they signed up or logged in.
+/// The range is only kept as far as the ip address policy of the config allows. The tokens handed
+/// out since sign ins are recorded carry its id, so that the user can revoke them.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "sign_in_signal"]
+pub struct SignInSignal {
+  pub id: i32,
+  pub user_id: i32,
+  pub ip_range: Option<String>,
+  pub fingerprint: Option<String>,
+  pub registration: bool,
+  pub published: chrono::NaiveDateTime,
+  /// The ISO code of the country of the address, if there is a GeoIP database
+  pub country: Option<String>,
+  /// From a new device or country, see `is_anomalous`
+  pub anomalous: bool,
+  pub reviewed: bool,
+  pub revoked: bool,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "sign_in_signal"]
+pub struct SignInSignalForm {
+  pub user_id: i32,
+  pub ip_range: Option<String>,
+  pub fingerprint: Option<String>,
+  pub registration: bool,
+  pub country: Option<String>,
+  pub anomalous: bool,
+}
+
+impl SignInSignal {
+  pub fn create(conn: &PgConnection, form: &SignInSignalForm) -> Result<Self, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    insert_into(sign_in_signal)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    sign_in_signal
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// The anomalous sign ins which the user didn't look at yet, newest first.
+  pub fn list_unreviewed(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    sign_in_signal
+      .filter(user_id.eq(for_user_id))
+      .filter(anomalous.eq(true))
+      .filter(reviewed.eq(false))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// Marks the sign in of the user as reviewed, and revokes its token if asked to. Revoked sign ins
+  /// stay revoked.
+  pub fn review(
+    conn: &PgConnection,
+    sign_in_id: i32,
+    for_user_id: i32,
+    revoke: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    let target = sign_in_signal
+      .filter(id.eq(sign_in_id))
+      .filter(user_id.eq(for_user_id));
+    if revoke {
+      diesel::update(target)
+        .set((reviewed.eq(true), revoked.eq(true)))
+        .get_result::<Self>(conn)
+    } else {
+      diesel::update(target)
+        .set(reviewed.eq(true))
+        .get_result::<Self>(conn)
+    }
+  }
+
+  /// Revokes the tokens of all sign ins of the user, eg when the password was reset.
+  pub fn revoke_all(conn: &PgConnection, for_user_id: i32) -> Result<usize, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    diesel::update(sign_in_signal.filter(user_id.eq(for_user_id)))
+      .set(revoked.eq(true))
+      .execute(conn)
+  }
+
+  /// Sign ins which were forgotten weren't revoked.
+  pub fn is_revoked(conn: &PgConnection, sign_in_id: i32) -> Result<bool, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    let is_revoked = sign_in_signal
+      .find(sign_in_id)
+      .select(revoked)
+      .first::<bool>(conn)
+      .optional()?;
+    Ok(is_revoked.unwrap_or(false))
+  }
+
+  /// Whether a sign in of the user with the fingerprint and from the country is from a new device
+  /// or a new country, compared to the sign ins which are still kept.
+  pub fn is_anomalous(
+    conn: &PgConnection,
+    for_user_id: i32,
+    for_fingerprint: Option<&str>,
+    for_country: Option<&str>,
+  ) -> Result<bool, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    let (fingerprints, countries): (Vec<Option<String>>, Vec<Option<String>>) = sign_in_signal
+      .filter(user_id.eq(for_user_id))
+      .select((fingerprint, country))
+      .load::<(Option<String>, Option<String>)>(conn)?
+      .into_iter()
+      .unzip();
+    Ok(is_new_signal(&fingerprints, for_fingerprint) || is_new_signal(&countries, for_country))
+  }
+
+  /// Revoked sign ins are only stripped of their signals, so that their tokens stay revoked.
+  pub fn delete_older_than(
+    conn: &PgConnection,
+    before: chrono::NaiveDateTime,
+  ) -> Result<usize, Error> {
+    use crate::schema::sign_in_signal::dsl::*;
+    diesel::update(
+      sign_in_signal
+        .filter(published.lt(before))
+        .filter(revoked.eq(true))
+        .filter(ip_range.is_not_null().or(fingerprint.is_not_null())),
+    )
+    .set((
+      ip_range.eq(None::<String>),
+      fingerprint.eq(None::<String>),
+      country.eq(None::<String>),
+    ))
+    .execute(conn)?;
+    diesel::delete(
+      sign_in_signal
+        .filter(published.lt(before))
+        .filter(revoked.eq(false)),
+    )
+    .execute(conn)
+  }
+}
+
+/// A signal is new if no earlier sign in had it. Signals which earlier sign ins didn't have at all,
+/// like the country before a GeoIP database was set up, don't count as new.
+fn is_new_signal(known: &[Option<String>], signal: Option<&str>) -> bool {
+  let mut known = known.iter().flatten().peekable();
+  match signal {
+    Some(signal) => known.peek().is_some() && known.all(|k| k != signal),
+    None => false,
+  }
+}
+
+/// Another account which shares signals with the one an admin is looking into.
+#[derive(PartialEq, Debug, Serialize, Clone)]
+pub struct DuplicateAccount {
+  pub user_id: i32,
+  pub name: String,
+  pub banned: bool,
+  pub published: chrono::NaiveDateTime,
+  pub shared_ip_ranges: i64,
+  pub shared_fingerprints: i64,
+  pub same_email_domain: bool,
+  /// From 0 to 1, see SHARED_IP_RANGE_WEIGHT
+  pub confidence: f64,
+}
+
+impl DuplicateAccount {
+  /// The accounts which signed in from the same address ranges or with the same fingerprints as
+  /// the user since the given time, or signed up with an email of the same uncommon domain, most
+  /// likely first.
+  pub fn for_user(
+    conn: &PgConnection,
+    for_user_id: i32,
+    since: chrono::NaiveDateTime,
+  ) -> Result<Vec<Self>, Error> {
+    let signals = sign_in_signal::table
+      .filter(sign_in_signal::user_id.eq(for_user_id))
+      .filter(sign_in_signal::published.gt(since))
+      .select((sign_in_signal::ip_range, sign_in_signal::fingerprint))
+      .load::<(Option<String>, Option<String>)>(conn)?;
+    let ip_ranges: Vec<String> = signals.iter().filter_map(|(r, _)| r.to_owned()).collect();
+    let fingerprints: Vec<String> = signals.iter().filter_map(|(_, f)| f.to_owned()).collect();
+
+    let shared = sign_in_signal::table
+      .filter(sign_in_signal::user_id.ne(for_user_id))
+      .filter(sign_in_signal::published.gt(since))
+      .filter(
+        sign_in_signal::ip_range
+          .eq_any(ip_ranges.to_owned())
+          .or(sign_in_signal::fingerprint.eq_any(fingerprints.to_owned())),
+      )
+      .select((
+        sign_in_signal::user_id,
+        sign_in_signal::ip_range,
+        sign_in_signal::fingerprint,
+      ))
+      .load::<(i32, Option<String>, Option<String>)>(conn)?;
+
+    let mut shared_ip_ranges: HashMap<i32, HashSet<String>> = HashMap::new();
+    let mut shared_fingerprints: HashMap<i32, HashSet<String>> = HashMap::new();
+    for (other_id, ip_range, fingerprint) in shared {
+      if let Some(ip_range) = ip_range.filter(|r| ip_ranges.contains(r)) {
+        shared_ip_ranges
+          .entry(other_id)
+          .or_default()
+          .insert(ip_range);
+      }
+      if let Some(fingerprint) = fingerprint.filter(|f| fingerprints.contains(f)) {
+        shared_fingerprints
+          .entry(other_id)
+          .or_default()
+          .insert(fingerprint);
+      }
+    }
+
+    let same_email_domain = match uncommon_email_domain(conn, for_user_id)? {
+      Some(domain) => user_::table
+        .filter(user_::id.ne(for_user_id))
+        .filter(user_::local.eq(true))
+        .filter(user_::published.gt(since))
+        .filter(user_::email.ilike(format!("%@{}", domain)))
+        .select(user_::id)
+        .load::<i32>(conn)?,
+      None => vec![],
+    };
+
+    let mut other_ids: HashSet<i32> = shared_ip_ranges.keys().copied().collect();
+    other_ids.extend(shared_fingerprints.keys());
+    other_ids.extend(same_email_domain.iter());
+    let others = user_::table
+      .filter(user_::id.eq_any(other_ids.into_iter().collect::<Vec<i32>>()))
+      .select((user_::id, user_::name, user_::banned, user_::published))
+      .load::<(i32, String, bool, chrono::NaiveDateTime)>(conn)?;
+
+    let mut accounts: Vec<Self> = others
+      .into_iter()
+      .map(|(user_id, name, banned, published)| {
+        let shared_ip_ranges = shared_ip_ranges.get(&user_id).map_or(0, |r| r.len()) as i64;
+        let shared_fingerprints = shared_fingerprints.get(&user_id).map_or(0, |f| f.len()) as i64;
+        let same_email_domain = same_email_domain.contains(&user_id);
+        DuplicateAccount {
+          user_id,
+          name,
+          banned,
+          published,
+          shared_ip_ranges,
+          shared_fingerprints,
+          same_email_domain,
+          confidence: confidence(shared_ip_ranges, shared_fingerprints, same_email_domain),
+        }
+      })
+      .collect();
+    accounts.sort_by(|a, b| {
+      b.confidence
+        .partial_cmp(&a.confidence)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then(b.published.cmp(&a.published))
+    });
+    accounts.truncate(MAX_DUPLICATE_ACCOUNTS);
+    Ok(accounts)
+  }
+}
+
+/// Each shared signal leaves a smaller chance that the accounts are unrelated.
+fn confidence(shared_ip_ranges: i64, shared_fingerprints: i64, same_email_domain: bool) -> f64 {
+  let mut unrelated = (1.0 - SHARED_IP_RANGE_WEIGHT).powi(shared_ip_ranges as i32)
+    * (1.0 - SHARED_FINGERPRINT_WEIGHT).powi(shared_fingerprints as i32);
+  if same_email_domain {
+    unrelated *= 1.0 - SHARED_EMAIL_DOMAIN_WEIGHT;
+  }
+  1.0 - unrelated
+}
+
+/// The domain of the email of the user, unless it's one of COMMON_EMAIL_DOMAIN_USERS.
+fn uncommon_email_domain(conn: &PgConnection, for_user_id: i32) -> Result<Option<String>, Error> {
+  let email = user_::table
+    .find(for_user_id)
+    .select(user_::email)
+    .first::<Option<String>>(conn)?;
+  let domain = match email
+    .as_ref()
+    .and_then(|e| e.rfind('@').map(|i| &e[i + 1..]))
+  {
+    Some(domain) if !domain.is_empty() && !domain.contains('%') && !domain.contains('_') => {
+      domain.to_lowercase()
+    }
+    _ => return Ok(None),
+  };
+  let users: i64 = user_::table
+    .filter(user_::local.eq(true))
+    .filter(user_::email.ilike(format!("%@{}", domain)))
+    .select(count_star())
+    .first(conn)?;
+  if users > COMMON_EMAIL_DOMAIN_USERS {
+    Ok(None)
+  } else {
+    Ok(Some(domain))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    naive_now,
+    sign_in_signal::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  fn user_form(name: &str, email: &str) -> UserForm {
+    UserForm {
+      name: name.into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: Some(Some(email.into())),
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    }
+  }
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let banned_user = User_::create(
+      &conn,
+      &user_form("signal_banned", "banned@signal.example.com"),
+    )
+    .unwrap();
+    let evading_user = User_::create(
+      &conn,
+      &user_form("signal_evading", "evading@signal.example.com"),
+    )
+    .unwrap();
+    let other_user = User_::create(&conn, &user_form("signal_other", "other@example.org")).unwrap();
+
+    let signals = vec![
+      (banned_user.id, "10.1.2.0", "fp_banned", true),
+      (evading_user.id, "10.1.2.0", "fp_evading", true),
+      (evading_user.id, "10.9.9.0", "fp_banned", false),
+      (other_user.id, "10.9.9.0", "fp_other", true),
+    ];
+    for (user_id, ip_range, fingerprint, registration) in signals {
+      let form = SignInSignalForm {
+        user_id,
+        ip_range: Some(ip_range.into()),
+        fingerprint: Some(fingerprint.into()),
+        registration,
+        country: None,
+        anomalous: false,
+      };
+      SignInSignal::create(&conn, &form).unwrap();
+    }
+
+    let since = naive_now() - chrono::Duration::days(1);
+    let duplicates = DuplicateAccount::for_user(&conn, banned_user.id, since).unwrap();
+    let later = DuplicateAccount::for_user(&conn, banned_user.id, naive_now()).unwrap();
+    let evading_signals = SignInSignal::list_for_user(&conn, evading_user.id).unwrap();
+    let new_device =
+      SignInSignal::is_anomalous(&conn, evading_user.id, Some("fp_new"), None).unwrap();
+    let known_device =
+      SignInSignal::is_anomalous(&conn, evading_user.id, Some("fp_evading"), None).unwrap();
+
+    let anomalous_form = SignInSignalForm {
+      user_id: other_user.id,
+      ip_range: None,
+      fingerprint: Some("fp_new".into()),
+      registration: false,
+      country: Some("NZ".into()),
+      anomalous: true,
+    };
+    let anomalous = SignInSignal::create(&conn, &anomalous_form).unwrap();
+    let unreviewed = SignInSignal::list_unreviewed(&conn, other_user.id).unwrap();
+    let not_theirs = SignInSignal::review(&conn, anomalous.id, banned_user.id, true);
+    let revoked = SignInSignal::review(&conn, anomalous.id, other_user.id, true).unwrap();
+    let still_revoked = SignInSignal::review(&conn, anomalous.id, other_user.id, false).unwrap();
+    let reviewed = SignInSignal::list_unreviewed(&conn, other_user.id).unwrap();
+    let is_revoked = SignInSignal::is_revoked(&conn, anomalous.id).unwrap();
+    SignInSignal::delete_older_than(&conn, naive_now() + chrono::Duration::days(1)).unwrap();
+    let kept = SignInSignal::list_for_user(&conn, other_user.id).unwrap();
+    let forgotten_is_revoked = SignInSignal::is_revoked(&conn, evading_signals[0].id).unwrap();
+
+    User_::delete(&conn, banned_user.id).unwrap();
+    User_::delete(&conn, evading_user.id).unwrap();
+    User_::delete(&conn, other_user.id).unwrap();
+
+    assert_eq!(1, duplicates.len());
+    assert_eq!(evading_user.id, duplicates[0].user_id);
+    assert_eq!(1, duplicates[0].shared_ip_ranges);
+    assert_eq!(1, duplicates[0].shared_fingerprints);
+    assert!(duplicates[0].same_email_domain);
+    assert!(duplicates[0].confidence > 0.75 && duplicates[0].confidence < 1.0);
+    assert!(later.is_empty());
+    assert_eq!(2, evading_signals.len());
+    assert!(new_device);
+    assert!(!known_device);
+    assert_eq!(vec![anomalous.to_owned()], unreviewed);
+    assert!(not_theirs.is_err());
+    assert!(revoked.reviewed && revoked.revoked);
+    assert!(still_revoked.revoked);
+    assert!(reviewed.is_empty());
+    assert!(is_revoked);
+    assert_eq!(1, kept.len());
+    assert_eq!(None, kept[0].fingerprint);
+    assert_eq!(None, kept[0].country);
+    assert!(!forgotten_is_revoked);
+  }
+
+  #[test]
+  fn test_is_new_signal() {
+    let known = vec![Some("a".to_string()), None];
+    assert!(is_new_signal(&known, Some("b")));
+    assert!(!is_new_signal(&known, Some("a")));
+    assert!(!is_new_signal(&known, None));
+    assert!(!is_new_signal(&[None], Some("b")));
+    assert!(!is_new_signal(&[], Some("b")));
+  }
+
+  #[test]
+  fn test_confidence() {
+    assert_eq!(0.0, confidence(0, 0, false));
+    assert!((confidence(1, 0, false) - SHARED_IP_RANGE_WEIGHT).abs() < 1e-9);
+    assert!(confidence(2, 0, false) > confidence(1, 0, false));
+    assert!(confidence(1, 1, true) > confidence(1, 1, false));
+    assert!(confidence(10, 10, true) < 1.0);
+  }
+}
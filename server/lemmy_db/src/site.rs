@@ -15,6 +15,12 @@ pub struct Site {
   pub enable_nsfw: bool,
   pub icon: Option<String>,
   pub banner: Option<String>,
+  pub require_alt_text: bool,
+  pub score_hidden_minutes: Option<i32>,
+  pub vote_lock_days: Option<i32>,
+  pub ninja_edit_minutes: Option<i32>,
+  pub edit_limit_days: Option<i32>,
+  pub undelete_window_days: Option<i32>,
 }
 
 #[derive(Insertable, AsChangeset)]
@@ -30,6 +36,12 @@ pub struct SiteForm {
   // when you want to null out a column, you have to send Some(None)), since sending None means you just don't want to update that column.
   pub icon: Option<Option<String>>,
   pub banner: Option<Option<String>>,
+  pub require_alt_text: bool,
+  pub score_hidden_minutes: Option<Option<i32>>,
+  pub vote_lock_days: Option<Option<i32>>,
+  pub ninja_edit_minutes: Option<Option<i32>>,
+  pub edit_limit_days: Option<Option<i32>>,
+  pub undelete_window_days: Option<Option<i32>>,
 }
 
 impl Crud<SiteForm> for Site {
@@ -52,6 +64,22 @@ impl Crud<SiteForm> for Site {
 }
 
 impl Site {
+  /// The icon is the logo of the instance.
+  pub fn update_branding(
+    conn: &PgConnection,
+    new_icon: Option<String>,
+    new_banner: Option<String>,
+  ) -> Result<Self, Error> {
+    use crate::schema::site::dsl::*;
+    diesel::update(site.find(1))
+      .set((
+        icon.eq(new_icon),
+        banner.eq(new_banner),
+        updated.eq(naive_now()),
+      ))
+      .get_result::<Self>(conn)
+  }
+
   pub fn transfer(conn: &PgConnection, new_creator_id: i32) -> Result<Self, Error> {
     use crate::schema::site::dsl::*;
     diesel::update(site.find(1))
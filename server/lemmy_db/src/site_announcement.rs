@@ -0,0 +1,251 @@
+use crate::{
+  naive_now,
+  schema::{site_announcement, site_announcement_dismissal},
+  Crud,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::{Deserialize, Serialize};
+
+#[derive(EnumString, ToString, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AnnouncementSeverity {
+  Info,
+  Warning,
+  Critical,
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "site_announcement"]
+pub struct SiteAnnouncement {
+  pub id: i32,
+  pub creator_id: i32,
+  pub content: String,
+  pub severity: String,
+  pub starts: chrono::NaiveDateTime,
+  pub ends: Option<chrono::NaiveDateTime>,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+  /// When it was sent to the connected clients, None while it's scheduled
+  #[serde(skip_serializing)]
+  pub pushed: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "site_announcement"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct SiteAnnouncementForm {
+  pub creator_id: i32,
+  pub content: String,
+  pub severity: String,
+  pub starts: chrono::NaiveDateTime,
+  pub ends: Option<chrono::NaiveDateTime>,
+  pub updated: Option<chrono::NaiveDateTime>,
+  pub pushed: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "site_announcement_dismissal"]
+pub struct SiteAnnouncementDismissalForm {
+  pub announcement_id: i32,
+  pub user_id: i32,
+}
+
+impl Crud<SiteAnnouncementForm> for SiteAnnouncement {
+  fn read(conn: &PgConnection, announcement_id: i32) -> Result<Self, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    site_announcement.find(announcement_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, announcement_id: i32) -> Result<usize, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    diesel::delete(site_announcement.find(announcement_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &SiteAnnouncementForm) -> Result<Self, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    insert_into(site_announcement)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    announcement_id: i32,
+    form: &SiteAnnouncementForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    diesel::update(site_announcement.find(announcement_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl SiteAnnouncement {
+  /// The announcements which are shown right now, without the ones the user dismissed.
+  pub fn list_active(conn: &PgConnection, for_user_id: Option<i32>) -> Result<Vec<Self>, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    let current_time = naive_now();
+    let mut query = site_announcement
+      .filter(starts.le(current_time))
+      .filter(ends.is_null().or(ends.gt(current_time)))
+      .into_boxed();
+    if let Some(for_user_id) = for_user_id {
+      let dismissed = site_announcement_dismissal::table
+        .filter(site_announcement_dismissal::user_id.eq(for_user_id))
+        .select(site_announcement_dismissal::announcement_id);
+      query = query.filter(id.ne_all(dismissed));
+    }
+    query.order_by(starts.desc()).load::<Self>(conn)
+  }
+
+  /// Every announcement, including past and scheduled ones, for admins.
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    site_announcement.order_by(starts.desc()).load::<Self>(conn)
+  }
+
+  /// The announcements which started, but weren't sent to the connected clients yet.
+  pub fn list_due_for_push(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    let current_time = naive_now();
+    site_announcement
+      .filter(pushed.is_null())
+      .filter(starts.le(current_time))
+      .filter(ends.is_null().or(ends.gt(current_time)))
+      .order_by(starts.asc())
+      .load::<Self>(conn)
+  }
+
+  /// Returns 0 if the announcement was already pushed, so that it's only sent once.
+  pub fn mark_pushed(conn: &PgConnection, announcement_id: i32) -> Result<usize, Error> {
+    use crate::schema::site_announcement::dsl::*;
+    diesel::update(
+      site_announcement
+        .find(announcement_id)
+        .filter(pushed.is_null()),
+    )
+    .set(pushed.eq(naive_now()))
+    .execute(conn)
+  }
+
+  pub fn dismiss(
+    conn: &PgConnection,
+    form: &SiteAnnouncementDismissalForm,
+  ) -> Result<usize, Error> {
+    insert_into(site_announcement_dismissal::table)
+      .values(form)
+      .on_conflict_do_nothing()
+      .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    site_announcement::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "announcement_admin".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: true,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let announcement_form = SiteAnnouncementForm {
+      creator_id: inserted_user.id,
+      content: "maintenance tonight".into(),
+      severity: AnnouncementSeverity::Warning.to_string(),
+      starts: naive_now() - chrono::Duration::minutes(1),
+      ends: None,
+      updated: None,
+      pushed: None,
+    };
+    let inserted = SiteAnnouncement::create(&conn, &announcement_form).unwrap();
+    let scheduled_form = SiteAnnouncementForm {
+      starts: naive_now() + chrono::Duration::days(1),
+      ..announcement_form.clone()
+    };
+    let scheduled = SiteAnnouncement::create(&conn, &scheduled_form).unwrap();
+
+    let active = SiteAnnouncement::list_active(&conn, Some(inserted_user.id)).unwrap();
+    let due_for_push = SiteAnnouncement::list_due_for_push(&conn).unwrap();
+    let num_marked = SiteAnnouncement::mark_pushed(&conn, inserted.id).unwrap();
+    let num_marked_again = SiteAnnouncement::mark_pushed(&conn, inserted.id).unwrap();
+    let due_after_push = SiteAnnouncement::list_due_for_push(&conn).unwrap();
+    let dismissal_form = SiteAnnouncementDismissalForm {
+      announcement_id: inserted.id,
+      user_id: inserted_user.id,
+    };
+    SiteAnnouncement::dismiss(&conn, &dismissal_form).unwrap();
+    SiteAnnouncement::dismiss(&conn, &dismissal_form).unwrap();
+    let active_after_dismissal =
+      SiteAnnouncement::list_active(&conn, Some(inserted_user.id)).unwrap();
+    let active_anonymous = SiteAnnouncement::list_active(&conn, None).unwrap();
+    let all = SiteAnnouncement::list_all(&conn).unwrap();
+    let num_deleted = SiteAnnouncement::delete(&conn, inserted.id).unwrap();
+    SiteAnnouncement::delete(&conn, scheduled.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let expected_announcement = SiteAnnouncement {
+      id: inserted.id,
+      creator_id: inserted_user.id,
+      content: "maintenance tonight".into(),
+      severity: "Warning".into(),
+      starts: inserted.starts,
+      ends: None,
+      published: inserted.published,
+      updated: None,
+      pushed: None,
+    };
+
+    assert_eq!(expected_announcement, inserted);
+    assert!(active.contains(&inserted));
+    assert!(!active.contains(&scheduled));
+    assert!(due_for_push.contains(&inserted));
+    assert!(!due_for_push.contains(&scheduled));
+    assert_eq!(1, num_marked);
+    assert_eq!(0, num_marked_again);
+    assert!(due_after_push.iter().all(|a| a.id != inserted.id));
+    // Marked as pushed in the meantime
+    assert!(active_after_dismissal.iter().all(|a| a.id != inserted.id));
+    assert!(active_anonymous.iter().any(|a| a.id == inserted.id));
+    assert!(all.contains(&scheduled));
+    assert_eq!(1, num_deleted);
+  }
+}
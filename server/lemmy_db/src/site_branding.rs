@@ -0,0 +1,103 @@
+use crate::{naive_now, schema::site_branding};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "site_branding"]
+pub struct SiteBranding {
+  pub id: i32,
+  pub favicon: Option<String>,
+  pub primary_color: Option<String>,
+  pub secondary_color: Option<String>,
+  pub background_color: Option<String>,
+  pub text_color: Option<String>,
+  pub default_theme: Option<String>,
+  pub updated: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Default)]
+#[table_name = "site_branding"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct SiteBrandingForm {
+  pub favicon: Option<String>,
+  pub primary_color: Option<String>,
+  pub secondary_color: Option<String>,
+  pub background_color: Option<String>,
+  pub text_color: Option<String>,
+  pub default_theme: Option<String>,
+}
+
+impl SiteBranding {
+  /// None until an admin saves the branding for the first time.
+  pub fn read(conn: &PgConnection) -> Result<Option<Self>, Error> {
+    use crate::schema::site_branding::dsl::*;
+    site_branding.first::<Self>(conn).optional()
+  }
+
+  /// Replaces the branding, unset fields are cleared.
+  pub fn save(conn: &PgConnection, form: &SiteBrandingForm) -> Result<Self, Error> {
+    use crate::schema::site_branding::dsl::*;
+    insert_into(site_branding)
+      .values(form)
+      .on_conflict(id)
+      .do_update()
+      .set((form, updated.eq(naive_now())))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{site_branding::*, tests::establish_unpooled_connection};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let original = SiteBranding::read(&conn).unwrap();
+
+    let form = SiteBrandingForm {
+      favicon: Some("https://example.com/favicon.png".into()),
+      primary_color: Some("#ff0000".into()),
+      default_theme: Some("darkly".into()),
+      ..SiteBrandingForm::default()
+    };
+    let saved = SiteBranding::save(&conn, &form).unwrap();
+    let cleared = SiteBranding::save(&conn, &SiteBrandingForm::default()).unwrap();
+    let read_cleared = SiteBranding::read(&conn).unwrap();
+
+    // Put back whatever was there before the test
+    match &original {
+      Some(o) => {
+        let original_form = SiteBrandingForm {
+          favicon: o.favicon.to_owned(),
+          primary_color: o.primary_color.to_owned(),
+          secondary_color: o.secondary_color.to_owned(),
+          background_color: o.background_color.to_owned(),
+          text_color: o.text_color.to_owned(),
+          default_theme: o.default_theme.to_owned(),
+        };
+        SiteBranding::save(&conn, &original_form).unwrap();
+      }
+      None => {
+        diesel::delete(site_branding::table).execute(&conn).unwrap();
+      }
+    }
+
+    let expected_saved = SiteBranding {
+      id: 1,
+      favicon: Some("https://example.com/favicon.png".into()),
+      primary_color: Some("#ff0000".into()),
+      secondary_color: None,
+      background_color: None,
+      text_color: None,
+      default_theme: Some("darkly".into()),
+      updated: saved.updated,
+    };
+
+    assert_eq!(expected_saved, saved);
+    assert_eq!(None, cleared.favicon);
+    assert_eq!(None, cleared.default_theme);
+    assert_eq!(Some(cleared), read_cleared);
+  }
+}
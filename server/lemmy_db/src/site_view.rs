@@ -14,6 +14,12 @@ table! {
     enable_nsfw -> Bool,
     icon -> Nullable<Text>,
     banner -> Nullable<Text>,
+    require_alt_text -> Bool,
+    score_hidden_minutes -> Nullable<Int4>,
+    vote_lock_days -> Nullable<Int4>,
+    ninja_edit_minutes -> Nullable<Int4>,
+    edit_limit_days -> Nullable<Int4>,
+    undelete_window_days -> Nullable<Int4>,
     creator_name -> Varchar,
     creator_preferred_username -> Nullable<Varchar>,
     creator_avatar -> Nullable<Text>,
@@ -38,6 +44,12 @@ pub struct SiteView {
   pub enable_nsfw: bool,
   pub icon: Option<String>,
   pub banner: Option<String>,
+  pub require_alt_text: bool,
+  pub score_hidden_minutes: Option<i32>,
+  pub vote_lock_days: Option<i32>,
+  pub ninja_edit_minutes: Option<i32>,
+  pub edit_limit_days: Option<i32>,
+  pub undelete_window_days: Option<i32>,
   pub creator_name: String,
   pub creator_preferred_username: Option<String>,
   pub creator_avatar: Option<String>,
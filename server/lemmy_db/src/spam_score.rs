@@ -0,0 +1,261 @@
+use crate::{
+  limit_and_offset,
+  schema::{comment, post, spam_score},
+  Crud,
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The reason of the scores which hold back the mentions of a comment, until an admin approves them.
+pub const HELD_MENTIONS_REASON: &str = "held_mentions";
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "spam_score"]
+pub struct SpamScore {
+  pub id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub private_message_id: Option<i32>,
+  pub creator_id: i32,
+  pub score: f32,
+  pub reasons: Vec<String>,
+  pub content_hash: String,
+  pub reported: bool,
+  pub removed: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "spam_score"]
+pub struct SpamScoreForm {
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub private_message_id: Option<i32>,
+  pub creator_id: i32,
+  pub score: f32,
+  pub reasons: Vec<String>,
+  pub content_hash: String,
+  pub reported: bool,
+  pub removed: bool,
+}
+
+impl Crud<SpamScoreForm> for SpamScore {
+  fn read(conn: &PgConnection, spam_score_id: i32) -> Result<Self, Error> {
+    use crate::schema::spam_score::dsl::*;
+    spam_score.find(spam_score_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, spam_score_id: i32) -> Result<usize, Error> {
+    use crate::schema::spam_score::dsl::*;
+    diesel::delete(spam_score.find(spam_score_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &SpamScoreForm) -> Result<Self, Error> {
+    use crate::schema::spam_score::dsl::*;
+    insert_into(spam_score)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, spam_score_id: i32, form: &SpamScoreForm) -> Result<Self, Error> {
+    use crate::schema::spam_score::dsl::*;
+    diesel::update(spam_score.find(spam_score_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl SpamScore {
+  /// Hashes content the same way for every kind of content, ignoring case and whitespace, so that
+  /// duplicates can be found across posts, comments and private messages.
+  pub fn hash_content(content: &str) -> String {
+    let normalized = content
+      .split_whitespace()
+      .collect::<Vec<&str>>()
+      .join(" ")
+      .to_lowercase();
+    let mut hasher = Sha256::new();
+    hasher.update(normalized);
+    hasher
+      .finalize()
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect()
+  }
+
+  /// Stops holding back the mentions of the comment. Returns whether they were held, so that they
+  /// are sent out only once, even when two admins approve them at the same time.
+  pub fn release_held_mentions(conn: &PgConnection, for_comment_id: i32) -> Result<bool, Error> {
+    use crate::schema::spam_score::dsl::*;
+    let held_mentions = format!("'{}' = any(reasons)", HELD_MENTIONS_REASON);
+    let released = diesel::delete(
+      spam_score
+        .filter(comment_id.eq(for_comment_id))
+        .filter(sql::<diesel::sql_types::Bool>(&held_mentions)),
+    )
+    .execute(conn)?;
+    Ok(released > 0)
+  }
+
+  /// How many times the user submitted the same content during the last day.
+  pub fn count_duplicates(
+    conn: &PgConnection,
+    for_creator_id: i32,
+    for_content_hash: &str,
+  ) -> Result<i64, Error> {
+    use crate::schema::spam_score::dsl::*;
+    spam_score
+      .filter(creator_id.eq(for_creator_id))
+      .filter(content_hash.eq(for_content_hash))
+      .filter(published.gt(now - 1.days()))
+      .count()
+      .get_result(conn)
+  }
+
+  pub fn list(
+    conn: &PgConnection,
+    reported_only: bool,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::spam_score::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    let mut query = spam_score.into_boxed();
+
+    if reported_only {
+      query = query.filter(reported.eq(true).or(removed.eq(true)));
+    }
+
+    query
+      .order_by(published.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  /// The reported posts and comments of these communities, which weren't removed or deleted yet,
+  /// oldest first.
+  pub fn list_open_for_communities(
+    conn: &PgConnection,
+    community_ids: Vec<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::spam_score::dsl::*;
+    let open_posts = post::table
+      .filter(post::community_id.eq_any(community_ids.to_owned()))
+      .filter(post::removed.eq(false))
+      .filter(post::deleted.eq(false))
+      .select(post::id.nullable());
+    let open_comments = comment::table
+      .inner_join(post::table)
+      .filter(post::community_id.eq_any(community_ids))
+      .filter(comment::removed.eq(false))
+      .filter(comment::deleted.eq(false))
+      .select(comment::id.nullable());
+
+    spam_score
+      .filter(reported.eq(true))
+      .filter(removed.eq(false))
+      .filter(
+        post_id
+          .eq_any(open_posts)
+          .or(comment_id.eq_any(open_comments)),
+      )
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    spam_score::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "spammer_mcspamface".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let hash = SpamScore::hash_content("Buy  cheap\nWATCHES");
+
+    let spam_score_form = SpamScoreForm {
+      post_id: None,
+      comment_id: None,
+      private_message_id: None,
+      creator_id: inserted_user.id,
+      score: 0.95,
+      reasons: vec!["link_density".into()],
+      content_hash: hash.to_owned(),
+      reported: true,
+      removed: true,
+    };
+
+    let inserted_spam_score = SpamScore::create(&conn, &spam_score_form).unwrap();
+
+    let expected_spam_score = SpamScore {
+      id: inserted_spam_score.id,
+      post_id: None,
+      comment_id: None,
+      private_message_id: None,
+      creator_id: inserted_user.id,
+      score: 0.95,
+      reasons: vec!["link_density".into()],
+      content_hash: hash.to_owned(),
+      reported: true,
+      removed: true,
+      published: inserted_spam_score.published,
+    };
+
+    let watches_hash = SpamScore::hash_content("buy cheap watches");
+    let duplicates = SpamScore::count_duplicates(&conn, inserted_user.id, &watches_hash).unwrap();
+    let duplicates_of_others =
+      SpamScore::count_duplicates(&conn, inserted_user.id + 1, &watches_hash).unwrap();
+    let reported_scores = SpamScore::list(&conn, true, None, None).unwrap();
+    let num_deleted = SpamScore::delete(&conn, inserted_spam_score.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(expected_spam_score, inserted_spam_score);
+    assert_eq!(1, duplicates);
+    assert_eq!(0, duplicates_of_others);
+    assert!(reported_scores.contains(&expected_spam_score));
+    assert_eq!(1, num_deleted);
+  }
+}
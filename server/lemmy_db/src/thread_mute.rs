@@ -0,0 +1,258 @@
+use crate::{
+  comment::Comment,
+  schema::{comment, thread_mute},
+};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "thread_mute"]
+pub struct ThreadMute {
+  pub id: i32,
+  pub user_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "thread_mute"]
+pub struct ThreadMuteForm {
+  pub user_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+}
+
+impl ThreadMute {
+  pub fn mute(conn: &PgConnection, form: &ThreadMuteForm) -> Result<usize, Error> {
+    use crate::schema::thread_mute::dsl::*;
+    insert_into(thread_mute)
+      .values(form)
+      .on_conflict_do_nothing()
+      .execute(conn)
+  }
+
+  pub fn unmute(conn: &PgConnection, form: &ThreadMuteForm) -> Result<usize, Error> {
+    use crate::schema::thread_mute::dsl::*;
+    match (form.post_id, form.comment_id) {
+      (Some(for_post_id), _) => diesel::delete(
+        thread_mute
+          .filter(user_id.eq(form.user_id))
+          .filter(post_id.eq(for_post_id)),
+      )
+      .execute(conn),
+      (None, Some(for_comment_id)) => diesel::delete(
+        thread_mute
+          .filter(user_id.eq(form.user_id))
+          .filter(comment_id.eq(for_comment_id)),
+      )
+      .execute(conn),
+      (None, None) => Ok(0),
+    }
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::thread_mute::dsl::*;
+    thread_mute
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// The users who shouldn't be notified about this comment, because they muted its post, the
+  /// comment itself, or one of the comments it replies to.
+  pub fn muted_user_ids(conn: &PgConnection, for_comment: &Comment) -> Result<Vec<i32>, Error> {
+    use crate::schema::thread_mute::dsl::*;
+    let mut thread_comment_ids = vec![for_comment.id];
+    let mut next_parent_id = for_comment.parent_id;
+    while let Some(thread_comment_id) = next_parent_id {
+      thread_comment_ids.push(thread_comment_id);
+      next_parent_id = comment::table
+        .find(thread_comment_id)
+        .select(comment::parent_id)
+        .first::<Option<i32>>(conn)?;
+    }
+
+    thread_mute
+      .filter(
+        post_id
+          .eq(for_comment.post_id)
+          .or(comment_id.eq_any(thread_comment_ids)),
+      )
+      .select(user_id)
+      .distinct()
+      .load::<i32>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    comment::*,
+    community::*,
+    post::*,
+    tests::establish_unpooled_connection,
+    thread_mute::*,
+    user::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "thread_muter".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_thread_mute".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A noisy thread".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A muted comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      published: None,
+      updated: None,
+      ap_id: None,
+      local: true,
+      distinguished: None,
+    };
+
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+    let inserted_other_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let reply_form = CommentForm {
+      content: "A reply deep down the muted comment".into(),
+      parent_id: Some(inserted_comment.id),
+      ..comment_form
+    };
+
+    let inserted_reply = Comment::create(&conn, &reply_form).unwrap();
+
+    let comment_mute_form = ThreadMuteForm {
+      user_id: inserted_user.id,
+      post_id: None,
+      comment_id: Some(inserted_comment.id),
+    };
+
+    let num_muted = ThreadMute::mute(&conn, &comment_mute_form).unwrap();
+    let num_muted_again = ThreadMute::mute(&conn, &comment_mute_form).unwrap();
+
+    let muted_for_reply = ThreadMute::muted_user_ids(&conn, &inserted_reply).unwrap();
+    let muted_for_other_comment =
+      ThreadMute::muted_user_ids(&conn, &inserted_other_comment).unwrap();
+
+    let post_mute_form = ThreadMuteForm {
+      user_id: inserted_user.id,
+      post_id: Some(inserted_post.id),
+      comment_id: None,
+    };
+
+    ThreadMute::mute(&conn, &post_mute_form).unwrap();
+    let muted_for_post = ThreadMute::muted_user_ids(&conn, &inserted_other_comment).unwrap();
+    let mutes = ThreadMute::list_for_user(&conn, inserted_user.id).unwrap();
+
+    let num_unmuted = ThreadMute::unmute(&conn, &comment_mute_form).unwrap()
+      + ThreadMute::unmute(&conn, &post_mute_form).unwrap();
+    let mutes_after = ThreadMute::list_for_user(&conn, inserted_user.id).unwrap();
+
+    Comment::delete(&conn, inserted_reply.id).unwrap();
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Comment::delete(&conn, inserted_other_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(1, num_muted);
+    assert_eq!(0, num_muted_again);
+    assert_eq!(vec![inserted_user.id], muted_for_reply);
+    assert!(muted_for_other_comment.is_empty());
+    assert_eq!(vec![inserted_user.id], muted_for_post);
+    assert_eq!(2, mutes.len());
+    assert_eq!(2, num_unmuted);
+    assert!(mutes_after.is_empty());
+  }
+}
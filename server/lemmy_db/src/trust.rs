@@ -0,0 +1,170 @@
+use crate::{
+  community::CommunitySettings,
+  naive_now,
+  schema::{comment, mod_ban, mod_ban_from_community, post},
+  user::User_,
+  user_view::UserView,
+  Crud,
+};
+use diesel::{result::Error, *};
+use serde::Serialize;
+
+/// What a user needs to be trusted. Instance wide, but communities can override some of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrustThresholds {
+  pub min_account_age_days: i32,
+  pub min_karma: i32,
+  pub max_mod_actions: i64,
+}
+
+impl TrustThresholds {
+  pub fn for_community(&self, settings: &CommunitySettings) -> Self {
+    TrustThresholds {
+      min_account_age_days: settings
+        .trust_min_account_age_days
+        .unwrap_or(self.min_account_age_days),
+      min_karma: settings.trust_min_karma.unwrap_or(self.min_karma),
+      max_mod_actions: self.max_mod_actions,
+    }
+  }
+}
+
+/// What the trust level of a user is derived from.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UserTrust {
+  pub account_age_days: i64,
+  pub karma: i64,
+  /// How often the user was banned, or had content removed by a mod
+  pub mod_actions: i64,
+}
+
+impl UserTrust {
+  pub fn read(conn: &PgConnection, user_id: i32) -> Result<Self, Error> {
+    let user = User_::read(conn, user_id)?;
+    let user_view = UserView::get_user_secure(conn, user_id)?;
+
+    let removed_posts: i64 = post::table
+      .filter(post::creator_id.eq(user_id))
+      .filter(post::removed.eq(true))
+      .count()
+      .get_result(conn)?;
+    let removed_comments: i64 = comment::table
+      .filter(comment::creator_id.eq(user_id))
+      .filter(comment::removed.eq(true))
+      .count()
+      .get_result(conn)?;
+    let site_bans: i64 = mod_ban::table
+      .filter(mod_ban::other_user_id.eq(user_id))
+      .filter(mod_ban::banned.eq(true))
+      .count()
+      .get_result(conn)?;
+    let community_bans: i64 = mod_ban_from_community::table
+      .filter(mod_ban_from_community::other_user_id.eq(user_id))
+      .filter(mod_ban_from_community::banned.eq(true))
+      .count()
+      .get_result(conn)?;
+
+    Ok(UserTrust {
+      account_age_days: (naive_now() - user.published).num_days(),
+      karma: user_view.post_score + user_view.comment_score,
+      mod_actions: removed_posts + removed_comments + site_bans + community_bans,
+    })
+  }
+
+  pub fn is_trusted(&self, thresholds: &TrustThresholds) -> bool {
+    self.account_age_days >= i64::from(thresholds.min_account_age_days)
+      && self.karma >= i64::from(thresholds.min_karma)
+      && self.mod_actions <= thresholds.max_mod_actions
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{tests::establish_unpooled_connection, trust::*, user::*, ListingType, SortType};
+
+  #[test]
+  fn test_trust() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "newcomer".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let trust = UserTrust::read(&conn, inserted_user.id).unwrap();
+
+    let expected_trust = UserTrust {
+      account_age_days: 0,
+      karma: 0,
+      mod_actions: 0,
+    };
+
+    let lenient = TrustThresholds {
+      min_account_age_days: 0,
+      min_karma: 0,
+      max_mod_actions: 0,
+    };
+    let strict_community = CommunitySettings {
+      id: 0,
+      community_id: 0,
+      require_alt_text: false,
+      trust_min_account_age_days: Some(7),
+      trust_min_karma: None,
+      allow_anonymous_posts: false,
+      join_requires_approval: false,
+      join_question: None,
+      private: false,
+      local_only: false,
+      allow_authors_close_comments: false,
+      automod_comment: None,
+      automod_user_id: None,
+      welcome_message: None,
+      max_post_body_length: None,
+      max_comment_length: None,
+      allowed_post_type: "any".into(),
+      require_post_tag: false,
+      title_pattern: None,
+      clean_titles: false,
+      title_capitalization: "none".into(),
+      allow_post_url_edits: true,
+      microblog_mentions: "ignore".into(),
+      digest_template: None,
+      discoverable: true,
+    };
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(expected_trust, trust);
+    assert!(trust.is_trusted(&lenient));
+    assert!(!trust.is_trusted(&lenient.for_community(&strict_community)));
+  }
+}
@@ -1,13 +1,24 @@
 use crate::{
+  comment::Comment,
   is_email_regex,
   naive_now,
-  schema::{user_, user_::dsl::*},
+  post::Post,
+  schema::{community_follower, user_, user_::dsl::*},
   Crud,
 };
-use bcrypt::{hash, DEFAULT_COST};
 use diesel::{dsl::*, result::Error, *};
 use serde::Serialize;
 
+/// The name of the local user which the content of deleted local accounts is attributed to. It
+/// isn't a valid username, so that no account can be registered with it.
+pub const DELETED_USER_NAME: &str = "deleted.user";
+
+/// The name of the local user which posts the automod comments and welcome messages of a
+/// community. Like DELETED_USER_NAME, it isn't a valid username.
+pub fn automod_user_name(community_id: i32) -> String {
+  format!("automod.{}", community_id)
+}
+
 #[derive(Clone, Queryable, Identifiable, PartialEq, Debug, Serialize)]
 #[table_name = "user_"]
 pub struct User_ {
@@ -36,6 +47,15 @@ pub struct User_ {
   pub public_key: Option<String>,
   pub last_refreshed_at: chrono::NaiveDateTime,
   pub banner: Option<String>,
+  pub discoverable: bool,
+  pub receive_welcome_messages: bool,
+  pub deleted: bool,
+  pub bot_account: bool,
+  pub collapse_comments_below_score: Option<i32>,
+  pub collapse_bot_comments: bool,
+  pub remember_comment_sort: bool,
+  /// Set when the password changes, see `Claims::session_id`
+  pub sessionless_tokens_revoked: bool,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -64,6 +84,12 @@ pub struct UserForm {
   pub public_key: Option<String>,
   pub last_refreshed_at: Option<chrono::NaiveDateTime>,
   pub banner: Option<Option<String>>,
+  pub discoverable: bool,
+  pub receive_welcome_messages: bool,
+  pub bot_account: bool,
+  pub collapse_comments_below_score: Option<i32>,
+  pub collapse_bot_comments: bool,
+  pub remember_comment_sort: bool,
 }
 
 impl Crud<UserForm> for User_ {
@@ -83,24 +109,22 @@ impl Crud<UserForm> for User_ {
   }
 }
 
-impl User_ {
-  pub fn register(conn: &PgConnection, form: &UserForm) -> Result<Self, Error> {
-    let mut edited_user = form.clone();
-    let password_hash =
-      hash(&form.password_encrypted, DEFAULT_COST).expect("Couldn't hash password");
-    edited_user.password_encrypted = password_hash;
-
-    Self::create(&conn, &edited_user)
-  }
+/// The ids of users who opted out of search, which the search queries leave out.
+pub fn undiscoverable_users(conn: &PgConnection) -> Result<Vec<i32>, Error> {
+  user_::table
+    .select(user_::id)
+    .filter(user_::discoverable.eq(false))
+    .load::<i32>(conn)
+}
 
+impl User_ {
   // TODO do more individual updates like these
+  /// Takes the hash, which is made with lemmy_utils::password::hash_password.
   pub fn update_password(
     conn: &PgConnection,
     user_id: i32,
-    new_password: &str,
+    password_hash: &str,
   ) -> Result<Self, Error> {
-    let password_hash = hash(new_password, DEFAULT_COST).expect("Couldn't hash password");
-
     diesel::update(user_.find(user_id))
       .set((
         password_encrypted.eq(password_hash),
@@ -125,6 +149,108 @@ impl User_ {
       .get_result::<Self>(conn)
   }
 
+  /// Makes the tokens of the user which don't belong to a sign in stop working.
+  pub fn revoke_sessionless_tokens(conn: &PgConnection, user_id: i32) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(sessionless_tokens_revoked.eq(true))
+      .get_result::<Self>(conn)
+  }
+
+  /// The placeholder user, see DELETED_USER_NAME.
+  pub fn read_deleted_placeholder(conn: &PgConnection) -> Result<Self, Error> {
+    user_
+      .filter(name.eq(DELETED_USER_NAME))
+      .filter(local.eq(true))
+      .first::<Self>(conn)
+  }
+
+  /// The local accounts which weren't deleted, without the placeholder of the deleted ones.
+  pub fn count_local(conn: &PgConnection) -> Result<i64, Error> {
+    user_
+      .filter(local.eq(true))
+      .filter(deleted.eq(false))
+      .filter(name.ne(DELETED_USER_NAME))
+      .count()
+      .get_result(conn)
+  }
+
+  /// Marks the account as deleted, and clears everything on its profile except the name, which its
+  /// actor id is made of. It can't log in anymore, and isn't subscribed to anything, so nothing is
+  /// sent to it anymore. The posts and comments of local accounts are attributed to the
+  /// placeholder user, while remote ones keep theirs, as a tombstone of their actor.
+  pub fn delete_account(conn: &PgConnection, user_id: i32) -> Result<Self, Error> {
+    conn.transaction(|| {
+      if Self::read(conn, user_id)?.local {
+        let placeholder = Self::read_deleted_placeholder(conn)?;
+        Comment::reassign_creator(conn, user_id, placeholder.id)?;
+        Post::reassign_creator(conn, user_id, placeholder.id)?;
+      }
+      diesel::delete(community_follower::table.filter(community_follower::user_id.eq(user_id)))
+        .execute(conn)?;
+
+      diesel::update(user_.find(user_id))
+        .set((
+          deleted.eq(true),
+          password_encrypted.eq(""),
+          preferred_username.eq(None::<String>),
+          email.eq(None::<String>),
+          matrix_user_id.eq(None::<String>),
+          avatar.eq(None::<String>),
+          banner.eq(None::<String>),
+          bio.eq(None::<String>),
+          discoverable.eq(false),
+          updated.eq(naive_now()),
+        ))
+        .get_result::<Self>(conn)
+    })
+  }
+
+  /// Remote users that weren't refetched since the given time, the oldest first.
+  pub fn list_stale_remote(
+    conn: &PgConnection,
+    refreshed_before: chrono::NaiveDateTime,
+    limit: i64,
+  ) -> Result<Vec<Self>, Error> {
+    user_
+      .filter(local.eq(false))
+      .filter(deleted.eq(false))
+      .filter(last_refreshed_at.lt(refreshed_before))
+      .order_by(last_refreshed_at.asc())
+      .limit(limit)
+      .load::<Self>(conn)
+  }
+
+  /// Moves the user to the back of the refetch queue, eg if its instance is unreachable.
+  pub fn mark_refreshed(conn: &PgConnection, user_id: i32) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(last_refreshed_at.eq(naive_now()))
+      .get_result::<Self>(conn)
+  }
+
+  /// The local users whose keys sign activities, that is those which weren't deleted.
+  pub fn list_local_ids(conn: &PgConnection) -> Result<Vec<i32>, Error> {
+    user_
+      .filter(local.eq(true))
+      .filter(deleted.eq(false))
+      .select(id)
+      .order_by(id)
+      .load::<i32>(conn)
+  }
+
+  pub fn update_keys(
+    conn: &PgConnection,
+    user_id: i32,
+    new_private_key: &str,
+    new_public_key: &str,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set((
+        private_key.eq(new_private_key),
+        public_key.eq(new_public_key),
+      ))
+      .get_result::<Self>(conn)
+  }
+
   pub fn read_from_actor_id(conn: &PgConnection, object_id: &str) -> Result<Self, Error> {
     use crate::schema::user_::dsl::*;
     user_.filter(actor_id.eq(object_id)).first::<Self>(conn)
@@ -161,6 +287,53 @@ impl User_ {
       .set(user_form)
       .get_result::<Self>(conn)
   }
+
+  /// A deleted remote user, for the content of an actor which was gone from its instance before
+  /// we ever fetched it. The content stays attributed to its own actor id.
+  pub fn upsert_remote_tombstone(
+    conn: &PgConnection,
+    tombstone_actor_id: &str,
+    tombstone_name: &str,
+  ) -> Result<User_, Error> {
+    let form = UserForm {
+      name: tombstone_name.chars().take(20).collect(),
+      preferred_username: None,
+      password_encrypted: "".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "".into(),
+      default_sort_type: 0,
+      default_listing_type: 0,
+      lang: "".into(),
+      show_avatars: false,
+      send_notifications_to_email: false,
+      actor_id: Some(tombstone_actor_id.to_owned()),
+      bio: None,
+      local: false,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: Some(naive_now()),
+      discoverable: false,
+      receive_welcome_messages: false,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+    let tombstone = insert_into(user_)
+      .values(&form)
+      .on_conflict(actor_id)
+      .do_update()
+      .set(last_refreshed_at.eq(naive_now()))
+      .get_result::<Self>(conn)?;
+    Self::delete_account(conn, tombstone.id)
+  }
 }
 
 #[cfg(test)]
@@ -195,6 +368,12 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     let inserted_user = User_::create(&conn, &new_user).unwrap();
@@ -225,15 +404,36 @@ mod tests {
       private_key: None,
       public_key: None,
       last_refreshed_at: inserted_user.published,
+      discoverable: true,
+      receive_welcome_messages: true,
+      deleted: false,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+      sessionless_tokens_revoked: false,
     };
 
     let read_user = User_::read(&conn, inserted_user.id).unwrap();
     let updated_user = User_::update(&conn, inserted_user.id, &new_user).unwrap();
+    let local_ids = User_::list_local_ids(&conn).unwrap();
+    let rotated_user = User_::update_keys(&conn, inserted_user.id, "private", "public").unwrap();
     let num_deleted = User_::delete(&conn, inserted_user.id).unwrap();
 
+    let tombstone =
+      User_::upsert_remote_tombstone(&conn, "https://example.com/u/gone_user", "gone_user")
+        .unwrap();
+    User_::delete(&conn, tombstone.id).unwrap();
+
     assert_eq!(expected_user, read_user);
     assert_eq!(expected_user, inserted_user);
     assert_eq!(expected_user, updated_user);
+    assert!(local_ids.contains(&inserted_user.id));
+    assert_eq!(Some("private".into()), rotated_user.private_key);
+    assert_eq!(Some("public".into()), rotated_user.public_key);
     assert_eq!(1, num_deleted);
+    assert!(tombstone.deleted);
+    assert!(!tombstone.local);
+    assert_eq!("gone_user", tombstone.name);
   }
 }
@@ -0,0 +1,133 @@
+use crate::schema::user_presence;
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "user_presence"]
+pub struct UserPresence {
+  pub id: i32,
+  pub user_id: i32,
+  pub last_active: chrono::NaiveDateTime,
+  pub show_presence: bool,
+}
+
+impl UserPresence {
+  pub fn read_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Option<Self>, Error> {
+    use crate::schema::user_presence::dsl::*;
+    user_presence
+      .filter(user_id.eq(for_user_id))
+      .first::<Self>(conn)
+      .optional()
+  }
+
+  pub fn mark_active(conn: &PgConnection, for_user_id: i32) -> Result<Self, Error> {
+    use crate::schema::user_presence::dsl::*;
+    insert_into(user_presence)
+      .values(user_id.eq(for_user_id))
+      .on_conflict(user_id)
+      .do_update()
+      .set(last_active.eq(now))
+      .get_result::<Self>(conn)
+  }
+
+  /// How many users were active since the given time, whether they show it or not.
+  pub fn count_active_since(
+    conn: &PgConnection,
+    since: chrono::NaiveDateTime,
+  ) -> Result<i64, Error> {
+    use crate::schema::user_presence::dsl::*;
+    user_presence
+      .filter(last_active.gt(since))
+      .count()
+      .get_result(conn)
+  }
+
+  pub fn update_show_presence(
+    conn: &PgConnection,
+    for_user_id: i32,
+    new_show_presence: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::user_presence::dsl::*;
+    insert_into(user_presence)
+      .values((user_id.eq(for_user_id), show_presence.eq(new_show_presence)))
+      .on_conflict(user_id)
+      .do_update()
+      .set(show_presence.eq(new_show_presence))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    tests::establish_unpooled_connection,
+    user::*,
+    user_presence::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "present_user".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let before_active = UserPresence::read_for_user(&conn, inserted_user.id).unwrap();
+    let first_active = UserPresence::mark_active(&conn, inserted_user.id).unwrap();
+    let hidden = UserPresence::update_show_presence(&conn, inserted_user.id, false).unwrap();
+    let second_active = UserPresence::mark_active(&conn, inserted_user.id).unwrap();
+    let read_presence = UserPresence::read_for_user(&conn, inserted_user.id).unwrap();
+    let active_since = first_active.last_active - chrono::Duration::seconds(1);
+    let active_users = UserPresence::count_active_since(&conn, active_since).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let expected_presence = UserPresence {
+      id: first_active.id,
+      user_id: inserted_user.id,
+      last_active: second_active.last_active,
+      show_presence: false,
+    };
+
+    assert_eq!(None, before_active);
+    assert!(first_active.show_presence);
+    assert!(!hidden.show_presence);
+    assert!(second_active.last_active >= first_active.last_active);
+    assert_eq!(expected_presence, second_active);
+    assert_eq!(Some(expected_presence), read_presence);
+    assert!(active_users >= 1);
+  }
+}
@@ -1,5 +1,5 @@
 use super::user_view::user_fast::BoxedQuery;
-use crate::{fuzzy_search, limit_and_offset, MaybeOptional, SortType};
+use crate::{fuzzy_search, limit_and_offset, user::undiscoverable_users, MaybeOptional, SortType};
 use diesel::{dsl::*, pg::Pg, result::Error, *};
 use serde::Serialize;
 
@@ -79,6 +79,7 @@ pub struct UserQueryBuilder<'a> {
   conn: &'a PgConnection,
   query: BoxedQuery<'a, Pg>,
   sort: &'a SortType,
+  search_term: Option<String>,
   page: Option<i64>,
   limit: Option<i64>,
 }
@@ -93,6 +94,7 @@ impl<'a> UserQueryBuilder<'a> {
       conn,
       query,
       sort: &SortType::Hot,
+      search_term: None,
       page: None,
       limit: None,
     }
@@ -104,10 +106,7 @@ impl<'a> UserQueryBuilder<'a> {
   }
 
   pub fn search_term<T: MaybeOptional<String>>(mut self, search_term: T) -> Self {
-    use super::user_view::user_fast::dsl::*;
-    if let Some(search_term) = search_term.get_optional() {
-      self.query = self.query.filter(name.ilike(fuzzy_search(&search_term)));
-    }
+    self.search_term = search_term.get_optional();
     self
   }
 
@@ -127,6 +126,12 @@ impl<'a> UserQueryBuilder<'a> {
 
     let mut query = self.query;
 
+    if let Some(search_term) = self.search_term {
+      query = query
+        .filter(name.ilike(fuzzy_search(&search_term)))
+        .filter(id.ne_all(undiscoverable_users(self.conn)?));
+    }
+
     query = match self.sort {
       SortType::Hot => query
         .order_by(comment_score.desc())
@@ -0,0 +1,227 @@
+use crate::schema::{post, vote_burst};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+/// A post gets a burst alert when this many votes from one remote instance arrive within
+/// VOTE_BURST_MINUTES.
+pub const VOTE_BURST_VOTES: i64 = 15;
+pub const VOTE_BURST_MINUTES: i64 = 10;
+
+/// Lots of votes on a post from one remote instance in a short time, for the mods to check.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "vote_burst"]
+pub struct VoteBurst {
+  pub id: i32,
+  pub post_id: i32,
+  pub instance: String,
+  pub votes: i32,
+  pub resolved: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "vote_burst"]
+pub struct VoteBurstForm {
+  pub post_id: i32,
+  pub instance: String,
+  pub votes: i32,
+}
+
+impl VoteBurst {
+  pub fn create(conn: &PgConnection, form: &VoteBurstForm) -> Result<Self, Error> {
+    use crate::schema::vote_burst::dsl::*;
+    insert_into(vote_burst)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection, vote_burst_id: i32) -> Result<Self, Error> {
+    use crate::schema::vote_burst::dsl::*;
+    vote_burst.find(vote_burst_id).first::<Self>(conn)
+  }
+
+  /// Whether the burst of the instance on the post was already recorded since the given time, so
+  /// that the mods only get one alert for it.
+  pub fn exists_since(
+    conn: &PgConnection,
+    for_post_id: i32,
+    for_instance: &str,
+    since: chrono::NaiveDateTime,
+  ) -> Result<bool, Error> {
+    use crate::schema::vote_burst::dsl::*;
+    select(exists(
+      vote_burst
+        .filter(post_id.eq(for_post_id))
+        .filter(instance.eq(for_instance))
+        .filter(published.gt(since)),
+    ))
+    .get_result(conn)
+  }
+
+  pub fn resolve(conn: &PgConnection, vote_burst_id: i32) -> Result<Self, Error> {
+    use crate::schema::vote_burst::dsl::*;
+    diesel::update(vote_burst.find(vote_burst_id))
+      .set(resolved.eq(true))
+      .get_result::<Self>(conn)
+  }
+
+  /// The community of the post.
+  pub fn community_id(&self, conn: &PgConnection) -> Result<i32, Error> {
+    post::table
+      .find(self.post_id)
+      .select(post::community_id)
+      .first::<i32>(conn)
+  }
+
+  /// The unresolved bursts on posts in these communities, which weren't removed or deleted yet,
+  /// oldest first.
+  pub fn list_open_for_communities(
+    conn: &PgConnection,
+    community_ids: Vec<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::vote_burst::dsl::*;
+    let open_posts = post::table
+      .filter(post::community_id.eq_any(community_ids))
+      .filter(post::removed.eq(false))
+      .filter(post::deleted.eq(false))
+      .select(post::id);
+
+    vote_burst
+      .filter(resolved.eq(false))
+      .filter(post_id.eq_any(open_posts))
+      .order_by(published.asc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    naive_now,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    vote_burst::*,
+    Crud,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "vote_burst_mod".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_vote_burst".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A popular post elsewhere".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let since = naive_now() - chrono::Duration::minutes(VOTE_BURST_MINUTES);
+    let existed_before =
+      VoteBurst::exists_since(&conn, inserted_post.id, "votes.example.com", since).unwrap();
+
+    let form = VoteBurstForm {
+      post_id: inserted_post.id,
+      instance: "votes.example.com".into(),
+      votes: 20,
+    };
+    let burst = VoteBurst::create(&conn, &form).unwrap();
+    let exists =
+      VoteBurst::exists_since(&conn, inserted_post.id, "votes.example.com", since).unwrap();
+    let community_id = burst.community_id(&conn).unwrap();
+    let open = VoteBurst::list_open_for_communities(&conn, vec![inserted_community.id]).unwrap();
+    let resolved = VoteBurst::resolve(&conn, burst.id).unwrap();
+    let open_after =
+      VoteBurst::list_open_for_communities(&conn, vec![inserted_community.id]).unwrap();
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert!(!existed_before);
+    assert!(exists);
+    assert_eq!(inserted_community.id, community_id);
+    assert_eq!(vec![burst.to_owned()], open);
+    assert!(resolved.resolved);
+    assert!(open_after.is_empty());
+  }
+}
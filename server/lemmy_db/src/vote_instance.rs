@@ -0,0 +1,384 @@
+use crate::schema::user_ignored_vote_instance;
+use diesel::{dsl::*, result::Error, sql_types::BigInt, *};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Counted by `group_by`, for the votes of each post or comment.
+const UPVOTES_SQL: &str = "count(*) filter (where score = 1)";
+const DOWNVOTES_SQL: &str = "count(*) filter (where score = -1)";
+
+/// A remote instance with at least this many votes on a post or comment is unusual if they are
+/// UNUSUAL_ORIGIN_SHARE of all its votes or more.
+pub const UNUSUAL_ORIGIN_MIN_VOTES: i64 = 10;
+pub const UNUSUAL_ORIGIN_SHARE: f64 = 0.5;
+
+/// The votes of a post or comment from one instance, or from local users if `instance` is none.
+#[derive(PartialEq, Debug, Serialize, Clone)]
+pub struct VoteOrigin {
+  pub instance: Option<String>,
+  pub upvotes: i64,
+  pub downvotes: i64,
+  /// Many of the votes come from this remote instance, which may be brigading
+  pub unusual: bool,
+}
+
+impl VoteOrigin {
+  /// Most votes first.
+  fn from_counts(counts: Vec<(Option<String>, i64, i64)>) -> Vec<Self> {
+    let total_votes: i64 = counts.iter().map(|(_, up, down)| up + down).sum();
+    let mut origins = counts
+      .into_iter()
+      .map(|(instance, upvotes, downvotes)| {
+        let votes = upvotes + downvotes;
+        let unusual = instance.is_some()
+          && votes >= UNUSUAL_ORIGIN_MIN_VOTES
+          && votes as f64 >= total_votes as f64 * UNUSUAL_ORIGIN_SHARE;
+        VoteOrigin {
+          instance,
+          upvotes,
+          downvotes,
+          unusual,
+        }
+      })
+      .collect::<Vec<Self>>();
+    origins.sort_by_key(|o| -(o.upvotes + o.downvotes));
+    origins
+  }
+
+  pub fn for_post(conn: &PgConnection, for_post_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::post_like::dsl::*;
+    let counts = post_like
+      .filter(post_id.eq(for_post_id))
+      .group_by(instance)
+      .select((
+        instance,
+        sql::<BigInt>(UPVOTES_SQL),
+        sql::<BigInt>(DOWNVOTES_SQL),
+      ))
+      .load::<(Option<String>, i64, i64)>(conn)?;
+    Ok(Self::from_counts(counts))
+  }
+
+  pub fn for_comment(conn: &PgConnection, for_comment_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::comment_like::dsl::*;
+    let counts = comment_like
+      .filter(comment_id.eq(for_comment_id))
+      .group_by(instance)
+      .select((
+        instance,
+        sql::<BigInt>(UPVOTES_SQL),
+        sql::<BigInt>(DOWNVOTES_SQL),
+      ))
+      .load::<(Option<String>, i64, i64)>(conn)?;
+    Ok(Self::from_counts(counts))
+  }
+}
+
+/// How many votes the post got from users of the instance since the given time.
+pub fn count_recent_post_votes_from_instance(
+  conn: &PgConnection,
+  for_post_id: i32,
+  from_instance: &str,
+  since: chrono::NaiveDateTime,
+) -> Result<i64, Error> {
+  use crate::schema::post_like::dsl::*;
+  post_like
+    .filter(post_id.eq(for_post_id))
+    .filter(instance.eq(from_instance))
+    .filter(published.gt(since))
+    .count()
+    .get_result(conn)
+}
+
+/// An instance whose votes a user leaves out of the scores they see.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "user_ignored_vote_instance"]
+pub struct UserIgnoredVoteInstance {
+  pub id: i32,
+  pub user_id: i32,
+  pub instance: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "user_ignored_vote_instance"]
+pub struct UserIgnoredVoteInstanceForm {
+  pub user_id: i32,
+  pub instance: String,
+}
+
+impl UserIgnoredVoteInstance {
+  pub fn ignore(conn: &PgConnection, form: &UserIgnoredVoteInstanceForm) -> Result<usize, Error> {
+    use crate::schema::user_ignored_vote_instance::dsl::*;
+    insert_into(user_ignored_vote_instance)
+      .values(form)
+      .on_conflict_do_nothing()
+      .execute(conn)
+  }
+
+  pub fn unignore(conn: &PgConnection, form: &UserIgnoredVoteInstanceForm) -> Result<usize, Error> {
+    use crate::schema::user_ignored_vote_instance::dsl::*;
+    diesel::delete(
+      user_ignored_vote_instance
+        .filter(user_id.eq(form.user_id))
+        .filter(instance.eq(&form.instance)),
+    )
+    .execute(conn)
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::user_ignored_vote_instance::dsl::*;
+    user_ignored_vote_instance
+      .filter(user_id.eq(for_user_id))
+      .order_by(instance.asc())
+      .load::<Self>(conn)
+  }
+
+  pub fn instances_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<String>, Error> {
+    use crate::schema::user_ignored_vote_instance::dsl::*;
+    user_ignored_vote_instance
+      .filter(user_id.eq(for_user_id))
+      .select(instance)
+      .load::<String>(conn)
+  }
+}
+
+/// The upvotes and downvotes from these instances, by post.
+pub fn post_votes_from_instances(
+  conn: &PgConnection,
+  post_ids: Vec<i32>,
+  instances: Vec<String>,
+) -> Result<HashMap<i32, (i64, i64)>, Error> {
+  use crate::schema::post_like::dsl::*;
+  let votes = post_like
+    .filter(post_id.eq_any(post_ids))
+    .filter(instance.eq_any(instances))
+    .group_by(post_id)
+    .select((
+      post_id,
+      sql::<BigInt>(UPVOTES_SQL),
+      sql::<BigInt>(DOWNVOTES_SQL),
+    ))
+    .load::<(i32, i64, i64)>(conn)?;
+  Ok(
+    votes
+      .into_iter()
+      .map(|(i, up, down)| (i, (up, down)))
+      .collect(),
+  )
+}
+
+/// The upvotes and downvotes from these instances, by comment.
+pub fn comment_votes_from_instances(
+  conn: &PgConnection,
+  comment_ids: Vec<i32>,
+  instances: Vec<String>,
+) -> Result<HashMap<i32, (i64, i64)>, Error> {
+  use crate::schema::comment_like::dsl::*;
+  let votes = comment_like
+    .filter(comment_id.eq_any(comment_ids))
+    .filter(instance.eq_any(instances))
+    .group_by(comment_id)
+    .select((
+      comment_id,
+      sql::<BigInt>(UPVOTES_SQL),
+      sql::<BigInt>(DOWNVOTES_SQL),
+    ))
+    .load::<(i32, i64, i64)>(conn)?;
+  Ok(
+    votes
+      .into_iter()
+      .map(|(i, up, down)| (i, (up, down)))
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    community::*,
+    naive_now,
+    post::*,
+    tests::establish_unpooled_connection,
+    user::*,
+    vote_instance::*,
+    Crud,
+    Likeable,
+    ListingType,
+    SortType,
+  };
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "vote_ignorer".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: None,
+      bio: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let remote_user_form = UserForm {
+      name: "remote_voter".into(),
+      actor_id: Some("https://Votes.Example.com/u/remote_voter".into()),
+      local: false,
+      ..new_user
+    };
+
+    let inserted_remote_user = User_::create(&conn, &remote_user_form).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_vote_instance".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: None,
+      local: true,
+      private_key: None,
+      public_key: None,
+      last_refreshed_at: None,
+      published: None,
+      icon: None,
+      banner: None,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A brigaded post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let local_like = PostLike::like(
+      &conn,
+      &PostLikeForm {
+        post_id: inserted_post.id,
+        user_id: inserted_user.id,
+        score: 1,
+      },
+    )
+    .unwrap();
+    let remote_like = PostLike::like(
+      &conn,
+      &PostLikeForm {
+        post_id: inserted_post.id,
+        user_id: inserted_remote_user.id,
+        score: -1,
+      },
+    )
+    .unwrap();
+
+    let ignore_form = UserIgnoredVoteInstanceForm {
+      user_id: inserted_user.id,
+      instance: "votes.example.com".into(),
+    };
+
+    let num_ignored = UserIgnoredVoteInstance::ignore(&conn, &ignore_form).unwrap();
+    let num_ignored_again = UserIgnoredVoteInstance::ignore(&conn, &ignore_form).unwrap();
+    let ignored = UserIgnoredVoteInstance::list_for_user(&conn, inserted_user.id).unwrap();
+    let instances = UserIgnoredVoteInstance::instances_for_user(&conn, inserted_user.id).unwrap();
+    let ignored_votes =
+      post_votes_from_instances(&conn, vec![inserted_post.id], instances.clone()).unwrap();
+    let origins = VoteOrigin::for_post(&conn, inserted_post.id).unwrap();
+    let recent_votes = count_recent_post_votes_from_instance(
+      &conn,
+      inserted_post.id,
+      "votes.example.com",
+      naive_now() - chrono::Duration::minutes(1),
+    )
+    .unwrap();
+
+    let num_unignored = UserIgnoredVoteInstance::unignore(&conn, &ignore_form).unwrap();
+    let instances_after =
+      UserIgnoredVoteInstance::instances_for_user(&conn, inserted_user.id).unwrap();
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_remote_user.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(None, local_like.instance);
+    assert_eq!(Some("votes.example.com".to_string()), remote_like.instance);
+    assert_eq!(1, num_ignored);
+    assert_eq!(0, num_ignored_again);
+    assert_eq!(1, ignored.len());
+    assert_eq!(vec!["votes.example.com".to_string()], instances);
+    assert_eq!(Some(&(0, 1)), ignored_votes.get(&inserted_post.id));
+    assert_eq!(2, origins.len());
+    assert!(origins
+      .iter()
+      .any(|o| o.instance.is_none() && o.upvotes == 1 && o.downvotes == 0));
+    assert!(origins.iter().all(|o| !o.unusual));
+    assert_eq!(1, recent_votes);
+    assert_eq!(1, num_unignored);
+    assert!(instances_after.is_empty());
+  }
+
+  #[test]
+  fn test_unusual_origins() {
+    let origins = VoteOrigin::from_counts(vec![
+      (None, 8, 1),
+      (Some("brigade.example.com".into()), 2, 12),
+      (Some("quiet.example.com".into()), 3, 0),
+    ]);
+
+    assert_eq!(Some("brigade.example.com".to_string()), origins[0].instance);
+    assert!(origins[0].unusual);
+    assert!(origins[1..].iter().all(|o| !o.unusual));
+  }
+}
@@ -0,0 +1,87 @@
+use crate::{naive_now, schema::wasm_plugin};
+use diesel::{dsl::*, result::Error, *};
+use serde::Serialize;
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Clone)]
+#[table_name = "wasm_plugin"]
+pub struct WasmPlugin {
+  pub id: i32,
+  pub name: String,
+  pub enabled: bool,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "wasm_plugin"]
+pub struct WasmPluginForm {
+  pub name: String,
+  pub enabled: bool,
+}
+
+impl WasmPlugin {
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::wasm_plugin::dsl::*;
+    wasm_plugin.order_by(name.asc()).load::<Self>(conn)
+  }
+
+  pub fn list_enabled_names(conn: &PgConnection) -> Result<Vec<String>, Error> {
+    use crate::schema::wasm_plugin::dsl::*;
+    wasm_plugin
+      .filter(enabled.eq(true))
+      .order_by(name.asc())
+      .select(name)
+      .load::<String>(conn)
+  }
+
+  /// Turns the plugin on or off, whether or not it was known before.
+  pub fn set_enabled(conn: &PgConnection, form: &WasmPluginForm) -> Result<Self, Error> {
+    use crate::schema::wasm_plugin::dsl::*;
+    insert_into(wasm_plugin)
+      .values(form)
+      .on_conflict(name)
+      .do_update()
+      .set((form, updated.eq(naive_now())))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn delete(conn: &PgConnection, plugin_name: &str) -> Result<usize, Error> {
+    use crate::schema::wasm_plugin::dsl::*;
+    diesel::delete(wasm_plugin.filter(name.eq(plugin_name))).execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{tests::establish_unpooled_connection, wasm_plugin::*};
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let form = WasmPluginForm {
+      name: "test_wasm_plugin".into(),
+      enabled: true,
+    };
+    let inserted_plugin = WasmPlugin::set_enabled(&conn, &form).unwrap();
+    let enabled_names = WasmPlugin::list_enabled_names(&conn).unwrap();
+
+    let disabled_form = WasmPluginForm {
+      enabled: false,
+      ..form
+    };
+    let disabled_plugin = WasmPlugin::set_enabled(&conn, &disabled_form).unwrap();
+    let enabled_after_disable = WasmPlugin::list_enabled_names(&conn).unwrap();
+    let all_plugins = WasmPlugin::list_all(&conn).unwrap();
+    let num_deleted = WasmPlugin::delete(&conn, "test_wasm_plugin").unwrap();
+
+    assert!(inserted_plugin.enabled);
+    assert!(enabled_names.contains(&"test_wasm_plugin".to_string()));
+    assert_eq!(inserted_plugin.id, disabled_plugin.id);
+    assert!(!disabled_plugin.enabled);
+    assert!(disabled_plugin.updated.is_some());
+    assert!(!enabled_after_disable.contains(&"test_wasm_plugin".to_string()));
+    assert!(all_plugins.contains(&disabled_plugin));
+    assert_eq!(1, num_deleted);
+  }
+}
@@ -13,12 +13,14 @@ use lemmy_utils::{
   LemmyError,
 };
 use rate_limiter::{RateLimitType, RateLimiter};
+use serde::Serialize;
 use std::{
   future::Future,
   pin::Pin,
   sync::Arc,
   task::{Context, Poll},
 };
+use strum::IntoEnumIterator;
 use tokio::sync::Mutex;
 
 pub mod rate_limiter;
@@ -30,6 +32,15 @@ pub struct RateLimit {
   pub rate_limiter: Arc<Mutex<RateLimiter>>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitAllowance {
+  pub type_: String,
+  pub rate: i32,
+  pub per_second: i32,
+  pub burst: i32,
+  pub remaining: i32,
+}
+
 #[derive(Debug, Clone)]
 pub struct RateLimited {
   rate_limiter: Arc<Mutex<RateLimiter>>,
@@ -58,6 +69,32 @@ impl RateLimit {
     self.kind(RateLimitType::Image)
   }
 
+  pub fn comment(&self) -> RateLimited {
+    self.kind(RateLimitType::Comment)
+  }
+
+  pub fn search(&self) -> RateLimited {
+    self.kind(RateLimitType::Search)
+  }
+
+  /// The remaining allowance of every bucket, for the given IP.
+  pub async fn allowances(&self, ip_addr: &str) -> Vec<RateLimitAllowance> {
+    let rate_limit: RateLimitConfig = Settings::get().rate_limit;
+    let limiter = self.rate_limiter.lock().await;
+    RateLimitType::iter()
+      .map(|type_| {
+        let config = type_.bucket_config(&rate_limit);
+        RateLimitAllowance {
+          type_: type_.as_ref().to_lowercase(),
+          rate: config.rate,
+          per_second: config.per_second,
+          burst: config.burst,
+          remaining: limiter.remaining(type_, ip_addr, config),
+        }
+      })
+      .collect()
+  }
+
   fn kind(&self, type_: RateLimitType) -> RateLimited {
     RateLimited {
       rate_limiter: self.rate_limiter.clone(),
@@ -78,82 +115,26 @@ impl RateLimited {
     // Does not need to be blocking because the RwLock in settings never held across await points,
     // and the operation here locks only long enough to clone
     let rate_limit: RateLimitConfig = Settings::get().rate_limit;
+    let config = self.type_.bucket_config(&rate_limit);
+
+    // Posts and registrations only count if they succeed
+    let count_after = match self.type_ {
+      RateLimitType::Post | RateLimitType::Register => true,
+      _ => false,
+    };
 
     // before
     {
       let mut limiter = self.rate_limiter.lock().await;
-
-      match self.type_ {
-        RateLimitType::Message => {
-          limiter.check_rate_limit_full(
-            self.type_,
-            &ip_addr,
-            rate_limit.message,
-            rate_limit.message_per_second,
-            false,
-          )?;
-
-          drop(limiter);
-          return fut.await;
-        }
-        RateLimitType::Post => {
-          limiter.check_rate_limit_full(
-            self.type_,
-            &ip_addr,
-            rate_limit.post,
-            rate_limit.post_per_second,
-            true,
-          )?;
-        }
-        RateLimitType::Register => {
-          limiter.check_rate_limit_full(
-            self.type_,
-            &ip_addr,
-            rate_limit.register,
-            rate_limit.register_per_second,
-            true,
-          )?;
-        }
-        RateLimitType::Image => {
-          limiter.check_rate_limit_full(
-            self.type_,
-            &ip_addr,
-            rate_limit.image,
-            rate_limit.image_per_second,
-            false,
-          )?;
-        }
-      };
+      limiter.check_rate_limit_full(self.type_, &ip_addr, config, count_after)?;
     }
 
     let res = fut.await;
 
     // after
-    {
+    if count_after && res.is_ok() {
       let mut limiter = self.rate_limiter.lock().await;
-      if res.is_ok() {
-        match self.type_ {
-          RateLimitType::Post => {
-            limiter.check_rate_limit_full(
-              self.type_,
-              &ip_addr,
-              rate_limit.post,
-              rate_limit.post_per_second,
-              false,
-            )?;
-          }
-          RateLimitType::Register => {
-            limiter.check_rate_limit_full(
-              self.type_,
-              &ip_addr,
-              rate_limit.register,
-              rate_limit.register_per_second,
-              false,
-            )?;
-          }
-          _ => (),
-        };
-      }
+      limiter.check_rate_limit_full(self.type_, &ip_addr, config, false)?;
     }
 
     res
@@ -1,6 +1,15 @@
-use lemmy_utils::{APIError, IPAddr, LemmyError};
+use lemmy_utils::{
+  settings::{RateLimitConfig, Settings},
+  utils::loggable_ip,
+  APIError,
+  IPAddr,
+  LemmyError,
+};
 use log::debug;
-use std::{collections::HashMap, time::SystemTime};
+use std::{
+  collections::HashMap,
+  time::{Duration, SystemTime},
+};
 use strum::IntoEnumIterator;
 
 #[derive(Debug, Clone)]
@@ -15,23 +24,81 @@ pub enum RateLimitType {
   Register,
   Post,
   Image,
+  Comment,
+  Search,
 }
 
+/// How fast a bucket refills, and how much it can hold.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitBucketConfig {
+  pub rate: i32,
+  pub per_second: i32,
+  pub burst: i32,
+}
+
+impl RateLimitType {
+  pub fn bucket_config(self, config: &RateLimitConfig) -> RateLimitBucketConfig {
+    let (rate, per_second, burst) = match self {
+      RateLimitType::Message => (
+        config.message,
+        config.message_per_second,
+        config.message_burst,
+      ),
+      RateLimitType::Register => (
+        config.register,
+        config.register_per_second,
+        config.register_burst,
+      ),
+      RateLimitType::Post => (config.post, config.post_per_second, config.post_burst),
+      RateLimitType::Image => (config.image, config.image_per_second, config.image_burst),
+      RateLimitType::Comment => (
+        config.comment,
+        config.comment_per_second,
+        config.comment_burst,
+      ),
+      RateLimitType::Search => (config.search, config.search_per_second, config.search_burst),
+    };
+    RateLimitBucketConfig {
+      rate,
+      per_second,
+      burst,
+    }
+  }
+}
+
+/// How often addresses past their retention are forgotten.
+const PURGE_INTERVAL: Duration = Duration::from_secs(600);
+
 /// Rate limiting based on rate type and IP addr
 #[derive(Debug, Clone)]
 pub struct RateLimiter {
   pub buckets: HashMap<RateLimitType, HashMap<IPAddr, RateLimitBucket>>,
+  last_purged: SystemTime,
 }
 
 impl Default for RateLimiter {
   fn default() -> Self {
     Self {
       buckets: HashMap::<RateLimitType, HashMap<IPAddr, RateLimitBucket>>::new(),
+      last_purged: SystemTime::now(),
     }
   }
 }
 
 impl RateLimiter {
+  /// Forgets the addresses which weren't seen during the retention period of the config.
+  fn purge_expired(&mut self) {
+    if self.last_purged.elapsed().unwrap_or_default() < PURGE_INTERVAL {
+      return;
+    }
+    self.last_purged = SystemTime::now();
+
+    let retention = Duration::from_secs(Settings::get().ip_addresses.retention_hours * 3600);
+    for bucket in self.buckets.values_mut() {
+      bucket.retain(|_, b| b.last_checked.elapsed().unwrap_or_default() < retention);
+    }
+  }
+
   fn insert_ip(&mut self, ip: &str) {
     for rate_limit_type in RateLimitType::iter() {
       if self.buckets.get(&rate_limit_type).is_none() {
@@ -57,10 +124,10 @@ impl RateLimiter {
     &mut self,
     type_: RateLimitType,
     ip: &str,
-    rate: i32,
-    per: i32,
+    config: RateLimitBucketConfig,
     check_only: bool,
   ) -> Result<(), LemmyError> {
+    self.purge_expired();
     self.insert_ip(ip);
     if let Some(bucket) = self.buckets.get_mut(&type_) {
       if let Some(rate_limit) = bucket.get_mut(ip) {
@@ -69,20 +136,20 @@ impl RateLimiter {
 
         // The initial value
         if rate_limit.allowance == -2f64 {
-          rate_limit.allowance = rate as f64;
+          rate_limit.allowance = config.burst as f64;
         };
 
         rate_limit.last_checked = current;
-        rate_limit.allowance += time_passed * (rate as f64 / per as f64);
-        if !check_only && rate_limit.allowance > rate as f64 {
-          rate_limit.allowance = rate as f64;
+        rate_limit.allowance += time_passed * (config.rate as f64 / config.per_second as f64);
+        if !check_only && rate_limit.allowance > config.burst as f64 {
+          rate_limit.allowance = config.burst as f64;
         }
 
         if rate_limit.allowance < 1.0 {
           debug!(
             "Rate limited type: {}, IP: {}, time_passed: {}, allowance: {}",
             type_.as_ref(),
-            ip,
+            loggable_ip(ip),
             time_passed,
             rate_limit.allowance
           );
@@ -91,9 +158,9 @@ impl RateLimiter {
               message: format!(
                 "Too many requests. type: {}, IP: {}, {} per {} seconds",
                 type_.as_ref(),
-                ip,
-                rate,
-                per
+                loggable_ip(ip),
+                config.rate,
+                config.per_second
               ),
             }
             .into(),
@@ -111,4 +178,22 @@ impl RateLimiter {
       Ok(())
     }
   }
+
+  /// How many requests of the given type the IP can still make right now, without changing
+  /// anything.
+  #[allow(clippy::float_cmp)]
+  pub fn remaining(&self, type_: RateLimitType, ip: &str, config: RateLimitBucketConfig) -> i32 {
+    let rate_limit = match self.buckets.get(&type_).and_then(|b| b.get(ip)) {
+      Some(rate_limit) if rate_limit.allowance != -2f64 => rate_limit,
+      _ => return config.burst,
+    };
+
+    let time_passed = SystemTime::now()
+      .duration_since(rate_limit.last_checked)
+      .map(|d| d.as_secs() as f64)
+      .unwrap_or(0f64);
+    let allowance =
+      rate_limit.allowance + time_passed * (config.rate as f64 / config.per_second as f64);
+    allowance.min(config.burst as f64).max(0f64) as i32
+  }
 }
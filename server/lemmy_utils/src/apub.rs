@@ -1,4 +1,4 @@
-use crate::settings::Settings;
+use crate::{settings::Settings, utils::replace_base_url};
 use openssl::{pkey::PKey, rsa::Rsa};
 use std::io::{Error, ErrorKind};
 use url::Url;
@@ -43,6 +43,18 @@ pub fn get_apub_protocol_string() -> &'static str {
   }
 }
 
+/// Rewrites links to the instance for clients of its onion service, which is served over plain http
+/// as tor encrypts the connection itself. Ids in the database and in federation always keep the
+/// canonical hostname.
+pub fn rewrite_links_for_onion(text: &str, onion_hostname: &str) -> String {
+  let canonical = format!(
+    "{}://{}",
+    get_apub_protocol_string(),
+    Settings::get().hostname
+  );
+  replace_base_url(text, &canonical, &format!("http://{}", onion_hostname))
+}
+
 /// Generates the ActivityPub ID for a given object type and ID.
 pub fn make_apub_endpoint(endpoint_type: EndpointType, name: &str) -> Url {
   let point = match endpoint_type {
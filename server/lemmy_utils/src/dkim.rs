@@ -0,0 +1,134 @@
+//! DKIM signatures (RFC 6376) for the emails of the instance. Headers and body use the relaxed
+//! canonicalization, so that the signature survives the usual whitespace changes by mail servers.
+use openssl::{
+  base64::encode_block,
+  error::ErrorStack,
+  hash::{hash, MessageDigest},
+  pkey::PKey,
+  sign::Signer,
+};
+
+/// The headers which are signed, if the email has them.
+const SIGNED_HEADERS: [&str; 8] = [
+  "from",
+  "to",
+  "subject",
+  "date",
+  "message-id",
+  "reply-to",
+  "mime-version",
+  "content-type",
+];
+
+/// Replaces every run of spaces and tabs with a single space.
+fn compress_whitespace(text: &str) -> String {
+  let mut compressed = String::with_capacity(text.len());
+  let mut in_whitespace = false;
+  for c in text.chars() {
+    if c == ' ' || c == '\t' {
+      if !in_whitespace {
+        compressed.push(' ');
+      }
+      in_whitespace = true;
+    } else {
+      compressed.push(c);
+      in_whitespace = false;
+    }
+  }
+  compressed
+}
+
+/// The body as it is hashed: whitespace runs become a single space, whitespace at the end of lines
+/// and empty lines at the end of the body are removed.
+pub fn canonicalize_body_relaxed(body: &str) -> String {
+  let mut lines = body
+    .split("\r\n")
+    .map(|l| compress_whitespace(l).trim_end().to_owned())
+    .collect::<Vec<String>>();
+  while lines.last().map_or(false, |l| l.is_empty()) {
+    lines.pop();
+  }
+  lines.iter().map(|l| format!("{}\r\n", l)).collect()
+}
+
+/// A header as it is signed, without the line break: the name is lowercased, the value unfolded
+/// and its whitespace compressed.
+pub fn canonicalize_header_relaxed(name: &str, value: &str) -> String {
+  let value = value.replace("\r\n", "");
+  format!(
+    "{}:{}",
+    name.trim().to_lowercase(),
+    compress_whitespace(&value).trim()
+  )
+}
+
+/// Splits the email into its headers and its body. Folded header lines are joined to the header
+/// they continue, with their line breaks.
+fn split_message(message: &str) -> (Vec<(String, String)>, &str) {
+  let (head, body) = match message.find("\r\n\r\n") {
+    Some(i) => (&message[..i], &message[i + 4..]),
+    None => (message, ""),
+  };
+  let mut headers: Vec<(String, String)> = vec![];
+  for line in head.split("\r\n") {
+    if line.starts_with(' ') || line.starts_with('\t') {
+      if let Some((_, value)) = headers.last_mut() {
+        value.push_str("\r\n");
+        value.push_str(line);
+      }
+    } else if let Some(i) = line.find(':') {
+      headers.push((line[..i].to_owned(), line[i + 1..].to_owned()));
+    }
+  }
+  (headers, body)
+}
+
+/// The DKIM-Signature header of the email, without a line break, to put in front of it.
+/// `timestamp` is when it was signed, in unix time.
+pub fn dkim_signature(
+  message: &str,
+  domain: &str,
+  selector: &str,
+  private_key_pem: &[u8],
+  timestamp: i64,
+) -> Result<String, ErrorStack> {
+  let (headers, body) = split_message(message);
+  let body_hash = encode_block(&hash(
+    MessageDigest::sha256(),
+    canonicalize_body_relaxed(body).as_bytes(),
+  )?);
+
+  // If a header appears more than once, the last one is signed
+  let signed_headers = SIGNED_HEADERS
+    .iter()
+    .filter_map(|name| {
+      headers
+        .iter()
+        .rev()
+        .find(|(n, _)| n.trim().eq_ignore_ascii_case(name))
+    })
+    .collect::<Vec<&(String, String)>>();
+  let header_names = signed_headers
+    .iter()
+    .map(|(n, _)| n.trim().to_lowercase())
+    .collect::<Vec<String>>()
+    .join(":");
+  let value = format!(
+    " v=1; a=rsa-sha256; c=relaxed/relaxed; d={}; s={}; t={}; h={}; bh={}; b=",
+    domain, selector, timestamp, header_names, body_hash
+  );
+
+  // The signature covers the signed headers and its own header, with an empty b= tag
+  let mut data = String::new();
+  for (name, header_value) in signed_headers {
+    data.push_str(&canonicalize_header_relaxed(name, header_value));
+    data.push_str("\r\n");
+  }
+  data.push_str(&canonicalize_header_relaxed("DKIM-Signature", &value));
+
+  let key = PKey::private_key_from_pem(private_key_pem)?;
+  let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+  signer.update(data.as_bytes())?;
+  let signature = encode_block(&signer.sign_to_vec()?);
+  Ok(format!("DKIM-Signature:{}{}", value, signature))
+}
@@ -1,37 +1,106 @@
-use crate::settings::Settings;
+use crate::{
+  dkim::dkim_signature,
+  settings::{DkimConfig, Settings},
+};
 use lettre::{
   smtp::{
     authentication::{Credentials, Mechanism},
+    error::Error as SmtpError,
     extension::ClientId,
     ConnectionReuseParameters,
   },
   ClientSecurity,
+  SendableEmail,
   SmtpClient,
   Transport,
 };
 use lettre_email::Email;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde::Serialize;
+
+/// Why an email couldn't be sent, as the email outbox records it and the SMTP test reports it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmailError {
+  /// not_configured, invalid_message, dkim, connection, rejected or other
+  pub kind: String,
+  /// The reply code of the SMTP server, if it rejected the email
+  pub code: Option<String>,
+  pub message: String,
+  /// Whether sending it again won't help, eg because the server doesn't accept the address
+  pub permanent: bool,
+}
+
+impl EmailError {
+  fn new(kind: &str, message: &str, permanent: bool) -> Self {
+    EmailError {
+      kind: kind.to_owned(),
+      code: None,
+      message: message.to_owned(),
+      permanent,
+    }
+  }
+
+  fn from_smtp(e: SmtpError) -> Self {
+    match e {
+      SmtpError::Transient(ref r) | SmtpError::Permanent(ref r) => EmailError {
+        kind: "rejected".to_owned(),
+        code: Some(r.code.to_string()),
+        message: r.message.join(" "),
+        permanent: matches!(e, SmtpError::Permanent(_)),
+      },
+      SmtpError::Io(_) | SmtpError::Resolution => {
+        EmailError::new("connection", &e.to_string(), false)
+      }
+      _ => EmailError::new("other", &e.to_string(), false),
+    }
+  }
+}
 
+impl std::fmt::Display for EmailError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self.code {
+      Some(code) => write!(f, "{} {}: {}", self.kind, code, self.message),
+      None => write!(f, "{}: {}", self.kind, self.message),
+    }
+  }
+}
+
+/// Sends the email right away. Notifications go through the email outbox instead, which retries
+/// them.
 pub fn send_email(
   subject: &str,
   to_email: &str,
   to_username: &str,
   html: &str,
-) -> Result<(), String> {
-  let email_config = Settings::get().email.ok_or("no_email_setup")?;
+  reply_to: Option<&str>,
+) -> Result<(), EmailError> {
+  let email_config = Settings::get()
+    .email
+    .ok_or_else(|| EmailError::new("not_configured", "no_email_setup", true))?;
 
-  let email = Email::builder()
+  let mut builder = Email::builder()
     .to((to_email, to_username))
     .from(email_config.smtp_from_address.to_owned())
     .subject(subject)
-    .html(html)
+    .html(html);
+  if let Some(reply_to) = reply_to {
+    builder = builder.reply_to(reply_to);
+  }
+  let email: SendableEmail = builder
     .build()
-    .unwrap();
+    .map_err(|e| EmailError::new("invalid_message", &e.to_string(), true))?
+    .into();
+  let email = match &email_config.dkim {
+    Some(dkim) => sign_with_dkim(email, dkim)?,
+    None => email,
+  };
 
   let mailer = if email_config.use_tls {
-    SmtpClient::new_simple(&email_config.smtp_server).unwrap()
+    SmtpClient::new_simple(&email_config.smtp_server)
   } else {
-    SmtpClient::new(&email_config.smtp_server, ClientSecurity::None).unwrap()
+    SmtpClient::new(&email_config.smtp_server, ClientSecurity::None)
   }
+  .map_err(EmailError::from_smtp)?
   .hello_name(ClientId::Domain(Settings::get().hostname))
   .smtp_utf8(true)
   .authentication_mechanism(Mechanism::Plain)
@@ -45,11 +114,216 @@ pub fn send_email(
   };
 
   let mut transport = mailer.transport();
-  let result = transport.send(email.into());
+  let result = transport.send(email);
   transport.close();
 
-  match result {
-    Ok(_) => Ok(()),
-    Err(e) => Err(e.to_string()),
+  result.map(|_| ()).map_err(EmailError::from_smtp)
+}
+
+/// Puts the DKIM-Signature header in front of the email. The key is read for every email, so that
+/// it can be rotated without a restart.
+fn sign_with_dkim(email: SendableEmail, dkim: &DkimConfig) -> Result<SendableEmail, EmailError> {
+  let envelope = email.envelope().clone();
+  let message_id = email.message_id().to_owned();
+  let message = email
+    .message_to_string()
+    .map_err(|e| EmailError::new("invalid_message", &e.to_string(), true))?;
+  let private_key = std::fs::read(&dkim.private_key_file)
+    .map_err(|e| EmailError::new("dkim", &e.to_string(), false))?;
+  let signature = dkim_signature(
+    &message,
+    &dkim.domain,
+    &dkim.selector,
+    &private_key,
+    chrono::Utc::now().timestamp(),
+  )
+  .map_err(|e| EmailError::new("dkim", &e.to_string(), false))?;
+  let signed = format!("{}\r\n{}", signature, message);
+  Ok(SendableEmail::new(
+    envelope,
+    message_id,
+    signed.into_bytes(),
+  ))
+}
+
+/// What a reply to a notification email answers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplyTarget {
+  /// Becomes a top level comment on the post
+  Post(i32),
+  /// Becomes a reply to the comment
+  Comment(i32),
+  /// Becomes a private message to the user
+  PrivateMessage(i32),
+}
+
+impl ReplyTarget {
+  fn tag(self) -> String {
+    match self {
+      ReplyTarget::Post(id) => format!("p{}", id),
+      ReplyTarget::Comment(id) => format!("c{}", id),
+      ReplyTarget::PrivateMessage(id) => format!("m{}", id),
+    }
   }
+
+  fn from_tag(tag: &str) -> Option<ReplyTarget> {
+    let id = tag.get(1..)?.parse().ok()?;
+    match tag.get(..1)? {
+      "p" => Some(ReplyTarget::Post(id)),
+      "c" => Some(ReplyTarget::Comment(id)),
+      "m" => Some(ReplyTarget::PrivateMessage(id)),
+      _ => None,
+    }
+  }
+}
+
+/// How long the address of a notification can be replied to.
+pub const REPLY_ADDRESS_DAYS: i64 = 30;
+
+/// The address for the user to reply to a notification about the target, or none if replying by
+/// email isn't set up. The tag is signed, so that only the user it was sent to can reply as
+/// themselves, and it expires after `REPLY_ADDRESS_DAYS`.
+pub fn reply_address(target: ReplyTarget, user_id: i32) -> Option<String> {
+  let reply_address = Settings::get().email?.reply_address?;
+  sign_reply_address(&reply_address, target, user_id, current_day())
+}
+
+/// The days since the unix epoch, which reply addresses are dated with.
+pub fn current_day() -> i64 {
+  chrono::Utc::now().timestamp() / 86_400
+}
+
+/// The reply address with the signed tag of the target, the user and the day it was sent on.
+pub fn sign_reply_address(
+  reply_address: &str,
+  target: ReplyTarget,
+  user_id: i32,
+  day: i64,
+) -> Option<String> {
+  let (local_part, domain) = split_address(reply_address)?;
+  let tag = format!("{}-{}-{}", target.tag(), user_id, day);
+  let signature = sign_tag(&tag)?;
+  Some(format!("{}+{}-{}@{}", local_part, tag, signature, domain))
+}
+
+/// The target and the user of a reply address, if it was signed by this instance and didn't
+/// expire by `today`.
+pub fn parse_reply_address(address: &str, today: i64) -> Option<(ReplyTarget, i32)> {
+  let (local_part, _domain) = split_address(address)?;
+  // Mail servers may change the case of the local part
+  let local_part = local_part.to_lowercase();
+  let mut parts = local_part.splitn(2, '+').nth(1)?.split('-');
+  let (target, user_id, day, signature) =
+    (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+  if parts.next().is_some() {
+    return None;
+  }
+  let tag = format!("{}-{}-{}", target, user_id, day);
+  if !check_signature(&tag, signature) {
+    return None;
+  }
+  let day: i64 = day.parse().ok()?;
+  if today - day > REPLY_ADDRESS_DAYS {
+    return None;
+  }
+  Some((ReplyTarget::from_tag(target)?, user_id.parse().ok()?))
+}
+
+fn split_address(address: &str) -> Option<(&str, &str)> {
+  // A full address like `Name <reply@example.com>`
+  let address = match (address.rfind('<'), address.rfind('>')) {
+    (Some(start), Some(end)) if start < end => &address[start + 1..end],
+    _ => address,
+  };
+  let at = address.rfind('@')?;
+  Some((address[..at].trim(), address[at + 1..].trim()))
+}
+
+fn check_signature(tag: &str, signature: &str) -> bool {
+  let signature = signature.to_lowercase();
+  match sign_tag(tag) {
+    Some(expected) => {
+      expected.len() == signature.len()
+        && openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes())
+    }
+    None => false,
+  }
+}
+
+/// The first 80 bits of the hmac of the tag, in lowercase hex.
+fn sign_tag(tag: &str) -> Option<String> {
+  let key = PKey::hmac(Settings::get().jwt_secret.as_bytes()).ok()?;
+  let mut signer = Signer::new(MessageDigest::sha256(), &key).ok()?;
+  signer.update(tag.as_bytes()).ok()?;
+  Some(
+    signer
+      .sign_to_vec()
+      .ok()?
+      .iter()
+      .take(10)
+      .map(|b| format!("{:02x}", b))
+      .collect(),
+  )
+}
+
+/// Whether both addresses are the same, ignoring the names and the case.
+pub fn same_address(a: &str, b: &str) -> bool {
+  match (split_address(a), split_address(b)) {
+    (Some((a_local, a_domain)), Some((b_local, b_domain))) => {
+      a_local.eq_ignore_ascii_case(b_local) && a_domain.eq_ignore_ascii_case(b_domain)
+    }
+    _ => false,
+  }
+}
+
+/// The new text of a reply to an email, without the quoted email and the signature below it.
+pub fn strip_quoted_reply(text: &str) -> String {
+  let mut lines = Vec::new();
+  for line in text.lines() {
+    let trimmed = line.trim();
+    let is_quote_header = trimmed.starts_with("On ") && trimmed.ends_with("wrote:");
+    if trimmed.starts_with('>') || is_quote_header || line == "-- " {
+      break;
+    }
+    lines.push(line);
+  }
+  lines.join("\n").trim().to_owned()
+}
+
+/// In characters.
+pub const MAX_DIGEST_TEMPLATE_LENGTH: usize = 2000;
+
+pub const DEFAULT_DIGEST_TEMPLATE: &str =
+  "The top posts in {community} this week:\n\n{top_posts}\n\n{new_members} new members joined.";
+
+/// Fills in the markdown of a weekly digest. The placeholders are `{community}`, `{top_posts}`, a
+/// list of links to the posts, and `{new_members}`, the number of new followers.
+pub fn render_digest_template(
+  template: &str,
+  community: &str,
+  top_posts: &str,
+  new_members: i64,
+) -> String {
+  template
+    .replace("{community}", community)
+    .replace("{new_members}", &new_members.to_string())
+    .replace("{top_posts}", top_posts)
+}
+
+/// The path of the link which stops the digest of the community for the user, signed so that it
+/// works without logging in.
+pub fn digest_unsubscribe_path(user_id: i32, community_id: i32) -> Option<String> {
+  let signature = sign_tag(&digest_tag(user_id, community_id))?;
+  Some(format!(
+    "/digest/unsubscribe/{}/{}/{}",
+    user_id, community_id, signature
+  ))
+}
+
+pub fn check_digest_unsubscribe(user_id: i32, community_id: i32, signature: &str) -> bool {
+  check_signature(&digest_tag(user_id, community_id), signature)
+}
+
+fn digest_tag(user_id: i32, community_id: i32) -> String {
+  format!("digest-{}-{}", user_id, community_id)
 }
@@ -0,0 +1,78 @@
+//! HyperLogLog sketches, which estimate how many different items were added to them, within a few
+//! percent, in a kilobyte whatever the number of items. Items are hashed with a secret salt, and
+//! each register only keeps the longest run of zeros of the hashes which fell into it, so the
+//! items can't be told from a sketch. Sketches merge by keeping the larger value of each register.
+
+/// The first bits of a hash pick the register.
+const PRECISION: u32 = 10;
+/// A byte each, for an error of about 3%.
+pub const HYPERLOGLOG_REGISTERS: usize = 1 << PRECISION;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+  registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+  fn default() -> Self {
+    HyperLogLog {
+      registers: vec![0; HYPERLOGLOG_REGISTERS],
+    }
+  }
+}
+
+impl HyperLogLog {
+  /// None if there are too few or too many registers.
+  pub fn from_registers(registers: Vec<u8>) -> Option<Self> {
+    if registers.len() != HYPERLOGLOG_REGISTERS {
+      return None;
+    }
+    Some(HyperLogLog { registers })
+  }
+
+  pub fn registers(&self) -> &[u8] {
+    &self.registers
+  }
+
+  pub fn insert(&mut self, item: &str, salt: &str) {
+    let digest = openssl::sha::sha256(format!("{}\n{}", salt, item).as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    let hash = u64::from_be_bytes(bytes);
+
+    let register = (hash >> (64 - PRECISION)) as usize;
+    // The marker bit stops the count at the end of the remaining bits
+    let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+    let rank = rest.leading_zeros() as u8 + 1;
+    if rank > self.registers[register] {
+      self.registers[register] = rank;
+    }
+  }
+
+  pub fn merge(&mut self, other: &HyperLogLog) {
+    for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+      if *other > *register {
+        *register = *other;
+      }
+    }
+  }
+
+  /// Small counts are estimated from the number of empty registers, which is more precise.
+  pub fn count(&self) -> u64 {
+    let m = HYPERLOGLOG_REGISTERS as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let sum: f64 = self
+      .registers
+      .iter()
+      .map(|r| 2f64.powi(-i32::from(*r)))
+      .sum();
+    let estimate = alpha * m * m / sum;
+
+    let empty = self.registers.iter().filter(|r| **r == 0).count();
+    if estimate <= 2.5 * m && empty > 0 {
+      (m * (m / empty as f64).ln()).round() as u64
+    } else {
+      estimate.round() as u64
+    }
+  }
+}
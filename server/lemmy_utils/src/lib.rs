@@ -2,6 +2,8 @@
 extern crate lazy_static;
 extern crate actix_web;
 extern crate anyhow;
+extern crate argon2;
+extern crate bcrypt;
 extern crate comrak;
 extern crate lettre;
 extern crate lettre_email;
@@ -13,14 +15,20 @@ extern crate thiserror;
 extern crate url;
 
 pub mod apub;
+pub mod dkim;
 pub mod email;
+pub mod hyperloglog;
+pub mod password;
+pub mod schedule;
 pub mod settings;
 #[cfg(test)]
 mod test;
 pub mod utils;
+pub mod web_push;
 
 use crate::settings::Settings;
 use regex::Regex;
+use serde::Serialize;
 use thiserror::Error;
 
 pub type ConnectionId = usize;
@@ -55,6 +63,87 @@ impl APIError {
   }
 }
 
+/// Returned for everything that writes to the database while an admin has made the site read
+/// only. Http requests get it with status 503.
+#[derive(Debug)]
+pub struct ReadOnlyError {
+  pub message: Option<String>,
+}
+
+impl std::fmt::Display for ReadOnlyError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let json = serde_json::json!({ "error": "read_only", "message": self.message });
+    write!(f, "{}", json)
+  }
+}
+
+impl std::error::Error for ReadOnlyError {}
+
+impl actix_web::error::ResponseError for ReadOnlyError {
+  fn status_code(&self) -> actix_web::http::StatusCode {
+    actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+  }
+
+  fn error_response(&self) -> actix_web::HttpResponse {
+    actix_web::HttpResponse::ServiceUnavailable()
+      .content_type("application/json")
+      .body(self.to_string())
+  }
+}
+
+/// Content which breaks one or more length limits. Each violated limit is listed, so that clients
+/// can show them next to the fields. Http requests get it with status 400.
+#[derive(Debug)]
+pub struct ValidationError {
+  pub violations: Vec<LengthViolation>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct LengthViolation {
+  pub field: String,
+  /// In characters
+  pub max: usize,
+  pub length: usize,
+}
+
+impl std::fmt::Display for ValidationError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let json = serde_json::json!({ "error": "validation_failed", "violations": self.violations });
+    write!(f, "{}", json)
+  }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl actix_web::error::ResponseError for ValidationError {
+  fn status_code(&self) -> actix_web::http::StatusCode {
+    actix_web::http::StatusCode::BAD_REQUEST
+  }
+
+  fn error_response(&self) -> actix_web::HttpResponse {
+    actix_web::HttpResponse::BadRequest()
+      .content_type("application/json")
+      .body(self.to_string())
+  }
+}
+
+/// A new post which breaks rules of its community, like its allowed post type or title pattern.
+/// Its message names every broken rule rather than only the first one, so that clients can explain
+/// them all at once.
+#[derive(Debug)]
+pub struct SubmissionRulesError {
+  pub rules: Vec<String>,
+}
+
+impl std::fmt::Display for SubmissionRulesError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let json = serde_json::json!({ "error": "submission_rules_violated", "rules": self.rules });
+    write!(f, "{}", json)
+  }
+}
+
+impl std::error::Error for SubmissionRulesError {}
+
 #[derive(Debug)]
 pub struct LemmyError {
   inner: anyhow::Error,
@@ -90,4 +179,8 @@ lazy_static! {
   .unwrap();
   pub static ref CACHE_CONTROL_REGEX: Regex =
     Regex::new("^((text|image)/.+|application/javascript)$").unwrap();
+  /// Responses with links for clients, as opposed to federation responses like
+  /// `application/activity+json`
+  pub static ref CLIENT_CONTENT_TYPE_REGEX: Regex =
+    Regex::new("^(application/json|application/rss\\+xml|text/html)").unwrap();
 }
@@ -0,0 +1,104 @@
+//! Password hashing and the password policy. New hashes are argon2id, and bcrypt hashes from older
+//! versions are still accepted until their users log in again.
+use crate::{settings::PasswordConfig, APIError};
+use argon2::{ThreadMode, Variant, Version};
+use rand::{thread_rng, RngCore};
+
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: u32 = 32;
+
+/// Common passwords which would pass the other checks.
+const COMMON_PASSWORDS: &[&str] = &[
+  "password1",
+  "password12",
+  "password123",
+  "password1234",
+  "passw0rd123",
+  "qwerty1234",
+  "qwerty12345",
+  "qwertyuiop1",
+  "1q2w3e4r5t",
+  "1qaz2wsx3edc",
+  "abcdef1234",
+  "abc1234567",
+  "iloveyou123",
+  "letmein123",
+  "welcome123",
+  "changeme123",
+  "lemmylemmy1",
+];
+
+fn argon2_config(config: &PasswordConfig) -> argon2::Config {
+  argon2::Config {
+    variant: Variant::Argon2id,
+    version: Version::Version13,
+    mem_cost: config.memory_kib,
+    time_cost: config.iterations,
+    lanes: config.parallelism,
+    thread_mode: ThreadMode::Sequential,
+    secret: &[],
+    ad: &[],
+    hash_length: HASH_LENGTH,
+  }
+}
+
+pub fn hash_password(password: &str, config: &PasswordConfig) -> Result<String, argon2::Error> {
+  let mut salt = [0u8; SALT_LENGTH];
+  thread_rng().fill_bytes(&mut salt);
+  argon2::hash_encoded(password.as_bytes(), &salt, &argon2_config(config))
+}
+
+/// Checks the password against an argon2 or a bcrypt hash. Anything else never matches.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+  if hash.starts_with("$argon2") {
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+  } else if hash.starts_with("$2") {
+    bcrypt::verify(password, hash).unwrap_or(false)
+  } else {
+    false
+  }
+}
+
+/// True for bcrypt hashes, and for argon2 hashes made with other parameters than the configured
+/// ones.
+pub fn needs_rehash(hash: &str, config: &PasswordConfig) -> bool {
+  let prefix = format!(
+    "$argon2id$v=19$m={},t={},p={}$",
+    config.memory_kib, config.iterations, config.parallelism
+  );
+  !hash.starts_with(&prefix)
+}
+
+/// The password policy for new passwords, with the length in characters, the number of character
+/// classes, and no passwords which are common or contain the username.
+pub fn check_password(
+  password: &str,
+  username: &str,
+  config: &PasswordConfig,
+) -> Result<(), APIError> {
+  let length = password.chars().count();
+  if length < config.min_length {
+    return Err(APIError::err("password_too_short"));
+  }
+  if length > config.max_length {
+    return Err(APIError::err("password_too_long"));
+  }
+
+  let classes = [
+    password.chars().any(|c| c.is_lowercase()),
+    password.chars().any(|c| c.is_uppercase()),
+    password.chars().any(|c| c.is_numeric()),
+    password.chars().any(|c| !c.is_alphanumeric()),
+  ]
+  .iter()
+  .filter(|c| **c)
+  .count();
+  let lowercase = password.to_lowercase();
+  if classes < config.min_character_classes
+    || (!username.is_empty() && lowercase.contains(&username.to_lowercase()))
+    || COMMON_PASSWORDS.contains(&lowercase.as_str())
+  {
+    return Err(APIError::err("password_too_weak"));
+  }
+  Ok(())
+}
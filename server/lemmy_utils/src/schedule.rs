@@ -0,0 +1,139 @@
+//! Cron-like schedules, like `0 12 * * 1` for every Monday at noon. The fields are minute, hour,
+//! day of the month, month and day of the week (0 or 7 is Sunday), and each one is `*`, a number,
+//! a range like `1-5`, a step like `*/15` or `1-20/2`, or a list of those like `1,15`. Like in
+//! cron, a day matches if either the day of the month or the day of the week matches, when both
+//! are given. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` work too. All times are UTC.
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+  minutes: Vec<bool>,
+  hours: Vec<bool>,
+  days_of_month: Vec<bool>,
+  months: Vec<bool>,
+  days_of_week: Vec<bool>,
+  any_day_of_month: bool,
+  any_day_of_week: bool,
+}
+
+/// Every day of the month falls on every day of the week within this many days, Feb 29 being the
+/// slowest.
+const MAX_SEARCH_DAYS: i64 = 366 * 28;
+
+impl Schedule {
+  pub fn parse(schedule: &str) -> Option<Schedule> {
+    let schedule = match schedule.trim() {
+      "@hourly" => "0 * * * *",
+      "@daily" => "0 0 * * *",
+      "@weekly" => "0 0 * * 0",
+      "@monthly" => "0 0 1 * *",
+      "@yearly" => "0 0 1 1 *",
+      s => s,
+    };
+    let fields = schedule.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() != 5 {
+      return None;
+    }
+
+    let mut days_of_week = parse_field(fields[4], 0, 7)?;
+    // Sunday is both 0 and 7
+    if days_of_week[7] {
+      days_of_week[0] = true;
+    }
+    days_of_week.truncate(7);
+
+    Some(Schedule {
+      minutes: parse_field(fields[0], 0, 59)?,
+      hours: parse_field(fields[1], 0, 23)?,
+      days_of_month: parse_field(fields[2], 1, 31)?,
+      months: parse_field(fields[3], 1, 12)?,
+      days_of_week,
+      any_day_of_month: fields[2] == "*",
+      any_day_of_week: fields[4] == "*",
+    })
+  }
+
+  /// The first time after the given one, to the minute. None if it never comes, like on Feb 30.
+  pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+    let start = after.date().and_hms(after.hour(), after.minute(), 0) + Duration::minutes(1);
+    let mut date = start.date();
+    for _ in 0..MAX_SEARCH_DAYS {
+      if self.matches_date(date) {
+        let first_minute = if date == start.date() {
+          start.hour() * 60 + start.minute()
+        } else {
+          0
+        };
+        for minute_of_day in first_minute..(24 * 60) {
+          let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+          if self.hours[hour as usize] && self.minutes[minute as usize] {
+            return Some(date.and_hms(hour, minute, 0));
+          }
+        }
+      }
+      date = date.succ();
+    }
+    None
+  }
+
+  fn matches_date(&self, date: NaiveDate) -> bool {
+    if !self.months[date.month() as usize] {
+      return false;
+    }
+    let day_of_month = self.days_of_month[date.day() as usize];
+    let day_of_week = self.days_of_week[date.weekday().num_days_from_sunday() as usize];
+    match (self.any_day_of_month, self.any_day_of_week) {
+      (true, true) => true,
+      (true, false) => day_of_week,
+      (false, true) => day_of_month,
+      (false, false) => day_of_month || day_of_week,
+    }
+  }
+}
+
+/// Which values from 0 to max match, so that the values can be used as indexes.
+fn parse_field(field: &str, min: u32, max: u32) -> Option<Vec<bool>> {
+  let mut matches = vec![false; max as usize + 1];
+  for part in field.split(',') {
+    let (range, step) = match part.find('/') {
+      Some(i) => (&part[..i], part[i + 1..].parse::<u32>().ok()?),
+      None => (part, 1),
+    };
+    let (first, last) = if range == "*" {
+      (min, max)
+    } else if let Some(i) = range.find('-') {
+      (
+        range[..i].parse::<u32>().ok()?,
+        range[i + 1..].parse::<u32>().ok()?,
+      )
+    } else {
+      let value = range.parse::<u32>().ok()?;
+      // Like cron, `5/10` means from 5 to the end, every 10
+      if part.contains('/') {
+        (value, max)
+      } else {
+        (value, value)
+      }
+    };
+    if step == 0 || first < min || last > max || first > last {
+      return None;
+    }
+    for value in (first..=last).step_by(step as usize) {
+      matches[value as usize] = true;
+    }
+  }
+  Some(matches)
+}
+
+/// Fills in the date in titles like `Weekly discussion {date}`. The placeholders are `{date}`,
+/// `{year}`, `{month}`, `{month_name}`, `{day}`, `{weekday}` and `{week}`, the ISO week number.
+pub fn render_title_template(template: &str, at: NaiveDateTime) -> String {
+  template
+    .replace("{date}", &at.format("%Y-%m-%d").to_string())
+    .replace("{year}", &at.format("%Y").to_string())
+    .replace("{month_name}", &at.format("%B").to_string())
+    .replace("{month}", &at.format("%m").to_string())
+    .replace("{day}", &at.format("%d").to_string())
+    .replace("{weekday}", &at.format("%A").to_string())
+    .replace("{week}", &at.format("%V").to_string())
+}
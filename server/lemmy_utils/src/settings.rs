@@ -1,15 +1,22 @@
 use config::{Config, ConfigError, Environment, File};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{env, fs, io::Error, net::IpAddr, sync::RwLock};
 
 static CONFIG_FILE_DEFAULTS: &str = "config/defaults.hjson";
 static CONFIG_FILE: &str = "config/config.hjson";
+/// Written by admins through the api, takes precedence over CONFIG_FILE.
+static CONFIG_FILE_RATE_LIMIT: &str = "config/rate_limit.hjson";
+/// Written by admins through the api, like CONFIG_FILE_RATE_LIMIT.
+static CONFIG_FILE_MAINTENANCE: &str = "config/maintenance.hjson";
+/// Post titles are varchar(200) in the database.
+const MAX_POST_TITLE_COLUMN_LENGTH: usize = 200;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
   pub setup: Option<Setup>,
   pub database: DatabaseConfig,
   pub hostname: String,
+  pub onion_hostname: Option<String>,
   pub bind: IpAddr,
   pub port: u16,
   pub jwt_secret: String,
@@ -17,8 +24,23 @@ pub struct Settings {
   pub pictrs_url: String,
   pub rate_limit: RateLimitConfig,
   pub email: Option<EmailConfig>,
+  pub web_push: Option<WebPushConfig>,
   pub federation: FederationConfig,
   pub captcha: CaptchaConfig,
+  pub spam: SpamConfig,
+  pub trust: TrustConfig,
+  pub mentions: MentionConfig,
+  pub ip_addresses: IpAddressConfig,
+  pub outbound_requests: OutboundRequestConfig,
+  pub oembed: OembedConfig,
+  pub widget: WidgetConfig,
+  pub maintenance: MaintenanceConfig,
+  pub wasm_plugins: WasmPluginConfig,
+  pub compression: CompressionConfig,
+  pub limits: LimitsConfig,
+  pub impersonation: ImpersonationConfig,
+  pub passwords: PasswordConfig,
+  pub web_sessions: WebSessionConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -29,16 +51,26 @@ pub struct Setup {
   pub site_name: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RateLimitConfig {
   pub message: i32,
   pub message_per_second: i32,
+  pub message_burst: i32,
   pub post: i32,
   pub post_per_second: i32,
+  pub post_burst: i32,
   pub register: i32,
   pub register_per_second: i32,
+  pub register_burst: i32,
   pub image: i32,
   pub image_per_second: i32,
+  pub image_burst: i32,
+  pub comment: i32,
+  pub comment_per_second: i32,
+  pub comment_burst: i32,
+  pub search: i32,
+  pub search_per_second: i32,
+  pub search_burst: i32,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -48,6 +80,31 @@ pub struct EmailConfig {
   pub smtp_password: Option<String>,
   pub smtp_from_address: String,
   pub use_tls: bool,
+  /// Turns on replying to notification emails. Replies go to this address with a signed tag, like
+  /// `reply+c12-3-abcdef@your-instance.com`, and have to be passed on to the inbound email hook.
+  pub reply_address: Option<String>,
+  /// What the mail server sends along with the replies to the inbound email hook, as bearer token.
+  pub inbound_secret: Option<String>,
+  /// Signs the emails with DKIM, so that mail servers can check they come from the instance.
+  pub dkim: Option<DkimConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DkimConfig {
+  /// The domain which publishes the public key, usually the one of `smtp_from_address`
+  pub domain: String,
+  /// The public key is published at `{selector}._domainkey.{domain}`
+  pub selector: String,
+  /// The PEM file of the RSA private key
+  pub private_key_file: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebPushConfig {
+  /// The PEM file of the VAPID key, an EC private key on the curve prime256v1
+  pub private_key_file: String,
+  /// A mailto: or https: url where push services can reach the admin
+  pub subject: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -56,6 +113,161 @@ pub struct CaptchaConfig {
   pub difficulty: String, // easy, medium, or hard
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct SpamConfig {
+  pub enabled: bool,
+  pub report_threshold: f32,
+  pub remove_threshold: f32,
+  pub classifier_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TrustConfig {
+  pub min_account_age_days: i32,
+  pub min_karma: i32,
+  pub max_mod_actions: i64,
+  pub untrusted_posts_per_hour: i64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionLimitAction {
+  /// Only notify the first mentioned users
+  Truncate,
+  /// Notify nobody, and report the comment to the admins, who can release the mentions
+  Approve,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MentionConfig {
+  pub max_mentions: usize,
+  pub over_limit: MentionLimitAction,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OembedConfig {
+  pub enabled: bool,
+  pub include_nsfw: bool,
+  /// Characters of the post body which are shown in the embed
+  pub excerpt_length: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WidgetConfig {
+  pub enabled: bool,
+  /// Comma separated origins of the sites which may load widgets, or * for all of them
+  pub allowed_origins: String,
+  pub max_posts: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImpersonationConfig {
+  /// Lets admins view the site as another user. Turning it off also ends the running ones.
+  pub enabled: bool,
+  /// How long an impersonation token works
+  pub duration_minutes: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordConfig {
+  /// Argon2id memory cost in KiB. Hashes made with other parameters are redone at the next login.
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+  /// In characters
+  pub min_length: usize,
+  pub max_length: usize,
+  /// Passwords need at least this many of lowercase and uppercase letters, digits and symbols
+  pub min_character_classes: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebSessionConfig {
+  /// Lets browser clients keep their token in an HttpOnly cookie instead of sending it as auth
+  pub enabled: bool,
+  /// Only sends the cookies over https, which should only be turned off for local development
+  pub secure_cookies: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MaintenanceConfig {
+  /// Refuses everything that writes to the database, so that it can be maintained safely
+  pub read_only: bool,
+  /// Shown to users while the site is read only
+  pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WasmPluginConfig {
+  /// Where the .wasm modules are loaded from, only used when built with the wasm-plugins feature
+  pub directory: String,
+  /// How long one call to a plugin may run
+  pub timeout_ms: u64,
+  pub max_memory_mb: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompressionConfig {
+  pub enabled: bool,
+  /// Comma separated, out of zstd, br and gzip, the one the server prefers first
+  pub algorithms: String,
+  /// In bytes, smaller responses are sent as they are
+  pub min_size: usize,
+  /// Comma separated prefixes of the content types which are compressed
+  pub content_types: String,
+}
+
+/// In characters. Federated content which is longer is cut to fit.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LimitsConfig {
+  /// At most 200, the size of the column
+  pub max_post_title_length: usize,
+  pub max_post_body_length: usize,
+  pub max_comment_length: usize,
+}
+
+impl LimitsConfig {
+  pub fn post_title_length(&self) -> usize {
+    self.max_post_title_length.min(MAX_POST_TITLE_COLUMN_LENGTH)
+  }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpPolicy {
+  /// Keep the whole address
+  Full,
+  /// Keep the /24 for IPv4, the /48 for IPv6
+  Truncate,
+  /// Keep a hash with a rotating salt
+  Hash,
+  /// Like Hash, but never log it
+  Disabled,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IpAddressConfig {
+  pub policy: IpPolicy,
+  pub retention_hours: u64,
+  pub salt_rotation_hours: u64,
+  pub sign_in_retention_days: i64,
+  /// Path of a MaxMind country database, like GeoLite2-Country.mmdb
+  pub geoip_database: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutboundRequestConfig {
+  /// Comma separated hosts which may be contacted even though they resolve to internal addresses
+  pub allowed_hosts: String,
+  pub max_redirects: usize,
+  pub max_response_bytes: u64,
+  pub federation_timeout_seconds: u64,
+  pub link_preview_timeout_seconds: u64,
+  pub max_idle_connections_per_host: usize,
+  /// Proxy url like http://proxy:3128 or socks5://proxy:1080
+  pub proxy: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseConfig {
   pub user: String,
@@ -66,12 +278,65 @@ pub struct DatabaseConfig {
   pub pool_size: u32,
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GoneActorContent {
+  /// Keep the posts and comments, attributed to the deleted actor
+  Keep,
+  /// Also delete them, so that only tombstones are left
+  Delete,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FederationMode {
+  /// Only federate with the allowed instances
+  Allowlist,
+  /// Federate with every instance except the blocked ones
+  Blocklist,
+  /// Federate with every instance
+  Open,
+}
+
+impl FederationMode {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      FederationMode::Allowlist => "allowlist",
+      FederationMode::Blocklist => "blocklist",
+      FederationMode::Open => "open",
+    }
+  }
+}
+
+/// Whether the instance can federate with `domain` in this mode. The lists have to be lowercase.
+pub fn is_instance_allowed(
+  mode: FederationMode,
+  allowed_instances: &[String],
+  blocked_instances: &[String],
+  domain: &str,
+) -> bool {
+  let domain = domain.to_lowercase();
+  match mode {
+    FederationMode::Allowlist => allowed_instances.contains(&domain),
+    FederationMode::Blocklist => !blocked_instances.contains(&domain),
+    FederationMode::Open => true,
+  }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct FederationConfig {
   pub enabled: bool,
   pub tls_enabled: bool,
+  /// If it isn't set, allowlist when there are allowed instances, blocklist otherwise
+  pub mode: Option<FederationMode>,
   pub allowed_instances: String,
   pub blocked_instances: String,
+  pub gone_actor_content: GoneActorContent,
+  pub stale_actor_days: i64,
+  /// Only serves posts, comments and collections to requests signed by a remote actor
+  pub require_signed_fetch: bool,
+  /// Off asks other instances to leave all local users and communities out of search
+  pub discoverable: bool,
 }
 
 lazy_static! {
@@ -96,6 +361,10 @@ impl Settings {
 
     s.merge(File::with_name(CONFIG_FILE).required(false))?;
 
+    s.merge(File::with_name(CONFIG_FILE_RATE_LIMIT).required(false))?;
+
+    s.merge(File::with_name(CONFIG_FILE_MAINTENANCE).required(false))?;
+
     // Add in settings from the environment (with a prefix of LEMMY)
     // Eg.. `LEMMY_DEBUG=1 ./target/app` would set the `debug` key
     // Note: we need to use double underscore here, because otherwise variables containing
@@ -130,12 +399,41 @@ impl Settings {
     fs::read_to_string(CONFIG_FILE)
   }
 
+  /// The host that a request was made to, if it is the onion service of the instance.
+  pub fn get_onion_host(&self, host: &str) -> Option<String> {
+    self
+      .onion_hostname
+      .to_owned()
+      .filter(|o| o.eq_ignore_ascii_case(host))
+  }
+
+  pub fn get_federation_mode(&self) -> FederationMode {
+    match self.federation.mode {
+      Some(mode) => mode,
+      None if !self.get_allowed_instances().is_empty() => FederationMode::Allowlist,
+      None => FederationMode::Blocklist,
+    }
+  }
+
+  /// Whether the instance can federate with the domain, which is always true for its own. Doesn't
+  /// check if federation is enabled.
+  pub fn is_instance_allowed(&self, domain: &str) -> bool {
+    let local_instance = self.hostname.split(':').next().unwrap_or_default();
+    domain.eq_ignore_ascii_case(local_instance)
+      || is_instance_allowed(
+        self.get_federation_mode(),
+        &self.get_allowed_instances(),
+        &self.get_blocked_instances(),
+        domain,
+      )
+  }
+
   pub fn get_allowed_instances(&self) -> Vec<String> {
     let mut allowed_instances: Vec<String> = self
       .federation
       .allowed_instances
       .split(',')
-      .map(|d| d.to_string())
+      .map(|d| d.trim().to_lowercase())
       .collect();
 
     // The defaults.hjson config always returns a [""]
@@ -149,7 +447,7 @@ impl Settings {
       .federation
       .blocked_instances
       .split(',')
-      .map(|d| d.to_string())
+      .map(|d| d.trim().to_lowercase())
       .collect();
 
     // The defaults.hjson config always returns a [""]
@@ -161,14 +459,42 @@ impl Settings {
   pub fn save_config_file(data: &str) -> Result<String, Error> {
     fs::write(CONFIG_FILE, data)?;
 
-    // Reload the new settings
+    Self::reload();
+
+    Self::read_config_file()
+  }
+
+  /// Saves the rate limits edited by an admin, and applies them right away. Json is valid hjson,
+  /// so the file can be merged like the others.
+  pub fn save_rate_limit_config(rate_limit: &RateLimitConfig) -> Result<(), Error> {
+    let data = serde_json::json!({ "rate_limit": rate_limit });
+    fs::write(CONFIG_FILE_RATE_LIMIT, serde_json::to_string_pretty(&data)?)?;
+
+    Self::reload();
+
+    Ok(())
+  }
+
+  /// Turns read only mode on or off. It's kept in a file instead of the database, so that it
+  /// still works while the database is down.
+  pub fn save_maintenance_config(maintenance: &MaintenanceConfig) -> Result<(), Error> {
+    let data = serde_json::json!({ "maintenance": maintenance });
+    fs::write(
+      CONFIG_FILE_MAINTENANCE,
+      serde_json::to_string_pretty(&data)?,
+    )?;
+
+    Self::reload();
+
+    Ok(())
+  }
+
+  fn reload() {
     // From https://stackoverflow.com/questions/29654927/how-do-i-assign-a-string-to-a-mutable-static-variable/47181804#47181804
     let mut new_settings = SETTINGS.write().unwrap();
     *new_settings = match Settings::init() {
       Ok(c) => c,
       Err(e) => panic!("{}", e),
     };
-
-    Self::read_config_file()
   }
 }
@@ -1,13 +1,61 @@
-use crate::utils::{
-  is_valid_community_name,
-  is_valid_post_title,
-  is_valid_preferred_username,
-  is_valid_username,
-  remove_slurs,
-  scrape_text_for_mentions,
-  slur_check,
-  slurs_vec_to_str,
+use crate::{
+  dkim::{canonicalize_body_relaxed, canonicalize_header_relaxed, dkim_signature},
+  email::{
+    check_digest_unsubscribe,
+    digest_unsubscribe_path,
+    parse_reply_address,
+    render_digest_template,
+    same_address,
+    sign_reply_address,
+    strip_quoted_reply,
+    ReplyTarget,
+    REPLY_ADDRESS_DAYS,
+  },
+  hyperloglog::{HyperLogLog, HYPERLOGLOG_REGISTERS},
+  password::{check_password, hash_password, needs_rehash, verify_password},
+  schedule::{render_title_template, Schedule},
+  settings::{is_instance_allowed, FederationMode, PasswordConfig},
+  utils::{
+    capitalize_first,
+    check_lengths,
+    clean_title,
+    fingerprint,
+    hash_ip,
+    html_links,
+    html_to_text,
+    is_all_caps,
+    is_valid_community_name,
+    is_valid_feature_flag_name,
+    is_valid_hex_color,
+    is_valid_post_title,
+    is_valid_preferred_username,
+    is_valid_theme_name,
+    is_valid_title_pattern,
+    is_valid_username,
+    microblog_title,
+    remove_slurs,
+    replace_base_url,
+    scrape_text_for_mentions,
+    search_keywords,
+    slur_check,
+    slurs_vec_to_str,
+    title_matches_pattern,
+    truncate_ip,
+    truncate_to_length,
+    without_port,
+    MICROBLOG_DEFAULT_TITLE,
+  },
+  web_push::{
+    base64_url_decode,
+    base64_url_encode,
+    check_push_subscription_keys,
+    encrypt_push_payload_with,
+    vapid_authorization,
+  },
+  LengthViolation,
 };
+use actix_web::{test::TestRequest, HttpRequest};
+use chrono::NaiveDate;
 
 #[test]
 fn test_mentions_regex() {
@@ -19,6 +67,15 @@ fn test_mentions_regex() {
   assert_eq!(mentions[1].domain, "lemmy-alpha:8540".to_string());
 }
 
+#[test]
+fn test_search_keywords() {
+  assert_eq!(
+    search_keywords("Rust, rust and RUST-lang!"),
+    vec!["rust".to_string(), "and".to_string(), "lang".to_string()]
+  );
+  assert!(search_keywords("  ... ").is_empty());
+}
+
 #[test]
 fn test_valid_register_username() {
   assert!(is_valid_username("Hello_98"));
@@ -50,6 +107,106 @@ fn test_valid_post_title() {
   assert!(!is_valid_post_title("\n \n \n \n    		")); // tabs/spaces/newlines
 }
 
+#[test]
+fn test_check_lengths() {
+  assert!(check_lengths(&[("name", Some("Post Title"), 10), ("body", None, 0)]).is_ok());
+  let err = check_lengths(&[
+    ("name", Some("😃😃😃"), 2),
+    ("body", Some("short"), 100),
+    ("url", Some("https://example.com"), 10),
+  ])
+  .unwrap_err();
+  assert_eq!(
+    vec![
+      LengthViolation {
+        field: "name".into(),
+        max: 2,
+        length: 3,
+      },
+      LengthViolation {
+        field: "url".into(),
+        max: 10,
+        length: 19,
+      },
+    ],
+    err.violations
+  );
+}
+
+#[test]
+fn test_truncate_to_length() {
+  assert_eq!("short", truncate_to_length("short", 5));
+  assert_eq!("shor…", truncate_to_length("shorter", 5));
+  assert_eq!("😃😃…", truncate_to_length("😃😃😃😃", 3));
+}
+
+#[test]
+fn test_valid_branding() {
+  assert!(is_valid_hex_color("#fff"));
+  assert!(is_valid_hex_color("#1A2b3c"));
+  assert!(!is_valid_hex_color("fff"));
+  assert!(!is_valid_hex_color("#12345"));
+  assert!(!is_valid_hex_color("#fff;}body{"));
+  assert!(is_valid_theme_name("darkly"));
+  assert!(!is_valid_theme_name("../darkly"));
+  assert!(!is_valid_theme_name(""));
+}
+
+#[test]
+fn test_clean_title() {
+  assert_eq!("A title", clean_title("  A \t title.  "));
+  assert_eq!("A title", clean_title("A title,;:"));
+  assert_eq!("Why?", clean_title("Why?"));
+  assert_eq!("...", clean_title("..."));
+  assert_eq!("Émile", capitalize_first("émile"));
+  assert_eq!("", capitalize_first(""));
+  assert!(is_all_caps("BREAKING: NEWS 2020"));
+  assert!(!is_all_caps("Breaking news"));
+  assert!(!is_all_caps("A 1"));
+  assert!(!is_all_caps("2020"));
+}
+
+#[test]
+fn test_microblog_html() {
+  let html = r#"<p><span class="h-card"><a href="https://lemmy.example/c/rust" class="u-url mention">@<span>rust</span></a></span> Rust &amp; friends<br />second line</p><p>Read <a href="https://blog.example/post?a=1&amp;b=2" rel="nofollow">this</a> <a href="https://masto.example/tags/rust" class="mention hashtag">#<span>rust</span></a></p>"#;
+  let text = html_to_text(html);
+  assert_eq!("@rust Rust & friends\nsecond line\n\nRead this #rust", text);
+  assert_eq!(
+    vec!["https://blog.example/post?a=1&b=2".to_string()],
+    html_links(html)
+  );
+  assert_eq!("Rust & friends", microblog_title(&text));
+  assert_eq!(
+    "Only this",
+    microblog_title("@rust@lemmy.example\n@a Only this")
+  );
+  assert_eq!(
+    MICROBLOG_DEFAULT_TITLE,
+    microblog_title("@rust@lemmy.example @a")
+  );
+}
+
+#[test]
+fn test_title_pattern() {
+  assert!(is_valid_title_pattern(r"^\[(Question|Guide)\] "));
+  assert!(!is_valid_title_pattern(r"^\[(Question"));
+  assert!(!is_valid_title_pattern(&"a".repeat(201)));
+  assert!(!is_valid_title_pattern(r"\w{1000}\w{1000}"));
+  assert!(title_matches_pattern(
+    "[Question] Why?",
+    r"^\[(Question|Guide)\] "
+  ));
+  assert!(!title_matches_pattern("Why?", r"^\[(Question|Guide)\] "));
+  assert!(title_matches_pattern("Why?", r"^\[(Question"));
+}
+
+#[test]
+fn test_valid_feature_flag_name() {
+  assert!(is_valid_feature_flag_name("new_sorts"));
+  assert!(!is_valid_feature_flag_name("New Sorts"));
+  assert!(!is_valid_feature_flag_name(""));
+}
+
 #[test]
 fn test_slur_filter() {
   let test =
@@ -84,3 +241,459 @@ fn test_slur_filter() {
 //  let result =  send_email("not a subject", "test_email@gmail.com", "ur user", "<h1>HI there</h1>");
 //   assert!(result.is_ok());
 // }
+
+#[test]
+fn test_anonymize_ip() {
+  assert_eq!(truncate_ip("192.168.13.37"), "192.168.13.0");
+  assert_eq!(
+    truncate_ip("2001:db8:85a3::8a2e:370:7334"),
+    "2001:db8:85a3::"
+  );
+  assert_eq!(truncate_ip("not an ip"), "unknown");
+
+  let hashed = hash_ip("192.168.13.37", "salt");
+  assert_eq!(hashed, hash_ip("192.168.13.37", "salt"));
+  assert_ne!(hashed, hash_ip("192.168.13.37", "pepper"));
+  assert!(!hashed.contains("192"));
+}
+
+#[test]
+fn test_get_ip() {
+  assert_eq!(without_port("192.168.13.37:5678"), "192.168.13.37");
+  assert_eq!(without_port("192.168.13.37"), "192.168.13.37");
+  assert_eq!(
+    without_port("[2001:db8:85a3::7334]:443"),
+    "2001:db8:85a3::7334"
+  );
+  assert_eq!(without_port("2001:db8:85a3::7334"), "2001:db8:85a3::7334");
+  assert_eq!(without_port("[2001:db8:85a3::7334]"), "2001:db8:85a3::7334");
+  assert_eq!(
+    truncate_ip(&without_port("[2001:db8:85a3::7334]:443")),
+    "2001:db8:85a3::"
+  );
+
+  // What get_ip anonymizes, which doesn't need the config
+  let client_address =
+    |req: HttpRequest| without_port(req.connection_info().realip_remote_addr().unwrap());
+  let peer = "[2001:db8:85a3::7334]:443".parse().unwrap();
+  let req = TestRequest::default().peer_addr(peer).to_http_request();
+  assert_eq!(client_address(req), "2001:db8:85a3::7334");
+  let req = TestRequest::default()
+    .header("x-forwarded-for", "2001:db8:85a3::1")
+    .peer_addr(peer)
+    .to_http_request();
+  assert_eq!(client_address(req), "2001:db8:85a3::1");
+}
+
+#[test]
+fn test_fingerprint() {
+  let firefox = fingerprint("Mozilla/5.0 Firefox/82.0", "en-US,en;q=0.5");
+  assert_eq!(32, firefox.len());
+  assert_eq!(
+    firefox,
+    fingerprint("Mozilla/5.0 Firefox/82.0", "en-US,en;q=0.5")
+  );
+  assert_ne!(firefox, fingerprint("Mozilla/5.0 Firefox/82.0", "de-DE"));
+  assert_ne!(
+    firefox,
+    fingerprint("Mozilla/5.0 Chrome/86.0", "en-US,en;q=0.5")
+  );
+}
+
+#[test]
+fn test_replace_base_url() {
+  let text = r#"{"actor_id":"https://example.com/u/alice","url":"https://example.com.evil.org/x","home":"https://example.com"}"#;
+  assert_eq!(
+    replace_base_url(text, "https://example.com", "http://example.onion"),
+    r#"{"actor_id":"http://example.onion/u/alice","url":"https://example.com.evil.org/x","home":"http://example.onion"}"#
+  );
+}
+
+#[test]
+fn test_schedule() {
+  // A Thursday
+  let now = NaiveDate::from_ymd(2020, 10, 1).and_hms(12, 30, 15);
+
+  let weekly = Schedule::parse("0 12 * * 1").unwrap();
+  assert_eq!(
+    weekly.next_after(now),
+    Some(NaiveDate::from_ymd(2020, 10, 5).and_hms(12, 0, 0))
+  );
+
+  let quarter_hours = Schedule::parse("*/15 * * * *").unwrap();
+  assert_eq!(
+    quarter_hours.next_after(now),
+    Some(NaiveDate::from_ymd(2020, 10, 1).and_hms(12, 45, 0))
+  );
+
+  // Either the day of the month or the day of the week
+  let first_or_sunday = Schedule::parse("0 0 1 * 0,7").unwrap();
+  assert_eq!(
+    first_or_sunday.next_after(now),
+    Some(NaiveDate::from_ymd(2020, 10, 4).and_hms(0, 0, 0))
+  );
+
+  assert_eq!(
+    Schedule::parse("@monthly").unwrap().next_after(now),
+    Some(NaiveDate::from_ymd(2020, 11, 1).and_hms(0, 0, 0))
+  );
+  assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(now), None);
+
+  assert!(Schedule::parse("0 12 * *").is_none());
+  assert!(Schedule::parse("60 * * * *").is_none());
+  assert!(Schedule::parse("*/0 * * * *").is_none());
+  assert!(Schedule::parse("5-1 * * * *").is_none());
+  assert!(Schedule::parse("0 0 0 * *").is_none());
+}
+
+#[test]
+fn test_render_title_template() {
+  let at = NaiveDate::from_ymd(2020, 10, 5).and_hms(12, 0, 0);
+  assert_eq!(
+    render_title_template("Weekly discussion {date}", at),
+    "Weekly discussion 2020-10-05"
+  );
+  assert_eq!(
+    render_title_template(
+      "{weekday} thread, {month_name} {day} {year} (week {week})",
+      at
+    ),
+    "Monday thread, October 05 2020 (week 41)"
+  );
+}
+
+/// Tests run in the directory of this crate, so signing has to read the settings of the server.
+fn use_server_config() {
+  std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+}
+
+#[test]
+fn test_reply_address() {
+  use_server_config();
+  let day = 18_550;
+  let address =
+    sign_reply_address("reply@lemmy.example", ReplyTarget::Comment(12), 3, day).unwrap();
+  assert!(address.starts_with("reply+c12-3-18550-"));
+  assert!(address.ends_with("@lemmy.example"));
+  assert_eq!(
+    Some((ReplyTarget::Comment(12), 3)),
+    parse_reply_address(&address, day)
+  );
+  assert_eq!(
+    Some((ReplyTarget::Comment(12), 3)),
+    parse_reply_address(&format!("Lemmy <{}>", address.to_uppercase()), day + 1)
+  );
+  assert_eq!(
+    None,
+    parse_reply_address(&address, day + REPLY_ADDRESS_DAYS + 1)
+  );
+
+  let other_user = address.replacen("-3-", "-4-", 1);
+  assert_eq!(None, parse_reply_address(&other_user, day));
+  let later = address.replacen("-18550-", "-18560-", 1);
+  assert_eq!(None, parse_reply_address(&later, day + 40));
+  let post = sign_reply_address("reply@lemmy.example", ReplyTarget::Post(12), 3, day).unwrap();
+  assert_eq!(
+    Some((ReplyTarget::Post(12), 3)),
+    parse_reply_address(&post, day)
+  );
+  assert_eq!(None, parse_reply_address("reply@lemmy.example", day));
+  assert_eq!(
+    None,
+    parse_reply_address("reply+c12-3-18550-00@lemmy.example", day)
+  );
+
+  assert!(same_address(
+    "Some One <Some.One@Example.com>",
+    "some.one@example.com"
+  ));
+  assert!(!same_address("some.one@example.com", "someone@example.com"));
+}
+
+#[test]
+fn test_strip_quoted_reply() {
+  let text = "Thanks, good point!\nSecond line\n\nOn Tue, 13 Oct 2020, Lemmy wrote:\n> The comment";
+  assert_eq!("Thanks, good point!\nSecond line", strip_quoted_reply(text));
+  assert_eq!("Hi", strip_quoted_reply("Hi\n-- \nMy signature"));
+  assert_eq!("", strip_quoted_reply("> only a quote"));
+}
+
+#[test]
+fn test_digest() {
+  use_server_config();
+  assert_eq!(
+    "Top of rust:\n\n- [A post](https://lemmy.example/post/1)\n\n2 joined",
+    render_digest_template(
+      "Top of {community}:\n\n{top_posts}\n\n{new_members} joined",
+      "rust",
+      "- [A post](https://lemmy.example/post/1)",
+      2
+    )
+  );
+
+  let path = digest_unsubscribe_path(3, 12).unwrap();
+  let signature = path.rsplit('/').next().unwrap();
+  assert!(path.starts_with("/digest/unsubscribe/3/12/"));
+  assert!(check_digest_unsubscribe(3, 12, signature));
+  assert!(!check_digest_unsubscribe(4, 12, signature));
+  assert!(!check_digest_unsubscribe(3, 12, "00"));
+}
+
+#[test]
+fn test_federation_mode() {
+  let allowed = vec!["lemmy.ml".to_string()];
+  let blocked = vec!["spam.example".to_string()];
+  let check = |mode, domain| is_instance_allowed(mode, &allowed, &blocked, domain);
+
+  assert!(check(FederationMode::Allowlist, "lemmy.ml"));
+  assert!(check(FederationMode::Allowlist, "Lemmy.ML"));
+  assert!(!check(FederationMode::Allowlist, "other.example"));
+  assert!(check(FederationMode::Blocklist, "other.example"));
+  assert!(!check(FederationMode::Blocklist, "spam.example"));
+  assert!(check(FederationMode::Open, "spam.example"));
+}
+
+#[test]
+fn test_dkim_canonicalization() {
+  // The example of RFC 6376, section 3.4.5
+  assert_eq!("a:X", canonicalize_header_relaxed("A", " X"));
+  assert_eq!("b:Y Z", canonicalize_header_relaxed("B ", " Y\t\r\n\tZ  "));
+  assert_eq!(
+    " C\r\nD E\r\n",
+    canonicalize_body_relaxed(" C \r\nD \t E\r\n\r\n\r\n")
+  );
+  assert_eq!("", canonicalize_body_relaxed("\r\n\r\n"));
+}
+
+#[test]
+fn test_dkim_signature() {
+  use openssl::{base64::decode_block, hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Verifier};
+
+  let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+  let message = "From: Lemmy <noreply@example.com>\r\nTo: user@example.com\r\nSubject: Hello\r\n  world\r\nX-Other: not signed\r\n\r\nHi  there\r\n\r\n";
+  let header = dkim_signature(
+    message,
+    "example.com",
+    "lemmy",
+    &key.private_key_to_pem_pkcs8().unwrap(),
+    1600000000,
+  )
+  .unwrap();
+
+  assert!(header.starts_with(
+    "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.com; s=lemmy; t=1600000000; h=from:to:subject; bh="
+  ));
+  let (value, signature) = header.split_at(header.find("; b=").unwrap() + 4);
+  let value = &value["DKIM-Signature:".len()..];
+  let data = format!(
+    "from:Lemmy <noreply@example.com>\r\nto:user@example.com\r\nsubject:Hello world\r\n{}",
+    canonicalize_header_relaxed("DKIM-Signature", value)
+  );
+  let mut verifier = Verifier::new(MessageDigest::sha256(), &key).unwrap();
+  verifier.update(data.as_bytes()).unwrap();
+  assert!(verifier.verify(&decode_block(signature).unwrap()).unwrap());
+}
+
+fn test_password_config() -> PasswordConfig {
+  PasswordConfig {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+    min_length: 10,
+    max_length: 20,
+    min_character_classes: 2,
+  }
+}
+
+#[test]
+fn test_password_hashes() {
+  let config = test_password_config();
+  let hash = hash_password("correct horse", &config).unwrap();
+  assert!(hash.starts_with("$argon2id$"));
+  assert!(verify_password("correct horse", &hash));
+  assert!(!verify_password("wrong horse", &hash));
+  assert!(!needs_rehash(&hash, &config));
+
+  let stronger = PasswordConfig {
+    iterations: 2,
+    ..test_password_config()
+  };
+  assert!(needs_rehash(&hash, &stronger));
+
+  let legacy = bcrypt::hash("correct horse", 4).unwrap();
+  assert!(verify_password("correct horse", &legacy));
+  assert!(!verify_password("wrong horse", &legacy));
+  assert!(needs_rehash(&legacy, &config));
+
+  assert!(!verify_password("correct horse", "correct horse"));
+}
+
+#[test]
+fn test_check_password() {
+  let config = test_password_config();
+  assert!(check_password("correct horse", "alice", &config).is_ok());
+  assert!(check_password("Tr0ub4dor&3", "alice", &config).is_ok());
+  let error = |password: &str| {
+    check_password(password, "alice", &config)
+      .unwrap_err()
+      .message
+  };
+  assert_eq!(error("short one"), "password_too_short");
+  assert_eq!(error("much too long for the limit"), "password_too_long");
+  assert_eq!(error("onlyletters"), "password_too_weak");
+  assert_eq!(error("Password123"), "password_too_weak");
+  assert_eq!(error("Alice-in-wonderland"), "password_too_weak");
+}
+
+#[test]
+fn test_hyperloglog() {
+  let mut first_half = HyperLogLog::default();
+  let mut second_half = HyperLogLog::default();
+  assert_eq!(0, first_half.count());
+  for i in 0..5000 {
+    first_half.insert(&format!("visitor {}", i), "salt");
+    // Adding an item again changes nothing
+    first_half.insert(&format!("visitor {}", i), "salt");
+    second_half.insert(&format!("visitor {}", i + 5000), "salt");
+  }
+  let count = first_half.count();
+  assert!(count > 4500 && count < 5500, "{}", count);
+
+  let mut all = first_half.to_owned();
+  all.merge(&second_half);
+  let count = all.count();
+  assert!(count > 9000 && count < 11000, "{}", count);
+  all.merge(&first_half);
+  assert_eq!(count, all.count());
+
+  let mut few = HyperLogLog::default();
+  for i in 0..20 {
+    few.insert(&i.to_string(), "salt");
+  }
+  let count = few.count();
+  assert!(count >= 18 && count <= 22, "{}", count);
+
+  let mut other_salt = HyperLogLog::default();
+  for i in 0..20 {
+    other_salt.insert(&i.to_string(), "pepper");
+  }
+  assert_ne!(few, other_salt);
+
+  let registers = few.registers().to_vec();
+  assert_eq!(Some(few), HyperLogLog::from_registers(registers));
+  assert_eq!(None, HyperLogLog::from_registers(vec![0; 16]));
+  assert_eq!(HYPERLOGLOG_REGISTERS, all.registers().len());
+}
+
+#[test]
+fn test_encrypt_push_payload() {
+  use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint},
+    nid::Nid,
+  };
+
+  // The example of RFC 8291, section 5
+  let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+  let private_number =
+    BigNum::from_slice(&base64_url_decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw").unwrap())
+      .unwrap();
+  let ctx = BigNumContext::new().unwrap();
+  let mut public_point = EcPoint::new(&group).unwrap();
+  public_point
+    .mul_generator(&group, &private_number, &ctx)
+    .unwrap();
+  let sender_key = EcKey::from_private_components(&group, &private_number, &public_point).unwrap();
+  let p256dh =
+    "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+  let auth = "BTBZMqHH6r4Tts7J_aSIgg";
+
+  let body = encrypt_push_payload_with(
+    b"When I grow up, I want to be a watermelon",
+    &base64_url_decode(p256dh).unwrap(),
+    &base64_url_decode(auth).unwrap(),
+    &sender_key,
+    &base64_url_decode("DGv6ra1nlYgDCS1FRnbzlw").unwrap(),
+  )
+  .unwrap();
+  assert_eq!(
+    "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN",
+    base64_url_encode(&body)
+  );
+
+  assert!(check_push_subscription_keys(p256dh, auth).is_ok());
+  assert!(check_push_subscription_keys(p256dh, "BTBZMqHH6r4Tts7J").is_err());
+  assert!(check_push_subscription_keys("BCVxsr7N_eNgVRqvHtD0zTZsEc6", auth).is_err());
+  assert!(encrypt_push_payload_with(
+    &[0; 4000],
+    &base64_url_decode(p256dh).unwrap(),
+    &base64_url_decode(auth).unwrap(),
+    &sender_key,
+    &[0; 16],
+  )
+  .is_err());
+}
+
+#[test]
+fn test_vapid_authorization() {
+  use openssl::{
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+  };
+  use url::Url;
+
+  let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+  let key = EcKey::generate(&group).unwrap();
+  let endpoint = Url::parse("https://push.example.net:8443/push/abc?x=1").unwrap();
+  let header = vapid_authorization(
+    &endpoint,
+    "mailto:admin@example.com",
+    &key.private_key_to_pem().unwrap(),
+    1600000000,
+  )
+  .unwrap();
+
+  let rest = header.strip_prefix("vapid t=").unwrap();
+  let (token, public_key) = rest.split_at(rest.find(", k=").unwrap());
+  let parts = token.split('.').collect::<Vec<&str>>();
+  assert_eq!(3, parts.len());
+  let claims: serde_json::Value =
+    serde_json::from_slice(&base64_url_decode(parts[1]).unwrap()).unwrap();
+  assert_eq!(
+    serde_json::json!({
+      "aud": "https://push.example.net:8443",
+      "exp": 1600043200,
+      "sub": "mailto:admin@example.com",
+    }),
+    claims
+  );
+
+  let mut ctx = openssl::bn::BigNumContext::new().unwrap();
+  let expected_key = key
+    .public_key()
+    .to_bytes(
+      &group,
+      openssl::ec::PointConversionForm::UNCOMPRESSED,
+      &mut ctx,
+    )
+    .unwrap();
+  assert_eq!(base64_url_encode(&expected_key), &public_key[4..]);
+
+  let signature = base64_url_decode(parts[2]).unwrap();
+  assert_eq!(64, signature.len());
+  let signature = EcdsaSig::from_private_components(
+    BigNum::from_slice(&signature[..32]).unwrap(),
+    BigNum::from_slice(&signature[32..]).unwrap(),
+  )
+  .unwrap();
+  let public_key = EcKey::from_public_key(&group, key.public_key()).unwrap();
+  let digest = hash(
+    MessageDigest::sha256(),
+    format!("{}.{}", parts[0], parts[1]).as_bytes(),
+  )
+  .unwrap();
+  assert!(signature.verify(&digest, &public_key).unwrap());
+}
@@ -1,9 +1,28 @@
-use crate::{settings::Settings, APIError};
-use actix_web::dev::ConnectionInfo;
+use crate::{
+  settings::{IpPolicy, Settings},
+  APIError,
+  LengthViolation,
+  ReadOnlyError,
+  ValidationError,
+};
+use actix_web::{
+  dev::ConnectionInfo,
+  http::{
+    header::{ACCEPT_LANGUAGE, USER_AGENT},
+    HeaderMap,
+  },
+};
 use chrono::{DateTime, FixedOffset, Local, NaiveDateTime};
 use itertools::Itertools;
+use log::error;
+use maxminddb::{geoip2, Reader};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use regex::{Regex, RegexBuilder};
+use std::{
+  net::{IpAddr, SocketAddr},
+  sync::RwLock,
+  time::{Duration, SystemTime},
+};
 
 lazy_static! {
 static ref EMAIL_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9.!#$%&’*+/=?^_`{|}~-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*$").unwrap();
@@ -15,6 +34,14 @@ static ref MENTIONS_REGEX: Regex = Regex::new(r"@(?P<name>[\w.]+)@(?P<domain>[a-
 static ref VALID_USERNAME_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_]{3,20}$").unwrap();
 static ref VALID_COMMUNITY_NAME_REGEX: Regex = Regex::new(r"^[a-z0-9_]{3,20}$").unwrap();
 static ref VALID_POST_TITLE_REGEX: Regex = Regex::new(r".*\S.*").unwrap();
+static ref VALID_HEX_COLOR_REGEX: Regex = Regex::new(r"^#([0-9a-fA-F]{3}|[0-9a-fA-F]{6})$").unwrap();
+static ref VALID_THEME_NAME_REGEX: Regex = Regex::new(r"^[a-z0-9_-]{1,40}$").unwrap();
+static ref VALID_FEATURE_FLAG_NAME_REGEX: Regex = Regex::new(r"^[a-z0-9_]{1,50}$").unwrap();
+static ref HTML_LINK_REGEX: Regex = Regex::new(r#"<a\s[^>]*href="(?P<href>[^"]+)"[^>]*>"#).unwrap();
+static ref HTML_LINE_BREAK_REGEX: Regex = Regex::new(r"(?i)<br\s*/?>").unwrap();
+static ref HTML_PARAGRAPH_END_REGEX: Regex = Regex::new(r"(?i)</p>").unwrap();
+static ref HTML_TAG_REGEX: Regex = Regex::new(r"<[^>]*>").unwrap();
+static ref IP_SALT: RwLock<(String, SystemTime)> = RwLock::new((generate_random_string(), SystemTime::now()));
 }
 
 pub fn naive_from_unix(time: i64) -> NaiveDateTime {
@@ -59,12 +86,59 @@ pub fn check_slurs_opt(text: &Option<String>) -> Result<(), APIError> {
   }
 }
 
+/// Fails while an admin has made the site read only.
+pub fn check_not_read_only() -> Result<(), ReadOnlyError> {
+  let maintenance = Settings::get().maintenance;
+  if maintenance.read_only {
+    Err(ReadOnlyError {
+      message: maintenance.message,
+    })
+  } else {
+    Ok(())
+  }
+}
+
 pub(crate) fn slurs_vec_to_str(slurs: Vec<&str>) -> String {
   let start = "No slurs - ";
   let combined = &slurs.join(", ");
   [start, combined].concat()
 }
 
+/// Checks the fields against their limits, given as (field, text, max), all at once, so that the
+/// error lists every field which is too long. Lengths are in characters.
+pub fn check_lengths(fields: &[(&str, Option<&str>, usize)]) -> Result<(), ValidationError> {
+  let violations: Vec<LengthViolation> = fields
+    .iter()
+    .filter_map(|&(field, text, max)| {
+      let length = text?.chars().count();
+      if length > max {
+        Some(LengthViolation {
+          field: field.to_string(),
+          max,
+          length,
+        })
+      } else {
+        None
+      }
+    })
+    .collect();
+  if violations.is_empty() {
+    Ok(())
+  } else {
+    Err(ValidationError { violations })
+  }
+}
+
+/// Cuts the text to at most max characters, ending with an ellipsis if anything was cut.
+pub fn truncate_to_length(text: &str, max: usize) -> String {
+  if text.chars().count() <= max {
+    return text.to_owned();
+  }
+  let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+  truncated.push('…');
+  truncated
+}
+
 pub fn generate_random_string() -> String {
   thread_rng().sample_iter(&Alphanumeric).take(30).collect()
 }
@@ -100,6 +174,16 @@ pub fn scrape_text_for_mentions(text: &str) -> Vec<MentionData> {
   out.into_iter().unique().collect()
 }
 
+/// Split text into lowercase search keywords, without duplicates.
+pub fn search_keywords(text: &str) -> Vec<String> {
+  text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|w| !w.is_empty())
+    .map(|w| w.to_lowercase())
+    .unique()
+    .collect()
+}
+
 pub fn is_valid_username(name: &str) -> bool {
   VALID_USERNAME_REGEX.is_match(name)
 }
@@ -119,12 +203,264 @@ pub fn is_valid_post_title(title: &str) -> bool {
   VALID_POST_TITLE_REGEX.is_match(title)
 }
 
+/// Collapses runs of whitespace into single spaces, and strips trailing periods, commas, colons and
+/// semicolons. Question and exclamation marks stay, and so does a title which is only punctuation.
+pub fn clean_title(title: &str) -> String {
+  let collapsed = title.split_whitespace().join(" ");
+  let stripped = collapsed.trim_end_matches(|c: char| matches!(c, '.' | ',' | ':' | ';'));
+  if stripped.trim().is_empty() {
+    collapsed
+  } else {
+    stripped.trim_end().to_owned()
+  }
+}
+
+pub fn capitalize_first(title: &str) -> String {
+  let mut chars = title.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
+
+/// Titles with at least two letters, all of them uppercase.
+pub fn is_all_caps(title: &str) -> bool {
+  let letters: Vec<char> = title.chars().filter(|c| c.is_alphabetic()).collect();
+  letters.len() >= 2 && letters.iter().all(|c| c.is_uppercase())
+}
+
+/// The text of html from microblogs, like Mastodon, with line breaks and paragraphs kept.
+pub fn html_to_text(html: &str) -> String {
+  let text = HTML_LINE_BREAK_REGEX.replace_all(html, "\n");
+  let text = HTML_PARAGRAPH_END_REGEX.replace_all(&text, "\n\n");
+  HTML_TAG_REGEX
+    .replace_all(&text, "")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&amp;", "&")
+    .trim()
+    .to_owned()
+}
+
+/// The links in html from microblogs, except the ones of mentions and hashtags.
+pub fn html_links(html: &str) -> Vec<String> {
+  HTML_LINK_REGEX
+    .captures_iter(html)
+    .filter(|caps| !caps[0].contains("mention"))
+    .map(|caps| caps["href"].replace("&amp;", "&"))
+    .unique()
+    .collect()
+}
+
+/// The title of posts from notes which only have mentions.
+pub const MICROBLOG_DEFAULT_TITLE: &str = "Untitled";
+
+/// A post title for a note from a microblog: its first line, without the mentions it starts with.
+pub fn microblog_title(text: &str) -> String {
+  text
+    .lines()
+    .map(|line| {
+      line
+        .split_whitespace()
+        .skip_while(|word| word.starts_with('@'))
+        .join(" ")
+    })
+    .find(|line| !line.is_empty())
+    .unwrap_or_else(|| MICROBLOG_DEFAULT_TITLE.to_string())
+}
+
+/// Colors like `#fff` or `#1a2b3c`, which can go into css as they are.
+pub fn is_valid_hex_color(color: &str) -> bool {
+  VALID_HEX_COLOR_REGEX.is_match(color)
+}
+
+pub fn is_valid_theme_name(name: &str) -> bool {
+  VALID_THEME_NAME_REGEX.is_match(name)
+}
+
+pub fn is_valid_feature_flag_name(name: &str) -> bool {
+  VALID_FEATURE_FLAG_NAME_REGEX.is_match(name)
+}
+
+/// Title patterns are set by mods, so they can't be longer than this, in characters.
+pub const MAX_TITLE_PATTERN_LENGTH: usize = 200;
+/// In bytes of the compiled regex, to refuse patterns which are short but expensive.
+const TITLE_PATTERN_SIZE_LIMIT: usize = 1 << 16;
+
+fn build_title_pattern(pattern: &str) -> Option<Regex> {
+  if pattern.chars().count() > MAX_TITLE_PATTERN_LENGTH {
+    return None;
+  }
+  RegexBuilder::new(pattern)
+    .size_limit(TITLE_PATTERN_SIZE_LIMIT)
+    .build()
+    .ok()
+}
+
+pub fn is_valid_title_pattern(pattern: &str) -> bool {
+  build_title_pattern(pattern).is_some()
+}
+
+/// A pattern which doesn't build anymore matches every title, so that it can't block posting.
+pub fn title_matches_pattern(title: &str, pattern: &str) -> bool {
+  build_title_pattern(pattern).map_or(true, |r| r.is_match(title))
+}
+
+/// Replaces every link starting with `from`, but not links to other hosts which happen to start
+/// the same, like `https://example.com.evil.org`.
+pub fn replace_base_url(text: &str, from: &str, to: &str) -> String {
+  let mut replaced = String::with_capacity(text.len());
+  let mut last_end = 0;
+  for (start, _) in text.match_indices(from) {
+    let end = start + from.len();
+    let continues_host = text[end..]
+      .chars()
+      .next()
+      .map(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == ':')
+      .unwrap_or(false);
+    if !continues_host {
+      replaced.push_str(&text[last_end..start]);
+      replaced.push_str(to);
+      last_end = end;
+    }
+  }
+  replaced.push_str(&text[last_end..]);
+  replaced
+}
+
+/// Returns the client address, anonymized according to the ip address policy of the config.
 pub fn get_ip(conn_info: &ConnectionInfo) -> String {
-  conn_info
-    .realip_remote_addr()
-    .unwrap_or("127.0.0.1:12345")
-    .split(':')
-    .next()
-    .unwrap_or("127.0.0.1")
-    .to_string()
+  let addr = conn_info.realip_remote_addr().unwrap_or("127.0.0.1");
+  anonymize_ip(&without_port(addr))
+}
+
+/// The peer address has a port, like `1.2.3.4:5678` or `[2001:db8::1]:443`, while forwarded
+/// addresses usually don't.
+pub fn without_port(addr: &str) -> String {
+  if let Ok(socket_addr) = addr.parse::<SocketAddr>() {
+    return socket_addr.ip().to_string();
+  }
+  if let Ok(ip) = addr.parse::<IpAddr>() {
+    return ip.to_string();
+  }
+  let host = if addr.starts_with('[') {
+    addr[1..].split(']').next()
+  } else {
+    addr.split(':').next()
+  };
+  host.unwrap_or(addr).to_string()
+}
+
+pub fn anonymize_ip(ip: &str) -> String {
+  let config = Settings::get().ip_addresses;
+  match config.policy {
+    IpPolicy::Full => ip.to_string(),
+    IpPolicy::Truncate => truncate_ip(ip),
+    IpPolicy::Hash | IpPolicy::Disabled => {
+      hash_ip(ip, &current_ip_salt(config.salt_rotation_hours))
+    }
+  }
+}
+
+/// What to write to the logs instead of an address returned by `get_ip`.
+pub fn loggable_ip(ip: &str) -> &str {
+  if Settings::get().ip_addresses.policy == IpPolicy::Disabled {
+    "-"
+  } else {
+    ip
+  }
+}
+
+/// The range to keep of an address returned by `get_ip`, if the ip address policy of the config
+/// allows keeping one. Hashed addresses only match the same address, until the salt changes.
+pub fn ip_range(ip: &str) -> Option<String> {
+  match Settings::get().ip_addresses.policy {
+    IpPolicy::Full => Some(truncate_ip(ip)),
+    IpPolicy::Truncate | IpPolicy::Hash => Some(ip.to_string()),
+    IpPolicy::Disabled => None,
+  }
+}
+
+lazy_static! {
+  static ref GEOIP_DATABASE: Option<Reader<Vec<u8>>> = Settings::get()
+    .ip_addresses
+    .geoip_database
+    .and_then(|path| match Reader::open_readfile(&path) {
+      Ok(reader) => Some(reader),
+      Err(e) => {
+        error!("Couldn't open GeoIP database {}: {}", path, e);
+        None
+      }
+    });
+}
+
+/// The ISO code of the country of an address returned by `get_ip`, if there is a GeoIP database.
+/// Hashed addresses can't be located.
+pub fn ip_country(ip: &str) -> Option<String> {
+  let database = GEOIP_DATABASE.as_ref()?;
+  let ip = ip.parse::<IpAddr>().ok()?;
+  let country = database.lookup::<geoip2::Country>(ip).ok()?;
+  country.country?.iso_code.map(str::to_owned)
+}
+
+/// Tells clients apart by the headers they send, or none if they don't send a user agent.
+pub fn client_fingerprint(headers: &HeaderMap) -> Option<String> {
+  let header = |name| {
+    headers
+      .get(name)
+      .and_then(|h| h.to_str().ok())
+      .unwrap_or("")
+  };
+  let user_agent = header(USER_AGENT);
+  if user_agent.is_empty() {
+    None
+  } else {
+    Some(fingerprint(user_agent, header(ACCEPT_LANGUAGE)))
+  }
+}
+
+pub fn fingerprint(user_agent: &str, accept_language: &str) -> String {
+  openssl::sha::sha256(format!("{}\n{}", user_agent, accept_language).as_bytes())
+    .iter()
+    .take(16)
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
+pub fn truncate_ip(ip: &str) -> String {
+  match ip.parse::<IpAddr>() {
+    Ok(IpAddr::V4(v4)) => {
+      let o = v4.octets();
+      format!("{}.{}.{}.0", o[0], o[1], o[2])
+    }
+    Ok(IpAddr::V6(v6)) => {
+      let s = v6.segments();
+      format!("{:x}:{:x}:{:x}::", s[0], s[1], s[2])
+    }
+    Err(_) => "unknown".to_string(),
+  }
+}
+
+pub fn hash_ip(ip: &str, salt: &str) -> String {
+  openssl::sha::sha256(format!("{}{}", salt, ip).as_bytes())
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
+fn current_ip_salt(rotation_hours: u64) -> String {
+  let rotation = Duration::from_secs(rotation_hours * 3600);
+  {
+    let salt = IP_SALT.read().unwrap();
+    if salt.1.elapsed().unwrap_or_default() < rotation {
+      return salt.0.to_owned();
+    }
+  }
+  let mut salt = IP_SALT.write().unwrap();
+  if salt.1.elapsed().unwrap_or_default() >= rotation {
+    *salt = (generate_random_string(), SystemTime::now());
+  }
+  salt.0.to_owned()
 }
@@ -0,0 +1,221 @@
+//! Web push (RFC 8030) of notifications to the browsers of users. The payload is encrypted for the
+//! browser with aes128gcm (RFC 8291), and the instance identifies itself to the push service with
+//! VAPID (RFC 8292), so that browsers only accept pushes for their subscription from this instance.
+use crate::LemmyError;
+use anyhow::anyhow;
+use openssl::{
+  base64::{decode_block, encode_block},
+  bn::BigNumContext,
+  derive::Deriver,
+  ec::{EcGroup, EcKey, EcKeyRef, EcPoint, PointConversionForm},
+  ecdsa::EcdsaSig,
+  error::ErrorStack,
+  hash::{hash, MessageDigest},
+  nid::Nid,
+  pkey::{PKey, Private},
+  rand::rand_bytes,
+  sign::Signer,
+  symm::{encrypt_aead, Cipher},
+};
+use url::Url;
+
+/// Push services accept bodies of 4096 bytes, which leaves this much for the payload after the
+/// header, the padding delimiter and the authentication tag of the encryption.
+pub const MAX_PUSH_PAYLOAD_LENGTH: usize = 3993;
+
+/// How long the VAPID token of a push is valid. Push services don't accept more than a day.
+const VAPID_TOKEN_HOURS: i64 = 12;
+
+/// The payload is sent as a single record, which can be up to this long.
+const RECORD_SIZE: u32 = 4096;
+
+/// The length of the secret which the browser shares with the instance when it subscribes.
+const AUTH_SECRET_LENGTH: usize = 16;
+
+/// The length of an uncompressed public key on P-256.
+const PUBLIC_KEY_LENGTH: usize = 65;
+
+fn p256() -> Result<EcGroup, ErrorStack> {
+  EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+}
+
+/// Base64 with the url alphabet and without padding, which web push uses for its keys.
+pub fn base64_url_encode(data: &[u8]) -> String {
+  encode_block(data)
+    .replace('+', "-")
+    .replace('/', "_")
+    .trim_end_matches('=')
+    .to_owned()
+}
+
+/// Decodes base64url, with or without padding.
+pub fn base64_url_decode(data: &str) -> Result<Vec<u8>, LemmyError> {
+  let mut standard = data
+    .trim()
+    .trim_end_matches('=')
+    .replace('-', "+")
+    .replace('_', "/");
+  while standard.len() % 4 != 0 {
+    standard.push('=');
+  }
+  Ok(decode_block(&standard)?)
+}
+
+/// Checks the keys which a browser sent for its push subscription, base64url encoded like the
+/// browser gives them out.
+pub fn check_push_subscription_keys(p256dh: &str, auth: &str) -> Result<(), LemmyError> {
+  let public_key = base64_url_decode(p256dh)?;
+  let auth_secret = base64_url_decode(auth)?;
+  if public_key.len() != PUBLIC_KEY_LENGTH || auth_secret.len() != AUTH_SECRET_LENGTH {
+    return Err(anyhow!("Invalid keys for push subscription").into());
+  }
+  let group = p256()?;
+  let mut ctx = BigNumContext::new()?;
+  EcPoint::from_bytes(&group, &public_key, &mut ctx)?;
+  Ok(())
+}
+
+fn read_vapid_key(private_key_pem: &[u8]) -> Result<EcKey<Private>, LemmyError> {
+  let key = EcKey::private_key_from_pem(private_key_pem)?;
+  if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+    return Err(anyhow!("The VAPID key has to be on the curve prime256v1").into());
+  }
+  Ok(key)
+}
+
+fn public_key_bytes(key: &EcKeyRef<Private>) -> Result<Vec<u8>, ErrorStack> {
+  let mut ctx = BigNumContext::new()?;
+  key
+    .public_key()
+    .to_bytes(key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)
+}
+
+/// The public key of the VAPID key, which browsers need as `applicationServerKey` to subscribe.
+pub fn vapid_public_key(private_key_pem: &[u8]) -> Result<String, LemmyError> {
+  let key = read_vapid_key(private_key_pem)?;
+  Ok(base64_url_encode(&public_key_bytes(&key)?))
+}
+
+/// The Authorization header of a push to the endpoint, with a JWT for the origin of the endpoint,
+/// signed with ES256. `subject` is a mailto: or https: url where the push service can reach the
+/// admin, `now` is in unix time.
+pub fn vapid_authorization(
+  endpoint: &Url,
+  subject: &str,
+  private_key_pem: &[u8],
+  now: i64,
+) -> Result<String, LemmyError> {
+  let key = read_vapid_key(private_key_pem)?;
+  let header = base64_url_encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+  let claims = serde_json::json!({
+    "aud": endpoint.origin().ascii_serialization(),
+    "exp": now + VAPID_TOKEN_HOURS * 60 * 60,
+    "sub": subject,
+  });
+  let signing_input = format!(
+    "{}.{}",
+    header,
+    base64_url_encode(claims.to_string().as_bytes())
+  );
+
+  let digest = hash(MessageDigest::sha256(), signing_input.as_bytes())?;
+  let signature = EcdsaSig::sign(&digest, &key)?;
+  // JWS wants r and s as 32 bytes each, instead of DER
+  let mut raw_signature = Vec::with_capacity(64);
+  for n in &[signature.r(), signature.s()] {
+    let bytes = n.to_vec();
+    raw_signature.extend(std::iter::repeat(0).take(32 - bytes.len()));
+    raw_signature.extend(bytes);
+  }
+
+  Ok(format!(
+    "vapid t={}.{}, k={}",
+    signing_input,
+    base64_url_encode(&raw_signature),
+    base64_url_encode(&public_key_bytes(&key)?)
+  ))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+  let key = PKey::hmac(key)?;
+  let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+  signer.update(data)?;
+  signer.sign_to_vec()
+}
+
+/// Encrypts the payload for the browser of a push subscription, with a new key and salt for every
+/// push. `p256dh` and `auth` are the keys of the subscription, base64url encoded.
+pub fn encrypt_push_payload(
+  payload: &[u8],
+  p256dh: &str,
+  auth: &str,
+) -> Result<Vec<u8>, LemmyError> {
+  let group = p256()?;
+  let sender_key = EcKey::generate(&group)?;
+  let mut salt = [0; 16];
+  rand_bytes(&mut salt)?;
+  encrypt_push_payload_with(
+    payload,
+    &base64_url_decode(p256dh)?,
+    &base64_url_decode(auth)?,
+    &sender_key,
+    &salt,
+  )
+}
+
+/// The body of a push, encrypted with the given key and salt of the instance.
+pub fn encrypt_push_payload_with(
+  payload: &[u8],
+  ua_public: &[u8],
+  auth_secret: &[u8],
+  sender_key: &EcKeyRef<Private>,
+  salt: &[u8],
+) -> Result<Vec<u8>, LemmyError> {
+  if payload.len() > MAX_PUSH_PAYLOAD_LENGTH {
+    return Err(anyhow!("Push payload is {} bytes long", payload.len()).into());
+  }
+  if ua_public.len() != PUBLIC_KEY_LENGTH || auth_secret.len() != AUTH_SECRET_LENGTH {
+    return Err(anyhow!("Invalid keys for push subscription").into());
+  }
+
+  let group = p256()?;
+  let mut ctx = BigNumContext::new()?;
+  let ua_point = EcPoint::from_bytes(&group, ua_public, &mut ctx)?;
+  let ua_key = PKey::from_ec_key(EcKey::from_public_key(&group, &ua_point)?)?;
+  let sender_pkey = PKey::from_ec_key(sender_key.to_owned())?;
+  let mut deriver = Deriver::new(&sender_pkey)?;
+  deriver.set_peer(&ua_key)?;
+  let shared_secret = deriver.derive_to_vec()?;
+  let as_public = public_key_bytes(sender_key)?;
+
+  // HKDF with sha256, where every output fits into the first block
+  let mut key_info = b"WebPush: info\0".to_vec();
+  key_info.extend_from_slice(ua_public);
+  key_info.extend_from_slice(&as_public);
+  key_info.push(1);
+  let ikm = hmac_sha256(&hmac_sha256(auth_secret, &shared_secret)?, &key_info)?;
+  let prk = hmac_sha256(salt, &ikm)?;
+  let cek = hmac_sha256(&prk, b"Content-Encoding: aes128gcm\0\x01")?;
+  let nonce = hmac_sha256(&prk, b"Content-Encoding: nonce\0\x01")?;
+
+  // The delimiter of the last record, without further padding
+  let mut plaintext = payload.to_vec();
+  plaintext.push(2);
+  let mut tag = [0; 16];
+  let ciphertext = encrypt_aead(
+    Cipher::aes_128_gcm(),
+    &cek[..16],
+    Some(&nonce[..12]),
+    &[],
+    &plaintext,
+    &mut tag,
+  )?;
+
+  let mut body = salt.to_vec();
+  body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+  body.push(as_public.len() as u8);
+  body.extend_from_slice(&as_public);
+  body.extend_from_slice(&ciphertext);
+  body.extend_from_slice(&tag);
+  Ok(body)
+}
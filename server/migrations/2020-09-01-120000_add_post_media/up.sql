@@ -0,0 +1,13 @@
+-- Ordered image attachments for gallery posts
+create table post_media (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null,
+  position int not null,
+  url text not null,
+  caption text,
+  alt_text text,
+  published timestamp not null default now(),
+  unique(post_id, position)
+);
+
+create index idx_post_media_post on post_media (post_id);
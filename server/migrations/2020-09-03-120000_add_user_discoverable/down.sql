@@ -0,0 +1 @@
+alter table user_ drop column discoverable;
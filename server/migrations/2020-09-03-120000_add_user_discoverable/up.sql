@@ -0,0 +1,2 @@
+-- Users who aren't discoverable are left out of search, and the flag is federated
+alter table user_ add column discoverable boolean default true not null;
@@ -0,0 +1,2 @@
+drop table saved_search_match;
+drop table saved_search;
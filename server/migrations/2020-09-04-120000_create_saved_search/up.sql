@@ -0,0 +1,22 @@
+create table saved_search (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  query text not null,
+  keywords text[] not null,
+  community_id int references community on update cascade on delete cascade,
+  creator_id int references user_ on update cascade on delete cascade,
+  published timestamp not null default now()
+);
+
+-- New posts are matched against the keywords of every saved search, so this needs to be fast
+create index idx_saved_search_keywords on saved_search using gin (keywords);
+create index idx_saved_search_user on saved_search (user_id);
+
+create table saved_search_match (
+  id serial primary key,
+  saved_search_id int references saved_search on update cascade on delete cascade not null,
+  post_id int references post on update cascade on delete cascade not null,
+  read boolean default false not null,
+  published timestamp not null default now(),
+  unique(saved_search_id, post_id)
+);
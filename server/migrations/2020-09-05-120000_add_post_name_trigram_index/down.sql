@@ -0,0 +1,3 @@
+drop index idx_post_aggregates_fast_name_trgm;
+
+drop extension pg_trgm;
@@ -0,0 +1,4 @@
+-- Trigram similarity, used to suggest similar posts at submission time
+create extension if not exists pg_trgm;
+
+create index idx_post_aggregates_fast_name_trgm on post_aggregates_fast using gin (name gin_trgm_ops);
@@ -0,0 +1,18 @@
+-- The spam score of a piece of local content, computed when it was created
+create table spam_score (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade,
+  comment_id int references comment on update cascade on delete cascade,
+  private_message_id int references private_message on update cascade on delete cascade,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  score real not null,
+  reasons text[] not null,
+  content_hash text not null,
+  reported boolean default false not null,
+  removed boolean default false not null,
+  published timestamp not null default now()
+);
+
+-- Used to find duplicate content
+create index idx_spam_score_content_hash on spam_score (content_hash, published);
+create index idx_spam_score_reported on spam_score (reported, published desc);
@@ -0,0 +1,2 @@
+alter table community_settings drop column trust_min_account_age_days;
+alter table community_settings drop column trust_min_karma;
@@ -0,0 +1,3 @@
+-- Per community overrides of the instance trust thresholds, null means the instance one is used
+alter table community_settings add column trust_min_account_age_days int;
+alter table community_settings add column trust_min_karma int;
@@ -0,0 +1,3 @@
+drop index idx_comment_aggregates_fast_post_published;
+drop index idx_comment_aggregates_fast_post_score;
+drop index idx_comment_parent_creator;
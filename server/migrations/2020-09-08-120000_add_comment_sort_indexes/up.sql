@@ -0,0 +1,6 @@
+-- For the comment sorts of a single post
+create index idx_comment_aggregates_fast_post_published on comment_aggregates_fast (post_id, published);
+create index idx_comment_aggregates_fast_post_score on comment_aggregates_fast (post_id, score desc, published desc);
+
+-- For the Q&A sort, which looks for replies of the post creator
+create index idx_comment_parent_creator on comment (parent_id, creator_id);
@@ -0,0 +1,2 @@
+drop table community_notification_post;
+drop table community_notification;
@@ -0,0 +1,18 @@
+create table community_notification (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  mod_posts_only boolean default false not null,
+  send_email boolean default false not null,
+  published timestamp not null default now(),
+  unique(community_id, user_id)
+);
+
+create table community_notification_post (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  post_id int references post on update cascade on delete cascade not null,
+  read boolean default false not null,
+  published timestamp not null default now(),
+  unique(user_id, post_id)
+);
@@ -0,0 +1 @@
+drop table thread_mute;
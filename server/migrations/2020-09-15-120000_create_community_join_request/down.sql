@@ -0,0 +1,4 @@
+drop view community_join_request_view;
+drop table community_join_request;
+alter table community_settings drop column join_question;
+alter table community_settings drop column join_requires_approval;
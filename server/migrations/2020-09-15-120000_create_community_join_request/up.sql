@@ -0,0 +1,30 @@
+-- Communities can require mods to approve who joins them, optionally answering a question first.
+-- Only members and mods can post in them.
+alter table community_settings add column join_requires_approval boolean default false not null;
+alter table community_settings add column join_question text;
+
+-- A pending application to join a community, until a mod approves or rejects it
+create table community_join_request (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  application text,
+  published timestamp not null default now(),
+  unique(community_id, user_id)
+);
+
+create view community_join_request_view as
+select
+  jr.*,
+  u.actor_id as user_actor_id,
+  u.local as user_local,
+  u.name as user_name,
+  u.preferred_username as user_preferred_username,
+  u.avatar as avatar,
+  c.actor_id as community_actor_id,
+  c.local as community_local,
+  c.name as community_name,
+  c.icon as community_icon
+from community_join_request jr
+inner join user_ u on jr.user_id = u.id
+inner join community c on jr.community_id = c.id;
@@ -0,0 +1 @@
+alter table community_settings drop column private;
@@ -0,0 +1,2 @@
+-- Private communities only show their content to members, and can only be joined with a mod's approval
+alter table community_settings add column private boolean default false not null;
@@ -0,0 +1,2 @@
+-- Local only communities don't federate, eg for the meta or admin communities of an instance
+alter table community_settings add column local_only boolean default false not null;
@@ -0,0 +1,17 @@
+-- Edits during the first ninja_edit_minutes after publishing aren't marked as edited, and posts and
+-- comments can't be edited anymore after edit_limit_days.
+alter table site add column ninja_edit_minutes int default 2;
+alter table site add column edit_limit_days int;
+
+drop view site_view;
+create view site_view as 
+select s.*,
+u.name as creator_name,
+u.preferred_username as creator_preferred_username, 
+u.avatar as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s
+left join user_ u on s.creator_id = u.id;
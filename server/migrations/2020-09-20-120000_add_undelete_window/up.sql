@@ -0,0 +1,21 @@
+-- Authors can restore their deleted posts and comments during the undelete_window_days of the site,
+-- after that the deletion is permanent.
+alter table site add column undelete_window_days int;
+alter table post add column deleted_at timestamp;
+alter table comment add column deleted_at timestamp;
+
+update post set deleted_at = coalesce(updated, published) where deleted = true;
+update comment set deleted_at = coalesce(updated, published) where deleted = true;
+
+drop view site_view;
+create view site_view as 
+select s.*,
+u.name as creator_name,
+u.preferred_username as creator_preferred_username, 
+u.avatar as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s
+left join user_ u on s.creator_id = u.id;
@@ -0,0 +1 @@
+drop table removal_reason;
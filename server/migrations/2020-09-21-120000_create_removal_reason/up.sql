@@ -0,0 +1,10 @@
+-- Reasons that mods can pick from when removing posts and comments of their community
+create table removal_reason (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  title varchar(100) not null,
+  reason text not null,
+  published timestamp not null default now()
+);
+
+create index idx_removal_reason_community on removal_reason (community_id);
@@ -0,0 +1,2 @@
+drop view mod_bulk_action_view;
+drop table mod_bulk_action;
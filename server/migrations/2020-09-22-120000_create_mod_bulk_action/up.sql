@@ -0,0 +1,22 @@
+-- Bulk removals and bans of mods. They run in the background, so the progress is saved here, and
+-- each one is a single entry in the modlog.
+create table mod_bulk_action (
+  id serial primary key,
+  mod_user_id int references user_ on update cascade on delete cascade not null,
+  community_id int references community on update cascade on delete cascade not null,
+  other_user_ids int[] not null,
+  banned boolean not null,
+  since timestamp,
+  until timestamp,
+  reason text,
+  total int not null default 0,
+  done int not null default 0,
+  finished boolean not null default false,
+  when_ timestamp not null default now()
+);
+
+create view mod_bulk_action_view as 
+select mba.*,
+(select name from user_ u where mba.mod_user_id = u.id) as mod_user_name,
+(select name from community c where mba.community_id = c.id) as community_name
+from mod_bulk_action mba;
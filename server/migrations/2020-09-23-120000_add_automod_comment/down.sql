@@ -0,0 +1,2 @@
+alter table community_settings drop column automod_user_id;
+alter table community_settings drop column automod_comment;
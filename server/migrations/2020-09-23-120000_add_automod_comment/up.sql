@@ -0,0 +1,3 @@
+-- A comment that the automod user of the community posts and pins on every new post
+alter table community_settings add column automod_comment text;
+alter table community_settings add column automod_user_id int references user_ on update cascade on delete set null;
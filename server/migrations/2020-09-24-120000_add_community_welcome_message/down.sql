@@ -0,0 +1,3 @@
+drop table community_welcome;
+alter table user_ drop column receive_welcome_messages;
+alter table community_settings drop column welcome_message;
@@ -0,0 +1,14 @@
+-- A private message that the automod user of the community sends to new subscribers
+alter table community_settings add column welcome_message text;
+alter table user_ add column receive_welcome_messages boolean default true not null;
+
+-- Every user gets the welcome message of a community only once
+create table community_welcome (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now(),
+  unique(community_id, user_id)
+);
+
+create index idx_community_welcome_user_published on community_welcome (user_id, published);
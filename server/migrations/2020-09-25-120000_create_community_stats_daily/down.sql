@@ -0,0 +1,14 @@
+drop trigger community_stats_daily_spam_score on spam_score;
+drop trigger community_stats_daily_mod_remove_comment on mod_remove_comment;
+drop trigger community_stats_daily_mod_remove_post on mod_remove_post;
+drop trigger community_stats_daily_comment on comment;
+drop trigger community_stats_daily_post on post;
+drop trigger community_stats_daily_follower on community_follower;
+drop function community_stats_daily_spam_score();
+drop function community_stats_daily_mod_remove_comment();
+drop function community_stats_daily_mod_remove_post();
+drop function community_stats_daily_comment();
+drop function community_stats_daily_post();
+drop function community_stats_daily_follower();
+drop function community_stats_daily_add(int, text);
+drop table community_stats_daily;
@@ -0,0 +1,203 @@
+-- Daily counts for the community stats of mods, kept up to date by triggers
+create table community_stats_daily (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  day date not null,
+  subscribers_gained int not null default 0,
+  subscribers_lost int not null default 0,
+  posts int not null default 0,
+  comments int not null default 0,
+  removals int not null default 0,
+  reports int not null default 0,
+  unique(community_id, day)
+);
+
+create or replace function community_stats_daily_add(for_community_id int, stat text)
+returns void language plpgsql
+as $$
+begin
+  IF (for_community_id is null) THEN
+    return;
+  END IF;
+
+  execute format(
+    'insert into community_stats_daily (community_id, day, %1$I) values ($1, current_date, 1)
+     on conflict (community_id, day) do update set %1$I = community_stats_daily.%1$I + 1',
+    stat
+  ) using for_community_id;
+end $$;
+
+-- Community follower
+
+create or replace function community_stats_daily_follower()
+returns trigger language plpgsql
+as $$
+begin
+  -- Followers are also deleted along with the community, which has no stats anymore then
+  IF (TG_OP = 'DELETE') THEN
+    IF exists (select 1 from community where id = OLD.community_id) THEN
+      perform community_stats_daily_add(OLD.community_id, 'subscribers_lost');
+    END IF;
+  ELSIF (TG_OP = 'INSERT') THEN
+    perform community_stats_daily_add(NEW.community_id, 'subscribers_gained');
+  END IF;
+
+  return null;
+end $$;
+
+create trigger community_stats_daily_follower
+after insert or delete
+on community_follower
+for each row
+execute procedure community_stats_daily_follower();
+
+-- Post
+
+create or replace function community_stats_daily_post()
+returns trigger language plpgsql
+as $$
+begin
+  perform community_stats_daily_add(NEW.community_id, 'posts');
+  return null;
+end $$;
+
+create trigger community_stats_daily_post
+after insert
+on post
+for each row
+execute procedure community_stats_daily_post();
+
+-- Comment
+
+create or replace function community_stats_daily_comment()
+returns trigger language plpgsql
+as $$
+begin
+  perform community_stats_daily_add(
+    (select community_id from post where id = NEW.post_id),
+    'comments'
+  );
+  return null;
+end $$;
+
+create trigger community_stats_daily_comment
+after insert
+on comment
+for each row
+execute procedure community_stats_daily_comment();
+
+-- Removals, counted from the modlog so that restores don't count
+
+create or replace function community_stats_daily_mod_remove_post()
+returns trigger language plpgsql
+as $$
+begin
+  IF (NEW.removed is distinct from false) THEN
+    perform community_stats_daily_add(
+      (select community_id from post where id = NEW.post_id),
+      'removals'
+    );
+  END IF;
+
+  return null;
+end $$;
+
+create trigger community_stats_daily_mod_remove_post
+after insert
+on mod_remove_post
+for each row
+execute procedure community_stats_daily_mod_remove_post();
+
+create or replace function community_stats_daily_mod_remove_comment()
+returns trigger language plpgsql
+as $$
+begin
+  IF (NEW.removed is distinct from false) THEN
+    perform community_stats_daily_add(
+      (select p.community_id from comment c join post p on p.id = c.post_id where c.id = NEW.comment_id),
+      'removals'
+    );
+  END IF;
+
+  return null;
+end $$;
+
+create trigger community_stats_daily_mod_remove_comment
+after insert
+on mod_remove_comment
+for each row
+execute procedure community_stats_daily_mod_remove_comment();
+
+-- Reports of the spam scoring
+
+create or replace function community_stats_daily_spam_score()
+returns trigger language plpgsql
+as $$
+begin
+  IF (NEW.reported) THEN
+    perform community_stats_daily_add(
+      coalesce(
+        (select community_id from post where id = NEW.post_id),
+        (select p.community_id from comment c join post p on p.id = c.post_id where c.id = NEW.comment_id)
+      ),
+      'reports'
+    );
+  END IF;
+
+  return null;
+end $$;
+
+create trigger community_stats_daily_spam_score
+after insert
+on spam_score
+for each row
+execute procedure community_stats_daily_spam_score();
+
+-- Fill in the stats of the existing content. Unsubscribes weren't recorded until now.
+
+insert into community_stats_daily (community_id, day, subscribers_gained)
+select community_id, published::date, count(*)
+from community_follower
+group by community_id, published::date;
+
+insert into community_stats_daily (community_id, day, posts)
+select community_id, published::date, count(*)
+from post
+group by community_id, published::date
+on conflict (community_id, day) do update set posts = excluded.posts;
+
+insert into community_stats_daily (community_id, day, comments)
+select p.community_id, c.published::date, count(*)
+from comment c
+join post p on p.id = c.post_id
+group by p.community_id, c.published::date
+on conflict (community_id, day) do update set comments = excluded.comments;
+
+insert into community_stats_daily (community_id, day, removals)
+select community_id, day, sum(removals)
+from (
+  select p.community_id, mrp.when_::date as day, count(*) as removals
+  from mod_remove_post mrp
+  join post p on p.id = mrp.post_id
+  where mrp.removed is distinct from false
+  group by p.community_id, mrp.when_::date
+  union all
+  select p.community_id, mrc.when_::date as day, count(*) as removals
+  from mod_remove_comment mrc
+  join comment c on c.id = mrc.comment_id
+  join post p on p.id = c.post_id
+  where mrc.removed is distinct from false
+  group by p.community_id, mrc.when_::date
+) removals
+group by community_id, day
+on conflict (community_id, day) do update set removals = excluded.removals;
+
+insert into community_stats_daily (community_id, day, reports)
+select coalesce(p.community_id, cp.community_id), ss.published::date, count(*)
+from spam_score ss
+left join post p on p.id = ss.post_id
+left join comment c on c.id = ss.comment_id
+left join post cp on cp.id = c.post_id
+where ss.reported and coalesce(p.community_id, cp.community_id) is not null
+group by coalesce(p.community_id, cp.community_id), ss.published::date
+on conflict (community_id, day) do update set reports = excluded.reports;
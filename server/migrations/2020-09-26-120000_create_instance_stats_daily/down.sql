@@ -0,0 +1,4 @@
+drop function refresh_instance_stats_daily(date);
+drop index idx_activity_published;
+drop table instance_peer_stats_daily;
+drop table instance_stats_daily;
@@ -0,0 +1,69 @@
+-- Daily instance stats for the admin dashboard, which the stats job updates every hour
+create table instance_stats_daily (
+  id serial primary key,
+  day date not null unique,
+  registrations int not null default 0,
+  active_users int not null default 0,
+  activities_in int not null default 0,
+  activities_out int not null default 0,
+  queue_pending int not null default 0,
+  queue_running int not null default 0,
+  queue_failed int not null default 0,
+  database_bytes bigint not null default 0,
+  updated timestamp not null default now()
+);
+
+-- The federation traffic with every other instance
+create table instance_peer_stats_daily (
+  id serial primary key,
+  day date not null,
+  domain text not null,
+  activities_in int not null default 0,
+  activities_out int not null default 0,
+  unique(day, domain)
+);
+
+create index idx_activity_published on activity (published);
+
+-- Recounts everything that can be counted from the database. The outgoing activities and the
+-- queue depths are only known to the server, so they're left alone.
+create or replace function refresh_instance_stats_daily(for_day date)
+returns void language plpgsql
+as $$
+begin
+  insert into instance_stats_daily (day, registrations, active_users, activities_in, database_bytes, updated)
+  select
+    for_day,
+    (select count(*) from user_ where local and published >= for_day and published < for_day + 1),
+    (
+      select count(distinct active.user_id) from (
+        select creator_id as user_id from post where local and published >= for_day and published < for_day + 1
+        union all
+        select creator_id from comment where local and published >= for_day and published < for_day + 1
+        union all
+        select user_id from post_like where published >= for_day and published < for_day + 1
+        union all
+        select user_id from comment_like where published >= for_day and published < for_day + 1
+      ) active
+      join user_ u on u.id = active.user_id
+      where u.local
+    ),
+    (select count(*) from activity where not local and published >= for_day and published < for_day + 1),
+    pg_database_size(current_database()),
+    now()
+  on conflict (day) do update set
+    registrations = excluded.registrations,
+    active_users = excluded.active_users,
+    activities_in = excluded.activities_in,
+    database_bytes = excluded.database_bytes,
+    updated = excluded.updated;
+
+  insert into instance_peer_stats_daily (day, domain, activities_in)
+  select for_day, substring(data->>'actor' from '^[a-z]+://([^/]+)') as peer, count(*)
+  from activity
+  where not local
+  and published >= for_day and published < for_day + 1
+  and substring(data->>'actor' from '^[a-z]+://([^/]+)') is not null
+  group by peer
+  on conflict (day, domain) do update set activities_in = excluded.activities_in;
+end $$;
@@ -0,0 +1,8 @@
+-- Kept out of user_, because its triggers refresh all the posts and comments of a user on every
+-- update.
+create table user_presence (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null unique,
+  last_active timestamp not null default now(),
+  show_presence boolean default true not null
+);
@@ -0,0 +1 @@
+drop table default_community;
@@ -0,0 +1,6 @@
+-- The communities which new users are subscribed to when they register
+create table default_community (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null unique,
+  published timestamp not null default now()
+);
@@ -0,0 +1,3 @@
+-- Deleted accounts are kept, so that lookups of their actor don't fail, but their content is
+-- attributed to the local "deleted" placeholder user, which a code migration creates
+alter table user_ add column deleted boolean default false not null;
@@ -0,0 +1,2 @@
+alter table instance_peer_stats_daily drop column requests_retried;
+alter table instance_peer_stats_daily drop column circuit_breaker_trips;
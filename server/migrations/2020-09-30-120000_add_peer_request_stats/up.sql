@@ -0,0 +1,4 @@
+-- Retried requests to other instances, and how often their circuit breaker stopped us from
+-- sending them requests
+alter table instance_peer_stats_daily add column requests_retried int not null default 0;
+alter table instance_peer_stats_daily add column circuit_breaker_trips int not null default 0;
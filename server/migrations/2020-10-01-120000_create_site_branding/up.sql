@@ -0,0 +1,12 @@
+-- The favicon and default theme of the instance. The logo and banner are the icon and banner of
+-- the site. There is only ever one row.
+create table site_branding (
+  id int primary key default 1 check (id = 1),
+  favicon text,
+  primary_color text,
+  secondary_color text,
+  background_color text,
+  text_color text,
+  default_theme text,
+  updated timestamp not null default now()
+);
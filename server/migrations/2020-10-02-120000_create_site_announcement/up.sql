@@ -0,0 +1,22 @@
+-- Announcements of the admins, which clients show as a banner between starts and ends
+create table site_announcement (
+  id serial primary key,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  content text not null,
+  severity text not null,
+  starts timestamp not null default now(),
+  ends timestamp,
+  published timestamp not null default now(),
+  updated timestamp
+);
+
+create index idx_site_announcement_starts_ends on site_announcement (starts, ends);
+
+-- The announcements a user closed, so that they aren't shown to them again
+create table site_announcement_dismissal (
+  id serial primary key,
+  announcement_id int references site_announcement on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now(),
+  unique(announcement_id, user_id)
+);
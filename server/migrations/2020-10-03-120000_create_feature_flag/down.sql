@@ -0,0 +1,2 @@
+drop table feature_flag_user;
+drop table feature_flag;
@@ -0,0 +1,21 @@
+-- Experimental features which admins turn on gradually. A disabled flag is off for everyone,
+-- otherwise it's on for rollout_percentage of the users, or for the ones set in feature_flag_user.
+create table feature_flag (
+  id serial primary key,
+  name varchar(50) unique not null,
+  description text,
+  enabled boolean default false not null,
+  rollout_percentage smallint default 0 not null check (rollout_percentage between 0 and 100),
+  published timestamp not null default now(),
+  updated timestamp
+);
+
+-- Turns a flag on or off for single users, regardless of the rollout percentage
+create table feature_flag_user (
+  id serial primary key,
+  feature_flag_id int references feature_flag on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  enabled boolean not null,
+  published timestamp not null default now(),
+  unique(feature_flag_id, user_id)
+);
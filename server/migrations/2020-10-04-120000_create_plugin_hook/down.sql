@@ -0,0 +1 @@
+drop table plugin_hook;
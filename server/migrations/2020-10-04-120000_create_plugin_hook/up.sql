@@ -0,0 +1,16 @@
+-- External services which admins register, and which get called at hook points like
+-- PrePostCreate. They can change or reject what is about to happen.
+create table plugin_hook (
+  id serial primary key,
+  name varchar(50) not null,
+  url text not null,
+  hook_point text not null,
+  timeout_ms int default 1000 not null,
+  -- What happens when the service can't be reached or answers with nonsense, Allow or Reject
+  failure_policy text default 'Allow' not null,
+  -- Used to sign the callbacks, so that the service knows they come from this instance
+  secret text,
+  enabled boolean default true not null,
+  published timestamp not null default now(),
+  updated timestamp
+);
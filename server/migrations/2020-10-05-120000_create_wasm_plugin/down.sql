@@ -0,0 +1 @@
+drop table wasm_plugin;
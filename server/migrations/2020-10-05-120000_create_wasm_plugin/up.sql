@@ -0,0 +1,9 @@
+-- Which of the wasm modules in the plugin directory are turned on. Modules without a row here
+-- stay off, so that copying a file to the server doesn't run it.
+create table wasm_plugin (
+  id serial primary key,
+  name varchar(100) not null unique,
+  enabled boolean default false not null,
+  published timestamp not null default now(),
+  updated timestamp
+);
@@ -0,0 +1,21 @@
+-- Recurring posts which mods define for their community, like weekly discussion threads. The
+-- automod user of the community creates them.
+create table scheduled_post (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  -- Can contain placeholders like {date}
+  title_template varchar(200) not null,
+  body text,
+  -- Cron-like, in UTC
+  schedule text not null,
+  -- Stickies the new post, and unstickies the one from the last time
+  stickied boolean default false not null,
+  enabled boolean default true not null,
+  next_run timestamp not null,
+  last_post_id int references post on update cascade on delete set null,
+  published timestamp not null default now(),
+  updated timestamp
+);
+
+create index idx_scheduled_post_next_run on scheduled_post (next_run) where enabled;
@@ -0,0 +1,10 @@
+-- How clients should show a community, federated with the group so that remote communities look
+-- like on their own instance. The icon and banner are in the community table.
+create table community_appearance (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null unique,
+  -- Like #1e90ff
+  accent_color varchar(7),
+  -- The sort type of the community page
+  default_sort varchar(20)
+);
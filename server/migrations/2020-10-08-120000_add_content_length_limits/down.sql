@@ -0,0 +1,2 @@
+alter table community_settings drop column max_post_body_length;
+alter table community_settings drop column max_comment_length;
@@ -0,0 +1,3 @@
+-- Communities can have lower length limits than the instance, null means the instance one
+alter table community_settings add column max_post_body_length int;
+alter table community_settings add column max_comment_length int;
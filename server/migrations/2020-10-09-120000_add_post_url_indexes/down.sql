@@ -0,0 +1,3 @@
+drop index idx_post_aggregates_fast_url_domain;
+drop index idx_post_aggregates_fast_url;
+drop function url_domain(text);
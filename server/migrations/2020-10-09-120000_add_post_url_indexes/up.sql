@@ -0,0 +1,7 @@
+-- The host of a url, lowercased and without www., to find the posts linking to a domain
+create function url_domain(url text) returns text as $$
+  select regexp_replace(lower(substring(url from '^[a-zA-Z][a-zA-Z0-9+.-]*://(?:[^/?#@]*@)?([^/?#:]+)')), '^www\.', '')
+$$ language sql immutable;
+
+create index idx_post_aggregates_fast_url on post_aggregates_fast (url);
+create index idx_post_aggregates_fast_url_domain on post_aggregates_fast (url_domain(url));
@@ -0,0 +1,4 @@
+drop table domain_post_approval;
+drop trigger domain_post_count on post;
+drop function domain_post_count();
+drop table domain;
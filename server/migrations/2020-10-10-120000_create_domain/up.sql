@@ -0,0 +1,55 @@
+-- The domains which posts link to, with their post counts kept up to date by a trigger, and what
+-- admins allow for them
+create table domain (
+  id serial primary key,
+  -- Like example.com, from url_domain
+  name text not null unique,
+  post_count int not null default 0,
+  last_post timestamp,
+  -- allowed, blocked or greylisted
+  policy varchar(20) not null default 'allowed',
+  policy_reason text,
+  published timestamp not null default now(),
+  updated timestamp
+);
+
+insert into domain (name, post_count, last_post, published)
+select url_domain(url), count(*), max(published), min(published)
+from post
+where url_domain(url) is not null
+group by url_domain(url);
+
+create or replace function domain_post_count()
+returns trigger language plpgsql
+as $$
+begin
+  IF (TG_OP = 'UPDATE' and OLD.url is not distinct from NEW.url) THEN
+    return null;
+  END IF;
+
+  IF (TG_OP in ('INSERT', 'UPDATE') and url_domain(NEW.url) is not null) THEN
+    insert into domain (name, post_count, last_post) values (url_domain(NEW.url), 1, NEW.published)
+    on conflict (name) do update set
+      post_count = domain.post_count + 1,
+      last_post = greatest(domain.last_post, excluded.last_post);
+  END IF;
+  IF (TG_OP in ('DELETE', 'UPDATE') and url_domain(OLD.url) is not null) THEN
+    update domain set post_count = post_count - 1 where name = url_domain(OLD.url);
+  END IF;
+
+  return null;
+end $$;
+
+create trigger domain_post_count
+after insert or delete or update of url
+on post
+for each row
+execute procedure domain_post_count();
+
+-- Posts linking to a greylisted domain, which are kept removed until a mod or admin approves them
+create table domain_post_approval (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null unique,
+  domain_id int references domain on update cascade on delete cascade not null,
+  published timestamp not null default now()
+);
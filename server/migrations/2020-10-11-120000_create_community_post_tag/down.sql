@@ -0,0 +1,6 @@
+drop table post_tag;
+drop table community_post_tag;
+
+alter table community_settings drop column allowed_post_type;
+alter table community_settings drop column require_post_tag;
+alter table community_settings drop column title_pattern;
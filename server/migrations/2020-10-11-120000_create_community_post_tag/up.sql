@@ -0,0 +1,24 @@
+-- What communities ask of new posts: any, text_only or link_only
+alter table community_settings add column allowed_post_type varchar(20) not null default 'any';
+alter table community_settings add column require_post_tag boolean not null default false;
+-- A regular expression which the titles of new posts have to match
+alter table community_settings add column title_pattern text;
+
+-- The tags which the mods of a community offer for its posts
+create table community_post_tag (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  name varchar(50) not null,
+  published timestamp not null default now(),
+  unique (community_id, name)
+);
+
+-- At most one tag per post
+create table post_tag (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null unique,
+  tag_id int references community_post_tag on update cascade on delete cascade not null,
+  published timestamp not null default now()
+);
+
+create index idx_post_tag_tag_id on post_tag (tag_id);
@@ -0,0 +1,2 @@
+alter table community_settings drop column clean_titles;
+alter table community_settings drop column title_capitalization;
@@ -0,0 +1,4 @@
+-- Cleans up the whitespace and trailing punctuation of new post titles
+alter table community_settings add column clean_titles boolean not null default false;
+-- none, capitalize_first or no_all_caps
+alter table community_settings add column title_capitalization varchar(20) not null default 'none';
@@ -0,0 +1,3 @@
+alter table user_ drop column bot_account;
+alter table user_ drop column collapse_comments_below_score;
+alter table user_ drop column collapse_bot_comments;
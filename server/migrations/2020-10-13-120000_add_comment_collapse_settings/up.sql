@@ -0,0 +1,8 @@
+-- Accounts which post automatically, like the automod users of communities
+alter table user_ add column bot_account boolean not null default false;
+update user_ set bot_account = true where id in (select automod_user_id from community_settings);
+
+-- Comments are sent with a collapsed hint when their score is below this, or when their creator is a
+-- bot
+alter table user_ add column collapse_comments_below_score int;
+alter table user_ add column collapse_bot_comments boolean not null default false;
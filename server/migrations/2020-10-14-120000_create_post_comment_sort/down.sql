@@ -0,0 +1,2 @@
+drop table post_comment_sort;
+alter table user_ drop column remember_comment_sort;
@@ -0,0 +1,13 @@
+-- Users can choose to have the last comment sort they picked for a post applied again, for a while
+alter table user_ add column remember_comment_sort boolean not null default false;
+
+create table post_comment_sort (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  post_id int references post on update cascade on delete cascade not null,
+  comment_sort varchar(20) not null,
+  updated timestamp not null default now(),
+  unique(user_id, post_id)
+);
+
+create index idx_post_comment_sort_updated on post_comment_sort (updated);
@@ -0,0 +1,2 @@
+drop table post_edit;
+alter table community_settings drop column allow_post_url_edits;
@@ -0,0 +1,14 @@
+alter table community_settings add column allow_post_url_edits boolean not null default true;
+
+-- The version of a post before each edit of its title, url or body
+create table post_edit (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null,
+  editor_id int references user_ on update cascade on delete cascade not null,
+  name varchar(200) not null,
+  url text,
+  body text,
+  published timestamp not null default now()
+);
+
+create index idx_post_edit_post on post_edit (post_id);
@@ -0,0 +1,12 @@
+-- The instances of the remote users and communities we know, with the software they run according
+-- to their nodeinfo
+create table instance (
+  id serial primary key,
+  domain varchar(255) not null unique,
+  software varchar(255),
+  version varchar(255),
+  published timestamp not null default now(),
+  crawled timestamp
+);
+
+create index idx_instance_crawled on instance (crawled nulls first);
@@ -0,0 +1,2 @@
+alter table instance drop column supports_groups;
+alter table instance drop column supports_votes;
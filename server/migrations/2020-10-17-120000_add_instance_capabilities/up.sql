@@ -0,0 +1,7 @@
+-- What the software of an instance understands, derived from its nodeinfo. Instances whose software
+-- is unknown are assumed to understand everything, like before.
+alter table instance add column supports_groups boolean default true not null;
+alter table instance add column supports_votes boolean default true not null;
+
+-- Crawl all instances again to fill them in
+update instance set crawled = null;
@@ -0,0 +1,2 @@
+-- What public notes from microblogs mentioning the community become: ignore, post or post_or_comment
+alter table community_settings add column microblog_mentions varchar(20) not null default 'ignore';
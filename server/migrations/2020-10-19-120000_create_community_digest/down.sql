@@ -0,0 +1,3 @@
+alter table community_settings drop column digest_template;
+drop table community_digest;
+drop table community_digest_subscription;
@@ -0,0 +1,18 @@
+-- Followers of a community who get its weekly digest by email
+create table community_digest_subscription (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now(),
+  unique (community_id, user_id)
+);
+
+-- When the digest of a community was last sent
+create table community_digest (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null unique,
+  sent timestamp not null default now()
+);
+
+-- The text of the digest emails, with placeholders for the top posts and the new members
+alter table community_settings add column digest_template text;
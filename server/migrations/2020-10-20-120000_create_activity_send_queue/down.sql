@@ -0,0 +1 @@
+drop table activity_send_queue;
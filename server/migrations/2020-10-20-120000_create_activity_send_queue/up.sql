@@ -0,0 +1,15 @@
+-- Outgoing activities which weren't delivered yet, one row per inbox. Each inbox gets its
+-- activities in order, so a failed delivery holds back the later ones until it's retried.
+create table activity_send_queue (
+  id serial primary key,
+  activity text not null,
+  inbox text not null,
+  actor_id text not null,
+  published timestamp not null default now(),
+  attempts int not null default 0,
+  next_attempt timestamp not null default now(),
+  last_error text
+);
+
+create index idx_activity_send_queue_inbox on activity_send_queue (inbox, id);
+create index idx_activity_send_queue_next_attempt on activity_send_queue (next_attempt);
@@ -0,0 +1,16 @@
+-- Emails waiting to be sent. Sent emails are removed, failed ones are kept for the admins to see.
+create table email_outbox (
+  id serial primary key,
+  to_email text not null,
+  to_name text not null,
+  subject text not null,
+  html text not null,
+  reply_to text,
+  published timestamp not null default now(),
+  attempts int not null default 0,
+  next_attempt timestamp not null default now(),
+  last_error text,
+  failed boolean not null default false
+);
+
+create index idx_email_outbox_next_attempt on email_outbox (next_attempt) where not failed;
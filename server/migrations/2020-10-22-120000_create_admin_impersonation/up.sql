@@ -0,0 +1,13 @@
+-- The audit log of admins viewing the site as another user, for debugging what they reported.
+create table admin_impersonation (
+  id serial primary key,
+  admin_id int references user_ on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  reason text not null,
+  published timestamp not null default now(),
+  expires timestamp not null,
+  requests int not null default 0,
+  last_request timestamp
+);
+
+create index idx_admin_impersonation_published on admin_impersonation (published desc);
@@ -0,0 +1,16 @@
+-- Posts and comments which users reported to the mods of the community. Reports by remote users
+-- arrive as Flag activities, whose id is kept so that they are only stored once.
+create table content_report (
+  id serial primary key,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  post_id int references post on update cascade on delete cascade,
+  comment_id int references comment on update cascade on delete cascade,
+  reason text not null,
+  resolved boolean not null default false,
+  resolver_id int references user_ on update cascade on delete set null,
+  ap_id text unique,
+  published timestamp not null default now(),
+  check ((post_id is null) != (comment_id is null))
+);
+
+create index idx_content_report_open on content_report (post_id, comment_id) where not resolved;
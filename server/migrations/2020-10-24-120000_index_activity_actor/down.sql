@@ -0,0 +1 @@
+drop index idx_activity_actor;
@@ -0,0 +1,2 @@
+-- For the outboxes of local users and communities, which page through their activities.
+create index idx_activity_actor on activity ((data->>'actor'), id) where local;
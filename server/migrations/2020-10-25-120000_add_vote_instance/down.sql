@@ -0,0 +1,6 @@
+drop table user_ignored_vote_instance;
+drop trigger post_like_instance on post_like;
+drop trigger comment_like_instance on comment_like;
+drop function vote_instance();
+alter table post_like drop column instance;
+alter table comment_like drop column instance;
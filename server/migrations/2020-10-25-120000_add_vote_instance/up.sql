@@ -0,0 +1,48 @@
+-- The instance of remote voters, so that users can leave out the votes from some instances in the
+-- scores they see. Local votes have none.
+alter table post_like add column instance varchar(255);
+alter table comment_like add column instance varchar(255);
+
+create or replace function vote_instance()
+returns trigger language plpgsql
+as $$
+begin
+  select lower(substring(u.actor_id from '^[a-z]+://([^/:]+)'))
+  into NEW.instance
+  from user_ u
+  where u.id = NEW.user_id and not u.local;
+  return NEW;
+end $$;
+
+create trigger post_like_instance
+before insert
+on post_like
+for each row
+execute procedure vote_instance();
+
+create trigger comment_like_instance
+before insert
+on comment_like
+for each row
+execute procedure vote_instance();
+
+update post_like pl
+set instance = lower(substring(u.actor_id from '^[a-z]+://([^/:]+)'))
+from user_ u
+where u.id = pl.user_id and not u.local;
+
+update comment_like cl
+set instance = lower(substring(u.actor_id from '^[a-z]+://([^/:]+)'))
+from user_ u
+where u.id = cl.user_id and not u.local;
+
+create index idx_post_like_instance on post_like (post_id, instance) where instance is not null;
+create index idx_comment_like_instance on comment_like (comment_id, instance) where instance is not null;
+
+create table user_ignored_vote_instance (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  instance varchar(255) not null,
+  published timestamp not null default now(),
+  unique (user_id, instance)
+);
@@ -0,0 +1,11 @@
+-- A post which got lots of votes from one remote instance in a short time, for its mods to check
+create table vote_burst (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null,
+  instance varchar(255) not null,
+  votes int not null,
+  resolved boolean default false not null,
+  published timestamp not null default now()
+);
+
+create index idx_vote_burst_post_instance on vote_burst (post_id, instance, published);
@@ -0,0 +1 @@
+drop table sign_in_signal;
@@ -0,0 +1,13 @@
+-- Where and with what a local user signed up or logged in, for admins looking into ban evasion
+create table sign_in_signal (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  ip_range varchar(255),
+  fingerprint varchar(255),
+  registration boolean not null,
+  published timestamp not null default now()
+);
+
+create index idx_sign_in_signal_user on sign_in_signal (user_id);
+create index idx_sign_in_signal_ip_range on sign_in_signal (ip_range, published);
+create index idx_sign_in_signal_fingerprint on sign_in_signal (fingerprint, published);
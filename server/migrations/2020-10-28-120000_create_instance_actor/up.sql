@@ -0,0 +1,10 @@
+-- The keys of the Application actor of the instance itself, created by a code migration
+create table instance_actor (
+  id serial primary key,
+  private_key text not null,
+  public_key text not null,
+  published timestamp not null default now()
+);
+
+-- It replaces the local user which signed the fetches of the instance before
+delete from user_ where name = 'instance.actor' and local = true;
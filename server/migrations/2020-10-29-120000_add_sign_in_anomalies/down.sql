@@ -0,0 +1,4 @@
+alter table sign_in_signal drop column country;
+alter table sign_in_signal drop column anomalous;
+alter table sign_in_signal drop column reviewed;
+alter table sign_in_signal drop column revoked;
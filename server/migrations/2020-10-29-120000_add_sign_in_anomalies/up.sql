@@ -0,0 +1,6 @@
+-- Sign ins from a new device or country are flagged, so that the user can review them, and the
+-- token of a sign in can be revoked
+alter table sign_in_signal add column country varchar(2);
+alter table sign_in_signal add column anomalous boolean default false not null;
+alter table sign_in_signal add column reviewed boolean default false not null;
+alter table sign_in_signal add column revoked boolean default false not null;
@@ -0,0 +1 @@
+drop table csp_report;
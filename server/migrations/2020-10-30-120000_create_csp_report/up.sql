@@ -0,0 +1,14 @@
+-- Content security policy violations which browsers reported, for admins tuning the policy of the
+-- front end. The uris are kept without their query, so that no tokens end up here.
+create table csp_report (
+  id serial primary key,
+  document_uri text not null,
+  violated_directive text not null,
+  blocked_uri text,
+  source_file text,
+  line_number int,
+  disposition varchar(20),
+  published timestamp not null default now()
+);
+
+create index idx_csp_report_directive on csp_report (violated_directive, published);
@@ -0,0 +1,13 @@
+-- The ids of activities which the inboxes received, so that activities which are delivered again
+-- are only handled once. The same activity can be sent to several inboxes of the instance, like to
+-- a community and to a mentioned user, so the ids are kept per inbox. Ids older than two weeks are
+-- deleted, no sender retries that long.
+create table received_activity (
+  id serial primary key,
+  inbox text not null,
+  ap_id text not null,
+  published timestamp not null default now(),
+  unique (inbox, ap_id)
+);
+
+create index idx_received_activity_published on received_activity (published);
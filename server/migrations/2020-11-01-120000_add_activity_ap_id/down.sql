@@ -0,0 +1,2 @@
+drop index idx_activity_ap_id;
+alter table activity drop column ap_id;
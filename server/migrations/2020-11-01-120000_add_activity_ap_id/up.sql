@@ -0,0 +1,6 @@
+-- The id of the activity, so that local activities can be served by their id.
+alter table activity add column ap_id text;
+
+update activity set ap_id = data->>'id';
+
+create index idx_activity_ap_id on activity (ap_id);
@@ -0,0 +1,12 @@
+-- How often each post was viewed, flushed from memory every few minutes. The visitors are a
+-- HyperLogLog sketch, which estimates how many different users or addresses viewed the post
+-- without keeping who they were.
+create table post_view_count (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null,
+  views bigint not null default 0,
+  visitors bytea not null,
+  unique_views bigint not null default 0,
+  updated timestamp not null default now(),
+  unique(post_id)
+);
@@ -0,0 +1,3 @@
+-- Tokens handed out before sign ins were recorded can't be revoked one by one, so they all stop
+-- working once the password of the user is changed
+alter table user_ add column sessionless_tokens_revoked boolean default false not null;
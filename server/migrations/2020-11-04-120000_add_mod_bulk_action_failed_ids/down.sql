@@ -0,0 +1,9 @@
+drop view mod_bulk_action_view;
+alter table mod_bulk_action drop column failed_post_ids;
+alter table mod_bulk_action drop column failed_comment_ids;
+
+create view mod_bulk_action_view as 
+select mba.*,
+(select name from user_ u where mba.mod_user_id = u.id) as mod_user_name,
+(select name from community c where mba.community_id = c.id) as community_name
+from mod_bulk_action mba;
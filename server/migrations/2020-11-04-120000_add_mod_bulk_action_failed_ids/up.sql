@@ -0,0 +1,11 @@
+-- The posts and comments which a bulk removal couldn't remove, so that the mod can retry them
+alter table mod_bulk_action add column failed_post_ids int[] not null default '{}';
+alter table mod_bulk_action add column failed_comment_ids int[] not null default '{}';
+
+-- The view has to be recreated to pick up the new columns
+drop view mod_bulk_action_view;
+create view mod_bulk_action_view as 
+select mba.*,
+(select name from user_ u where mba.mod_user_id = u.id) as mod_user_name,
+(select name from community c where mba.community_id = c.id) as community_name
+from mod_bulk_action mba;
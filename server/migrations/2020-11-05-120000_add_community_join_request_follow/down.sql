@@ -0,0 +1 @@
+alter table community_join_request drop column follow;
@@ -0,0 +1,3 @@
+-- The Follow of a remote user asking to join, to echo it in the Accept or Reject once the mods
+-- decided
+alter table community_join_request add column follow jsonb;
@@ -0,0 +1,3 @@
+-- Communities which aren't discoverable are left out of search, with their posts and comments, and
+-- the flag is federated like the one of users
+alter table community_settings add column discoverable boolean not null default true;
@@ -0,0 +1,5 @@
+drop index idx_community_notification_post_email_pending;
+alter table community_notification_post drop column email_pending;
+alter table community_notification drop column email_digest;
+alter table community_notification drop column send_push;
+drop table push_subscription;
@@ -0,0 +1,19 @@
+-- The browsers of a user which get their notifications by web push, with the keys to encrypt the
+-- pushes for them
+create table push_subscription (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  endpoint text not null unique,
+  p256dh text not null,
+  auth text not null,
+  published timestamp not null default now()
+);
+
+alter table community_notification add column send_push boolean not null default false;
+-- Collects the emails of the community into a daily digest, instead of one email per post
+alter table community_notification add column email_digest boolean not null default false;
+-- Posts which go into the next digest email of the user
+alter table community_notification_post add column email_pending boolean not null default false;
+
+create index idx_community_notification_post_email_pending on community_notification_post (user_id)
+  where email_pending;
@@ -0,0 +1 @@
+alter table site_announcement drop column pushed;
@@ -0,0 +1,7 @@
+-- When the announcement was sent to the connected clients, null while it's scheduled. The job
+-- which sends scheduled announcements uses it, so that they are also sent after a restart.
+alter table site_announcement add column pushed timestamp;
+
+update site_announcement set pushed = starts where starts <= now();
+
+create index idx_site_announcement_not_pushed on site_announcement (starts) where pushed is null;
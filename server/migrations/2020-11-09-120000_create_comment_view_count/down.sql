@@ -0,0 +1 @@
+drop table comment_view_count;
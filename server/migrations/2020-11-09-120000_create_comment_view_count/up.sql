@@ -0,0 +1,11 @@
+-- How often each comment was viewed on its own, through its context, counted like the views of
+-- posts.
+create table comment_view_count (
+  id serial primary key,
+  comment_id int references comment on update cascade on delete cascade not null,
+  views bigint not null default 0,
+  visitors bytea not null,
+  unique_views bigint not null default 0,
+  updated timestamp not null default now(),
+  unique(comment_id)
+);
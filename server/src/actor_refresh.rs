@@ -0,0 +1,77 @@
+//! The actor refresh job, which refetches remote users and communities that weren't seen in a long
+//! time, to find the ones that are gone from their instance.
+use crate::{
+  apub::fetcher::{get_or_fetch_and_upsert_community, get_or_fetch_and_upsert_user},
+  LemmyContext,
+};
+use lemmy_api_structs::blocking;
+use lemmy_db::{community::Community, naive_now, user::User_};
+use lemmy_utils::{settings::Settings, LemmyError};
+use log::{debug, error};
+use std::time::Duration;
+use url::Url;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// At most this many users, and as many communities, are refetched every hour, so that other
+/// instances don't get all the requests at once.
+const REFRESH_BATCH_SIZE: i64 = 50;
+
+pub async fn run_actor_refresh_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(REFRESH_INTERVAL);
+  loop {
+    interval.tick().await;
+    let settings = Settings::get();
+    if !settings.federation.enabled || settings.maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = refresh_stale_actors(&context).await {
+      error!("Couldn't refresh the stale actors: {}", e);
+    }
+  }
+}
+
+async fn refresh_stale_actors(context: &LemmyContext) -> Result<(), LemmyError> {
+  let stale_actor_days = Settings::get().federation.stale_actor_days;
+  let refreshed_before = naive_now() - chrono::Duration::days(stale_actor_days);
+
+  let users = blocking(context.pool(), move |conn| {
+    User_::list_stale_remote(conn, refreshed_before, REFRESH_BATCH_SIZE)
+  })
+  .await??;
+  for user in users {
+    let actor_id = Url::parse(&user.actor_id)?;
+    let actor_context = context.with_fetch_budget();
+    // Gone users are deleted by the fetcher, the others are tried again next time they're stale
+    if let Err(e) = get_or_fetch_and_upsert_user(&actor_id, &actor_context).await {
+      debug!("Couldn't refresh the user {}: {}", user.actor_id, e);
+      let user_id = user.id;
+      blocking(context.pool(), move |conn| {
+        User_::mark_refreshed(conn, user_id)
+      })
+      .await??;
+    }
+  }
+
+  let communities = blocking(context.pool(), move |conn| {
+    Community::list_stale_remote(conn, refreshed_before, REFRESH_BATCH_SIZE)
+  })
+  .await??;
+  for community in communities {
+    let actor_id = Url::parse(&community.actor_id)?;
+    let actor_context = context.with_fetch_budget();
+    if let Err(e) = get_or_fetch_and_upsert_community(&actor_id, &actor_context).await {
+      debug!(
+        "Couldn't refresh the community {}: {}",
+        community.actor_id, e
+      );
+      let community_id = community.id;
+      blocking(context.pool(), move |conn| {
+        Community::mark_refreshed(conn, community_id)
+      })
+      .await??;
+    }
+  }
+
+  Ok(())
+}
@@ -0,0 +1,383 @@
+//! A socks5 proxy on the loopback interface, which all outbound requests go through. It resolves
+//! the host of each connection itself, refuses private addresses and connects to the address it
+//! checked, through the proxy of the config if there is one. `check_outbound_url` alone isn't
+//! enough, since the host is resolved again when connecting, and can resolve to another address by
+//! then.
+use crate::request::resolve_checked;
+use anyhow::anyhow;
+use futures::future::{select, Either};
+use lemmy_utils::{settings::Settings, LemmyError};
+use log::debug;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::{
+  io::{copy, AsyncReadExt, AsyncWriteExt},
+  net::{TcpListener, TcpStream},
+};
+use url::Url;
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 1;
+
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_NOT_ALLOWED: u8 = 2;
+const REPLY_CONNECTION_REFUSED: u8 = 5;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+const USERNAME_PASSWORD: u8 = 2;
+const USERNAME_PASSWORD_VERSION: u8 = 1;
+
+/// The longest response header of an http proxy to a CONNECT request.
+const MAX_PROXY_RESPONSE_LENGTH: usize = 8192;
+
+/// Returns the address to give the http client as its proxy.
+pub fn start_address_guard() -> Result<SocketAddr, LemmyError> {
+  let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+  listener.set_nonblocking(true)?;
+  let addr = listener.local_addr()?;
+  let listener = TcpListener::from_std(listener)?;
+  actix_web::rt::spawn(run_address_guard(listener));
+  Ok(addr)
+}
+
+async fn run_address_guard(mut listener: TcpListener) {
+  loop {
+    match listener.accept().await {
+      Ok((stream, _)) => actix_web::rt::spawn(async move {
+        if let Err(e) = handle_connection(stream).await {
+          debug!("Outbound connection failed: {}", e);
+        }
+      }),
+      Err(e) => debug!("Couldn't accept an outbound connection: {}", e),
+    }
+  }
+}
+
+async fn handle_connection(mut client: TcpStream) -> Result<(), LemmyError> {
+  let mut greeting = [0u8; 2];
+  client.read_exact(&mut greeting).await?;
+  let mut methods = vec![0u8; greeting[1] as usize];
+  client.read_exact(&mut methods).await?;
+  if greeting[0] != SOCKS_VERSION || !methods.contains(&NO_AUTHENTICATION) {
+    client
+      .write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS])
+      .await?;
+    return Ok(());
+  }
+  client
+    .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+    .await?;
+
+  let mut request = [0u8; 4];
+  client.read_exact(&mut request).await?;
+  if request[1] != CONNECT {
+    return reply(&mut client, REPLY_COMMAND_NOT_SUPPORTED).await;
+  }
+  let host = match request[3] {
+    ADDRESS_IPV4 => {
+      let mut ip = [0u8; 4];
+      client.read_exact(&mut ip).await?;
+      Ipv4Addr::from(ip).to_string()
+    }
+    ADDRESS_IPV6 => {
+      let mut ip = [0u8; 16];
+      client.read_exact(&mut ip).await?;
+      Ipv6Addr::from(ip).to_string()
+    }
+    ADDRESS_DOMAIN => {
+      let mut len = [0u8; 1];
+      client.read_exact(&mut len).await?;
+      let mut domain = vec![0u8; len[0] as usize];
+      client.read_exact(&mut domain).await?;
+      String::from_utf8(domain)?
+    }
+    _ => return reply(&mut client, REPLY_ADDRESS_NOT_SUPPORTED).await,
+  };
+  let mut port = [0u8; 2];
+  client.read_exact(&mut port).await?;
+  let port = u16::from_be_bytes(port);
+
+  let target = match resolve_checked(&host, port).await {
+    Ok(target) => target,
+    Err(e) => {
+      debug!("{}", e);
+      return reply(&mut client, REPLY_NOT_ALLOWED).await;
+    }
+  };
+  let mut upstream = match connect(target).await {
+    Ok(upstream) => upstream,
+    Err(e) => {
+      debug!("{}", e);
+      return reply(&mut client, REPLY_CONNECTION_REFUSED).await;
+    }
+  };
+  reply(&mut client, REPLY_SUCCEEDED).await?;
+
+  // Once either side is done, so is the connection
+  let (mut client_read, mut client_write) = client.split();
+  let (mut upstream_read, mut upstream_write) = upstream.split();
+  let to_upstream = copy(&mut client_read, &mut upstream_write);
+  let to_client = copy(&mut upstream_read, &mut client_write);
+  futures::pin_mut!(to_upstream, to_client);
+  match select(to_upstream, to_client).await {
+    Either::Left((res, _)) | Either::Right((res, _)) => res?,
+  };
+  Ok(())
+}
+
+/// Connects to the checked address, through the proxy of the config if there is one. The proxy
+/// gets the address instead of the host, so that it can't resolve the host to another address.
+async fn connect(target: SocketAddr) -> Result<TcpStream, LemmyError> {
+  let proxy = match Settings::get().outbound_requests.proxy {
+    Some(proxy) => Url::parse(&proxy)?,
+    None => return Ok(TcpStream::connect(target).await?),
+  };
+  let proxy_host = proxy
+    .host_str()
+    .ok_or_else(|| anyhow!("No host in proxy url {}", proxy))?
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+    .to_owned();
+  let proxy_port = proxy
+    .port_or_known_default()
+    .unwrap_or(if proxy.scheme() == "http" { 80 } else { 1080 });
+  let mut stream = TcpStream::connect((proxy_host.as_str(), proxy_port)).await?;
+  let username = percent_decode(proxy.username());
+  let password = percent_decode(proxy.password().unwrap_or_default());
+  match proxy.scheme() {
+    "http" => connect_http_proxy(&mut stream, target, &username, &password).await?,
+    "socks5" | "socks5h" => connect_socks_proxy(&mut stream, target, &username, &password).await?,
+    scheme => return Err(anyhow!("Unsupported proxy scheme {}", scheme).into()),
+  }
+  Ok(stream)
+}
+
+fn percent_decode(text: &str) -> String {
+  percent_encoding::percent_decode_str(text)
+    .decode_utf8_lossy()
+    .into_owned()
+}
+
+async fn connect_http_proxy(
+  stream: &mut TcpStream,
+  target: SocketAddr,
+  username: &str,
+  password: &str,
+) -> Result<(), LemmyError> {
+  let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+  if !username.is_empty() {
+    let credentials = base64::encode(format!("{}:{}", username, password));
+    request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+  }
+  request.push_str("\r\n");
+  stream.write_all(request.as_bytes()).await?;
+
+  // Read byte by byte, so that nothing after the header is taken from the tunnel
+  let mut response = Vec::new();
+  while !response.ends_with(b"\r\n\r\n") {
+    if response.len() >= MAX_PROXY_RESPONSE_LENGTH {
+      return Err(anyhow!("Response of the proxy is too long").into());
+    }
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte).await?;
+    response.push(byte[0]);
+  }
+  let response = String::from_utf8_lossy(&response);
+  let status = response.split_whitespace().nth(1).unwrap_or_default();
+  if !status.starts_with('2') {
+    return Err(
+      anyhow!(
+        "Proxy answered {}",
+        response.lines().next().unwrap_or_default()
+      )
+      .into(),
+    );
+  }
+  Ok(())
+}
+
+async fn connect_socks_proxy(
+  stream: &mut TcpStream,
+  target: SocketAddr,
+  username: &str,
+  password: &str,
+) -> Result<(), LemmyError> {
+  let method = if username.is_empty() {
+    NO_AUTHENTICATION
+  } else {
+    USERNAME_PASSWORD
+  };
+  stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+  let mut chosen = [0u8; 2];
+  stream.read_exact(&mut chosen).await?;
+  if chosen != [SOCKS_VERSION, method] {
+    return Err(anyhow!("Proxy refused the authentication method").into());
+  }
+  if method == USERNAME_PASSWORD {
+    if username.len() > 255 || password.len() > 255 {
+      return Err(anyhow!("Proxy username or password is too long").into());
+    }
+    let mut auth = vec![USERNAME_PASSWORD_VERSION, username.len() as u8];
+    auth.extend_from_slice(username.as_bytes());
+    auth.push(password.len() as u8);
+    auth.extend_from_slice(password.as_bytes());
+    stream.write_all(&auth).await?;
+    let mut status = [0u8; 2];
+    stream.read_exact(&mut status).await?;
+    if status[1] != 0 {
+      return Err(anyhow!("Proxy refused the username and password").into());
+    }
+  }
+
+  let mut request = vec![SOCKS_VERSION, CONNECT, 0];
+  match target.ip() {
+    IpAddr::V4(ip) => {
+      request.push(ADDRESS_IPV4);
+      request.extend_from_slice(&ip.octets());
+    }
+    IpAddr::V6(ip) => {
+      request.push(ADDRESS_IPV6);
+      request.extend_from_slice(&ip.octets());
+    }
+  }
+  request.extend_from_slice(&target.port().to_be_bytes());
+  stream.write_all(&request).await?;
+
+  let mut reply = [0u8; 4];
+  stream.read_exact(&mut reply).await?;
+  if reply[1] != REPLY_SUCCEEDED {
+    return Err(anyhow!("Proxy refused the connection with code {}", reply[1]).into());
+  }
+  // The bound address, which isn't needed
+  let address_length = match reply[3] {
+    ADDRESS_IPV4 => 4,
+    ADDRESS_IPV6 => 16,
+    ADDRESS_DOMAIN => {
+      let mut len = [0u8; 1];
+      stream.read_exact(&mut len).await?;
+      len[0] as usize
+    }
+    _ => return Err(anyhow!("Invalid reply of the proxy").into()),
+  };
+  let mut bound = vec![0u8; address_length + 2];
+  stream.read_exact(&mut bound).await?;
+  Ok(())
+}
+
+/// The bound address of the reply isn't used by clients, so it's left empty.
+async fn reply(client: &mut TcpStream, code: u8) -> Result<(), LemmyError> {
+  client
+    .write_all(&[SOCKS_VERSION, code, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+    .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::address_guard::*;
+
+  async fn connect_through_guard(guard: SocketAddr, host: &str, port: u16) -> u8 {
+    let mut stream = TcpStream::connect(guard).await.unwrap();
+    stream
+      .write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])
+      .await
+      .unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!([SOCKS_VERSION, NO_AUTHENTICATION], method);
+
+    let mut request = vec![SOCKS_VERSION, CONNECT, 0, ADDRESS_DOMAIN, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    reply[1]
+  }
+
+  #[test]
+  fn test_connect_through_proxy() {
+    actix_rt::System::new("test_connect_through_proxy").block_on(async move {
+      let target: SocketAddr = "93.184.216.34:443".parse().unwrap();
+
+      let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+      let proxy = listener.local_addr().unwrap();
+      actix_web::rt::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0u8; 256];
+        let len = stream.read(&mut request).await.unwrap();
+        assert_eq!(
+          "CONNECT 93.184.216.34:443 HTTP/1.1\r\nHost: 93.184.216.34:443\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n",
+          String::from_utf8_lossy(&request[..len])
+        );
+        stream
+          .write_all(b"HTTP/1.1 200 Connection established\r\n\r\ntunnel")
+          .await
+          .unwrap();
+      });
+      let mut stream = TcpStream::connect(proxy).await.unwrap();
+      connect_http_proxy(&mut stream, target, "user", "pass")
+        .await
+        .unwrap();
+      let mut tunnel = [0u8; 6];
+      stream.read_exact(&mut tunnel).await.unwrap();
+      assert_eq!(b"tunnel", &tunnel);
+
+      let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+      let proxy = listener.local_addr().unwrap();
+      actix_web::rt::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!([SOCKS_VERSION, 1, NO_AUTHENTICATION], greeting);
+        stream
+          .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+          .await
+          .unwrap();
+        let mut request = [0u8; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        // The checked address, not a host which the proxy would resolve again
+        assert_eq!(
+          [SOCKS_VERSION, CONNECT, 0, ADDRESS_IPV4, 93, 184, 216, 34, 1, 187],
+          request
+        );
+        reply(&mut stream, REPLY_SUCCEEDED).await.unwrap();
+      });
+      let mut stream = TcpStream::connect(proxy).await.unwrap();
+      assert!(connect_socks_proxy(&mut stream, target, "", "")
+        .await
+        .is_ok());
+    });
+  }
+
+  #[test]
+  fn test_address_guard() {
+    actix_rt::System::new("test_address_guard").block_on(async move {
+      let guard = start_address_guard().unwrap();
+      // Something is listening there, but it's not a public address
+      let port = guard.port();
+      assert_eq!(
+        REPLY_NOT_ALLOWED,
+        connect_through_guard(guard, "127.0.0.1", port).await
+      );
+      assert_eq!(
+        REPLY_NOT_ALLOWED,
+        connect_through_guard(guard, "localhost", port).await
+      );
+      assert_eq!(
+        REPLY_NOT_ALLOWED,
+        connect_through_guard(guard, "10.0.0.1", 80).await
+      );
+      assert_eq!(
+        REPLY_NOT_ALLOWED,
+        connect_through_guard(guard, "::1", port).await
+      );
+    });
+  }
+}
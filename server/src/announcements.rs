@@ -0,0 +1,47 @@
+//! The announcement job, which sends scheduled announcements to the connected clients when they
+//! start. Whether an announcement was sent is stored with it, so that the ones which start while
+//! the server is down are sent once it's up again.
+use crate::{
+  websocket::{messages::SendAllMessage, UserOperation},
+  LemmyContext,
+};
+use lemmy_api_structs::{blocking, site::AnnouncementResponse};
+use lemmy_db::site_announcement::SiteAnnouncement;
+use lemmy_utils::LemmyError;
+use log::error;
+use std::time::Duration;
+
+const ANNOUNCEMENT_INTERVAL: Duration = Duration::from_secs(30);
+
+pub async fn run_announcement_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(ANNOUNCEMENT_INTERVAL);
+  loop {
+    interval.tick().await;
+    if let Err(e) = push_due_announcements(&context).await {
+      error!("Couldn't send the scheduled announcements: {}", e);
+    }
+  }
+}
+
+async fn push_due_announcements(context: &LemmyContext) -> Result<(), LemmyError> {
+  let due = blocking(context.pool(), move |conn| {
+    SiteAnnouncement::list_due_for_push(conn)
+  })
+  .await??;
+  for announcement in due {
+    let id = announcement.id;
+    let marked = blocking(context.pool(), move |conn| {
+      SiteAnnouncement::mark_pushed(conn, id)
+    })
+    .await??;
+    // Clients haven't seen it yet, even if it was edited while it was scheduled
+    if marked > 0 {
+      context.chat_server().do_send(SendAllMessage {
+        op: UserOperation::CreateAnnouncement,
+        response: AnnouncementResponse { announcement },
+        websocket_id: None,
+      });
+    }
+  }
+  Ok(())
+}
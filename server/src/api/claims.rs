@@ -1,5 +1,5 @@
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
-use lemmy_db::user::User_;
+use lemmy_db::{admin_impersonation::AdminImpersonation, user::User_};
 use lemmy_utils::settings::Settings;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +9,13 @@ type Jwt = String;
 pub struct Claims {
   pub id: i32,
   pub iss: String,
+  /// Set in the tokens with which admins view the site as the user, see `AdminImpersonation`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub impersonation_id: Option<i32>,
+  /// The sign in the token was handed out for, so that it can be revoked, see `SignInSignal`.
+  /// Older tokens don't have one, and stop working when the password of the user changes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub session_id: Option<i32>,
 }
 
 impl Claims {
@@ -28,6 +35,39 @@ impl Claims {
     let my_claims = Claims {
       id: user.id,
       iss: hostname,
+      impersonation_id: None,
+      session_id: None,
+    };
+    encode(
+      &Header::default(),
+      &my_claims,
+      &EncodingKey::from_secret(Settings::get().jwt_secret.as_ref()),
+    )
+  }
+
+  /// The same token, for the recorded sign in.
+  pub fn session_jwt(jwt: &str, session_id: i32) -> Result<Jwt, jsonwebtoken::errors::Error> {
+    let my_claims = Claims {
+      session_id: Some(session_id),
+      ..Claims::decode(jwt)?.claims
+    };
+    encode(
+      &Header::default(),
+      &my_claims,
+      &EncodingKey::from_secret(Settings::get().jwt_secret.as_ref()),
+    )
+  }
+
+  /// The token to view the site as the user. It only works while the impersonation hasn't expired.
+  pub fn impersonation_jwt(
+    impersonation: &AdminImpersonation,
+    hostname: String,
+  ) -> Result<Jwt, jsonwebtoken::errors::Error> {
+    let my_claims = Claims {
+      id: impersonation.user_id,
+      iss: hostname,
+      impersonation_id: Some(impersonation.id),
+      session_id: None,
     };
     encode(
       &Header::default(),
@@ -1,28 +1,58 @@
 use crate::{
   api::{
     check_community_ban,
+    check_community_membership,
+    check_community_visible,
+    check_edit_window,
+    check_undelete_window,
+    check_votes_unlocked,
+    comment_sort_for_post,
+    community::send_mod_inbox_counts,
+    get_content_limits,
     get_post,
+    get_removal_reason,
     get_user_from_jwt,
-    get_user_from_jwt_opt,
+    get_user_from_jwt_opt_for_reading,
+    is_admin,
+    is_impersonation,
     is_mod_or_admin,
+    is_trusted,
+    send_mod_message,
     Perform,
   },
-  apub::{ApubLikeableType, ApubObjectType},
+  apub::{
+    comment::{send_pin_comment, send_undo_pin_comment},
+    ApubLikeableType,
+    ApubObjectType,
+  },
+  post_views::{current_views, Viewed},
+  spam::{score_content, SpamContentType, SpamTarget},
+  wasm_plugins::{run_content_filters, CommentFilterPayload, ContentKind},
   websocket::{
-    messages::{JoinCommunityRoom, SendComment},
+    messages::{JoinCommunityRoom, SendComment, SendPost},
     UserOperation,
   },
   LemmyContext,
 };
 use actix_web::web::Data;
-use lemmy_api_structs::{blocking, comment::*, send_local_notifs};
+use lemmy_api_structs::{
+  blocking,
+  comment::*,
+  post::PostResponse,
+  send_approved_mentions,
+  send_local_notifs,
+};
 use lemmy_db::{
   comment::*,
   comment_view::*,
+  comment_view_count::CommentViewCount,
   moderator::*,
   post::*,
+  post_view::PostView,
   site_view::*,
+  spam_score::SpamScore,
   user::*,
+  CommentSortType,
   Crud,
   Likeable,
   ListingType,
@@ -31,7 +61,7 @@ use lemmy_db::{
 };
 use lemmy_utils::{
   apub::{make_apub_endpoint, EndpointType},
-  utils::{remove_slurs, scrape_text_for_mentions},
+  utils::{check_lengths, remove_slurs, scrape_text_for_mentions},
   APIError,
   ConnectionId,
   LemmyError,
@@ -50,7 +80,16 @@ impl Perform for CreateComment {
     let data: &CreateComment = &self;
     let user = get_user_from_jwt(&data.auth, context.pool()).await?;
 
-    let content_slurs_removed = remove_slurs(&data.content.to_owned());
+    let filtered = run_content_filters(
+      ContentKind::Comment,
+      CommentFilterPayload {
+        content: data.content.to_owned(),
+        post_id: data.post_id,
+        creator_id: user.id,
+      },
+    )
+    .await?;
+    let content_slurs_removed = remove_slurs(&filtered.content);
 
     let comment_form = CommentForm {
       content: content_slurs_removed,
@@ -64,6 +103,7 @@ impl Perform for CreateComment {
       updated: None,
       ap_id: None,
       local: true,
+      distinguished: None,
     };
 
     // Check for a community ban
@@ -71,14 +111,37 @@ impl Perform for CreateComment {
     let post = get_post(post_id, context.pool()).await?;
 
     check_community_ban(user.id, post.community_id, context.pool()).await?;
+    check_community_membership(user.id, post.community_id, context.pool()).await?;
+
+    let limits = get_content_limits(post.community_id, context.pool()).await?;
+    check_lengths(&[("content", Some(&comment_form.content), limits.comment)])?;
 
     // Check if post is locked, no new comments
     if post.locked {
       return Err(APIError::err("locked").into());
     }
+    if post.comments_closed {
+      return Err(APIError::err("comments_closed").into());
+    }
+
+    let spam = if is_trusted(&user, Some(post.community_id), context.pool()).await? {
+      None
+    } else {
+      score_content(
+        SpamContentType::Comment,
+        &comment_form.content,
+        &user,
+        context,
+      )
+      .await?
+    };
+    let spam_removed = spam.as_ref().map_or(false, |s| s.removed);
 
     // Create the comment
-    let comment_form2 = comment_form.clone();
+    let comment_form2 = CommentForm {
+      removed: Some(spam_removed),
+      ..comment_form.clone()
+    };
     let inserted_comment = match blocking(context.pool(), move |conn| {
       Comment::create(&conn, &comment_form2)
     })
@@ -101,19 +164,34 @@ impl Perform for CreateComment {
       Err(_e) => return Err(APIError::err("couldnt_create_comment").into()),
     };
 
-    updated_comment.send_create(&user, context).await?;
+    if let Some(spam) = spam {
+      let needs_review = spam.reported && !spam.removed;
+      spam
+        .save(SpamTarget::Comment(inserted_comment_id), context.pool())
+        .await?;
+      if needs_review {
+        send_mod_inbox_counts(post.community_id, context).await?;
+      }
+    }
 
-    // Scan the comment for user mentions, add those rows
-    let mentions = scrape_text_for_mentions(&comment_form.content);
-    let recipient_ids = send_local_notifs(
-      mentions,
-      updated_comment.clone(),
-      &user,
-      post,
-      context.pool(),
-      true,
-    )
-    .await?;
+    // Content removed as spam isn't federated, and doesn't notify anyone
+    let recipient_ids = if spam_removed {
+      Vec::new()
+    } else {
+      updated_comment.send_create(&user, context).await?;
+
+      // Scan the comment for user mentions, add those rows
+      let mentions = scrape_text_for_mentions(&comment_form.content);
+      send_local_notifs(
+        mentions,
+        updated_comment.clone(),
+        &user,
+        post,
+        context.pool(),
+        true,
+      )
+      .await?
+    };
 
     // You like your own comment by default
     let like_form = CommentLikeForm {
@@ -128,7 +206,9 @@ impl Perform for CreateComment {
       return Err(APIError::err("couldnt_like_comment").into());
     }
 
-    updated_comment.send_like(&user, context).await?;
+    if !spam_removed {
+      updated_comment.send_like(&user, context).await?;
+    }
 
     let user_id = user.id;
     let comment_view = blocking(context.pool(), move |conn| {
@@ -176,16 +256,22 @@ impl Perform for EditComment {
 
     check_community_ban(user.id, orig_comment.community_id, context.pool()).await?;
 
+    let limits = get_content_limits(orig_comment.community_id, context.pool()).await?;
+    check_lengths(&[("content", Some(&data.content), limits.comment)])?;
+
     // Verify that only the creator can edit
     if user.id != orig_comment.creator_id {
       return Err(APIError::err("no_comment_edit_allowed").into());
     }
 
+    let updated =
+      check_edit_window(orig_comment.published, orig_comment.updated, context.pool()).await?;
+
     // Do the update
     let content_slurs_removed = remove_slurs(&data.content.to_owned());
     let edit_id = data.edit_id;
     let updated_comment = match blocking(context.pool(), move |conn| {
-      Comment::update_content(conn, edit_id, &content_slurs_removed)
+      Comment::update_content(conn, edit_id, &content_slurs_removed, updated)
     })
     .await?
     {
@@ -264,6 +350,11 @@ impl Perform for DeleteComment {
       return Err(APIError::err("no_comment_edit_allowed").into());
     }
 
+    if !data.deleted {
+      let comment = blocking(context.pool(), move |conn| Comment::read(conn, edit_id)).await??;
+      check_undelete_window(comment.deleted_at, context.pool()).await?;
+    }
+
     // Do the delete
     let deleted = data.deleted;
     let updated_comment = match blocking(context.pool(), move |conn| {
@@ -347,6 +438,20 @@ impl Perform for RemoveComment {
     // Verify that only a mod or admin can remove
     is_mod_or_admin(context.pool(), user.id, orig_comment.community_id).await?;
 
+    let reason = if data.removed {
+      Some(
+        get_removal_reason(
+          &data.reason,
+          data.reason_id,
+          orig_comment.community_id,
+          context.pool(),
+        )
+        .await?,
+      )
+    } else {
+      data.reason.to_owned()
+    };
+
     // Do the remove
     let removed = data.removed;
     let updated_comment = match blocking(context.pool(), move |conn| {
@@ -363,7 +468,7 @@ impl Perform for RemoveComment {
       mod_user_id: user.id,
       comment_id: data.edit_id,
       removed: Some(removed),
-      reason: data.reason.to_owned(),
+      reason: reason.to_owned(),
     };
     blocking(context.pool(), move |conn| {
       ModRemoveComment::create(conn, &form)
@@ -372,7 +477,9 @@ impl Perform for RemoveComment {
 
     // Send the apub message
     if removed {
-      updated_comment.send_remove(&user, context).await?;
+      updated_comment
+        .send_remove(&user, reason.to_owned(), context)
+        .await?;
     } else {
       updated_comment.send_undo_remove(&user, context).await?;
     }
@@ -385,6 +492,17 @@ impl Perform for RemoveComment {
     })
     .await??;
 
+    // Tell the author why, if the mod asked for it
+    if let Some(reason) = reason.filter(|_| removed && data.notify_author.unwrap_or(false)) {
+      if orig_comment.creator_id != user.id {
+        let content = format!(
+          "Your comment on \"{}\" in !{} was removed:\n\n{}",
+          comment_view.post_name, comment_view.community_name, reason
+        );
+        send_mod_message(&user, orig_comment.creator_id, content, context).await?;
+      }
+    }
+
     // Build the recipients
     let post_id = comment_view.post_id;
     let post = get_post(post_id, context.pool()).await?;
@@ -411,6 +529,9 @@ impl Perform for RemoveComment {
       websocket_id,
     });
 
+    // Removing reported comments takes them off the mod inbox
+    send_mod_inbox_counts(orig_comment.community_id, context).await?;
+
     // strip out the recipient_ids, so that
     // users don't get double notifs
     res.recipient_ids = Vec::new();
@@ -419,6 +540,182 @@ impl Perform for RemoveComment {
   }
 }
 
+#[async_trait::async_trait(?Send)]
+impl Perform for DistinguishComment {
+  type Response = CommentResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<CommentResponse, LemmyError> {
+    let data: &DistinguishComment = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let edit_id = data.edit_id;
+    let orig_comment = blocking(context.pool(), move |conn| {
+      CommentView::read(&conn, edit_id, None)
+    })
+    .await??;
+
+    check_community_ban(user.id, orig_comment.community_id, context.pool()).await?;
+
+    // Mods and admins can only distinguish their own comments
+    if user.id != orig_comment.creator_id {
+      return Err(APIError::err("no_comment_edit_allowed").into());
+    }
+    is_mod_or_admin(context.pool(), user.id, orig_comment.community_id).await?;
+
+    let distinguished = data.distinguished;
+    let updated_comment = match blocking(context.pool(), move |conn| {
+      Comment::update_distinguished(conn, edit_id, distinguished)
+    })
+    .await?
+    {
+      Ok(comment) => comment,
+      Err(_e) => return Err(APIError::err("couldnt_update_comment").into()),
+    };
+
+    updated_comment.send_update(&user, context).await?;
+
+    let user_id = user.id;
+    let comment_view = blocking(context.pool(), move |conn| {
+      CommentView::read(conn, edit_id, Some(user_id))
+    })
+    .await??;
+
+    let res = CommentResponse {
+      comment: comment_view,
+      recipient_ids: Vec::new(),
+      form_id: None,
+    };
+
+    context.chat_server().do_send(SendComment {
+      op: UserOperation::DistinguishComment,
+      comment: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for PinComment {
+  type Response = PostResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<PostResponse, LemmyError> {
+    let data: &PinComment = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let edit_id = data.edit_id;
+    let comment = blocking(context.pool(), move |conn| Comment::read(conn, edit_id)).await??;
+    let post = get_post(comment.post_id, context.pool()).await?;
+
+    check_community_ban(user.id, post.community_id, context.pool()).await?;
+
+    // The post creator can pin comments in their thread, mods and admins in any thread
+    if !Post::is_post_creator(user.id, post.creator_id) {
+      is_mod_or_admin(context.pool(), user.id, post.community_id).await?;
+    }
+
+    // Only a visible, top level comment can be shown at the top of the thread
+    if data.pinned && (comment.parent_id.is_some() || comment.deleted || comment.removed) {
+      return Err(APIError::err("couldnt_update_post").into());
+    }
+
+    let post_id = post.id;
+    let pinned_comment_id = if data.pinned {
+      Some(comment.id)
+    } else if post.pinned_comment_id == Some(comment.id) {
+      None
+    } else {
+      // Another comment is pinned, leave it
+      post.pinned_comment_id
+    };
+    blocking(context.pool(), move |conn| {
+      Post::update_pinned_comment(conn, post_id, pinned_comment_id)
+    })
+    .await??;
+
+    if data.pinned {
+      send_pin_comment(&comment, &user, context).await?;
+    } else {
+      send_undo_pin_comment(&comment, &user, context).await?;
+    }
+
+    let user_id = user.id;
+    let post_view = blocking(context.pool(), move |conn| {
+      PostView::read(conn, post_id, Some(user_id))
+    })
+    .await??;
+
+    let res = PostResponse { post: post_view };
+
+    context.chat_server().do_send(SendPost {
+      op: UserOperation::PinComment,
+      post: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ApproveMentions {
+  type Response = CommentResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<CommentResponse, LemmyError> {
+    let data: &ApproveMentions = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let comment_id = data.comment_id;
+    let comment = blocking(context.pool(), move |conn| Comment::read(conn, comment_id)).await??;
+    let creator_id = comment.creator_id;
+    let creator = blocking(context.pool(), move |conn| User_::read(conn, creator_id)).await??;
+
+    let release = move |conn: &'_ _| SpamScore::release_held_mentions(conn, comment_id);
+    if !blocking(context.pool(), release).await?? {
+      return Err(APIError::err("mentions_not_held").into());
+    }
+
+    // The limit isn't checked again, the admin decided that these mentions are fine
+    let mentions = scrape_text_for_mentions(&comment.content);
+    let recipient_ids = send_approved_mentions(mentions, comment, &creator, context.pool()).await?;
+
+    let user_id = user.id;
+    let comment_view = blocking(context.pool(), move |conn| {
+      CommentView::read(conn, comment_id, Some(user_id))
+    })
+    .await??;
+
+    let res = CommentResponse {
+      comment: comment_view,
+      recipient_ids,
+      form_id: None,
+    };
+
+    context.chat_server().do_send(SendComment {
+      op: UserOperation::ApproveMentions,
+      comment: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for MarkCommentAsRead {
   type Response = CommentResponse;
@@ -566,6 +863,8 @@ impl Perform for CreateCommentLike {
     let post_id = orig_comment.post_id;
     let post = get_post(post_id, context.pool()).await?;
     check_community_ban(user.id, post.community_id, context.pool()).await?;
+    check_community_visible(Some(user.id), post.community_id, context.pool()).await?;
+    check_votes_unlocked(&post, context.pool()).await?;
 
     let comment_id = data.comment_id;
     let comment = blocking(context.pool(), move |conn| Comment::read(conn, comment_id)).await??;
@@ -658,24 +957,45 @@ impl Perform for GetComments {
     websocket_id: Option<ConnectionId>,
   ) -> Result<GetCommentsResponse, LemmyError> {
     let data: &GetComments = &self;
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
-    let user_id = user.map(|u| u.id);
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
+    let user_id = user.as_ref().map(|u| u.id);
 
     let type_ = ListingType::from_str(&data.type_)?;
     let sort = SortType::from_str(&data.sort)?;
+    let comment_sort = match &data.comment_sort {
+      Some(comment_sort) => Some(CommentSortType::from_str(comment_sort)?),
+      None => None,
+    };
+    let comment_sort = match data.post_id {
+      Some(post_id) => {
+        comment_sort_for_post(
+          user.as_ref(),
+          is_impersonation(&data.auth),
+          post_id,
+          comment_sort,
+          context.pool(),
+        )
+        .await?
+      }
+      None => comment_sort,
+    };
 
     let community_id = data.community_id;
+    let post_id = data.post_id;
     let page = data.page;
     let limit = data.limit;
     let comments = blocking(context.pool(), move |conn| {
-      CommentQueryBuilder::create(conn)
+      let comments = CommentQueryBuilder::create(conn)
         .listing_type(type_)
         .sort(&sort)
+        .comment_sort(comment_sort)
         .for_community_id(community_id)
+        .for_post_id(post_id)
         .my_user_id(user_id)
         .page(page)
         .limit(limit)
-        .list()
+        .list()?;
+      CollapsibleCommentView::from_comments(conn, comments, user_id)
     })
     .await?;
     let comments = match comments {
@@ -698,3 +1018,86 @@ impl Perform for GetComments {
     Ok(GetCommentsResponse { comments })
   }
 }
+
+/// The most parent comments and levels of replies of a comment context.
+const MAX_COMMENT_CONTEXT: i64 = 10;
+
+/// The most replies of a comment context, over all levels.
+const MAX_COMMENT_CONTEXT_DESCENDANTS: i64 = 500;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommentContext {
+  type Response = GetCommentContextResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetCommentContextResponse, LemmyError> {
+    let data: &GetCommentContext = &self;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
+    let user_id = user.map(|u| u.id);
+
+    let comment_id = data.comment_id;
+    let comment = match blocking(context.pool(), move |conn| {
+      CommentView::read(conn, comment_id, user_id)
+    })
+    .await?
+    {
+      Ok(comment) => comment,
+      Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+    };
+
+    check_community_visible(user_id, comment.community_id, context.pool()).await?;
+
+    let max_ancestors = data.context.unwrap_or(3).max(0).min(MAX_COMMENT_CONTEXT);
+    let depth = data.depth.unwrap_or(3).max(0).min(MAX_COMMENT_CONTEXT);
+    let post_id = comment.post_id;
+    let first_parent_id = comment.parent_id;
+    let (ancestors, descendants, post, kept_views) = blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        let mut ancestors = Vec::new();
+        let mut parent_id = first_parent_id;
+        while let Some(id) = parent_id.filter(|_| (ancestors.len() as i64) < max_ancestors) {
+          let parent = CommentView::read(conn, id, user_id)?;
+          parent_id = parent.parent_id;
+          ancestors.push(parent);
+        }
+        ancestors.reverse();
+
+        // One level of replies after the other, until the limit is reached
+        let mut descendants = Vec::new();
+        let mut parent_ids = vec![comment_id];
+        for _ in 0..depth {
+          let remaining = MAX_COMMENT_CONTEXT_DESCENDANTS - descendants.len() as i64;
+          if parent_ids.is_empty() || remaining <= 0 {
+            break;
+          }
+          let replies = CommentView::list_replies(conn, parent_ids, user_id, remaining)?;
+          parent_ids = replies.iter().map(|r| r.id).collect();
+          descendants.extend(replies);
+        }
+
+        Ok((
+          CollapsibleCommentView::from_comments(conn, ancestors, user_id)?,
+          CollapsibleCommentView::from_comments(conn, descendants, user_id)?,
+          PostView::read(conn, post_id, user_id)?,
+          CommentViewCount::read_for_comment(conn, comment_id)?,
+        ))
+      },
+    )
+    .await??;
+    let kept_views = kept_views.map(|kept| (kept.views, kept.visitors));
+    let (views, unique_views) = current_views(Viewed::Comment(comment_id), kept_views);
+
+    Ok(GetCommentContextResponse {
+      comment,
+      ancestors,
+      descendants,
+      post,
+      views,
+      unique_views,
+    })
+  }
+}
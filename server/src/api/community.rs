@@ -1,40 +1,94 @@
 use crate::{
-  api::{get_user_from_jwt, get_user_from_jwt_opt, is_admin, is_mod_or_admin, Perform},
-  apub::ActorType,
+  api::{
+    check_community_visible,
+    get_post,
+    get_removal_reason,
+    get_stats_range,
+    get_user_from_jwt,
+    get_user_from_jwt_for_reading,
+    get_user_from_jwt_opt_for_reading,
+    is_admin,
+    is_mod_or_admin,
+    send_mod_message,
+    Perform,
+  },
+  apub::{
+    activities::send_flag,
+    community::{join_request_follow, send_add_mod, send_reject_join_request, send_remove_mod},
+    ActorType,
+    ApubObjectType,
+  },
   websocket::{
-    messages::{GetCommunityUsersOnline, JoinCommunityRoom, SendCommunityRoomMessage},
+    messages::{
+      GetCommunityUsersOnline,
+      JoinCommunityRoom,
+      SendCommunityRoomMessage,
+      SendUserRoomMessage,
+      SetCommunityPrivate,
+    },
     UserOperation,
   },
+  DbPool,
   LemmyContext,
 };
 use actix_web::web::Data;
 use anyhow::Context;
+use diesel::PgConnection;
 use lemmy_api_structs::{blocking, community::*};
 use lemmy_db::{
   comment::Comment,
   comment_view::CommentQueryBuilder,
   community::*,
+  community_digest::*,
+  community_join_request::*,
+  community_notification::*,
+  community_post_tag::{CommunityPostTag, CommunityPostTagForm},
+  community_stats::{CommunityContributor, CommunityStatsDaily},
   community_view::*,
+  community_welcome::*,
+  content_report::{ContentReport, ContentReportForm},
   diesel_option_overwrite,
+  domain::DomainPostApproval,
   moderator::*,
+  moderator_views::ModBulkActionView,
   naive_now,
   post::Post,
+  post_view::PostView,
+  post_view_count::PostViewCount,
+  removal_reason::*,
+  scheduled_post::{ScheduledPost, ScheduledPostForm},
   site::*,
+  spam_score::SpamScore,
+  user::{automod_user_name, UserForm, User_},
   user_view::*,
+  vote_burst::{VoteBurst, VoteBurstForm, VOTE_BURST_MINUTES, VOTE_BURST_VOTES},
+  vote_instance::{count_recent_post_votes_from_instance, VoteOrigin},
   Bannable,
   Crud,
   Followable,
   Joinable,
+  ListingType,
   SortType,
 };
 use lemmy_utils::{
   apub::{generate_actor_keypair, make_apub_endpoint, EndpointType},
+  email::MAX_DIGEST_TEMPLATE_LENGTH,
   location_info,
-  utils::{check_slurs, check_slurs_opt, is_valid_community_name, naive_from_unix},
+  schedule::{render_title_template, Schedule},
+  utils::{
+    check_slurs,
+    check_slurs_opt,
+    is_valid_community_name,
+    is_valid_hex_color,
+    is_valid_post_title,
+    is_valid_title_pattern,
+    naive_from_unix,
+  },
   APIError,
   ConnectionId,
   LemmyError,
 };
+use log::error;
 use std::str::FromStr;
 
 #[async_trait::async_trait(?Send)]
@@ -47,7 +101,7 @@ impl Perform for GetCommunity {
     websocket_id: Option<ConnectionId>,
   ) -> Result<GetCommunityResponse, LemmyError> {
     let data: &GetCommunity = &self;
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
     let user_id = user.map(|u| u.id);
 
     let name = data.name.to_owned().unwrap_or_else(|| "main".to_string());
@@ -83,6 +137,26 @@ impl Perform for GetCommunity {
       Err(_e) => return Err(APIError::err("couldnt_find_community").into()),
     };
 
+    let settings = blocking(context.pool(), move |conn| {
+      CommunitySettings::read_for_community(conn, community_id)
+    })
+    .await??;
+
+    let appearance = blocking(context.pool(), move |conn| {
+      CommunityAppearance::read_for_community(conn, community_id)
+    })
+    .await??;
+
+    let join_request = match user_id {
+      Some(user_id) => {
+        blocking(context.pool(), move |conn| {
+          CommunityJoinRequest::read_for_user(conn, community_id, user_id)
+        })
+        .await??
+      }
+      None => None,
+    };
+
     if let Some(id) = websocket_id {
       context
         .chat_server()
@@ -98,6 +172,9 @@ impl Perform for GetCommunity {
     let res = GetCommunityResponse {
       community: community_view,
       moderators,
+      settings,
+      appearance,
+      join_request,
       online,
     };
 
@@ -215,6 +292,47 @@ impl Perform for EditCommunity {
 
     check_slurs(&data.title)?;
     check_slurs_opt(&data.description)?;
+    check_slurs_opt(&data.join_question)?;
+    check_slurs_opt(&data.automod_comment)?;
+    check_slurs_opt(&data.welcome_message)?;
+    check_slurs_opt(&data.digest_template)?;
+    let digest_template_length = data.digest_template.as_ref().map(|t| t.chars().count());
+    if digest_template_length.map_or(false, |l| l > MAX_DIGEST_TEMPLATE_LENGTH) {
+      return Err(APIError::err("digest_template_too_long").into());
+    }
+    let accent_color = data.accent_color.as_deref().map(str::trim);
+    if !accent_color.map_or(true, |c| c.is_empty() || is_valid_hex_color(c)) {
+      return Err(APIError::err("invalid_color").into());
+    }
+    let default_sort = data.default_sort.as_deref().map(str::trim);
+    if !default_sort.map_or(true, |s| s.is_empty() || SortType::from_str(s).is_ok()) {
+      return Err(APIError::err("invalid_default_sort").into());
+    }
+    let allowed_post_type = match &data.allowed_post_type {
+      Some(t) => match AllowedPostType::from_str(t) {
+        Ok(t) => Some(t),
+        Err(_e) => return Err(APIError::err("invalid_post_type").into()),
+      },
+      None => None,
+    };
+    let title_capitalization = match &data.title_capitalization {
+      Some(c) => match TitleCapitalization::from_str(c) {
+        Ok(c) => Some(c),
+        Err(_e) => return Err(APIError::err("invalid_title_capitalization").into()),
+      },
+      None => None,
+    };
+    let microblog_mentions = match &data.microblog_mentions {
+      Some(m) => match MicroblogMentions::from_str(m) {
+        Ok(m) => Some(m),
+        Err(_e) => return Err(APIError::err("invalid_microblog_mentions").into()),
+      },
+      None => None,
+    };
+    let title_pattern = data.title_pattern.as_deref().map(str::trim);
+    if !title_pattern.map_or(true, |p| p.is_empty() || is_valid_title_pattern(p)) {
+      return Err(APIError::err("invalid_title_pattern").into());
+    }
 
     // Verify its a mod (only mods can edit it)
     let edit_id = data.edit_id;
@@ -264,6 +382,155 @@ impl Perform for EditCommunity {
       Err(_e) => return Err(APIError::err("couldnt_update_community").into()),
     };
 
+    if data.require_alt_text.is_some()
+      || data.trust_min_account_age_days.is_some()
+      || data.trust_min_karma.is_some()
+      || data.allow_anonymous_posts.is_some()
+      || data.join_requires_approval.is_some()
+      || data.join_question.is_some()
+      || data.private.is_some()
+      || data.local_only.is_some()
+      || data.allow_authors_close_comments.is_some()
+      || data.automod_comment.is_some()
+      || data.welcome_message.is_some()
+      || data.max_post_body_length.is_some()
+      || data.max_comment_length.is_some()
+      || allowed_post_type.is_some()
+      || data.require_post_tag.is_some()
+      || title_pattern.is_some()
+      || data.clean_titles.is_some()
+      || title_capitalization.is_some()
+      || data.allow_post_url_edits.is_some()
+      || microblog_mentions.is_some()
+      || data.digest_template.is_some()
+      || data.discoverable.is_some()
+    {
+      let edit_id = data.edit_id;
+      let settings = blocking(context.pool(), move |conn| {
+        CommunitySettings::read_for_community(conn, edit_id)
+      })
+      .await??;
+
+      // A negative threshold or limit goes back to the instance one
+      let threshold = |new: Option<i32>, old: Option<i32>| match new {
+        Some(t) if t < 0 => None,
+        Some(t) => Some(t),
+        None => old,
+      };
+
+      // An empty comment or welcome message turns it off, but the automod user stays for the next
+      // time
+      let automod_comment = match &data.automod_comment {
+        Some(c) if c.trim().is_empty() => None,
+        Some(c) => Some(c.trim().to_owned()),
+        None => settings.automod_comment,
+      };
+      let welcome_message = match &data.welcome_message {
+        Some(m) if m.trim().is_empty() => None,
+        Some(m) => Some(m.trim().to_owned()),
+        None => settings.welcome_message,
+      };
+      let automod_user_id = match settings.automod_user_id {
+        None if automod_comment.is_some() || welcome_message.is_some() => {
+          Some(create_automod_user(data.edit_id, context.pool()).await?)
+        }
+        automod_user_id => automod_user_id,
+      };
+
+      let settings_form = CommunitySettingsForm {
+        community_id: data.edit_id,
+        require_alt_text: data.require_alt_text.unwrap_or(settings.require_alt_text),
+        trust_min_account_age_days: threshold(
+          data.trust_min_account_age_days,
+          settings.trust_min_account_age_days,
+        ),
+        trust_min_karma: threshold(data.trust_min_karma, settings.trust_min_karma),
+        allow_anonymous_posts: data
+          .allow_anonymous_posts
+          .unwrap_or(settings.allow_anonymous_posts),
+        join_requires_approval: data
+          .join_requires_approval
+          .unwrap_or(settings.join_requires_approval),
+        // An empty question removes it
+        join_question: match &data.join_question {
+          Some(q) if q.trim().is_empty() => None,
+          Some(q) => Some(q.trim().to_owned()),
+          None => settings.join_question,
+        },
+        private: data.private.unwrap_or(settings.private),
+        local_only: data.local_only.unwrap_or(settings.local_only),
+        allow_authors_close_comments: data
+          .allow_authors_close_comments
+          .unwrap_or(settings.allow_authors_close_comments),
+        automod_comment,
+        automod_user_id,
+        welcome_message,
+        max_post_body_length: threshold(data.max_post_body_length, settings.max_post_body_length),
+        max_comment_length: threshold(data.max_comment_length, settings.max_comment_length),
+        allowed_post_type: allowed_post_type
+          .map(|t| t.to_string())
+          .unwrap_or(settings.allowed_post_type),
+        require_post_tag: data.require_post_tag.unwrap_or(settings.require_post_tag),
+        // An empty pattern removes it
+        title_pattern: match title_pattern {
+          Some(p) if p.is_empty() => None,
+          Some(p) => Some(p.to_owned()),
+          None => settings.title_pattern,
+        },
+        clean_titles: data.clean_titles.unwrap_or(settings.clean_titles),
+        title_capitalization: title_capitalization
+          .map(|c| c.to_string())
+          .unwrap_or(settings.title_capitalization),
+        allow_post_url_edits: data
+          .allow_post_url_edits
+          .unwrap_or(settings.allow_post_url_edits),
+        microblog_mentions: microblog_mentions
+          .map(|m| m.to_string())
+          .unwrap_or(settings.microblog_mentions),
+        // An empty template goes back to the default one
+        digest_template: match &data.digest_template {
+          Some(t) if t.trim().is_empty() => None,
+          Some(t) => Some(t.trim().to_owned()),
+          None => settings.digest_template,
+        },
+        discoverable: data.discoverable.unwrap_or(settings.discoverable),
+      };
+      let private = settings_form.private;
+      let upsert = move |conn: &'_ _| CommunitySettings::upsert(conn, &settings_form);
+      if blocking(context.pool(), upsert).await?.is_err() {
+        return Err(APIError::err("couldnt_update_community").into());
+      }
+
+      context.chat_server().do_send(SetCommunityPrivate {
+        community_id: data.edit_id,
+        private,
+      });
+    }
+
+    if accent_color.is_some() || default_sort.is_some() {
+      let edit_id = data.edit_id;
+      let appearance = blocking(context.pool(), move |conn| {
+        CommunityAppearance::read_for_community(conn, edit_id)
+      })
+      .await??;
+
+      // Empty removes it
+      let overwrite = |new: Option<&str>, old: Option<String>| match new {
+        Some(n) if n.is_empty() => None,
+        Some(n) => Some(n.to_owned()),
+        None => old,
+      };
+      let appearance_form = CommunityAppearanceForm {
+        community_id: data.edit_id,
+        accent_color: overwrite(accent_color, appearance.accent_color),
+        default_sort: overwrite(default_sort, appearance.default_sort),
+      };
+      let upsert = move |conn: &'_ _| CommunityAppearance::upsert(conn, &appearance_form);
+      if blocking(context.pool(), upsert).await?.is_err() {
+        return Err(APIError::err("couldnt_update_community").into());
+      }
+    }
+
     // TODO there needs to be some kind of an apub update
     // process for communities and users
 
@@ -418,7 +685,7 @@ impl Perform for ListCommunities {
     _websocket_id: Option<ConnectionId>,
   ) -> Result<ListCommunitiesResponse, LemmyError> {
     let data: &ListCommunities = &self;
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
 
     let user_id = match &user {
       Some(user) => Some(user.id),
@@ -450,6 +717,49 @@ impl Perform for ListCommunities {
   }
 }
 
+const DEFAULT_RECOMMENDED_COMMUNITIES: i64 = 10;
+const MAX_RECOMMENDED_COMMUNITIES: i64 = 50;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetRecommendedCommunities {
+  type Response = ListCommunitiesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListCommunitiesResponse, LemmyError> {
+    let data: &GetRecommendedCommunities = &self;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
+
+    let user_id = user.as_ref().map(|u| u.id);
+    let show_nsfw = user.as_ref().map(|u| u.show_nsfw).unwrap_or(false);
+
+    // A comma separated list, so that it also fits in a query string
+    let category_ids = match &data.category_ids {
+      Some(category_ids) => category_ids
+        .split(',')
+        .filter(|c| !c.trim().is_empty())
+        .map(|c| c.trim().parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|_| APIError::err("invalid_category"))?,
+      None => Vec::new(),
+    };
+
+    let limit = data
+      .limit
+      .unwrap_or(DEFAULT_RECOMMENDED_COMMUNITIES)
+      .max(1)
+      .min(MAX_RECOMMENDED_COMMUNITIES);
+    let communities = blocking(context.pool(), move |conn| {
+      CommunityView::list_recommended(conn, user_id, category_ids, show_nsfw, limit)
+    })
+    .await??;
+
+    Ok(ListCommunitiesResponse { communities })
+  }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for FollowCommunity {
   type Response = CommunityResponse;
@@ -471,14 +781,56 @@ impl Perform for FollowCommunity {
       community_id: data.community_id,
       user_id: user.id,
     };
+    let join_request_form = CommunityJoinRequestForm {
+      community_id: data.community_id,
+      user_id: user.id,
+      application: data
+        .application
+        .as_ref()
+        .map(|a| a.trim().to_owned())
+        .filter(|a| !a.is_empty()),
+      follow: None,
+    };
+    check_slurs_opt(&join_request_form.application)?;
+
+    let community_id = data.community_id;
+    let user_id = user.id;
+    let withdraw = move |conn: &'_ _| CommunityJoinRequest::remove(conn, community_id, user_id);
 
     if community.local {
       if data.follow {
-        let follow = move |conn: &'_ _| CommunityFollower::follow(conn, &community_follower_form);
-        if blocking(context.pool(), follow).await?.is_err() {
-          return Err(APIError::err("community_follower_already_exists").into());
+        let (settings, needs_approval) = blocking(context.pool(), move |conn| {
+          CommunitySettings::read_for_community(conn, community_id).and_then(|settings| {
+            let is_follower = CommunityFollower::is_follower(conn, community_id, user_id)?;
+            let needs_approval = settings.requires_membership()
+              && !is_follower
+              && !Community::is_mod_or_admin(conn, user_id, community_id);
+            Ok((settings, needs_approval))
+          })
+        })
+        .await??;
+
+        if needs_approval {
+          // The follow stays pending until a mod reviews the request
+          if settings.join_question.is_some() && join_request_form.application.is_none() {
+            return Err(APIError::err("join_application_required").into());
+          }
+          let apply = move |conn: &'_ _| CommunityJoinRequest::apply(conn, &join_request_form);
+          if blocking(context.pool(), apply).await?.is_err() {
+            return Err(APIError::err("couldnt_request_to_join_community").into());
+          }
+          send_mod_inbox_counts(community_id, context).await?;
+        } else {
+          let follow = move |conn: &'_ _| CommunityFollower::follow(conn, &community_follower_form);
+          if blocking(context.pool(), follow).await?.is_err() {
+            return Err(APIError::err("community_follower_already_exists").into());
+          }
+          send_welcome_message(community_id, &user, context).await?;
         }
       } else {
+        if blocking(context.pool(), withdraw).await?? > 0 {
+          send_mod_inbox_counts(community_id, context).await?;
+        }
         let unfollow =
           move |conn: &'_ _| CommunityFollower::unfollow(conn, &community_follower_form);
         if blocking(context.pool(), unfollow).await?.is_err() {
@@ -487,16 +839,20 @@ impl Perform for FollowCommunity {
       }
     } else if data.follow {
       // Dont actually add to the community followers here, because you need
-      // to wait for the accept
+      // to wait for the accept. Until then the follow is a pending join request.
+      let apply = move |conn: &'_ _| CommunityJoinRequest::apply(conn, &join_request_form);
+      if blocking(context.pool(), apply).await?.is_err() {
+        return Err(APIError::err("couldnt_request_to_join_community").into());
+      }
       user.send_follow(&community.actor_id()?, context).await?;
     } else {
       user.send_unfollow(&community.actor_id()?, context).await?;
+      blocking(context.pool(), withdraw).await??;
       let unfollow = move |conn: &'_ _| CommunityFollower::unfollow(conn, &community_follower_form);
       if blocking(context.pool(), unfollow).await?.is_err() {
         return Err(APIError::err("community_follower_already_exists").into());
       }
     }
-    // TODO: this needs to return a "pending" state, until Accept is received from the remote server
 
     let community_id = data.community_id;
     let user_id = user.id;
@@ -521,7 +877,7 @@ impl Perform for GetFollowedCommunities {
     _websocket_id: Option<ConnectionId>,
   ) -> Result<GetFollowedCommunitiesResponse, LemmyError> {
     let data: &GetFollowedCommunities = &self;
-    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
 
     let user_id = user.id;
     let communities = match blocking(context.pool(), move |conn| {
@@ -538,6 +894,193 @@ impl Perform for GetFollowedCommunities {
   }
 }
 
+#[async_trait::async_trait(?Send)]
+impl Perform for SubscribeCommunityNotifications {
+  type Response = CommunityNotificationResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityNotificationResponse, LemmyError> {
+    let data: &SubscribeCommunityNotifications = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    let user_id = user.id;
+    if !data.subscribe {
+      let unsubscribe =
+        move |conn: &'_ _| CommunityNotification::unsubscribe(conn, community_id, user_id);
+      if blocking(context.pool(), unsubscribe).await?.is_err() {
+        return Err(APIError::err("couldnt_update_community_notifications").into());
+      }
+      return Ok(CommunityNotificationResponse {
+        community_notification: None,
+      });
+    }
+
+    // Make sure the community exists
+    blocking(context.pool(), move |conn| {
+      Community::read(conn, community_id)
+    })
+    .await??;
+
+    let notification_form = CommunityNotificationForm {
+      community_id,
+      user_id,
+      mod_posts_only: data.mod_posts_only,
+      send_email: data.send_email,
+      send_push: data.send_push.unwrap_or(false),
+      email_digest: data.email_digest.unwrap_or(false),
+    };
+    let community_notification = match blocking(context.pool(), move |conn| {
+      CommunityNotification::subscribe(conn, &notification_form)
+    })
+    .await?
+    {
+      Ok(n) => n,
+      Err(_e) => return Err(APIError::err("couldnt_update_community_notifications").into()),
+    };
+
+    Ok(CommunityNotificationResponse {
+      community_notification: Some(community_notification),
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityNotifications {
+  type Response = GetCommunityNotificationsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetCommunityNotificationsResponse, LemmyError> {
+    let data: &GetCommunityNotifications = &self;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let community_notifications = blocking(context.pool(), move |conn| {
+      CommunityNotification::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(GetCommunityNotificationsResponse {
+      community_notifications,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SubscribeCommunityDigest {
+  type Response = CommunityDigestResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityDigestResponse, LemmyError> {
+    let data: &SubscribeCommunityDigest = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    let user_id = user.id;
+    if !data.subscribe {
+      let unsubscribe =
+        move |conn: &'_ _| CommunityDigestSubscription::unsubscribe(conn, community_id, user_id);
+      if blocking(context.pool(), unsubscribe).await?.is_err() {
+        return Err(APIError::err("couldnt_update_community_digest").into());
+      }
+      return Ok(CommunityDigestResponse {
+        community_digest: None,
+      });
+    }
+
+    // The digest is only sent to followers of the community
+    let is_follower = blocking(context.pool(), move |conn| {
+      CommunityFollower::is_follower(conn, community_id, user_id)
+    })
+    .await??;
+    if !is_follower {
+      return Err(APIError::err("not_following_community").into());
+    }
+
+    let digest_form = CommunityDigestSubscriptionForm {
+      community_id,
+      user_id,
+    };
+    let community_digest = match blocking(context.pool(), move |conn| {
+      CommunityDigestSubscription::subscribe(conn, &digest_form)
+    })
+    .await?
+    {
+      Ok(d) => d,
+      Err(_e) => return Err(APIError::err("couldnt_update_community_digest").into()),
+    };
+
+    Ok(CommunityDigestResponse {
+      community_digest: Some(community_digest),
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityDigests {
+  type Response = GetCommunityDigestsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetCommunityDigestsResponse, LemmyError> {
+    let data: &GetCommunityDigests = &self;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let community_digests = blocking(context.pool(), move |conn| {
+      CommunityDigestSubscription::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(GetCommunityDigestsResponse { community_digests })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityNotificationPosts {
+  type Response = GetCommunityNotificationPostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetCommunityNotificationPostsResponse, LemmyError> {
+    let data: &GetCommunityNotificationPosts = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let unread_only = data.unread_only;
+    let page = data.page;
+    let limit = data.limit;
+    let (notifications, posts) = blocking(context.pool(), move |conn| {
+      let notifications =
+        CommunityNotificationPost::list_for_user(conn, user_id, unread_only, page, limit)?;
+      let posts = notifications
+        .iter()
+        .map(|n| PostView::read(conn, n.post_id, Some(user_id)))
+        .collect::<Result<Vec<PostView>, _>>()?;
+      Ok((notifications, posts)) as Result<_, LemmyError>
+    })
+    .await??;
+
+    Ok(GetCommunityNotificationPostsResponse {
+      notifications,
+      posts,
+    })
+  }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for BanFromCommunity {
   type Response = BanFromCommunityResponse;
@@ -689,6 +1232,22 @@ impl Perform for AddModToCommunity {
     })
     .await??;
 
+    // Mods of remote communities are changed on their instance, and synced from there
+    let community = blocking(context.pool(), move |conn| {
+      Community::read(conn, community_id)
+    })
+    .await??;
+    if community.local {
+      let other_user_id = data.user_id;
+      let other_user =
+        blocking(context.pool(), move |conn| User_::read(conn, other_user_id)).await??;
+      if data.added {
+        send_add_mod(&community, &user, &other_user, context).await?;
+      } else {
+        send_remove_mod(&community, &user, &other_user, context).await?;
+      }
+    }
+
     let community_id = data.community_id;
     let moderators = blocking(context.pool(), move |conn| {
       CommunityModeratorView::for_community(conn, community_id)
@@ -817,15 +1376,1434 @@ impl Perform for TransferCommunity {
       Err(_e) => return Err(APIError::err("couldnt_find_community").into()),
     };
 
+    let community_id = data.community_id;
+    let settings = blocking(context.pool(), move |conn| {
+      CommunitySettings::read_for_community(conn, community_id)
+    })
+    .await??;
+
+    let appearance = blocking(context.pool(), move |conn| {
+      CommunityAppearance::read_for_community(conn, community_id)
+    })
+    .await??;
+
     // Return the jwt
     Ok(GetCommunityResponse {
       community: community_view,
       moderators,
+      settings,
+      appearance,
+      join_request: None,
       online: 0,
     })
   }
 }
 
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityJoinRequests {
+  type Response = CommunityJoinRequestsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityJoinRequestsResponse, LemmyError> {
+    let data: &GetCommunityJoinRequests = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let join_requests = blocking(context.pool(), move |conn| {
+      CommunityJoinRequestView::for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityJoinRequestsResponse { join_requests })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ReviewCommunityJoinRequest {
+  type Response = CommunityJoinRequestsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityJoinRequestsResponse, LemmyError> {
+    let data: &ReviewCommunityJoinRequest = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let join_request_id = data.join_request_id;
+    let join_request = blocking(context.pool(), move |conn| {
+      CommunityJoinRequest::read(conn, join_request_id)
+    })
+    .await??;
+
+    let community_id = join_request.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let applicant_id = join_request.user_id;
+    let (community, applicant) = blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        Ok((
+          Community::read(conn, community_id)?,
+          User_::read(conn, applicant_id)?,
+        ))
+      },
+    )
+    .await??;
+
+    if data.approve {
+      let community_follower_form = CommunityFollowerForm {
+        community_id,
+        user_id: applicant_id,
+      };
+      let follow = move |conn: &'_ _| CommunityFollower::follow(conn, &community_follower_form);
+      if blocking(context.pool(), follow).await?.is_err() {
+        return Err(APIError::err("community_follower_already_exists").into());
+      }
+      send_welcome_message(community_id, &applicant, context).await?;
+    }
+
+    blocking(context.pool(), move |conn| {
+      CommunityJoinRequest::remove(conn, community_id, applicant_id)
+    })
+    .await??;
+    send_mod_inbox_counts(community_id, context).await?;
+
+    if applicant.local {
+      // Let the applicant know, if they are online
+      let community_view = blocking(context.pool(), move |conn| {
+        CommunityView::read(conn, community_id, Some(applicant_id))
+      })
+      .await??;
+      context.chat_server().do_send(SendUserRoomMessage {
+        op: UserOperation::FollowCommunity,
+        response: CommunityResponse {
+          community: community_view,
+        },
+        recipient_id: applicant_id,
+        websocket_id: None,
+      });
+    } else {
+      let follow = join_request_follow(join_request.follow, &community, &applicant)?;
+      if data.approve {
+        community.send_accept_follow(follow, context).await?;
+      } else {
+        send_reject_join_request(&community, &applicant, follow, context).await?;
+      }
+    }
+
+    let join_requests = blocking(context.pool(), move |conn| {
+      CommunityJoinRequestView::for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityJoinRequestsResponse { join_requests })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityMembers {
+  type Response = CommunityMembersResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityMembersResponse, LemmyError> {
+    let data: &GetCommunityMembers = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let members = blocking(context.pool(), move |conn| {
+      CommunityFollowerView::for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityMembersResponse { members })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for RemoveCommunityMember {
+  type Response = CommunityMembersResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityMembersResponse, LemmyError> {
+    let data: &RemoveCommunityMember = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    // Mods and admins count as members whether they follow the community or not
+    let member_id = data.user_id;
+    if blocking(context.pool(), move |conn| {
+      Community::is_mod_or_admin(conn, member_id, community_id)
+    })
+    .await?
+    {
+      return Err(APIError::err("couldnt_remove_community_member").into());
+    }
+
+    let community_follower_form = CommunityFollowerForm {
+      community_id,
+      user_id: member_id,
+    };
+    let unfollow = move |conn: &'_ _| CommunityFollower::unfollow(conn, &community_follower_form);
+    if blocking(context.pool(), unfollow).await?.is_err() {
+      return Err(APIError::err("couldnt_remove_community_member").into());
+    }
+
+    let members = blocking(context.pool(), move |conn| {
+      CommunityFollowerView::for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityMembersResponse { members })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetModInbox {
+  type Response = GetModInboxResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetModInboxResponse, LemmyError> {
+    let data: &GetModInbox = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let (join_requests, reports, user_reports, vote_bursts) =
+      blocking(context.pool(), move |conn| {
+        let community_ids = moderated_community_ids(conn, user_id)?;
+        Ok((
+          CommunityJoinRequestView::for_communities(conn, community_ids.to_owned())?,
+          SpamScore::list_open_for_communities(conn, community_ids.to_owned())?,
+          ContentReport::list_open_for_communities(conn, community_ids.to_owned())?,
+          VoteBurst::list_open_for_communities(conn, community_ids)?,
+        )) as Result<_, diesel::result::Error>
+      })
+      .await??;
+
+    Ok(GetModInboxResponse {
+      join_requests,
+      reports,
+      user_reports,
+      vote_bursts,
+    })
+  }
+}
+
+/// In characters. Longer reasons of remote reports are cut off.
+pub const MAX_REPORT_REASON_LENGTH: usize = 1000;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreateReport {
+  type Response = ReportResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ReportResponse, LemmyError> {
+    let data: &CreateReport = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let reason = data.reason.trim().to_owned();
+    if reason.is_empty() {
+      return Err(APIError::err("report_reason_required").into());
+    }
+    if reason.chars().count() > MAX_REPORT_REASON_LENGTH {
+      return Err(APIError::err("report_reason_too_long").into());
+    }
+    check_slurs(&reason)?;
+
+    // The ap_id of the reported post or comment, for the Flag to a remote community
+    let (post_id, comment_id) = (data.post_id, data.comment_id);
+    let (post, object_id) = match (post_id, comment_id) {
+      (Some(post_id), None) => {
+        let post = get_post(post_id, context.pool()).await?;
+        let ap_id = post.ap_id.to_owned();
+        (post, ap_id)
+      }
+      (None, Some(comment_id)) => {
+        let comment =
+          match blocking(context.pool(), move |conn| Comment::read(conn, comment_id)).await? {
+            Ok(comment) => comment,
+            Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+          };
+        (
+          get_post(comment.post_id, context.pool()).await?,
+          comment.ap_id,
+        )
+      }
+      _ => return Err(APIError::err("couldnt_create_report").into()),
+    };
+    check_community_visible(Some(user.id), post.community_id, context.pool()).await?;
+
+    let form = ContentReportForm {
+      creator_id: user.id,
+      post_id,
+      comment_id,
+      reason: reason.to_owned(),
+      ap_id: None,
+    };
+    let report = match blocking(context.pool(), move |conn| {
+      ContentReport::report(conn, &form)
+    })
+    .await?
+    {
+      Ok(Some(report)) => report,
+      _ => return Err(APIError::err("couldnt_create_report").into()),
+    };
+
+    let community_id = post.community_id;
+    let community = blocking(context.pool(), move |conn| {
+      Community::read(conn, community_id)
+    })
+    .await??;
+    if community.local {
+      send_mod_inbox_counts(community_id, context).await?;
+    } else {
+      // Only the mods on the instance of the community can act on it
+      send_flag(&user, &community, &object_id, &reason, context).await?;
+    }
+
+    Ok(ReportResponse { report })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ResolveReport {
+  type Response = ReportResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ReportResponse, LemmyError> {
+    let data: &ResolveReport = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let report_id = data.report_id;
+    let (report, community_id) = match blocking(context.pool(), move |conn| {
+      let report = ContentReport::read(conn, report_id)?;
+      let community_id = report.community_id(conn)?;
+      Ok::<_, diesel::result::Error>((report, community_id))
+    })
+    .await?
+    {
+      Ok(found) => found,
+      Err(_e) => return Err(APIError::err("couldnt_find_report").into()),
+    };
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let user_id = user.id;
+    let report = if report.resolved {
+      report
+    } else {
+      blocking(context.pool(), move |conn| {
+        ContentReport::resolve(conn, report_id, user_id)
+      })
+      .await??
+    };
+    send_mod_inbox_counts(community_id, context).await?;
+
+    Ok(ReportResponse { report })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ResolveVoteBurst {
+  type Response = VoteBurstResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<VoteBurstResponse, LemmyError> {
+    let data: &ResolveVoteBurst = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let vote_burst_id = data.vote_burst_id;
+    let (vote_burst, community_id) = match blocking(context.pool(), move |conn| {
+      let vote_burst = VoteBurst::read(conn, vote_burst_id)?;
+      let community_id = vote_burst.community_id(conn)?;
+      Ok::<_, diesel::result::Error>((vote_burst, community_id))
+    })
+    .await?
+    {
+      Ok(found) => found,
+      Err(_e) => return Err(APIError::err("couldnt_find_vote_burst").into()),
+    };
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let vote_burst = if vote_burst.resolved {
+      vote_burst
+    } else {
+      blocking(context.pool(), move |conn| {
+        VoteBurst::resolve(conn, vote_burst_id)
+      })
+      .await??
+    };
+    send_mod_inbox_counts(community_id, context).await?;
+
+    Ok(VoteBurstResponse { vote_burst })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetVoteOrigins {
+  type Response = GetVoteOriginsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetVoteOriginsResponse, LemmyError> {
+    let data: &GetVoteOrigins = &self;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
+
+    let origins = match (data.post_id, data.comment_id) {
+      (Some(post_id), None) => {
+        let post = get_post(post_id, context.pool()).await?;
+        is_mod_or_admin(context.pool(), user.id, post.community_id).await?;
+        blocking(context.pool(), move |conn| {
+          VoteOrigin::for_post(conn, post_id)
+        })
+        .await??
+      }
+      (None, Some(comment_id)) => {
+        let comment =
+          match blocking(context.pool(), move |conn| Comment::read(conn, comment_id)).await? {
+            Ok(comment) => comment,
+            Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+          };
+        let post = get_post(comment.post_id, context.pool()).await?;
+        is_mod_or_admin(context.pool(), user.id, post.community_id).await?;
+        blocking(context.pool(), move |conn| {
+          VoteOrigin::for_comment(conn, comment_id)
+        })
+        .await??
+      }
+      _ => return Err(APIError::err("couldnt_get_vote_origins").into()),
+    };
+
+    Ok(GetVoteOriginsResponse { origins })
+  }
+}
+
+/// Alerts the mods of a local community when one of its posts gets a burst of votes from a remote
+/// instance, see VOTE_BURST_VOTES. Each burst is only reported once.
+pub async fn check_vote_burst(
+  post_id: i32,
+  instance: String,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let since = naive_now() - chrono::Duration::minutes(VOTE_BURST_MINUTES);
+  let community_id = blocking(context.pool(), move |conn| {
+    let post = Post::read(conn, post_id)?;
+    let community = Community::read(conn, post.community_id)?;
+    if !community.local {
+      return Ok(None);
+    }
+    let votes = count_recent_post_votes_from_instance(conn, post_id, &instance, since)?;
+    if votes < VOTE_BURST_VOTES || VoteBurst::exists_since(conn, post_id, &instance, since)? {
+      return Ok(None);
+    }
+    let form = VoteBurstForm {
+      post_id,
+      instance,
+      votes: votes as i32,
+    };
+    VoteBurst::create(conn, &form)?;
+    Ok(Some(community.id)) as Result<_, diesel::result::Error>
+  })
+  .await??;
+
+  if let Some(community_id) = community_id {
+    send_mod_inbox_counts(community_id, context).await?;
+  }
+  Ok(())
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetModInboxCounts {
+  type Response = ModInboxCountsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ModInboxCountsResponse, LemmyError> {
+    let data: &GetModInboxCounts = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    get_mod_inbox_counts(user.id, context.pool()).await
+  }
+}
+
+/// The communities which the user moderates. Unlike elsewhere, admins don't count as mods of every
+/// community here, as they have the spam list of the whole instance.
+pub(in crate::api) fn moderated_community_ids(
+  conn: &PgConnection,
+  user_id: i32,
+) -> Result<Vec<i32>, diesel::result::Error> {
+  Ok(
+    CommunityModeratorView::for_user(conn, user_id)?
+      .into_iter()
+      .map(|m| m.community_id)
+      .collect(),
+  )
+}
+
+async fn get_mod_inbox_counts(
+  user_id: i32,
+  pool: &DbPool,
+) -> Result<ModInboxCountsResponse, LemmyError> {
+  let (join_requests, reports, user_reports, vote_bursts, pending_posts) =
+    blocking(pool, move |conn| {
+      let community_ids = moderated_community_ids(conn, user_id)?;
+      Ok((
+        CommunityJoinRequestView::for_communities(conn, community_ids.to_owned())?.len(),
+        SpamScore::list_open_for_communities(conn, community_ids.to_owned())?.len(),
+        ContentReport::list_open_for_communities(conn, community_ids.to_owned())?.len(),
+        VoteBurst::list_open_for_communities(conn, community_ids.to_owned())?.len(),
+        DomainPostApproval::count_for_communities(conn, community_ids)?,
+      )) as Result<_, diesel::result::Error>
+    })
+    .await??;
+  Ok(ModInboxCountsResponse {
+    join_requests: join_requests as i64,
+    reports: reports as i64,
+    user_reports: user_reports as i64,
+    vote_bursts: vote_bursts as i64,
+    pending_posts,
+  })
+}
+
+/// Pushes the new mod inbox counts to the mods of the community who are online, after something
+/// was added to or taken off its queues.
+pub async fn send_mod_inbox_counts(
+  community_id: i32,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let moderators = blocking(context.pool(), move |conn| {
+    CommunityModeratorView::for_community(conn, community_id)
+  })
+  .await??;
+  for moderator in moderators.into_iter().filter(|m| m.user_local) {
+    let counts = get_mod_inbox_counts(moderator.user_id, context.pool()).await?;
+    context.chat_server().do_send(SendUserRoomMessage {
+      op: UserOperation::GetModInboxCounts,
+      response: counts,
+      recipient_id: moderator.user_id,
+      websocket_id: None,
+    });
+  }
+  Ok(())
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetRemovalReasons {
+  type Response = RemovalReasonsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<RemovalReasonsResponse, LemmyError> {
+    let data: &GetRemovalReasons = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let removal_reasons = blocking(context.pool(), move |conn| {
+      RemovalReason::list_for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(RemovalReasonsResponse { removal_reasons })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreateRemovalReason {
+  type Response = RemovalReasonsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<RemovalReasonsResponse, LemmyError> {
+    let data: &CreateRemovalReason = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    check_slurs(&data.title)?;
+    check_slurs(&data.reason)?;
+
+    let removal_reason_form = RemovalReasonForm {
+      community_id,
+      title: data.title.to_owned(),
+      reason: data.reason.to_owned(),
+    };
+    let create = move |conn: &'_ _| RemovalReason::create(conn, &removal_reason_form);
+    if blocking(context.pool(), create).await?.is_err() {
+      return Err(APIError::err("couldnt_create_removal_reason").into());
+    }
+
+    let removal_reasons = blocking(context.pool(), move |conn| {
+      RemovalReason::list_for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(RemovalReasonsResponse { removal_reasons })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeleteRemovalReason {
+  type Response = RemovalReasonsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<RemovalReasonsResponse, LemmyError> {
+    let data: &DeleteRemovalReason = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let removal_reason_id = data.removal_reason_id;
+    let removal_reason = match blocking(context.pool(), move |conn| {
+      RemovalReason::read(conn, removal_reason_id)
+    })
+    .await?
+    {
+      Ok(removal_reason) => removal_reason,
+      Err(_e) => return Err(APIError::err("couldnt_find_removal_reason").into()),
+    };
+
+    let community_id = removal_reason.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    blocking(context.pool(), move |conn| {
+      RemovalReason::delete(conn, removal_reason_id)
+    })
+    .await??;
+
+    let removal_reasons = blocking(context.pool(), move |conn| {
+      RemovalReason::list_for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(RemovalReasonsResponse { removal_reasons })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityPostTags {
+  type Response = CommunityPostTagsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityPostTagsResponse, LemmyError> {
+    let data: &GetCommunityPostTags = &self;
+    let community_id = data.community_id;
+    let tags = blocking(context.pool(), move |conn| {
+      CommunityPostTag::list_for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityPostTagsResponse { tags })
+  }
+}
+
+/// The most tags a community can have.
+const MAX_COMMUNITY_POST_TAGS: i64 = 50;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreateCommunityPostTag {
+  type Response = CommunityPostTagsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityPostTagsResponse, LemmyError> {
+    let data: &CreateCommunityPostTag = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    // Names are a varchar(50)
+    let name = data.name.trim().to_owned();
+    check_slurs(&name)?;
+    if name.is_empty() || name.chars().count() > 50 {
+      return Err(APIError::err("invalid_post_tag_name").into());
+    }
+
+    let tag_count = blocking(context.pool(), move |conn| {
+      CommunityPostTag::count_for_community(conn, community_id)
+    })
+    .await??;
+    if tag_count >= MAX_COMMUNITY_POST_TAGS {
+      return Err(APIError::err("too_many_post_tags").into());
+    }
+
+    let tag_form = CommunityPostTagForm { community_id, name };
+    let create = move |conn: &'_ _| CommunityPostTag::create(conn, &tag_form);
+    if blocking(context.pool(), create).await?.is_err() {
+      return Err(APIError::err("couldnt_create_post_tag").into());
+    }
+
+    let tags = blocking(context.pool(), move |conn| {
+      CommunityPostTag::list_for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityPostTagsResponse { tags })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeleteCommunityPostTag {
+  type Response = CommunityPostTagsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CommunityPostTagsResponse, LemmyError> {
+    let data: &DeleteCommunityPostTag = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let tag_id = data.tag_id;
+    let tag = match blocking(context.pool(), move |conn| {
+      CommunityPostTag::read(conn, tag_id)
+    })
+    .await?
+    {
+      Ok(tag) => tag,
+      Err(_e) => return Err(APIError::err("couldnt_find_post_tag").into()),
+    };
+
+    let community_id = tag.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    // The posts which had the tag just lose it
+    blocking(context.pool(), move |conn| {
+      CommunityPostTag::delete(conn, tag_id)
+    })
+    .await??;
+
+    let tags = blocking(context.pool(), move |conn| {
+      CommunityPostTag::list_for_community(conn, community_id)
+    })
+    .await??;
+
+    Ok(CommunityPostTagsResponse { tags })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for BulkRemoveFromCommunity {
+  type Response = ModBulkActionResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ModBulkActionResponse, LemmyError> {
+    let data: &BulkRemoveFromCommunity = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let reason =
+      get_removal_reason(&data.reason, data.reason_id, community_id, context.pool()).await?;
+
+    let since = data.since.map(naive_from_unix);
+    let until = data.until.map(naive_from_unix);
+    let removed_user_id = data.user_id;
+    let (posts, comments) = blocking(context.pool(), move |conn| {
+      Ok::<_, LemmyError>((
+        Post::list_for_creator_in_community(conn, removed_user_id, community_id, since, until)?,
+        Comment::list_for_creator_in_community(conn, removed_user_id, community_id, since, until)?,
+      ))
+    })
+    .await??;
+
+    let form = ModBulkActionForm {
+      mod_user_id: user.id,
+      community_id,
+      other_user_ids: vec![removed_user_id],
+      banned: false,
+      since,
+      until,
+      reason: Some(reason.to_owned()),
+      total: (posts.len() + comments.len()) as i32,
+    };
+    let bulk_action = create_bulk_action(form, context).await?;
+
+    let bulk_action_id = bulk_action.id;
+    let context = context.to_owned();
+    actix_web::rt::spawn(async move {
+      let removed = bulk_remove(bulk_action_id, posts, comments, &user, reason, &context).await;
+      finish_bulk_action(bulk_action_id, user.id, removed, &context).await;
+    });
+
+    Ok(ModBulkActionResponse { bulk_action })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for BulkBanFromCommunity {
+  type Response = ModBulkActionResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ModBulkActionResponse, LemmyError> {
+    let data: &BulkBanFromCommunity = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    // Mods and admins can't be banned from the community
+    let user_ids = data.user_ids.to_owned();
+    let banned_user_ids = blocking(context.pool(), move |conn| {
+      user_ids
+        .into_iter()
+        .filter(|user_id| !Community::is_mod_or_admin(conn, *user_id, community_id))
+        .collect::<Vec<i32>>()
+    })
+    .await?;
+
+    let form = ModBulkActionForm {
+      mod_user_id: user.id,
+      community_id,
+      other_user_ids: banned_user_ids.to_owned(),
+      banned: true,
+      since: None,
+      until: None,
+      reason: data.reason.to_owned(),
+      total: banned_user_ids.len() as i32,
+    };
+    let bulk_action = create_bulk_action(form, context).await?;
+
+    let bulk_action_id = bulk_action.id;
+    let context = context.to_owned();
+    actix_web::rt::spawn(async move {
+      let banned = bulk_ban(bulk_action_id, community_id, banned_user_ids, &context).await;
+      finish_bulk_action(bulk_action_id, user.id, banned, &context).await;
+    });
+
+    Ok(ModBulkActionResponse { bulk_action })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetModBulkAction {
+  type Response = ModBulkActionResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ModBulkActionResponse, LemmyError> {
+    let data: &GetModBulkAction = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let id = data.id;
+    let bulk_action = blocking(context.pool(), move |conn| {
+      ModBulkActionView::read(conn, id)
+    })
+    .await??;
+
+    is_mod_or_admin(context.pool(), user.id, bulk_action.community_id).await?;
+
+    Ok(ModBulkActionResponse { bulk_action })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetCommunityStats {
+  type Response = GetCommunityStatsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetCommunityStatsResponse, LemmyError> {
+    let data: &GetCommunityStats = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let (since, until) = get_stats_range(data.since, data.until)?;
+
+    let user_id = user.id;
+    let (community, days, top_contributors, (views, unique_views)) = blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        Ok((
+          CommunityView::read(conn, community_id, Some(user_id))?,
+          CommunityStatsDaily::list_for_community(conn, community_id, since.date(), until.date())?,
+          CommunityContributor::list_top(conn, community_id, since, until, 10)?,
+          PostViewCount::totals_for_community(conn, community_id)?,
+        ))
+      },
+    )
+    .await??;
+
+    Ok(GetCommunityStatsResponse {
+      community,
+      days,
+      top_contributors,
+      views,
+      unique_views,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListScheduledPosts {
+  type Response = ScheduledPostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ScheduledPostsResponse, LemmyError> {
+    let data: &ListScheduledPosts = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    scheduled_posts_response(community_id, context.pool()).await
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreateScheduledPost {
+  type Response = ScheduledPostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ScheduledPostsResponse, LemmyError> {
+    let data: &CreateScheduledPost = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let community_id = data.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let next_run = check_scheduled_post(&data.title_template, &data.body, &data.schedule)?;
+
+    // The posts are created by the automod user
+    get_or_create_automod_user(community_id, context.pool()).await?;
+
+    let form = ScheduledPostForm {
+      community_id,
+      creator_id: user.id,
+      title_template: data.title_template.trim().to_owned(),
+      body: data.body.to_owned(),
+      schedule: data.schedule.trim().to_owned(),
+      stickied: data.stickied,
+      enabled: true,
+      next_run,
+      updated: None,
+    };
+    let create = move |conn: &'_ _| ScheduledPost::create(conn, &form);
+    if blocking(context.pool(), create).await?.is_err() {
+      return Err(APIError::err("couldnt_create_scheduled_post").into());
+    }
+
+    scheduled_posts_response(community_id, context.pool()).await
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for EditScheduledPost {
+  type Response = ScheduledPostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ScheduledPostsResponse, LemmyError> {
+    let data: &EditScheduledPost = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let orig = get_scheduled_post(data.id, context.pool()).await?;
+    let community_id = orig.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    let next_run = check_scheduled_post(&data.title_template, &data.body, &data.schedule)?;
+
+    let id = data.id;
+    let form = ScheduledPostForm {
+      community_id,
+      creator_id: orig.creator_id,
+      title_template: data.title_template.trim().to_owned(),
+      body: data.body.to_owned(),
+      schedule: data.schedule.trim().to_owned(),
+      stickied: data.stickied,
+      enabled: data.enabled,
+      next_run,
+      updated: Some(naive_now()),
+    };
+    let update = move |conn: &'_ _| ScheduledPost::update(conn, id, &form);
+    if blocking(context.pool(), update).await?.is_err() {
+      return Err(APIError::err("couldnt_update_scheduled_post").into());
+    }
+
+    scheduled_posts_response(community_id, context.pool()).await
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeleteScheduledPost {
+  type Response = ScheduledPostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ScheduledPostsResponse, LemmyError> {
+    let data: &DeleteScheduledPost = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let orig = get_scheduled_post(data.id, context.pool()).await?;
+    let community_id = orig.community_id;
+    is_mod_or_admin(context.pool(), user.id, community_id).await?;
+
+    // The posts which were already created stay
+    let id = data.id;
+    blocking(context.pool(), move |conn| ScheduledPost::delete(conn, id)).await??;
+
+    scheduled_posts_response(community_id, context.pool()).await
+  }
+}
+
+/// Returns the next time the post is created.
+fn check_scheduled_post(
+  title_template: &str,
+  body: &Option<String>,
+  schedule: &str,
+) -> Result<chrono::NaiveDateTime, LemmyError> {
+  check_slurs(title_template)?;
+  check_slurs_opt(body)?;
+
+  let next_run = match Schedule::parse(schedule).and_then(|s| s.next_after(naive_now())) {
+    Some(n) => n,
+    None => return Err(APIError::err("invalid_schedule").into()),
+  };
+  if !is_valid_post_title(&render_title_template(title_template, next_run)) {
+    return Err(APIError::err("invalid_post_title").into());
+  }
+  Ok(next_run)
+}
+
+async fn get_scheduled_post(id: i32, pool: &DbPool) -> Result<ScheduledPost, LemmyError> {
+  match blocking(pool, move |conn| ScheduledPost::read(conn, id)).await? {
+    Ok(scheduled_post) => Ok(scheduled_post),
+    Err(_e) => Err(APIError::err("couldnt_find_scheduled_post").into()),
+  }
+}
+
+async fn scheduled_posts_response(
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<ScheduledPostsResponse, LemmyError> {
+  let scheduled_posts = blocking(pool, move |conn| {
+    ScheduledPost::list_for_community(conn, community_id)
+  })
+  .await??;
+  Ok(ScheduledPostsResponse { scheduled_posts })
+}
+
+async fn create_bulk_action(
+  form: ModBulkActionForm,
+  context: &Data<LemmyContext>,
+) -> Result<ModBulkActionView, LemmyError> {
+  let bulk_action = match blocking(context.pool(), move |conn| {
+    ModBulkAction::create(conn, &form)
+  })
+  .await?
+  {
+    Ok(bulk_action) => bulk_action,
+    Err(_e) => return Err(APIError::err("couldnt_create_bulk_action").into()),
+  };
+
+  let bulk_action_id = bulk_action.id;
+  let bulk_action_view = blocking(context.pool(), move |conn| {
+    ModBulkActionView::read(conn, bulk_action_id)
+  })
+  .await??;
+  Ok(bulk_action_view)
+}
+
+/// Removes the posts and comments one by one in the background, saving the progress after each.
+/// Every removal is federated, but they share the single modlog entry of the bulk action. Items
+/// which fail are logged and recorded on the bulk action, without stopping the rest.
+async fn bulk_remove(
+  bulk_action_id: i32,
+  posts: Vec<Post>,
+  comments: Vec<Comment>,
+  mod_: &User_,
+  reason: String,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let mut done = 0;
+  let mut failed_post_ids = Vec::new();
+  let mut failed_comment_ids = Vec::new();
+
+  for post in posts {
+    let post_id = post.id;
+    if let Err(e) = bulk_remove_post(post_id, mod_, &reason, context).await {
+      error!(
+        "Bulk action {} couldn't remove post {}: {}",
+        bulk_action_id, post_id, e
+      );
+      failed_post_ids.push(post_id);
+      update_bulk_action_failed(
+        bulk_action_id,
+        &failed_post_ids,
+        &failed_comment_ids,
+        context,
+      )
+      .await?;
+    }
+
+    done += 1;
+    blocking(context.pool(), move |conn| {
+      ModBulkAction::update_progress(conn, bulk_action_id, done)
+    })
+    .await??;
+  }
+
+  for comment in comments {
+    let comment_id = comment.id;
+    if let Err(e) = bulk_remove_comment(comment_id, mod_, &reason, context).await {
+      error!(
+        "Bulk action {} couldn't remove comment {}: {}",
+        bulk_action_id, comment_id, e
+      );
+      failed_comment_ids.push(comment_id);
+      update_bulk_action_failed(
+        bulk_action_id,
+        &failed_post_ids,
+        &failed_comment_ids,
+        context,
+      )
+      .await?;
+    }
+
+    done += 1;
+    blocking(context.pool(), move |conn| {
+      ModBulkAction::update_progress(conn, bulk_action_id, done)
+    })
+    .await??;
+  }
+
+  Ok(())
+}
+
+async fn bulk_remove_post(
+  post_id: i32,
+  mod_: &User_,
+  reason: &str,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let removed_post = blocking(context.pool(), move |conn| {
+    Post::update_removed(conn, post_id, true)
+  })
+  .await??;
+  removed_post
+    .send_remove(mod_, Some(reason.to_owned()), context)
+    .await
+}
+
+async fn bulk_remove_comment(
+  comment_id: i32,
+  mod_: &User_,
+  reason: &str,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let removed_comment = blocking(context.pool(), move |conn| {
+    Comment::update_removed(conn, comment_id, true)
+  })
+  .await??;
+  removed_comment
+    .send_remove(mod_, Some(reason.to_owned()), context)
+    .await
+}
+
+async fn update_bulk_action_failed(
+  bulk_action_id: i32,
+  failed_post_ids: &[i32],
+  failed_comment_ids: &[i32],
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let failed_post_ids = failed_post_ids.to_vec();
+  let failed_comment_ids = failed_comment_ids.to_vec();
+  blocking(context.pool(), move |conn| {
+    ModBulkAction::update_failed(conn, bulk_action_id, failed_post_ids, failed_comment_ids)
+  })
+  .await??;
+  Ok(())
+}
+
+async fn bulk_ban(
+  bulk_action_id: i32,
+  community_id: i32,
+  user_ids: Vec<i32>,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let mut done = 0;
+
+  for user_id in user_ids {
+    let community_user_ban_form = CommunityUserBanForm {
+      community_id,
+      user_id,
+    };
+    // Users who are banned already are skipped
+    blocking(context.pool(), move |conn| {
+      CommunityUserBan::ban(conn, &community_user_ban_form)
+    })
+    .await?
+    .ok();
+
+    done += 1;
+    blocking(context.pool(), move |conn| {
+      ModBulkAction::update_progress(conn, bulk_action_id, done)
+    })
+    .await??;
+  }
+
+  Ok(())
+}
+
+/// Marks the bulk action as finished, even if it failed halfway, and sends it to the mod.
+async fn finish_bulk_action(
+  bulk_action_id: i32,
+  mod_user_id: i32,
+  result: Result<(), LemmyError>,
+  context: &LemmyContext,
+) {
+  if let Err(e) = result {
+    error!("Bulk action {} failed: {}", bulk_action_id, e);
+  }
+
+  let finished = blocking(context.pool(), move |conn| {
+    ModBulkAction::finish(conn, bulk_action_id)?;
+    ModBulkActionView::read(conn, bulk_action_id)
+  })
+  .await;
+
+  match finished {
+    Ok(Ok(bulk_action)) => context.chat_server().do_send(SendUserRoomMessage {
+      op: UserOperation::GetModBulkAction,
+      response: ModBulkActionResponse { bulk_action },
+      recipient_id: mod_user_id,
+      websocket_id: None,
+    }),
+    Ok(Err(e)) => error!("Couldn't finish bulk action {}: {}", bulk_action_id, e),
+    Err(e) => error!("Couldn't finish bulk action {}: {}", bulk_action_id, e),
+  }
+}
+
+/// The automod is a local user that posts the automod comment of the community and sends its
+/// welcome message, so that they federate like anything else. It's a mod of the community, to be
+/// able to pin the comment.
+async fn create_automod_user(community_id: i32, pool: &DbPool) -> Result<i32, LemmyError> {
+  let keypair = generate_actor_keypair()?;
+  let name = automod_user_name(community_id);
+
+  let user_form = UserForm {
+    name: name.to_owned(),
+    preferred_username: Some("AutoMod".into()),
+    password_encrypted: "".into(),
+    email: None,
+    matrix_user_id: None,
+    avatar: None,
+    banner: None,
+    admin: false,
+    banned: false,
+    updated: None,
+    show_nsfw: false,
+    theme: "darkly".into(),
+    default_sort_type: SortType::Active as i16,
+    default_listing_type: ListingType::Subscribed as i16,
+    lang: "browser".into(),
+    show_avatars: true,
+    send_notifications_to_email: false,
+    actor_id: Some(make_apub_endpoint(EndpointType::User, &name).to_string()),
+    bio: None,
+    local: true,
+    private_key: Some(keypair.private_key),
+    public_key: Some(keypair.public_key),
+    last_refreshed_at: None,
+    discoverable: false,
+    receive_welcome_messages: true,
+    bot_account: true,
+    collapse_comments_below_score: None,
+    collapse_bot_comments: false,
+    remember_comment_sort: false,
+  };
+
+  let automod = blocking(pool, move |conn| {
+    let automod = User_::create(conn, &user_form)?;
+    let community_moderator_form = CommunityModeratorForm {
+      community_id,
+      user_id: automod.id,
+    };
+    CommunityModerator::join(conn, &community_moderator_form)?;
+    Ok(automod) as Result<User_, LemmyError>
+  })
+  .await?;
+
+  match automod {
+    Ok(automod) => Ok(automod.id),
+    Err(_e) => Err(APIError::err("couldnt_create_automod").into()),
+  }
+}
+
+/// Creates the automod user the first time something needs it, without changing the other
+/// settings of the community.
+async fn get_or_create_automod_user(community_id: i32, pool: &DbPool) -> Result<i32, LemmyError> {
+  let settings = blocking(pool, move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  if let Some(automod_user_id) = settings.automod_user_id {
+    return Ok(automod_user_id);
+  }
+
+  let automod_user_id = create_automod_user(community_id, pool).await?;
+  let settings_form = CommunitySettingsForm {
+    community_id,
+    require_alt_text: settings.require_alt_text,
+    trust_min_account_age_days: settings.trust_min_account_age_days,
+    trust_min_karma: settings.trust_min_karma,
+    allow_anonymous_posts: settings.allow_anonymous_posts,
+    join_requires_approval: settings.join_requires_approval,
+    join_question: settings.join_question,
+    private: settings.private,
+    local_only: settings.local_only,
+    allow_authors_close_comments: settings.allow_authors_close_comments,
+    automod_comment: settings.automod_comment,
+    automod_user_id: Some(automod_user_id),
+    welcome_message: settings.welcome_message,
+    max_post_body_length: settings.max_post_body_length,
+    max_comment_length: settings.max_comment_length,
+    allowed_post_type: settings.allowed_post_type,
+    require_post_tag: settings.require_post_tag,
+    title_pattern: settings.title_pattern,
+    clean_titles: settings.clean_titles,
+    title_capitalization: settings.title_capitalization,
+    allow_post_url_edits: settings.allow_post_url_edits,
+    microblog_mentions: settings.microblog_mentions,
+    digest_template: settings.digest_template,
+    discoverable: settings.discoverable,
+  };
+  let upsert = move |conn: &'_ _| CommunitySettings::upsert(conn, &settings_form);
+  if blocking(pool, upsert).await?.is_err() {
+    return Err(APIError::err("couldnt_update_community").into());
+  }
+  Ok(automod_user_id)
+}
+
+/// Users get at most this many welcome messages a day, so that subscribing to a lot of communities
+/// at once doesn't flood their inbox.
+const MAX_WELCOME_MESSAGES_PER_DAY: i64 = 5;
+
+/// Sends the welcome message of the community to a new subscriber, as a private message from the
+/// automod user. Every user gets it only once per community, and never if they turned welcome
+/// messages off.
+pub async fn send_welcome_message(
+  community_id: i32,
+  user: &User_,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  if !user.receive_welcome_messages {
+    return Ok(());
+  }
+
+  let settings = blocking(context.pool(), move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  let (welcome_message, automod_user_id) =
+    match (settings.welcome_message, settings.automod_user_id) {
+      (Some(welcome_message), Some(automod_user_id)) => (welcome_message, automod_user_id),
+      _ => return Ok(()),
+    };
+
+  let user_id = user.id;
+  let welcome_form = CommunityWelcomeForm {
+    community_id,
+    user_id,
+  };
+  let since = naive_now() - chrono::Duration::days(1);
+  let welcome = blocking(
+    context.pool(),
+    move |conn| -> Result<_, diesel::result::Error> {
+      if CommunityWelcome::count_for_user_since(conn, user_id, since)?
+        >= MAX_WELCOME_MESSAGES_PER_DAY
+      {
+        return Ok(None);
+      }
+      match CommunityWelcome::record(conn, &welcome_form)? {
+        Some(_) => Ok(Some((
+          Community::read(conn, community_id)?,
+          User_::read(conn, automod_user_id)?,
+        ))),
+        None => Ok(None),
+      }
+    },
+  )
+  .await??;
+  let (community, automod) = match welcome {
+    Some(welcome) => welcome,
+    None => return Ok(()),
+  };
+
+  let content = format!(
+    "{}\n\n---\n\n*You got this message because you subscribed to {}. Welcome messages of \
+     communities can be turned off in your settings.*",
+    welcome_message, community.title
+  );
+  send_mod_message(&automod, user_id, content, context).await
+}
+
 pub fn send_community_websocket(
   res: &CommunityResponse,
   context: &Data<LemmyContext>,
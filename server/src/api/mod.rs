@@ -1,14 +1,39 @@
-use crate::{api::claims::Claims, DbPool, LemmyContext};
+use crate::{
+  api::claims::Claims,
+  apub::ApubObjectType,
+  websocket::{messages::SendUserRoomMessage, UserOperation},
+  DbPool,
+  LemmyContext,
+};
 use actix_web::web::Data;
-use lemmy_api_structs::blocking;
+use lemmy_api_structs::{blocking, user::PrivateMessageResponse};
 use lemmy_db::{
-  community::Community,
+  admin_impersonation::AdminImpersonation,
+  community::{hidden_private_communities, Community, CommunityFollower, CommunitySettings},
   community_view::CommunityUserBanView,
+  naive_now,
   post::Post,
+  post_comment_sort::PostCommentSort,
+  private_message::{PrivateMessage, PrivateMessageForm},
+  private_message_view::PrivateMessageView,
+  removal_reason::RemovalReason,
+  sign_in_signal::SignInSignal,
+  site::Site,
   user::User_,
+  user_presence::UserPresence,
+  CommentSortType,
   Crud,
 };
-use lemmy_utils::{APIError, ConnectionId, LemmyError};
+use lemmy_utils::{
+  apub::{make_apub_endpoint, EndpointType},
+  settings::Settings,
+  utils::naive_from_unix,
+  APIError,
+  ConnectionId,
+  LemmyError,
+};
+use log::error;
+use std::{collections::HashMap, sync::Mutex};
 
 pub mod claims;
 pub mod comment;
@@ -57,16 +82,106 @@ pub(in crate::api) async fn get_post(post_id: i32, pool: &DbPool) -> Result<Post
   }
 }
 
-pub(in crate::api) async fn get_user_from_jwt(
+/// For users who turned on `remember_comment_sort`, the comment sort they pick for a post is
+/// remembered, and applied again when they don't pick one. Admins viewing the site as the user
+/// don't change what is remembered, and neither does anyone while the site is read only.
+pub(in crate::api) async fn comment_sort_for_post(
+  user: Option<&User_>,
+  impersonated: bool,
+  post_id: i32,
+  comment_sort: Option<CommentSortType>,
+  pool: &DbPool,
+) -> Result<Option<CommentSortType>, LemmyError> {
+  let user_id = match user {
+    Some(user) if user.remember_comment_sort => user.id,
+    _ => return Ok(comment_sort),
+  };
+  let read_only = impersonated || Settings::get().maintenance.read_only;
+  let comment_sort = blocking(pool, move |conn| -> Result<_, diesel::result::Error> {
+    match comment_sort {
+      Some(comment_sort) if read_only => Ok(Some(comment_sort)),
+      Some(comment_sort) => {
+        PostCommentSort::remember(conn, user_id, post_id, comment_sort.to_owned())?;
+        Ok(Some(comment_sort))
+      }
+      None => PostCommentSort::recall(conn, user_id, post_id),
+    }
+  })
+  .await??;
+  Ok(comment_sort)
+}
+
+pub(crate) async fn get_user_from_jwt(jwt: &str, pool: &DbPool) -> Result<User_, LemmyError> {
+  let claims = decode_jwt(jwt)?;
+  if claims.impersonation_id.is_some() {
+    return Err(APIError::err("impersonation_is_read_only").into());
+  }
+  if let Some(session_id) = claims.session_id {
+    check_session_not_revoked(session_id, pool).await?;
+  }
+  let user = read_logged_in_user(claims.id, pool).await?;
+  if claims.session_id.is_none() && user.sessionless_tokens_revoked {
+    return Err(APIError::err("session_revoked").into());
+  }
+  mark_active(user.id, pool).await;
+  Ok(user)
+}
+
+/// Like `get_user_from_jwt`, for the operations which only read. These also accept the tokens with
+/// which admins view the site as the user, see `ImpersonateUser`, and count the request in the
+/// impersonation log.
+pub(in crate::api) async fn get_user_from_jwt_for_reading(
   jwt: &str,
   pool: &DbPool,
 ) -> Result<User_, LemmyError> {
-  let claims = match Claims::decode(&jwt) {
-    Ok(claims) => claims.claims,
-    Err(_e) => return Err(APIError::err("not_logged_in").into()),
+  let claims = decode_jwt(jwt)?;
+  let impersonation_id = match claims.impersonation_id {
+    Some(id) => id,
+    None => return get_user_from_jwt(jwt, pool).await,
   };
-  let user_id = claims.id;
+  if !Settings::get().impersonation.enabled {
+    return Err(APIError::err("impersonation_disabled").into());
+  }
+  let impersonation = blocking(pool, move |conn| {
+    AdminImpersonation::record_request(conn, impersonation_id)
+  })
+  .await??;
+  match impersonation {
+    Some(i) if i.user_id == claims.id => {}
+    _ => return Err(APIError::err("impersonation_expired").into()),
+  }
+  // The user didn't do anything, so they aren't marked as active
+  read_logged_in_user(claims.id, pool).await
+}
+
+/// Whether the token is one with which an admin views the site as the user.
+pub(in crate::api) fn is_impersonation(jwt: &Option<String>) -> bool {
+  match jwt {
+    Some(jwt) => Claims::decode(jwt).map_or(false, |c| c.claims.impersonation_id.is_some()),
+    None => false,
+  }
+}
+
+fn decode_jwt(jwt: &str) -> Result<Claims, LemmyError> {
+  match Claims::decode(&jwt) {
+    Ok(claims) => Ok(claims.claims),
+    Err(_e) => Err(APIError::err("not_logged_in").into()),
+  }
+}
+
+async fn check_session_not_revoked(session_id: i32, pool: &DbPool) -> Result<(), LemmyError> {
+  let revoked = blocking(pool, move |conn| SignInSignal::is_revoked(conn, session_id)).await??;
+  if revoked {
+    return Err(APIError::err("session_revoked").into());
+  }
+  Ok(())
+}
+
+async fn read_logged_in_user(user_id: i32, pool: &DbPool) -> Result<User_, LemmyError> {
   let user = blocking(pool, move |conn| User_::read(conn, user_id)).await??;
+  if user.deleted {
+    return Err(APIError::err("not_logged_in").into());
+  }
   // Check for a site ban
   if user.banned {
     return Err(APIError::err("site_ban").into());
@@ -74,6 +189,46 @@ pub(in crate::api) async fn get_user_from_jwt(
   Ok(user)
 }
 
+/// The last active time of users is only written every few minutes, it doesn't need to be more
+/// precise than that.
+const LAST_ACTIVE_MINUTES: i64 = 5;
+/// When this many users are remembered, the ones which weren't active recently are forgotten.
+const MAX_LAST_ACTIVE_USERS: usize = 50_000;
+
+lazy_static! {
+  static ref LAST_ACTIVE: Mutex<HashMap<i32, chrono::NaiveDateTime>> = Mutex::new(HashMap::new());
+}
+
+/// Nothing is written while the site is read only. Failing to write it doesn't fail the request,
+/// it's only logged.
+async fn mark_active(user_id: i32, pool: &DbPool) {
+  if Settings::get().maintenance.read_only {
+    return;
+  }
+  let now = naive_now();
+  let recently = now - chrono::Duration::minutes(LAST_ACTIVE_MINUTES);
+  let outdated = match LAST_ACTIVE.lock() {
+    Ok(mut last_active) => match last_active.get(&user_id) {
+      Some(time) if *time > recently => false,
+      _ => {
+        if last_active.len() >= MAX_LAST_ACTIVE_USERS {
+          last_active.retain(|_, time| *time > recently);
+        }
+        last_active.insert(user_id, now);
+        true
+      }
+    },
+    Err(_) => false,
+  };
+  if outdated {
+    match blocking(pool, move |conn| UserPresence::mark_active(conn, user_id)).await {
+      Ok(Ok(_)) => {}
+      Ok(Err(e)) => error!("Couldn't mark user {} as active: {}", user_id, e),
+      Err(e) => error!("Couldn't mark user {} as active: {}", user_id, e),
+    }
+  }
+}
+
 pub(in crate::api) async fn get_user_from_jwt_opt(
   jwt: &Option<String>,
   pool: &DbPool,
@@ -84,6 +239,16 @@ pub(in crate::api) async fn get_user_from_jwt_opt(
   }
 }
 
+pub(in crate::api) async fn get_user_from_jwt_opt_for_reading(
+  jwt: &Option<String>,
+  pool: &DbPool,
+) -> Result<Option<User_>, LemmyError> {
+  match jwt {
+    Some(jwt) => Ok(Some(get_user_from_jwt_for_reading(jwt, pool).await?)),
+    None => Ok(None),
+  }
+}
+
 pub(in crate::api) async fn check_community_ban(
   user_id: i32,
   community_id: i32,
@@ -96,3 +261,235 @@ pub(in crate::api) async fn check_community_ban(
     Ok(())
   }
 }
+
+/// Communities which require approval to join, and private ones, only let their members, mods and
+/// admins post and comment. Also used for activities received from remote users.
+pub async fn check_community_membership(
+  user_id: i32,
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<(), LemmyError> {
+  let is_member = blocking(pool, move |conn| -> Result<bool, diesel::result::Error> {
+    let settings = CommunitySettings::read_for_community(conn, community_id)?;
+    Ok(
+      !settings.requires_membership()
+        || CommunityFollower::is_follower(conn, community_id, user_id)?
+        || Community::is_mod_or_admin(conn, user_id, community_id),
+    )
+  })
+  .await??;
+  if is_member {
+    Ok(())
+  } else {
+    Err(APIError::err("not_a_community_member").into())
+  }
+}
+
+/// Length limits in characters, the ones of the instance lowered by the ones of the community.
+pub(in crate::api) struct ContentLimits {
+  pub post_title: usize,
+  pub post_body: usize,
+  pub comment: usize,
+}
+
+pub(in crate::api) async fn get_content_limits(
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<ContentLimits, LemmyError> {
+  let settings = blocking(pool, move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  let limits = Settings::get().limits;
+  let lower = |instance: usize, community: Option<i32>| match community {
+    Some(c) if c >= 0 => instance.min(c as usize),
+    _ => instance,
+  };
+  Ok(ContentLimits {
+    post_title: limits.post_title_length(),
+    post_body: lower(limits.max_post_body_length, settings.max_post_body_length),
+    comment: lower(limits.max_comment_length, settings.max_comment_length),
+  })
+}
+
+/// Posts and comments can only be edited during the edit_limit_days of the site after they were
+/// published. Returns the `updated` time to save with the edit, which stays as it was during the
+/// first ninja_edit_minutes, so that quick fixes aren't marked as edited.
+pub(in crate::api) async fn check_edit_window(
+  published: chrono::NaiveDateTime,
+  updated: Option<chrono::NaiveDateTime>,
+  pool: &DbPool,
+) -> Result<Option<chrono::NaiveDateTime>, LemmyError> {
+  let site = blocking(pool, move |conn| Site::read(conn, 1)).await?.ok();
+  let now = naive_now();
+
+  let edit_limit_days = site.as_ref().and_then(|s| s.edit_limit_days);
+  if let Some(days) = edit_limit_days {
+    if published < now - chrono::Duration::days(days as i64) {
+      return Err(APIError::err("edit_window_expired").into());
+    }
+  }
+
+  match site.and_then(|s| s.ninja_edit_minutes) {
+    Some(minutes) if published > now - chrono::Duration::minutes(minutes as i64) => Ok(updated),
+    _ => Ok(Some(now)),
+  }
+}
+
+/// Private communities only show their content to their members, mods and admins.
+pub(in crate::api) async fn check_community_visible(
+  user_id: Option<i32>,
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<(), LemmyError> {
+  let hidden = blocking(pool, move |conn| hidden_private_communities(conn, user_id)).await??;
+  if hidden.contains(&community_id) {
+    Err(APIError::err("not_a_community_member").into())
+  } else {
+    Ok(())
+  }
+}
+
+/// Posts older than the vote_lock_days of the site are archived, and neither they nor their
+/// comments can be voted on anymore. Also used for votes received from remote users.
+pub async fn check_votes_unlocked(post: &Post, pool: &DbPool) -> Result<(), LemmyError> {
+  let site = blocking(pool, move |conn| Site::read(conn, 1)).await??;
+  if post.is_archived(site.vote_lock_days) {
+    Err(APIError::err("votes_locked").into())
+  } else {
+    Ok(())
+  }
+}
+
+/// Authors can only restore their deleted posts and comments during the undelete_window_days of
+/// the site, after that the deletion is permanent.
+pub(in crate::api) async fn check_undelete_window(
+  deleted_at: Option<chrono::NaiveDateTime>,
+  pool: &DbPool,
+) -> Result<(), LemmyError> {
+  let site = blocking(pool, move |conn| Site::read(conn, 1)).await?;
+  let undelete_window_days = site.ok().and_then(|s| s.undelete_window_days);
+  let expired = match (deleted_at, undelete_window_days) {
+    (Some(deleted_at), Some(days)) => {
+      deleted_at < naive_now() - chrono::Duration::days(days as i64)
+    }
+    _ => false,
+  };
+  if expired {
+    Err(APIError::err("undelete_window_expired").into())
+  } else {
+    Ok(())
+  }
+}
+
+/// Mods have to give a reason when they remove something, either written out or picked from the
+/// removal reasons of the community. A written reason is added after the picked one.
+pub(in crate::api) async fn get_removal_reason(
+  reason: &Option<String>,
+  reason_id: Option<i32>,
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<String, LemmyError> {
+  let picked = match reason_id {
+    Some(reason_id) => {
+      match blocking(pool, move |conn| RemovalReason::read(conn, reason_id)).await? {
+        Ok(removal_reason) if removal_reason.community_id == community_id => {
+          Some(removal_reason.reason)
+        }
+        _ => return Err(APIError::err("couldnt_find_removal_reason").into()),
+      }
+    }
+    None => None,
+  };
+  let written = reason
+    .as_ref()
+    .map(|r| r.trim().to_owned())
+    .filter(|r| !r.is_empty());
+
+  match (picked, written) {
+    (Some(picked), Some(written)) => Ok(format!("{}\n\n{}", picked, written)),
+    (Some(reason), None) | (None, Some(reason)) => Ok(reason),
+    (None, None) => Err(APIError::err("removal_reason_required").into()),
+  }
+}
+
+/// Stats cover at most a year, so that they stay cheap to compute.
+const MAX_STATS_DAYS: i64 = 366;
+
+/// The time range of the stats between the given unix timestamps, the last 30 days by default.
+pub(in crate::api) fn get_stats_range(
+  since: Option<i64>,
+  until: Option<i64>,
+) -> Result<(chrono::NaiveDateTime, chrono::NaiveDateTime), LemmyError> {
+  let until = until.map(naive_from_unix).unwrap_or_else(naive_now);
+  let since = since
+    .map(naive_from_unix)
+    .unwrap_or_else(|| until - chrono::Duration::days(30));
+  if since > until || until - since > chrono::Duration::days(MAX_STATS_DAYS) {
+    return Err(APIError::err("invalid_stats_range").into());
+  }
+  Ok((since, until))
+}
+
+/// Sends a private message from a mod, like the reason why a post or comment was removed, or the
+/// welcome message of a community.
+pub(in crate::api) async fn send_mod_message(
+  mod_: &User_,
+  recipient_id: i32,
+  content: String,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let private_message_form = PrivateMessageForm {
+    content,
+    creator_id: mod_.id,
+    recipient_id,
+    deleted: None,
+    read: None,
+    updated: None,
+    ap_id: None,
+    local: true,
+    published: None,
+  };
+  let private_message = match blocking(context.pool(), move |conn| {
+    let inserted = PrivateMessage::create(conn, &private_message_form)?;
+    let apub_id =
+      make_apub_endpoint(EndpointType::PrivateMessage, &inserted.id.to_string()).to_string();
+    PrivateMessage::update_ap_id(conn, inserted.id, apub_id)
+  })
+  .await?
+  {
+    Ok(private_message) => private_message,
+    Err(_e) => return Err(APIError::err("couldnt_create_private_message").into()),
+  };
+
+  private_message.send_create(mod_, context).await?;
+
+  let private_message_id = private_message.id;
+  let message = blocking(context.pool(), move |conn| {
+    PrivateMessageView::read(conn, private_message_id)
+  })
+  .await??;
+
+  context.chat_server().do_send(SendUserRoomMessage {
+    op: UserOperation::CreatePrivateMessage,
+    response: PrivateMessageResponse { message },
+    recipient_id,
+    websocket_id: None,
+  });
+
+  Ok(())
+}
+
+/// See `lemmy_api_structs::is_trusted`.
+pub(in crate::api) async fn is_trusted(
+  user: &User_,
+  community_id: Option<i32>,
+  pool: &DbPool,
+) -> Result<bool, LemmyError> {
+  let user = user.to_owned();
+  let trusted = blocking(pool, move |conn| {
+    lemmy_api_structs::is_trusted(conn, &user, community_id)
+  })
+  .await??;
+  Ok(trusted)
+}
@@ -1,23 +1,75 @@
 use crate::{
-  api::{check_community_ban, get_user_from_jwt, get_user_from_jwt_opt, is_mod_or_admin, Perform},
-  apub::{ApubLikeableType, ApubObjectType},
+  api::{
+    check_community_ban,
+    check_community_membership,
+    check_community_visible,
+    check_edit_window,
+    check_undelete_window,
+    check_votes_unlocked,
+    comment_sort_for_post,
+    community::{moderated_community_ids, send_mod_inbox_counts},
+    get_content_limits,
+    get_post,
+    get_removal_reason,
+    get_user_from_jwt,
+    get_user_from_jwt_opt,
+    get_user_from_jwt_opt_for_reading,
+    is_impersonation,
+    is_mod_or_admin,
+    is_trusted,
+    send_mod_message,
+    Perform,
+  },
+  apub::{comment::send_pin_comment, ApubLikeableType, ApubObjectType},
+  canonical_url::{canonicalize_post_url, url_domain},
   fetch_iframely_and_pictrs_data,
+  plugins::{run_api_hooks, PostHookPayload},
+  post_views::{current_views, Viewed},
+  push::{send_pushes, PushMessage},
+  spam::{score_content, SpamContentType, SpamTarget},
+  wasm_plugins::{run_content_filters, ContentKind},
   websocket::{
-    messages::{GetPostUsersOnline, JoinCommunityRoom, JoinPostRoom, SendPost},
+    messages::{
+      GetPostUsersOnline,
+      JoinCommunityRoom,
+      JoinPostRoom,
+      SendComment,
+      SendPost,
+      SendUserRoomMessage,
+    },
     UserOperation,
   },
+  DbPool,
   LemmyContext,
 };
 use actix_web::web::Data;
-use lemmy_api_structs::{blocking, post::*};
+use lemmy_api_structs::{
+  blocking,
+  comment::CommentResponse,
+  community::GetCommunityNotificationPostsResponse,
+  post::*,
+  send_community_notifs,
+  send_saved_search_notifs,
+  user::GetSavedSearchMatchesResponse,
+};
 use lemmy_db::{
+  comment::{Comment, CommentForm},
   comment_view::*,
+  community::{AllowedPostType, Community, CommunitySettings, TitleCapitalization},
+  community_post_tag::{CommunityPostTag, PostTag},
   community_view::*,
+  domain::{Domain, DomainPolicy, DomainPostApproval, DomainPostApprovalForm},
   moderator::*,
-  naive_now,
+  plugin_hook::HookPoint,
   post::*,
+  post_edit::PostEdit,
+  post_media::*,
   post_view::*,
+  post_view_count::PostViewCount,
+  site::Site,
   site_view::*,
+  user::User_,
+  CommentSortType,
   Crud,
   Likeable,
   ListingType,
@@ -26,14 +78,377 @@ use lemmy_db::{
 };
 use lemmy_utils::{
   apub::{make_apub_endpoint, EndpointType},
-  utils::{check_slurs, check_slurs_opt, is_valid_post_title},
+  settings::Settings,
+  utils::{
+    capitalize_first,
+    check_lengths,
+    check_slurs,
+    check_slurs_opt,
+    clean_title,
+    is_all_caps,
+    is_valid_post_title,
+    title_matches_pattern,
+  },
   APIError,
   ConnectionId,
   LemmyError,
+  SubmissionRulesError,
 };
+use log::error;
 use std::str::FromStr;
 use url::Url;
 
+/// The maximum number of similar posts suggested at submission time, or shown as related posts.
+const MAX_SIMILAR_POSTS: i64 = 10;
+
+/// The most copies of a post which are counted as its crossposts.
+const MAX_CROSSPOSTS: i64 = 50;
+
+/// The most comments shown over all the copies of a crossposted post.
+const MAX_CROSSPOST_TOP_COMMENTS: i64 = 5;
+
+fn check_post_media(media: &Option<Vec<PostMediaItem>>) -> Result<(), LemmyError> {
+  if let Some(media) = media {
+    if media.len() > MAX_POST_MEDIA {
+      return Err(APIError::err("too_many_post_media").into());
+    }
+    for m in media {
+      if Url::parse(&m.url).is_err() {
+        return Err(APIError::err("invalid_url").into());
+      }
+      check_slurs_opt(&m.caption)?;
+      check_slurs_opt(&m.alt_text)?;
+    }
+  }
+  Ok(())
+}
+
+/// Refuses links to blocked domains. Returns the domain if it's greylisted, so that the post has
+/// to wait for approval.
+pub async fn check_domain_policy(
+  url: &Option<String>,
+  pool: &DbPool,
+) -> Result<Option<Domain>, LemmyError> {
+  let name = match url.as_deref().and_then(url_domain) {
+    Some(name) => name,
+    None => return Ok(None),
+  };
+  let domain = blocking(pool, move |conn| Domain::find_by_name(conn, &name)).await??;
+  match domain {
+    Some(d) if d.parsed_policy() == DomainPolicy::Blocked => {
+      Err(APIError::err("domain_blocked").into())
+    }
+    Some(d) if d.parsed_policy() == DomainPolicy::Greylisted => Ok(Some(d)),
+    _ => Ok(None),
+  }
+}
+
+/// Whether a post waiting for approval of its domain since `held` was removed by a mod in the
+/// meantime, or its creator was banned.
+async fn removed_since_held(
+  post: &Post,
+  held: chrono::NaiveDateTime,
+  pool: &DbPool,
+) -> Result<bool, LemmyError> {
+  let post_id = post.id;
+  let creator_id = post.creator_id;
+  let community_id = post.community_id;
+  let removed = blocking(pool, move |conn| -> Result<bool, LemmyError> {
+    let creator = User_::read(conn, creator_id)?;
+    Ok(
+      creator.banned
+        || CommunityUserBanView::get(conn, creator_id, community_id).is_ok()
+        || ModRemovePost::exists_for_post_since(conn, post_id, held)?,
+    )
+  })
+  .await??;
+  Ok(removed)
+}
+
+/// Cleans up the title of a post the way its community asks for, or only trims it.
+async fn normalize_post_title(
+  community_id: i32,
+  title: &str,
+  pool: &DbPool,
+) -> Result<String, LemmyError> {
+  let settings = blocking(pool, move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  Ok(normalize_title(title, &settings))
+}
+
+fn normalize_title(title: &str, settings: &CommunitySettings) -> String {
+  let title = if settings.clean_titles {
+    clean_title(title)
+  } else {
+    title.trim().to_owned()
+  };
+  match settings.parsed_title_capitalization() {
+    TitleCapitalization::None => title,
+    TitleCapitalization::CapitalizeFirst => capitalize_first(&title),
+    TitleCapitalization::NoAllCaps if is_all_caps(&title) => {
+      capitalize_first(&title.to_lowercase())
+    }
+    TitleCapitalization::NoAllCaps => title,
+  }
+}
+
+/// Checks a post against the submission rules of its community, all at once, so that the error
+/// lists every broken rule. The tag has to be one of the community, but mods and admins aren't
+/// bound by the rules.
+async fn check_submission_rules(
+  community_id: i32,
+  user_id: i32,
+  name: &str,
+  has_url: bool,
+  tag_id: Option<i32>,
+  pool: &DbPool,
+) -> Result<(), LemmyError> {
+  let (settings, tags, is_mod) = blocking(pool, move |conn| -> Result<_, diesel::result::Error> {
+    Ok((
+      CommunitySettings::read_for_community(conn, community_id)?,
+      CommunityPostTag::list_for_community(conn, community_id)?,
+      Community::is_mod_or_admin(conn, user_id, community_id),
+    ))
+  })
+  .await??;
+
+  if tag_id.map_or(false, |tag_id| !tags.iter().any(|t| t.id == tag_id)) {
+    return Err(APIError::err("invalid_post_tag").into());
+  }
+  if is_mod {
+    return Ok(());
+  }
+
+  let mut rules = Vec::new();
+  match settings.parsed_allowed_post_type() {
+    AllowedPostType::TextOnly if has_url => rules.push("text_only"),
+    AllowedPostType::LinkOnly if !has_url => rules.push("link_only"),
+    _ => {}
+  }
+  // A community without tags can't require one
+  if settings.require_post_tag && !tags.is_empty() && tag_id.is_none() {
+    rules.push("tag_required");
+  }
+  if let Some(pattern) = &settings.title_pattern {
+    if !title_matches_pattern(name, pattern) {
+      rules.push("title_pattern");
+    }
+  }
+
+  if rules.is_empty() {
+    Ok(())
+  } else {
+    let rules = rules.into_iter().map(String::from).collect();
+    Err(SubmissionRulesError { rules }.into())
+  }
+}
+
+/// Refuse image posts without alt text if the site or the community asks for it.
+async fn check_alt_text(
+  community_id: i32,
+  has_thumbnail: bool,
+  thumbnail_alt_text: &Option<String>,
+  media: &Option<Vec<PostMediaItem>>,
+  pool: &DbPool,
+) -> Result<(), LemmyError> {
+  let required = blocking(pool, move |conn| {
+    let site_requires = Site::read(conn, 1)
+      .map(|s| s.require_alt_text)
+      .unwrap_or(false);
+    CommunitySettings::read_for_community(conn, community_id)
+      .map(|c| site_requires || c.require_alt_text)
+  })
+  .await??;
+
+  if !required {
+    return Ok(());
+  }
+
+  let missing = |alt: &Option<String>| alt.as_ref().map(|a| a.trim().is_empty()).unwrap_or(true);
+  let media_missing = media
+    .as_ref()
+    .map(|m| m.iter().any(|i| missing(&i.alt_text)))
+    .unwrap_or(false);
+
+  if (has_thumbnail && missing(thumbnail_alt_text)) || media_missing {
+    return Err(APIError::err("alt_text_required").into());
+  }
+  Ok(())
+}
+
+fn post_media_forms(post_id: i32, media: &[PostMediaItem]) -> Vec<PostMediaForm> {
+  media
+    .iter()
+    .enumerate()
+    .map(|(i, m)| PostMediaForm {
+      post_id,
+      position: i as i32,
+      url: m.url.to_owned(),
+      caption: m.caption.to_owned(),
+      alt_text: m.alt_text.to_owned(),
+    })
+    .collect()
+}
+
+/// Notifies the owners of saved searches that a new post matches, by email and over the
+/// websocket.
+pub async fn send_saved_search_matches(
+  post: Post,
+  context: &LemmyContext,
+  do_send_email: bool,
+) -> Result<(), LemmyError> {
+  let post_id = post.id;
+  let matches = send_saved_search_notifs(post, context.pool(), do_send_email).await?;
+  if matches.is_empty() {
+    return Ok(());
+  }
+
+  let post_view = blocking(context.pool(), move |conn| {
+    PostView::read(conn, post_id, None)
+  })
+  .await??;
+
+  for (recipient_id, saved_search_match) in matches {
+    context.chat_server().do_send(SendUserRoomMessage {
+      op: UserOperation::GetSavedSearchMatches,
+      response: GetSavedSearchMatchesResponse {
+        matches: vec![saved_search_match],
+        posts: vec![post_view.clone()],
+      },
+      recipient_id,
+      websocket_id: None,
+    });
+  }
+
+  Ok(())
+}
+
+/// Posts the automod comment of the community on a new post, and pins it at the top of the thread.
+/// The post exists already, so failing to do that only gets logged.
+pub async fn send_automod_comment(post: &Post, context: &LemmyContext) {
+  if let Err(e) = post_automod_comment(post, context).await {
+    error!(
+      "Couldn't post the automod comment on post {}: {}",
+      post.id, e
+    );
+  }
+}
+
+async fn post_automod_comment(post: &Post, context: &LemmyContext) -> Result<(), LemmyError> {
+  // Federated posts can arrive twice
+  if post.pinned_comment_id.is_some() {
+    return Ok(());
+  }
+
+  let community_id = post.community_id;
+  let settings = blocking(context.pool(), move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  let (content, automod_user_id) = match (settings.automod_comment, settings.automod_user_id) {
+    (Some(content), Some(automod_user_id)) => (content, automod_user_id),
+    _ => return Ok(()),
+  };
+  let automod = blocking(context.pool(), move |conn| {
+    User_::read(conn, automod_user_id)
+  })
+  .await??;
+
+  let comment_form = CommentForm {
+    content,
+    parent_id: None,
+    post_id: post.id,
+    creator_id: automod.id,
+    removed: None,
+    deleted: None,
+    read: None,
+    published: None,
+    updated: None,
+    ap_id: None,
+    local: true,
+    distinguished: Some(true),
+  };
+  let comment = blocking(context.pool(), move |conn| {
+    let inserted_comment = Comment::create(conn, &comment_form)?;
+    let apub_id =
+      make_apub_endpoint(EndpointType::Comment, &inserted_comment.id.to_string()).to_string();
+    Comment::update_ap_id(conn, inserted_comment.id, apub_id)
+  })
+  .await??;
+  comment.send_create(&automod, context).await?;
+
+  let post_id = post.id;
+  let comment_id = comment.id;
+  blocking(context.pool(), move |conn| {
+    Post::update_pinned_comment(conn, post_id, Some(comment_id))
+  })
+  .await??;
+  send_pin_comment(&comment, &automod, context).await?;
+
+  let comment_view = blocking(context.pool(), move |conn| {
+    CommentView::read(conn, comment_id, None)
+  })
+  .await??;
+
+  context.chat_server().do_send(SendComment {
+    op: UserOperation::CreateComment,
+    comment: CommentResponse {
+      comment: comment_view,
+      recipient_ids: Vec::new(),
+      form_id: None,
+    },
+    websocket_id: None,
+  });
+
+  Ok(())
+}
+
+/// Notifies the users who subscribed to notifications for the community of the post, by email
+/// and over the websocket.
+pub async fn send_community_notifications(
+  post: Post,
+  context: &LemmyContext,
+  do_send_email: bool,
+) -> Result<(), LemmyError> {
+  let post_id = post.id;
+  let notifications = send_community_notifs(post, context.pool(), do_send_email).await?;
+  if notifications.is_empty() {
+    return Ok(());
+  }
+
+  let post_view = blocking(context.pool(), move |conn| {
+    PostView::read(conn, post_id, None)
+  })
+  .await??;
+
+  let mut push_recipients = Vec::new();
+  for (subscription, notification) in notifications {
+    if subscription.send_push {
+      push_recipients.push(subscription.user_id);
+    }
+    context.chat_server().do_send(SendUserRoomMessage {
+      op: UserOperation::GetCommunityNotificationPosts,
+      response: GetCommunityNotificationPostsResponse {
+        notifications: vec![notification],
+        posts: vec![post_view.clone()],
+      },
+      recipient_id: subscription.user_id,
+      websocket_id: None,
+    });
+  }
+
+  let message = PushMessage {
+    title: format!("New post in {}", post_view.community_name),
+    body: post_view.name.to_owned(),
+    url: format!("https://{}/post/{}", Settings::get().hostname, post_id),
+  };
+  send_pushes(push_recipients, message, context);
+
+  Ok(())
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for CreatePost {
   type Response = PostResponse;
@@ -46,35 +461,122 @@ impl Perform for CreatePost {
     let data: &CreatePost = &self;
     let user = get_user_from_jwt(&data.auth, context.pool()).await?;
 
-    check_slurs(&data.name)?;
-    check_slurs_opt(&data.body)?;
+    let hooked = run_api_hooks(
+      context.client(),
+      HookPoint::PrePostCreate,
+      PostHookPayload {
+        name: data.name.to_owned(),
+        url: data.url.to_owned(),
+        body: data.body.to_owned(),
+        nsfw: data.nsfw,
+        community_id: data.community_id,
+        creator_id: user.id,
+      },
+    )
+    .await?;
+    let hooked = run_content_filters(ContentKind::Post, hooked).await?;
 
-    if !is_valid_post_title(&data.name) {
+    check_slurs(&hooked.name)?;
+    check_slurs_opt(&hooked.body)?;
+    check_slurs_opt(&data.thumbnail_alt_text)?;
+
+    if !is_valid_post_title(&hooked.name) {
       return Err(APIError::err("invalid_post_title").into());
     }
 
+    let name = normalize_post_title(data.community_id, &hooked.name, context.pool()).await?;
+
+    let limits = get_content_limits(data.community_id, context.pool()).await?;
+    check_lengths(&[
+      ("name", Some(name.as_str()), limits.post_title),
+      ("body", hooked.body.as_deref(), limits.post_body),
+    ])?;
+
+    check_submission_rules(
+      data.community_id,
+      user.id,
+      &name,
+      hooked.url.is_some(),
+      data.tag_id,
+      context.pool(),
+    )
+    .await?;
+
     check_community_ban(user.id, data.community_id, context.pool()).await?;
+    check_community_membership(user.id, data.community_id, context.pool()).await?;
+
+    let anonymous = data.anonymous.unwrap_or(false);
+    if anonymous {
+      let community_id = data.community_id;
+      let settings = blocking(context.pool(), move |conn| {
+        CommunitySettings::read_for_community(conn, community_id)
+      })
+      .await??;
+      if !settings.allow_anonymous_posts {
+        return Err(APIError::err("anonymous_posts_not_allowed").into());
+      }
+    }
 
-    if let Some(url) = data.url.as_ref() {
-      match Url::parse(url) {
-        Ok(_t) => (),
-        Err(_e) => return Err(APIError::err("invalid_url").into()),
+    // Untrusted users can only post a few times per hour
+    let trusted = is_trusted(&user, Some(data.community_id), context.pool()).await?;
+    if !trusted {
+      let user_id = user.id;
+      let recent_posts = blocking(context.pool(), move |conn| {
+        Post::count_recent_for_creator(conn, user_id)
+      })
+      .await??;
+      if recent_posts >= Settings::get().trust.untrusted_posts_per_hour {
+        return Err(APIError::err("too_many_posts").into());
       }
     }
 
+    check_post_media(&data.media)?;
+
+    // Shortened links and tracking parameters would hide duplicates
+    let url = match &hooked.url {
+      Some(url) => Some(canonicalize_post_url(context.client(), url).await?),
+      None => None,
+    };
+    let greylisted_domain = check_domain_policy(&url, context.pool()).await?;
+
     // Fetch Iframely and pictrs cached image
     let (iframely_title, iframely_description, iframely_html, pictrs_thumbnail) =
-      fetch_iframely_and_pictrs_data(context.client(), data.url.to_owned()).await;
+      fetch_iframely_and_pictrs_data(context.client(), url.to_owned()).await;
+
+    check_alt_text(
+      data.community_id,
+      pictrs_thumbnail.is_some(),
+      &data.thumbnail_alt_text,
+      &data.media,
+      context.pool(),
+    )
+    .await?;
+
+    let spam_content = format!(
+      "{} {} {}",
+      hooked.name,
+      url.to_owned().unwrap_or_default(),
+      hooked.body.to_owned().unwrap_or_default()
+    );
+    let spam = if trusted {
+      None
+    } else {
+      score_content(SpamContentType::Post, &spam_content, &user, context).await?
+    };
+    let spam_removed = spam.as_ref().map_or(false, |s| s.removed);
+    // Posts of untrusted users linking to greylisted domains stay removed until they're approved
+    let greylisted_domain = greylisted_domain.filter(|_| !trusted);
+    let held_back = spam_removed || greylisted_domain.is_some();
 
     let post_form = PostForm {
-      name: data.name.trim().to_owned(),
-      url: data.url.to_owned(),
-      body: data.body.to_owned(),
+      name,
+      url,
+      body: hooked.body.to_owned(),
       community_id: data.community_id,
       creator_id: user.id,
-      removed: None,
+      removed: Some(held_back),
       deleted: None,
-      nsfw: data.nsfw,
+      nsfw: hooked.nsfw,
       locked: None,
       stickied: None,
       updated: None,
@@ -82,6 +584,10 @@ impl Perform for CreatePost {
       embed_description: iframely_description,
       embed_html: iframely_html,
       thumbnail_url: pictrs_thumbnail,
+      thumbnail_alt_text: data.thumbnail_alt_text.to_owned(),
+      distinguished: None,
+      anonymous: Some(anonymous),
+      comments_closed: None,
       ap_id: None,
       local: true,
       published: None,
@@ -113,7 +619,49 @@ impl Perform for CreatePost {
       Err(_e) => return Err(APIError::err("couldnt_create_post").into()),
     };
 
-    updated_post.send_create(&user, context).await?;
+    if let Some(media) = &data.media {
+      let media_forms = post_media_forms(inserted_post_id, media);
+      let replace =
+        move |conn: &'_ _| PostMedia::replace_for_post(conn, inserted_post_id, &media_forms);
+      if blocking(context.pool(), replace).await?.is_err() {
+        return Err(APIError::err("couldnt_create_post").into());
+      }
+    }
+
+    if let Some(tag_id) = data.tag_id {
+      let set_tag = move |conn: &'_ _| PostTag::set_for_post(conn, inserted_post_id, Some(tag_id));
+      if blocking(context.pool(), set_tag).await?.is_err() {
+        return Err(APIError::err("couldnt_create_post").into());
+      }
+    }
+
+    if let Some(spam) = spam {
+      let needs_review = spam.reported && !spam.removed;
+      spam
+        .save(SpamTarget::Post(inserted_post_id), context.pool())
+        .await?;
+      if needs_review {
+        send_mod_inbox_counts(inserted_post.community_id, context).await?;
+      }
+    }
+
+    // Posts removed as spam are reviewed as such, approving the domain mustn't restore them
+    if let Some(domain) = greylisted_domain.filter(|_| !spam_removed) {
+      let approval_form = DomainPostApprovalForm {
+        post_id: inserted_post_id,
+        domain_id: domain.id,
+      };
+      let create = move |conn: &'_ _| DomainPostApproval::create(conn, &approval_form);
+      if blocking(context.pool(), create).await?.is_err() {
+        return Err(APIError::err("couldnt_create_post").into());
+      }
+      send_mod_inbox_counts(inserted_post.community_id, context).await?;
+    }
+
+    // Content removed as spam, or waiting for approval, isn't federated, and doesn't notify anyone
+    if !held_back {
+      updated_post.send_create(&user, context).await?;
+    }
 
     // They like their own post by default
     let like_form = PostLikeForm {
@@ -127,7 +675,12 @@ impl Perform for CreatePost {
       return Err(APIError::err("couldnt_like_post").into());
     }
 
-    updated_post.send_like(&user, context).await?;
+    if !held_back {
+      updated_post.send_like(&user, context).await?;
+      send_automod_comment(&updated_post, context).await;
+      send_saved_search_matches(updated_post.to_owned(), context, true).await?;
+      send_community_notifications(updated_post, context, true).await?;
+    }
 
     // Refetch the view
     let inserted_post_id = inserted_post.id;
@@ -162,8 +715,8 @@ impl Perform for GetPost {
     websocket_id: Option<ConnectionId>,
   ) -> Result<GetPostResponse, LemmyError> {
     let data: &GetPost = &self;
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
-    let user_id = user.map(|u| u.id);
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
+    let user_id = user.as_ref().map(|u| u.id);
 
     let id = data.id;
     let post_view = match blocking(context.pool(), move |conn| {
@@ -175,13 +728,29 @@ impl Perform for GetPost {
       Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
     };
 
+    check_community_visible(user_id, post_view.community_id, context.pool()).await?;
+
     let id = data.id;
+    let comment_sort = match &data.comment_sort {
+      Some(comment_sort) => Some(CommentSortType::from_str(comment_sort)?),
+      None => None,
+    };
+    let comment_sort = comment_sort_for_post(
+      user.as_ref(),
+      is_impersonation(&data.auth),
+      id,
+      comment_sort,
+      context.pool(),
+    )
+    .await?;
     let comments = blocking(context.pool(), move |conn| {
-      CommentQueryBuilder::create(conn)
+      let comments = CommentQueryBuilder::create(conn)
+        .comment_sort(comment_sort)
         .for_post_id(id)
         .my_user_id(user_id)
         .limit(9999)
-        .list()
+        .list()?;
+      CollapsibleCommentView::from_comments(conn, comments, user_id)
     })
     .await??;
 
@@ -197,6 +766,32 @@ impl Perform for GetPost {
     })
     .await??;
 
+    let id = data.id;
+    let media = blocking(context.pool(), move |conn| {
+      PostMedia::list_for_post(conn, id)
+    })
+    .await??;
+
+    let id = data.id;
+    let tag = blocking(context.pool(), move |conn| PostTag::read_for_post(conn, id)).await??;
+
+    let id = data.id;
+    let community_id = post_view.community_id;
+    let name = post_view.name.to_owned();
+    let url = post_view.url.to_owned();
+    let related = blocking(context.pool(), move |conn| {
+      PostView::list_similar(
+        conn,
+        community_id,
+        &name,
+        url,
+        Some(id),
+        user_id,
+        MAX_SIMILAR_POSTS,
+      )
+    })
+    .await??;
+
     if let Some(id) = websocket_id {
       context.chat_server().do_send(JoinPostRoom {
         post_id: data.id,
@@ -210,13 +805,26 @@ impl Perform for GetPost {
       .await
       .unwrap_or(1);
 
+    let id = data.id;
+    let kept_views = blocking(context.pool(), move |conn| {
+      PostViewCount::read_for_post(conn, id)
+    })
+    .await??
+    .map(|kept| (kept.views, kept.visitors));
+    let (views, unique_views) = current_views(Viewed::Post(data.id), kept_views);
+
     // Return the jwt
     Ok(GetPostResponse {
       post: post_view,
       comments,
       community,
       moderators,
+      media,
+      tag,
+      related,
       online,
+      views,
+      unique_views,
     })
   }
 }
@@ -231,7 +839,7 @@ impl Perform for GetPosts {
     websocket_id: Option<ConnectionId>,
   ) -> Result<GetPostsResponse, LemmyError> {
     let data: &GetPosts = &self;
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
 
     let user_id = match &user {
       Some(user) => Some(user.id),
@@ -250,8 +858,8 @@ impl Perform for GetPosts {
     let limit = data.limit;
     let community_id = data.community_id;
     let community_name = data.community_name.to_owned();
-    let posts = match blocking(context.pool(), move |conn| {
-      PostQueryBuilder::create(conn)
+    let (posts, media) = match blocking(context.pool(), move |conn| {
+      let posts = PostQueryBuilder::create(conn)
         .listing_type(type_)
         .sort(&sort)
         .show_nsfw(show_nsfw)
@@ -260,7 +868,10 @@ impl Perform for GetPosts {
         .my_user_id(user_id)
         .page(page)
         .limit(limit)
-        .list()
+        .list()?;
+      let post_ids = posts.iter().map(|p| p.id).collect::<Vec<i32>>();
+      let media = PostMedia::list_for_posts(conn, &post_ids)?;
+      Ok((posts, media)) as Result<_, diesel::result::Error>
     })
     .await?
     {
@@ -269,18 +880,369 @@ impl Perform for GetPosts {
     };
 
     if let Some(id) = websocket_id {
-      // You don't need to join the specific community room, bc this is already handled by
-      // GetCommunity
-      if data.community_id.is_none() {
-        // 0 is the "all" community
-        context.chat_server().do_send(JoinCommunityRoom {
-          community_id: 0,
-          id,
-        });
+      // GetCommunity already joins the specific community room, but joining again on every
+      // refresh resets the count of new posts available. 0 is the "all" community
+      context.chat_server().do_send(JoinCommunityRoom {
+        community_id: data.community_id.unwrap_or(0),
+        id,
+      });
+    }
+
+    Ok(GetPostsResponse { posts, media })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListDomainPostApprovals {
+  type Response = ListDomainPostApprovalsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListDomainPostApprovalsResponse, LemmyError> {
+    let data: &ListDomainPostApprovals = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Admins approve the posts of every community
+    let user_id = user.id;
+    let admin = user.admin;
+    let page = data.page;
+    let limit = data.limit;
+    let posts = blocking(context.pool(), move |conn| {
+      let community_ids = if admin {
+        None
+      } else {
+        Some(moderated_community_ids(conn, user_id)?)
+      };
+      DomainPostApproval::list_for_communities(conn, community_ids, page, limit)?
+        .into_iter()
+        .map(|a| PostView::read(conn, a.post_id, Some(user_id)))
+        .collect::<Result<Vec<PostView>, _>>()
+    })
+    .await??;
+
+    Ok(ListDomainPostApprovalsResponse { posts })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ApproveDomainPost {
+  type Response = PostResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<PostResponse, LemmyError> {
+    let data: &ApproveDomainPost = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let post_id = data.post_id;
+    let post = get_post(post_id, context.pool()).await?;
+    is_mod_or_admin(context.pool(), user.id, post.community_id).await?;
+
+    let approval = match blocking(context.pool(), move |conn| {
+      DomainPostApproval::read_for_post(conn, post_id)
+    })
+    .await?
+    {
+      Ok(approval) => approval,
+      Err(_e) => return Err(APIError::err("post_not_awaiting_approval").into()),
+    };
+    let delete = move |conn: &'_ _| DomainPostApproval::delete_for_post(conn, post_id);
+    if blocking(context.pool(), delete).await?? == 0 {
+      return Err(APIError::err("post_not_awaiting_approval").into());
+    }
+
+    // A rejected post just stays removed, and so does one which was removed for another reason
+    // while it was waiting
+    let approved =
+      data.approved && !removed_since_held(&post, approval.published, context.pool()).await?;
+    if approved {
+      let updated_post = match blocking(context.pool(), move |conn| {
+        Post::update_removed(conn, post_id, false)
+      })
+      .await?
+      {
+        Ok(post) => post,
+        Err(_e) => return Err(APIError::err("couldnt_update_post").into()),
+      };
+
+      // Now it's sent out like a new post. Remote ones were already sent out by their instance,
+      // they're only shown here from now on.
+      if updated_post.local {
+        let creator_id = updated_post.creator_id;
+        let creator = blocking(context.pool(), move |conn| User_::read(conn, creator_id)).await??;
+        updated_post.send_create(&creator, context).await?;
       }
+      send_automod_comment(&updated_post, context).await;
+      send_saved_search_matches(updated_post.to_owned(), context, true).await?;
+      send_community_notifications(updated_post, context, true).await?;
     }
+    send_mod_inbox_counts(post.community_id, context).await?;
+
+    let user_id = user.id;
+    let post_view = match blocking(context.pool(), move |conn| {
+      PostView::read(conn, post_id, Some(user_id))
+    })
+    .await?
+    {
+      Ok(post) => post,
+      Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+    };
+
+    let res = PostResponse { post: post_view };
 
-    Ok(GetPostsResponse { posts })
+    if approved {
+      context.chat_server().do_send(SendPost {
+        op: UserOperation::CreatePost,
+        post: res.clone(),
+        websocket_id,
+      });
+    }
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetPostsByUrl {
+  type Response = GetPostsByUrlResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetPostsByUrlResponse, LemmyError> {
+    let data: &GetPostsByUrl = &self;
+    if data.url.is_none() && data.domain.is_none() {
+      return Err(APIError::err("no_url_or_domain").into());
+    }
+    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user_id = user.as_ref().map(|u| u.id);
+    let show_nsfw = user.as_ref().map_or(false, |u| u.show_nsfw);
+
+    // Post urls are saved in their canonical form
+    let url = match &data.url {
+      Some(url) => Some(
+        canonicalize_post_url(context.client(), url)
+          .await
+          .unwrap_or_else(|_| url.to_owned()),
+      ),
+      None => None,
+    };
+    let domain = data.domain.to_owned();
+    let sort = SortType::from_str(&data.sort)?;
+    let page = data.page;
+    let limit = data.limit;
+    let posts = match blocking(context.pool(), move |conn| {
+      PostQueryBuilder::create(conn)
+        .sort(&sort)
+        .show_nsfw(show_nsfw)
+        .url_search(url)
+        .for_domain(domain)
+        .my_user_id(user_id)
+        .page(page)
+        .limit(limit)
+        .list()
+    })
+    .await?
+    {
+      Ok(posts) => posts,
+      Err(_e) => return Err(APIError::err("couldnt_get_posts").into()),
+    };
+
+    Ok(GetPostsByUrlResponse { posts })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetPostEditHistory {
+  type Response = GetPostEditHistoryResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetPostEditHistoryResponse, LemmyError> {
+    let data: &GetPostEditHistory = &self;
+    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user_id = user.map(|u| u.id);
+
+    let post = get_post(data.post_id, context.pool()).await?;
+    check_community_visible(user_id, post.community_id, context.pool()).await?;
+
+    // Mods and admins see who edited anonymous posts, and the history of removed and deleted ones
+    let community_id = post.community_id;
+    let is_mod_or_admin = match user_id {
+      Some(user_id) => {
+        blocking(context.pool(), move |conn| {
+          Community::is_mod_or_admin(conn, user_id, community_id)
+        })
+        .await?
+      }
+      None => false,
+    };
+    if (post.removed || post.deleted) && !is_mod_or_admin {
+      return Err(APIError::err("couldnt_find_post").into());
+    }
+    let hide_editor = post.anonymous && !is_mod_or_admin && user_id != Some(post.creator_id);
+
+    let post_id = data.post_id;
+    let edits = blocking(context.pool(), move |conn| {
+      PostEdit::list_for_post(conn, post_id)
+    })
+    .await??;
+    let edits = if hide_editor {
+      edits.into_iter().map(PostEdit::hide_editor).collect()
+    } else {
+      edits
+    };
+
+    Ok(GetPostEditHistoryResponse { edits })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetPostCrossposts {
+  type Response = GetPostCrosspostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetPostCrosspostsResponse, LemmyError> {
+    let data: &GetPostCrossposts = &self;
+    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user_id = user.as_ref().map(|u| u.id);
+    let show_nsfw = user.as_ref().map_or(false, |u| u.show_nsfw);
+
+    let post_id = data.post_id;
+    let post = match blocking(context.pool(), move |conn| {
+      PostView::read(conn, post_id, user_id)
+    })
+    .await?
+    {
+      Ok(post) => post,
+      Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+    };
+
+    check_community_visible(user_id, post.community_id, context.pool()).await?;
+
+    let (posts, top_comments) = blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        // Crossposts link to the same url, which is saved in its canonical form. The query
+        // builders leave out the posts and comments of private communities the user can't see.
+        let sort = SortType::New;
+        let copies = match &post.url {
+          Some(url) => PostQueryBuilder::create(conn)
+            .sort(&sort)
+            .show_nsfw(show_nsfw)
+            .url_search(url.to_owned())
+            .my_user_id(user_id)
+            .limit(MAX_CROSSPOSTS)
+            .list()?,
+          None => Vec::new(),
+        };
+        let mut posts = vec![post];
+        posts.extend(copies.into_iter().filter(|p| p.id != post_id));
+
+        let post_ids = posts.iter().map(|p| p.id).collect::<Vec<i32>>();
+        let sort = SortType::TopAll;
+        let top_comments = CommentQueryBuilder::create(conn)
+          .sort(&sort)
+          .for_post_ids(post_ids)
+          .my_user_id(user_id)
+          .limit(MAX_CROSSPOST_TOP_COMMENTS)
+          .list()?;
+        Ok((posts, top_comments))
+      },
+    )
+    .await??;
+
+    let comment_count = posts.iter().map(|p| p.number_of_comments).sum();
+    let mut community_ids = posts.iter().map(|p| p.community_id).collect::<Vec<i32>>();
+    community_ids.sort_unstable();
+    community_ids.dedup();
+
+    Ok(GetPostCrosspostsResponse {
+      posts,
+      comment_count,
+      community_count: community_ids.len(),
+      top_comments,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetSimilarPosts {
+  type Response = GetSimilarPostsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetSimilarPostsResponse, LemmyError> {
+    let data: &GetSimilarPosts = &self;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
+    let user_id = user.map(|u| u.id);
+
+    let community_id = data.community_id;
+    let name = data.name.to_owned();
+    // Urls which aren't valid are still looked for as they are
+    let url = match &data.url {
+      Some(url) => Some(
+        canonicalize_post_url(context.client(), url)
+          .await
+          .unwrap_or_else(|_| url.to_owned()),
+      ),
+      None => None,
+    };
+    let limit = data
+      .limit
+      .unwrap_or(MAX_SIMILAR_POSTS)
+      .min(MAX_SIMILAR_POSTS);
+    let posts = match blocking(context.pool(), move |conn| {
+      PostView::list_similar(conn, community_id, &name, url, None, user_id, limit)
+    })
+    .await?
+    {
+      Ok(posts) => posts,
+      Err(_e) => return Err(APIError::err("couldnt_get_posts").into()),
+    };
+
+    Ok(GetSimilarPostsResponse { posts })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CheckPostTitle {
+  type Response = CheckPostTitleResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<CheckPostTitleResponse, LemmyError> {
+    let data: &CheckPostTitle = &self;
+    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user_id = user.map(|u| u.id);
+
+    let community_id = data.community_id;
+    let name = normalize_post_title(community_id, &data.name, context.pool()).await?;
+    let title = name.to_owned();
+    let duplicates = match blocking(context.pool(), move |conn| {
+      PostView::list_duplicate_titles(conn, community_id, &title, user_id, MAX_SIMILAR_POSTS)
+    })
+    .await?
+    {
+      Ok(posts) => posts,
+      Err(_e) => return Err(APIError::err("couldnt_get_posts").into()),
+    };
+
+    Ok(CheckPostTitleResponse { name, duplicates })
   }
 }
 
@@ -309,6 +1271,8 @@ impl Perform for CreatePostLike {
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
 
     check_community_ban(user.id, post.community_id, context.pool()).await?;
+    check_community_visible(Some(user.id), post.community_id, context.pool()).await?;
+    check_votes_unlocked(&post, context.pool()).await?;
 
     let like_form = PostLikeForm {
       post_id: data.post_id,
@@ -378,6 +1342,7 @@ impl Perform for EditPost {
 
     check_slurs(&data.name)?;
     check_slurs_opt(&data.body)?;
+    check_slurs_opt(&data.thumbnail_alt_text)?;
 
     if !is_valid_post_title(&data.name) {
       return Err(APIError::err("invalid_post_title").into());
@@ -388,18 +1353,83 @@ impl Perform for EditPost {
 
     check_community_ban(user.id, orig_post.community_id, context.pool()).await?;
 
+    let name = normalize_post_title(orig_post.community_id, &data.name, context.pool()).await?;
+
+    let limits = get_content_limits(orig_post.community_id, context.pool()).await?;
+    check_lengths(&[
+      ("name", Some(name.as_str()), limits.post_title),
+      ("body", data.body.as_deref(), limits.post_body),
+    ])?;
+
+    // Leaving the tag out keeps it, 0 removes it
+    let tag_id = match data.tag_id {
+      Some(0) => None,
+      Some(tag_id) => Some(tag_id),
+      None => blocking(context.pool(), move |conn| {
+        PostTag::read_for_post(conn, edit_id)
+      })
+      .await??
+      .map(|t| t.id),
+    };
+    check_submission_rules(
+      orig_post.community_id,
+      user.id,
+      &name,
+      data.url.is_some(),
+      tag_id,
+      context.pool(),
+    )
+    .await?;
+
     // Verify that only the creator can edit
     if !Post::is_post_creator(user.id, orig_post.creator_id) {
       return Err(APIError::err("no_post_edit_allowed").into());
     }
 
+    let updated = check_edit_window(orig_post.published, orig_post.updated, context.pool()).await?;
+
+    check_post_media(&data.media)?;
+
+    let url = match &data.url {
+      Some(url) => Some(canonicalize_post_url(context.client(), url).await?),
+      None => None,
+    };
+    // A post can't be moved to a greylisted domain without approval, so it has to be a new one,
+    // unless the user is trusted
+    let greylisted_domain = check_domain_policy(&url, context.pool()).await?;
+    let orig_domain = orig_post.url.as_deref().and_then(url_domain);
+    if greylisted_domain.map_or(false, |d| Some(d.name) != orig_domain)
+      && !is_trusted(&user, Some(orig_post.community_id), context.pool()).await?
+    {
+      return Err(APIError::err("domain_requires_approval").into());
+    }
+    if orig_post.url.is_some() && url != orig_post.url {
+      let community_id = orig_post.community_id;
+      let settings = blocking(context.pool(), move |conn| {
+        CommunitySettings::read_for_community(conn, community_id)
+      })
+      .await??;
+      if !settings.allow_post_url_edits {
+        return Err(APIError::err("post_url_edit_not_allowed").into());
+      }
+    }
+
     // Fetch Iframely and Pictrs cached image
     let (iframely_title, iframely_description, iframely_html, pictrs_thumbnail) =
-      fetch_iframely_and_pictrs_data(context.client(), data.url.to_owned()).await;
+      fetch_iframely_and_pictrs_data(context.client(), url.to_owned()).await;
+
+    check_alt_text(
+      orig_post.community_id,
+      pictrs_thumbnail.is_some(),
+      &data.thumbnail_alt_text,
+      &data.media,
+      context.pool(),
+    )
+    .await?;
 
     let post_form = PostForm {
-      name: data.name.trim().to_owned(),
-      url: data.url.to_owned(),
+      name,
+      url,
       body: data.body.to_owned(),
       nsfw: data.nsfw,
       creator_id: orig_post.creator_id.to_owned(),
@@ -408,18 +1438,24 @@ impl Perform for EditPost {
       deleted: Some(orig_post.deleted),
       locked: Some(orig_post.locked),
       stickied: Some(orig_post.stickied),
-      updated: Some(naive_now()),
+      updated,
       embed_title: iframely_title,
       embed_description: iframely_description,
       embed_html: iframely_html,
       thumbnail_url: pictrs_thumbnail,
-      ap_id: Some(orig_post.ap_id),
+      thumbnail_alt_text: data.thumbnail_alt_text.to_owned(),
+      distinguished: Some(orig_post.distinguished),
+      anonymous: None,
+      comments_closed: None,
+      ap_id: Some(orig_post.ap_id.to_owned()),
       local: orig_post.local,
       published: None,
     };
 
     let edit_id = data.edit_id;
+    let editor_id = user.id;
     let res = blocking(context.pool(), move |conn| {
+      PostEdit::record(conn, &orig_post, &post_form, editor_id)?;
       Post::update(conn, edit_id, &post_form)
     })
     .await?;
@@ -436,6 +1472,22 @@ impl Perform for EditPost {
       }
     };
 
+    // Leaving media out keeps the existing gallery
+    if let Some(media) = &data.media {
+      let media_forms = post_media_forms(edit_id, media);
+      let replace = move |conn: &'_ _| PostMedia::replace_for_post(conn, edit_id, &media_forms);
+      if blocking(context.pool(), replace).await?.is_err() {
+        return Err(APIError::err("couldnt_update_post").into());
+      }
+    }
+
+    if data.tag_id.is_some() {
+      let set_tag = move |conn: &'_ _| PostTag::set_for_post(conn, edit_id, tag_id);
+      if blocking(context.pool(), set_tag).await?.is_err() {
+        return Err(APIError::err("couldnt_update_post").into());
+      }
+    }
+
     // Send apub update
     updated_post.send_update(&user, context).await?;
 
@@ -479,6 +1531,10 @@ impl Perform for DeletePost {
       return Err(APIError::err("no_post_edit_allowed").into());
     }
 
+    if !data.deleted {
+      check_undelete_window(orig_post.deleted_at, context.pool()).await?;
+    }
+
     // Update the post
     let edit_id = data.edit_id;
     let deleted = data.deleted;
@@ -533,6 +1589,20 @@ impl Perform for RemovePost {
     // Verify that only the mods can remove
     is_mod_or_admin(context.pool(), user.id, orig_post.community_id).await?;
 
+    let reason = if data.removed {
+      Some(
+        get_removal_reason(
+          &data.reason,
+          data.reason_id,
+          orig_post.community_id,
+          context.pool(),
+        )
+        .await?,
+      )
+    } else {
+      data.reason.to_owned()
+    };
+
     // Update the post
     let edit_id = data.edit_id;
     let removed = data.removed;
@@ -546,7 +1616,7 @@ impl Perform for RemovePost {
       mod_user_id: user.id,
       post_id: data.edit_id,
       removed: Some(removed),
-      reason: data.reason.to_owned(),
+      reason: reason.to_owned(),
     };
     blocking(context.pool(), move |conn| {
       ModRemovePost::create(conn, &form)
@@ -555,7 +1625,9 @@ impl Perform for RemovePost {
 
     // apub updates
     if removed {
-      updated_post.send_remove(&user, context).await?;
+      updated_post
+        .send_remove(&user, reason.to_owned(), context)
+        .await?;
     } else {
       updated_post.send_undo_remove(&user, context).await?;
     }
@@ -568,6 +1640,17 @@ impl Perform for RemovePost {
     })
     .await??;
 
+    // Tell the author why, if the mod asked for it
+    if let Some(reason) = reason.filter(|_| removed && data.notify_author.unwrap_or(false)) {
+      if orig_post.creator_id != user.id {
+        let content = format!(
+          "Your post \"{}\" in !{} was removed:\n\n{}",
+          post_view.name, post_view.community_name, reason
+        );
+        send_mod_message(&user, orig_post.creator_id, content, context).await?;
+      }
+    }
+
     let res = PostResponse { post: post_view };
 
     context.chat_server().do_send(SendPost {
@@ -576,6 +1659,9 @@ impl Perform for RemovePost {
       websocket_id,
     });
 
+    // Removing reported posts takes them off the mod inbox
+    send_mod_inbox_counts(orig_post.community_id, context).await?;
+
     Ok(res)
   }
 }
@@ -700,6 +1786,114 @@ impl Perform for StickyPost {
   }
 }
 
+#[async_trait::async_trait(?Send)]
+impl Perform for DistinguishPost {
+  type Response = PostResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<PostResponse, LemmyError> {
+    let data: &DistinguishPost = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let edit_id = data.edit_id;
+    let orig_post = blocking(context.pool(), move |conn| Post::read(conn, edit_id)).await??;
+
+    check_community_ban(user.id, orig_post.community_id, context.pool()).await?;
+
+    // Mods and admins can only distinguish their own posts
+    if !Post::is_post_creator(user.id, orig_post.creator_id) {
+      return Err(APIError::err("no_post_edit_allowed").into());
+    }
+    is_mod_or_admin(context.pool(), user.id, orig_post.community_id).await?;
+
+    let distinguished = data.distinguished;
+    let updated_post = blocking(context.pool(), move |conn| {
+      Post::update_distinguished(conn, edit_id, distinguished)
+    })
+    .await??;
+
+    updated_post.send_update(&user, context).await?;
+
+    let post_view = blocking(context.pool(), move |conn| {
+      PostView::read(conn, edit_id, Some(user.id))
+    })
+    .await??;
+
+    let res = PostResponse { post: post_view };
+
+    context.chat_server().do_send(SendPost {
+      op: UserOperation::DistinguishPost,
+      post: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ClosePostComments {
+  type Response = PostResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<PostResponse, LemmyError> {
+    let data: &ClosePostComments = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let edit_id = data.edit_id;
+    let orig_post = blocking(context.pool(), move |conn| Post::read(conn, edit_id)).await??;
+
+    check_community_ban(user.id, orig_post.community_id, context.pool()).await?;
+
+    // Mods can close the comments of any post, authors only if the community lets them
+    if is_mod_or_admin(context.pool(), user.id, orig_post.community_id)
+      .await
+      .is_err()
+    {
+      if !Post::is_post_creator(user.id, orig_post.creator_id) {
+        return Err(APIError::err("no_post_edit_allowed").into());
+      }
+      let community_id = orig_post.community_id;
+      let settings = blocking(context.pool(), move |conn| {
+        CommunitySettings::read_for_community(conn, community_id)
+      })
+      .await??;
+      if !settings.allow_authors_close_comments {
+        return Err(APIError::err("closing_comments_not_allowed").into());
+      }
+    }
+
+    let closed = data.closed;
+    let updated_post = blocking(context.pool(), move |conn| {
+      Post::update_comments_closed(conn, edit_id, closed)
+    })
+    .await??;
+
+    updated_post.send_update(&user, context).await?;
+
+    let post_view = blocking(context.pool(), move |conn| {
+      PostView::read(conn, edit_id, Some(user.id))
+    })
+    .await??;
+
+    let res = PostResponse { post: post_view };
+
+    context.chat_server().do_send(SendPost {
+      op: UserOperation::ClosePostComments,
+      post: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for SavePost {
   type Response = PostResponse;
@@ -1,42 +1,87 @@
 use crate::{
-  api::{get_user_from_jwt, get_user_from_jwt_opt, is_admin, Perform},
-  apub::fetcher::search_by_apub_id,
+  api::{
+    claims::Claims,
+    get_stats_range,
+    get_user_from_jwt,
+    get_user_from_jwt_opt,
+    get_user_from_jwt_opt_for_reading,
+    is_admin,
+    is_mod_or_admin,
+    Perform,
+  },
+  apub::{fetcher::search_by_apub_id, key_rotation::rotate_local_actor_keys},
+  canonical_url::{canonicalize_post_url, url_domain},
+  plugins::reload_hooks,
   version,
+  wasm_plugins::{list_module_names, list_plugin_infos, reload_wasm_plugins},
   websocket::{
     messages::{GetUsersOnline, SendAllMessage},
     UserOperation,
   },
+  DbPool,
   LemmyContext,
 };
 use actix_web::web::Data;
 use anyhow::Context;
+use diesel::{Connection, PgConnection};
 use lemmy_api_structs::{blocking, site::*, user::Register};
 use lemmy_db::{
+  admin_impersonation::{AdminImpersonation, AdminImpersonationForm},
   category::*,
   comment_view::*,
+  community::{Community, CommunityFollower, CommunitySettings},
   community_view::*,
+  csp_report::CspReport,
+  default_community::DefaultCommunity,
   diesel_option_overwrite,
+  domain::{Domain, DomainPolicy, DomainPolicyForm},
+  email_outbox::EmailOutbox,
+  feature_flag::{FeatureFlag, FeatureFlagForm, FeatureFlagUser, FeatureFlagUserForm},
+  instance::Instance,
+  instance_stats::{InstancePeerStatsDaily, InstanceStatsDaily},
   moderator::*,
   moderator_views::*,
   naive_now,
+  plugin_hook::{HookFailurePolicy, HookPoint, PluginHook, PluginHookForm},
   post_view::*,
+  sign_in_signal::DuplicateAccount,
   site::*,
+  site_announcement::{
+    AnnouncementSeverity,
+    SiteAnnouncement,
+    SiteAnnouncementDismissalForm,
+    SiteAnnouncementForm,
+  },
+  site_branding::{SiteBranding, SiteBrandingForm},
   site_view::*,
+  spam_score::SpamScore,
+  user::User_,
   user_view::*,
+  wasm_plugin::{WasmPlugin, WasmPluginForm},
   Crud,
   SearchType,
   SortType,
 };
 use lemmy_utils::{
+  apub::get_apub_protocol_string,
+  email::send_email,
   location_info,
-  settings::Settings,
-  utils::{check_slurs, check_slurs_opt},
+  settings::{MaintenanceConfig, Settings},
+  utils::{
+    check_slurs,
+    check_slurs_opt,
+    is_valid_feature_flag_name,
+    is_valid_hex_color,
+    is_valid_theme_name,
+    naive_from_unix,
+  },
   APIError,
   ConnectionId,
   LemmyError,
 };
-use log::{debug, info};
+use log::{debug, error, info};
 use std::str::FromStr;
+use url::Url;
 
 #[async_trait::async_trait(?Send)]
 impl Perform for ListCategories {
@@ -56,6 +101,30 @@ impl Perform for ListCategories {
   }
 }
 
+#[async_trait::async_trait(?Send)]
+impl Perform for GetFederatedInstances {
+  type Response = GetFederatedInstancesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetFederatedInstancesResponse, LemmyError> {
+    let _data: &GetFederatedInstances = &self;
+
+    let settings = Settings::get();
+    let mut linked = blocking(context.pool(), move |conn| Instance::list(conn)).await??;
+    linked.retain(|i| settings.is_instance_allowed(&i.domain));
+
+    Ok(GetFederatedInstancesResponse {
+      linked,
+      mode: settings.get_federation_mode().as_str().to_owned(),
+      allowed: settings.get_allowed_instances(),
+      blocked: settings.get_blocked_instances(),
+    })
+  }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for GetModlog {
   type Response = GetModlogResponse;
@@ -101,6 +170,11 @@ impl Perform for GetModlog {
     })
     .await??;
 
+    let bulk_actions = blocking(context.pool(), move |conn| {
+      ModBulkActionView::list(conn, community_id, mod_user_id, page, limit)
+    })
+    .await??;
+
     // These arrays are only for the full modlog, when a community isn't given
     let (removed_communities, banned, added) = if data.community_id.is_none() {
       blocking(context.pool(), move |conn| {
@@ -126,10 +200,16 @@ impl Perform for GetModlog {
       banned,
       added_to_community,
       added,
+      bulk_actions,
     })
   }
 }
 
+/// None leaves the limit as it is, zero or a negative value turns it off.
+fn site_limit_overwrite(limit: Option<i32>) -> Option<Option<i32>> {
+  limit.map(|l| if l > 0 { Some(l) } else { None })
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for CreateSite {
   type Response = SiteResponse;
@@ -158,6 +238,12 @@ impl Perform for CreateSite {
       enable_downvotes: data.enable_downvotes,
       open_registration: data.open_registration,
       enable_nsfw: data.enable_nsfw,
+      require_alt_text: data.require_alt_text.unwrap_or(false),
+      score_hidden_minutes: site_limit_overwrite(data.score_hidden_minutes),
+      vote_lock_days: site_limit_overwrite(data.vote_lock_days),
+      ninja_edit_minutes: site_limit_overwrite(data.ninja_edit_minutes),
+      edit_limit_days: site_limit_overwrite(data.edit_limit_days),
+      undelete_window_days: site_limit_overwrite(data.undelete_window_days),
       updated: None,
     };
 
@@ -204,6 +290,12 @@ impl Perform for EditSite {
       enable_downvotes: data.enable_downvotes,
       open_registration: data.open_registration,
       enable_nsfw: data.enable_nsfw,
+      require_alt_text: data.require_alt_text.unwrap_or(found_site.require_alt_text),
+      score_hidden_minutes: site_limit_overwrite(data.score_hidden_minutes),
+      vote_lock_days: site_limit_overwrite(data.vote_lock_days),
+      ninja_edit_minutes: site_limit_overwrite(data.ninja_edit_minutes),
+      edit_limit_days: site_limit_overwrite(data.edit_limit_days),
+      undelete_window_days: site_limit_overwrite(data.undelete_window_days),
     };
 
     let update_site = move |conn: &'_ _| Site::update(conn, 1, &site_form);
@@ -262,6 +354,12 @@ impl Perform for GetSite {
         enable_downvotes: true,
         open_registration: true,
         enable_nsfw: true,
+        require_alt_text: None,
+        score_hidden_minutes: None,
+        vote_lock_days: None,
+        ninja_edit_minutes: None,
+        edit_limit_days: None,
+        undelete_window_days: None,
         auth: login_response.jwt,
       };
       create_site.perform(context, websocket_id).await?;
@@ -292,7 +390,7 @@ impl Perform for GetSite {
       .await
       .unwrap_or(1);
 
-    let my_user = get_user_from_jwt_opt(&data.auth, context.pool())
+    let my_user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool())
       .await?
       .map(|mut u| {
         u.password_encrypted = "".to_string();
@@ -331,11 +429,18 @@ impl Perform for Search {
       Err(e) => debug!("Failed to resolve search query as activitypub ID: {}", e),
     }
 
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
-    let user_id = user.map(|u| u.id);
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
+    let user_id = user.as_ref().map(|u| u.id);
 
     let type_ = SearchType::from_str(&data.type_)?;
 
+    let include_removed = data.include_removed.unwrap_or(false);
+    let mod_community_ids = if include_removed {
+      get_mod_search_community_ids(user.as_ref(), data.community_id, context.pool()).await?
+    } else {
+      None
+    };
+
     let mut posts = Vec::new();
     let mut comments = Vec::new();
     let mut communities = Vec::new();
@@ -355,6 +460,8 @@ impl Perform for Search {
             .sort(&sort)
             .show_nsfw(true)
             .for_community_id(community_id)
+            .for_community_ids(mod_community_ids)
+            .include_removed(include_removed)
             .search_term(q)
             .my_user_id(user_id)
             .page(page)
@@ -367,6 +474,8 @@ impl Perform for Search {
         comments = blocking(context.pool(), move |conn| {
           CommentQueryBuilder::create(&conn)
             .sort(&sort)
+            .for_community_ids(mod_community_ids)
+            .include_removed(include_removed)
             .search_term(q)
             .my_user_id(user_id)
             .page(page)
@@ -398,11 +507,14 @@ impl Perform for Search {
         .await??;
       }
       SearchType::All => {
+        let post_community_ids = mod_community_ids.to_owned();
         posts = blocking(context.pool(), move |conn| {
           PostQueryBuilder::create(conn)
             .sort(&sort)
             .show_nsfw(true)
             .for_community_id(community_id)
+            .for_community_ids(post_community_ids)
+            .include_removed(include_removed)
             .search_term(q)
             .my_user_id(user_id)
             .page(page)
@@ -417,6 +529,8 @@ impl Perform for Search {
         comments = blocking(context.pool(), move |conn| {
           CommentQueryBuilder::create(conn)
             .sort(&sort)
+            .for_community_ids(mod_community_ids)
+            .include_removed(include_removed)
             .search_term(q)
             .my_user_id(user_id)
             .page(page)
@@ -452,11 +566,17 @@ impl Perform for Search {
         .await??;
       }
       SearchType::Url => {
+        // Post urls are saved in their canonical form
+        let q = canonicalize_post_url(context.client(), &q)
+          .await
+          .unwrap_or(q);
         posts = blocking(context.pool(), move |conn| {
           PostQueryBuilder::create(conn)
             .sort(&sort)
             .show_nsfw(true)
             .for_community_id(community_id)
+            .for_community_ids(mod_community_ids)
+            .include_removed(include_removed)
             .url_search(q)
             .page(page)
             .limit(limit)
@@ -477,6 +597,38 @@ impl Perform for Search {
   }
 }
 
+/// The communities in which a mod search also finds removed content, None for admins searching
+/// all of them.
+async fn get_mod_search_community_ids(
+  user: Option<&User_>,
+  community_id: Option<i32>,
+  pool: &DbPool,
+) -> Result<Option<Vec<i32>>, LemmyError> {
+  let user = match user {
+    Some(u) => u,
+    None => return Err(APIError::err("not_logged_in").into()),
+  };
+  if let Some(community_id) = community_id {
+    is_mod_or_admin(pool, user.id, community_id).await?;
+    return Ok(Some(vec![community_id]));
+  }
+  if user.admin {
+    return Ok(None);
+  }
+
+  let user_id = user.id;
+  let moderated = blocking(pool, move |conn| {
+    CommunityModeratorView::for_user(conn, user_id)
+  })
+  .await??;
+  if moderated.is_empty() {
+    return Err(APIError::err("not_a_mod_or_admin").into());
+  }
+  Ok(Some(
+    moderated.into_iter().map(|m| m.community_id).collect(),
+  ))
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for TransferSite {
   type Response = GetSiteResponse;
@@ -590,3 +742,1455 @@ impl Perform for SaveSiteConfig {
     Ok(GetSiteConfigResponse { config_hjson })
   }
 }
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListSpamScores {
+  type Response = ListSpamScoresResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListSpamScoresResponse, LemmyError> {
+    let data: &ListSpamScores = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    let reported_only = data.reported_only;
+    let page = data.page;
+    let limit = data.limit;
+    let spam_scores = blocking(context.pool(), move |conn| {
+      SpamScore::list(conn, reported_only, page, limit)
+    })
+    .await??;
+
+    Ok(ListSpamScoresResponse { spam_scores })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetInstanceStats {
+  type Response = GetInstanceStatsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetInstanceStatsResponse, LemmyError> {
+    let data: &GetInstanceStats = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    let (since, until) = get_stats_range(data.since, data.until)?;
+    let (days, peers) = blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        Ok((
+          InstanceStatsDaily::list(conn, since.date(), until.date())?,
+          InstancePeerStatsDaily::list(conn, since.date(), until.date())?,
+        ))
+      },
+    )
+    .await??;
+
+    Ok(GetInstanceStatsResponse { days, peers })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SendTestEmail {
+  type Response = SendTestEmailResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<SendTestEmailResponse, LemmyError> {
+    let data: &SendTestEmail = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let to_email = match data.email.to_owned().or(user.email) {
+      Some(e) => e,
+      None => return Err(APIError::err("no_email_address").into()),
+    };
+    let to_name = user.name;
+    let subject = format!("{} - Test email", Settings::get().hostname);
+    let html = format!(
+      "<h1>Test email</h1><br><div>Emails from {} are working.</div>",
+      Settings::get().hostname
+    );
+    // Sent right away rather than through the outbox, to report what the SMTP server answered
+    let sent =
+      actix_web::web::block(move || send_email(&subject, &to_email, &to_name, &html, None)).await;
+    let error = match sent {
+      Ok(()) => None,
+      Err(actix_web::error::BlockingError::Error(e)) => Some(e),
+      Err(actix_web::error::BlockingError::Canceled) => {
+        return Err(APIError::err("couldnt_send_test_email").into())
+      }
+    };
+
+    Ok(SendTestEmailResponse {
+      sent: error.is_none(),
+      error,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetEmailOutbox {
+  type Response = GetEmailOutboxResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetEmailOutboxResponse, LemmyError> {
+    let data: &GetEmailOutbox = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    let failed_only = data.failed_only;
+    let page = data.page;
+    let limit = data.limit;
+    let emails = blocking(context.pool(), move |conn| {
+      EmailOutbox::list(conn, failed_only, page, limit)
+    })
+    .await??;
+
+    Ok(GetEmailOutboxResponse { emails })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListCspReports {
+  type Response = ListCspReportsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListCspReportsResponse, LemmyError> {
+    let data: &ListCspReports = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    let directive = data.directive.to_owned();
+    let page = data.page;
+    let limit = data.limit;
+    let reports = blocking(context.pool(), move |conn| {
+      CspReport::list(conn, directive, page, limit)
+    })
+    .await??;
+
+    Ok(ListCspReportsResponse { reports })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for RotateActorKeys {
+  type Response = RotateActorKeysResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<RotateActorKeysResponse, LemmyError> {
+    let data: &RotateActorKeys = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins do this
+    is_admin(context.pool(), user.id).await?;
+
+    let (user_ids, community_ids) = blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        Ok((
+          User_::list_local_ids(conn)?,
+          Community::list_local_ids(conn)?,
+        ))
+      },
+    )
+    .await??;
+    let res = RotateActorKeysResponse {
+      users: user_ids.len(),
+      communities: community_ids.len(),
+    };
+    actix_web::rt::spawn(rotate_local_actor_keys(
+      user_ids,
+      community_ids,
+      context.get_ref().to_owned(),
+    ));
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ImpersonateUser {
+  type Response = ImpersonateUserResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ImpersonateUserResponse, LemmyError> {
+    let data: &ImpersonateUser = &self;
+    let settings = Settings::get().impersonation;
+    if !settings.enabled {
+      return Err(APIError::err("impersonation_disabled").into());
+    }
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let reason = data.reason.trim().to_owned();
+    if reason.is_empty() {
+      return Err(APIError::err("impersonation_reason_required").into());
+    }
+
+    let user_id = data.user_id;
+    let impersonated =
+      match blocking(context.pool(), move |conn| User_::read(conn, user_id)).await? {
+        Ok(u) => u,
+        Err(_e) => return Err(APIError::err("couldnt_find_that_username_or_email").into()),
+      };
+    // Admins can see everything anyway, and only local users have a view of the site to debug
+    if impersonated.admin || !impersonated.local {
+      return Err(APIError::err("cannot_impersonate_user").into());
+    }
+
+    let form = AdminImpersonationForm {
+      admin_id: user.id,
+      user_id,
+      reason,
+      expires: naive_now() + chrono::Duration::minutes(settings.duration_minutes),
+    };
+    let impersonation = blocking(context.pool(), move |conn| {
+      AdminImpersonation::start(conn, &form)
+    })
+    .await??;
+    info!(
+      "Admin {} is viewing the site as user {}: {}",
+      user.name, impersonated.name, impersonation.reason
+    );
+
+    Ok(ImpersonateUserResponse {
+      jwt: Claims::impersonation_jwt(&impersonation, Settings::get().hostname)?,
+      impersonation,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetImpersonationLog {
+  type Response = GetImpersonationLogResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetImpersonationLogResponse, LemmyError> {
+    let data: &GetImpersonationLog = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    let page = data.page;
+    let limit = data.limit;
+    let impersonations = blocking(context.pool(), move |conn| {
+      AdminImpersonation::list(conn, page, limit)
+    })
+    .await??;
+
+    Ok(GetImpersonationLogResponse { impersonations })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetDuplicateAccounts {
+  type Response = GetDuplicateAccountsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetDuplicateAccountsResponse, LemmyError> {
+    let data: &GetDuplicateAccounts = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    // Older sign ins are forgotten anyway
+    let retention_days = Settings::get().ip_addresses.sign_in_retention_days;
+    let days = data.days.unwrap_or(30).max(1).min(retention_days.max(1));
+    let since = naive_now() - chrono::Duration::days(days);
+    let user_id = data.user_id;
+    let accounts = blocking(context.pool(), move |conn| {
+      DuplicateAccount::for_user(conn, user_id, since)
+    })
+    .await??;
+
+    Ok(GetDuplicateAccountsResponse { accounts })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetDefaultCommunities {
+  type Response = DefaultCommunitiesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<DefaultCommunitiesResponse, LemmyError> {
+    let data: &GetDefaultCommunities = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins read this
+    is_admin(context.pool(), user.id).await?;
+
+    let user_id = user.id;
+    let communities = blocking(context.pool(), move |conn| {
+      read_default_communities(conn, user_id)
+    })
+    .await??;
+
+    Ok(DefaultCommunitiesResponse { communities })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SaveDefaultCommunities {
+  type Response = DefaultCommunitiesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<DefaultCommunitiesResponse, LemmyError> {
+    let data: &SaveDefaultCommunities = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins edit this
+    is_admin(context.pool(), user.id).await?;
+
+    // Only local communities can be followed without waiting for an accept, and only the ones
+    // which don't require approval to join
+    let community_ids = data.community_ids.to_owned();
+    let all_valid = blocking(context.pool(), move |conn| {
+      community_ids.iter().all(|community_id| {
+        let community = Community::read(conn, *community_id);
+        let settings = CommunitySettings::read_for_community(conn, *community_id);
+        match (community, settings) {
+          (Ok(c), Ok(s)) => c.local && !c.deleted && !c.removed && !s.requires_membership(),
+          _ => false,
+        }
+      })
+    })
+    .await?;
+    if !all_valid {
+      return Err(APIError::err("invalid_default_community").into());
+    }
+
+    let community_ids = data.community_ids.to_owned();
+    let replace = move |conn: &'_ _| DefaultCommunity::replace(conn, &community_ids);
+    if blocking(context.pool(), replace).await?.is_err() {
+      return Err(APIError::err("couldnt_update_default_communities").into());
+    }
+
+    // Subscribing everyone can take a while on big instances
+    if data.apply_to_existing_users {
+      let community_ids = data.community_ids.to_owned();
+      let pool = context.pool().clone();
+      actix_web::rt::spawn(async move {
+        let followed = blocking(&pool, move |conn| {
+          community_ids
+            .iter()
+            .map(|community_id| CommunityFollower::follow_all_local_users(conn, *community_id))
+            .sum::<Result<usize, diesel::result::Error>>()
+        })
+        .await;
+        match followed {
+          Ok(Ok(followed)) => info!("Subscribed {} users to default communities", followed),
+          Ok(Err(e)) => error!("Couldn't subscribe users to default communities: {}", e),
+          Err(e) => error!("Couldn't subscribe users to default communities: {}", e),
+        }
+      });
+    }
+
+    let user_id = user.id;
+    let communities = blocking(context.pool(), move |conn| {
+      read_default_communities(conn, user_id)
+    })
+    .await??;
+
+    Ok(DefaultCommunitiesResponse { communities })
+  }
+}
+
+fn read_default_communities(
+  conn: &PgConnection,
+  user_id: i32,
+) -> Result<Vec<CommunityView>, diesel::result::Error> {
+  DefaultCommunity::list_community_ids(conn)?
+    .into_iter()
+    .map(|community_id| CommunityView::read(conn, community_id, Some(user_id)))
+    .collect()
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetSiteBranding {
+  type Response = SiteBrandingResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<SiteBrandingResponse, LemmyError> {
+    let res = blocking(context.pool(), move |conn| read_site_branding(conn)).await??;
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SaveSiteBranding {
+  type Response = SiteBrandingResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<SiteBrandingResponse, LemmyError> {
+    let data: &SaveSiteBranding = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins edit this
+    is_admin(context.pool(), user.id).await?;
+
+    // The images are served through stable redirects, so they have to be uploads to this instance
+    let images = [&data.logo, &data.banner, &data.favicon];
+    if !images
+      .iter()
+      .all(|i| i.as_deref().map_or(true, is_local_image))
+    {
+      return Err(APIError::err("invalid_branding_image").into());
+    }
+    let colors = [
+      &data.primary_color,
+      &data.secondary_color,
+      &data.background_color,
+      &data.text_color,
+    ];
+    if !colors
+      .iter()
+      .all(|c| c.as_deref().map_or(true, is_valid_hex_color))
+    {
+      return Err(APIError::err("invalid_color").into());
+    }
+    if !data
+      .default_theme
+      .as_deref()
+      .map_or(true, is_valid_theme_name)
+    {
+      return Err(APIError::err("invalid_theme").into());
+    }
+
+    let logo = data.logo.to_owned();
+    let banner = data.banner.to_owned();
+    let form = SiteBrandingForm {
+      favicon: data.favicon.to_owned(),
+      primary_color: data.primary_color.to_owned(),
+      secondary_color: data.secondary_color.to_owned(),
+      background_color: data.background_color.to_owned(),
+      text_color: data.text_color.to_owned(),
+      default_theme: data.default_theme.to_owned(),
+    };
+    let save = move |conn: &'_ PgConnection| {
+      conn.transaction::<_, diesel::result::Error, _>(|| {
+        Site::update_branding(conn, logo, banner)?;
+        SiteBranding::save(conn, &form)
+      })
+    };
+    if blocking(context.pool(), save).await?.is_err() {
+      return Err(APIError::err("couldnt_update_site").into());
+    }
+
+    let res = blocking(context.pool(), move |conn| read_site_branding(conn)).await??;
+
+    context.chat_server().do_send(SendAllMessage {
+      op: UserOperation::SaveSiteBranding,
+      response: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
+/// The logo and banner are the icon and banner of the site, the rest has its own table.
+pub fn read_site_branding(
+  conn: &PgConnection,
+) -> Result<SiteBrandingResponse, diesel::result::Error> {
+  let site = Site::read(conn, 1)?;
+  let branding = SiteBranding::read(conn)?;
+  let b = branding.as_ref();
+  Ok(SiteBrandingResponse {
+    logo: site.icon,
+    banner: site.banner,
+    favicon: b.and_then(|b| b.favicon.to_owned()),
+    primary_color: b.and_then(|b| b.primary_color.to_owned()),
+    secondary_color: b.and_then(|b| b.secondary_color.to_owned()),
+    background_color: b.and_then(|b| b.background_color.to_owned()),
+    text_color: b.and_then(|b| b.text_color.to_owned()),
+    default_theme: b.and_then(|b| b.default_theme.to_owned()),
+  })
+}
+
+/// Images uploaded to the pictrs of this instance.
+fn is_local_image(url: &str) -> bool {
+  url.starts_with(&format!(
+    "{}://{}/pictrs/image/",
+    get_apub_protocol_string(),
+    Settings::get().hostname
+  ))
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListAnnouncements {
+  type Response = ListAnnouncementsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListAnnouncementsResponse, LemmyError> {
+    let data: &ListAnnouncements = &self;
+    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+
+    // Past and scheduled announcements are only for admins
+    let announcements = if data.include_inactive.unwrap_or(false) {
+      let user_id = match &user {
+        Some(u) => u.id,
+        None => return Err(APIError::err("not_an_admin").into()),
+      };
+      is_admin(context.pool(), user_id).await?;
+      blocking(context.pool(), move |conn| SiteAnnouncement::list_all(conn)).await??
+    } else {
+      let user_id = user.map(|u| u.id);
+      blocking(context.pool(), move |conn| {
+        SiteAnnouncement::list_active(conn, user_id)
+      })
+      .await??
+    };
+
+    Ok(ListAnnouncementsResponse { announcements })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreateAnnouncement {
+  type Response = AnnouncementResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<AnnouncementResponse, LemmyError> {
+    let data: &CreateAnnouncement = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let form = announcement_form(
+      user.id,
+      &data.content,
+      &data.severity,
+      data.starts,
+      data.ends,
+      None,
+    )?;
+    let announcement = match blocking(context.pool(), move |conn| {
+      SiteAnnouncement::create(conn, &form)
+    })
+    .await?
+    {
+      Ok(announcement) => announcement,
+      Err(_e) => return Err(APIError::err("couldnt_create_announcement").into()),
+    };
+
+    let res = AnnouncementResponse { announcement };
+    push_announcement(
+      UserOperation::CreateAnnouncement,
+      &res,
+      context,
+      websocket_id,
+    );
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for EditAnnouncement {
+  type Response = AnnouncementResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<AnnouncementResponse, LemmyError> {
+    let data: &EditAnnouncement = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let id = data.id;
+    let orig = match blocking(context.pool(), move |conn| SiteAnnouncement::read(conn, id)).await? {
+      Ok(announcement) => announcement,
+      Err(_e) => return Err(APIError::err("couldnt_find_announcement").into()),
+    };
+
+    // The announcement stays with the admin who wrote it
+    let form = announcement_form(
+      orig.creator_id,
+      &data.content,
+      &data.severity,
+      data.starts,
+      data.ends,
+      Some(naive_now()),
+    )?;
+    let announcement = match blocking(context.pool(), move |conn| {
+      SiteAnnouncement::update(conn, id, &form)
+    })
+    .await?
+    {
+      Ok(announcement) => announcement,
+      Err(_e) => return Err(APIError::err("couldnt_update_announcement").into()),
+    };
+
+    let res = AnnouncementResponse { announcement };
+    push_announcement(UserOperation::EditAnnouncement, &res, context, websocket_id);
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeleteAnnouncement {
+  type Response = AnnouncementResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<AnnouncementResponse, LemmyError> {
+    let data: &DeleteAnnouncement = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let id = data.id;
+    let announcement =
+      match blocking(context.pool(), move |conn| SiteAnnouncement::read(conn, id)).await? {
+        Ok(announcement) => announcement,
+        Err(_e) => return Err(APIError::err("couldnt_find_announcement").into()),
+      };
+    if blocking(context.pool(), move |conn| {
+      SiteAnnouncement::delete(conn, id)
+    })
+    .await?
+    .is_err()
+    {
+      return Err(APIError::err("couldnt_delete_announcement").into());
+    }
+
+    // Clients remove the banner when they get this
+    let res = AnnouncementResponse { announcement };
+    context.chat_server().do_send(SendAllMessage {
+      op: UserOperation::DeleteAnnouncement,
+      response: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DismissAnnouncement {
+  type Response = AnnouncementResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<AnnouncementResponse, LemmyError> {
+    let data: &DismissAnnouncement = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let id = data.id;
+    let announcement =
+      match blocking(context.pool(), move |conn| SiteAnnouncement::read(conn, id)).await? {
+        Ok(announcement) => announcement,
+        Err(_e) => return Err(APIError::err("couldnt_find_announcement").into()),
+      };
+
+    let form = SiteAnnouncementDismissalForm {
+      announcement_id: id,
+      user_id: user.id,
+    };
+    if blocking(context.pool(), move |conn| {
+      SiteAnnouncement::dismiss(conn, &form)
+    })
+    .await?
+    .is_err()
+    {
+      return Err(APIError::err("couldnt_dismiss_announcement").into());
+    }
+
+    Ok(AnnouncementResponse { announcement })
+  }
+}
+
+fn announcement_form(
+  creator_id: i32,
+  content: &str,
+  severity: &str,
+  starts: Option<i64>,
+  ends: Option<i64>,
+  updated: Option<chrono::NaiveDateTime>,
+) -> Result<SiteAnnouncementForm, LemmyError> {
+  check_slurs(content)?;
+  if content.trim().is_empty() {
+    return Err(APIError::err("invalid_announcement_content").into());
+  }
+  let severity = match AnnouncementSeverity::from_str(severity) {
+    Ok(severity) => severity,
+    Err(_e) => return Err(APIError::err("invalid_severity").into()),
+  };
+  let starts = starts.map(naive_from_unix).unwrap_or_else(naive_now);
+  let ends = ends.map(naive_from_unix);
+  if ends.map_or(false, |e| e <= starts) {
+    return Err(APIError::err("invalid_announcement_time").into());
+  }
+  // Scheduled ones are left to the announcement job, also when an edit moves them to the future
+  let now = naive_now();
+  let pushed = if starts <= now { Some(now) } else { None };
+  Ok(SiteAnnouncementForm {
+    creator_id,
+    content: content.to_owned(),
+    severity: severity.to_string(),
+    starts,
+    ends,
+    updated,
+    pushed,
+  })
+}
+
+/// Sends the announcement to everyone who is connected, if it already started. Scheduled ones are
+/// sent by `run_announcement_job`.
+fn push_announcement(
+  op: UserOperation,
+  res: &AnnouncementResponse,
+  context: &Data<LemmyContext>,
+  websocket_id: Option<ConnectionId>,
+) {
+  if res.announcement.pushed.is_some() {
+    context.chat_server().do_send(SendAllMessage {
+      op,
+      response: res.clone(),
+      websocket_id,
+    });
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListFeatureFlags {
+  type Response = ListFeatureFlagsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListFeatureFlagsResponse, LemmyError> {
+    let data: &ListFeatureFlags = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let flags = blocking(context.pool(), move |conn| FeatureFlag::list_all(conn)).await??;
+
+    Ok(ListFeatureFlagsResponse { flags })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SaveFeatureFlag {
+  type Response = FeatureFlagResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<FeatureFlagResponse, LemmyError> {
+    let data: &SaveFeatureFlag = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    if !is_valid_feature_flag_name(&data.name) {
+      return Err(APIError::err("invalid_feature_flag_name").into());
+    }
+    if data.rollout_percentage < 0 || data.rollout_percentage > 100 {
+      return Err(APIError::err("invalid_rollout_percentage").into());
+    }
+
+    let form = FeatureFlagForm {
+      name: data.name.to_owned(),
+      description: data.description.to_owned(),
+      enabled: data.enabled,
+      rollout_percentage: data.rollout_percentage,
+    };
+    let flag = match blocking(context.pool(), move |conn| FeatureFlag::save(conn, &form)).await? {
+      Ok(flag) => flag,
+      Err(_e) => return Err(APIError::err("couldnt_save_feature_flag").into()),
+    };
+
+    let flag_id = flag.id;
+    let users = blocking(context.pool(), move |conn| {
+      FeatureFlagUser::list_for_flag(conn, flag_id)
+    })
+    .await??;
+
+    Ok(FeatureFlagResponse { flag, users })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeleteFeatureFlag {
+  type Response = FeatureFlagResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<FeatureFlagResponse, LemmyError> {
+    let data: &DeleteFeatureFlag = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let flag = get_feature_flag(data.name.to_owned(), context).await?;
+    let flag_id = flag.id;
+    if blocking(context.pool(), move |conn| {
+      FeatureFlag::delete(conn, flag_id)
+    })
+    .await?
+    .is_err()
+    {
+      return Err(APIError::err("couldnt_delete_feature_flag").into());
+    }
+
+    // The settings for single users are deleted with the flag
+    Ok(FeatureFlagResponse {
+      flag,
+      users: Vec::new(),
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SetFeatureFlagUser {
+  type Response = FeatureFlagResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<FeatureFlagResponse, LemmyError> {
+    let data: &SetFeatureFlagUser = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let flag = get_feature_flag(data.name.to_owned(), context).await?;
+    let flag_id = flag.id;
+    let user_id = data.user_id;
+    let set = match data.enabled {
+      Some(enabled) => {
+        let form = FeatureFlagUserForm {
+          feature_flag_id: flag_id,
+          user_id,
+          enabled,
+        };
+        blocking(context.pool(), move |conn| {
+          FeatureFlagUser::set(conn, &form).map(|_| ())
+        })
+        .await?
+      }
+      None => {
+        blocking(context.pool(), move |conn| {
+          FeatureFlagUser::remove(conn, flag_id, user_id).map(|_| ())
+        })
+        .await?
+      }
+    };
+    if set.is_err() {
+      return Err(APIError::err("couldnt_save_feature_flag").into());
+    }
+
+    let users = blocking(context.pool(), move |conn| {
+      FeatureFlagUser::list_for_flag(conn, flag_id)
+    })
+    .await??;
+
+    Ok(FeatureFlagResponse { flag, users })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetEnabledFeatures {
+  type Response = GetEnabledFeaturesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetEnabledFeaturesResponse, LemmyError> {
+    let data: &GetEnabledFeatures = &self;
+    let user_id = get_user_from_jwt_opt(&data.auth, context.pool())
+      .await?
+      .map(|u| u.id);
+
+    let features = blocking(context.pool(), move |conn| {
+      FeatureFlag::list_enabled_names(conn, user_id)
+    })
+    .await??;
+
+    Ok(GetEnabledFeaturesResponse { features })
+  }
+}
+
+async fn get_feature_flag(name: String, context: &LemmyContext) -> Result<FeatureFlag, LemmyError> {
+  match blocking(context.pool(), move |conn| {
+    FeatureFlag::read_from_name(conn, &name)
+  })
+  .await?
+  {
+    Ok(flag) => Ok(flag),
+    Err(_e) => Err(APIError::err("couldnt_find_feature_flag").into()),
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListPluginHooks {
+  type Response = ListPluginHooksResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListPluginHooksResponse, LemmyError> {
+    let data: &ListPluginHooks = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let hooks = blocking(context.pool(), move |conn| PluginHook::list_all(conn)).await??;
+
+    Ok(ListPluginHooksResponse { hooks })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreatePluginHook {
+  type Response = PluginHookResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<PluginHookResponse, LemmyError> {
+    let data: &CreatePluginHook = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let form = plugin_hook_form(
+      &data.name,
+      &data.url,
+      &data.hook_point,
+      data.timeout_ms,
+      &data.failure_policy,
+      data.secret.to_owned(),
+      data.enabled,
+    )?;
+    let hook = match blocking(context.pool(), move |conn| PluginHook::create(conn, &form)).await? {
+      Ok(hook) => hook,
+      Err(_e) => return Err(APIError::err("couldnt_save_plugin_hook").into()),
+    };
+
+    reload_hooks(context.pool()).await?;
+
+    Ok(PluginHookResponse { hook })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for EditPluginHook {
+  type Response = PluginHookResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<PluginHookResponse, LemmyError> {
+    let data: &EditPluginHook = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let id = data.id;
+    let existing = match blocking(context.pool(), move |conn| PluginHook::read(conn, id)).await? {
+      Ok(hook) => hook,
+      Err(_e) => return Err(APIError::err("couldnt_find_plugin_hook").into()),
+    };
+    let secret = match &data.secret {
+      Some(secret) if secret.is_empty() => None,
+      Some(secret) => Some(secret.to_owned()),
+      None => existing.secret,
+    };
+
+    let form = PluginHookForm {
+      updated: Some(naive_now()),
+      ..plugin_hook_form(
+        &data.name,
+        &data.url,
+        &data.hook_point,
+        data.timeout_ms,
+        &data.failure_policy,
+        secret,
+        data.enabled,
+      )?
+    };
+    let hook = match blocking(context.pool(), move |conn| {
+      PluginHook::update(conn, id, &form)
+    })
+    .await?
+    {
+      Ok(hook) => hook,
+      Err(_e) => return Err(APIError::err("couldnt_save_plugin_hook").into()),
+    };
+
+    reload_hooks(context.pool()).await?;
+
+    Ok(PluginHookResponse { hook })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeletePluginHook {
+  type Response = PluginHookResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<PluginHookResponse, LemmyError> {
+    let data: &DeletePluginHook = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    let id = data.id;
+    let hook = match blocking(context.pool(), move |conn| PluginHook::read(conn, id)).await? {
+      Ok(hook) => hook,
+      Err(_e) => return Err(APIError::err("couldnt_find_plugin_hook").into()),
+    };
+    if blocking(context.pool(), move |conn| PluginHook::delete(conn, id))
+      .await?
+      .is_err()
+    {
+      return Err(APIError::err("couldnt_save_plugin_hook").into());
+    }
+
+    reload_hooks(context.pool()).await?;
+
+    Ok(PluginHookResponse { hook })
+  }
+}
+
+fn plugin_hook_form(
+  name: &str,
+  url: &str,
+  hook_point: &str,
+  timeout_ms: Option<i32>,
+  failure_policy: &Option<String>,
+  secret: Option<String>,
+  enabled: Option<bool>,
+) -> Result<PluginHookForm, LemmyError> {
+  check_slurs(name)?;
+  match Url::parse(url) {
+    Ok(u) if u.scheme() == "http" || u.scheme() == "https" => (),
+    _ => return Err(APIError::err("invalid_hook_url").into()),
+  }
+  let hook_point = match HookPoint::from_str(hook_point) {
+    Ok(h) => h,
+    Err(_e) => return Err(APIError::err("invalid_hook_point").into()),
+  };
+  let failure_policy = match failure_policy {
+    Some(f) => match HookFailurePolicy::from_str(f) {
+      Ok(f) => f,
+      Err(_e) => return Err(APIError::err("invalid_failure_policy").into()),
+    },
+    None => HookFailurePolicy::Allow,
+  };
+  // Hooks are called while the user waits for an answer
+  let timeout_ms = timeout_ms.unwrap_or(1000);
+  if timeout_ms < 100 || timeout_ms > 10_000 {
+    return Err(APIError::err("invalid_hook_timeout").into());
+  }
+
+  Ok(PluginHookForm {
+    name: name.trim().to_owned(),
+    url: url.to_owned(),
+    hook_point: hook_point.to_string(),
+    timeout_ms,
+    failure_policy: failure_policy.to_string(),
+    secret,
+    enabled: enabled.unwrap_or(true),
+    updated: None,
+  })
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListWasmPlugins {
+  type Response = ListWasmPluginsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListWasmPluginsResponse, LemmyError> {
+    let data: &ListWasmPlugins = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    wasm_plugins_response(context).await
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SetWasmPluginEnabled {
+  type Response = ListWasmPluginsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListWasmPluginsResponse, LemmyError> {
+    let data: &SetWasmPluginEnabled = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    // Plugins can be turned off after their module was removed, but not turned on before it's there
+    if data.enabled && !list_module_names().contains(&data.name) {
+      return Err(APIError::err("couldnt_find_wasm_plugin").into());
+    }
+
+    let form = WasmPluginForm {
+      name: data.name.to_owned(),
+      enabled: data.enabled,
+    };
+    let set = move |conn: &'_ _| WasmPlugin::set_enabled(conn, &form);
+    if blocking(context.pool(), set).await?.is_err() {
+      return Err(APIError::err("couldnt_save_wasm_plugin").into());
+    }
+
+    reload_wasm_plugins(context.pool()).await?;
+
+    wasm_plugins_response(context).await
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ReloadWasmPlugins {
+  type Response = ListWasmPluginsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListWasmPluginsResponse, LemmyError> {
+    let data: &ReloadWasmPlugins = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    is_admin(context.pool(), user.id).await?;
+
+    reload_wasm_plugins(context.pool()).await?;
+
+    wasm_plugins_response(context).await
+  }
+}
+
+async fn wasm_plugins_response(
+  context: &LemmyContext,
+) -> Result<ListWasmPluginsResponse, LemmyError> {
+  let plugins = blocking(context.pool(), move |conn| WasmPlugin::list_all(conn)).await??;
+  Ok(ListWasmPluginsResponse {
+    plugins: list_plugin_infos(plugins),
+  })
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetMaintenanceMode {
+  type Response = MaintenanceModeResponse;
+
+  async fn perform(
+    &self,
+    _context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<MaintenanceModeResponse, LemmyError> {
+    Ok(maintenance_mode_response())
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SaveMaintenanceMode {
+  type Response = MaintenanceModeResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<MaintenanceModeResponse, LemmyError> {
+    let data: &SaveMaintenanceMode = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins edit this
+    is_admin(context.pool(), user.id).await?;
+
+    check_slurs_opt(&data.message)?;
+
+    let maintenance = MaintenanceConfig {
+      read_only: data.read_only,
+      message: data.message.to_owned(),
+    };
+    if Settings::save_maintenance_config(&maintenance).is_err() {
+      return Err(APIError::err("couldnt_update_site").into());
+    }
+
+    // Lets connected clients show or hide the maintenance notice
+    let res = maintenance_mode_response();
+    context.chat_server().do_send(SendAllMessage {
+      op: UserOperation::SaveMaintenanceMode,
+      response: res.clone(),
+      websocket_id,
+    });
+
+    Ok(res)
+  }
+}
+
+fn maintenance_mode_response() -> MaintenanceModeResponse {
+  let maintenance = Settings::get().maintenance;
+  MaintenanceModeResponse {
+    read_only: maintenance.read_only,
+    message: maintenance.message,
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetRateLimitConfig {
+  type Response = GetRateLimitConfigResponse;
+
+  async fn perform(
+    &self,
+    _context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetRateLimitConfigResponse, LemmyError> {
+    Ok(GetRateLimitConfigResponse {
+      rate_limit: Settings::get().rate_limit,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SaveRateLimitConfig {
+  type Response = GetRateLimitConfigResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetRateLimitConfigResponse, LemmyError> {
+    let data: &SaveRateLimitConfig = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins edit this
+    is_admin(context.pool(), user.id).await?;
+
+    let r = &data.rate_limit;
+    let values = [
+      r.message,
+      r.message_per_second,
+      r.message_burst,
+      r.post,
+      r.post_per_second,
+      r.post_burst,
+      r.register,
+      r.register_per_second,
+      r.register_burst,
+      r.image,
+      r.image_per_second,
+      r.image_burst,
+      r.comment,
+      r.comment_per_second,
+      r.comment_burst,
+      r.search,
+      r.search_per_second,
+      r.search_burst,
+    ];
+    if values.iter().any(|v| *v < 1) {
+      return Err(APIError::err("invalid_rate_limit").into());
+    }
+
+    // The new limits apply right away
+    if Settings::save_rate_limit_config(&data.rate_limit).is_err() {
+      return Err(APIError::err("couldnt_update_site").into());
+    }
+
+    Ok(GetRateLimitConfigResponse {
+      rate_limit: Settings::get().rate_limit,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ListDomains {
+  type Response = ListDomainsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ListDomainsResponse, LemmyError> {
+    let data: &ListDomains = &self;
+    let policy = data.policy.to_owned();
+    let page = data.page;
+    let limit = data.limit;
+    let domains = blocking(context.pool(), move |conn| {
+      Domain::list(conn, policy, page, limit)
+    })
+    .await??;
+    Ok(ListDomainsResponse { domains })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetDomain {
+  type Response = GetDomainResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetDomainResponse, LemmyError> {
+    let data: &GetDomain = &self;
+    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user_id = user.as_ref().map(|u| u.id);
+    let show_nsfw = user.as_ref().map_or(false, |u| u.show_nsfw);
+
+    let name = domain_name(&data.name)?;
+    let domain = match blocking(context.pool(), move |conn| {
+      Domain::read_by_name(conn, &name)
+    })
+    .await?
+    {
+      Ok(domain) => domain,
+      Err(_e) => return Err(APIError::err("couldnt_find_domain").into()),
+    };
+
+    let for_domain = domain.name.to_owned();
+    let sort = SortType::from_str(&data.sort)?;
+    let page = data.page;
+    let limit = data.limit;
+    let posts = match blocking(context.pool(), move |conn| {
+      PostQueryBuilder::create(conn)
+        .sort(&sort)
+        .show_nsfw(show_nsfw)
+        .for_domain(for_domain)
+        .my_user_id(user_id)
+        .page(page)
+        .limit(limit)
+        .list()
+    })
+    .await?
+    {
+      Ok(posts) => posts,
+      Err(_e) => return Err(APIError::err("couldnt_get_posts").into()),
+    };
+
+    Ok(GetDomainResponse { domain, posts })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SetDomainPolicy {
+  type Response = DomainResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<DomainResponse, LemmyError> {
+    let data: &SetDomainPolicy = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Only let admins restrict domains
+    is_admin(context.pool(), user.id).await?;
+
+    let policy = match DomainPolicy::from_str(&data.policy) {
+      Ok(policy) => policy,
+      Err(_e) => return Err(APIError::err("invalid_domain_policy").into()),
+    };
+    check_slurs_opt(&data.reason)?;
+
+    let form = DomainPolicyForm {
+      name: domain_name(&data.name)?,
+      policy: policy.to_string(),
+      policy_reason: data.reason.to_owned(),
+      updated: None,
+    };
+    let domain = match blocking(context.pool(), move |conn| Domain::set_policy(conn, &form)).await?
+    {
+      Ok(domain) => domain,
+      Err(_e) => return Err(APIError::err("couldnt_update_domain").into()),
+    };
+
+    Ok(DomainResponse { domain })
+  }
+}
+
+/// Domains are saved like the url_domain of the post urls linking to them.
+fn domain_name(name: &str) -> Result<String, LemmyError> {
+  match url_domain(&format!("http://{}", name.trim())) {
+    Some(name) if !name.is_empty() => Ok(name),
+    _ => Err(APIError::err("invalid_domain").into()),
+  }
+}
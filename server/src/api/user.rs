@@ -1,38 +1,70 @@
 use crate::{
-  api::{claims::Claims, get_user_from_jwt, get_user_from_jwt_opt, is_admin, Perform},
+  api::{
+    claims::Claims,
+    get_user_from_jwt,
+    get_user_from_jwt_for_reading,
+    get_user_from_jwt_opt_for_reading,
+    is_admin,
+    is_trusted,
+    Perform,
+  },
   apub::ApubObjectType,
   captcha_espeak_wav_base64,
+  plugins::{run_api_hooks, RegisterHookPayload},
+  push::push_public_key,
+  spam::{score_content, SpamContentType, SpamTarget},
   websocket::{
-    messages::{CaptchaItem, CheckCaptcha, JoinUserRoom, SendAllMessage, SendUserRoomMessage},
+    messages::{
+      CaptchaItem,
+      CheckCaptcha,
+      IsUserOnline,
+      JoinUserRoom,
+      SendAllMessage,
+      SendUserRoomMessage,
+      WaitForUserMessage,
+    },
     UserOperation,
   },
+  DbPool,
   LemmyContext,
 };
-use actix_web::web::Data;
+use actix_web::{
+  rt::time::timeout,
+  web::{self, Data},
+};
 use anyhow::Context;
-use bcrypt::verify;
 use captcha::{gen, Difficulty};
-use chrono::Duration;
-use lemmy_api_structs::{blocking, user::*};
+use chrono::{Duration, NaiveDateTime};
+use futures::channel::oneshot;
+use lemmy_api_structs::{blocking, queue_email, user::*};
 use lemmy_db::{
   comment::*,
   comment_view::*,
   community::*,
+  community_notification::CommunityNotificationPost,
   community_view::*,
+  default_community::DefaultCommunity,
   diesel_option_overwrite,
   moderator::*,
   naive_now,
   password_reset_request::*,
+  plugin_hook::HookPoint,
   post::*,
   post_view::*,
   private_message::*,
   private_message_view::*,
+  push_subscription::{PushSubscription, PushSubscriptionForm},
+  saved_search::*,
+  sign_in_signal::{SignInSignal, SignInSignalForm},
   site::*,
   site_view::*,
+  thread_mute::*,
   user::*,
   user_mention::*,
   user_mention_view::*,
+  user_presence::UserPresence,
   user_view::*,
+  vote_instance::*,
   Crud,
   Followable,
   Joinable,
@@ -41,23 +73,47 @@ use lemmy_db::{
 };
 use lemmy_utils::{
   apub::{generate_actor_keypair, make_apub_endpoint, EndpointType},
-  email::send_email,
+  email::{reply_address, send_email, ReplyTarget},
   location_info,
-  settings::Settings,
+  password::{check_password, hash_password, needs_rehash, verify_password},
+  settings::{PasswordConfig, Settings},
   utils::{
     check_slurs,
     generate_random_string,
+    ip_country,
+    ip_range,
     is_valid_preferred_username,
     is_valid_username,
     naive_from_unix,
     remove_slurs,
+    search_keywords,
   },
+  web_push::check_push_subscription_keys,
   APIError,
   ConnectionId,
   LemmyError,
 };
-use log::error;
-use std::str::FromStr;
+use std::{str::FromStr, time};
+use url::Url;
+
+/// Argon2 is slow on purpose, so it runs on the thread pool instead of the executor.
+async fn hash_password_off_thread(
+  password: &str,
+  config: PasswordConfig,
+) -> Result<String, LemmyError> {
+  let password = password.to_owned();
+  let hash =
+    web::block(move || hash_password(&password, &config).map_err(LemmyError::from)).await?;
+  Ok(hash)
+}
+
+async fn verify_password_off_thread(password: &str, hash: &str) -> Result<bool, LemmyError> {
+  let password = password.to_owned();
+  let hash = hash.to_owned();
+  let valid =
+    web::block(move || Ok(verify_password(&password, &hash)) as Result<_, LemmyError>).await?;
+  Ok(valid)
+}
 
 #[async_trait::async_trait(?Send)]
 impl Perform for Login {
@@ -82,11 +138,23 @@ impl Perform for Login {
     };
 
     // Verify the password
-    let valid: bool = verify(&data.password, &user.password_encrypted).unwrap_or(false);
+    let valid = verify_password_off_thread(&data.password, &user.password_encrypted).await?;
     if !valid {
       return Err(APIError::err("password_incorrect").into());
     }
 
+    // Bcrypt hashes, and ones made with older parameters, are replaced while the password is known
+    let passwords = Settings::get().passwords;
+    if needs_rehash(&user.password_encrypted, &passwords) && !Settings::get().maintenance.read_only
+    {
+      let password_hash = hash_password_off_thread(&data.password, passwords).await?;
+      let user_id = user.id;
+      blocking(context.pool(), move |conn| {
+        User_::update_password(conn, user_id, &password_hash)
+      })
+      .await??;
+    }
+
     // Return the jwt
     Ok(LoginResponse {
       jwt: Claims::jwt(user, Settings::get().hostname)?,
@@ -94,6 +162,95 @@ impl Perform for Login {
   }
 }
 
+/// Keeps the address range and the fingerprint of the client with which the user of the token
+/// signed up or logged in, see `DuplicateAccount`, and returns the token for this sign in, which
+/// the user can revoke. Users are told about logins from a new device or country. Sign ins older
+/// than the retention of the config are forgotten on the way.
+pub async fn record_sign_in(
+  jwt: &str,
+  ip: &str,
+  fingerprint: Option<String>,
+  registration: bool,
+  context: &LemmyContext,
+) -> Result<String, LemmyError> {
+  if Settings::get().maintenance.read_only {
+    return Ok(jwt.to_owned());
+  }
+  let user_id = Claims::decode(jwt)?.claims.id;
+  let form = SignInSignalForm {
+    user_id,
+    ip_range: ip_range(ip),
+    fingerprint,
+    registration,
+    country: ip_country(ip),
+    anomalous: false,
+  };
+  let before = naive_now() - Duration::days(Settings::get().ip_addresses.sign_in_retention_days);
+  let sign_in = blocking(
+    context.pool(),
+    move |conn| -> Result<_, diesel::result::Error> {
+      SignInSignal::delete_older_than(conn, before)?;
+      let anomalous = !registration
+        && SignInSignal::is_anomalous(
+          conn,
+          user_id,
+          form.fingerprint.as_deref(),
+          form.country.as_deref(),
+        )?;
+      SignInSignal::create(conn, &SignInSignalForm { anomalous, ..form })
+    },
+  )
+  .await??;
+
+  if sign_in.anomalous {
+    notify_anomalous_sign_in(sign_in.to_owned(), context).await?;
+  }
+  Ok(Claims::session_jwt(jwt, sign_in.id)?)
+}
+
+/// Emails the user about the login, and sends it to their websockets and notification polls.
+async fn notify_anomalous_sign_in(
+  sign_in: SignInSignal,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let user_id = sign_in.user_id;
+  let user = blocking(context.pool(), move |conn| User_::read(conn, user_id)).await??;
+
+  if let Some(email) = user.email {
+    let hostname = Settings::get().hostname;
+    let subject = format!("{} - New login to your account", hostname);
+    let country = match &sign_in.country {
+      Some(country) => format!(" from {}", country),
+      None => "".to_string(),
+    };
+    let html = format!(
+      "<h1>New login for {}</h1><br><div>Your account was logged in to{} at {} UTC, with a \
+       device or from a country it wasn't used with before. If this wasn't you, revoke the login \
+       in your <a href=https://{}/settings>settings</a> and change your password.</div>",
+      user.name,
+      country,
+      sign_in.published.format("%Y-%m-%d %H:%M"),
+      hostname
+    );
+    let user_name = user.name;
+    blocking(context.pool(), move |conn| {
+      queue_email(conn, &subject, &email, &user_name, &html, None)
+    })
+    .await?;
+  }
+
+  context.chat_server().do_send(SendUserRoomMessage {
+    op: UserOperation::GetSignIns,
+    response: GetSignInsResponse {
+      sign_ins: vec![sign_in],
+      current_sign_in_id: None,
+    },
+    recipient_id: user_id,
+    websocket_id: None,
+  });
+  Ok(())
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for Register {
   type Response = LoginResponse;
@@ -117,6 +274,7 @@ impl Perform for Register {
     if data.password != data.password_verify {
       return Err(APIError::err("passwords_dont_match").into());
     }
+    check_password(&data.password, &data.username, &Settings::get().passwords)?;
 
     // If its not the admin, check the captcha
     if !data.admin && Settings::get().captcha.enabled {
@@ -138,7 +296,17 @@ impl Perform for Register {
       }
     }
 
-    check_slurs(&data.username)?;
+    let hooked = run_api_hooks(
+      context.client(),
+      HookPoint::PreRegister,
+      RegisterHookPayload {
+        username: data.username.to_owned(),
+        email: data.email.to_owned(),
+      },
+    )
+    .await?;
+
+    check_slurs(&hooked.username)?;
 
     // Make sure there are no admins
     let any_admins = blocking(context.pool(), move |conn| {
@@ -150,18 +318,20 @@ impl Perform for Register {
     }
 
     let user_keypair = generate_actor_keypair()?;
-    if !is_valid_username(&data.username) {
+    if !is_valid_username(&hooked.username) {
       return Err(APIError::err("invalid_username").into());
     }
+    let password_encrypted =
+      hash_password_off_thread(&data.password, Settings::get().passwords).await?;
 
     // Register the new user
     let user_form = UserForm {
-      name: data.username.to_owned(),
-      email: Some(data.email.to_owned()),
+      name: hooked.username.to_owned(),
+      email: Some(hooked.email.to_owned()),
       matrix_user_id: None,
       avatar: None,
       banner: None,
-      password_encrypted: data.password.to_owned(),
+      password_encrypted,
       preferred_username: None,
       updated: None,
       admin: data.admin,
@@ -173,33 +343,36 @@ impl Perform for Register {
       lang: "browser".into(),
       show_avatars: true,
       send_notifications_to_email: false,
-      actor_id: Some(make_apub_endpoint(EndpointType::User, &data.username).to_string()),
+      actor_id: Some(make_apub_endpoint(EndpointType::User, &hooked.username).to_string()),
       bio: None,
       local: true,
       private_key: Some(user_keypair.private_key),
       public_key: Some(user_keypair.public_key),
       last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
     };
 
     // Create the user
-    let inserted_user = match blocking(context.pool(), move |conn| {
-      User_::register(conn, &user_form)
-    })
-    .await?
-    {
-      Ok(user) => user,
-      Err(e) => {
-        let err_type = if e.to_string()
-          == "duplicate key value violates unique constraint \"user__email_key\""
-        {
-          "email_already_exists"
-        } else {
-          "user_already_exists"
-        };
+    let inserted_user =
+      match blocking(context.pool(), move |conn| User_::create(conn, &user_form)).await? {
+        Ok(user) => user,
+        Err(e) => {
+          let err_type = if e.to_string()
+            == "duplicate key value violates unique constraint \"user__email_key\""
+          {
+            "email_already_exists"
+          } else {
+            "user_already_exists"
+          };
 
-        return Err(APIError::err(err_type).into());
-      }
-    };
+          return Err(APIError::err(err_type).into());
+        }
+      };
 
     let main_community_keypair = generate_actor_keypair()?;
 
@@ -248,6 +421,24 @@ impl Perform for Register {
       return Err(APIError::err("community_follower_already_exists").into());
     };
 
+    // And for the default communities of the instance, which might include main
+    let user_id = inserted_user.id;
+    blocking(context.pool(), move |conn| {
+      for community_id in DefaultCommunity::list_community_ids(conn)? {
+        // It may have started to require approval to join since it was made a default
+        if CommunitySettings::read_for_community(conn, community_id)?.requires_membership() {
+          continue;
+        }
+        let community_follower_form = CommunityFollowerForm {
+          community_id,
+          user_id,
+        };
+        CommunityFollower::follow(conn, &community_follower_form).ok();
+      }
+      Ok(()) as Result<(), diesel::result::Error>
+    })
+    .await??;
+
     // If its an admin, add them as a mod and follower to main
     if data.admin {
       let community_moderator_form = CommunityModeratorForm {
@@ -368,14 +559,16 @@ impl Perform for SaveUserSettings {
             // Check the old password
             match &data.old_password {
               Some(old_password) => {
-                let valid: bool =
-                  verify(old_password, &read_user.password_encrypted).unwrap_or(false);
+                let valid =
+                  verify_password_off_thread(old_password, &read_user.password_encrypted).await?;
                 if !valid {
                   return Err(APIError::err("password_incorrect").into());
                 }
-                let new_password = new_password.to_owned();
+                let passwords = Settings::get().passwords;
+                check_password(new_password, &read_user.name, &passwords)?;
+                let password_hash = hash_password_off_thread(new_password, passwords).await?;
                 let user = blocking(context.pool(), move |conn| {
-                  User_::update_password(conn, user_id, &new_password)
+                  User_::update_password(conn, user_id, &password_hash)
                 })
                 .await??;
                 user.password_encrypted
@@ -389,6 +582,14 @@ impl Perform for SaveUserSettings {
       None => read_user.password_encrypted,
     };
 
+    let collapse_comments_below_score = if data.collapse_comments_by_score == Some(false) {
+      None
+    } else {
+      data
+        .collapse_comments_below_score
+        .or(read_user.collapse_comments_below_score)
+    };
+
     let user_form = UserForm {
       name: read_user.name,
       email,
@@ -413,6 +614,18 @@ impl Perform for SaveUserSettings {
       private_key: read_user.private_key,
       public_key: read_user.public_key,
       last_refreshed_at: None,
+      discoverable: data.discoverable.unwrap_or(read_user.discoverable),
+      receive_welcome_messages: data
+        .receive_welcome_messages
+        .unwrap_or(read_user.receive_welcome_messages),
+      bot_account: data.bot_account.unwrap_or(read_user.bot_account),
+      collapse_comments_below_score,
+      collapse_bot_comments: data
+        .collapse_bot_comments
+        .unwrap_or(read_user.collapse_bot_comments),
+      remember_comment_sort: data
+        .remember_comment_sort
+        .unwrap_or(read_user.remember_comment_sort),
     };
 
     let res = blocking(context.pool(), move |conn| {
@@ -434,13 +647,30 @@ impl Perform for SaveUserSettings {
       }
     };
 
-    // Return the jwt
+    if let Some(show_presence) = data.show_presence {
+      blocking(context.pool(), move |conn| {
+        UserPresence::update_show_presence(conn, user_id, show_presence)
+      })
+      .await??;
+    }
+
+    // Return the jwt, for the same sign in, so that it can still be revoked
     Ok(LoginResponse {
-      jwt: Claims::jwt(updated_user, Settings::get().hostname)?,
+      jwt: same_session_jwt(&data.auth, updated_user)?,
     })
   }
 }
 
+/// A token for the user, which belongs to the same sign in as the given one.
+fn same_session_jwt(jwt: &str, user: User_) -> Result<String, LemmyError> {
+  let session_id = Claims::decode(jwt)?.claims.session_id;
+  let new_jwt = Claims::jwt(user, Settings::get().hostname)?;
+  match session_id {
+    Some(session_id) => Ok(Claims::session_jwt(&new_jwt, session_id)?),
+    None => Ok(new_jwt),
+  }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for GetUserDetails {
   type Response = GetUserDetailsResponse;
@@ -451,7 +681,7 @@ impl Perform for GetUserDetails {
     _websocket_id: Option<ConnectionId>,
   ) -> Result<GetUserDetailsResponse, LemmyError> {
     let data: &GetUserDetails = &self;
-    let user = get_user_from_jwt_opt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_opt_for_reading(&data.auth, context.pool()).await?;
 
     let show_nsfw = match &user {
       Some(user) => user.show_nsfw,
@@ -483,6 +713,21 @@ impl Perform for GetUserDetails {
     })
     .await??;
 
+    // Users who hide their presence only see it themselves
+    let presence = blocking(context.pool(), move |conn| {
+      UserPresence::read_for_user(conn, user_details_id)
+    })
+    .await??
+    .filter(|p| p.show_presence || user.as_ref().map(|u| u.id) == Some(user_details_id));
+    let online = if presence.is_some() {
+      let is_online = IsUserOnline {
+        user_id: user_details_id,
+      };
+      Some(context.chat_server().send(is_online).await.unwrap_or(false))
+    } else {
+      None
+    };
+
     let page = data.page;
     let limit = data.limit;
     let saved_only = data.saved_only;
@@ -531,6 +776,8 @@ impl Perform for GetUserDetails {
     // Return the jwt
     Ok(GetUserDetailsResponse {
       user: user_view,
+      presence,
+      online,
       follows,
       moderates,
       comments,
@@ -685,7 +932,7 @@ impl Perform for GetReplies {
     _websocket_id: Option<ConnectionId>,
   ) -> Result<GetRepliesResponse, LemmyError> {
     let data: &GetReplies = &self;
-    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
 
     let sort = SortType::from_str(&data.sort)?;
 
@@ -717,7 +964,7 @@ impl Perform for GetUserMentions {
     _websocket_id: Option<ConnectionId>,
   ) -> Result<GetUserMentionsResponse, LemmyError> {
     let data: &GetUserMentions = &self;
-    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
 
     let sort = SortType::from_str(&data.sort)?;
 
@@ -829,6 +1076,22 @@ impl Perform for MarkAllAsRead {
       return Err(APIError::err("couldnt_update_private_message").into());
     }
 
+    // Mark all saved search matches as read
+    let update_matches = move |conn: &'_ _| SavedSearchMatch::mark_all_as_read(conn, user_id);
+    if blocking(context.pool(), update_matches).await?.is_err() {
+      return Err(APIError::err("couldnt_update_saved_search").into());
+    }
+
+    // Mark all community notifications as read
+    let update_community_notifications =
+      move |conn: &'_ _| CommunityNotificationPost::mark_all_as_read(conn, user_id);
+    if blocking(context.pool(), update_community_notifications)
+      .await?
+      .is_err()
+    {
+      return Err(APIError::err("couldnt_update_community_notifications").into());
+    }
+
     Ok(GetRepliesResponse { replies: vec![] })
   }
 }
@@ -846,7 +1109,7 @@ impl Perform for DeleteAccount {
     let user = get_user_from_jwt(&data.auth, context.pool()).await?;
 
     // Verify the password
-    let valid: bool = verify(&data.password, &user.password_encrypted).unwrap_or(false);
+    let valid = verify_password_off_thread(&data.password, &user.password_encrypted).await?;
     if !valid {
       return Err(APIError::err("password_incorrect").into());
     }
@@ -864,6 +1127,12 @@ impl Perform for DeleteAccount {
       return Err(APIError::err("couldnt_update_post").into());
     }
 
+    // Attribute everything to the placeholder user, and clear the profile
+    let delete_user = move |conn: &'_ _| User_::delete_account(conn, user_id);
+    if blocking(context.pool(), delete_user).await?.is_err() {
+      return Err(APIError::err("couldnt_update_user").into());
+    }
+
     Ok(LoginResponse {
       jwt: data.auth.to_owned(),
     })
@@ -905,14 +1174,19 @@ impl Perform for PasswordReset {
 
     // Email the pure token to the user.
     // TODO no i18n support here.
-    let user_email = &user.email.expect("email");
-    let subject = &format!("Password reset for {}", user.name);
+    if Settings::get().email.is_none() {
+      return Err(APIError::err("no_email_setup").into());
+    }
+    let user_email = user.email.expect("email");
+    let subject = format!("Password reset for {}", user.name);
     let hostname = &format!("https://{}", Settings::get().hostname); //TODO add https for now.
-    let html = &format!("<h1>Password Reset Request for {}</h1><br><a href={}/password_change/{}>Click here to reset your password</a>", user.name, hostname, &token);
-    match send_email(subject, user_email, &user.name, html) {
-      Ok(_o) => _o,
-      Err(_e) => return Err(APIError::err(&_e).into()),
-    };
+    let html = format!("<h1>Password Reset Request for {}</h1><br><a href={}/password_change/{}>Click here to reset your password</a>", user.name, hostname, &token);
+    let user_name = user.name;
+    // Sent right away rather than through the outbox, which would keep the token around
+    let sent = web::block(move || send_email(&subject, &user_email, &user_name, &html, None)).await;
+    if sent.is_err() {
+      return Err(APIError::err("couldnt_send_email").into());
+    }
 
     Ok(PasswordResetResponse {})
   }
@@ -940,19 +1214,27 @@ impl Perform for PasswordChange {
     if data.password != data.password_verify {
       return Err(APIError::err("passwords_dont_match").into());
     }
-
-    // Update the user with the new password
-    let password = data.password.clone();
-    let updated_user = match blocking(context.pool(), move |conn| {
-      User_::update_password(conn, user_id, &password)
-    })
+    let user = blocking(context.pool(), move |conn| User_::read(conn, user_id)).await??;
+    let passwords = Settings::get().passwords;
+    check_password(&data.password, &user.name, &passwords)?;
+
+    // Update the user with the new password. Whoever knew the old one is logged out.
+    let password_hash = hash_password_off_thread(&data.password, passwords).await?;
+    let updated_user = match blocking(
+      context.pool(),
+      move |conn| -> Result<_, diesel::result::Error> {
+        User_::update_password(conn, user_id, &password_hash)?;
+        SignInSignal::revoke_all(conn, user_id)?;
+        User_::revoke_sessionless_tokens(conn, user_id)
+      },
+    )
     .await?
     {
       Ok(user) => user,
       Err(_e) => return Err(APIError::err("couldnt_update_user").into()),
     };
 
-    // Return the jwt
+    // Return the jwt, which gets its own sign in, see `record_sign_in`
     Ok(LoginResponse {
       jwt: Claims::jwt(updated_user, Settings::get().hostname)?,
     })
@@ -975,6 +1257,22 @@ impl Perform for CreatePrivateMessage {
 
     let content_slurs_removed = remove_slurs(&data.content.to_owned());
 
+    // Private messages can't be removed, so spam is refused instead
+    let spam = if is_trusted(&user, None, context.pool()).await? {
+      None
+    } else {
+      score_content(
+        SpamContentType::PrivateMessage,
+        &content_slurs_removed,
+        &user,
+        context,
+      )
+      .await?
+    };
+    if spam.as_ref().map_or(false, |s| s.removed) {
+      return Err(APIError::err("spam_detected").into());
+    }
+
     let private_message_form = PrivateMessageForm {
       content: content_slurs_removed.to_owned(),
       creator_id: user.id,
@@ -1013,6 +1311,15 @@ impl Perform for CreatePrivateMessage {
       Err(_e) => return Err(APIError::err("couldnt_create_private_message").into()),
     };
 
+    if let Some(spam) = spam {
+      spam
+        .save(
+          SpamTarget::PrivateMessage(inserted_private_message_id),
+          context.pool(),
+        )
+        .await?;
+    }
+
     updated_private_message.send_create(&user, context).await?;
 
     // Send notifications to the recipient
@@ -1021,19 +1328,21 @@ impl Perform for CreatePrivateMessage {
       blocking(context.pool(), move |conn| User_::read(conn, recipient_id)).await??;
     if recipient_user.send_notifications_to_email {
       if let Some(email) = recipient_user.email {
-        let subject = &format!(
+        let subject = format!(
           "{} - Private Message from {}",
           Settings::get().hostname,
           user.name,
         );
-        let html = &format!(
+        let html = format!(
           "<h1>Private Message</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
           user.name, &content_slurs_removed, hostname
         );
-        match send_email(subject, &email, &recipient_user.name, html) {
-          Ok(_o) => _o,
-          Err(e) => error!("{}", e),
-        };
+        let reply_to = reply_address(ReplyTarget::PrivateMessage(user.id), recipient_user.id);
+        let recipient_name = recipient_user.name;
+        blocking(context.pool(), move |conn| {
+          queue_email(conn, &subject, &email, &recipient_name, &html, reply_to)
+        })
+        .await?;
       }
     }
 
@@ -1260,6 +1569,130 @@ impl Perform for GetPrivateMessages {
   }
 }
 
+/// The longest a notification poll is held, in seconds.
+const MAX_NOTIFICATION_POLL: u64 = 60;
+
+/// The most notifications of each kind in one response.
+const MAX_NOTIFICATIONS: i64 = 50;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetNotifications {
+  type Response = GetNotificationsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetNotificationsResponse, LemmyError> {
+    let data: &GetNotifications = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Registered before looking, so that a notification which arrives in between isn't missed
+    let (sender, receiver) = oneshot::channel();
+    context.chat_server().do_send(WaitForUserMessage {
+      user_id: user.id,
+      sender,
+    });
+    let notifications = get_notifications(user.id, data.cursor, context.pool()).await?;
+    let hold = data.timeout.unwrap_or(30).min(MAX_NOTIFICATION_POLL);
+    if !is_empty(&notifications) || hold == 0 {
+      return Ok(notifications);
+    }
+
+    if timeout(time::Duration::from_secs(hold), receiver)
+      .await
+      .is_err()
+    {
+      return Ok(notifications);
+    }
+    get_notifications(user.id, data.cursor, context.pool()).await
+  }
+}
+
+/// The unread replies, mentions, private messages, posts of subscribed communities and unreviewed
+/// anomalous sign ins, which are newer than the cursor.
+async fn get_notifications(
+  user_id: i32,
+  cursor: Option<i64>,
+  pool: &DbPool,
+) -> Result<GetNotificationsResponse, LemmyError> {
+  let since = cursor.map(|c| {
+    NaiveDateTime::from_timestamp(
+      c.div_euclid(1_000_000),
+      c.rem_euclid(1_000_000) as u32 * 1000,
+    )
+  });
+  blocking(pool, move |conn| {
+    let is_new = |published: &NaiveDateTime| since.map_or(true, |s| *published > s);
+    let sort = SortType::New;
+    let replies = ReplyQueryBuilder::create(conn, user_id)
+      .sort(&sort)
+      .unread_only(true)
+      .limit(MAX_NOTIFICATIONS)
+      .list()?
+      .into_iter()
+      .filter(|r| is_new(&r.published))
+      .collect::<Vec<ReplyView>>();
+    let mentions = UserMentionQueryBuilder::create(conn, user_id)
+      .sort(&sort)
+      .unread_only(true)
+      .limit(MAX_NOTIFICATIONS)
+      .list()?
+      .into_iter()
+      .filter(|m| is_new(&m.published))
+      .collect::<Vec<UserMentionView>>();
+    let messages = PrivateMessageQueryBuilder::create(conn, user_id)
+      .unread_only(true)
+      .limit(MAX_NOTIFICATIONS)
+      .list()?
+      .into_iter()
+      .filter(|m| is_new(&m.published))
+      .collect::<Vec<PrivateMessageView>>();
+    let community_notifications =
+      CommunityNotificationPost::list_for_user(conn, user_id, true, None, Some(MAX_NOTIFICATIONS))?
+        .into_iter()
+        .filter(|n| is_new(&n.published))
+        .collect::<Vec<CommunityNotificationPost>>();
+    let community_posts = community_notifications
+      .iter()
+      .map(|n| PostView::read(conn, n.post_id, Some(user_id)))
+      .collect::<Result<Vec<PostView>, _>>()?;
+    let sign_ins = SignInSignal::list_unreviewed(conn, user_id)?
+      .into_iter()
+      .filter(|s| is_new(&s.published))
+      .take(MAX_NOTIFICATIONS as usize)
+      .collect::<Vec<SignInSignal>>();
+
+    let newest = replies
+      .iter()
+      .map(|r| r.published)
+      .chain(mentions.iter().map(|m| m.published))
+      .chain(messages.iter().map(|m| m.published))
+      .chain(community_notifications.iter().map(|n| n.published))
+      .chain(sign_ins.iter().map(|s| s.published))
+      .max()
+      .or(since)
+      .unwrap_or_else(naive_now);
+    Ok(GetNotificationsResponse {
+      replies,
+      mentions,
+      messages,
+      community_posts,
+      sign_ins,
+      cursor: newest.timestamp() * 1_000_000 + i64::from(newest.timestamp_subsec_micros()),
+    }) as Result<GetNotificationsResponse, LemmyError>
+  })
+  .await?
+}
+
+fn is_empty(notifications: &GetNotificationsResponse) -> bool {
+  notifications.replies.is_empty()
+    && notifications.mentions.is_empty()
+    && notifications.messages.is_empty()
+    && notifications.community_posts.is_empty()
+    && notifications.sign_ins.is_empty()
+}
+
 #[async_trait::async_trait(?Send)]
 impl Perform for UserJoin {
   type Response = UserJoinResponse;
@@ -1282,3 +1715,432 @@ impl Perform for UserJoin {
     Ok(UserJoinResponse { user_id: user.id })
   }
 }
+
+#[async_trait::async_trait(?Send)]
+impl Perform for CreateSavedSearch {
+  type Response = SavedSearchResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<SavedSearchResponse, LemmyError> {
+    let data: &CreateSavedSearch = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    check_slurs(&data.query)?;
+
+    let keywords = search_keywords(&data.query);
+    if keywords.is_empty() {
+      return Err(APIError::err("invalid_saved_search").into());
+    }
+
+    let saved_search_form = SavedSearchForm {
+      user_id: user.id,
+      query: data.query.trim().to_owned(),
+      keywords,
+      community_id: data.community_id,
+      creator_id: data.creator_id,
+    };
+
+    let saved_search = match blocking(context.pool(), move |conn| {
+      SavedSearch::create(conn, &saved_search_form)
+    })
+    .await?
+    {
+      Ok(saved_search) => saved_search,
+      Err(_e) => return Err(APIError::err("couldnt_create_saved_search").into()),
+    };
+
+    Ok(SavedSearchResponse { saved_search })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeleteSavedSearch {
+  type Response = SavedSearchResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<SavedSearchResponse, LemmyError> {
+    let data: &DeleteSavedSearch = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let id = data.id;
+    let saved_search = blocking(context.pool(), move |conn| SavedSearch::read(conn, id)).await??;
+
+    if saved_search.user_id != user.id {
+      return Err(APIError::err("couldnt_update_saved_search").into());
+    }
+
+    let delete = move |conn: &'_ _| SavedSearch::delete(conn, id);
+    if blocking(context.pool(), delete).await?.is_err() {
+      return Err(APIError::err("couldnt_update_saved_search").into());
+    }
+
+    Ok(SavedSearchResponse { saved_search })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetSavedSearches {
+  type Response = GetSavedSearchesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetSavedSearchesResponse, LemmyError> {
+    let data: &GetSavedSearches = &self;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let saved_searches = blocking(context.pool(), move |conn| {
+      SavedSearch::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(GetSavedSearchesResponse { saved_searches })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetSavedSearchMatches {
+  type Response = GetSavedSearchMatchesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetSavedSearchMatchesResponse, LemmyError> {
+    let data: &GetSavedSearchMatches = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let unread_only = data.unread_only;
+    let page = data.page;
+    let limit = data.limit;
+    let (matches, posts) = blocking(context.pool(), move |conn| {
+      let matches = SavedSearchMatch::list_for_user(conn, user_id, unread_only, page, limit)?;
+      let posts = matches
+        .iter()
+        .map(|m| PostView::read(conn, m.post_id, Some(user_id)))
+        .collect::<Result<Vec<PostView>, _>>()?;
+      Ok((matches, posts)) as Result<_, LemmyError>
+    })
+    .await??;
+
+    Ok(GetSavedSearchMatchesResponse { matches, posts })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for MuteThread {
+  type Response = ThreadMutesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ThreadMutesResponse, LemmyError> {
+    let data: &MuteThread = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Either a whole post, or a comment with its replies
+    match (data.post_id, data.comment_id) {
+      (Some(post_id), None) => {
+        blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+      }
+      (None, Some(comment_id)) => {
+        blocking(context.pool(), move |conn| Comment::read(conn, comment_id)).await??;
+      }
+      _ => return Err(APIError::err("couldnt_mute_thread").into()),
+    };
+
+    let user_id = user.id;
+    let thread_mute_form = ThreadMuteForm {
+      user_id,
+      post_id: data.post_id,
+      comment_id: data.comment_id,
+    };
+
+    if data.mute {
+      let mute = move |conn: &'_ _| ThreadMute::mute(conn, &thread_mute_form);
+      if blocking(context.pool(), mute).await?.is_err() {
+        return Err(APIError::err("couldnt_mute_thread").into());
+      }
+    } else {
+      let unmute = move |conn: &'_ _| ThreadMute::unmute(conn, &thread_mute_form);
+      if blocking(context.pool(), unmute).await?.is_err() {
+        return Err(APIError::err("couldnt_mute_thread").into());
+      }
+    }
+
+    let thread_mutes = blocking(context.pool(), move |conn| {
+      ThreadMute::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(ThreadMutesResponse { thread_mutes })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetThreadMutes {
+  type Response = ThreadMutesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ThreadMutesResponse, LemmyError> {
+    let data: &GetThreadMutes = &self;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let thread_mutes = blocking(context.pool(), move |conn| {
+      ThreadMute::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(ThreadMutesResponse { thread_mutes })
+  }
+}
+
+/// Browsers a user can get web pushes on.
+const MAX_PUSH_SUBSCRIPTIONS: usize = 10;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetPushPublicKey {
+  type Response = GetPushPublicKeyResponse;
+
+  async fn perform(
+    &self,
+    _context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetPushPublicKeyResponse, LemmyError> {
+    Ok(GetPushPublicKeyResponse {
+      public_key: push_public_key()?,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for SavePushSubscription {
+  type Response = PushSubscriptionResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<PushSubscriptionResponse, LemmyError> {
+    let data: &SavePushSubscription = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    if Settings::get().web_push.is_none() {
+      return Err(APIError::err("web_push_disabled").into());
+    }
+    let endpoint = match Url::parse(&data.endpoint) {
+      Ok(url) if url.scheme() == "https" => url,
+      _ => return Err(APIError::err("invalid_push_subscription").into()),
+    };
+    if check_push_subscription_keys(&data.keys.p256dh, &data.keys.auth).is_err() {
+      return Err(APIError::err("invalid_push_subscription").into());
+    }
+
+    let user_id = user.id;
+    let endpoint = endpoint.to_string();
+    let for_endpoint = endpoint.to_owned();
+    let subscriptions = blocking(context.pool(), move |conn| {
+      PushSubscription::list_for_users(conn, vec![user_id])
+    })
+    .await??;
+    // Saving a subscription again is fine, eg with new keys
+    if subscriptions.len() >= MAX_PUSH_SUBSCRIPTIONS
+      && !subscriptions.iter().any(|s| s.endpoint == for_endpoint)
+    {
+      return Err(APIError::err("too_many_push_subscriptions").into());
+    }
+
+    let subscription_form = PushSubscriptionForm {
+      user_id,
+      endpoint,
+      p256dh: data.keys.p256dh.to_owned(),
+      auth: data.keys.auth.to_owned(),
+    };
+    let push_subscription = match blocking(context.pool(), move |conn| {
+      PushSubscription::subscribe(conn, &subscription_form)
+    })
+    .await?
+    {
+      Ok(s) => s,
+      Err(_e) => return Err(APIError::err("couldnt_save_push_subscription").into()),
+    };
+
+    Ok(PushSubscriptionResponse {
+      push_subscription: Some(push_subscription),
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for DeletePushSubscription {
+  type Response = PushSubscriptionResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<PushSubscriptionResponse, LemmyError> {
+    let data: &DeletePushSubscription = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    // Saved in the normalized form of the url
+    let endpoint = match Url::parse(&data.endpoint) {
+      Ok(url) => url.to_string(),
+      Err(_e) => return Err(APIError::err("invalid_push_subscription").into()),
+    };
+    let unsubscribe =
+      move |conn: &'_ _| PushSubscription::unsubscribe(conn, &endpoint, user_id);
+    if blocking(context.pool(), unsubscribe).await?.is_err() {
+      return Err(APIError::err("couldnt_save_push_subscription").into());
+    }
+
+    Ok(PushSubscriptionResponse {
+      push_subscription: None,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for IgnoreVoteInstance {
+  type Response = IgnoredVoteInstancesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<IgnoredVoteInstancesResponse, LemmyError> {
+    let data: &IgnoreVoteInstance = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    // Votes are stored with the lowercase domain of the voter
+    let instance = data.instance.trim().to_lowercase();
+    if instance.is_empty()
+      || instance.len() > 255
+      || instance.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+    {
+      return Err(APIError::err("invalid_instance").into());
+    }
+
+    let user_id = user.id;
+    let form = UserIgnoredVoteInstanceForm { user_id, instance };
+
+    if data.ignore {
+      let ignore = move |conn: &'_ _| UserIgnoredVoteInstance::ignore(conn, &form);
+      if blocking(context.pool(), ignore).await?.is_err() {
+        return Err(APIError::err("couldnt_ignore_vote_instance").into());
+      }
+    } else {
+      let unignore = move |conn: &'_ _| UserIgnoredVoteInstance::unignore(conn, &form);
+      if blocking(context.pool(), unignore).await?.is_err() {
+        return Err(APIError::err("couldnt_ignore_vote_instance").into());
+      }
+    }
+
+    let ignored_vote_instances = blocking(context.pool(), move |conn| {
+      UserIgnoredVoteInstance::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(IgnoredVoteInstancesResponse {
+      ignored_vote_instances,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetIgnoredVoteInstances {
+  type Response = IgnoredVoteInstancesResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<IgnoredVoteInstancesResponse, LemmyError> {
+    let data: &GetIgnoredVoteInstances = &self;
+    let user = get_user_from_jwt_for_reading(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let ignored_vote_instances = blocking(context.pool(), move |conn| {
+      UserIgnoredVoteInstance::list_for_user(conn, user_id)
+    })
+    .await??;
+
+    Ok(IgnoredVoteInstancesResponse {
+      ignored_vote_instances,
+    })
+  }
+}
+
+/// The most sign ins in one response.
+const MAX_SIGN_INS: usize = 100;
+
+#[async_trait::async_trait(?Send)]
+impl Perform for GetSignIns {
+  type Response = GetSignInsResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<GetSignInsResponse, LemmyError> {
+    let data: &GetSignIns = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let user_id = user.id;
+    let anomalous_only = data.anomalous_only.unwrap_or(false);
+    let mut sign_ins = blocking(context.pool(), move |conn| {
+      SignInSignal::list_for_user(conn, user_id)
+    })
+    .await??;
+    sign_ins.retain(|s| s.anomalous || !anomalous_only);
+    sign_ins.truncate(MAX_SIGN_INS);
+
+    Ok(GetSignInsResponse {
+      sign_ins,
+      current_sign_in_id: Claims::decode(&data.auth)?.claims.session_id,
+    })
+  }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Perform for ReviewSignIn {
+  type Response = ReviewSignInResponse;
+
+  async fn perform(
+    &self,
+    context: &Data<LemmyContext>,
+    _websocket_id: Option<ConnectionId>,
+  ) -> Result<ReviewSignInResponse, LemmyError> {
+    let data: &ReviewSignIn = &self;
+    let user = get_user_from_jwt(&data.auth, context.pool()).await?;
+
+    let sign_in_id = data.id;
+    let user_id = user.id;
+    let revoke = data.revoke;
+    let sign_in = match blocking(context.pool(), move |conn| {
+      SignInSignal::review(conn, sign_in_id, user_id, revoke)
+    })
+    .await?
+    {
+      Ok(sign_in) => sign_in,
+      Err(_e) => return Err(APIError::err("couldnt_find_sign_in").into()),
+    };
+
+    Ok(ReviewSignInResponse { sign_in })
+  }
+}
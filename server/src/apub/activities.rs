@@ -1,14 +1,33 @@
 use crate::{
-  apub::{activity_queue::send_activity, community::do_announce, insert_activity},
+  apub::{
+    activity_queue::send_activity,
+    community::do_announce,
+    create_apub_response,
+    fetch_signer,
+    insert_activity,
+    is_fetch_authorized,
+    is_public_fetch_authorized,
+    with_current_object,
+    ActorType,
+  },
+  DbPool,
   LemmyContext,
 };
 use activitystreams::{
-  base::{Extends, ExtendsExt},
+  activity::{Flag, Undo},
+  base::{AnyBase, Extends, ExtendsExt},
   object::AsObject,
+  prelude::*,
+  public,
 };
-use lemmy_db::{community::Community, user::User_};
-use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings, LemmyError};
+use actix_web::{body::Body, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use lemmy_api_structs::blocking;
+use lemmy_db::{activity::Activity, community::Community, instance::Instance, user::User_};
+use lemmy_utils::{apub::get_apub_protocol_string, location_info, settings::Settings, LemmyError};
 use serde::{export::fmt::Debug, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use url::{ParseError, Url};
 use uuid::Uuid;
 
@@ -31,22 +50,184 @@ where
   if community.local {
     do_announce(activity.into_any_base()?, &community, creator, context).await?;
   } else {
-    send_activity(context.activity_queue(), activity, creator, to)?;
+    let mut to = to;
+    if is_vote(&activity.clone().into_any_base()?)? {
+      let capabilities = InboxCapabilities::load(&to, context.pool()).await?;
+      to.retain(|i| capabilities.supports_votes(i));
+    }
+    if !to.is_empty() {
+      send_activity(context, activity, creator, to).await?;
+    }
   }
 
   Ok(())
 }
 
-pub(in crate::apub) fn generate_activity_id<T>(kind: T) -> Result<Url, ParseError>
-where
-  T: ToString,
-{
+/// Reports a post or comment of a remote community to the mods of the community, as a Flag
+/// activity with the reason as summary.
+pub async fn send_flag(
+  reporter: &User_,
+  community: &Community,
+  object_id: &str,
+  reason: &str,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let mut flag = Flag::new(reporter.actor_id.to_owned(), object_id);
+  flag
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(Url::parse(&community.actor_id)?)
+    .set_summary(reason.to_owned());
+
+  insert_activity(reporter.id, flag.clone(), true, context.pool()).await?;
+
+  send_activity(context, flag, reporter, vec![community.get_inbox_url()?]).await?;
+  Ok(())
+}
+
+/// A new id for an activity, under which it is served once it's stored with insert_activity. Older
+/// versions had the kind of the activity in the id, like `/activities/create/{uuid}`.
+pub(in crate::apub) fn generate_activity_id() -> Result<Url, ParseError> {
   let id = format!(
-    "{}://{}/activities/{}/{}",
+    "{}://{}/activities/{}",
     get_apub_protocol_string(),
     Settings::get().hostname,
-    kind.to_string().to_lowercase(),
     Uuid::new_v4()
   );
   Url::parse(&id)
 }
+
+/// Return a local activity over HTTP, by its id. Activities about a local community are served like
+/// the other objects of the community, and other activities addressed to the public like public
+/// objects. The rest, like follows and private messages, are only served to requests signed by an
+/// actor on the instance of one of their recipients. Like in outboxes, their local post or comment
+/// is shown as it is now.
+pub async fn get_apub_activity(
+  request: HttpRequest,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse<Body>, LemmyError> {
+  let ap_id = format!(
+    "{}://{}{}",
+    get_apub_protocol_string(),
+    Settings::get().hostname,
+    request.path()
+  );
+  let activity = blocking(context.pool(), move |conn| {
+    Activity::read_local_from_apub_id(conn, &ap_id)
+  })
+  .await?;
+  let activity = match activity {
+    Ok(activity) => activity,
+    Err(_) => return Ok(HttpResponse::NotFound().finish()),
+  };
+
+  let recipients = activity_recipients(&activity.data);
+  let community_ids = recipients
+    .iter()
+    .map(|r| r.trim_end_matches("/followers").to_owned())
+    .collect::<Vec<String>>();
+  let community = blocking(context.pool(), move |conn| {
+    community_ids
+      .iter()
+      .filter_map(|id| Community::read_from_actor_id(conn, id).ok())
+      .find(|c| c.local)
+  })
+  .await?;
+
+  let authorized = if let Some(community) = community {
+    is_fetch_authorized(&request, community.id, &context).await?
+  } else if recipients.iter().any(|r| r == public().as_str()) {
+    is_public_fetch_authorized(&request, &context).await?
+  } else {
+    match fetch_signer(&request, &context).await? {
+      Some(signer) => recipients
+        .iter()
+        .filter_map(|r| Url::parse(r).ok())
+        .any(|r| r.domain() == signer.domain()),
+      None => false,
+    }
+  };
+  if !authorized {
+    return Ok(HttpResponse::Unauthorized().finish());
+  }
+  let data = with_current_object(activity.data, context.pool()).await?;
+  Ok(create_apub_response(&data))
+}
+
+/// The ids in the to, cc, bto and bcc of an activity, which can each be a single id or a list.
+fn activity_recipients(activity: &Value) -> Vec<String> {
+  ["to", "cc", "bto", "bcc"]
+    .iter()
+    .filter_map(|field| activity.get(field))
+    .flat_map(|recipients| match recipients {
+      Value::String(recipient) => vec![recipient.to_owned()],
+      Value::Array(recipients) => recipients
+        .iter()
+        .filter_map(Value::as_str)
+        .map(str::to_owned)
+        .collect(),
+      _ => vec![],
+    })
+    .collect()
+}
+
+/// What the instances of some inboxes understand, going by the software the instance crawler found
+/// in their nodeinfo. Instances which weren't crawled yet are assumed to understand everything.
+pub(in crate::apub) struct InboxCapabilities {
+  instances: HashMap<String, Instance>,
+}
+
+impl InboxCapabilities {
+  pub(in crate::apub) async fn load(
+    inboxes: &[Url],
+    pool: &DbPool,
+  ) -> Result<InboxCapabilities, LemmyError> {
+    let domains = inboxes
+      .iter()
+      .filter_map(|i| i.host_str())
+      .map(|h| h.to_lowercase())
+      .collect::<Vec<String>>();
+    let instances = blocking(pool, move |conn| Instance::list_for_domains(conn, domains)).await??;
+    Ok(InboxCapabilities {
+      instances: instances
+        .into_iter()
+        .map(|i| (i.domain.to_owned(), i))
+        .collect(),
+    })
+  }
+
+  fn instance(&self, inbox: &Url) -> Option<&Instance> {
+    inbox
+      .host_str()
+      .and_then(|h| self.instances.get(&h.to_lowercase()))
+  }
+
+  pub(in crate::apub) fn supports_groups(&self, inbox: &Url) -> bool {
+    self
+      .instance(inbox)
+      .map(|i| i.supports_groups)
+      .unwrap_or(true)
+  }
+
+  pub(in crate::apub) fn supports_votes(&self, inbox: &Url) -> bool {
+    self
+      .instance(inbox)
+      .map(|i| i.supports_votes)
+      .unwrap_or(true)
+  }
+}
+
+/// Whether the activity is a like or dislike, or the undo of one.
+pub(in crate::apub) fn is_vote(activity: &AnyBase) -> Result<bool, LemmyError> {
+  match activity.kind_str() {
+    Some("Like") | Some("Dislike") => Ok(true),
+    Some("Undo") => {
+      let undo = Undo::from_any_base(activity.to_owned())?.context(location_info!())?;
+      Ok(matches!(
+        undo.object().as_single_kind_str(),
+        Some("Like") | Some("Dislike")
+      ))
+    }
+    _ => Ok(false),
+  }
+}
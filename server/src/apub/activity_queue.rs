@@ -1,10 +1,20 @@
-use crate::apub::{check_is_apub_id_valid, extensions::signatures::sign, ActorType};
+use crate::{
+  apub::{
+    check_is_apub_id_valid,
+    extensions::signatures::sign,
+    instance::instance_actor_id,
+    ActorType,
+  },
+  plugins::{run_hooks, FederationHookPayload, HookResult},
+  request::{check_outbound_url, RetryPolicy},
+  DbPool,
+  LemmyContext,
+};
 use activitystreams::{
   base::{Extends, ExtendsExt},
   object::AsObject,
 };
 use anyhow::{anyhow, Context, Error};
-use awc::Client;
 use background_jobs::{
   create_server,
   memory_storage::Storage,
@@ -14,18 +24,62 @@ use background_jobs::{
   QueueHandle,
   WorkerConfig,
 };
+use chrono::DateTime;
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  activity_send_queue::{ActivitySendQueue, ActivitySendQueueForm},
+  community::Community,
+  instance_actor::InstanceActor,
+  naive_now,
+  plugin_hook::HookPoint,
+  user::User_,
+};
 use lemmy_utils::{location_info, settings::Settings, LemmyError};
-use log::warn;
+use log::{error, warn};
+use reqwest::{header::RETRY_AFTER, Client, Request, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::{future::Future, pin::Pin};
-use url::Url;
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Mutex, time::Duration};
+use url::{Position, Url};
 
-pub fn send_activity<T, Kind>(
-  activity_sender: &QueueHandle,
+/// How often the queue is checked for activities whose retry is due.
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A claimed activity becomes due again after this, in case its delivery never finished, eg
+/// because the server was restarted.
+const DELIVERY_LEASE_MINUTES: i64 = 10;
+
+lazy_static! {
+  /// Activities sent to every other instance, since the stats job last collected them.
+  static ref OUTGOING_ACTIVITIES: Mutex<HashMap<String, i32>> = Mutex::new(HashMap::new());
+}
+
+/// Stores the activity in the send queue, once for each inbox, and delivers it right away to the
+/// inboxes which don't have older activities waiting.
+pub async fn send_activity<T, Kind>(
+  context: &LemmyContext,
   activity: T,
   actor: &dyn ActorType,
   to: Vec<Url>,
 ) -> Result<(), LemmyError>
+where
+  T: AsObject<Kind>,
+  T: Extends<Kind>,
+  Kind: Serialize,
+  <T as Extends<Kind>>::Error: From<serde_json::Error> + Send + Sync + 'static,
+{
+  // The key is read again when sending, but an actor without one can't send anything
+  actor.private_key().context(location_info!())?;
+  enqueue_activity(context, activity, actor.actor_id()?, to).await
+}
+
+/// Like send_activity, for actors which aren't users or communities, like the instance actor.
+/// Their key has to be found by read_private_key.
+pub(in crate::apub) async fn enqueue_activity<T, Kind>(
+  context: &LemmyContext,
+  activity: T,
+  actor_id: Url,
+  to: Vec<Url>,
+) -> Result<(), LemmyError>
 where
   T: AsObject<Kind>,
   T: Extends<Kind>,
@@ -43,24 +97,101 @@ where
     check_is_apub_id_valid(&to_url)?;
   }
 
-  // TODO: it would make sense to create a separate task for each destination server
-  let message = SendActivityTask {
-    activity: serialised_activity,
-    to,
-    actor_id: actor.actor_id()?,
-    private_key: actor.private_key().context(location_info!())?,
-  };
-  activity_sender.queue::<SendActivityTask>(message)?;
+  if let Ok(mut outgoing) = OUTGOING_ACTIVITIES.lock() {
+    for to_url in &to {
+      let domain = &to_url[Position::BeforeHost..Position::AfterPort];
+      *outgoing.entry(domain.to_owned()).or_insert(0) += 1;
+    }
+  }
+
+  let actor_id = actor_id.to_string();
+  let forms = to
+    .iter()
+    .map(|inbox| ActivitySendQueueForm {
+      activity: serialised_activity.to_owned(),
+      inbox: inbox.to_string(),
+      actor_id: actor_id.to_owned(),
+    })
+    .collect::<Vec<ActivitySendQueueForm>>();
+  blocking(context.pool(), move |conn| {
+    ActivitySendQueue::enqueue(conn, &forms)
+  })
+  .await??;
+
+  for inbox in to {
+    dispatch_next(context.pool(), context.activity_queue(), inbox.to_string()).await?;
+  }
+
+  Ok(())
+}
+
+/// Returns the counts of outgoing activities per instance, and starts counting from zero again.
+pub fn take_outgoing_activity_counts() -> HashMap<String, i32> {
+  match OUTGOING_ACTIVITIES.lock() {
+    Ok(mut outgoing) => std::mem::take(&mut *outgoing),
+    Err(_) => HashMap::new(),
+  }
+}
+
+/// Adds counts which were taken, but couldn't be saved, back for the next run of the stats job.
+pub fn restore_outgoing_activity_counts(counts: HashMap<String, i32>) {
+  if let Ok(mut outgoing) = OUTGOING_ACTIVITIES.lock() {
+    for (domain, count) in counts {
+      *outgoing.entry(domain).or_insert(0) += count;
+    }
+  }
+}
+
+/// Hands the failed activities whose retry is due, and those whose delivery never finished, to the
+/// workers again.
+pub async fn run_delivery_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(DELIVERY_INTERVAL);
+  loop {
+    interval.tick().await;
+    // The queue is kept until the site is writable again
+    if !Settings::get().federation.enabled || Settings::get().maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = dispatch_due(&context).await {
+      error!("Couldn't retry the outgoing activities: {}", e);
+    }
+  }
+}
 
+async fn dispatch_due(context: &LemmyContext) -> Result<(), LemmyError> {
+  let inboxes = blocking(context.pool(), move |conn| {
+    ActivitySendQueue::list_due_inboxes(conn)
+  })
+  .await??;
+  for inbox in inboxes {
+    dispatch_next(context.pool(), context.activity_queue(), inbox).await?;
+  }
+  Ok(())
+}
+
+/// Queues the oldest activity for the inbox, unless it's already being sent or waits for its retry,
+/// so that every inbox gets its activities in order.
+async fn dispatch_next(
+  pool: &DbPool,
+  activity_sender: &QueueHandle,
+  inbox: String,
+) -> Result<(), LemmyError> {
+  let lease_until = naive_now() + chrono::Duration::minutes(DELIVERY_LEASE_MINUTES);
+  let claimed = blocking(pool, move |conn| {
+    ActivitySendQueue::claim_next(conn, &inbox, lease_until)
+  })
+  .await??;
+  if let Some(queued) = claimed {
+    activity_sender.queue::<SendActivityTask>(SendActivityTask {
+      queue_id: queued.id,
+    })?;
+  }
   Ok(())
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct SendActivityTask {
-  activity: String,
-  to: Vec<Url>,
-  actor_id: Url,
-  private_key: String,
+  queue_id: i32,
 }
 
 impl ActixJob for SendActivityTask {
@@ -68,55 +199,234 @@ impl ActixJob for SendActivityTask {
   type Future = Pin<Box<dyn Future<Output = Result<(), Error>>>>;
   const NAME: &'static str = "SendActivityTask";
 
-  const MAX_RETRIES: MaxRetries = MaxRetries::Count(10);
+  // Retries are scheduled in the send queue, so they survive restarts
+  const MAX_RETRIES: MaxRetries = MaxRetries::Count(0);
   const BACKOFF: Backoff = Backoff::Exponential(2);
 
   fn run(self, state: Self::State) -> Self::Future {
     Box::pin(async move {
-      for to_url in &self.to {
-        let request = state
-          .client
-          .post(to_url.as_str())
-          .header("Content-Type", "application/json");
-
-        // TODO: i believe we have to do the signing in here because it is only valid for a few seconds
-        let signed = sign(
-          request,
-          self.activity.clone(),
-          &self.actor_id,
-          self.private_key.to_owned(),
-        )
-        .await;
-        let signed = match signed {
-          Ok(s) => s,
-          Err(e) => {
-            warn!("{}", e);
-            // dont return an error because retrying would probably not fix the signing
-            return Ok(());
-          }
-        };
-        if let Err(e) = signed.send().await {
-          warn!("{}", e);
-          return Err(anyhow!(
-            "Failed to send activity {} to {}",
-            &self.activity,
-            to_url
-          ));
+      send_queued(self.queue_id, &state)
+        .await
+        .map_err(|e| anyhow!("{}", e))
+    })
+  }
+}
+
+/// Sends the activity, and removes it from the queue or schedules its retry. Fails only if the
+/// activity was given up on, so that the queue stats count it.
+async fn send_queued(queue_id: i32, state: &MyState) -> Result<(), LemmyError> {
+  let queued = blocking(&state.pool, move |conn| {
+    ActivitySendQueue::read(conn, queue_id)
+  })
+  .await??;
+  let queued = match queued {
+    Some(q) => q,
+    None => return Ok(()),
+  };
+
+  match deliver(&queued, state).await {
+    Ok(()) => {
+      blocking(&state.pool, move |conn| {
+        ActivitySendQueue::delivered(conn, queue_id)
+      })
+      .await??;
+      dispatch_next(&state.pool, &state.queue_handle, queued.inbox).await?;
+      Ok(())
+    }
+    Err(DeliveryFailure { error, retry_after }) => {
+      warn!("{}", error);
+      let error = error.to_string();
+      let retry = blocking(&state.pool, move |conn| {
+        ActivitySendQueue::failed(conn, queue_id, &error, retry_after)
+      })
+      .await??;
+      match retry {
+        Some(_) => Ok(()),
+        None => {
+          // Dropping it lets the later activities for the inbox through
+          dispatch_next(&state.pool, &state.queue_handle, queued.inbox.to_owned()).await?;
+          Err(
+            anyhow!(
+              "Gave up on sending activity {} to {}",
+              &queued.activity,
+              &queued.inbox
+            )
+            .into(),
+          )
         }
       }
+    }
+  }
+}
 
-      Ok(())
+/// Why an activity couldn't be delivered this time. It's sent again after the backoff of the send
+/// queue, or later if the inbox asked for that.
+struct DeliveryFailure {
+  error: LemmyError,
+  retry_after: Option<chrono::Duration>,
+}
+
+impl From<LemmyError> for DeliveryFailure {
+  fn from(error: LemmyError) -> Self {
+    DeliveryFailure {
+      error,
+      retry_after: None,
+    }
+  }
+}
+
+/// Sends the activity to its inbox. Activities which can't ever be delivered, because a plugin
+/// rejected them, the inbox isn't allowed or refuses them, count as delivered. Server errors and
+/// rate limits are retried.
+async fn deliver(queued: &ActivitySendQueue, state: &MyState) -> Result<(), DeliveryFailure> {
+  let (activity, request) = match prepare_delivery(queued, state).await? {
+    Some(prepared) => prepared,
+    None => return Ok(()),
+  };
+  let sent = RetryPolicy::BACKGROUND
+    .run_custom(&queued.inbox, || async {
+      // The body is a string, so the request can always be cloned
+      let request = request.try_clone().context(location_info!())?;
+      Ok(state.client.execute(request).await)
+    })
+    .await;
+  let response = match sent {
+    Ok(r) => r,
+    Err(e) => {
+      let error = anyhow!(
+        "Failed to send activity {} to {}: {}",
+        &activity,
+        &queued.inbox,
+        e
+      );
+      return Err(LemmyError::from(error).into());
+    }
+  };
+
+  let status = response.status();
+  if status.is_success() {
+    Ok(())
+  } else if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+    let retry_after = response
+      .headers()
+      .get(RETRY_AFTER)
+      .and_then(|v| v.to_str().ok())
+      .and_then(parse_retry_after);
+    let error = anyhow!(
+      "{} answered {} to activity {}",
+      &queued.inbox,
+      status,
+      &activity
+    );
+    Err(DeliveryFailure {
+      error: error.into(),
+      retry_after,
     })
+  } else {
+    // Other client errors don't go away by retrying
+    warn!(
+      "{} refused activity {}: {}",
+      &queued.inbox, &activity, status
+    );
+    Ok(())
+  }
+}
+
+/// The signed request for the activity, or `None` if it can't ever be delivered.
+async fn prepare_delivery(
+  queued: &ActivitySendQueue,
+  state: &MyState,
+) -> Result<Option<(String, Request)>, LemmyError> {
+  let payload = FederationHookPayload {
+    activity: serde_json::from_str(&queued.activity)?,
+    inboxes: vec![queued.inbox.to_owned()],
+  };
+  let hooked = match run_hooks(&state.client, HookPoint::PreFederationSend, payload).await {
+    Ok(HookResult::Allowed(p)) => p,
+    // the plugin doesn't want the activity to be sent at all
+    Ok(HookResult::Rejected) => return Ok(None),
+    Err(e) => return Err(anyhow!("Plugin failed for activity {}: {}", &queued.activity, e).into()),
+  };
+  // Plugins can only leave out inboxes, not add new ones
+  if !hooked.inboxes.contains(&queued.inbox) {
+    return Ok(None);
+  }
+  let activity = serde_json::to_string(&hooked.activity)?;
+
+  let to_url = Url::parse(&queued.inbox)?;
+  if let Err(e) = check_outbound_url(&to_url).await {
+    warn!("{}", e);
+    // the inbox won't become public by retrying
+    return Ok(None);
+  }
+  let actor_id = Url::parse(&queued.actor_id)?;
+  let private_key = match read_private_key(&state.pool, queued.actor_id.to_owned()).await? {
+    Some(k) => k,
+    None => {
+      warn!("No private key to sign the activities of {}", actor_id);
+      return Ok(None);
+    }
+  };
+  let timeout = Settings::get().outbound_requests.federation_timeout_seconds;
+  let request = state
+    .client
+    .post(to_url.as_str())
+    .header("Content-Type", "application/json")
+    .timeout(Duration::from_secs(timeout));
+
+  // The signature is only valid for a few seconds, so it's made right before sending
+  match sign(request, activity.clone(), &actor_id, private_key) {
+    Ok(signed) => Ok(Some((activity, signed))),
+    Err(e) => {
+      warn!("{}", e);
+      // dont return an error because retrying would probably not fix the signing
+      Ok(None)
+    }
   }
 }
 
-pub fn create_activity_queue() -> QueueHandle {
+/// The delay which an inbox asks for in its Retry-After header, in seconds or as a date, up to a
+/// day.
+fn parse_retry_after(value: &str) -> Option<chrono::Duration> {
+  let max_seconds = chrono::Duration::days(1).num_seconds();
+  let seconds = match value.trim().parse::<i64>() {
+    Ok(seconds) => seconds,
+    Err(_) => {
+      let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+      (date.naive_utc() - naive_now()).num_seconds()
+    }
+  };
+  Some(chrono::Duration::seconds(seconds.max(0).min(max_seconds)))
+}
+
+/// The key of the local user, community or instance actor which sent the activity.
+async fn read_private_key(pool: &DbPool, actor_id: String) -> Result<Option<String>, LemmyError> {
+  let is_instance_actor = instance_actor_id()?.as_str() == actor_id;
+  let key = blocking(pool, move |conn| {
+    if is_instance_actor {
+      return InstanceActor::read(conn).ok().map(|a| a.private_key);
+    }
+    match User_::read_from_actor_id(conn, &actor_id) {
+      Ok(user) => user.private_key,
+      Err(_) => Community::read_from_actor_id(conn, &actor_id)
+        .ok()
+        .and_then(|c| c.private_key),
+    }
+  })
+  .await?;
+  Ok(key)
+}
+
+pub fn create_activity_queue(client: Client, pool: DbPool) -> QueueHandle {
   // Start the application server. This guards access to to the jobs store
   let queue_handle = create_server(Storage::new());
 
   // Configure and start our workers
-  WorkerConfig::new(|| MyState {
-    client: Client::default(),
+  let worker_queue_handle = queue_handle.clone();
+  WorkerConfig::new(move || MyState {
+    client: client.clone(),
+    pool: pool.clone(),
+    queue_handle: worker_queue_handle.clone(),
   })
   .register::<SendActivityTask>()
   .start(queue_handle.clone());
@@ -127,4 +437,28 @@ pub fn create_activity_queue() -> QueueHandle {
 #[derive(Clone)]
 struct MyState {
   pub client: Client,
+  pub pool: DbPool,
+  pub queue_handle: QueueHandle,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::apub::activity_queue::parse_retry_after;
+
+  #[test]
+  fn test_parse_retry_after() {
+    assert_eq!(
+      Some(chrono::Duration::seconds(120)),
+      parse_retry_after("120")
+    );
+    assert_eq!(
+      Some(chrono::Duration::days(1)),
+      parse_retry_after("10000000000")
+    );
+    assert_eq!(
+      Some(chrono::Duration::zero()),
+      parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT")
+    );
+    assert_eq!(None, parse_retry_after("soon"));
+  }
 }
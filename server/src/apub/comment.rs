@@ -2,15 +2,20 @@ use crate::{
   apub::{
     activities::{generate_activity_id, send_activity_to_community},
     check_actor_domain,
+    check_distinguished,
     create_apub_response,
     create_apub_tombstone_response,
     create_tombstone,
+    extensions::{add_extension::AddExtension, note_extension::NoteExtension},
     fetch_webfinger_url,
     fetcher::{
       get_or_fetch_and_insert_comment,
       get_or_fetch_and_insert_post,
+      get_or_fetch_and_upsert_creator,
       get_or_fetch_and_upsert_user,
     },
+    is_fetch_authorized,
+    post::is_withheld,
     ActorType,
     ApubLikeableType,
     ApubObjectType,
@@ -21,26 +26,18 @@ use crate::{
   LemmyContext,
 };
 use activitystreams::{
-  activity::{
-    kind::{CreateType, DeleteType, DislikeType, LikeType, RemoveType, UndoType, UpdateType},
-    Create,
-    Delete,
-    Dislike,
-    Like,
-    Remove,
-    Undo,
-    Update,
-  },
+  activity::{Add, Create, Delete, Dislike, Like, Remove, Undo, Update},
   base::AnyBase,
   link::Mention,
   object::{kind::NoteType, Note, Tombstone},
   prelude::*,
   public,
 };
-use actix_web::{body::Body, web, web::Path, HttpResponse};
+use activitystreams_ext::UnparsedExtension;
+use actix_web::{body::Body, web, web::Path, HttpRequest, HttpResponse};
 use anyhow::Context;
 use itertools::Itertools;
-use lemmy_api_structs::blocking;
+use lemmy_api_structs::{blocking, limit_mentions};
 use lemmy_db::{
   comment::{Comment, CommentForm},
   community::Community,
@@ -49,8 +46,16 @@ use lemmy_db::{
   Crud,
 };
 use lemmy_utils::{
+  apub::get_apub_protocol_string,
   location_info,
-  utils::{convert_datetime, remove_slurs, scrape_text_for_mentions, MentionData},
+  settings::Settings,
+  utils::{
+    convert_datetime,
+    remove_slurs,
+    scrape_text_for_mentions,
+    truncate_to_length,
+    MentionData,
+  },
   LemmyError,
 };
 use log::debug;
@@ -58,20 +63,41 @@ use serde::Deserialize;
 use serde_json::Error;
 use url::Url;
 
+/// The page of a comment for people rather than for federation. The id of a local comment only
+/// redirects there, so its federated `url` points straight into the comment thread of the post.
+pub fn comment_html_url(comment: &Comment) -> Result<Url, LemmyError> {
+  if comment.local {
+    Ok(Url::parse(&format!(
+      "{}://{}/post/{}/comment/{}",
+      get_apub_protocol_string(),
+      Settings::get().hostname,
+      comment.post_id,
+      comment.id
+    ))?)
+  } else {
+    Ok(Url::parse(&comment.ap_id)?)
+  }
+}
+
 #[derive(Deserialize)]
 pub struct CommentQuery {
-  comment_id: String,
+  pub comment_id: String,
 }
 
 /// Return the post json over HTTP.
 pub async fn get_apub_comment(
+  request: HttpRequest,
   info: Path<CommentQuery>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse<Body>, LemmyError> {
   let id = info.comment_id.parse::<i32>()?;
   let comment = blocking(context.pool(), move |conn| Comment::read(conn, id)).await??;
 
-  if !comment.deleted {
+  let post_id = comment.post_id;
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  if !is_fetch_authorized(&request, post.community_id, &context).await? {
+    Ok(HttpResponse::NotFound().finish())
+  } else if !comment.deleted {
     Ok(create_apub_response(
       &comment.to_apub(context.pool()).await?,
     ))
@@ -110,6 +136,7 @@ impl ToApub for Comment {
       // Not needed when the Post is embedded in a collection (like for community outbox)
       .set_context(activitystreams::context())
       .set_id(Url::parse(&self.ap_id)?)
+      .set_url(comment_html_url(self)?.to_string())
       .set_published(convert_datetime(self.published))
       .set_to(community.actor_id)
       .set_many_in_reply_tos(in_reply_to_vec)
@@ -120,6 +147,11 @@ impl ToApub for Comment {
       comment.set_updated(convert_datetime(u));
     }
 
+    let ext = NoteExtension {
+      distinguished: self.distinguished,
+    };
+    ext.try_into_unparsed(&mut comment)?;
+
     Ok(comment)
   }
 
@@ -144,7 +176,7 @@ impl FromApub for CommentForm {
       .as_single_xsd_any_uri()
       .context(location_info!())?;
 
-    let creator = get_or_fetch_and_upsert_user(creator_actor_id, context).await?;
+    let creator = get_or_fetch_and_upsert_creator(creator_actor_id, context).await?;
 
     let mut in_reply_tos = note
       .in_reply_to()
@@ -177,7 +209,19 @@ impl FromApub for CommentForm {
       .as_single_xsd_string()
       .context(location_info!())?
       .to_string();
-    let content_slurs_removed = remove_slurs(&content);
+    // Remote instances can have higher limits, what doesn't fit here is cut
+    let content_slurs_removed = truncate_to_length(
+      &remove_slurs(&content),
+      Settings::get().limits.max_comment_length,
+    );
+    let ext = NoteExtension::try_from_unparsed(&mut note.to_owned())?;
+    let distinguished = check_distinguished(
+      ext.distinguished,
+      creator.id,
+      post.community_id,
+      context.pool(),
+    )
+    .await?;
 
     Ok(CommentForm {
       creator_id: creator.id,
@@ -191,6 +235,7 @@ impl FromApub for CommentForm {
       deleted: None,
       ap_id: Some(check_actor_domain(note, expected_domain)?),
       local: false,
+      distinguished: Some(distinguished),
     })
   }
 }
@@ -203,6 +248,9 @@ impl ApubObjectType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -210,12 +258,13 @@ impl ApubObjectType for Comment {
     })
     .await??;
 
-    let maa = collect_non_local_mentions_and_addresses(&self.content, &community, context).await?;
+    let maa =
+      collect_non_local_mentions_and_addresses(&self.content, creator, &community, context).await?;
 
     let mut create = Create::new(creator.actor_id.to_owned(), note.into_any_base()?);
     create
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(CreateType::Create)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(maa.addressed_ccs.to_owned())
       // Set the mention tags
@@ -231,6 +280,9 @@ impl ApubObjectType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -238,12 +290,13 @@ impl ApubObjectType for Comment {
     })
     .await??;
 
-    let maa = collect_non_local_mentions_and_addresses(&self.content, &community, context).await?;
+    let maa =
+      collect_non_local_mentions_and_addresses(&self.content, creator, &community, context).await?;
 
     let mut update = Update::new(creator.actor_id.to_owned(), note.into_any_base()?);
     update
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UpdateType::Update)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(maa.addressed_ccs.to_owned())
       // Set the mention tags
@@ -258,6 +311,9 @@ impl ApubObjectType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -268,7 +324,7 @@ impl ApubObjectType for Comment {
     let mut delete = Delete::new(creator.actor_id.to_owned(), note.into_any_base()?);
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -292,6 +348,9 @@ impl ApubObjectType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -303,7 +362,7 @@ impl ApubObjectType for Comment {
     let mut delete = Delete::new(creator.actor_id.to_owned(), note.into_any_base()?);
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -311,7 +370,7 @@ impl ApubObjectType for Comment {
     let mut undo = Undo::new(creator.actor_id.to_owned(), delete.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -326,11 +385,19 @@ impl ApubObjectType for Comment {
     Ok(())
   }
 
-  async fn send_remove(&self, mod_: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+  async fn send_remove(
+    &self,
+    mod_: &User_,
+    reason: Option<String>,
+    context: &LemmyContext,
+  ) -> Result<(), LemmyError> {
     let note = self.to_apub(context.pool()).await?;
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -341,9 +408,12 @@ impl ApubObjectType for Comment {
     let mut remove = Remove::new(mod_.actor_id.to_owned(), note.into_any_base()?);
     remove
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(RemoveType::Remove)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
+    if let Some(reason) = reason {
+      remove.set_summary(reason);
+    }
 
     send_activity_to_community(
       &mod_,
@@ -361,6 +431,9 @@ impl ApubObjectType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -372,7 +445,7 @@ impl ApubObjectType for Comment {
     let mut remove = Remove::new(mod_.actor_id.to_owned(), note.into_any_base()?);
     remove
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(RemoveType::Remove)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -380,7 +453,7 @@ impl ApubObjectType for Comment {
     let mut undo = Undo::new(mod_.actor_id.to_owned(), remove.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -396,6 +469,99 @@ impl ApubObjectType for Comment {
   }
 }
 
+/// ActivityPub has no pinning, so it is sent as adding the comment to its post, which is the target.
+fn create_pin_activity(
+  note: Note,
+  post: &Post,
+  mod_: &User_,
+  community: &Community,
+) -> Result<Add, LemmyError> {
+  let mut add = Add::new(mod_.actor_id.to_owned(), note.into_any_base()?);
+  add
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(public())
+    .set_many_ccs(vec![community.get_followers_url()?]);
+  let ext = AddExtension {
+    target: Some(Url::parse(&post.ap_id)?),
+  };
+  ext.try_into_unparsed(&mut add)?;
+  Ok(add)
+}
+
+/// Send out that a comment was pinned to the top of its post.
+pub async fn send_pin_comment(
+  comment: &Comment,
+  mod_: &User_,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let note = comment.to_apub(context.pool()).await?;
+
+  let post_id = comment.post_id;
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  if is_withheld(&post, context.pool()).await? {
+    return Ok(());
+  }
+
+  let community_id = post.community_id;
+  let community = blocking(context.pool(), move |conn| {
+    Community::read(conn, community_id)
+  })
+  .await??;
+
+  let add = create_pin_activity(note, &post, mod_, &community)?;
+
+  send_activity_to_community(
+    &mod_,
+    &community,
+    vec![community.get_shared_inbox_url()?],
+    add,
+    context,
+  )
+  .await?;
+  Ok(())
+}
+
+/// Send out that a comment is not pinned anymore, by undoing the add.
+pub async fn send_undo_pin_comment(
+  comment: &Comment,
+  mod_: &User_,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let note = comment.to_apub(context.pool()).await?;
+
+  let post_id = comment.post_id;
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  if is_withheld(&post, context.pool()).await? {
+    return Ok(());
+  }
+
+  let community_id = post.community_id;
+  let community = blocking(context.pool(), move |conn| {
+    Community::read(conn, community_id)
+  })
+  .await??;
+
+  let add = create_pin_activity(note, &post, mod_, &community)?;
+
+  let mut undo = Undo::new(mod_.actor_id.to_owned(), add.into_any_base()?);
+  undo
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(public())
+    .set_many_ccs(vec![community.get_followers_url()?]);
+
+  send_activity_to_community(
+    &mod_,
+    &community,
+    vec![community.get_shared_inbox_url()?],
+    undo,
+    context,
+  )
+  .await?;
+  Ok(())
+}
+
 #[async_trait::async_trait(?Send)]
 impl ApubLikeableType for Comment {
   async fn send_like(&self, creator: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
@@ -403,6 +569,9 @@ impl ApubLikeableType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -413,7 +582,7 @@ impl ApubLikeableType for Comment {
     let mut like = Like::new(creator.actor_id.to_owned(), note.into_any_base()?);
     like
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(LikeType::Like)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -433,6 +602,9 @@ impl ApubLikeableType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -443,7 +615,7 @@ impl ApubLikeableType for Comment {
     let mut dislike = Dislike::new(creator.actor_id.to_owned(), note.into_any_base()?);
     dislike
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DislikeType::Dislike)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -467,6 +639,9 @@ impl ApubLikeableType for Comment {
 
     let post_id = self.post_id;
     let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+    if is_withheld(&post, context.pool()).await? {
+      return Ok(());
+    }
 
     let community_id = post.community_id;
     let community = blocking(context.pool(), move |conn| {
@@ -477,7 +652,7 @@ impl ApubLikeableType for Comment {
     let mut like = Like::new(creator.actor_id.to_owned(), note.into_any_base()?);
     like
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DislikeType::Dislike)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -485,7 +660,7 @@ impl ApubLikeableType for Comment {
     let mut undo = Undo::new(creator.actor_id.to_owned(), like.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -520,8 +695,10 @@ impl MentionsAndAddresses {
 /// This takes a comment, and builds a list of to_addresses, inboxes,
 /// and mention tags, so they know where to be sent to.
 /// Addresses are the users / addresses that go in the cc field.
+/// Remote users count against the same mention limit as local ones.
 async fn collect_non_local_mentions_and_addresses(
   content: &str,
+  creator: &User_,
   community: &Community,
   context: &LemmyContext,
 ) -> Result<MentionsAndAddresses, LemmyError> {
@@ -531,7 +708,15 @@ async fn collect_non_local_mentions_and_addresses(
   let mut tags = Vec::new();
 
   // Get the inboxes for any mentions
-  let mentions = scrape_text_for_mentions(&content)
+  let mentions = scrape_text_for_mentions(&content);
+  let creator = creator.to_owned();
+  let community_id = community.id;
+  let allowed = blocking(context.pool(), move |conn| {
+    limit_mentions(conn, mentions, &creator, community_id)
+  })
+  .await?;
+  let mentions = allowed
+    .mentions
     .into_iter()
     // Filter only the non-local ones
     .filter(|m| !m.is_local())
@@ -1,49 +1,46 @@
 use crate::{
   apub::{
-    activities::generate_activity_id,
+    activities::{generate_activity_id, is_vote, InboxCapabilities},
     activity_queue::send_activity,
     check_actor_domain,
     create_apub_response,
     create_apub_tombstone_response,
+    create_cached_apub_response,
+    create_outbox_response,
     create_tombstone,
-    extensions::group_extensions::GroupExtension,
+    extensions::{add_extension::AddExtension, group_extensions::GroupExtension},
+    fetch_signer,
     fetcher::{get_or_fetch_and_upsert_actor, get_or_fetch_and_upsert_user},
     insert_activity,
+    is_fetch_authorized,
+    post::is_community_withheld,
     ActorType,
     FromApub,
     GroupExt,
+    OutboxQuery,
     ToApub,
   },
   DbPool,
   LemmyContext,
 };
 use activitystreams::{
-  activity::{
-    kind::{AcceptType, AnnounceType, DeleteType, LikeType, RemoveType, UndoType},
-    Accept,
-    Announce,
-    Delete,
-    Follow,
-    Remove,
-    Undo,
-  },
+  activity::{Accept, Add, Announce, Create, Delete, Follow, Reject, Remove, Undo, Update},
   actor::{kind::GroupType, ApActor, Endpoints, Group},
   base::{AnyBase, BaseExt},
-  collection::{OrderedCollection, UnorderedCollection},
+  collection::{OrderedCollection, OrderedCollectionPage},
   object::{Image, Tombstone},
   prelude::*,
   public,
 };
-use activitystreams_ext::Ext2;
-use actix_web::{body::Body, web, HttpResponse};
+use activitystreams_ext::{Ext2, UnparsedExtension};
+use actix_web::{body::Body, web, HttpRequest, HttpResponse};
 use anyhow::Context;
 use itertools::Itertools;
 use lemmy_api_structs::blocking;
 use lemmy_db::{
-  community::{Community, CommunityForm},
+  community::{Community, CommunityFollower, CommunityForm, CommunitySettings},
   community_view::{CommunityFollowerView, CommunityModeratorView},
   naive_now,
-  post::Post,
   user::User_,
 };
 use lemmy_utils::{
@@ -53,7 +50,8 @@ use lemmy_utils::{
   LemmyError,
 };
 use serde::Deserialize;
-use url::Url;
+use serde_json::Value;
+use url::{Position, Url};
 
 #[derive(Deserialize)]
 pub struct CommunityQuery {
@@ -108,10 +106,12 @@ impl ToApub for Community {
 
     let nsfw = self.nsfw;
     let category_id = self.category_id;
-    let group_extension = blocking(pool, move |conn| {
-      GroupExtension::new(conn, category_id, nsfw)
+    let community_id = self.id;
+    let mut group_extension = blocking(pool, move |conn| {
+      GroupExtension::new(conn, category_id, nsfw, community_id)
     })
     .await??;
+    group_extension.moderators = Some(get_moderators_url(self)?);
 
     Ok(Ext2::new(
       ap_actor,
@@ -154,12 +154,12 @@ impl ActorType for Community {
     let to = actor.get_inbox_url()?;
     accept
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(AcceptType::Accept)?)
+      .set_id(generate_activity_id()?)
       .set_to(to.clone());
 
     insert_activity(self.creator_id, accept.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), accept, self, vec![to])?;
+    send_activity(context, accept, self, vec![to]).await?;
     Ok(())
   }
 
@@ -169,7 +169,7 @@ impl ActorType for Community {
     let mut delete = Delete::new(creator.actor_id.to_owned(), group.into_any_base()?);
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![self.get_followers_url()?]);
 
@@ -180,7 +180,7 @@ impl ActorType for Community {
     // Note: For an accept, since it was automatic, no one pushed a button,
     // the community was the actor.
     // But for delete, the creator is the actor, and does the signing
-    send_activity(context.activity_queue(), delete, creator, inboxes)?;
+    send_activity(context, delete, creator, inboxes).await?;
     Ok(())
   }
 
@@ -194,14 +194,14 @@ impl ActorType for Community {
     let mut delete = Delete::new(creator.actor_id.to_owned(), group.into_any_base()?);
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![self.get_followers_url()?]);
 
     let mut undo = Undo::new(creator.actor_id.to_owned(), delete.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![self.get_followers_url()?]);
 
@@ -212,7 +212,7 @@ impl ActorType for Community {
     // Note: For an accept, since it was automatic, no one pushed a button,
     // the community was the actor.
     // But for delete, the creator is the actor, and does the signing
-    send_activity(context.activity_queue(), undo, creator, inboxes)?;
+    send_activity(context, undo, creator, inboxes).await?;
     Ok(())
   }
 
@@ -222,7 +222,7 @@ impl ActorType for Community {
     let mut remove = Remove::new(mod_.actor_id.to_owned(), group.into_any_base()?);
     remove
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(RemoveType::Remove)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![self.get_followers_url()?]);
 
@@ -233,7 +233,7 @@ impl ActorType for Community {
     // Note: For an accept, since it was automatic, no one pushed a button,
     // the community was the actor.
     // But for delete, the creator is the actor, and does the signing
-    send_activity(context.activity_queue(), remove, mod_, inboxes)?;
+    send_activity(context, remove, mod_, inboxes).await?;
     Ok(())
   }
 
@@ -243,7 +243,7 @@ impl ActorType for Community {
     let mut remove = Remove::new(mod_.actor_id.to_owned(), group.into_any_base()?);
     remove
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(RemoveType::Remove)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![self.get_followers_url()?]);
 
@@ -251,7 +251,7 @@ impl ActorType for Community {
     let mut undo = Undo::new(mod_.actor_id.to_owned(), remove.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(LikeType::Like)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![self.get_followers_url()?]);
 
@@ -262,7 +262,7 @@ impl ActorType for Community {
     // Note: For an accept, since it was automatic, no one pushed a button,
     // the community was the actor.
     // But for remove , the creator is the actor, and does the signing
-    send_activity(context.activity_queue(), undo, mod_, inboxes)?;
+    send_activity(context, undo, mod_, inboxes).await?;
     Ok(())
   }
 
@@ -273,6 +273,15 @@ impl ActorType for Community {
   async fn get_follower_inboxes(&self, pool: &DbPool) -> Result<Vec<Url>, LemmyError> {
     let id = self.id;
 
+    // Local only communities don't send anything to other instances
+    let settings = blocking(pool, move |conn| {
+      CommunitySettings::read_for_community(conn, id)
+    })
+    .await??;
+    if settings.local_only {
+      return Ok(Vec::new());
+    }
+
     let inboxes = blocking(pool, move |conn| {
       CommunityFollowerView::for_community(conn, id)
     })
@@ -422,6 +431,11 @@ pub async fn get_apub_community_http(
   info: web::Path<CommunityQuery>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse<Body>, LemmyError> {
+  let cache_key = format!("/c/{}", info.community_name);
+  if let Some(res) = context.response_cache().get(&cache_key) {
+    return Ok(res);
+  }
+
   let community = blocking(context.pool(), move |conn| {
     Community::read_from_name(conn, &info.community_name)
   })
@@ -430,37 +444,115 @@ pub async fn get_apub_community_http(
   if !community.deleted {
     let apub = community.to_apub(context.pool()).await?;
 
-    Ok(create_apub_response(&apub))
+    create_cached_apub_response(&apub, cache_key, Some(community.id), &context)
   } else {
     Ok(create_apub_tombstone_response(&community.to_tombstone()?))
   }
 }
 
-/// Returns an empty followers collection, only populating the size (for privacy).
+/// Followers on each page of a followers collection.
+const FOLLOWERS_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct FollowersQuery {
+  page: Option<bool>,
+  /// The id of the last follow on the previous page
+  before: Option<i32>,
+}
+
+/// The followers collection of a local community. Without `page`, it is the public collection with
+/// the number of followers and a link to the first page. The pages list the actor ids of the
+/// followers, newest first, so that other instances can check which of their users follow the
+/// community, see `run_follower_sync_job`. They have to be fetched with a signature, and only list
+/// the followers from the instance of the signer. Older versions asked for them with an `instance`
+/// parameter, which is kept in the page links.
 pub async fn get_apub_community_followers(
+  request: HttpRequest,
   info: web::Path<CommunityQuery>,
+  query: web::Query<FollowersQuery>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse<Body>, LemmyError> {
   let community = blocking(context.pool(), move |conn| {
     Community::read_from_name(&conn, &info.community_name)
   })
   .await??;
-
+  let followers_url = community.get_followers_url()?;
   let community_id = community.id;
-  let community_followers = blocking(context.pool(), move |conn| {
-    CommunityFollowerView::for_community(&conn, community_id)
+
+  if !query.page.unwrap_or(false) {
+    let community_followers = blocking(context.pool(), move |conn| {
+      CommunityFollowerView::for_community(&conn, community_id)
+    })
+    .await??;
+    let mut collection = OrderedCollection::new();
+    collection
+      .set_context(activitystreams::context())
+      .set_id(followers_url.to_owned())
+      .set_total_items(community_followers.len() as u64)
+      .set_first(Url::parse(&format!("{}?page=true", followers_url))?);
+    return Ok(create_apub_response(&collection));
+  }
+
+  let signer = match fetch_signer(&request, &context).await? {
+    Some(signer) => signer,
+    None => return Ok(HttpResponse::Unauthorized().finish()),
+  };
+  let before = query.before;
+  let instance = signer[Position::BeforeHost..Position::AfterPort].to_lowercase();
+  let instance_param = instance.to_owned();
+  let followers = blocking(context.pool(), move |conn| {
+    CommunityFollower::list_actor_ids(
+      conn,
+      community_id,
+      Some(&instance_param),
+      before,
+      FOLLOWERS_PAGE_SIZE,
+    )
   })
   .await??;
 
-  let mut collection = UnorderedCollection::new();
-  collection
+  let page_url = |before: Option<i32>| -> Url {
+    let mut url = followers_url.to_owned();
+    url.query_pairs_mut().append_pair("page", "true");
+    if let Some(before) = before {
+      url
+        .query_pairs_mut()
+        .append_pair("before", &before.to_string());
+    }
+    url.query_pairs_mut().append_pair("instance", &instance);
+    url
+  };
+  // A full page may be followed by more
+  let next = match followers.last() {
+    Some((last, _)) if followers.len() as i64 == FOLLOWERS_PAGE_SIZE => Some(page_url(Some(*last))),
+    _ => None,
+  };
+  let items = followers
+    .into_iter()
+    .map(|(_, actor_id)| Url::parse(&actor_id))
+    .collect::<Result<Vec<Url>, _>>()?;
+
+  let mut page = OrderedCollectionPage::new();
+  page
     .set_context(activitystreams::context())
-    .set_id(community.get_followers_url()?)
-    .set_total_items(community_followers.len() as u64);
-  Ok(create_apub_response(&collection))
+    .set_id(page_url(before))
+    .set_part_of(followers_url.to_owned())
+    .set_many_items(items);
+  if let Some(next) = next {
+    page.set_next(next);
+  }
+  Ok(create_apub_response(&page))
 }
 
-pub async fn get_apub_community_outbox(
+pub fn get_moderators_url(community: &Community) -> Result<Url, LemmyError> {
+  Ok(Url::parse(&format!("{}/moderators", community.actor_id))?)
+}
+
+/// The moderators collection of a local community, with the actor ids of its mods, the creator
+/// first. Other instances follow the changes to it through Add and Remove activities, see
+/// `send_add_mod`. Like outboxes, the one of a private community is only served to its members.
+pub async fn get_apub_community_moderators(
+  request: HttpRequest,
   info: web::Path<CommunityQuery>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse<Body>, LemmyError> {
@@ -468,38 +560,72 @@ pub async fn get_apub_community_outbox(
     Community::read_from_name(&conn, &info.community_name)
   })
   .await??;
-
   let community_id = community.id;
-  let posts = blocking(context.pool(), move |conn| {
-    Post::list_for_community(conn, community_id)
+  if !is_fetch_authorized(&request, community_id, &context).await? {
+    return Ok(HttpResponse::NotFound().finish());
+  }
+
+  let moderators = blocking(context.pool(), move |conn| {
+    CommunityModeratorView::for_community(&conn, community_id)
   })
   .await??;
+  let moderators = moderators
+    .into_iter()
+    .map(|m| Url::parse(&m.user_actor_id))
+    .collect::<Result<Vec<Url>, _>>()?;
 
-  let mut pages: Vec<AnyBase> = vec![];
-  for p in posts {
-    pages.push(p.to_apub(context.pool()).await?.into_any_base()?);
-  }
-
-  let len = pages.len();
   let mut collection = OrderedCollection::new();
   collection
-    .set_many_items(pages)
     .set_context(activitystreams::context())
-    .set_id(community.get_outbox_url()?)
-    .set_total_items(len as u64);
+    .set_id(get_moderators_url(&community)?)
+    .set_total_items(moderators.len() as u64)
+    .set_many_items(moderators);
   Ok(create_apub_response(&collection))
 }
 
+/// The activities of the community, mostly announcements of what its users posted.
+pub async fn get_apub_community_outbox(
+  request: HttpRequest,
+  info: web::Path<CommunityQuery>,
+  query: web::Query<OutboxQuery>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse<Body>, LemmyError> {
+  let community = blocking(context.pool(), move |conn| {
+    Community::read_from_name(&conn, &info.community_name)
+  })
+  .await??;
+
+  let community_id = community.id;
+  if !is_fetch_authorized(&request, community_id, &context).await? {
+    return Ok(HttpResponse::NotFound().finish());
+  }
+
+  create_outbox_response(&community, query.into_inner(), context.pool()).await
+}
+
 pub async fn do_announce(
   activity: AnyBase,
   community: &Community,
   sender: &User_,
   context: &LemmyContext,
 ) -> Result<(), LemmyError> {
+  // Announces are public, so the content of private and local only communities isn't announced
+  if is_community_withheld(community.id, context.pool()).await? {
+    return Ok(());
+  }
+
+  let vote = is_vote(&activity)?;
+  let created_object = if activity.kind_str() == Some("Create") {
+    let create = Create::from_any_base(activity.to_owned())?.context(location_info!())?;
+    Some(create.object().to_owned())
+  } else {
+    None
+  };
+
   let mut announce = Announce::new(community.actor_id.to_owned(), activity);
   announce
     .set_context(activitystreams::context())
-    .set_id(generate_activity_id(AnnounceType::Announce)?)
+    .set_id(generate_activity_id()?)
     .set_to(public())
     .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -515,7 +641,137 @@ pub async fn do_announce(
   let community_shared_inbox = community.get_shared_inbox_url()?;
   to.retain(|x| x != &community_shared_inbox);
 
-  send_activity(context.activity_queue(), announce, community, to)?;
+  // Votes only go to the instances which understand them. Instances without groups don't get any
+  // votes, and get new posts and comments boosted the way microblogs share them.
+  let capabilities = InboxCapabilities::load(&to, context.pool()).await?;
+  if vote {
+    to.retain(|i| capabilities.supports_votes(i));
+  }
+  let (mut to, microblog_inboxes): (Vec<Url>, Vec<Url>) = to
+    .into_iter()
+    .partition(|i| capabilities.supports_groups(i));
+  if let Some(object) = created_object {
+    if !microblog_inboxes.is_empty() {
+      let mut boost = Announce::new(community.actor_id.to_owned(), object);
+      boost
+        .set_context(activitystreams::context())
+        .set_id(generate_activity_id()?)
+        .set_to(public())
+        .set_many_ccs(vec![community.get_followers_url()?]);
+      send_activity(context, boost, community, microblog_inboxes).await?;
+    }
+  } else if !vote {
+    to.extend(microblog_inboxes);
+  }
+
+  send_activity(context, announce, community, to).await?;
+
+  Ok(())
+}
+
+/// The Follow of a remote user who asked to join a local community, as it was received, to echo it
+/// in the Accept or Reject. Join requests from before the Follow was kept get a new one.
+pub fn join_request_follow(
+  follow: Option<Value>,
+  community: &Community,
+  applicant: &User_,
+) -> Result<Follow, LemmyError> {
+  match follow {
+    Some(follow) => {
+      let follow = serde_json::from_value::<AnyBase>(follow)?;
+      Ok(Follow::from_any_base(follow)?.context(location_info!())?)
+    }
+    None => Ok(Follow::new(
+      applicant.actor_id.to_owned(),
+      community.actor_id.to_owned(),
+    )),
+  }
+}
+
+/// As a local community which requires approval to join, or which is local only, reject the follow
+/// of a remote user.
+pub async fn send_reject_join_request(
+  community: &Community,
+  applicant: &User_,
+  follow: Follow,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let mut reject = Reject::new(community.actor_id.to_owned(), follow.into_any_base()?);
+  let to = applicant.get_inbox_url()?;
+  reject
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(to.clone());
+
+  insert_activity(community.creator_id, reject.clone(), true, context.pool()).await?;
+
+  send_activity(context, reject, community, vec![to]).await?;
+  Ok(())
+}
+
+/// As a local community, send out its current profile to its followers, eg after its key changed.
+pub async fn send_update_community(
+  community: &Community,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let group = community.to_apub(context.pool()).await?;
+  let mut update = Update::new(community.actor_id.to_owned(), group.into_any_base()?);
+  update
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(public())
+    .set_many_ccs(vec![community.get_followers_url()?]);
+
+  insert_activity(community.creator_id, update.clone(), true, context.pool()).await?;
+
+  let inboxes = community.get_follower_inboxes(context.pool()).await?;
+  send_activity(context, update, community, inboxes).await?;
+  Ok(())
+}
+
+/// As a mod of a local community, send out that a user was added to its moderators collection.
+pub async fn send_add_mod(
+  community: &Community,
+  mod_: &User_,
+  added_mod: &User_,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let mut add = Add::new(mod_.actor_id.to_owned(), added_mod.actor_id()?);
+  add
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(public())
+    .set_many_ccs(vec![community.get_followers_url()?]);
+  let ext = AddExtension {
+    target: Some(get_moderators_url(community)?),
+  };
+  ext.try_into_unparsed(&mut add)?;
+
+  insert_activity(mod_.id, add.clone(), true, context.pool()).await?;
+
+  let inboxes = community.get_follower_inboxes(context.pool()).await?;
+  send_activity(context, add, mod_, inboxes).await?;
+  Ok(())
+}
+
+/// As a mod of a local community, send out that a user was removed from its moderators collection.
+pub async fn send_remove_mod(
+  community: &Community,
+  mod_: &User_,
+  removed_mod: &User_,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let mut remove = Remove::new(mod_.actor_id.to_owned(), removed_mod.actor_id()?);
+  remove
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_target(get_moderators_url(community)?)
+    .set_to(public())
+    .set_many_ccs(vec![community.get_followers_url()?]);
+
+  insert_activity(mod_.id, remove.clone(), true, context.pool()).await?;
 
+  let inboxes = community.get_follower_inboxes(context.pool()).await?;
+  send_activity(context, remove, mod_, inboxes).await?;
   Ok(())
 }
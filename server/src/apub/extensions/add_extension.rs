@@ -0,0 +1,29 @@
+use activitystreams::unparsed::UnparsedMutExt;
+use activitystreams_ext::UnparsedExtension;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The target of an Add, like the post which a comment is pinned to. This version of
+/// activitystreams has no target on Add, so it's read from and written to the unparsed fields.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AddExtension {
+  pub target: Option<Url>,
+}
+
+impl<U> UnparsedExtension<U> for AddExtension
+where
+  U: UnparsedMutExt,
+{
+  type Error = serde_json::Error;
+
+  fn try_from_unparsed(unparsed_mut: &mut U) -> Result<Self, Self::Error> {
+    Ok(AddExtension {
+      target: unparsed_mut.remove("target")?,
+    })
+  }
+
+  fn try_into_unparsed(self, unparsed_mut: &mut U) -> Result<(), Self::Error> {
+    unparsed_mut.insert("target", self.target)?;
+    Ok(())
+  }
+}
@@ -1,15 +1,29 @@
 use activitystreams::unparsed::UnparsedMutExt;
 use activitystreams_ext::UnparsedExtension;
 use diesel::PgConnection;
-use lemmy_db::{category::Category, Crud};
-use lemmy_utils::LemmyError;
+use lemmy_db::{
+  category::Category,
+  community::{CommunityAppearance, CommunitySettings},
+  Crud,
+  SortType,
+};
+use lemmy_utils::{settings::Settings, utils::is_valid_hex_color, LemmyError};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use url::Url;
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GroupExtension {
   pub category: GroupCategory,
   pub sensitive: bool,
+  pub accent_color: Option<String>,
+  pub default_sort: Option<String>,
+  /// The moderators collection, which only newer instances have
+  pub moderators: Option<Url>,
+  /// Search opt-out flags, like the ones of persons
+  pub discoverable: Option<bool>,
+  pub indexable: Option<bool>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -25,17 +39,45 @@ impl GroupExtension {
     conn: &PgConnection,
     category_id: i32,
     sensitive: bool,
+    community_id: i32,
   ) -> Result<GroupExtension, LemmyError> {
     let category = Category::read(conn, category_id)?;
     let group_category = GroupCategory {
       identifier: category_id.to_string(),
       name: category.name,
     };
+    let appearance = CommunityAppearance::read_for_community(conn, community_id)?;
+    let settings = CommunitySettings::read_for_community(conn, community_id)?;
+    let discoverable = settings.discoverable && Settings::get().federation.discoverable;
     Ok(GroupExtension {
       category: group_category,
       sensitive,
+      accent_color: appearance.accent_color,
+      default_sort: appearance.default_sort,
+      moderators: None,
+      discoverable: Some(discoverable),
+      indexable: Some(discoverable),
     })
   }
+
+  pub fn is_discoverable(&self) -> bool {
+    self.discoverable.unwrap_or(true) && self.indexable.unwrap_or(true)
+  }
+
+  /// What another instance sent is dropped if it couldn't be shown anyway.
+  pub fn valid_accent_color(&self) -> Option<String> {
+    self
+      .accent_color
+      .to_owned()
+      .filter(|c| is_valid_hex_color(c))
+  }
+
+  pub fn valid_default_sort(&self) -> Option<String> {
+    self
+      .default_sort
+      .to_owned()
+      .filter(|s| SortType::from_str(s).is_ok())
+  }
 }
 
 impl<U> UnparsedExtension<U> for GroupExtension
@@ -48,12 +90,29 @@ where
     Ok(GroupExtension {
       category: unparsed_mut.remove("category")?,
       sensitive: unparsed_mut.remove("sensitive")?,
+      // Older instances don't send these
+      accent_color: unparsed_mut.remove("accentColor")?,
+      default_sort: unparsed_mut.remove("defaultSort")?,
+      moderators: unparsed_mut.remove("moderators")?,
+      discoverable: unparsed_mut.remove("discoverable")?,
+      indexable: unparsed_mut.remove("indexable")?,
     })
   }
 
   fn try_into_unparsed(self, unparsed_mut: &mut U) -> Result<(), Self::Error> {
     unparsed_mut.insert("category", self.category)?;
     unparsed_mut.insert("sensitive", self.sensitive)?;
+    if let Some(accent_color) = self.accent_color {
+      unparsed_mut.insert("accentColor", accent_color)?;
+    }
+    if let Some(default_sort) = self.default_sort {
+      unparsed_mut.insert("defaultSort", default_sort)?;
+    }
+    if let Some(moderators) = self.moderators {
+      unparsed_mut.insert("moderators", moderators)?;
+    }
+    unparsed_mut.insert("discoverable", self.discoverable)?;
+    unparsed_mut.insert("indexable", self.indexable)?;
     Ok(())
   }
 }
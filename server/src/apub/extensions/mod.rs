@@ -1,3 +1,6 @@
+pub mod add_extension;
 pub mod group_extensions;
+pub mod note_extension;
 pub mod page_extension;
+pub mod person_extension;
 pub mod signatures;
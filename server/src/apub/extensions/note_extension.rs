@@ -0,0 +1,31 @@
+use activitystreams::unparsed::UnparsedMutExt;
+use activitystreams_ext::UnparsedExtension;
+use serde::{Deserialize, Serialize};
+
+/// Fields of a comment which don't have an ActivityStreams equivalent. Comments are sent as plain
+/// notes, so this is read from and written to their unparsed fields directly.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteExtension {
+  pub distinguished: bool,
+}
+
+impl<U> UnparsedExtension<U> for NoteExtension
+where
+  U: UnparsedMutExt,
+{
+  type Error = serde_json::Error;
+
+  fn try_from_unparsed(unparsed_mut: &mut U) -> Result<Self, Self::Error> {
+    Ok(NoteExtension {
+      distinguished: unparsed_mut
+        .remove::<Option<bool>>("distinguished")?
+        .unwrap_or(false),
+    })
+  }
+
+  fn try_into_unparsed(self, unparsed_mut: &mut U) -> Result<(), Self::Error> {
+    unparsed_mut.insert("distinguished", self.distinguished)?;
+    Ok(())
+  }
+}
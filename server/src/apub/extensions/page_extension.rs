@@ -8,6 +8,10 @@ pub struct PageExtension {
   pub comments_enabled: bool,
   pub sensitive: bool,
   pub stickied: bool,
+  #[serde(default)]
+  pub distinguished: bool,
+  #[serde(default)]
+  pub comments_closed: bool,
 }
 
 impl<U> UnparsedExtension<U> for PageExtension
@@ -21,6 +25,13 @@ where
       comments_enabled: unparsed_mut.remove("commentsEnabled")?,
       sensitive: unparsed_mut.remove("sensitive")?,
       stickied: unparsed_mut.remove("stickied")?,
+      // Older instances don't send this
+      distinguished: unparsed_mut
+        .remove::<Option<bool>>("distinguished")?
+        .unwrap_or(false),
+      comments_closed: unparsed_mut
+        .remove::<Option<bool>>("commentsClosed")?
+        .unwrap_or(false),
     })
   }
 
@@ -28,6 +39,8 @@ where
     unparsed_mut.insert("commentsEnabled", self.comments_enabled)?;
     unparsed_mut.insert("sensitive", self.sensitive)?;
     unparsed_mut.insert("stickied", self.stickied)?;
+    unparsed_mut.insert("distinguished", self.distinguished)?;
+    unparsed_mut.insert("commentsClosed", self.comments_closed)?;
     Ok(())
   }
 }
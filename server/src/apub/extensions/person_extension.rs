@@ -0,0 +1,45 @@
+use activitystreams::unparsed::UnparsedMutExt;
+use activitystreams_ext::UnparsedExtension;
+use serde::{Deserialize, Serialize};
+
+/// Search opt-out flags, as used by Mastodon. Both are optional since most other software
+/// doesn't send them, in which case the actor counts as discoverable.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonExtension {
+  pub discoverable: Option<bool>,
+  pub indexable: Option<bool>,
+}
+
+impl PersonExtension {
+  pub fn new(discoverable: bool) -> PersonExtension {
+    PersonExtension {
+      discoverable: Some(discoverable),
+      indexable: Some(discoverable),
+    }
+  }
+
+  pub fn is_discoverable(&self) -> bool {
+    self.discoverable.unwrap_or(true) && self.indexable.unwrap_or(true)
+  }
+}
+
+impl<U> UnparsedExtension<U> for PersonExtension
+where
+  U: UnparsedMutExt,
+{
+  type Error = serde_json::Error;
+
+  fn try_from_unparsed(unparsed_mut: &mut U) -> Result<Self, Self::Error> {
+    Ok(PersonExtension {
+      discoverable: unparsed_mut.remove("discoverable")?,
+      indexable: unparsed_mut.remove("indexable")?,
+    })
+  }
+
+  fn try_into_unparsed(self, unparsed_mut: &mut U) -> Result<(), Self::Error> {
+    unparsed_mut.insert("discoverable", self.discoverable)?;
+    unparsed_mut.insert("indexable", self.indexable)?;
+    Ok(())
+  }
+}
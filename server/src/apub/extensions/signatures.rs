@@ -1,12 +1,16 @@
-use crate::apub::ActorType;
+use crate::{
+  apub::{fetcher::refetch_actor, ActorType},
+  LemmyContext,
+};
 use activitystreams::unparsed::UnparsedMutExt;
 use activitystreams_ext::UnparsedExtension;
-use actix_web::{client::ClientRequest, HttpRequest};
-use anyhow::{anyhow, Context};
-use http_signature_normalization_actix::{
-  digest::{DigestClient, SignExt},
-  Config,
+use actix_web::{
+  http::{HeaderMap, Method, Uri},
+  HttpRequest,
 };
+use anyhow::{anyhow, Context};
+use http_signature_normalization_actix::Config as ConfigActix;
+use http_signature_normalization_reqwest::prelude::{Config, Sign};
 use lemmy_utils::{location_info, LemmyError};
 use log::debug;
 use openssl::{
@@ -14,49 +18,153 @@ use openssl::{
   pkey::PKey,
   sign::{Signer, Verifier},
 };
+use reqwest::{Request, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::{
+  collections::HashMap,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
 use url::Url;
 
+/// An actor whose signature didn't match its key is refetched at most once in this time, so that
+/// requests with bad signatures can't make us fetch it over and over.
+const KEY_REFETCH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 lazy_static! {
   static ref HTTP_SIG_CONFIG: Config = Config::new();
+  static ref HTTP_SIG_CONFIG_ACTIX: ConfigActix = ConfigActix::new();
+  static ref KEY_REFETCHES: Mutex<HashMap<Url, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// The parts of an incoming request which are signed. Unlike the request itself, they can be
+/// kept while the activity is handled.
+#[derive(Clone)]
+pub struct SignedRequest {
+  method: Method,
+  uri: Uri,
+  headers: HeaderMap,
+}
+
+impl SignedRequest {
+  pub fn new(request: &HttpRequest) -> Self {
+    SignedRequest {
+      method: request.method().to_owned(),
+      uri: request.uri().to_owned(),
+      headers: request.headers().to_owned(),
+    }
+  }
 }
 
-/// Signs request headers with the given keypair.
-pub async fn sign(
-  request: ClientRequest,
+/// Signs request headers with the given keypair. The request isn't sent, signature_with_digest
+/// would send it right away, so the digest is added here.
+pub fn sign(
+  request: RequestBuilder,
   activity: String,
   actor_id: &Url,
   private_key: String,
-) -> Result<DigestClient<String>, LemmyError> {
+) -> Result<Request, LemmyError> {
+  let signing_key_id = format!("{}#main-key", actor_id);
+  let digest = base64::encode(Sha256::digest(activity.as_bytes()));
+
+  let signed_request = request
+    .header("Digest", format!("SHA-256={}", digest))
+    .signature(&HTTP_SIG_CONFIG, signing_key_id, move |signing_string| {
+      let private_key = PKey::private_key_from_pem(private_key.as_bytes())?;
+      let mut signer = Signer::new(MessageDigest::sha256(), &private_key)?;
+      signer.update(signing_string.as_bytes())?;
+
+      Ok(base64::encode(signer.sign_to_vec()?)) as Result<_, LemmyError>
+    })?
+    .body(activity)
+    .build()?;
+
+  Ok(signed_request)
+}
+
+/// Signs the headers of a GET request, which has no body to digest.
+pub fn sign_get(
+  request: RequestBuilder,
+  actor_id: &Url,
+  private_key: String,
+) -> Result<Request, LemmyError> {
   let signing_key_id = format!("{}#main-key", actor_id);
 
-  let digest_client = request
-    .signature_with_digest(
-      HTTP_SIG_CONFIG.clone(),
-      signing_key_id,
-      Sha256::new(),
-      activity,
-      move |signing_string| {
-        let private_key = PKey::private_key_from_pem(private_key.as_bytes())?;
-        let mut signer = Signer::new(MessageDigest::sha256(), &private_key)?;
-        signer.update(signing_string.as_bytes())?;
-
-        Ok(base64::encode(signer.sign_to_vec()?)) as Result<_, LemmyError>
-      },
-    )
-    .await?;
-
-  Ok(digest_client)
+  let signed_request = request
+    .signature(&HTTP_SIG_CONFIG, signing_key_id, move |signing_string| {
+      let private_key = PKey::private_key_from_pem(private_key.as_bytes())?;
+      let mut signer = Signer::new(MessageDigest::sha256(), &private_key)?;
+      signer.update(signing_string.as_bytes())?;
+
+      Ok(base64::encode(signer.sign_to_vec()?)) as Result<_, LemmyError>
+    })?
+    .build()?;
+
+  Ok(signed_request)
+}
+
+/// The actor who signed the request, going by the key id of its signature. It still has to be
+/// verified with that actor's public key.
+pub fn signing_actor_id(request: &HttpRequest) -> Result<Url, LemmyError> {
+  let unverified = HTTP_SIG_CONFIG_ACTIX.begin_verify(
+    request.method(),
+    request.uri().path_and_query(),
+    request.headers().clone(),
+  )?;
+  let actor_id = unverified
+    .key_id()
+    .split('#')
+    .next()
+    .context(location_info!())?;
+  Ok(Url::parse(actor_id)?)
 }
 
-pub fn verify(request: &HttpRequest, actor: &dyn ActorType) -> Result<(), LemmyError> {
+pub fn verify(request: &SignedRequest, actor: &dyn ActorType) -> Result<(), LemmyError> {
   let public_key = actor.public_key().context(location_info!())?;
-  let verified = HTTP_SIG_CONFIG
+  verify_with_key(request, &public_key)
+}
+
+/// Verifies the request with the key we have for the actor. If that fails, the actor may have a new
+/// key, so it's refetched from its instance, and the request is verified again with the key it has
+/// now.
+pub async fn verify_or_refetch(
+  request: &SignedRequest,
+  actor: &dyn ActorType,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let error = match verify(request, actor) {
+    Ok(()) => return Ok(()),
+    Err(e) => e,
+  };
+  let actor_id = actor.actor_id()?;
+  if actor.private_key().is_some() || !may_refetch_key(&actor_id) {
+    return Err(error);
+  }
+
+  debug!("Refetching {} for its current key", actor_id);
+  let refetched = refetch_actor(&actor_id, context).await?;
+  verify(request, refetched.as_ref())
+}
+
+/// Whether the key of the actor can be refetched now, which then counts as a refetch.
+pub fn may_refetch_key(actor_id: &Url) -> bool {
+  let mut refetches = KEY_REFETCHES.lock().unwrap();
+  refetches.retain(|_, refetched| refetched.elapsed() < KEY_REFETCH_INTERVAL);
+  if refetches.contains_key(actor_id) {
+    return false;
+  }
+  refetches.insert(actor_id.to_owned(), Instant::now());
+  true
+}
+
+/// Like verify, for signers which aren't stored as users or communities.
+pub fn verify_with_key(request: &SignedRequest, public_key: &str) -> Result<(), LemmyError> {
+  let verified = HTTP_SIG_CONFIG_ACTIX
     .begin_verify(
-      request.method(),
-      request.uri().path_and_query(),
-      request.headers().clone(),
+      &request.method,
+      request.uri.path_and_query(),
+      request.headers.clone(),
     )?
     .verify(|signature, signing_string| -> Result<bool, LemmyError> {
       debug!(
@@ -70,10 +178,10 @@ pub fn verify(request: &HttpRequest, actor: &dyn ActorType) -> Result<(), LemmyE
     })?;
 
   if verified {
-    debug!("verified signature for {}", &request.uri());
+    debug!("verified signature for {}", &request.uri);
     Ok(())
   } else {
-    Err(anyhow!("Invalid signature on request: {}", &request.uri()).into())
+    Err(anyhow!("Invalid signature on request: {}", &request.uri).into())
   }
 }
 
@@ -0,0 +1,45 @@
+use crate::{
+  apub::{check_is_apub_id_valid, fetcher::fetch_remote_object, FromApub},
+  LemmyContext,
+};
+use activitystreams::object::Note;
+use diesel::result::Error::NotFound;
+use lemmy_api_structs::blocking;
+use lemmy_db::comment::{Comment, CommentForm};
+use lemmy_utils::LemmyError;
+use log::debug;
+use url::Url;
+
+pub async fn get_or_fetch_and_insert_comment(
+  comment_ap_id: &Url,
+  context: &LemmyContext,
+) -> Result<Comment, LemmyError> {
+  check_is_apub_id_valid(comment_ap_id)?;
+  let comment_ap_id_owned = comment_ap_id.to_owned();
+  let comment = blocking(context.pool(), move |conn| {
+    Comment::read_from_apub_id(conn, comment_ap_id_owned.as_str())
+  })
+  .await?;
+
+  match comment {
+    Ok(p) => Ok(p),
+    Err(NotFound {}) => {
+      debug!(
+        "Fetching and creating remote comment and its parents: {}",
+        comment_ap_id
+      );
+      let _depth = context.fetch_budget().descend()?;
+      let comment = fetch_remote_object::<Note>(context, comment_ap_id).await?;
+      let comment_form =
+        CommentForm::from_apub(&comment, context, Some(comment_ap_id.to_owned())).await?;
+
+      let comment = blocking(context.pool(), move |conn| {
+        Comment::upsert(conn, &comment_form)
+      })
+      .await??;
+
+      Ok(comment)
+    }
+    Err(e) => Err(e.into()),
+  }
+}
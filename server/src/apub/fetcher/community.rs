@@ -0,0 +1,215 @@
+use crate::{
+  apub::{
+    check_is_apub_id_valid,
+    community::get_moderators_url,
+    fetcher::{
+      fetch_remote_object,
+      fetch_remote_object_opt,
+      followers::{collection_items, item_id},
+      get_or_fetch_and_upsert_user,
+      should_refetch_actor,
+    },
+    post::upsert_post_media_from_apub,
+    ActorType,
+    FromApub,
+    GroupExt,
+    PageExt,
+  },
+  LemmyContext,
+};
+use activitystreams::prelude::*;
+use anyhow::{anyhow, Context};
+use diesel::result::Error::NotFound;
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  community::{
+    Community,
+    CommunityAppearance,
+    CommunityAppearanceForm,
+    CommunityForm,
+    CommunityModerator,
+    CommunityModeratorForm,
+    CommunitySettings,
+  },
+  post::{Post, PostForm},
+  Crud,
+  Joinable,
+};
+use lemmy_utils::{location_info, LemmyError};
+use log::debug;
+use serde_json::Value;
+use url::Url;
+
+/// The actor ids in the moderators collection of a remote community, as its instance serves it.
+pub async fn fetch_moderator_actor_ids(
+  community: &Community,
+  context: &LemmyContext,
+) -> Result<Vec<String>, LemmyError> {
+  let collection = fetch_remote_object::<Value>(context, &get_moderators_url(community)?).await?;
+  let items = collection_items(&collection).context(location_info!())?;
+  Ok(
+    items
+      .iter()
+      .filter_map(|i| item_id(i))
+      .map(str::to_owned)
+      .collect(),
+  )
+}
+
+/// Check if a remote community exists, create if not found, if its too old update it.Fetch a community, insert/update it in the database and return the community.
+pub async fn get_or_fetch_and_upsert_community(
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<Community, LemmyError> {
+  // Also for known communities, in case their instance isn't allowed anymore
+  check_is_apub_id_valid(apub_id)?;
+  let apub_id_owned = apub_id.to_owned();
+  let community = blocking(context.pool(), move |conn| {
+    Community::read_from_actor_id(conn, apub_id_owned.as_str())
+  })
+  .await?;
+
+  match community {
+    Ok(c) if !c.local && !c.deleted && should_refetch_actor(c.last_refreshed_at) => {
+      debug!("Fetching and updating from remote community: {}", apub_id);
+      fetch_remote_community(apub_id, context, Some(c.id)).await
+    }
+    Ok(c) => Ok(c),
+    Err(NotFound {}) => {
+      debug!("Fetching and creating remote community: {}", apub_id);
+      fetch_remote_community(apub_id, context, None).await
+    }
+    Err(e) => Err(e.into()),
+  }
+}
+
+pub(in crate::apub::fetcher) async fn fetch_remote_community(
+  apub_id: &Url,
+  context: &LemmyContext,
+  community_id: Option<i32>,
+) -> Result<Community, LemmyError> {
+  let _depth = context.fetch_budget().descend()?;
+  let group = match fetch_remote_object_opt::<GroupExt>(context, apub_id).await? {
+    Some(group) => group,
+    None => match community_id {
+      Some(community_id) => {
+        debug!("Remote community is gone, deleting it: {}", apub_id);
+        let community = blocking(context.pool(), move |conn| {
+          Community::update_deleted(conn, community_id, true)
+        })
+        .await??;
+        return Ok(community);
+      }
+      None => return Err(anyhow!("Remote community is gone, {}", apub_id).into()),
+    },
+  };
+
+  let cf = CommunityForm::from_apub(&group, context, Some(apub_id.to_owned())).await?;
+  let community = blocking(context.pool(), move |conn| Community::upsert(conn, &cf)).await??;
+
+  let community_id = community.id;
+  let appearance_form = CommunityAppearanceForm {
+    community_id,
+    accent_color: group.ext_one.valid_accent_color(),
+    default_sort: group.ext_one.valid_default_sort(),
+  };
+  // Remote communities have no settings of their own besides this one
+  let discoverable = group.ext_one.is_discoverable();
+  blocking(context.pool(), move |conn| {
+    CommunityAppearance::upsert(conn, &appearance_form)?;
+    CommunitySettings::update_discoverable(conn, community_id, discoverable)
+  })
+  .await??;
+
+  // Also add the community moderators too
+  let attributed_to = group.inner.attributed_to().context(location_info!())?;
+  let creator_and_moderator_uris: Vec<&Url> = attributed_to
+    .as_many()
+    .context(location_info!())?
+    .iter()
+    .map(|a| a.as_xsd_any_uri().context(""))
+    .collect::<Result<Vec<&Url>, anyhow::Error>>()?;
+
+  let mut creator_and_moderators = Vec::new();
+
+  for uri in creator_and_moderator_uris {
+    let c_or_m = get_or_fetch_and_upsert_user(uri, context).await?;
+
+    creator_and_moderators.push(c_or_m);
+  }
+
+  // The instance of the community is where its mods are changed, so the ones it lists replace
+  // ours, in case an Add or Remove of a mod got lost
+  blocking(context.pool(), move |conn| {
+    CommunityModerator::delete_for_community(conn, community_id)?;
+    for mod_ in creator_and_moderators {
+      let community_moderator_form = CommunityModeratorForm {
+        community_id,
+        user_id: mod_.id,
+      };
+
+      CommunityModerator::join(conn, &community_moderator_form)?;
+    }
+    Ok(()) as Result<(), LemmyError>
+  })
+  .await??;
+
+  // fetch outbox (maybe make this conditional)
+  let outbox = fetch_remote_object::<Value>(context, &community.get_outbox_url()?).await?;
+  // Paged outboxes link to their newest activities, older ones have the posts right in them
+  let outbox = match outbox.get("first") {
+    Some(Value::String(first)) => {
+      fetch_remote_object::<Value>(context, &Url::parse(first)?).await?
+    }
+    Some(first) => first.to_owned(),
+    None => outbox,
+  };
+  let outbox_items = outbox
+    .get("orderedItems")
+    .or_else(|| outbox.get("items"))
+    .and_then(|i| i.as_array())
+    .context(location_info!())?;
+  let pages = outbox_items
+    .iter()
+    .filter_map(page_from_outbox_item)
+    .take(20)
+    .collect::<Vec<Value>>();
+  for p in pages {
+    let page = serde_json::from_value::<PageExt>(p)?;
+    let post = PostForm::from_apub(&page, context, None).await?;
+    let post_ap_id = post.ap_id.as_ref().context(location_info!())?.clone();
+    // Check whether the post already exists in the local db
+    let existing = blocking(context.pool(), move |conn| {
+      Post::read_from_apub_id(conn, &post_ap_id)
+    })
+    .await?;
+    let post = match existing {
+      // Older outboxes still have the posts which were deleted since
+      Ok(e) if e.deleted || e.removed => continue,
+      Ok(e) => blocking(context.pool(), move |conn| Post::update(conn, e.id, &post)).await??,
+      Err(_) => blocking(context.pool(), move |conn| Post::upsert(conn, &post)).await??,
+    };
+    upsert_post_media_from_apub(&page, post.id, context.pool()).await?;
+    // TODO: we need to send a websocket update here
+  }
+
+  Ok(community)
+}
+
+/// The post in an outbox item, which is a page in older outboxes, and the creation of a page or the
+/// announcement of it in newer ones. Other activities, and the tombstones of deleted posts, are
+/// skipped.
+fn page_from_outbox_item(item: &Value) -> Option<Value> {
+  match item.get("type")?.as_str()? {
+    "Page" => Some(item.to_owned()),
+    "Create" => item
+      .get("object")
+      .filter(|o| o.get("type").and_then(|t| t.as_str()) == Some("Page"))
+      .cloned(),
+    "Announce" => item
+      .get("object")
+      .filter(|o| o.get("type").and_then(|t| t.as_str()) == Some("Create"))
+      .and_then(page_from_outbox_item),
+    _ => None,
+  }
+}
@@ -0,0 +1,223 @@
+//! The follower sync job, which compares the followers collections of remote communities with the
+//! local users who follow them, to repeat the follows and unfollows which got lost on the way.
+use crate::{
+  apub::{fetcher::fetch_remote_object, ActorType},
+  LemmyContext,
+};
+use anyhow::anyhow;
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  community::{Community, CommunityFollower, CommunityFollowerForm},
+  community_join_request::CommunityJoinRequest,
+  user::User_,
+  Followable,
+};
+use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings, LemmyError};
+use log::{debug, error};
+use serde_json::Value;
+use std::{collections::HashSet, time::Duration};
+use url::Url;
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The most pages of a followers collection which are read, for instances which list all of the
+/// followers instead of only ours.
+const MAX_FOLLOWER_PAGES: usize = 20;
+
+pub async fn run_follower_sync_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(SYNC_INTERVAL);
+  loop {
+    interval.tick().await;
+    let settings = Settings::get();
+    if !settings.federation.enabled || settings.maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = sync_followers(&context).await {
+      error!("Couldn't sync the followers of remote communities: {}", e);
+    }
+  }
+}
+
+async fn sync_followers(context: &LemmyContext) -> Result<(), LemmyError> {
+  let communities = blocking(context.pool(), move |conn| {
+    Community::list_remote_with_local_followers(conn)
+  })
+  .await??;
+  for community in communities {
+    let community_context = context.with_fetch_budget();
+    if let Err(e) = sync_community_followers(&community, &community_context).await {
+      debug!(
+        "Couldn't sync the followers of the community {}: {}",
+        community.actor_id, e
+      );
+    }
+  }
+  Ok(())
+}
+
+/// Our follower table is what our users want, so the community gets their follows again if it
+/// doesn't list them, and their unfollows if it lists users who don't follow it. If they asked to
+/// join and the community lists them, its accept got lost.
+async fn sync_community_followers(
+  community: &Community,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let listed = match fetch_listed_local_followers(community, context).await? {
+    Some(listed) => listed,
+    None => return Ok(()),
+  };
+
+  let community_id = community.id;
+  let followers = blocking(context.pool(), move |conn| {
+    CommunityFollower::list_local_followers(conn, community_id)
+  })
+  .await??;
+  let community_actor_id = community.actor_id()?;
+
+  for user in followers.iter().filter(|u| !listed.contains(&u.actor_id)) {
+    debug!(
+      "Following {} again for {}",
+      community.actor_id, user.actor_id
+    );
+    user.send_follow(&community_actor_id, context).await?;
+  }
+
+  for actor_id in listed {
+    if followers.iter().any(|u| u.actor_id == actor_id) {
+      continue;
+    }
+    let user = match blocking(context.pool(), move |conn| {
+      User_::read_from_actor_id(conn, &actor_id)
+    })
+    .await?
+    {
+      Ok(user) if user.local => user,
+      _ => continue,
+    };
+
+    let user_id = user.id;
+    let join_request = blocking(context.pool(), move |conn| {
+      CommunityJoinRequest::read_for_user(conn, community_id, user_id)
+    })
+    .await??;
+    if join_request.is_some() {
+      debug!(
+        "Accepting the follow of {} for {}",
+        community.actor_id, user.actor_id
+      );
+      let follower_form = CommunityFollowerForm {
+        community_id,
+        user_id,
+      };
+      blocking(context.pool(), move |conn| {
+        CommunityJoinRequest::remove(conn, community_id, user_id)?;
+        CommunityFollower::follow(conn, &follower_form)
+      })
+      .await??;
+    } else {
+      debug!(
+        "Unfollowing {} again for {}",
+        community.actor_id, user.actor_id
+      );
+      user.send_unfollow(&community_actor_id, context).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// The actor ids of our users in the followers collection of the community. None if it doesn't
+/// list its followers, like older versions which only have the number, or instances where the
+/// followers are hidden.
+async fn fetch_listed_local_followers(
+  community: &Community,
+  context: &LemmyContext,
+) -> Result<Option<HashSet<String>>, LemmyError> {
+  let followers_url = community.get_followers_url()?;
+  let collection = fetch_remote_object::<Value>(context, &followers_url).await?;
+  let mut page = match collection.get("first") {
+    Some(Value::String(first)) => {
+      let mut first = Url::parse(first)?;
+      // Instances which know the parameter only list our users
+      first
+        .query_pairs_mut()
+        .append_pair("instance", &Settings::get().hostname);
+      fetch_follower_page(&first, &followers_url, context).await?
+    }
+    Some(first) => first.to_owned(),
+    None if collection_items(&collection).is_some() => collection.to_owned(),
+    None => return Ok(None),
+  };
+
+  let local_prefix = format!(
+    "{}://{}/",
+    get_apub_protocol_string(),
+    Settings::get().hostname
+  );
+  let mut listed = HashSet::new();
+  let mut any_items = false;
+  for _ in 0..MAX_FOLLOWER_PAGES {
+    let items = collection_items(&page).unwrap_or_default();
+    any_items |= !items.is_empty();
+    listed.extend(
+      items
+        .iter()
+        .filter_map(|i| item_id(i))
+        .filter(|id| id.starts_with(&local_prefix))
+        .map(str::to_owned),
+    );
+
+    let next = page
+      .get("next")
+      .and_then(item_id)
+      .map(Url::parse)
+      .transpose()?;
+    match next {
+      Some(next) => page = fetch_follower_page(&next, &followers_url, context).await?,
+      None => {
+        let total_items = collection.get("totalItems").and_then(|t| t.as_u64());
+        if !any_items && total_items.unwrap_or(0) > 0 {
+          return Ok(None);
+        }
+        return Ok(Some(listed));
+      }
+    }
+  }
+  // Followers on the pages which weren't read would be taken as missing
+  Err(
+    anyhow!(
+      "Followers collection {} has more than {} pages",
+      followers_url,
+      MAX_FOLLOWER_PAGES
+    )
+    .into(),
+  )
+}
+
+/// Only the instance of the community can say who follows it.
+async fn fetch_follower_page(
+  url: &Url,
+  followers_url: &Url,
+  context: &LemmyContext,
+) -> Result<Value, LemmyError> {
+  if url.host_str() != followers_url.host_str() || url.port() != followers_url.port() {
+    return Err(anyhow!("Followers page {} is on another instance", url).into());
+  }
+  fetch_remote_object::<Value>(context, url).await
+}
+
+pub(in crate::apub::fetcher) fn collection_items(collection: &Value) -> Option<Vec<Value>> {
+  collection
+    .get("orderedItems")
+    .or_else(|| collection.get("items"))
+    .and_then(|i| i.as_array())
+    .cloned()
+}
+
+/// Items are either ids, or objects with one.
+pub(in crate::apub::fetcher) fn item_id(item: &Value) -> Option<&str> {
+  match item {
+    Value::String(id) => Some(id),
+    _ => item.get("id")?.as_str(),
+  }
+}
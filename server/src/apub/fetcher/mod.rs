@@ -0,0 +1,229 @@
+//! Resolves ActivityPub ids to local objects, fetching them from their instance if needed. There is
+//! a resolver for every type of object, and all of them share the fetch budget of the context, so
+//! that one incoming activity can't make us fetch an endless chain of objects.
+mod comment;
+mod community;
+mod followers;
+mod post;
+mod search;
+mod user;
+
+pub use comment::get_or_fetch_and_insert_comment;
+pub use community::{fetch_moderator_actor_ids, get_or_fetch_and_upsert_community};
+pub use followers::run_follower_sync_job;
+pub use post::get_or_fetch_and_insert_post;
+pub use search::search_by_apub_id;
+pub use user::{get_or_fetch_and_upsert_creator, get_or_fetch_and_upsert_user};
+
+use crate::{
+  apub::{
+    check_is_apub_id_valid,
+    extensions::signatures::sign_get,
+    instance::{instance_actor_id, read_instance_actor},
+    ActorType,
+    APUB_JSON_CONTENT_TYPE,
+  },
+  request::{get_checked_with, read_body, RecvError},
+  LemmyContext,
+};
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use lemmy_api_structs::blocking;
+use lemmy_db::{community::Community, naive_now, user::User_};
+use lemmy_utils::LemmyError;
+use log::debug;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU32, Ordering};
+use url::Url;
+
+static ACTOR_REFETCH_INTERVAL_SECONDS: i64 = 24 * 60 * 60;
+static ACTOR_REFETCH_INTERVAL_SECONDS_DEBUG: i64 = 10;
+
+/// The most requests that one incoming activity or search can make to other instances.
+const MAX_FETCH_REQUESTS: u32 = 100;
+
+/// How deep resolving an object can go into the objects it references, eg the parents of a
+/// comment, their creators and their post.
+const MAX_FETCH_DEPTH: u32 = 16;
+
+/// Limits the fetching caused by one incoming activity or search. The budget of the shared
+/// context is unlimited, LemmyContext::with_fetch_budget gives each activity its own one.
+#[derive(Default)]
+pub struct FetchBudget {
+  limited: bool,
+  requests: AtomicU32,
+  depth: AtomicU32,
+}
+
+impl FetchBudget {
+  pub fn limited() -> Self {
+    FetchBudget {
+      limited: true,
+      ..Default::default()
+    }
+  }
+
+  fn spend_request(&self) -> Result<(), LemmyError> {
+    let requests = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+    if self.limited && requests > MAX_FETCH_REQUESTS {
+      return Err(anyhow!("Reached the limit of {} fetches", MAX_FETCH_REQUESTS).into());
+    }
+    Ok(())
+  }
+
+  /// Counts the resolving of a referenced object, until the returned guard is dropped.
+  fn descend(&self) -> Result<FetchDepthGuard<'_>, LemmyError> {
+    let depth = self.depth.fetch_add(1, Ordering::SeqCst) + 1;
+    let guard = FetchDepthGuard(self);
+    if self.limited && depth > MAX_FETCH_DEPTH {
+      return Err(anyhow!("Reached the fetch depth limit of {}", MAX_FETCH_DEPTH).into());
+    }
+    Ok(guard)
+  }
+}
+
+struct FetchDepthGuard<'a>(&'a FetchBudget);
+
+impl Drop for FetchDepthGuard<'_> {
+  fn drop(&mut self) {
+    self.0.depth.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+/// Fetch any type of ActivityPub object, handling things like HTTP headers, deserialisation,
+/// timeouts etc.
+pub async fn fetch_remote_object<Response>(
+  context: &LemmyContext,
+  url: &Url,
+) -> Result<Response, LemmyError>
+where
+  Response: for<'de> Deserialize<'de>,
+{
+  match fetch_remote_object_opt(context, url).await? {
+    Some(object) => Ok(object),
+    None => Err(anyhow!("Remote object is gone, {}", url).into()),
+  }
+}
+
+/// Like fetch_remote_object, but returns None if the object is gone from its instance. Only a 410
+/// Gone counts, a 404 might be temporary. The request is signed by the instance actor, for
+/// instances which only serve signed fetches.
+async fn fetch_remote_object_opt<Response>(
+  context: &LemmyContext,
+  url: &Url,
+) -> Result<Option<Response>, LemmyError>
+where
+  Response: for<'de> Deserialize<'de>,
+{
+  check_is_apub_id_valid(&url)?;
+  context.fetch_budget().spend_request()?;
+
+  let instance_actor = read_instance_actor(context.pool()).await?;
+  let instance_actor_id = instance_actor_id()?;
+
+  let response = get_checked_with(context.client(), url, |r| {
+    sign_get(
+      r.header("Accept", APUB_JSON_CONTENT_TYPE),
+      &instance_actor_id,
+      instance_actor.private_key.to_owned(),
+    )
+  })
+  .await?;
+
+  if response.status() == StatusCode::GONE {
+    return Ok(None);
+  }
+
+  let body = read_body(response).await?;
+  let json = serde_json::from_slice(&body).map_err(|e| {
+    debug!("Receive error, {}", e);
+    RecvError(e.to_string())
+  })?;
+
+  Ok(Some(json))
+}
+
+pub async fn get_or_fetch_and_upsert_actor(
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<Box<dyn ActorType>, LemmyError> {
+  let user = get_or_fetch_and_upsert_user(apub_id, context).await;
+  let actor: Box<dyn ActorType> = match user {
+    Ok(u) => Box::new(u),
+    Err(_) => Box::new(get_or_fetch_and_upsert_community(apub_id, context).await?),
+  };
+  Ok(actor)
+}
+
+/// Fetches a remote actor again, even if it was refetched recently, eg because it signed a request
+/// with a key we don't have yet. Actors we don't know yet are fetched like with
+/// get_or_fetch_and_upsert_actor.
+pub async fn refetch_actor(
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<Box<dyn ActorType>, LemmyError> {
+  check_is_apub_id_valid(apub_id)?;
+  let id = apub_id.to_string();
+  let (user, community) = blocking(context.pool(), move |conn| {
+    (
+      User_::read_from_actor_id(conn, &id).ok(),
+      Community::read_from_actor_id(conn, &id).ok(),
+    )
+  })
+  .await?;
+
+  let actor: Box<dyn ActorType> = match (user, community) {
+    (Some(u), _) if !u.local && !u.deleted => {
+      Box::new(user::refetch_user(u.id, apub_id, context).await?)
+    }
+    (_, Some(c)) if !c.local && !c.deleted => {
+      Box::new(community::fetch_remote_community(apub_id, context, Some(c.id)).await?)
+    }
+    _ => get_or_fetch_and_upsert_actor(apub_id, context).await?,
+  };
+  Ok(actor)
+}
+
+/// Determines when a remote actor should be refetched from its instance. In release builds, this is
+/// ACTOR_REFETCH_INTERVAL_SECONDS after the last refetch, in debug builds always.
+///
+/// TODO it won't pick up new avatars, summaries etc until a day after.
+/// Actors need an "update" activity pushed to other servers to fix this.
+fn should_refetch_actor(last_refreshed: NaiveDateTime) -> bool {
+  let update_interval = if cfg!(debug_assertions) {
+    // avoid infinite loop when fetching community outbox
+    chrono::Duration::seconds(ACTOR_REFETCH_INTERVAL_SECONDS_DEBUG)
+  } else {
+    chrono::Duration::seconds(ACTOR_REFETCH_INTERVAL_SECONDS)
+  };
+  last_refreshed.lt(&(naive_now() - update_interval))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::apub::fetcher::{FetchBudget, MAX_FETCH_DEPTH, MAX_FETCH_REQUESTS};
+
+  #[test]
+  fn test_fetch_budget() {
+    let budget = FetchBudget::limited();
+    for _ in 0..MAX_FETCH_REQUESTS {
+      assert!(budget.spend_request().is_ok());
+    }
+    assert!(budget.spend_request().is_err());
+
+    let mut guards = Vec::new();
+    for _ in 0..MAX_FETCH_DEPTH {
+      guards.push(budget.descend().unwrap());
+    }
+    assert!(budget.descend().is_err());
+    // Leaving a nested object makes room again
+    guards.pop();
+    assert!(budget.descend().is_ok());
+
+    let unlimited = FetchBudget::default();
+    for _ in 0..=MAX_FETCH_REQUESTS {
+      assert!(unlimited.spend_request().is_ok());
+    }
+  }
+}
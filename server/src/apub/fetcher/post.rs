@@ -0,0 +1,44 @@
+use crate::{
+  apub::{
+    check_is_apub_id_valid,
+    fetcher::fetch_remote_object,
+    post::upsert_post_media_from_apub,
+    FromApub,
+    PageExt,
+  },
+  LemmyContext,
+};
+use diesel::result::Error::NotFound;
+use lemmy_api_structs::blocking;
+use lemmy_db::post::{Post, PostForm};
+use lemmy_utils::LemmyError;
+use log::debug;
+use url::Url;
+
+pub async fn get_or_fetch_and_insert_post(
+  post_ap_id: &Url,
+  context: &LemmyContext,
+) -> Result<Post, LemmyError> {
+  check_is_apub_id_valid(post_ap_id)?;
+  let post_ap_id_owned = post_ap_id.to_owned();
+  let post = blocking(context.pool(), move |conn| {
+    Post::read_from_apub_id(conn, post_ap_id_owned.as_str())
+  })
+  .await?;
+
+  match post {
+    Ok(p) => Ok(p),
+    Err(NotFound {}) => {
+      debug!("Fetching and creating remote post: {}", post_ap_id);
+      let _depth = context.fetch_budget().descend()?;
+      let post = fetch_remote_object::<PageExt>(context, post_ap_id).await?;
+      let post_form = PostForm::from_apub(&post, context, Some(post_ap_id.to_owned())).await?;
+
+      let inserted = blocking(context.pool(), move |conn| Post::upsert(conn, &post_form)).await??;
+      upsert_post_media_from_apub(&post, inserted.id, context.pool()).await?;
+
+      Ok(inserted)
+    }
+    Err(e) => Err(e.into()),
+  }
+}
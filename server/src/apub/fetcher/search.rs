@@ -0,0 +1,153 @@
+use crate::{
+  apub::{
+    fetcher::{
+      fetch_remote_object,
+      get_or_fetch_and_upsert_community,
+      get_or_fetch_and_upsert_user,
+    },
+    post::upsert_post_media_from_apub,
+    FromApub,
+    GroupExt,
+    PageExt,
+    PersonExt,
+  },
+  LemmyContext,
+};
+use activitystreams::{base::BaseExt, object::Note};
+use anyhow::{anyhow, Context};
+use lemmy_api_structs::{blocking, site::SearchResponse};
+use lemmy_db::{
+  comment::{Comment, CommentForm},
+  comment_view::CommentView,
+  community_view::CommunityView,
+  post::{Post, PostForm},
+  post_view::PostView,
+  user_view::UserView,
+  SearchType,
+};
+use lemmy_utils::{apub::get_apub_protocol_string, LemmyError};
+use log::debug;
+use url::Url;
+
+/// The types of ActivityPub objects that can be fetched directly by searching for their ID.
+#[serde(untagged)]
+#[derive(serde::Deserialize, Debug)]
+pub enum SearchAcceptedObjects {
+  Person(Box<PersonExt>),
+  Group(Box<GroupExt>),
+  Page(Box<PageExt>),
+  Comment(Box<Note>),
+}
+
+/// Attempt to parse the query as URL, and fetch an ActivityPub object from it.
+///
+/// Some working examples for use with the docker/federation/ setup:
+/// http://lemmy_alpha:8540/c/main, or !main@lemmy_alpha:8540
+/// http://lemmy_alpha:8540/u/lemmy_alpha, or @lemmy_alpha@lemmy_alpha:8540
+/// http://lemmy_alpha:8540/post/3
+/// http://lemmy_alpha:8540/comment/2
+pub async fn search_by_apub_id(
+  query: &str,
+  context: &LemmyContext,
+) -> Result<SearchResponse, LemmyError> {
+  // A search is like an incoming activity, it shouldn't fetch without limits
+  let context = &context.with_fetch_budget();
+
+  // Parse the shorthand query url
+  let query_url = if query.contains('@') {
+    debug!("{}", query);
+    let split = query.split('@').collect::<Vec<&str>>();
+
+    // User type will look like ['', username, instance]
+    // Community will look like [!community, instance]
+    let (name, instance) = if split.len() == 3 {
+      (format!("/u/{}", split[1]), split[2])
+    } else if split.len() == 2 {
+      if split[0].contains('!') {
+        let split2 = split[0].split('!').collect::<Vec<&str>>();
+        (format!("/c/{}", split2[1]), split[1])
+      } else {
+        return Err(anyhow!("Invalid search query: {}", query).into());
+      }
+    } else {
+      return Err(anyhow!("Invalid search query: {}", query).into());
+    };
+
+    let url = format!("{}://{}{}", get_apub_protocol_string(), instance, name);
+    Url::parse(&url)?
+  } else {
+    Url::parse(&query)?
+  };
+
+  let mut response = SearchResponse {
+    type_: SearchType::All.to_string(),
+    comments: vec![],
+    posts: vec![],
+    communities: vec![],
+    users: vec![],
+  };
+
+  let domain = query_url.domain().context("url has no domain")?;
+  let response = match fetch_remote_object::<SearchAcceptedObjects>(context, &query_url).await? {
+    SearchAcceptedObjects::Person(p) => {
+      let user_uri = p.inner.id(domain)?.context("person has no id")?;
+
+      let user = get_or_fetch_and_upsert_user(&user_uri, context).await?;
+
+      response.users = vec![
+        blocking(context.pool(), move |conn| {
+          UserView::get_user_secure(conn, user.id)
+        })
+        .await??,
+      ];
+
+      response
+    }
+    SearchAcceptedObjects::Group(g) => {
+      let community_uri = g.inner.id(domain)?.context("group has no id")?;
+
+      let community = get_or_fetch_and_upsert_community(community_uri, context).await?;
+
+      response.communities = vec![
+        blocking(context.pool(), move |conn| {
+          CommunityView::read(conn, community.id, None)
+        })
+        .await??,
+      ];
+
+      response
+    }
+    SearchAcceptedObjects::Page(p) => {
+      let post_form = PostForm::from_apub(&p, context, Some(query_url)).await?;
+
+      let post = blocking(context.pool(), move |conn| Post::upsert(conn, &post_form)).await??;
+      upsert_post_media_from_apub(&p, post.id, context.pool()).await?;
+      response.posts = vec![
+        blocking(context.pool(), move |conn| {
+          PostView::read(conn, post.id, None)
+        })
+        .await??,
+      ];
+
+      response
+    }
+    SearchAcceptedObjects::Comment(c) => {
+      let comment_form = CommentForm::from_apub(&c, context, Some(query_url)).await?;
+
+      let c = blocking(context.pool(), move |conn| {
+        Comment::upsert(conn, &comment_form)
+      })
+      .await??;
+      response.comments = vec![
+        blocking(context.pool(), move |conn| {
+          CommentView::read(conn, c.id, None)
+        })
+        .await??,
+      ];
+
+      response
+    }
+  };
+
+  Ok(response)
+}
@@ -0,0 +1,139 @@
+use crate::{
+  apub::{
+    check_is_apub_id_valid,
+    fetcher::{fetch_remote_object_opt, should_refetch_actor},
+    FromApub,
+    PersonExt,
+  },
+  LemmyContext,
+};
+use anyhow::anyhow;
+use diesel::result::Error::NotFound;
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  comment::Comment,
+  naive_now,
+  post::Post,
+  user::{UserForm, User_},
+  Crud,
+};
+use lemmy_utils::{
+  settings::{GoneActorContent, Settings},
+  LemmyError,
+};
+use log::debug;
+use url::Url;
+
+/// Check if a remote user exists, create if not found, if its too old update it.Fetch a user, insert/update it in the database and return the user.
+pub async fn get_or_fetch_and_upsert_user(
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<User_, LemmyError> {
+  match get_or_fetch_and_upsert_user_opt(apub_id, context).await? {
+    Some(user) => Ok(user),
+    None => Err(anyhow!("Remote user is gone, {}", apub_id).into()),
+  }
+}
+
+/// Returns the user which content of the given actor is attributed to. That's the actor itself,
+/// also if it was deleted, or a remote tombstone of it if it's gone and we never knew it, eg for
+/// old content of a purged actor.
+pub async fn get_or_fetch_and_upsert_creator(
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<User_, LemmyError> {
+  match get_or_fetch_and_upsert_user_opt(apub_id, context).await? {
+    Some(user) => Ok(user),
+    None => {
+      let tombstone_actor_id = apub_id.to_string();
+      let tombstone_name = apub_id
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or("deleted")
+        .to_owned();
+      let tombstone = blocking(context.pool(), move |conn| {
+        User_::upsert_remote_tombstone(conn, &tombstone_actor_id, &tombstone_name)
+      })
+      .await??;
+      Ok(tombstone)
+    }
+  }
+}
+
+/// Like get_or_fetch_and_upsert_user, but returns None if the user is gone from its instance and
+/// we didn't know it yet. Known users that are gone are deleted here too, instead of failing.
+async fn get_or_fetch_and_upsert_user_opt(
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<Option<User_>, LemmyError> {
+  // Also for known users, in case their instance isn't allowed anymore
+  check_is_apub_id_valid(apub_id)?;
+  let apub_id_owned = apub_id.to_owned();
+  let user = blocking(context.pool(), move |conn| {
+    User_::read_from_actor_id(conn, apub_id_owned.as_ref())
+  })
+  .await?;
+
+  match user {
+    // If its older than a day, re-fetch it
+    Ok(u) if !u.local && !u.deleted && should_refetch_actor(u.last_refreshed_at) => {
+      debug!("Fetching and updating from remote user: {}", apub_id);
+      Ok(Some(refetch_user(u.id, apub_id, context).await?))
+    }
+    Ok(u) => Ok(Some(u)),
+    Err(NotFound {}) => {
+      debug!("Fetching and creating remote user: {}", apub_id);
+      let _depth = context.fetch_budget().descend()?;
+      let person = match fetch_remote_object_opt::<PersonExt>(context, apub_id).await? {
+        Some(person) => person,
+        None => return Ok(None),
+      };
+
+      let uf = UserForm::from_apub(&person, context, Some(apub_id.to_owned())).await?;
+      let user = blocking(context.pool(), move |conn| User_::upsert(conn, &uf)).await??;
+
+      Ok(Some(user))
+    }
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Fetches a known remote user again and updates it, or deletes it if it's gone from its instance.
+pub(in crate::apub::fetcher) async fn refetch_user(
+  user_id: i32,
+  apub_id: &Url,
+  context: &LemmyContext,
+) -> Result<User_, LemmyError> {
+  let _depth = context.fetch_budget().descend()?;
+  let person = match fetch_remote_object_opt::<PersonExt>(context, apub_id).await? {
+    Some(person) => person,
+    None => {
+      debug!("Remote user is gone, deleting it: {}", apub_id);
+      return delete_gone_user(user_id, context).await;
+    }
+  };
+
+  let mut uf = UserForm::from_apub(&person, context, Some(apub_id.to_owned())).await?;
+  uf.last_refreshed_at = Some(naive_now());
+  let user = blocking(context.pool(), move |conn| {
+    User_::update(conn, user_id, &uf)
+  })
+  .await??;
+  Ok(user)
+}
+
+/// Deletes a remote user whose instance says it's gone, so that it isn't refetched anymore. What
+/// happens to its content depends on the gone_actor_content setting.
+async fn delete_gone_user(user_id: i32, context: &LemmyContext) -> Result<User_, LemmyError> {
+  let delete_content = Settings::get().federation.gone_actor_content == GoneActorContent::Delete;
+  let user = blocking(context.pool(), move |conn| {
+    if delete_content {
+      Post::update_deleted_for_creator(conn, user_id, true)?;
+      Comment::update_deleted_for_creator(conn, user_id, true)?;
+    }
+    User_::delete_account(conn, user_id)
+  })
+  .await??;
+  Ok(user)
+}
@@ -0,0 +1,217 @@
+use crate::{
+  apub::{
+    community::get_moderators_url,
+    extensions::add_extension::AddExtension,
+    fetcher::{
+      fetch_moderator_actor_ids,
+      get_or_fetch_and_insert_comment,
+      get_or_fetch_and_upsert_user,
+    },
+    inbox::shared_inbox::{
+      announce_if_community_is_local,
+      get_user_from_activity,
+      receive_unhandled_activity,
+    },
+    ActorType,
+    FromApub,
+  },
+  websocket::{
+    messages::{SendCommunityRoomMessage, SendPost},
+    UserOperation,
+  },
+  LemmyContext,
+};
+use activitystreams::{activity::Add, base::AnyBase, object::Note, prelude::*};
+use activitystreams_ext::UnparsedExtension;
+use actix_web::HttpResponse;
+use anyhow::{anyhow, Context};
+use lemmy_api_structs::{blocking, community::AddModToCommunityResponse, post::PostResponse};
+use lemmy_db::{
+  comment::{Comment, CommentForm},
+  community::{Community, CommunityModerator, CommunityModeratorForm},
+  community_view::CommunityModeratorView,
+  post::Post,
+  post_view::PostView,
+  user::User_,
+  Joinable,
+};
+use lemmy_utils::{location_info, LemmyError};
+use url::Url;
+
+pub async fn receive_add(
+  activity: AnyBase,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let add = Add::from_any_base(activity)?.context(location_info!())?;
+  match add.object().as_single_kind_str() {
+    Some("Note") => receive_add_comment(add, context).await,
+    // Users are only sent by their id
+    None => receive_add_mod(add, context).await,
+    _ => receive_unhandled_activity(add),
+  }
+}
+
+/// Adding a user to the moderators collection of a community makes them a mod.
+async fn receive_add_mod(add: Add, context: &LemmyContext) -> Result<HttpResponse, LemmyError> {
+  let actor = get_user_from_activity(&add, context).await?;
+  let target = AddExtension::try_from_unparsed(&mut add.to_owned())?
+    .target
+    .context(location_info!())?;
+  let object = add
+    .object()
+    .as_single_xsd_any_uri()
+    .context(location_info!())?;
+  let (community, user) = get_moderator_change(&actor, &target, object, true, context).await?;
+
+  let form = CommunityModeratorForm {
+    community_id: community.id,
+    user_id: user.id,
+  };
+  blocking(context.pool(), move |conn| {
+    // Already being a mod is fine
+    CommunityModerator::leave(conn, &form)?;
+    CommunityModerator::join(conn, &form)
+  })
+  .await??;
+
+  send_moderators_to_websocket(community.id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
+
+/// Returns the community and the user of an Add or Remove which targets the moderators collection
+/// of a community, after checking that the community is from another instance, and that the actor
+/// is allowed to change its mods. That's its mods, and the admins of its instance, which aren't
+/// known here. So if the actor isn't a mod, the change has to be in the moderators collection which
+/// the instance of the community serves. Only communities which are known already are changed.
+pub(in crate::apub::inbox) async fn get_moderator_change(
+  actor: &User_,
+  target: &Url,
+  object: &Url,
+  added: bool,
+  context: &LemmyContext,
+) -> Result<(Community, User_), LemmyError> {
+  let community_actor_id = target.as_str().trim_end_matches("/moderators").to_owned();
+  let community = blocking(context.pool(), move |conn| {
+    Community::read_from_actor_id(conn, &community_actor_id)
+  })
+  .await??;
+  if &get_moderators_url(&community)? != target {
+    return Err(anyhow!("Target {} isn't a moderators collection", target).into());
+  }
+  if community.local {
+    return Err(anyhow!("Mods of local communities are only changed here").into());
+  }
+  if actor.actor_id()?.domain() != Url::parse(&community.actor_id)?.domain() {
+    return Err(anyhow!("Only mods from the instance of the community can change its mods").into());
+  }
+
+  let actor_id = actor.id;
+  let community_id = community.id;
+  let is_mod = blocking(context.pool(), move |conn| {
+    CommunityModeratorView::for_community(conn, community_id)
+      .map(|mods| mods.iter().any(|m| m.user_id == actor_id))
+  })
+  .await??;
+  if !is_mod {
+    let listed = fetch_moderator_actor_ids(&community, context).await?;
+    if listed.iter().any(|m| m == object.as_str()) != added {
+      return Err(anyhow!("Only mods and admins of the community can change its mods").into());
+    }
+  }
+
+  let user = get_or_fetch_and_upsert_user(object, context).await?;
+  Ok((community, user))
+}
+
+pub(in crate::apub::inbox) async fn send_moderators_to_websocket(
+  community_id: i32,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let moderators = blocking(context.pool(), move |conn| {
+    CommunityModeratorView::for_community(conn, community_id)
+  })
+  .await??;
+
+  context.chat_server().do_send(SendCommunityRoomMessage {
+    op: UserOperation::AddModToCommunity,
+    response: AddModToCommunityResponse { moderators },
+    community_id,
+    websocket_id: None,
+  });
+  Ok(())
+}
+
+/// Adding a comment to a post pins it to the top of the thread.
+async fn receive_add_comment(add: Add, context: &LemmyContext) -> Result<HttpResponse, LemmyError> {
+  let (user, comment, post) = get_pinned_comment_from_add(&add, context).await?;
+
+  let post_id = post.id;
+  let comment_id = comment.id;
+  blocking(context.pool(), move |conn| {
+    Post::update_pinned_comment(conn, post_id, Some(comment_id))
+  })
+  .await??;
+
+  send_post_to_websocket(post_id, context).await?;
+
+  announce_if_community_is_local(add, &user, post.community_id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
+
+/// Returns the actor, comment and post of a pin, after checking that the comment belongs to the
+/// post, and that the actor is allowed to pin it. Only the post creator, the mods of the community,
+/// from whichever instance, and the admins of its instance can pin comments. Admins of other
+/// instances aren't known here, so in remote communities it takes a mod.
+pub(in crate::apub::inbox) async fn get_pinned_comment_from_add(
+  add: &Add,
+  context: &LemmyContext,
+) -> Result<(User_, Comment, Post), LemmyError> {
+  let user = get_user_from_activity(add, context).await?;
+  let note = Note::from_any_base(add.object().to_owned().one().context(location_info!())?)?
+    .context(location_info!())?;
+
+  let comment_ap_id = CommentForm::from_apub(&note, context, None)
+    .await?
+    .get_ap_id()?;
+  let comment = get_or_fetch_and_insert_comment(&comment_ap_id, context).await?;
+
+  let post_id = comment.post_id;
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  let target = AddExtension::try_from_unparsed(&mut add.to_owned())?
+    .target
+    .context(location_info!())?;
+  if target.as_str() != post.ap_id {
+    return Err(anyhow!("Pinned comment doesn't belong to the target post").into());
+  }
+
+  let user_id = user.id;
+  let community_id = post.community_id;
+  let is_mod_or_admin = blocking(context.pool(), move |conn| {
+    Community::is_mod_or_admin(conn, user_id, community_id)
+  })
+  .await?;
+  if user.id != post.creator_id && !is_mod_or_admin {
+    return Err(anyhow!("Only the post creator and mods can pin comments").into());
+  }
+
+  Ok((user, comment, post))
+}
+
+pub(in crate::apub::inbox) async fn send_post_to_websocket(
+  post_id: i32,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let post_view = blocking(context.pool(), move |conn| {
+    PostView::read(conn, post_id, None)
+  })
+  .await??;
+
+  let res = PostResponse { post: post_view };
+
+  context.chat_server().do_send(SendPost {
+    op: UserOperation::EditPost,
+    post: res,
+    websocket_id: None,
+  });
+  Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::{
   apub::inbox::{
     activities::{
+      add::receive_add,
       create::receive_create,
       delete::receive_delete,
       dislike::receive_dislike,
@@ -43,6 +44,7 @@ pub async fn receive_announce(
     Some("Delete") => receive_delete(object2, context).await,
     Some("Remove") => receive_remove(object2, context).await,
     Some("Undo") => receive_undo(object2, context).await,
+    Some("Add") => receive_add(object2, context).await,
     _ => receive_unhandled_activity(announce),
   }
 }
@@ -1,10 +1,22 @@
 use crate::{
+  api::{
+    check_community_membership,
+    community::send_mod_inbox_counts,
+    post::{
+      check_domain_policy,
+      send_automod_comment,
+      send_community_notifications,
+      send_saved_search_matches,
+    },
+  },
   apub::{
+    check_actor_domain,
     inbox::shared_inbox::{
       announce_if_community_is_local,
       get_user_from_activity,
       receive_unhandled_activity,
     },
+    post::upsert_post_media_from_apub,
     ActorType,
     FromApub,
     PageExt,
@@ -15,22 +27,47 @@ use crate::{
   },
   LemmyContext,
 };
-use activitystreams::{activity::Create, base::AnyBase, object::Note, prelude::*};
+use activitystreams::{
+  activity::Create,
+  base::AnyBase,
+  link::Mention,
+  object::Note,
+  prelude::*,
+  public,
+};
 use actix_web::HttpResponse;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use lemmy_api_structs::{
   blocking,
   comment::CommentResponse,
+  is_trusted,
   post::PostResponse,
   send_local_notifs,
 };
 use lemmy_db::{
   comment::{Comment, CommentForm},
   comment_view::CommentView,
+  community::{Community, CommunitySettings, MicroblogMentions},
+  domain::{DomainPostApproval, DomainPostApprovalForm},
   post::{Post, PostForm},
   post_view::PostView,
+  user::User_,
 };
-use lemmy_utils::{location_info, utils::scrape_text_for_mentions, LemmyError};
+use lemmy_utils::{
+  location_info,
+  settings::Settings,
+  utils::{
+    html_links,
+    html_to_text,
+    microblog_title,
+    remove_slurs,
+    scrape_text_for_mentions,
+    truncate_to_length,
+    MentionData,
+  },
+  LemmyError,
+};
+use url::Position;
 
 pub async fn receive_create(
   activity: AnyBase,
@@ -44,7 +81,14 @@ pub async fn receive_create(
 
   match create.object().as_single_kind_str() {
     Some("Page") => receive_create_post(create, context).await,
-    Some("Note") => receive_create_comment(create, context).await,
+    Some("Note") => {
+      let note = Note::from_any_base(create.object().to_owned().one().context(location_info!())?)?
+        .context(location_info!())?;
+      match mentioned_local_community(&note, context).await? {
+        Some(community) => receive_microblog_mention(create, note, user, community, context).await,
+        None => receive_create_comment(create, context).await,
+      }
+    }
     _ => receive_unhandled_activity(create),
   }
 }
@@ -57,14 +101,63 @@ async fn receive_create_post(
   let page = PageExt::from_any_base(create.object().to_owned().one().context(location_info!())?)?
     .context(location_info!())?;
 
-  let post = PostForm::from_apub(&page, context, Some(user.actor_id()?)).await?;
+  let mut post = PostForm::from_apub(&page, context, Some(user.actor_id()?)).await?;
+  check_community_membership(user.id, post.community_id, context.pool()).await?;
+
+  // Like local ones, posts of untrusted users linking to greylisted domains stay removed until
+  // they're approved
+  let greylisted_domain = check_domain_policy(&post.url, context.pool()).await?;
+  let greylisted_domain = match greylisted_domain {
+    Some(domain) => {
+      let user = user.to_owned();
+      let community_id = post.community_id;
+      let trusted = blocking(context.pool(), move |conn| {
+        is_trusted(conn, &user, Some(community_id))
+      })
+      .await??;
+      Some(domain).filter(|_| !trusted)
+    }
+    None => None,
+  };
+  if greylisted_domain.is_some() {
+    post.removed = Some(true);
+  }
 
   // Using an upsert, since likes (which fetch the post), sometimes come in before the create
   // resulting in double posts.
   let inserted_post = blocking(context.pool(), move |conn| Post::upsert(conn, &post)).await??;
+  upsert_post_media_from_apub(&page, inserted_post.id, context.pool()).await?;
+
+  let community_id = inserted_post.community_id;
+  if let Some(domain) = greylisted_domain {
+    let approval_form = DomainPostApprovalForm {
+      post_id: inserted_post.id,
+      domain_id: domain.id,
+    };
+    blocking(context.pool(), move |conn| {
+      DomainPostApproval::create(conn, &approval_form)
+    })
+    .await??;
+    send_mod_inbox_counts(community_id, context).await?;
+    // Waiting for approval, so it isn't announced, and doesn't notify anyone
+    return Ok(HttpResponse::Ok().finish());
+  }
+  notify_created_post(inserted_post, context).await?;
+
+  announce_if_community_is_local(create, &user, community_id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
 
+async fn notify_created_post(
+  inserted_post: Post,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
   // Refetch the view
   let inserted_post_id = inserted_post.id;
+  send_automod_comment(&inserted_post, context).await;
+  send_saved_search_matches(inserted_post.to_owned(), context, true).await?;
+  send_community_notifications(inserted_post, context, true).await?;
+
   let post_view = blocking(context.pool(), move |conn| {
     PostView::read(conn, inserted_post_id, None)
   })
@@ -77,9 +170,7 @@ async fn receive_create_post(
     post: res,
     websocket_id: None,
   });
-
-  announce_if_community_is_local(create, &user, context).await?;
-  Ok(HttpResponse::Ok().finish())
+  Ok(())
 }
 
 async fn receive_create_comment(
@@ -92,21 +183,39 @@ async fn receive_create_comment(
 
   let comment = CommentForm::from_apub(&note, context, Some(user.actor_id()?)).await?;
 
+  let post_id = comment.post_id;
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  check_community_membership(user.id, post.community_id, context.pool()).await?;
+  if post.local && post.comments_closed {
+    return Err(anyhow!("Comments on post {} are closed", post.ap_id).into());
+  }
+
   let inserted_comment =
     blocking(context.pool(), move |conn| Comment::upsert(conn, &comment)).await??;
 
-  let post_id = inserted_comment.post_id;
-  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
-
   // Note:
   // Although mentions could be gotten from the post tags (they are included there), or the ccs,
   // Its much easier to scrape them from the comment body, since the API has to do that
   // anyway.
   let mentions = scrape_text_for_mentions(&inserted_comment.content);
+  let community_id = post.community_id;
+  notify_created_comment(inserted_comment, mentions, &user, post, context).await?;
+
+  announce_if_community_is_local(create, &user, community_id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
+
+async fn notify_created_comment(
+  inserted_comment: Comment,
+  mentions: Vec<MentionData>,
+  user: &User_,
+  post: Post,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
   let recipient_ids = send_local_notifs(
     mentions,
     inserted_comment.clone(),
-    &user,
+    user,
     post,
     context.pool(),
     true,
@@ -130,7 +239,162 @@ async fn receive_create_comment(
     comment: res,
     websocket_id: None,
   });
+  Ok(())
+}
+
+/// The local community mentioned by a public note which doesn't reply to anything, the way
+/// Mastodon users address a community.
+async fn mentioned_local_community(
+  note: &Note,
+  context: &LemmyContext,
+) -> Result<Option<Community>, LemmyError> {
+  let public = public();
+  let is_public = note.to().map_or(false, |to| {
+    to.as_single_xsd_any_uri() == Some(&public)
+      || to.as_many().map_or(false, |to| {
+        to.iter().any(|t| t.as_xsd_any_uri() == Some(&public))
+      })
+  });
+  if note.in_reply_to().is_some() || !is_public {
+    return Ok(None);
+  }
+
+  let hostname = Settings::get().hostname;
+  let tags = note
+    .tag()
+    .and_then(|t| t.as_many())
+    .map(|t| t.to_vec())
+    .unwrap_or_default();
+  let mut mentioned = Vec::new();
+  for tag in tags {
+    if let Some(mention) = Mention::from_any_base(tag)? {
+      match mention.href() {
+        Some(href) if href[Position::BeforeHost..Position::AfterPort] == hostname => {
+          mentioned.push(href.to_string())
+        }
+        _ => {}
+      }
+    }
+  }
+  if mentioned.is_empty() {
+    return Ok(None);
+  }
+
+  let community = blocking(context.pool(), move |conn| {
+    mentioned
+      .iter()
+      .filter_map(|actor_id| Community::read_from_actor_id(conn, actor_id).ok())
+      .find(|c| c.local)
+  })
+  .await?;
+  Ok(community)
+}
+
+/// Turns a note from a microblog mentioning a local community into a post in it, or into a comment
+/// on the post of the community it links to, as the community chose. They aren't announced, other
+/// instances wouldn't take the note from us.
+async fn receive_microblog_mention(
+  create: Create,
+  note: Note,
+  user: User_,
+  community: Community,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let community_id = community.id;
+  let settings = blocking(context.pool(), move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  let handling = settings.parsed_microblog_mentions();
+  if settings.local_only || handling == MicroblogMentions::Ignore {
+    return receive_unhandled_activity(create);
+  }
+  check_community_membership(user.id, community_id, context.pool()).await?;
+
+  let ap_id = check_actor_domain(&note, Some(user.actor_id()?))?;
+  let html = note
+    .content()
+    .context(location_info!())?
+    .as_single_xsd_string()
+    .context(location_info!())?
+    .to_string();
+  let text = remove_slurs(&html_to_text(&html));
+  let published = note.published().map(|p| p.to_owned().naive_local());
+
+  let linked_post = if handling == MicroblogMentions::PostOrComment {
+    let links = html_links(&html);
+    blocking(context.pool(), move |conn| {
+      links
+        .iter()
+        .filter_map(|link| Post::read_from_apub_id(conn, link).ok())
+        .find(|p| p.community_id == community_id)
+    })
+    .await?
+  } else {
+    None
+  };
+
+  match linked_post {
+    Some(post) => {
+      if post.locked || (post.local && post.comments_closed) {
+        return Err(anyhow!("Comments on post {} are closed", post.ap_id).into());
+      }
+      let comment_form = CommentForm {
+        creator_id: user.id,
+        post_id: post.id,
+        parent_id: None,
+        content: truncate_to_length(&text, Settings::get().limits.max_comment_length),
+        removed: None,
+        read: None,
+        published,
+        updated: None,
+        deleted: None,
+        ap_id: Some(ap_id),
+        local: false,
+        distinguished: None,
+      };
+      let inserted_comment = blocking(context.pool(), move |conn| {
+        Comment::upsert(conn, &comment_form)
+      })
+      .await??;
+
+      // The mention of the community isn't one of a user
+      let mut mentions = scrape_text_for_mentions(&inserted_comment.content);
+      mentions.retain(|m| !(m.is_local() && m.name == community.name));
+      notify_created_comment(inserted_comment, mentions, &user, post, context).await?;
+    }
+    None => {
+      let limits = Settings::get().limits;
+      let name = microblog_title(&text);
+      let post_form = PostForm {
+        name: truncate_to_length(&name, limits.post_title_length()),
+        url: None,
+        body: Some(truncate_to_length(&text, limits.max_post_body_length)),
+        creator_id: user.id,
+        community_id,
+        removed: None,
+        deleted: None,
+        locked: None,
+        stickied: None,
+        updated: None,
+        nsfw: community.nsfw,
+        embed_title: None,
+        embed_description: None,
+        embed_html: None,
+        thumbnail_url: None,
+        thumbnail_alt_text: None,
+        distinguished: None,
+        anonymous: None,
+        comments_closed: None,
+        ap_id: Some(ap_id),
+        local: false,
+        published,
+      };
+      let inserted_post =
+        blocking(context.pool(), move |conn| Post::upsert(conn, &post_form)).await??;
+      notify_created_post(inserted_post, context).await?;
+    }
+  }
 
-  announce_if_community_is_local(create, &user, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
@@ -81,6 +81,10 @@ async fn receive_delete_post(
     embed_description: post.embed_description,
     embed_html: post.embed_html,
     thumbnail_url: post.thumbnail_url,
+    thumbnail_alt_text: post.thumbnail_alt_text,
+    distinguished: None,
+    anonymous: None,
+    comments_closed: None,
     ap_id: Some(post.ap_id),
     local: post.local,
     published: None,
@@ -98,6 +102,7 @@ async fn receive_delete_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -106,7 +111,7 @@ async fn receive_delete_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(delete, &user, context).await?;
+  announce_if_community_is_local(delete, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -136,6 +141,7 @@ async fn receive_delete_comment(
     updated: Some(naive_now()),
     ap_id: Some(comment.ap_id),
     local: comment.local,
+    distinguished: None,
   };
   let comment_id = comment.id;
   blocking(context.pool(), move |conn| {
@@ -152,6 +158,7 @@ async fn receive_delete_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -164,7 +171,7 @@ async fn receive_delete_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(delete, &user, context).await?;
+  announce_if_community_is_local(delete, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -229,6 +236,6 @@ async fn receive_delete_community(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(delete, &user, context).await?;
+  announce_if_community_is_local(delete, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
@@ -1,4 +1,5 @@
 use crate::{
+  api::{check_votes_unlocked, community::check_vote_burst},
   apub::{
     fetcher::{get_or_fetch_and_insert_comment, get_or_fetch_and_insert_post},
     inbox::shared_inbox::{
@@ -22,11 +23,12 @@ use lemmy_api_structs::{blocking, comment::CommentResponse, post::PostResponse};
 use lemmy_db::{
   comment::{CommentForm, CommentLike, CommentLikeForm},
   comment_view::CommentView,
-  post::{PostForm, PostLike, PostLikeForm},
+  post::{Post, PostForm, PostLike, PostLikeForm},
   post_view::PostView,
   Likeable,
 };
 use lemmy_utils::{location_info, LemmyError};
+use log::error;
 
 pub async fn receive_like(
   activity: AnyBase,
@@ -47,9 +49,9 @@ async fn receive_like_post(like: Like, context: &LemmyContext) -> Result<HttpRes
 
   let post = PostForm::from_apub(&page, context, None).await?;
 
-  let post_id = get_or_fetch_and_insert_post(&post.get_ap_id()?, context)
-    .await?
-    .id;
+  let post = get_or_fetch_and_insert_post(&post.get_ap_id()?, context).await?;
+  check_votes_unlocked(&post, context.pool()).await?;
+  let post_id = post.id;
 
   let like_form = PostLikeForm {
     post_id,
@@ -57,18 +59,26 @@ async fn receive_like_post(like: Like, context: &LemmyContext) -> Result<HttpRes
     score: 1,
   };
   let user_id = user.id;
-  blocking(context.pool(), move |conn| {
+  let post_like = blocking(context.pool(), move |conn| {
     PostLike::remove(conn, user_id, post_id)?;
     PostLike::like(conn, &like_form)
   })
   .await??;
 
+  // The vote is already stored, so the activity is handled either way
+  if let Some(instance) = post_like.instance {
+    if let Err(e) = check_vote_burst(post_id, instance, context).await {
+      error!("Couldn't check post {} for a vote burst: {}", post_id, e);
+    }
+  }
+
   // Refetch the view
   let post_view = blocking(context.pool(), move |conn| {
     PostView::read(conn, post_id, None)
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -77,7 +87,7 @@ async fn receive_like_post(like: Like, context: &LemmyContext) -> Result<HttpRes
     websocket_id: None,
   });
 
-  announce_if_community_is_local(like, &user, context).await?;
+  announce_if_community_is_local(like, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -94,6 +104,9 @@ async fn receive_like_comment(
   let comment_id = get_or_fetch_and_insert_comment(&comment.get_ap_id()?, context)
     .await?
     .id;
+  let post_id = comment.post_id;
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  check_votes_unlocked(&post, context.pool()).await?;
 
   let like_form = CommentLikeForm {
     comment_id,
@@ -116,6 +129,7 @@ async fn receive_like_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -128,6 +142,6 @@ async fn receive_like_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(like, &user, context).await?;
+  announce_if_community_is_local(like, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
@@ -1,3 +1,4 @@
+pub mod add;
 pub mod announce;
 pub mod create;
 pub mod delete;
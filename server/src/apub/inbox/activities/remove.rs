@@ -1,11 +1,14 @@
 use crate::{
   apub::{
     fetcher::{get_or_fetch_and_insert_comment, get_or_fetch_and_insert_post},
-    inbox::shared_inbox::{
-      announce_if_community_is_local,
-      get_community_id_from_activity,
-      get_user_from_activity,
-      receive_unhandled_activity,
+    inbox::{
+      activities::add::{get_moderator_change, send_moderators_to_websocket},
+      shared_inbox::{
+        announce_if_community_is_local,
+        get_community_id_from_activity,
+        get_user_from_activity,
+        receive_unhandled_activity,
+      },
     },
     ActorType,
     FromApub,
@@ -30,12 +33,15 @@ use lemmy_api_structs::{
 use lemmy_db::{
   comment::{Comment, CommentForm},
   comment_view::CommentView,
-  community::{Community, CommunityForm},
+  community::{Community, CommunityForm, CommunityModerator, CommunityModeratorForm},
   community_view::CommunityView,
+  moderator::{ModRemoveComment, ModRemoveCommentForm, ModRemovePost, ModRemovePostForm},
   naive_now,
   post::{Post, PostForm},
   post_view::PostView,
+  user::User_,
   Crud,
+  Joinable,
 };
 use lemmy_utils::{location_info, LemmyError};
 
@@ -54,10 +60,51 @@ pub async fn receive_remove(
     Some("Page") => receive_remove_post(remove, context).await,
     Some("Note") => receive_remove_comment(remove, context).await,
     Some("Group") => receive_remove_community(remove, context).await,
+    // Users are only sent by their id
+    None => receive_remove_mod(remove, actor, context).await,
     _ => receive_unhandled_activity(remove),
   }
 }
 
+/// Removing a user from the moderators collection of a community makes them a regular member.
+async fn receive_remove_mod(
+  remove: Remove,
+  actor: User_,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let target = remove
+    .target()
+    .context(location_info!())?
+    .as_single_xsd_any_uri()
+    .context(location_info!())?;
+  let object = remove
+    .object()
+    .as_single_xsd_any_uri()
+    .context(location_info!())?;
+  let (community, user) = get_moderator_change(&actor, target, object, false, context).await?;
+
+  let form = CommunityModeratorForm {
+    community_id: community.id,
+    user_id: user.id,
+  };
+  blocking(context.pool(), move |conn| {
+    CommunityModerator::leave(conn, &form)
+  })
+  .await??;
+
+  send_moderators_to_websocket(community.id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
+
+/// The reason that the mod gave for the removal, if any.
+fn get_remove_reason(remove: &Remove) -> Option<String> {
+  remove
+    .summary()
+    .map(|s| s.as_single_xsd_string())
+    .flatten()
+    .map(|s| s.to_string())
+}
+
 async fn receive_remove_post(
   remove: Remove,
   context: &LemmyContext,
@@ -88,6 +135,10 @@ async fn receive_remove_post(
     embed_description: post.embed_description,
     embed_html: post.embed_html,
     thumbnail_url: post.thumbnail_url,
+    thumbnail_alt_text: post.thumbnail_alt_text,
+    distinguished: None,
+    anonymous: None,
+    comments_closed: None,
     ap_id: Some(post.ap_id),
     local: post.local,
     published: None,
@@ -98,6 +149,17 @@ async fn receive_remove_post(
   })
   .await??;
 
+  let form = ModRemovePostForm {
+    mod_user_id: mod_.id,
+    post_id,
+    removed: Some(true),
+    reason: get_remove_reason(&remove),
+  };
+  blocking(context.pool(), move |conn| {
+    ModRemovePost::create(conn, &form)
+  })
+  .await??;
+
   // Refetch the view
   let post_id = post.id;
   let post_view = blocking(context.pool(), move |conn| {
@@ -105,6 +167,7 @@ async fn receive_remove_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -113,7 +176,7 @@ async fn receive_remove_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(remove, &mod_, context).await?;
+  announce_if_community_is_local(remove, &mod_, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -143,6 +206,7 @@ async fn receive_remove_comment(
     updated: Some(naive_now()),
     ap_id: Some(comment.ap_id),
     local: comment.local,
+    distinguished: None,
   };
   let comment_id = comment.id;
   blocking(context.pool(), move |conn| {
@@ -150,6 +214,17 @@ async fn receive_remove_comment(
   })
   .await??;
 
+  let form = ModRemoveCommentForm {
+    mod_user_id: mod_.id,
+    comment_id,
+    removed: Some(true),
+    reason: get_remove_reason(&remove),
+  };
+  blocking(context.pool(), move |conn| {
+    ModRemoveComment::create(conn, &form)
+  })
+  .await??;
+
   // Refetch the view
   let comment_id = comment.id;
   let comment_view = blocking(context.pool(), move |conn| {
@@ -159,6 +234,7 @@ async fn receive_remove_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -171,7 +247,7 @@ async fn receive_remove_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(remove, &mod_, context).await?;
+  announce_if_community_is_local(remove, &mod_, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -236,6 +312,6 @@ async fn receive_remove_community(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(remove, &mod_, context).await?;
+  announce_if_community_is_local(remove, &mod_, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
@@ -1,10 +1,13 @@
 use crate::{
   apub::{
     fetcher::{get_or_fetch_and_insert_comment, get_or_fetch_and_insert_post},
-    inbox::shared_inbox::{
-      announce_if_community_is_local,
-      get_user_from_activity,
-      receive_unhandled_activity,
+    inbox::{
+      activities::add::{get_pinned_comment_from_add, send_post_to_websocket},
+      shared_inbox::{
+        announce_if_community_is_local,
+        get_user_from_activity,
+        receive_unhandled_activity,
+      },
     },
     ActorType,
     FromApub,
@@ -54,6 +57,7 @@ pub async fn receive_undo(
     Some("Remove") => receive_undo_remove(undo, context).await,
     Some("Like") => receive_undo_like(undo, context).await,
     Some("Dislike") => receive_undo_dislike(undo, context).await,
+    Some("Add") => receive_undo_add(undo, context).await,
     _ => receive_unhandled_activity(undo),
   }
 }
@@ -118,6 +122,27 @@ async fn receive_undo_remove(
   }
 }
 
+/// Unpins a comment, unless another one was pinned in the meantime.
+async fn receive_undo_add(undo: Undo, context: &LemmyContext) -> Result<HttpResponse, LemmyError> {
+  let add = Add::from_any_base(undo.object().to_owned().one().context(location_info!())?)?
+    .context(location_info!())?;
+  check_is_undo_valid(&undo, &add)?;
+
+  let (user, comment, post) = get_pinned_comment_from_add(&add, context).await?;
+
+  if post.pinned_comment_id == Some(comment.id) {
+    let post_id = post.id;
+    blocking(context.pool(), move |conn| {
+      Post::update_pinned_comment(conn, post_id, None)
+    })
+    .await??;
+    send_post_to_websocket(post_id, context).await?;
+  }
+
+  announce_if_community_is_local(undo, &user, post.community_id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
+
 async fn receive_undo_like(undo: Undo, context: &LemmyContext) -> Result<HttpResponse, LemmyError> {
   let like = Like::from_any_base(undo.object().to_owned().one().context(location_info!())?)?
     .context(location_info!())?;
@@ -180,6 +205,7 @@ async fn receive_undo_delete_comment(
     updated: Some(naive_now()),
     ap_id: Some(comment.ap_id),
     local: comment.local,
+    distinguished: None,
   };
   let comment_id = comment.id;
   blocking(context.pool(), move |conn| {
@@ -196,6 +222,7 @@ async fn receive_undo_delete_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -208,7 +235,7 @@ async fn receive_undo_delete_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -239,6 +266,7 @@ async fn receive_undo_remove_comment(
     updated: Some(naive_now()),
     ap_id: Some(comment.ap_id),
     local: comment.local,
+    distinguished: None,
   };
   let comment_id = comment.id;
   blocking(context.pool(), move |conn| {
@@ -255,6 +283,7 @@ async fn receive_undo_remove_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -267,7 +296,7 @@ async fn receive_undo_remove_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &mod_, context).await?;
+  announce_if_community_is_local(undo, &mod_, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -302,6 +331,10 @@ async fn receive_undo_delete_post(
     embed_description: post.embed_description,
     embed_html: post.embed_html,
     thumbnail_url: post.thumbnail_url,
+    thumbnail_alt_text: post.thumbnail_alt_text,
+    distinguished: None,
+    anonymous: None,
+    comments_closed: None,
     ap_id: Some(post.ap_id),
     local: post.local,
     published: None,
@@ -319,6 +352,7 @@ async fn receive_undo_delete_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -327,7 +361,7 @@ async fn receive_undo_delete_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -362,6 +396,10 @@ async fn receive_undo_remove_post(
     embed_description: post.embed_description,
     embed_html: post.embed_html,
     thumbnail_url: post.thumbnail_url,
+    thumbnail_alt_text: post.thumbnail_alt_text,
+    distinguished: None,
+    anonymous: None,
+    comments_closed: None,
     ap_id: Some(post.ap_id),
     local: post.local,
     published: None,
@@ -379,6 +417,7 @@ async fn receive_undo_remove_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -387,7 +426,7 @@ async fn receive_undo_remove_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &mod_, context).await?;
+  announce_if_community_is_local(undo, &mod_, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -453,7 +492,7 @@ async fn receive_undo_delete_community(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -519,7 +558,7 @@ async fn receive_undo_remove_community(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &mod_, context).await?;
+  announce_if_community_is_local(undo, &mod_, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -552,6 +591,7 @@ async fn receive_undo_like_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -564,7 +604,7 @@ async fn receive_undo_like_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -595,6 +635,7 @@ async fn receive_undo_like_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -603,7 +644,7 @@ async fn receive_undo_like_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -642,6 +683,7 @@ async fn receive_undo_dislike_comment(
 
   // TODO get those recipient actor ids from somewhere
   let recipient_ids = vec![];
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -654,7 +696,7 @@ async fn receive_undo_dislike_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -691,6 +733,7 @@ async fn receive_undo_dislike_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -699,6 +742,6 @@ async fn receive_undo_dislike_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(undo, &user, context).await?;
+  announce_if_community_is_local(undo, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
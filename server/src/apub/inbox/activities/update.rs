@@ -1,15 +1,19 @@
 use crate::{
+  api::post::check_domain_policy,
   apub::{
-    fetcher::{get_or_fetch_and_insert_comment, get_or_fetch_and_insert_post},
+    fetcher::{get_or_fetch_and_insert_comment, get_or_fetch_and_insert_post, refetch_actor},
     inbox::shared_inbox::{
       announce_if_community_is_local,
       get_user_from_activity,
       receive_unhandled_activity,
     },
+    post::upsert_post_media_from_apub,
     ActorType,
     FromApub,
     PageExt,
   },
+  canonical_url::url_domain,
+  fetch_iframely_and_pictrs_data,
   websocket::{
     messages::{SendComment, SendPost},
     UserOperation,
@@ -18,17 +22,20 @@ use crate::{
 };
 use activitystreams::{activity::Update, base::AnyBase, object::Note, prelude::*};
 use actix_web::HttpResponse;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use lemmy_api_structs::{
   blocking,
   comment::CommentResponse,
+  is_trusted,
   post::PostResponse,
   send_local_notifs,
 };
 use lemmy_db::{
   comment::{Comment, CommentForm},
   comment_view::CommentView,
+  community::CommunitySettings,
   post::{Post, PostForm},
+  post_edit::PostEdit,
   post_view::PostView,
   Crud,
 };
@@ -40,6 +47,10 @@ pub async fn receive_update(
 ) -> Result<HttpResponse, LemmyError> {
   let update = Update::from_any_base(activity)?.context(location_info!())?;
 
+  if let Some("Person") | Some("Group") | Some("Service") = update.object().as_single_kind_str() {
+    return receive_update_actor(update, context).await;
+  }
+
   // ensure that update and actor come from the same instance
   let user = get_user_from_activity(&update, context).await?;
   update.id(user.actor_id()?.domain().context(location_info!())?)?;
@@ -51,6 +62,30 @@ pub async fn receive_update(
   }
 }
 
+/// A remote user or community changed its profile or its key. Actors can only update themselves,
+/// and are refetched from their instance rather than taken from the activity.
+async fn receive_update_actor(
+  update: Update,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let actor_id = update
+    .actor()?
+    .as_single_xsd_any_uri()
+    .context(location_info!())?
+    .to_owned();
+  let object = update.object().as_one().context(location_info!())?;
+  let object_id = object
+    .id()
+    .or_else(|| object.as_xsd_any_uri())
+    .context(location_info!())?;
+  if object_id != &actor_id {
+    return Err(anyhow!("{} can't update {}", actor_id, object_id).into());
+  }
+
+  refetch_actor(&actor_id, context).await?;
+  Ok(HttpResponse::Ok().finish())
+}
+
 async fn receive_update_post(
   update: Update,
   context: &LemmyContext,
@@ -59,16 +94,57 @@ async fn receive_update_post(
   let page = PageExt::from_any_base(update.object().to_owned().one().context(location_info!())?)?
     .context(location_info!())?;
 
-  let post = PostForm::from_apub(&page, context, Some(user.actor_id()?)).await?;
+  let mut post = PostForm::from_apub(&page, context, Some(user.actor_id()?)).await?;
+
+  let original_post = get_or_fetch_and_insert_post(&post.get_ap_id()?, context).await?;
+  let original_post_id = original_post.id;
+
+  // Like local edits, a post can't be moved to a greylisted domain without approval
+  let greylisted_domain = check_domain_policy(&post.url, context.pool()).await?;
+  let orig_domain = original_post.url.as_deref().and_then(url_domain);
+  if greylisted_domain.map_or(false, |d| Some(d.name) != orig_domain) {
+    let user = user.to_owned();
+    let community_id = original_post.community_id;
+    let trusted = blocking(context.pool(), move |conn| {
+      is_trusted(conn, &user, Some(community_id))
+    })
+    .await??;
+    if !trusted {
+      return Err(anyhow!("Post was moved to a domain which requires approval").into());
+    }
+  }
 
-  let original_post_id = get_or_fetch_and_insert_post(&post.get_ap_id()?, context)
-    .await?
-    .id;
+  if post.url != original_post.url {
+    let community_id = original_post.community_id;
+    let settings = blocking(context.pool(), move |conn| {
+      CommunitySettings::read_for_community(conn, community_id)
+    })
+    .await??;
+    if original_post.url.is_some() && !settings.allow_post_url_edits {
+      // Link posts of communities which don't allow url edits keep what they linked to
+      post.url = original_post.url.to_owned();
+      post.embed_title = original_post.embed_title.to_owned();
+      post.embed_description = original_post.embed_description.to_owned();
+      post.embed_html = original_post.embed_html.to_owned();
+      post.thumbnail_url = original_post.thumbnail_url.to_owned();
+    } else {
+      // Like for local edits, the embed and thumbnail of the new url are fetched again
+      let (iframely_title, iframely_description, iframely_html, pictrs_thumbnail) =
+        fetch_iframely_and_pictrs_data(context.client(), post.url.to_owned()).await;
+      post.embed_title = iframely_title;
+      post.embed_description = iframely_description;
+      post.embed_html = iframely_html;
+      post.thumbnail_url = pictrs_thumbnail.or(post.thumbnail_url);
+    }
+  }
 
+  let editor_id = user.id;
   blocking(context.pool(), move |conn| {
+    PostEdit::record(conn, &original_post, &post, editor_id)?;
     Post::update(conn, original_post_id, &post)
   })
   .await??;
+  upsert_post_media_from_apub(&page, original_post_id, context.pool()).await?;
 
   // Refetch the view
   let post_view = blocking(context.pool(), move |conn| {
@@ -76,6 +152,7 @@ async fn receive_update_post(
   })
   .await??;
 
+  let community_id = post_view.community_id;
   let res = PostResponse { post: post_view };
 
   context.chat_server().do_send(SendPost {
@@ -84,7 +161,7 @@ async fn receive_update_post(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(update, &user, context).await?;
+  announce_if_community_is_local(update, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
 
@@ -127,6 +204,7 @@ async fn receive_update_comment(
   })
   .await??;
 
+  let community_id = comment_view.community_id;
   let res = CommentResponse {
     comment: comment_view,
     recipient_ids,
@@ -139,6 +217,6 @@ async fn receive_update_comment(
     websocket_id: None,
   });
 
-  announce_if_community_is_local(update, &user, context).await?;
+  announce_if_community_is_local(update, &user, community_id, context).await?;
   Ok(HttpResponse::Ok().finish())
 }
@@ -1,15 +1,31 @@
 use crate::{
+  api::community::{send_mod_inbox_counts, send_welcome_message, MAX_REPORT_REASON_LENGTH},
   apub::{
     check_is_apub_id_valid,
-    extensions::signatures::verify,
+    community::send_reject_join_request,
+    extensions::signatures::{verify_or_refetch, SignedRequest},
     fetcher::get_or_fetch_and_upsert_user,
+    inbox::{
+      activities::{
+        create::receive_create,
+        delete::receive_delete,
+        dislike::receive_dislike,
+        like::receive_like,
+        undo::receive_undo,
+        update::receive_update,
+      },
+      read_only::refuse_when_read_only,
+      received::receive_once,
+      shared_inbox::{get_recipients, is_addressed_to, receive_unhandled_activity},
+    },
     insert_activity,
+    post::is_community_withheld,
     ActorType,
   },
   LemmyContext,
 };
 use activitystreams::{
-  activity::{ActorAndObject, Follow, Undo},
+  activity::{ActorAndObject, Flag, Follow, Undo},
   base::AnyBase,
   prelude::*,
 };
@@ -17,11 +33,15 @@ use actix_web::{web, HttpRequest, HttpResponse};
 use anyhow::{anyhow, Context};
 use lemmy_api_structs::blocking;
 use lemmy_db::{
-  community::{Community, CommunityFollower, CommunityFollowerForm},
+  comment::Comment,
+  community::{Community, CommunityFollower, CommunityFollowerForm, CommunitySettings},
+  community_join_request::{CommunityJoinRequest, CommunityJoinRequestForm},
+  content_report::{ContentReport, ContentReportForm},
+  post::Post,
   user::User_,
   Followable,
 };
-use lemmy_utils::{location_info, LemmyError};
+use lemmy_utils::{location_info, utils::check_slurs, LemmyError};
 use log::debug;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
@@ -31,6 +51,12 @@ use std::fmt::Debug;
 pub enum ValidTypes {
   Follow,
   Undo,
+  Flag,
+  Create,
+  Update,
+  Like,
+  Dislike,
+  Delete,
 }
 
 pub type AcceptedActivities = ActorAndObject<ValidTypes>;
@@ -42,9 +68,20 @@ pub async fn community_inbox(
   path: web::Path<String>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, LemmyError> {
-  let activity = input.into_inner();
+  if let Some(res) = refuse_when_read_only() {
+    return Ok(res);
+  }
+  let request = SignedRequest::new(&request);
+  receive_community_activity(request, input.into_inner(), path.into_inner(), &context).await
+}
 
-  let path = path.into_inner();
+async fn receive_community_activity(
+  request: SignedRequest,
+  activity: AcceptedActivities,
+  path: String,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let context = context.with_fetch_budget();
   let community = blocking(&context.pool(), move |conn| {
     Community::read_from_name(&conn, &path)
   })
@@ -71,22 +108,33 @@ pub async fn community_inbox(
 
   let user = get_or_fetch_and_upsert_user(&user_uri, &context).await?;
 
-  verify(&request, &user)?;
+  verify_or_refetch(&request, &user, &context).await?;
 
-  let any_base = activity.clone().into_any_base()?;
-  let kind = activity.kind().context(location_info!())?;
-  let user_id = user.id;
-  let res = match kind {
-    ValidTypes::Follow => handle_follow(any_base, user, community, &context).await,
-    ValidTypes::Undo => handle_undo_follow(any_base, user, community, &context).await,
-  };
+  let inbox = format!("/c/{}/inbox", &community.name);
+  let activity_id = activity.id_unchecked().map(|id| id.to_owned());
+  receive_once(&inbox, activity_id.as_ref(), user_uri, &context, async {
+    let any_base = activity.clone().into_any_base()?;
+    let kind = activity.kind().context(location_info!())?;
+    let recipients = get_recipients(&activity);
+    let user_id = user.id;
+    let res = match kind {
+      ValidTypes::Follow => handle_follow(any_base, user, community, &context).await,
+      ValidTypes::Undo if is_undo_follow(&any_base)? => {
+        handle_undo_follow(any_base, user, community, &context).await
+      }
+      ValidTypes::Flag => handle_flag(any_base, user, community, &context).await,
+      _ => receive_community_content(any_base, kind, &recipients, &community, &context).await,
+    };
 
-  insert_activity(user_id, activity.clone(), false, context.pool()).await?;
-  res
+    insert_activity(user_id, activity.clone(), false, context.pool()).await?;
+    res
+  })
+  .await
 }
 
 /// Handle a follow request from a remote user, adding it to the local database and returning an
-/// Accept activity.
+/// Accept activity. If the community requires approval to join, the follow waits for the mods
+/// as a join request instead. Local only communities reject it.
 async fn handle_follow(
   activity: AnyBase,
   user: User_,
@@ -94,6 +142,42 @@ async fn handle_follow(
   context: &LemmyContext,
 ) -> Result<HttpResponse, LemmyError> {
   let follow = Follow::from_any_base(activity)?.context(location_info!())?;
+
+  let community_id = community.id;
+  let user_id = user.id;
+  let (local_only, needs_approval) = blocking(
+    &context.pool(),
+    move |conn| -> Result<_, diesel::result::Error> {
+      let settings = CommunitySettings::read_for_community(conn, community_id)?;
+      let is_follower = CommunityFollower::is_follower(conn, community_id, user_id)?;
+      Ok((
+        settings.local_only,
+        settings.requires_membership() && !is_follower,
+      ))
+    },
+  )
+  .await??;
+
+  if local_only {
+    send_reject_join_request(&community, &user, follow, context).await?;
+    return Ok(HttpResponse::Ok().finish());
+  }
+
+  if needs_approval {
+    let join_request_form = CommunityJoinRequestForm {
+      community_id,
+      user_id,
+      application: None,
+      follow: Some(serde_json::to_value(&follow)?),
+    };
+    blocking(&context.pool(), move |conn| {
+      CommunityJoinRequest::apply(conn, &join_request_form)
+    })
+    .await??;
+    send_mod_inbox_counts(community_id, context).await?;
+    return Ok(HttpResponse::Ok().finish());
+  }
+
   let community_follower_form = CommunityFollowerForm {
     community_id: community.id,
     user_id: user.id,
@@ -106,10 +190,54 @@ async fn handle_follow(
   .await?;
 
   community.send_accept_follow(follow, context).await?;
+  send_welcome_message(community.id, &user, context).await?;
 
   Ok(HttpResponse::Ok().finish())
 }
 
+/// Older versions undid follows without saying what they undo.
+fn is_undo_follow(activity: &AnyBase) -> Result<bool, LemmyError> {
+  let undo = Undo::from_any_base(activity.to_owned())?.context(location_info!())?;
+  Ok(matches!(
+    undo.object().as_single_kind_str(),
+    Some("Follow") | None
+  ))
+}
+
+/// Posts, comments and votes which are sent right to the community, like Mastodon does with groups.
+/// They are handled like in the shared inbox, which also announces them to the followers of the
+/// community.
+async fn receive_community_content(
+  activity: AnyBase,
+  kind: &ValidTypes,
+  recipients: &[String],
+  community: &Community,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  if !is_addressed_to(recipients, community) {
+    return Err(
+      anyhow!(
+        "Received activity isn't addressed to community {}",
+        &community.actor_id
+      )
+      .into(),
+    );
+  }
+  if is_community_withheld(community.id, context.pool()).await? {
+    return Err(anyhow!("Community {} doesn't federate", &community.actor_id).into());
+  }
+
+  match kind {
+    ValidTypes::Create => receive_create(activity, context).await,
+    ValidTypes::Update => receive_update(activity, context).await,
+    ValidTypes::Like => receive_like(activity, context).await,
+    ValidTypes::Dislike => receive_dislike(activity, context).await,
+    ValidTypes::Delete => receive_delete(activity, context).await,
+    ValidTypes::Undo => receive_undo(activity, context).await,
+    _ => receive_unhandled_activity(activity),
+  }
+}
+
 async fn handle_undo_follow(
   activity: AnyBase,
   user: User_,
@@ -123,11 +251,81 @@ async fn handle_undo_follow(
     user_id: user.id,
   };
 
-  // This will fail if they aren't a follower, but ignore the error.
-  blocking(&context.pool(), move |conn| {
-    CommunityFollower::unfollow(&conn, &community_follower_form).ok()
+  // This will fail if they aren't a follower, but ignore the error. A pending join request is
+  // withdrawn too.
+  let withdrawn = blocking(&context.pool(), move |conn| {
+    let withdrawn = CommunityJoinRequest::remove(
+      &conn,
+      community_follower_form.community_id,
+      community_follower_form.user_id,
+    );
+    CommunityFollower::unfollow(&conn, &community_follower_form).ok();
+    withdrawn
   })
   .await?;
+  if withdrawn.unwrap_or(0) > 0 {
+    send_mod_inbox_counts(community.id, context).await?;
+  }
+
+  Ok(HttpResponse::Ok().finish())
+}
+
+/// A remote user reported a post or comment of the community. The report goes to the mod inbox, a
+/// Flag which was received before is ignored.
+async fn handle_flag(
+  activity: AnyBase,
+  user: User_,
+  community: Community,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let flag = Flag::from_any_base(activity)?.context(location_info!())?;
+  let flag_id = flag.id_unchecked().context(location_info!())?.to_string();
+  let object_id = flag
+    .object()
+    .as_single_xsd_any_uri()
+    .context(location_info!())?
+    .to_string();
+  // Mastodon puts the reason in the content instead
+  let reason = flag
+    .summary()
+    .or_else(|| flag.content())
+    .map(|s| s.as_single_xsd_string())
+    .flatten()
+    .unwrap_or("")
+    .trim()
+    .chars()
+    .take(MAX_REPORT_REASON_LENGTH)
+    .collect::<String>();
+  check_slurs(&reason)?;
+
+  let community_id = community.id;
+  let user_id = user.id;
+  let report = blocking(&context.pool(), move |conn| -> Result<_, LemmyError> {
+    let (post_id, comment_id, reported_community_id) =
+      match Post::read_from_apub_id(conn, &object_id) {
+        Ok(post) => (Some(post.id), None, post.community_id),
+        Err(_) => {
+          let comment = Comment::read_from_apub_id(conn, &object_id)?;
+          let post = Post::read(conn, comment.post_id)?;
+          (None, Some(comment.id), post.community_id)
+        }
+      };
+    if reported_community_id != community_id {
+      return Err(anyhow!("Reported object {} isn't in the community", object_id).into());
+    }
+    let form = ContentReportForm {
+      creator_id: user_id,
+      post_id,
+      comment_id,
+      reason,
+      ap_id: Some(flag_id),
+    };
+    Ok(ContentReport::report(conn, &form)?)
+  })
+  .await??;
+  if report.is_some() {
+    send_mod_inbox_counts(community_id, context).await?;
+  }
 
   Ok(HttpResponse::Ok().finish())
 }
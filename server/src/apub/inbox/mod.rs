@@ -1,4 +1,6 @@
 pub mod activities;
 pub mod community_inbox;
+pub mod read_only;
+pub mod received;
 pub mod shared_inbox;
 pub mod user_inbox;
@@ -0,0 +1,23 @@
+//! Activities which arrive while the site is read only are refused with a `Retry-After` header, so
+//! that their senders keep them in their own queues, and deliver them again once the site is
+//! writable. Nothing is lost when the server restarts meanwhile.
+use actix_web::{
+  http::header::{HeaderValue, RETRY_AFTER},
+  HttpResponse,
+};
+use lemmy_utils::settings::Settings;
+
+/// How long senders are asked to wait.
+const RETRY_AFTER_SECONDS: &str = "600";
+
+/// Returns the response for the sender if the site is read only.
+pub(in crate::apub::inbox) fn refuse_when_read_only() -> Option<HttpResponse> {
+  if !Settings::get().maintenance.read_only {
+    return None;
+  }
+  Some(
+    HttpResponse::ServiceUnavailable()
+      .header(RETRY_AFTER, HeaderValue::from_static(RETRY_AFTER_SECONDS))
+      .finish(),
+  )
+}
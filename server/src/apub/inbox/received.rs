@@ -0,0 +1,139 @@
+//! Each inbox handles an activity only once. Senders deliver activities again when they don't get
+//! an answer in time, so the ids of received activities are kept for two weeks, and activities
+//! which arrive again are answered with 200 without being handled. While the first delivery is
+//! still being handled, others are answered with 503, so that the sender tries again in case it
+//! fails.
+use crate::LemmyContext;
+use actix_web::{http::header::RETRY_AFTER, HttpResponse};
+use lemmy_api_structs::blocking;
+use lemmy_db::{naive_now, received_activity::ReceivedActivity};
+use lemmy_utils::{settings::Settings, LemmyError};
+use log::{debug, error};
+use std::{collections::HashSet, future::Future, sync::Mutex, time::Duration};
+use url::Url;
+
+/// Senders give up on an activity before this, Lemmy after about a week.
+const KEEP_RECEIVED_ACTIVITY_DAYS: i64 = 14;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// In seconds, for duplicates of an activity which is still being handled.
+const RETRY_AFTER_SECONDS: &str = "60";
+
+lazy_static! {
+  /// The inboxes and ids of the activities which are being handled.
+  static ref IN_FLIGHT: Mutex<HashSet<(String, String)>> = Mutex::new(HashSet::new());
+}
+
+/// Marks an activity as being handled, until it's dropped.
+struct InFlight {
+  key: (String, String),
+}
+
+impl InFlight {
+  /// None if the activity is being handled already.
+  fn start(inbox: &str, activity_id: &str) -> Option<InFlight> {
+    let key = (inbox.to_owned(), activity_id.to_owned());
+    if !IN_FLIGHT.lock().unwrap().insert(key.to_owned()) {
+      return None;
+    }
+    Some(InFlight { key })
+  }
+}
+
+impl Drop for InFlight {
+  fn drop(&mut self) {
+    IN_FLIGHT.lock().unwrap().remove(&self.key);
+  }
+}
+
+/// Handles the activity, unless the inbox received it before. Ids are only kept if they are on the
+/// domain of the actor, otherwise anyone could send an activity with the id of someone else's
+/// activity first, and the real one would be skipped. If handling fails, the id is forgotten again,
+/// so that the activity is handled when the sender retries it.
+pub(in crate::apub::inbox) async fn receive_once<F>(
+  inbox: &str,
+  activity_id: Option<&Url>,
+  actor_id: &Url,
+  context: &LemmyContext,
+  handle: F,
+) -> Result<HttpResponse, LemmyError>
+where
+  F: Future<Output = Result<HttpResponse, LemmyError>>,
+{
+  let activity_id = match activity_id.filter(|id| id.domain() == actor_id.domain()) {
+    Some(activity_id) => activity_id.to_string(),
+    None => return handle.await,
+  };
+
+  let _in_flight = match InFlight::start(inbox, &activity_id) {
+    Some(in_flight) => in_flight,
+    None => {
+      debug!("Inbox {} is still handling activity {}", inbox, activity_id);
+      return Ok(
+        HttpResponse::ServiceUnavailable()
+          .header(RETRY_AFTER, RETRY_AFTER_SECONDS)
+          .finish(),
+      );
+    }
+  };
+
+  let inbox = inbox.to_owned();
+  let (for_inbox, for_activity_id) = (inbox.to_owned(), activity_id.to_owned());
+  let first_time = blocking(context.pool(), move |conn| {
+    ReceivedActivity::insert(conn, &for_inbox, &for_activity_id)
+  })
+  .await??;
+  if !first_time {
+    debug!("Inbox {} already received activity {}", inbox, activity_id);
+    return Ok(HttpResponse::Ok().finish());
+  }
+
+  let res = handle.await;
+  if res.is_err() {
+    blocking(context.pool(), move |conn| {
+      ReceivedActivity::remove(conn, &inbox, &activity_id)
+    })
+    .await??;
+  }
+  res
+}
+
+pub async fn run_received_activity_cleanup_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(CLEANUP_INTERVAL);
+  loop {
+    interval.tick().await;
+    if Settings::get().maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = delete_old_received_activities(&context).await {
+      error!("Couldn't delete the old received activity ids: {}", e);
+    }
+  }
+}
+
+async fn delete_old_received_activities(context: &LemmyContext) -> Result<(), LemmyError> {
+  let received_before = naive_now() - chrono::Duration::days(KEEP_RECEIVED_ACTIVITY_DAYS);
+  let deleted = blocking(context.pool(), move |conn| {
+    ReceivedActivity::delete_older_than(conn, received_before)
+  })
+  .await??;
+  debug!("Deleted {} old received activity ids", deleted);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::apub::inbox::received::InFlight;
+
+  #[test]
+  fn test_in_flight() {
+    let inbox = "https://example.com/inbox";
+    let activity_id = "https://example.net/activities/1";
+    let in_flight = InFlight::start(inbox, activity_id).unwrap();
+    assert!(InFlight::start(inbox, activity_id).is_none());
+    assert!(InFlight::start(inbox, "https://example.net/activities/2").is_some());
+    drop(in_flight);
+    assert!(InFlight::start(inbox, activity_id).is_some());
+  }
+}
@@ -2,35 +2,38 @@ use crate::{
   apub::{
     check_is_apub_id_valid,
     community::do_announce,
-    extensions::signatures::verify,
-    fetcher::{
-      get_or_fetch_and_upsert_actor,
-      get_or_fetch_and_upsert_community,
-      get_or_fetch_and_upsert_user,
-    },
-    inbox::activities::{
-      announce::receive_announce,
-      create::receive_create,
-      delete::receive_delete,
-      dislike::receive_dislike,
-      like::receive_like,
-      remove::receive_remove,
-      undo::receive_undo,
-      update::receive_update,
+    extensions::signatures::{verify_or_refetch, SignedRequest},
+    fetcher::{get_or_fetch_and_upsert_actor, get_or_fetch_and_upsert_user},
+    inbox::{
+      activities::{
+        add::receive_add,
+        announce::receive_announce,
+        create::receive_create,
+        delete::receive_delete,
+        dislike::receive_dislike,
+        like::receive_like,
+        remove::receive_remove,
+        undo::receive_undo,
+        update::receive_update,
+      },
+      read_only::refuse_when_read_only,
+      received::receive_once,
     },
     insert_activity,
+    post::is_community_withheld,
   },
   LemmyContext,
 };
 use activitystreams::{
   activity::{ActorAndObject, ActorAndObjectRef},
-  base::{AsBase, Extends},
+  base::{AnyBase, AsBase, Extends},
   object::AsObject,
   prelude::*,
 };
 use actix_web::{web, HttpRequest, HttpResponse};
-use anyhow::Context;
-use lemmy_db::user::User_;
+use anyhow::{anyhow, Context};
+use lemmy_api_structs::blocking;
+use lemmy_db::{community::Community, user::User_, Crud};
 use lemmy_utils::{location_info, LemmyError};
 use log::debug;
 use serde::{Deserialize, Serialize};
@@ -48,6 +51,7 @@ pub enum ValidTypes {
   Undo,
   Remove,
   Announce,
+  Add,
 }
 
 // TODO: this isnt entirely correct, cause some of these activities are not ActorAndObject,
@@ -60,13 +64,23 @@ pub async fn shared_inbox(
   input: web::Json<AcceptedActivities>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, LemmyError> {
-  let activity = input.into_inner();
+  if let Some(res) = refuse_when_read_only() {
+    return Ok(res);
+  }
+  let request = SignedRequest::new(&request);
+  receive_shared_activity(request, input.into_inner(), &context).await
+}
+
+async fn receive_shared_activity(
+  request: SignedRequest,
+  activity: AcceptedActivities,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let context = context.with_fetch_budget();
 
   let json = serde_json::to_string(&activity)?;
   debug!("Shared inbox received activity: {}", json);
 
-  // TODO: if we already received an activity with identical ID, then ignore this (same in other inboxes)
-
   let sender = &activity
     .actor()?
     .to_owned()
@@ -76,25 +90,53 @@ pub async fn shared_inbox(
 
   check_is_apub_id_valid(sender)?;
   check_is_apub_id_valid(&community)?;
+  check_community_federates(&community, &context).await?;
 
   let actor = get_or_fetch_and_upsert_actor(sender, &context).await?;
-  verify(&request, actor.as_ref())?;
-
-  let any_base = activity.clone().into_any_base()?;
-  let kind = activity.kind().context(location_info!())?;
-  let res = match kind {
-    ValidTypes::Announce => receive_announce(any_base, &context).await,
-    ValidTypes::Create => receive_create(any_base, &context).await,
-    ValidTypes::Update => receive_update(any_base, &context).await,
-    ValidTypes::Like => receive_like(any_base, &context).await,
-    ValidTypes::Dislike => receive_dislike(any_base, &context).await,
-    ValidTypes::Remove => receive_remove(any_base, &context).await,
-    ValidTypes::Delete => receive_delete(any_base, &context).await,
-    ValidTypes::Undo => receive_undo(any_base, &context).await,
-  };
-
-  insert_activity(actor.user_id(), activity.clone(), false, context.pool()).await?;
-  res
+  verify_or_refetch(&request, actor.as_ref(), &context).await?;
+
+  let activity_id = activity.id_unchecked().map(|id| id.to_owned());
+  receive_once("/inbox", activity_id.as_ref(), sender, &context, async {
+    let any_base = activity.clone().into_any_base()?;
+    let kind = activity.kind().context(location_info!())?;
+    let res = match kind {
+      ValidTypes::Announce => receive_announce(any_base, &context).await,
+      ValidTypes::Create => receive_create(any_base, &context).await,
+      ValidTypes::Update => receive_update(any_base, &context).await,
+      ValidTypes::Like => receive_like(any_base, &context).await,
+      ValidTypes::Dislike => receive_dislike(any_base, &context).await,
+      ValidTypes::Remove => receive_remove(any_base, &context).await,
+      ValidTypes::Delete => receive_delete(any_base, &context).await,
+      ValidTypes::Undo => receive_undo(any_base, &context).await,
+      ValidTypes::Add => receive_add(any_base, &context).await,
+    };
+
+    insert_activity(actor.user_id(), activity.clone(), false, context.pool()).await?;
+    res
+  })
+  .await
+}
+
+/// The content of private and local only communities doesn't federate, so activities for them are
+/// refused.
+async fn check_community_federates(
+  community_uri: &Url,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  // The activity is addressed to the followers of the community
+  let community_actor_id = community_uri.to_string().replace("/followers", "");
+  let actor_id = community_actor_id.to_owned();
+  let community = blocking(context.pool(), move |conn| {
+    Community::read_from_actor_id(conn, &actor_id)
+  })
+  .await?;
+
+  if let Ok(community) = community {
+    if community.local && is_community_withheld(community.id, context.pool()).await? {
+      return Err(anyhow!("Community {} doesn't federate", community_actor_id).into());
+    }
+  }
+  Ok(())
 }
 
 pub(in crate::apub::inbox) fn receive_unhandled_activity<A>(
@@ -119,9 +161,7 @@ where
   get_or_fetch_and_upsert_user(&user_uri, context).await
 }
 
-pub(in crate::apub::inbox) fn get_community_id_from_activity<T, A>(
-  activity: &T,
-) -> Result<Url, LemmyError>
+pub(in crate::apub) fn get_community_id_from_activity<T, A>(activity: &T) -> Result<Url, LemmyError>
 where
   T: AsBase<A> + ActorAndObjectRef + AsObject<A>,
 {
@@ -136,9 +176,13 @@ where
   )
 }
 
+/// Announces the activity to the followers of the community which its object belongs to, if that
+/// community is local. The community is taken from the object rather than from the addressing,
+/// so that an activity can't be announced to some other community by listing it as a recipient.
 pub(in crate::apub::inbox) async fn announce_if_community_is_local<T, Kind>(
   activity: T,
   user: &User_,
+  community_id: i32,
   context: &LemmyContext,
 ) -> Result<(), LemmyError>
 where
@@ -147,21 +191,42 @@ where
   Kind: Serialize,
   <T as Extends<Kind>>::Error: From<serde_json::Error> + Send + Sync + 'static,
 {
-  let cc = activity.cc().context(location_info!())?;
-  let cc = cc.as_many().context(location_info!())?;
-  let community_followers_uri = cc
-    .first()
-    .context(location_info!())?
-    .as_xsd_any_uri()
-    .context(location_info!())?;
-  // TODO: this is hacky but seems to be the only way to get the community ID
-  let community_uri = community_followers_uri
-    .to_string()
-    .replace("/followers", "");
-  let community = get_or_fetch_and_upsert_community(&Url::parse(&community_uri)?, context).await?;
-
+  let community = blocking(context.pool(), move |conn| {
+    Community::read(conn, community_id)
+  })
+  .await??;
   if community.local {
     do_announce(activity.into_any_base()?, &community, &user, context).await?;
   }
   Ok(())
 }
+
+/// The ids in `to` and `cc` of the activity.
+pub(in crate::apub::inbox) fn get_recipients<T, Kind>(activity: &T) -> Vec<String>
+where
+  T: AsObject<Kind>,
+{
+  activity
+    .to()
+    .into_iter()
+    .chain(activity.cc())
+    .flat_map(|recipients| match recipients.as_many() {
+      Some(many) => many.iter().collect::<Vec<&AnyBase>>(),
+      None => recipients.as_one().into_iter().collect(),
+    })
+    .filter_map(|recipient| recipient.as_xsd_any_uri())
+    .map(|recipient| recipient.to_string())
+    .collect()
+}
+
+/// Whether the community is a recipient of the activity, either itself, like Mastodon addresses
+/// groups, or through its followers, like Lemmy does.
+pub(in crate::apub::inbox) fn is_addressed_to(
+  recipients: &[String],
+  community: &Community,
+) -> bool {
+  let followers = format!("{}/followers", community.actor_id);
+  recipients
+    .iter()
+    .any(|r| r == &community.actor_id || r == &followers)
+}
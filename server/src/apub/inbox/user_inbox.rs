@@ -1,8 +1,9 @@
 use crate::{
   apub::{
     check_is_apub_id_valid,
-    extensions::signatures::verify,
+    extensions::signatures::{verify_or_refetch, SignedRequest},
     fetcher::{get_or_fetch_and_upsert_actor, get_or_fetch_and_upsert_community},
+    inbox::{read_only::refuse_when_read_only, received::receive_once},
     insert_activity,
     FromApub,
   },
@@ -10,7 +11,7 @@ use crate::{
   LemmyContext,
 };
 use activitystreams::{
-  activity::{Accept, ActorAndObject, Create, Delete, Undo, Update},
+  activity::{Accept, ActorAndObject, Create, Delete, Reject, Undo, Update},
   base::AnyBase,
   object::Note,
   prelude::*,
@@ -20,6 +21,7 @@ use anyhow::Context;
 use lemmy_api_structs::{blocking, user::PrivateMessageResponse};
 use lemmy_db::{
   community::{CommunityFollower, CommunityFollowerForm},
+  community_join_request::CommunityJoinRequest,
   naive_now,
   private_message::{PrivateMessage, PrivateMessageForm},
   private_message_view::PrivateMessageView,
@@ -36,6 +38,7 @@ use std::fmt::Debug;
 #[serde(rename_all = "PascalCase")]
 pub enum ValidTypes {
   Accept,
+  Reject,
   Create,
   Update,
   Delete,
@@ -51,8 +54,20 @@ pub async fn user_inbox(
   path: web::Path<String>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, LemmyError> {
-  let activity = input.into_inner();
-  let username = path.into_inner();
+  if let Some(res) = refuse_when_read_only() {
+    return Ok(res);
+  }
+  let request = SignedRequest::new(&request);
+  receive_user_activity(request, input.into_inner(), path.into_inner(), &context).await
+}
+
+async fn receive_user_activity(
+  request: SignedRequest,
+  activity: AcceptedActivities,
+  username: String,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let context = context.with_fetch_budget();
   debug!("User {} received activity: {:?}", &username, &activity);
 
   let actor_uri = activity
@@ -63,20 +78,26 @@ pub async fn user_inbox(
   check_is_apub_id_valid(actor_uri)?;
 
   let actor = get_or_fetch_and_upsert_actor(actor_uri, &context).await?;
-  verify(&request, actor.as_ref())?;
-
-  let any_base = activity.clone().into_any_base()?;
-  let kind = activity.kind().context(location_info!())?;
-  let res = match kind {
-    ValidTypes::Accept => receive_accept(any_base, username, &context).await,
-    ValidTypes::Create => receive_create_private_message(any_base, &context).await,
-    ValidTypes::Update => receive_update_private_message(any_base, &context).await,
-    ValidTypes::Delete => receive_delete_private_message(any_base, &context).await,
-    ValidTypes::Undo => receive_undo_delete_private_message(any_base, &context).await,
-  };
-
-  insert_activity(actor.user_id(), activity.clone(), false, context.pool()).await?;
-  res
+  verify_or_refetch(&request, actor.as_ref(), &context).await?;
+
+  let inbox = format!("/u/{}/inbox", &username);
+  let activity_id = activity.id_unchecked().map(|id| id.to_owned());
+  receive_once(&inbox, activity_id.as_ref(), actor_uri, &context, async {
+    let any_base = activity.clone().into_any_base()?;
+    let kind = activity.kind().context(location_info!())?;
+    let res = match kind {
+      ValidTypes::Accept => receive_accept(any_base, username, &context).await,
+      ValidTypes::Reject => receive_reject(any_base, username, &context).await,
+      ValidTypes::Create => receive_create_private_message(any_base, &context).await,
+      ValidTypes::Update => receive_update_private_message(any_base, &context).await,
+      ValidTypes::Delete => receive_delete_private_message(any_base, &context).await,
+      ValidTypes::Undo => receive_undo_delete_private_message(any_base, &context).await,
+    };
+
+    insert_activity(actor.user_id(), activity.clone(), false, context.pool()).await?;
+    res
+  })
+  .await
 }
 
 /// Handle accepted follows.
@@ -107,6 +128,12 @@ async fn receive_accept(
 
   // This will fail if they're already a follower
   blocking(&context.pool(), move |conn| {
+    CommunityJoinRequest::remove(
+      conn,
+      community_follower_form.community_id,
+      community_follower_form.user_id,
+    )
+    .ok();
     CommunityFollower::follow(conn, &community_follower_form).ok()
   })
   .await?;
@@ -115,6 +142,35 @@ async fn receive_accept(
   Ok(HttpResponse::Ok().finish())
 }
 
+/// Handle follows rejected by a community which requires approval to join.
+async fn receive_reject(
+  activity: AnyBase,
+  username: String,
+  context: &LemmyContext,
+) -> Result<HttpResponse, LemmyError> {
+  let reject = Reject::from_any_base(activity)?.context(location_info!())?;
+  let community_uri = reject
+    .actor()?
+    .to_owned()
+    .single_xsd_any_uri()
+    .context(location_info!())?;
+
+  let community = get_or_fetch_and_upsert_community(&community_uri, context).await?;
+
+  let user = blocking(&context.pool(), move |conn| {
+    User_::read_from_name(conn, &username)
+  })
+  .await??;
+
+  let community_id = community.id;
+  blocking(&context.pool(), move |conn| {
+    CommunityJoinRequest::remove(conn, community_id, user.id)
+  })
+  .await??;
+
+  Ok(HttpResponse::Ok().finish())
+}
+
 async fn receive_create_private_message(
   activity: AnyBase,
   context: &LemmyContext,
@@ -133,7 +189,7 @@ async fn receive_create_private_message(
   let private_message = PrivateMessageForm::from_apub(&note, context, domain).await?;
 
   let inserted_private_message = blocking(&context.pool(), move |conn| {
-    PrivateMessage::create(conn, &private_message)
+    PrivateMessage::upsert(conn, &private_message)
   })
   .await??;
 
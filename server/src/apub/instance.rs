@@ -0,0 +1,98 @@
+//! The Application actor of the instance itself, at `/actor`. It signs the fetches of the instance,
+//! and the activities which aren't sent by a user or community.
+use crate::{
+  apub::{
+    activity_queue::enqueue_activity,
+    create_apub_response,
+    extensions::signatures::{PublicKey, PublicKeyExtension},
+  },
+  DbPool,
+  LemmyContext,
+};
+use activitystreams::{
+  actor::{ApActor, Application, Endpoints},
+  base::Extends,
+  object::AsObject,
+  prelude::*,
+};
+use activitystreams_ext::Ext1;
+use actix_web::{body::Body, web, HttpResponse};
+use lemmy_api_structs::blocking;
+use lemmy_db::instance_actor::InstanceActor;
+use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings, LemmyError};
+use serde::Serialize;
+use url::{ParseError, Url};
+
+pub type ApplicationExt = Ext1<ApActor<Application>, PublicKeyExtension>;
+
+pub fn instance_actor_id() -> Result<Url, ParseError> {
+  Url::parse(&format!(
+    "{}://{}/actor",
+    get_apub_protocol_string(),
+    Settings::get().hostname
+  ))
+}
+
+/// The webfinger resource of the instance actor. Like on Mastodon, its username is the hostname.
+pub fn instance_actor_webfinger() -> String {
+  let hostname = Settings::get().hostname;
+  format!("acct:{}@{}", hostname, hostname)
+}
+
+pub async fn read_instance_actor(pool: &DbPool) -> Result<InstanceActor, LemmyError> {
+  let instance_actor = blocking(pool, move |conn| InstanceActor::read(conn)).await??;
+  Ok(instance_actor)
+}
+
+/// Sends an activity of the instance itself, signed with the key of the instance actor.
+pub async fn send_instance_activity<T, Kind>(
+  context: &LemmyContext,
+  activity: T,
+  to: Vec<Url>,
+) -> Result<(), LemmyError>
+where
+  T: AsObject<Kind>,
+  T: Extends<Kind>,
+  Kind: Serialize,
+  <T as Extends<Kind>>::Error: From<serde_json::Error> + Send + Sync + 'static,
+{
+  // Makes sure there is a key to sign it with
+  read_instance_actor(context.pool()).await?;
+  enqueue_activity(context, activity, instance_actor_id()?, to).await
+}
+
+pub async fn get_apub_instance_actor(
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse<Body>, LemmyError> {
+  let instance_actor = read_instance_actor(context.pool()).await?;
+  let actor_id = instance_actor_id()?;
+  let hostname = Settings::get().hostname;
+  // Activities for it go to the shared inbox
+  let inbox = Url::parse(&format!(
+    "{}://{}/inbox",
+    get_apub_protocol_string(),
+    hostname
+  ))?;
+
+  let mut application = Application::new();
+  application
+    .set_context(activitystreams::context())
+    .set_id(actor_id.to_owned())
+    .set_name(hostname.to_owned());
+
+  let mut ap_actor = ApActor::new(inbox.to_owned(), application);
+  ap_actor
+    .set_preferred_username(hostname)
+    .set_endpoints(Endpoints {
+      shared_inbox: Some(inbox),
+      ..Default::default()
+    });
+
+  let public_key = PublicKey {
+    id: format!("{}#main-key", actor_id),
+    owner: actor_id.to_string(),
+    public_key_pem: instance_actor.public_key,
+  };
+  let application: ApplicationExt = Ext1::new(ap_actor, public_key.to_ext());
+  Ok(create_apub_response(&application))
+}
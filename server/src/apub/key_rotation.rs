@@ -0,0 +1,95 @@
+//! Rotation of the keys of all local actors, eg after the database leaked. Each user and community
+//! gets a new keypair, and sends out an Update of itself so that other instances take the new key.
+//! Instances which don't get the Update refetch the actor once its signatures don't match the key
+//! they have.
+use crate::{
+  apub::{community::send_update_community, user::send_update_user},
+  LemmyContext,
+};
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  community::Community,
+  instance_actor::{InstanceActor, InstanceActorForm},
+  user::User_,
+};
+use lemmy_utils::{apub::generate_actor_keypair, LemmyError};
+use log::{error, info};
+
+/// Generating the keys takes a while, so this is meant to run in the background.
+pub async fn rotate_local_actor_keys(
+  user_ids: Vec<i32>,
+  community_ids: Vec<i32>,
+  context: LemmyContext,
+) {
+  if let Err(e) = rotate_instance_actor_key(&context).await {
+    error!("Couldn't rotate the key of the instance actor: {}", e);
+  }
+  for user_id in &user_ids {
+    if let Err(e) = rotate_user_key(*user_id, &context).await {
+      error!("Couldn't rotate the key of user {}: {}", user_id, e);
+    }
+  }
+  for community_id in &community_ids {
+    if let Err(e) = rotate_community_key(*community_id, &context).await {
+      error!(
+        "Couldn't rotate the key of community {}: {}",
+        community_id, e
+      );
+    }
+  }
+  info!(
+    "Rotated the keys of {} users and {} communities",
+    user_ids.len(),
+    community_ids.len()
+  );
+}
+
+async fn rotate_instance_actor_key(context: &LemmyContext) -> Result<(), LemmyError> {
+  blocking(context.pool(), move |conn| -> Result<(), LemmyError> {
+    let instance_actor = InstanceActor::read(conn)?;
+    let keypair = generate_actor_keypair()?;
+    let form = InstanceActorForm {
+      private_key: keypair.private_key,
+      public_key: keypair.public_key,
+    };
+    InstanceActor::update_keys(conn, instance_actor.id, &form)?;
+    Ok(())
+  })
+  .await??;
+  Ok(())
+}
+
+async fn rotate_user_key(user_id: i32, context: &LemmyContext) -> Result<(), LemmyError> {
+  let user = blocking(context.pool(), move |conn| -> Result<User_, LemmyError> {
+    let keypair = generate_actor_keypair()?;
+    Ok(User_::update_keys(
+      conn,
+      user_id,
+      &keypair.private_key,
+      &keypair.public_key,
+    )?)
+  })
+  .await??;
+  context
+    .response_cache()
+    .remove(&format!("/u/{}", user.name));
+  send_update_user(&user, context).await
+}
+
+async fn rotate_community_key(community_id: i32, context: &LemmyContext) -> Result<(), LemmyError> {
+  let community = blocking(
+    context.pool(),
+    move |conn| -> Result<Community, LemmyError> {
+      let keypair = generate_actor_keypair()?;
+      Ok(Community::update_keys(
+        conn,
+        community_id,
+        &keypair.private_key,
+        &keypair.public_key,
+      )?)
+    },
+  )
+  .await??;
+  context.response_cache().invalidate_community(community.id);
+  send_update_community(&community, context).await
+}
@@ -5,35 +5,62 @@ pub mod community;
 pub mod extensions;
 pub mod fetcher;
 pub mod inbox;
+pub mod instance;
+pub mod key_rotation;
 pub mod post;
 pub mod private_message;
+#[cfg(test)]
+mod test;
 pub mod user;
 
 use crate::{
-  apub::extensions::{
-    group_extensions::GroupExtension,
-    page_extension::PageExtension,
-    signatures::{PublicKey, PublicKeyExtension},
+  apub::{
+    extensions::{
+      group_extensions::GroupExtension,
+      page_extension::PageExtension,
+      person_extension::PersonExtension,
+      signatures::{
+        may_refetch_key,
+        signing_actor_id,
+        verify_or_refetch,
+        verify_with_key,
+        PublicKey,
+        PublicKeyExtension,
+        SignedRequest,
+      },
+    },
+    fetcher::{fetch_remote_object, get_or_fetch_and_upsert_user, refetch_actor},
   },
-  request::{retry, RecvError},
+  request::{get_checked, read_body, RecvError},
   routes::webfinger::WebFingerResponse,
   DbPool,
   LemmyContext,
 };
 use activitystreams::{
   activity::Follow,
-  actor::{ApActor, Group, Person},
-  base::AsBase,
+  actor::{Actor, ApActor, Group},
+  base::{AnyBase, AsBase},
+  collection::{OrderedCollection, OrderedCollectionPage},
   markers::Base,
-  object::{Page, Tombstone},
+  object::{
+    kind::{NoteType, PageType},
+    Page,
+    Tombstone,
+  },
   prelude::*,
 };
 use activitystreams_ext::{Ext1, Ext2};
-use actix_web::{body::Body, HttpResponse};
+use actix_web::{body::Body, HttpRequest, HttpResponse};
 use anyhow::{anyhow, Context};
 use chrono::NaiveDateTime;
 use lemmy_api_structs::blocking;
-use lemmy_db::{activity::do_insert_activity, user::User_};
+use lemmy_db::{
+  activity::{do_insert_activity, Activity},
+  comment::Comment,
+  community::{Community, CommunityFollower, CommunitySettings},
+  post::Post,
+  user::User_,
+};
 use lemmy_utils::{
   apub::get_apub_protocol_string,
   location_info,
@@ -43,13 +70,38 @@ use lemmy_utils::{
 };
 use log::debug;
 use reqwest::Client;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  collections::HashMap,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
 use url::{ParseError, Url};
 
 type GroupExt = Ext2<ApActor<Group>, GroupExtension, PublicKeyExtension>;
-type PersonExt = Ext1<ApActor<Person>, PublicKeyExtension>;
+type PersonExt = Ext2<ApActor<Actor<UserActorType>>, PersonExtension, PublicKeyExtension>;
 type PageExt = Ext1<Page, PageExtension>;
 
+/// Users are `Person` actors, except bot accounts which are `Service` actors, as on Mastodon.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum UserActorType {
+  Person,
+  Service,
+}
+
+impl Default for UserActorType {
+  fn default() -> Self {
+    UserActorType::Person
+  }
+}
+
+impl std::fmt::Display for UserActorType {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
 pub static APUB_JSON_CONTENT_TYPE: &str = "application/activity+json";
 
 /// Convert the data to json and turn it into an HTTP Response with the correct ActivityPub
@@ -63,6 +115,167 @@ where
     .json(data)
 }
 
+/// Like `create_apub_response`, but also caches the response for the next requests. The cache is
+/// dropped early if `community_id` is given and something changes in the community.
+fn create_cached_apub_response<T>(
+  data: &T,
+  cache_key: String,
+  community_id: Option<i32>,
+  context: &LemmyContext,
+) -> Result<HttpResponse<Body>, LemmyError>
+where
+  T: Serialize,
+{
+  let body = serde_json::to_string(data)?;
+  context.response_cache().insert(
+    cache_key,
+    body.to_owned(),
+    APUB_JSON_CONTENT_TYPE,
+    community_id,
+  );
+  Ok(
+    HttpResponse::Ok()
+      .content_type(APUB_JSON_CONTENT_TYPE)
+      .body(body),
+  )
+}
+
+/// Activities on each page of an outbox.
+const OUTBOX_PAGE_SIZE: i64 = 20;
+
+#[derive(Deserialize)]
+pub struct OutboxQuery {
+  page: Option<bool>,
+  /// The id of the last activity on the previous page
+  before: Option<i32>,
+}
+
+/// The outbox of a local user or community. Without `page`, it is the collection with the number of
+/// activities and a link to the first page. The pages have the newest activities first, and link to
+/// the next older page, so that other instances can page back through the history of the actor.
+async fn create_outbox_response(
+  actor: &dyn ActorType,
+  query: OutboxQuery,
+  pool: &DbPool,
+) -> Result<HttpResponse<Body>, LemmyError> {
+  let outbox_url = actor.get_outbox_url()?;
+  let actor_id = actor.actor_id_str();
+
+  if !query.page.unwrap_or(false) {
+    let total_items = blocking(pool, move |conn| {
+      Activity::count_for_outbox(conn, &actor_id)
+    })
+    .await??;
+    let mut collection = OrderedCollection::new();
+    collection
+      .set_context(activitystreams::context())
+      .set_id(outbox_url.to_owned())
+      .set_total_items(total_items as u64)
+      .set_first(Url::parse(&format!("{}?page=true", outbox_url))?);
+    return Ok(create_apub_response(&collection));
+  }
+
+  let before = query.before;
+  let activities = blocking(pool, move |conn| {
+    Activity::list_for_outbox(conn, &actor_id, before, OUTBOX_PAGE_SIZE)
+  })
+  .await??;
+  let page_url = match before {
+    Some(before) => format!("{}?page=true&before={}", outbox_url, before),
+    None => format!("{}?page=true", outbox_url),
+  };
+  // A full page may be followed by more
+  let next = match activities.last() {
+    Some(last) if activities.len() as i64 == OUTBOX_PAGE_SIZE => Some(Url::parse(&format!(
+      "{}?page=true&before={}",
+      outbox_url, last.id
+    ))?),
+    _ => None,
+  };
+  let mut items = Vec::with_capacity(activities.len());
+  for activity in activities {
+    let data = with_current_object(activity.data, pool).await?;
+    items.push(serde_json::from_value::<AnyBase>(data)?);
+  }
+
+  let mut page = OrderedCollectionPage::new();
+  page
+    .set_context(activitystreams::context())
+    .set_id(Url::parse(&page_url)?)
+    .set_part_of(outbox_url)
+    .set_many_items(items);
+  if let Some(next) = next {
+    page.set_next(next);
+  }
+  Ok(create_apub_response(&page))
+}
+
+/// Stored activities have their object as it was sent. When they are served again, in outboxes or
+/// by id, local posts and comments are shown as they are now: with their edits, or as a tombstone
+/// once they are deleted or removed.
+async fn with_current_object(mut activity: Value, pool: &DbPool) -> Result<Value, LemmyError> {
+  // Announces wrap the activity of the object
+  let path = if activity.pointer("/object/object/type").is_some() {
+    "/object/object"
+  } else {
+    "/object"
+  };
+  let current = match activity.pointer(path) {
+    Some(object) => current_object(object, pool).await?,
+    None => None,
+  };
+  if let (Some(current), Some(object)) = (current, activity.pointer_mut(path)) {
+    *object = current;
+  }
+  Ok(activity)
+}
+
+/// The local post or comment with the id of the object as it is now, or its tombstone.
+async fn current_object(object: &Value, pool: &DbPool) -> Result<Option<Value>, LemmyError> {
+  let id = match object.get("id").and_then(Value::as_str) {
+    Some(id) => id.to_owned(),
+    None => return Ok(None),
+  };
+  let current = match object.get("type").and_then(Value::as_str) {
+    Some("Page") => {
+      let post = match blocking(pool, move |conn| Post::read_from_apub_id(conn, &id)).await? {
+        Ok(post) if post.local => post,
+        _ => return Ok(None),
+      };
+      if post.deleted || post.removed {
+        let deleted = post.updated.unwrap_or(post.published);
+        serde_json::to_value(create_tombstone(
+          true,
+          &post.ap_id,
+          Some(deleted),
+          PageType::Page,
+        )?)?
+      } else {
+        serde_json::to_value(post.to_apub(pool).await?)?
+      }
+    }
+    Some("Note") => {
+      let comment = match blocking(pool, move |conn| Comment::read_from_apub_id(conn, &id)).await? {
+        Ok(comment) if comment.local => comment,
+        _ => return Ok(None),
+      };
+      if comment.deleted || comment.removed {
+        let deleted = comment.updated.unwrap_or(comment.published);
+        serde_json::to_value(create_tombstone(
+          true,
+          &comment.ap_id,
+          Some(deleted),
+          NoteType::Note,
+        )?)?
+      } else {
+        serde_json::to_value(comment.to_apub(pool).await?)?
+      }
+    }
+    _ => return Ok(None),
+  };
+  Ok(Some(current))
+}
+
 fn create_apub_tombstone_response<T>(data: &T) -> HttpResponse<Body>
 where
   T: Serialize,
@@ -72,7 +285,169 @@ where
     .json(data)
 }
 
-// Checks if the ID has a valid format, correct scheme, and is in the allowed instance list.
+/// How long the keys of signers which aren't stored as users or communities are kept.
+const SIGNING_KEY_CACHE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+  static ref SIGNING_KEYS: Mutex<HashMap<Url, (String, Instant)>> = Mutex::new(HashMap::new());
+}
+
+/// The public key of the actor who signed a request. Mastodon signs its fetches with a service
+/// actor, which isn't a user or community, so those are fetched and cached for a while.
+async fn signing_public_key(actor_id: &Url, context: &LemmyContext) -> Result<String, LemmyError> {
+  let id = actor_id.to_string();
+  let stored = blocking(context.pool(), move |conn| {
+    User_::read_from_actor_id(conn, &id)
+      .map(|u| u.public_key)
+      .or_else(|_| Community::read_from_actor_id(conn, &id).map(|c| c.public_key))
+  })
+  .await?;
+  if let Ok(Some(public_key)) = stored {
+    return Ok(public_key);
+  }
+
+  if let Some((public_key, fetched)) = SIGNING_KEYS.lock().unwrap().get(actor_id) {
+    if fetched.elapsed() < SIGNING_KEY_CACHE_DURATION {
+      return Ok(public_key.to_owned());
+    }
+  }
+  let actor = fetch_remote_object::<Value>(context, actor_id).await?;
+  if actor.get("id").and_then(|i| i.as_str()) != Some(actor_id.as_str()) {
+    return Err(anyhow!("Fetched actor has a different id than {}", actor_id).into());
+  }
+  let public_key = actor
+    .get("publicKey")
+    .and_then(|k| k.get("publicKeyPem"))
+    .and_then(|k| k.as_str())
+    .context(location_info!())?
+    .to_owned();
+  let mut signing_keys = SIGNING_KEYS.lock().unwrap();
+  signing_keys.retain(|_, (_, fetched)| fetched.elapsed() < SIGNING_KEY_CACHE_DURATION);
+  signing_keys.insert(actor_id.to_owned(), (public_key.to_owned(), Instant::now()));
+  Ok(public_key)
+}
+
+/// The actor who signed the request, if it's an actor of an instance we federate with, and the
+/// signature is valid.
+async fn fetch_signer(
+  request: &HttpRequest,
+  context: &LemmyContext,
+) -> Result<Option<Url>, LemmyError> {
+  let actor_id = match signing_actor_id(request) {
+    Ok(actor_id) => actor_id,
+    Err(_) => return Ok(None),
+  };
+  if check_is_apub_id_valid(&actor_id).is_err() {
+    return Ok(None);
+  }
+  let public_key = match signing_public_key(&actor_id, context).await {
+    Ok(public_key) => public_key,
+    Err(e) => {
+      debug!("Couldn't get the key of {}: {}", actor_id, e);
+      return Ok(None);
+    }
+  };
+  let signed_request = SignedRequest::new(request);
+  if verify_with_key(&signed_request, &public_key).is_ok() {
+    return Ok(Some(actor_id));
+  }
+
+  // The signer may have a new key
+  if !may_refetch_key(&actor_id) {
+    return Ok(None);
+  }
+  let public_key = match refetch_signing_public_key(&actor_id, context).await {
+    Ok(public_key) => public_key,
+    Err(e) => {
+      debug!("Couldn't refetch the key of {}: {}", actor_id, e);
+      return Ok(None);
+    }
+  };
+  if verify_with_key(&signed_request, &public_key).is_err() {
+    return Ok(None);
+  }
+  Ok(Some(actor_id))
+}
+
+/// Like signing_public_key, but fetches the actor again even if we have its key.
+async fn refetch_signing_public_key(
+  actor_id: &Url,
+  context: &LemmyContext,
+) -> Result<String, LemmyError> {
+  SIGNING_KEYS.lock().unwrap().remove(actor_id);
+  let id = actor_id.to_string();
+  let stored = blocking(context.pool(), move |conn| {
+    User_::read_from_actor_id(conn, &id).is_ok() || Community::read_from_actor_id(conn, &id).is_ok()
+  })
+  .await?;
+  if !stored {
+    return signing_public_key(actor_id, context).await;
+  }
+  let actor = refetch_actor(actor_id, context).await?;
+  Ok(actor.public_key().context(location_info!())?)
+}
+
+/// Whether the request is signed by an actor of an instance we federate with.
+async fn is_fetch_signed(
+  request: &HttpRequest,
+  context: &LemmyContext,
+) -> Result<bool, LemmyError> {
+  Ok(fetch_signer(request, context).await?.is_some())
+}
+
+/// Public objects are served to everyone, unless the require_signed_fetch setting is on. Then the
+/// request has to be signed.
+async fn is_public_fetch_authorized(
+  request: &HttpRequest,
+  context: &LemmyContext,
+) -> Result<bool, LemmyError> {
+  if !Settings::get().federation.require_signed_fetch {
+    return Ok(true);
+  }
+  is_fetch_signed(request, context).await
+}
+
+/// The objects of private communities are only served to their members, who have to sign the
+/// request, and those of local only communities aren't served at all. Everything else is public,
+/// see is_public_fetch_authorized.
+async fn is_fetch_authorized(
+  request: &HttpRequest,
+  community_id: i32,
+  context: &LemmyContext,
+) -> Result<bool, LemmyError> {
+  let settings = blocking(context.pool(), move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  if settings.local_only {
+    return Ok(false);
+  } else if !settings.private {
+    return is_public_fetch_authorized(request, context).await;
+  }
+
+  let actor_id = match signing_actor_id(request) {
+    Ok(actor_id) => actor_id,
+    Err(_) => return Ok(false),
+  };
+  check_is_apub_id_valid(&actor_id)?;
+  let user = get_or_fetch_and_upsert_user(&actor_id, context).await?;
+  if verify_or_refetch(&SignedRequest::new(request), &user, context)
+    .await
+    .is_err()
+  {
+    return Ok(false);
+  }
+
+  let user_id = user.id;
+  let is_member = blocking(context.pool(), move |conn| {
+    CommunityFollower::is_follower(conn, community_id, user_id)
+      .map(|follows| follows || Community::is_mod_or_admin(conn, user_id, community_id))
+  })
+  .await??;
+  Ok(is_member)
+}
+
+// Checks if the ID has a valid format, correct scheme, and is allowed by the federation mode.
 fn check_is_apub_id_valid(apub_id: &Url) -> Result<(), LemmyError> {
   let settings = Settings::get();
   let domain = apub_id.domain().context(location_info!())?.to_string();
@@ -102,28 +477,37 @@ fn check_is_apub_id_valid(apub_id: &Url) -> Result<(), LemmyError> {
     return Err(anyhow!("invalid apub id scheme: {:?}", apub_id.scheme()).into());
   }
 
-  let mut allowed_instances = Settings::get().get_allowed_instances();
-  let blocked_instances = Settings::get().get_blocked_instances();
-
-  if !allowed_instances.is_empty() {
-    // need to allow this explicitly because apub activities might contain objects from our local
-    // instance. split is needed to remove the port in our federation test setup.
-    allowed_instances.push(local_instance);
-
-    if allowed_instances.contains(&domain) {
-      Ok(())
-    } else {
-      Err(anyhow!("{} not in federation allowlist", domain).into())
-    }
-  } else if !blocked_instances.is_empty() {
-    if blocked_instances.contains(&domain) {
-      Err(anyhow!("{} is in federation blocklist", domain).into())
-    } else {
-      Ok(())
-    }
+  // Our own instance is always allowed, because activities might contain local objects
+  if settings.is_instance_allowed(&domain) {
+    Ok(())
   } else {
-    panic!("Invalid config, both allowed_instances and blocked_instances are specified");
+    Err(
+      anyhow!(
+        "{} isn't allowed in federation mode {}",
+        domain,
+        settings.get_federation_mode().as_str()
+      )
+      .into(),
+    )
+  }
+}
+
+/// Only mods and admins can distinguish what they post, so the flag of remote posts and comments
+/// by other users is dropped. Admins of other instances aren't known here, only their mods.
+async fn check_distinguished(
+  distinguished: bool,
+  creator_id: i32,
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<bool, LemmyError> {
+  if !distinguished {
+    return Ok(false);
   }
+  let is_mod_or_admin = blocking(pool, move |conn| {
+    Community::is_mod_or_admin(conn, creator_id, community_id)
+  })
+  .await?;
+  Ok(is_mod_or_admin)
 }
 
 #[async_trait::async_trait(?Send)]
@@ -187,7 +571,13 @@ pub trait ApubObjectType {
     creator: &User_,
     context: &LemmyContext,
   ) -> Result<(), LemmyError>;
-  async fn send_remove(&self, mod_: &User_, context: &LemmyContext) -> Result<(), LemmyError>;
+  /// The reason of the mod goes into the summary of the activity.
+  async fn send_remove(
+    &self,
+    mod_: &User_,
+    reason: Option<String>,
+    context: &LemmyContext,
+  ) -> Result<(), LemmyError>;
   async fn send_undo_remove(&self, mod_: &User_, context: &LemmyContext) -> Result<(), LemmyError>;
 }
 
@@ -327,12 +717,11 @@ pub async fn fetch_webfinger_url(
   );
   debug!("Fetching webfinger url: {}", &fetch_url);
 
-  let response = retry(|| client.get(&fetch_url).send()).await?;
+  let response = get_checked(client, &Url::parse(&fetch_url)?, |r| r).await?;
 
-  let res: WebFingerResponse = response
-    .json()
-    .await
-    .map_err(|e| RecvError(e.to_string()))?;
+  let body = read_body(response).await?;
+  let res: WebFingerResponse =
+    serde_json::from_slice(&body).map_err(|e| RecvError(e.to_string()))?;
 
   let link = res
     .links
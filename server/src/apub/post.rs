@@ -2,11 +2,13 @@ use crate::{
   apub::{
     activities::{generate_activity_id, send_activity_to_community},
     check_actor_domain,
+    check_distinguished,
     create_apub_response,
     create_apub_tombstone_response,
     create_tombstone,
     extensions::page_extension::PageExtension,
-    fetcher::{get_or_fetch_and_upsert_community, get_or_fetch_and_upsert_user},
+    fetcher::{get_or_fetch_and_upsert_community, get_or_fetch_and_upsert_creator},
+    is_fetch_authorized,
     ActorType,
     ApubLikeableType,
     ApubObjectType,
@@ -18,33 +20,26 @@ use crate::{
   LemmyContext,
 };
 use activitystreams::{
-  activity::{
-    kind::{CreateType, DeleteType, DislikeType, LikeType, RemoveType, UndoType, UpdateType},
-    Create,
-    Delete,
-    Dislike,
-    Like,
-    Remove,
-    Undo,
-    Update,
-  },
+  activity::{Create, Delete, Dislike, Like, Remove, Undo, Update},
   object::{kind::PageType, Image, Object, Page, Tombstone},
   prelude::*,
   public,
 };
 use activitystreams_ext::Ext1;
-use actix_web::{body::Body, web, HttpResponse};
+use actix_web::{body::Body, web, HttpRequest, HttpResponse};
 use anyhow::Context;
 use lemmy_api_structs::blocking;
 use lemmy_db::{
-  community::Community,
+  community::{Community, CommunitySettings},
   post::{Post, PostForm},
+  post_media::{PostMedia, PostMediaForm, MAX_POST_MEDIA},
   user::User_,
   Crud,
 };
 use lemmy_utils::{
   location_info,
-  utils::{check_slurs, convert_datetime, remove_slurs},
+  settings::Settings,
+  utils::{check_slurs, convert_datetime, remove_slurs, truncate_to_length},
   LemmyError,
 };
 use serde::Deserialize;
@@ -57,19 +52,44 @@ pub struct PostQuery {
 
 /// Return the post json over HTTP.
 pub async fn get_apub_post(
+  request: HttpRequest,
   info: web::Path<PostQuery>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse<Body>, LemmyError> {
   let id = info.post_id.parse::<i32>()?;
   let post = blocking(context.pool(), move |conn| Post::read(conn, id)).await??;
 
-  if !post.deleted {
+  if post.anonymous || !is_fetch_authorized(&request, post.community_id, &context).await? {
+    Ok(HttpResponse::NotFound().finish())
+  } else if !post.deleted {
     Ok(create_apub_response(&post.to_apub(context.pool()).await?))
   } else {
     Ok(create_apub_tombstone_response(&post.to_tombstone()?))
   }
 }
 
+/// Anonymous posts don't federate, so their creator stays hidden. Neither does the content of
+/// private communities, as other instances would show it to everyone, nor that of local only
+/// communities.
+pub(in crate::apub) async fn is_withheld(post: &Post, pool: &DbPool) -> Result<bool, LemmyError> {
+  if post.anonymous {
+    return Ok(true);
+  }
+  is_community_withheld(post.community_id, pool).await
+}
+
+/// Whether the content of the community doesn't federate, because it is private or local only.
+pub(in crate::apub) async fn is_community_withheld(
+  community_id: i32,
+  pool: &DbPool,
+) -> Result<bool, LemmyError> {
+  let settings = blocking(pool, move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  Ok(settings.private || settings.local_only)
+}
+
 #[async_trait::async_trait(?Send)]
 impl ToApub for Post {
   type Response = PageExt;
@@ -129,17 +149,41 @@ impl ToApub for Post {
     if let Some(thumbnail_url) = &self.thumbnail_url {
       let mut image = Image::new();
       image.set_url(thumbnail_url.to_string());
+      if let Some(alt_text) = &self.thumbnail_alt_text {
+        image.set_name(alt_text.to_owned());
+      }
       page.set_image(image.into_any_base()?);
     }
 
+    let post_id = self.id;
+    let media = blocking(pool, move |conn| PostMedia::list_for_post(conn, post_id)).await??;
+    if !media.is_empty() {
+      let mut attachments = Vec::new();
+      for m in media {
+        let mut image = Image::new();
+        image.set_url(m.url);
+        if let Some(caption) = m.caption {
+          image.set_summary(caption);
+        }
+        if let Some(alt_text) = m.alt_text {
+          image.set_name(alt_text);
+        }
+        attachments.push(image.into_any_base()?);
+      }
+      page.set_many_attachments(attachments);
+    }
+
     if let Some(u) = self.updated {
       page.set_updated(convert_datetime(u));
     }
 
     let ext = PageExtension {
-      comments_enabled: !self.locked,
+      // Instances which don't know about closed comments treat them like a lock
+      comments_enabled: !self.locked && !self.comments_closed,
       sensitive: self.nsfw,
       stickied: self.stickied,
+      distinguished: self.distinguished,
+      comments_closed: self.comments_closed,
     };
     Ok(Ext1::new(page, ext))
   }
@@ -193,6 +237,67 @@ fn extract_embed_from_apub(
   }
 }
 
+/// Read the image attachments of a page, in order. Anything that isn't an image is skipped.
+pub(in crate::apub) fn extract_media_from_apub(page: &PageExt, post_id: i32) -> Vec<PostMediaForm> {
+  let attachments = match page.inner.attachment() {
+    Some(a) => match a.as_many() {
+      Some(many) => many.to_vec(),
+      None => a.as_one().into_iter().cloned().collect(),
+    },
+    None => return Vec::new(),
+  };
+
+  let mut media = Vec::new();
+  for attachment in attachments {
+    if media.len() == MAX_POST_MEDIA {
+      break;
+    }
+    // Other software attaches links, documents and the like, which are left out
+    let image = match Image::from_any_base(attachment) {
+      Ok(Some(i)) => i,
+      _ => continue,
+    };
+    let url = match image.url().map(|u| u.as_single_xsd_any_uri()).flatten() {
+      Some(u) => u.to_string(),
+      None => continue,
+    };
+    let caption = image
+      .summary()
+      .map(|s| s.as_single_xsd_string())
+      .flatten()
+      .map(remove_slurs);
+    let alt_text = image
+      .name()
+      .map(|n| n.one())
+      .flatten()
+      .map(|s| s.as_xsd_string())
+      .flatten()
+      .map(remove_slurs);
+    media.push(PostMediaForm {
+      post_id,
+      position: media.len() as i32,
+      url,
+      caption,
+      alt_text,
+    });
+  }
+  media
+}
+
+/// Store the gallery of a remote post, replacing whatever was there before.
+pub async fn upsert_post_media_from_apub(
+  page: &PageExt,
+  post_id: i32,
+  pool: &DbPool,
+) -> Result<(), LemmyError> {
+  let media = extract_media_from_apub(page, post_id);
+  blocking(pool, move |conn| {
+    PostMedia::replace_for_post(conn, post_id, &media)
+  })
+  .await??;
+  Ok(())
+}
+
 #[async_trait::async_trait(?Send)]
 impl FromApub for PostForm {
   type ApubType = PageExt;
@@ -203,7 +308,6 @@ impl FromApub for PostForm {
     context: &LemmyContext,
     expected_domain: Option<Url>,
   ) -> Result<PostForm, LemmyError> {
-    let ext = &page.ext_one;
     let creator_actor_id = page
       .inner
       .attributed_to()
@@ -212,7 +316,7 @@ impl FromApub for PostForm {
       .as_single_xsd_any_uri()
       .context(location_info!())?;
 
-    let creator = get_or_fetch_and_upsert_user(creator_actor_id, context).await?;
+    let creator = get_or_fetch_and_upsert_creator(creator_actor_id, context).await?;
 
     let community_actor_id = page
       .inner
@@ -224,83 +328,127 @@ impl FromApub for PostForm {
 
     let community = get_or_fetch_and_upsert_community(community_actor_id, context).await?;
 
-    let thumbnail_url = match &page.inner.image() {
-      Some(any_image) => Image::from_any_base(
+    let mut form = post_form_from_page(page, creator.id, community.id, expected_domain)?;
+    let distinguished = form.distinguished.unwrap_or(false);
+    form.distinguished =
+      Some(check_distinguished(distinguished, creator.id, community.id, context.pool()).await?);
+    Ok(form)
+  }
+}
+
+/// Everything `from_apub` does after fetching the creator and community, which can run without a
+/// database.
+pub(in crate::apub) fn post_form_from_page(
+  page: &PageExt,
+  creator_id: i32,
+  community_id: i32,
+  expected_domain: Option<Url>,
+) -> Result<PostForm, LemmyError> {
+  let ext = &page.ext_one;
+  let thumbnail = match &page.inner.image() {
+    Some(any_image) => Some(
+      Image::from_any_base(
         any_image
           .to_owned()
           .as_one()
           .context(location_info!())?
           .to_owned(),
       )?
-      .context(location_info!())?
+      .context(location_info!())?,
+    ),
+    None => None,
+  };
+  let thumbnail_url = match &thumbnail {
+    Some(image) => image
       .url()
       .context(location_info!())?
       .as_single_xsd_any_uri()
       .map(|u| u.to_string()),
-      None => None,
-    };
-
-    let embed = extract_embed_from_apub(page)?;
-
-    let name = page
+    None => None,
+  };
+  let thumbnail_alt_text = thumbnail
+    .as_ref()
+    .map(|i| i.name())
+    .flatten()
+    .map(|n| n.one())
+    .flatten()
+    .map(|s| s.as_xsd_string())
+    .flatten()
+    .map(remove_slurs);
+
+  let embed = extract_embed_from_apub(page)?;
+
+  let name = page
+    .inner
+    .summary()
+    .as_ref()
+    .context(location_info!())?
+    .as_single_xsd_string()
+    .context(location_info!())?
+    .to_string();
+  let url = page
+    .inner
+    .url()
+    .as_ref()
+    .map(|u| u.as_single_xsd_string())
+    .flatten()
+    .map(|s| s.to_string());
+  let body = page
+    .inner
+    .content()
+    .as_ref()
+    .map(|c| c.as_single_xsd_string())
+    .flatten()
+    .map(|s| s.to_string());
+  check_slurs(&name)?;
+  let body_slurs_removed = body.map(|b| remove_slurs(&b));
+  // Remote instances can have higher limits, what doesn't fit here is cut
+  let limits = Settings::get().limits;
+  let name = truncate_to_length(&name, limits.post_title_length());
+  let body_slurs_removed =
+    body_slurs_removed.map(|b| truncate_to_length(&b, limits.max_post_body_length));
+  Ok(PostForm {
+    name,
+    url,
+    body: body_slurs_removed,
+    creator_id,
+    community_id,
+    removed: None,
+    locked: Some(!ext.comments_enabled && !ext.comments_closed),
+    published: page
       .inner
-      .summary()
+      .published()
       .as_ref()
-      .context(location_info!())?
-      .as_single_xsd_string()
-      .context(location_info!())?
-      .to_string();
-    let url = page
-      .inner
-      .url()
-      .as_ref()
-      .map(|u| u.as_single_xsd_string())
-      .flatten()
-      .map(|s| s.to_string());
-    let body = page
+      .map(|u| u.to_owned().naive_local()),
+    updated: page
       .inner
-      .content()
+      .updated()
       .as_ref()
-      .map(|c| c.as_single_xsd_string())
-      .flatten()
-      .map(|s| s.to_string());
-    check_slurs(&name)?;
-    let body_slurs_removed = body.map(|b| remove_slurs(&b));
-    Ok(PostForm {
-      name,
-      url,
-      body: body_slurs_removed,
-      creator_id: creator.id,
-      community_id: community.id,
-      removed: None,
-      locked: Some(!ext.comments_enabled),
-      published: page
-        .inner
-        .published()
-        .as_ref()
-        .map(|u| u.to_owned().naive_local()),
-      updated: page
-        .inner
-        .updated()
-        .as_ref()
-        .map(|u| u.to_owned().naive_local()),
-      deleted: None,
-      nsfw: ext.sensitive,
-      stickied: Some(ext.stickied),
-      embed_title: embed.title,
-      embed_description: embed.description,
-      embed_html: embed.html,
-      thumbnail_url,
-      ap_id: Some(check_actor_domain(page, expected_domain)?),
-      local: false,
-    })
-  }
+      .map(|u| u.to_owned().naive_local()),
+    deleted: None,
+    nsfw: ext.sensitive,
+    stickied: Some(ext.stickied),
+    distinguished: Some(ext.distinguished),
+    anonymous: None,
+    comments_closed: Some(ext.comments_closed),
+    embed_title: embed.title,
+    embed_description: embed.description,
+    embed_html: embed.html,
+    thumbnail_url,
+    thumbnail_alt_text,
+    ap_id: Some(check_actor_domain(page, expected_domain)?),
+    local: false,
+  })
 }
 
 #[async_trait::async_trait(?Send)]
 impl ApubObjectType for Post {
   /// Send out information about a newly created post, to the followers of the community.
   async fn send_create(&self, creator: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -312,7 +460,7 @@ impl ApubObjectType for Post {
     let mut create = Create::new(creator.actor_id.to_owned(), page.into_any_base()?);
     create
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(CreateType::Create)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -329,6 +477,10 @@ impl ApubObjectType for Post {
 
   /// Send out information about an edited post, to the followers of the community.
   async fn send_update(&self, creator: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -340,7 +492,7 @@ impl ApubObjectType for Post {
     let mut update = Update::new(creator.actor_id.to_owned(), page.into_any_base()?);
     update
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UpdateType::Update)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -356,6 +508,10 @@ impl ApubObjectType for Post {
   }
 
   async fn send_delete(&self, creator: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -367,7 +523,7 @@ impl ApubObjectType for Post {
     let mut delete = Delete::new(creator.actor_id.to_owned(), page.into_any_base()?);
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -387,6 +543,10 @@ impl ApubObjectType for Post {
     creator: &User_,
     context: &LemmyContext,
   ) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -398,7 +558,7 @@ impl ApubObjectType for Post {
     let mut delete = Delete::new(creator.actor_id.to_owned(), page.into_any_base()?);
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -406,7 +566,7 @@ impl ApubObjectType for Post {
     let mut undo = Undo::new(creator.actor_id.to_owned(), delete.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -421,7 +581,16 @@ impl ApubObjectType for Post {
     Ok(())
   }
 
-  async fn send_remove(&self, mod_: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+  async fn send_remove(
+    &self,
+    mod_: &User_,
+    reason: Option<String>,
+    context: &LemmyContext,
+  ) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -433,9 +602,12 @@ impl ApubObjectType for Post {
     let mut remove = Remove::new(mod_.actor_id.to_owned(), page.into_any_base()?);
     remove
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(RemoveType::Remove)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
+    if let Some(reason) = reason {
+      remove.set_summary(reason);
+    }
 
     send_activity_to_community(
       mod_,
@@ -449,6 +621,10 @@ impl ApubObjectType for Post {
   }
 
   async fn send_undo_remove(&self, mod_: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -460,7 +636,7 @@ impl ApubObjectType for Post {
     let mut remove = Remove::new(mod_.actor_id.to_owned(), page.into_any_base()?);
     remove
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(RemoveType::Remove)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -468,7 +644,7 @@ impl ApubObjectType for Post {
     let mut undo = Undo::new(mod_.actor_id.to_owned(), remove.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -487,6 +663,10 @@ impl ApubObjectType for Post {
 #[async_trait::async_trait(?Send)]
 impl ApubLikeableType for Post {
   async fn send_like(&self, creator: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -498,7 +678,7 @@ impl ApubLikeableType for Post {
     let mut like = Like::new(creator.actor_id.to_owned(), page.into_any_base()?);
     like
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(LikeType::Like)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -514,6 +694,10 @@ impl ApubLikeableType for Post {
   }
 
   async fn send_dislike(&self, creator: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -525,7 +709,7 @@ impl ApubLikeableType for Post {
     let mut dislike = Dislike::new(creator.actor_id.to_owned(), page.into_any_base()?);
     dislike
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DislikeType::Dislike)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -545,6 +729,10 @@ impl ApubLikeableType for Post {
     creator: &User_,
     context: &LemmyContext,
   ) -> Result<(), LemmyError> {
+    if is_withheld(self, context.pool()).await? {
+      return Ok(());
+    }
+
     let page = self.to_apub(context.pool()).await?;
 
     let community_id = self.community_id;
@@ -556,7 +744,7 @@ impl ApubLikeableType for Post {
     let mut like = Like::new(creator.actor_id.to_owned(), page.into_any_base()?);
     like
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(LikeType::Like)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
@@ -564,7 +752,7 @@ impl ApubLikeableType for Post {
     let mut undo = Undo::new(creator.actor_id.to_owned(), like.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(public())
       .set_many_ccs(vec![community.get_followers_url()?]);
 
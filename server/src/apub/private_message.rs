@@ -16,13 +16,7 @@ use crate::{
   LemmyContext,
 };
 use activitystreams::{
-  activity::{
-    kind::{CreateType, DeleteType, UndoType, UpdateType},
-    Create,
-    Delete,
-    Undo,
-    Update,
-  },
+  activity::{Create, Delete, Undo, Update},
   object::{kind::NoteType, Note, Tombstone},
   prelude::*,
 };
@@ -129,12 +123,12 @@ impl ApubObjectType for PrivateMessage {
     let to = recipient.get_inbox_url()?;
     create
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(CreateType::Create)?)
+      .set_id(generate_activity_id()?)
       .set_to(to.clone());
 
     insert_activity(creator.id, create.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), create, creator, vec![to])?;
+    send_activity(context, create, creator, vec![to]).await?;
     Ok(())
   }
 
@@ -149,12 +143,12 @@ impl ApubObjectType for PrivateMessage {
     let to = recipient.get_inbox_url()?;
     update
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UpdateType::Update)?)
+      .set_id(generate_activity_id()?)
       .set_to(to.clone());
 
     insert_activity(creator.id, update.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), update, creator, vec![to])?;
+    send_activity(context, update, creator, vec![to]).await?;
     Ok(())
   }
 
@@ -168,12 +162,12 @@ impl ApubObjectType for PrivateMessage {
     let to = recipient.get_inbox_url()?;
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(to.clone());
 
     insert_activity(creator.id, delete.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), delete, creator, vec![to])?;
+    send_activity(context, delete, creator, vec![to]).await?;
     Ok(())
   }
 
@@ -191,23 +185,28 @@ impl ApubObjectType for PrivateMessage {
     let to = recipient.get_inbox_url()?;
     delete
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(DeleteType::Delete)?)
+      .set_id(generate_activity_id()?)
       .set_to(to.clone());
 
     // Undo that fake activity
     let mut undo = Undo::new(creator.actor_id.to_owned(), delete.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?)
+      .set_id(generate_activity_id()?)
       .set_to(to.clone());
 
     insert_activity(creator.id, undo.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), undo, creator, vec![to])?;
+    send_activity(context, undo, creator, vec![to]).await?;
     Ok(())
   }
 
-  async fn send_remove(&self, _mod_: &User_, _context: &LemmyContext) -> Result<(), LemmyError> {
+  async fn send_remove(
+    &self,
+    _mod_: &User_,
+    _reason: Option<String>,
+    _context: &LemmyContext,
+  ) -> Result<(), LemmyError> {
     unimplemented!()
   }
 
@@ -0,0 +1,275 @@
+//! Feeds malformed and adversarial ActivityPub json into the parsing code. Whatever other instances
+//! send, parsing has to fail with an error instead of panicking.
+use crate::apub::{
+  check_actor_domain,
+  extensions::note_extension::NoteExtension,
+  inbox::shared_inbox::{get_community_id_from_activity, AcceptedActivities},
+  post::{extract_media_from_apub, post_form_from_page},
+  user::user_form_from_person,
+  GroupExt,
+  PageExt,
+  PersonExt,
+};
+use activitystreams::{object::Note, prelude::*};
+use activitystreams_ext::UnparsedExtension;
+use proptest::prelude::*;
+use serde_json::{json, Map, Value};
+use url::Url;
+
+/// Keys which the parsing code looks at, so that generated objects hit them more often than random
+/// keys would.
+const KEYS: &[&str] = &[
+  "type",
+  "id",
+  "actor",
+  "object",
+  "to",
+  "cc",
+  "attributedTo",
+  "inReplyTo",
+  "published",
+  "updated",
+  "url",
+  "icon",
+  "image",
+  "attachment",
+  "preview",
+  "name",
+  "summary",
+  "content",
+  "preferredUsername",
+  "inbox",
+  "outbox",
+  "publicKey",
+  "category",
+  "sensitive",
+  "commentsEnabled",
+  "stickied",
+  "discoverable",
+  "distinguished",
+];
+
+fn expected_domain() -> Option<Url> {
+  Some(Url::parse("https://example.com").unwrap())
+}
+
+fn arb_string() -> impl Strategy<Value = String> {
+  prop_oneof![
+    any::<String>(),
+    Just("https://example.com/u/alice".to_string()),
+    Just("https://evil.example.org/c/main".to_string()),
+    Just("http://[::1]/post/1".to_string()),
+    Just("2020-09-01T12:00:00+00:00".to_string()),
+    Just("9999-12-31T23:59:59-23:59".to_string()),
+    Just("Page".to_string()),
+    Just("Person".to_string()),
+    Just("Service".to_string()),
+    Just("Image".to_string()),
+    Just("Create".to_string()),
+    Just("Undo".to_string()),
+  ]
+}
+
+fn arb_json() -> impl Strategy<Value = Value> {
+  let leaf = prop_oneof![
+    Just(Value::Null),
+    any::<bool>().prop_map(Value::Bool),
+    any::<i64>().prop_map(Value::from),
+    any::<f64>().prop_map(Value::from),
+    arb_string().prop_map(Value::String),
+  ];
+  leaf.prop_recursive(4, 64, 8, |inner| {
+    prop_oneof![
+      prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Array),
+      prop::collection::vec((prop::sample::select(KEYS), inner), 0..8).prop_map(|fields| {
+        Value::Object(
+          fields
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<Map<String, Value>>(),
+        )
+      }),
+    ]
+  })
+}
+
+/// Replaces or removes one field of a valid object.
+fn mutate(valid: Value, key: &str, replacement: Option<Value>) -> Value {
+  let mut object = valid.as_object().unwrap().to_owned();
+  match replacement {
+    Some(value) => object.insert(key.to_string(), value),
+    None => object.remove(key),
+  };
+  Value::Object(object)
+}
+
+fn valid_page() -> Value {
+  json!({
+    "type": "Page",
+    "id": "https://example.com/post/1",
+    "attributedTo": "https://example.com/u/alice",
+    "to": "https://example.com/c/main",
+    "summary": "A post",
+    "content": "Its body",
+    "url": "https://example.org/article",
+    "image": { "type": "Image", "url": "https://example.org/thumbnail.png", "name": "alt" },
+    "attachment": [{ "type": "Image", "url": "https://example.org/1.png" }],
+    "published": "2020-09-01T12:00:00+00:00",
+    "commentsEnabled": true,
+    "sensitive": false,
+    "stickied": false
+  })
+}
+
+fn valid_person() -> Value {
+  json!({
+    "type": "Person",
+    "id": "https://example.com/u/alice",
+    "name": "alice",
+    "preferredUsername": "Alice",
+    "summary": "About alice",
+    "icon": { "type": "Image", "url": "https://example.com/avatar.png" },
+    "inbox": "https://example.com/u/alice/inbox",
+    "outbox": "https://example.com/u/alice/outbox",
+    "publicKey": {
+      "id": "https://example.com/u/alice#main-key",
+      "owner": "https://example.com/u/alice",
+      "publicKeyPem": "-----BEGIN PUBLIC KEY-----"
+    }
+  })
+}
+
+fn valid_activity() -> Value {
+  json!({
+    "type": "Create",
+    "id": "https://example.com/activities/create/1",
+    "actor": "https://example.com/u/alice",
+    "to": "https://www.w3.org/ns/activitystreams#Public",
+    "cc": ["https://example.com/c/main"],
+    "object": valid_page()
+  })
+}
+
+fn check_page(json: Value) {
+  if let Ok(page) = serde_json::from_value::<PageExt>(json) {
+    let _ = post_form_from_page(&page, 1, 1, expected_domain());
+    let _ = extract_media_from_apub(&page, 1);
+  }
+}
+
+fn check_person(json: Value) {
+  if let Ok(person) = serde_json::from_value::<PersonExt>(json) {
+    let _ = user_form_from_person(&person, expected_domain());
+  }
+}
+
+fn check_activity(json: Value) {
+  if let Ok(activity) = serde_json::from_value::<AcceptedActivities>(json) {
+    let _ = get_community_id_from_activity(&activity);
+    let _ = activity.actor().map(|a| a.as_single_xsd_any_uri().cloned());
+    let _ = activity.object().as_single_kind_str();
+    let _ = activity.kind();
+  }
+}
+
+fn check_note(json: Value) {
+  if let Ok(note) = serde_json::from_value::<Note>(json) {
+    let _ = check_actor_domain(&note, expected_domain());
+    let _ = NoteExtension::try_from_unparsed(&mut note.to_owned());
+    let _ = note
+      .in_reply_to()
+      .and_then(|i| i.as_many())
+      .map(|i| i.iter().map(|i| i.as_xsd_any_uri()).count());
+  }
+}
+
+#[test]
+fn test_valid_fixtures_parse() {
+  let page = serde_json::from_value::<PageExt>(valid_page()).unwrap();
+  let post_form = post_form_from_page(&page, 1, 2, expected_domain()).unwrap();
+  assert_eq!("A post", post_form.name);
+  assert_eq!(2, post_form.community_id);
+  // Instances which don't know about distinguished posts leave the field out
+  assert_eq!(Some(false), post_form.distinguished);
+
+  let person = serde_json::from_value::<PersonExt>(valid_person()).unwrap();
+  let user_form = user_form_from_person(&person, expected_domain()).unwrap();
+  assert_eq!("alice", user_form.name);
+  assert!(!user_form.bot_account);
+
+  let activity = serde_json::from_value::<AcceptedActivities>(valid_activity()).unwrap();
+  assert_eq!(
+    "https://example.com/c/main",
+    get_community_id_from_activity(&activity).unwrap().as_str()
+  );
+}
+
+#[test]
+fn test_foreign_domain_is_rejected() {
+  let page = mutate(
+    valid_page(),
+    "id",
+    Some(json!("https://evil.example.org/post/1")),
+  );
+  let page = serde_json::from_value::<PageExt>(page).unwrap();
+  assert!(post_form_from_page(&page, 1, 1, expected_domain()).is_err());
+}
+
+#[test]
+fn test_other_attachments_are_skipped() {
+  let page = mutate(
+    valid_page(),
+    "attachment",
+    Some(json!([
+      { "type": "Link", "href": "https://example.org/article" },
+      { "type": "Document", "url": "https://example.org/1.pdf" },
+      { "type": "Image", "url": "https://example.org/1.png" }
+    ])),
+  );
+  let page = serde_json::from_value::<PageExt>(page).unwrap();
+  let media = extract_media_from_apub(&page, 1);
+  assert_eq!(1, media.len());
+  assert_eq!("https://example.org/1.png", media[0].url);
+  assert_eq!(0, media[0].position);
+}
+
+#[test]
+fn test_service_is_bot_account() {
+  let person = mutate(valid_person(), "type", Some(json!("Service")));
+  let person = serde_json::from_value::<PersonExt>(person).unwrap();
+  let user_form = user_form_from_person(&person, expected_domain()).unwrap();
+  assert!(user_form.bot_account);
+}
+
+proptest! {
+  #[test]
+  fn proptest_arbitrary_json(json in arb_json()) {
+    check_page(json.clone());
+    check_person(json.clone());
+    check_activity(json.clone());
+    check_note(json.clone());
+    let _ = serde_json::from_value::<GroupExt>(json);
+  }
+
+  #[test]
+  fn proptest_arbitrary_text(text in any::<String>()) {
+    let _ = serde_json::from_str::<AcceptedActivities>(&text);
+    let _ = serde_json::from_str::<PageExt>(&text);
+    let _ = serde_json::from_str::<PersonExt>(&text);
+  }
+
+  #[test]
+  fn proptest_mutated_page(key in prop::sample::select(KEYS), value in prop::option::of(arb_json())) {
+    check_page(mutate(valid_page(), key, value));
+  }
+
+  #[test]
+  fn proptest_mutated_person(key in prop::sample::select(KEYS), value in prop::option::of(arb_json())) {
+    check_person(mutate(valid_person(), key, value));
+  }
+
+  #[test]
+  fn proptest_mutated_activity(key in prop::sample::select(KEYS), value in prop::option::of(arb_json())) {
+    check_activity(mutate(valid_activity(), key, value));
+  }
+}
@@ -3,42 +3,48 @@ use crate::{
     activities::generate_activity_id,
     activity_queue::send_activity,
     check_actor_domain,
-    create_apub_response,
+    create_apub_tombstone_response,
+    create_cached_apub_response,
+    create_outbox_response,
+    create_tombstone,
+    extensions::person_extension::PersonExtension,
     fetcher::get_or_fetch_and_upsert_actor,
     insert_activity,
+    is_public_fetch_authorized,
     ActorType,
     FromApub,
+    OutboxQuery,
     PersonExt,
     ToApub,
+    UserActorType,
   },
   DbPool,
   LemmyContext,
 };
 use activitystreams::{
-  activity::{
-    kind::{FollowType, UndoType},
-    Follow,
-    Undo,
-  },
-  actor::{ApActor, Endpoints, Person},
+  activity::{Follow, Undo, Update},
+  actor::{Actor, ApActor, Endpoints},
   object::{Image, Tombstone},
   prelude::*,
+  public,
 };
-use activitystreams_ext::Ext1;
-use actix_web::{body::Body, web, HttpResponse};
+use activitystreams_ext::Ext2;
+use actix_web::{body::Body, web, HttpRequest, HttpResponse};
 use anyhow::Context;
 use lemmy_api_structs::blocking;
 use lemmy_db::{
+  community_view::CommunityFollowerView,
   naive_now,
   user::{UserForm, User_},
 };
 use lemmy_utils::{
   location_info,
+  settings::Settings,
   utils::{check_slurs, check_slurs_opt, convert_datetime},
   LemmyError,
 };
 use serde::Deserialize;
-use url::Url;
+use url::{Position, Url};
 
 #[derive(Deserialize)]
 pub struct UserQuery {
@@ -52,7 +58,8 @@ impl ToApub for User_ {
   // Turn a Lemmy Community into an ActivityPub group that can be sent out over the network.
   async fn to_apub(&self, _pool: &DbPool) -> Result<PersonExt, LemmyError> {
     // TODO go through all these to_string and to_owned()
-    let mut person = Person::new();
+    let mut person = Actor::<UserActorType>::new();
+    person.set_kind(user_actor_type(self));
     person
       .set_context(activitystreams::context())
       .set_id(Url::parse(&self.actor_id)?)
@@ -94,10 +101,16 @@ impl ToApub for User_ {
       ap_actor.set_preferred_username(i.to_owned());
     }
 
-    Ok(Ext1::new(ap_actor, self.get_public_key_ext()?))
+    let ext = PersonExtension::new(self.discoverable && Settings::get().federation.discoverable);
+    Ok(Ext2::new(ap_actor, ext, self.get_public_key_ext()?))
   }
   fn to_tombstone(&self) -> Result<Tombstone, LemmyError> {
-    unimplemented!()
+    create_tombstone(
+      self.deleted,
+      &self.actor_id,
+      self.updated,
+      user_actor_type(self),
+    )
   }
 }
 
@@ -124,13 +137,13 @@ impl ActorType for User_ {
     let mut follow = Follow::new(self.actor_id.to_owned(), follow_actor_id.as_str());
     follow
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(FollowType::Follow)?);
+      .set_id(generate_activity_id()?);
     let follow_actor = get_or_fetch_and_upsert_actor(follow_actor_id, context).await?;
     let to = follow_actor.get_inbox_url()?;
 
     insert_activity(self.id, follow.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), follow, self, vec![to])?;
+    send_activity(context, follow, self, vec![to]).await?;
     Ok(())
   }
 
@@ -142,7 +155,7 @@ impl ActorType for User_ {
     let mut follow = Follow::new(self.actor_id.to_owned(), follow_actor_id.as_str());
     follow
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(FollowType::Follow)?);
+      .set_id(generate_activity_id()?);
     let follow_actor = get_or_fetch_and_upsert_actor(follow_actor_id, context).await?;
 
     let to = follow_actor.get_inbox_url()?;
@@ -151,11 +164,11 @@ impl ActorType for User_ {
     let mut undo = Undo::new(Url::parse(&self.actor_id)?, follow.into_any_base()?);
     undo
       .set_context(activitystreams::context())
-      .set_id(generate_activity_id(UndoType::Undo)?);
+      .set_id(generate_activity_id()?);
 
     insert_activity(self.id, undo.clone(), true, context.pool()).await?;
 
-    send_activity(context.activity_queue(), undo, self, vec![to])?;
+    send_activity(context, undo, self, vec![to]).await?;
     Ok(())
   }
 
@@ -209,88 +222,179 @@ impl FromApub for UserForm {
     _context: &LemmyContext,
     expected_domain: Option<Url>,
   ) -> Result<Self, LemmyError> {
-    let avatar = match person.icon() {
-      Some(any_image) => Some(
-        Image::from_any_base(any_image.as_one().context(location_info!())?.clone())?
-          .context(location_info!())?
-          .url()
-          .context(location_info!())?
-          .as_single_xsd_any_uri()
-          .map(|u| u.to_string()),
-      ),
-      None => None,
-    };
-
-    let banner = match person.image() {
-      Some(any_image) => Some(
-        Image::from_any_base(any_image.as_one().context(location_info!())?.clone())
-          .context(location_info!())?
-          .context(location_info!())?
-          .url()
-          .context(location_info!())?
-          .as_single_xsd_any_uri()
-          .map(|u| u.to_string()),
-      ),
-      None => None,
-    };
-
-    let name = person
-      .name()
-      .context(location_info!())?
-      .one()
-      .context(location_info!())?
-      .as_xsd_string()
-      .context(location_info!())?
-      .to_string();
-    let preferred_username = person.inner.preferred_username().map(|u| u.to_string());
-    let bio = person
-      .inner
-      .summary()
-      .map(|s| s.as_single_xsd_string())
-      .flatten()
-      .map(|s| s.to_string());
-    check_slurs(&name)?;
-    check_slurs_opt(&preferred_username)?;
-    check_slurs_opt(&bio)?;
-
-    Ok(UserForm {
-      name,
-      preferred_username,
-      password_encrypted: "".to_string(),
-      admin: false,
-      banned: false,
-      email: None,
-      avatar,
-      banner,
-      updated: person.updated().map(|u| u.to_owned().naive_local()),
-      show_nsfw: false,
-      theme: "".to_string(),
-      default_sort_type: 0,
-      default_listing_type: 0,
-      lang: "".to_string(),
-      show_avatars: false,
-      send_notifications_to_email: false,
-      matrix_user_id: None,
-      actor_id: Some(check_actor_domain(person, expected_domain)?),
-      bio,
-      local: false,
-      private_key: None,
-      public_key: Some(person.ext_one.public_key.to_owned().public_key_pem),
-      last_refreshed_at: Some(naive_now()),
-    })
+    user_form_from_person(person, expected_domain)
+  }
+}
+
+fn user_actor_type(user: &User_) -> UserActorType {
+  if user.bot_account {
+    UserActorType::Service
+  } else {
+    UserActorType::Person
   }
 }
 
+/// Parsing a person doesn't need any context, unlike the other `FromApub` impls.
+pub(in crate::apub) fn user_form_from_person(
+  person: &PersonExt,
+  expected_domain: Option<Url>,
+) -> Result<UserForm, LemmyError> {
+  let avatar = match person.icon() {
+    Some(any_image) => Some(
+      Image::from_any_base(any_image.as_one().context(location_info!())?.clone())?
+        .context(location_info!())?
+        .url()
+        .context(location_info!())?
+        .as_single_xsd_any_uri()
+        .map(|u| u.to_string()),
+    ),
+    None => None,
+  };
+
+  let banner = match person.image() {
+    Some(any_image) => Some(
+      Image::from_any_base(any_image.as_one().context(location_info!())?.clone())
+        .context(location_info!())?
+        .context(location_info!())?
+        .url()
+        .context(location_info!())?
+        .as_single_xsd_any_uri()
+        .map(|u| u.to_string()),
+    ),
+    None => None,
+  };
+
+  let name = person
+    .name()
+    .context(location_info!())?
+    .one()
+    .context(location_info!())?
+    .as_xsd_string()
+    .context(location_info!())?
+    .to_string();
+  let preferred_username = person.inner.preferred_username().map(|u| u.to_string());
+  let bio = person
+    .inner
+    .summary()
+    .map(|s| s.as_single_xsd_string())
+    .flatten()
+    .map(|s| s.to_string());
+  check_slurs(&name)?;
+  check_slurs_opt(&preferred_username)?;
+  check_slurs_opt(&bio)?;
+
+  Ok(UserForm {
+    name,
+    preferred_username,
+    password_encrypted: "".to_string(),
+    admin: false,
+    banned: false,
+    email: None,
+    avatar,
+    banner,
+    updated: person.updated().map(|u| u.to_owned().naive_local()),
+    show_nsfw: false,
+    theme: "".to_string(),
+    default_sort_type: 0,
+    default_listing_type: 0,
+    lang: "".to_string(),
+    show_avatars: false,
+    send_notifications_to_email: false,
+    matrix_user_id: None,
+    actor_id: Some(check_actor_domain(person, expected_domain)?),
+    bio,
+    local: false,
+    private_key: None,
+    public_key: Some(person.ext_two.public_key.to_owned().public_key_pem),
+    last_refreshed_at: Some(naive_now()),
+    discoverable: person.ext_one.is_discoverable(),
+    receive_welcome_messages: true,
+    bot_account: person.kind() == Some(&UserActorType::Service),
+    collapse_comments_below_score: None,
+    collapse_bot_comments: false,
+    remember_comment_sort: false,
+  })
+}
+
 /// Return the user json over HTTP.
 pub async fn get_apub_user_http(
   info: web::Path<UserQuery>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse<Body>, LemmyError> {
+  let user_name = info.into_inner().user_name;
+  let cache_key = format!("/u/{}", user_name);
+  if let Some(res) = context.response_cache().get(&cache_key) {
+    return Ok(res);
+  }
+
+  let user = blocking(context.pool(), move |conn| {
+    User_::find_by_email_or_username(conn, &user_name)
+  })
+  .await??;
+
+  if !user.deleted {
+    let u = user.to_apub(context.pool()).await?;
+    create_cached_apub_response(&u, cache_key, None, &context)
+  } else {
+    Ok(create_apub_tombstone_response(&user.to_tombstone()?))
+  }
+}
+
+/// The activities of the user.
+pub async fn get_apub_user_outbox(
+  request: HttpRequest,
+  info: web::Path<UserQuery>,
+  query: web::Query<OutboxQuery>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse<Body>, LemmyError> {
+  if !is_public_fetch_authorized(&request, &context).await? {
+    return Ok(HttpResponse::Unauthorized().finish());
+  }
+
   let user_name = info.into_inner().user_name;
   let user = blocking(context.pool(), move |conn| {
     User_::find_by_email_or_username(conn, &user_name)
   })
   .await??;
-  let u = user.to_apub(context.pool()).await?;
-  Ok(create_apub_response(&u))
+
+  if user.deleted || !user.local {
+    return Ok(HttpResponse::NotFound().finish());
+  }
+
+  create_outbox_response(&user, query.into_inner(), context.pool()).await
+}
+
+/// As a local user, send out the current profile, eg after its key changed. It goes to the
+/// instances of the remote communities the user follows, others refetch the user when its
+/// signatures don't match the key they have.
+pub async fn send_update_user(user: &User_, context: &LemmyContext) -> Result<(), LemmyError> {
+  let person = user.to_apub(context.pool()).await?;
+  let mut update = Update::new(user.actor_id.to_owned(), person.into_any_base()?);
+  update
+    .set_context(activitystreams::context())
+    .set_id(generate_activity_id()?)
+    .set_to(public())
+    .set_many_ccs(vec![user.get_followers_url()?]);
+
+  let user_id = user.id;
+  let follows = blocking(context.pool(), move |conn| {
+    CommunityFollowerView::for_user(conn, user_id)
+  })
+  .await??;
+  let mut inboxes = Vec::new();
+  for follow in follows.iter().filter(|f| !f.community_local) {
+    let community_id = Url::parse(&follow.community_actor_id)?;
+    let inbox = Url::parse(&format!(
+      "{}://{}/inbox",
+      community_id.scheme(),
+      &community_id[Position::BeforeHost..Position::AfterPort]
+    ))?;
+    if !inboxes.contains(&inbox) {
+      inboxes.push(inbox);
+    }
+  }
+
+  insert_activity(user.id, update.clone(), true, context.pool()).await?;
+  send_activity(context, update, user, inboxes).await?;
+  Ok(())
 }
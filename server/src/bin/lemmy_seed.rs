@@ -0,0 +1,384 @@
+//! Fills the database with a large, realistic looking dataset for load testing and benchmarks.
+//!
+//! Run it against an empty test database, as the users it creates can log in with the password
+//! `seed_password`:
+//!
+//! ```bash
+//! cargo run --release --bin lemmy_seed -- 10
+//! ```
+//!
+//! The argument scales the dataset, 1 creates 100 users, 10 communities, 1000 posts and 10000
+//! comments.
+use chrono::Duration;
+use diesel::{Connection, PgConnection};
+use lemmy_db::{
+  comment::{Comment, CommentForm, CommentLike, CommentLikeForm},
+  community::{Community, CommunityFollower, CommunityFollowerForm, CommunityForm},
+  get_database_url_from_env,
+  naive_now,
+  post::{Post, PostForm, PostLike, PostLikeForm},
+  user::{UserForm, User_},
+  Crud,
+  Followable,
+  Likeable,
+  ListingType,
+  SortType,
+};
+use lemmy_utils::{
+  apub::{generate_actor_keypair, make_apub_endpoint, EndpointType},
+  password::hash_password,
+  settings::Settings,
+  LemmyError,
+};
+use rand::{
+  distributions::{Alphanumeric, WeightedIndex},
+  prelude::*,
+};
+
+const USERS: usize = 100;
+const COMMUNITIES: usize = 10;
+const POSTS: usize = 1000;
+const COMMENTS: usize = 10000;
+/// Posts and comments are spread over this many days, so that the hot ranking has something to do
+const DAYS: i64 = 30;
+
+static WORDS: &[&str] = &[
+  "the",
+  "a",
+  "federated",
+  "link",
+  "aggregator",
+  "rust",
+  "community",
+  "server",
+  "release",
+  "new",
+  "why",
+  "how",
+  "does",
+  "anyone",
+  "know",
+  "about",
+  "this",
+  "open",
+  "source",
+  "project",
+  "is",
+  "finally",
+  "here",
+  "update",
+  "discussion",
+  "weekly",
+  "question",
+  "help",
+  "with",
+  "my",
+  "first",
+  "time",
+  "using",
+  "privacy",
+  "self",
+  "hosting",
+  "guide",
+  "to",
+  "and",
+  "of",
+  "in",
+  "for",
+  "news",
+  "today",
+  "thoughts",
+  "on",
+  "best",
+  "way",
+  "learn",
+  "programming",
+  "linux",
+  "music",
+  "art",
+];
+
+fn main() -> Result<(), LemmyError> {
+  let scale = std::env::args()
+    .nth(1)
+    .map(|s| s.parse::<usize>().expect("The scale has to be a number"))
+    .unwrap_or(1);
+
+  let db_url = match get_database_url_from_env() {
+    Ok(url) => url,
+    Err(_) => Settings::get().get_database_url(),
+  };
+  let conn = PgConnection::establish(&db_url)?;
+  let mut rng = thread_rng();
+
+  // Names have to be unique, so every run gets its own prefix
+  let run: String = thread_rng()
+    .sample_iter(&Alphanumeric)
+    .filter(|c| c.is_ascii_lowercase())
+    .take(4)
+    .collect();
+
+  let users = seed_users(&conn, &run, USERS * scale)?;
+  println!("Created {} users", users.len());
+
+  let communities = seed_communities(&conn, &run, COMMUNITIES * scale, &users, &mut rng)?;
+  println!("Created {} communities", communities.len());
+
+  let posts = seed_posts(&conn, POSTS * scale, &users, &communities, &mut rng)?;
+  println!("Created {} posts", posts.len());
+
+  let comments = seed_comments(&conn, COMMENTS * scale, &users, &posts, &mut rng)?;
+  println!("Created {} comments", comments);
+
+  Ok(())
+}
+
+fn seed_users(conn: &PgConnection, run: &str, count: usize) -> Result<Vec<User_>, LemmyError> {
+  let password_encrypted = hash_password("seed_password", &Settings::get().passwords)?;
+  // Generating a keypair for every user would take most of the time, they can share one
+  let keypair = generate_actor_keypair()?;
+
+  let mut users = Vec::with_capacity(count);
+  for i in 0..count {
+    let name = format!("seed_{}_{}", run, i);
+    let form = UserForm {
+      name: name.to_owned(),
+      preferred_username: None,
+      password_encrypted: password_encrypted.to_owned(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      banner: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+      actor_id: Some(make_apub_endpoint(EndpointType::User, &name).to_string()),
+      bio: None,
+      local: true,
+      private_key: Some(keypair.private_key.to_owned()),
+      public_key: Some(keypair.public_key.to_owned()),
+      last_refreshed_at: None,
+      discoverable: true,
+      receive_welcome_messages: true,
+      bot_account: false,
+      collapse_comments_below_score: None,
+      collapse_bot_comments: false,
+      remember_comment_sort: false,
+    };
+    users.push(User_::create(conn, &form)?);
+  }
+  Ok(users)
+}
+
+fn seed_communities(
+  conn: &PgConnection,
+  run: &str,
+  count: usize,
+  users: &[User_],
+  rng: &mut ThreadRng,
+) -> Result<Vec<Community>, LemmyError> {
+  let keypair = generate_actor_keypair()?;
+
+  let mut communities = Vec::with_capacity(count);
+  for i in 0..count {
+    let name = format!("seed_{}_{}", run, i);
+    let form = CommunityForm {
+      name: name.to_owned(),
+      title: sentence(rng, 2, 5),
+      description: Some(paragraphs(rng, 1)),
+      category_id: rng.gen_range(1, 10),
+      creator_id: users.choose(rng).expect("no users").id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+      actor_id: Some(make_apub_endpoint(EndpointType::Community, &name).to_string()),
+      local: true,
+      private_key: Some(keypair.private_key.to_owned()),
+      public_key: Some(keypair.public_key.to_owned()),
+      last_refreshed_at: None,
+      published: Some(past(rng)),
+      icon: None,
+      banner: None,
+    };
+    let community = Community::create(conn, &form)?;
+
+    // A few big communities have most of the subscribers
+    let followers = users.len() / (i + 1);
+    for user in users.choose_multiple(rng, followers) {
+      let follower_form = CommunityFollowerForm {
+        community_id: community.id,
+        user_id: user.id,
+      };
+      CommunityFollower::follow(conn, &follower_form)?;
+    }
+    communities.push(community);
+  }
+  Ok(communities)
+}
+
+fn seed_posts(
+  conn: &PgConnection,
+  count: usize,
+  users: &[User_],
+  communities: &[Community],
+  rng: &mut ThreadRng,
+) -> Result<Vec<Post>, LemmyError> {
+  let community_weights =
+    WeightedIndex::new((1..=communities.len()).map(|i| 1.0 / i as f64)).expect("no communities");
+
+  let mut posts = Vec::with_capacity(count);
+  for _ in 0..count {
+    let is_link = rng.gen_bool(0.4);
+    let body_paragraphs = rng.gen_range(1, 5);
+    let form = PostForm {
+      name: sentence(rng, 3, 12),
+      url: if is_link {
+        Some(format!("https://example.com/{}", rng.gen::<u32>()))
+      } else {
+        None
+      },
+      body: if is_link {
+        None
+      } else {
+        Some(paragraphs(rng, body_paragraphs))
+      },
+      creator_id: users.choose(rng).expect("no users").id,
+      community_id: communities[community_weights.sample(rng)].id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      nsfw: false,
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      thumbnail_alt_text: None,
+      distinguished: None,
+      anonymous: None,
+      comments_closed: None,
+      ap_id: None,
+      local: true,
+      published: Some(past(rng)),
+    };
+    let post = Post::create(conn, &form)?;
+    let apub_id = make_apub_endpoint(EndpointType::Post, &post.id.to_string()).to_string();
+    let post = Post::update_ap_id(conn, post.id, apub_id)?;
+
+    // Scores follow a long tail, most posts barely get any votes
+    let voters = (users.len() as f64 * rng.gen::<f64>().powi(4)) as usize;
+    for user in users.choose_multiple(rng, voters) {
+      let like_form = PostLikeForm {
+        post_id: post.id,
+        user_id: user.id,
+        score: if rng.gen_bool(0.85) { 1 } else { -1 },
+      };
+      PostLike::like(conn, &like_form)?;
+    }
+    posts.push(post);
+  }
+  Ok(posts)
+}
+
+/// Comments go to popular posts more often, and build deep threads by replying to each other.
+fn seed_comments(
+  conn: &PgConnection,
+  count: usize,
+  users: &[User_],
+  posts: &[Post],
+  rng: &mut ThreadRng,
+) -> Result<usize, LemmyError> {
+  let post_weights =
+    WeightedIndex::new((1..=posts.len()).map(|i| 1.0 / i as f64)).expect("no posts");
+  let mut post_comments: Vec<Vec<Comment>> = vec![Vec::new(); posts.len()];
+
+  for _ in 0..count {
+    let post_index = post_weights.sample(rng);
+    let post = &posts[post_index];
+    let parent_id = if rng.gen_bool(0.7) {
+      post_comments[post_index].choose(rng).map(|c| c.id)
+    } else {
+      None
+    };
+    let content_paragraphs = rng.gen_range(1, 3);
+    let form = CommentForm {
+      creator_id: users.choose(rng).expect("no users").id,
+      post_id: post.id,
+      parent_id,
+      content: paragraphs(rng, content_paragraphs),
+      removed: None,
+      read: None,
+      published: Some(post.published + Duration::minutes(rng.gen_range(1, 60 * 24))),
+      updated: None,
+      deleted: None,
+      ap_id: None,
+      local: true,
+      distinguished: None,
+    };
+    let comment = Comment::create(conn, &form)?;
+    let apub_id = make_apub_endpoint(EndpointType::Comment, &comment.id.to_string()).to_string();
+    let comment = Comment::update_ap_id(conn, comment.id, apub_id)?;
+
+    let voters = (users.len() as f64 * rng.gen::<f64>().powi(6)) as usize;
+    for user in users.choose_multiple(rng, voters) {
+      let like_form = CommentLikeForm {
+        user_id: user.id,
+        comment_id: comment.id,
+        post_id: post.id,
+        score: if rng.gen_bool(0.8) { 1 } else { -1 },
+      };
+      CommentLike::like(conn, &like_form)?;
+    }
+    post_comments[post_index].push(comment);
+  }
+  Ok(count)
+}
+
+fn past(rng: &mut ThreadRng) -> chrono::NaiveDateTime {
+  naive_now() - Duration::minutes(rng.gen_range(0, DAYS * 24 * 60))
+}
+
+fn sentence(rng: &mut ThreadRng, min_words: usize, max_words: usize) -> String {
+  let words = rng.gen_range(min_words, max_words + 1);
+  let mut sentence = (0..words)
+    .map(|_| *WORDS.choose(rng).expect("no words"))
+    .collect::<Vec<&str>>()
+    .join(" ");
+  if let Some(first) = sentence.get_mut(0..1) {
+    first.make_ascii_uppercase();
+  }
+  sentence
+}
+
+/// Markdown with some formatting, so that rendering it isn't trivial.
+fn paragraphs(rng: &mut ThreadRng, count: usize) -> String {
+  (0..count)
+    .map(|_| match rng.gen_range(0, 5) {
+      0 => format!("**{}**. {}.", sentence(rng, 2, 4), sentence(rng, 8, 20)),
+      1 => format!(
+        "{} [{}](https://example.com/{}).",
+        sentence(rng, 5, 15),
+        sentence(rng, 1, 3),
+        rng.gen::<u32>()
+      ),
+      2 => format!("> {}\n\n{}.", sentence(rng, 5, 15), sentence(rng, 5, 15)),
+      3 => format!(
+        "- {}\n- {}\n- {}",
+        sentence(rng, 2, 6),
+        sentence(rng, 2, 6),
+        sentence(rng, 2, 6)
+      ),
+      _ => format!("{}. {}.", sentence(rng, 8, 20), sentence(rng, 8, 20)),
+    })
+    .collect::<Vec<String>>()
+    .join("\n\n")
+}
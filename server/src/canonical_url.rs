@@ -0,0 +1,142 @@
+//! Canonical forms of the urls of posts, so that a link submitted in different ways is still found
+//! as a duplicate, and by searching for it.
+use crate::request::{check_outbound_url, link_preview_timeout};
+use anyhow::Context;
+use lemmy_utils::{location_info, settings::Settings, APIError, LemmyError};
+use log::debug;
+use reqwest::{header::LOCATION, Client};
+use url::Url;
+
+/// Query parameters which only track where a click came from
+const TRACKING_PARAMS: &[&str] = &[
+  "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_hsenc", "_hsmi",
+  "ref_src",
+];
+const TRACKING_PARAM_PREFIXES: &[&str] = &["utm_"];
+
+/// Hosts which only redirect to the real url
+const SHORTENER_HOSTS: &[&str] = &[
+  "bit.ly",
+  "buff.ly",
+  "dlvr.it",
+  "goo.gl",
+  "is.gd",
+  "lnkd.in",
+  "ow.ly",
+  "t.co",
+  "tinyurl.com",
+  "trib.al",
+];
+
+/// Removes tracking parameters, and an empty query or fragment. Parsing the url already lowercased
+/// the host, and removed the default port.
+pub fn canonicalize_url(url: &Url) -> Url {
+  let mut url = url.to_owned();
+  if let Some(query) = url.query() {
+    let kept = query
+      .split('&')
+      .filter(|p| !p.is_empty() && !is_tracking_param(p.split('=').next().unwrap_or_default()))
+      .collect::<Vec<&str>>()
+      .join("&");
+    url.set_query(if kept.is_empty() { None } else { Some(&kept) });
+  }
+  if url.fragment() == Some("") {
+    url.set_fragment(None);
+  }
+  url
+}
+
+/// Resolves url shorteners, then canonicalizes the url. A shortener which doesn't answer leaves the
+/// url as it was given.
+pub async fn canonicalize_post_url(client: &Client, url: &str) -> Result<String, LemmyError> {
+  let url = Url::parse(url).map_err(|_| APIError::err("invalid_url"))?;
+  let resolved = match resolve_shortener(client, &url).await {
+    Ok(resolved) => resolved,
+    Err(e) => {
+      debug!("Couldn't resolve shortened url {}: {}", url, e);
+      url
+    }
+  };
+  Ok(canonicalize_url(&resolved).to_string())
+}
+
+/// Follows the redirects of known shorteners with HEAD requests, as long as they lead to another
+/// shortener.
+async fn resolve_shortener(client: &Client, url: &Url) -> Result<Url, LemmyError> {
+  let mut url = url.to_owned();
+  for _ in 0..Settings::get().outbound_requests.max_redirects {
+    if !is_shortener(&url) {
+      break;
+    }
+    check_outbound_url(&url).await?;
+    let response = client
+      .head(url.as_str())
+      .timeout(link_preview_timeout())
+      .send()
+      .await?;
+    if !response.status().is_redirection() {
+      break;
+    }
+    let location = response
+      .headers()
+      .get(LOCATION)
+      .context(location_info!())?
+      .to_str()?;
+    url = url.join(location)?;
+  }
+  Ok(url)
+}
+
+/// The host of the url, lowercased and without www., like the url_domain function of the database.
+pub fn url_domain(url: &str) -> Option<String> {
+  let url = Url::parse(url).ok()?;
+  let host = url.host_str()?.to_lowercase();
+  Some(host.strip_prefix("www.").unwrap_or(&host).to_owned())
+}
+
+fn is_shortener(url: &Url) -> bool {
+  url
+    .host_str()
+    .map_or(false, |host| SHORTENER_HOSTS.contains(&host))
+}
+
+fn is_tracking_param(name: &str) -> bool {
+  let name = name.to_lowercase();
+  TRACKING_PARAMS.contains(&name.as_str())
+    || TRACKING_PARAM_PREFIXES
+      .iter()
+      .any(|prefix| name.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::canonical_url::{canonicalize_url, is_shortener, url_domain};
+  use url::Url;
+
+  fn canonical(url: &str) -> String {
+    canonicalize_url(&Url::parse(url).unwrap()).to_string()
+  }
+
+  #[test]
+  fn test_canonicalize_url() {
+    assert_eq!(
+      "https://example.com/a?id=3",
+      canonical("HTTPS://Example.COM:443/a?utm_source=x&id=3&fbclid=abc")
+    );
+    assert_eq!(
+      "http://example.com/",
+      canonical("http://example.com:80/?UTM_Medium=feed#")
+    );
+    assert_eq!(
+      "https://example.com:8443/a?b&c=d#top",
+      canonical("https://example.com:8443/a?b&c=d#top")
+    );
+    assert_eq!(
+      Some("example.com".to_string()),
+      url_domain("https://WWW.Example.com/a")
+    );
+    assert_eq!(None, url_domain("not a url"));
+    assert!(is_shortener(&Url::parse("https://bit.ly/3abc").unwrap()));
+    assert!(!is_shortener(&Url::parse("https://example.com/").unwrap()));
+  }
+}
@@ -6,11 +6,14 @@ use diesel::{
 use lemmy_db::{
   comment::Comment,
   community::{Community, CommunityForm},
+  instance_actor::{InstanceActor, InstanceActorForm},
   naive_now,
   post::Post,
   private_message::PrivateMessage,
-  user::{UserForm, User_},
+  user::{automod_user_name, UserForm, User_, DELETED_USER_NAME},
   Crud,
+  ListingType,
+  SortType,
 };
 use lemmy_utils::{
   apub::{generate_actor_keypair, get_apub_protocol_string, make_apub_endpoint, EndpointType},
@@ -26,6 +29,9 @@ pub fn run_advanced_migrations(conn: &PgConnection) -> Result<(), LemmyError> {
   comment_updates_2020_04_03(&conn)?;
   private_message_updates_2020_05_05(&conn)?;
   post_thumbnail_url_updates_2020_07_27(&conn)?;
+  deleted_user_placeholder_2020_09_29(&conn)?;
+  instance_actor_2020_10_28(&conn)?;
+  automod_user_names_2020_11_02(&conn)?;
 
   Ok(())
 }
@@ -70,6 +76,12 @@ fn user_updates_2020_04_02(conn: &PgConnection) -> Result<(), LemmyError> {
       private_key: Some(keypair.private_key),
       public_key: Some(keypair.public_key),
       last_refreshed_at: Some(naive_now()),
+      discoverable: cuser.discoverable,
+      receive_welcome_messages: cuser.receive_welcome_messages,
+      bot_account: cuser.bot_account,
+      collapse_comments_below_score: cuser.collapse_comments_below_score,
+      collapse_bot_comments: cuser.collapse_bot_comments,
+      remember_comment_sort: cuser.remember_comment_sort,
     };
 
     User_::update(&conn, cuser.id, &form)?;
@@ -227,3 +239,149 @@ fn post_thumbnail_url_updates_2020_07_27(conn: &PgConnection) -> Result<(), Lemm
 
   Ok(())
 }
+
+/// Creates the local user which the content of deleted accounts is attributed to. It needs keys,
+/// to be served as an actor like any other user. It replaces the placeholder named `deleted`, which
+/// was a valid username, and is renamed.
+fn deleted_user_placeholder_2020_09_29(conn: &PgConnection) -> Result<(), LemmyError> {
+  use lemmy_db::schema::user_::dsl::*;
+
+  info!("Running deleted_user_placeholder_2020_09_29");
+
+  // Accounts which were registered with that name have a password, the old placeholder doesn't
+  let renamed_users = diesel::update(
+    user_
+      .filter(name.eq("deleted"))
+      .filter(local.eq(true))
+      .filter(deleted.eq(false))
+      .filter(password_encrypted.eq("")),
+  )
+  .set((
+    name.eq(DELETED_USER_NAME),
+    actor_id.eq(make_apub_endpoint(EndpointType::User, DELETED_USER_NAME).to_string()),
+  ))
+  .execute(conn)?;
+  if renamed_users > 0 {
+    info!("Renamed the old deleted user placeholder.");
+  }
+
+  if User_::read_deleted_placeholder(conn).is_ok() {
+    info!("The deleted user placeholder exists already.");
+    return Ok(());
+  }
+
+  let keypair = generate_actor_keypair()?;
+
+  let form = UserForm {
+    name: DELETED_USER_NAME.into(),
+    preferred_username: None,
+    password_encrypted: "".into(),
+    email: None,
+    matrix_user_id: None,
+    avatar: None,
+    banner: None,
+    admin: false,
+    banned: false,
+    updated: None,
+    show_nsfw: false,
+    theme: "darkly".into(),
+    default_sort_type: SortType::Active as i16,
+    default_listing_type: ListingType::Subscribed as i16,
+    lang: "browser".into(),
+    show_avatars: true,
+    send_notifications_to_email: false,
+    actor_id: Some(make_apub_endpoint(EndpointType::User, DELETED_USER_NAME).to_string()),
+    bio: None,
+    local: true,
+    private_key: Some(keypair.private_key),
+    public_key: Some(keypair.public_key),
+    last_refreshed_at: None,
+    discoverable: false,
+    receive_welcome_messages: false,
+    bot_account: false,
+    collapse_comments_below_score: None,
+    collapse_bot_comments: false,
+    remember_comment_sort: false,
+  };
+
+  User_::create(&conn, &form)?;
+
+  info!("Deleted user placeholder created.");
+
+  Ok(())
+}
+
+/// Creates the keys of the instance actor, which signs the fetches of the instance, for instances
+/// which only serve their objects to signed requests. It replaces the local user `instance.actor`,
+/// which signed them before, and is deleted.
+fn instance_actor_2020_10_28(conn: &PgConnection) -> Result<(), LemmyError> {
+  use lemmy_db::schema::user_::dsl::*;
+
+  info!("Running instance_actor_2020_10_28");
+
+  let deleted_users = diesel::delete(
+    user_
+      .filter(name.eq("instance.actor"))
+      .filter(local.eq(true)),
+  )
+  .execute(conn)?;
+  if deleted_users > 0 {
+    info!("Deleted the old instance.actor user.");
+  }
+
+  if InstanceActor::read(conn).is_ok() {
+    info!("The instance actor exists already.");
+    return Ok(());
+  }
+
+  let keypair = generate_actor_keypair()?;
+
+  let form = InstanceActorForm {
+    private_key: keypair.private_key,
+    public_key: keypair.public_key,
+  };
+
+  InstanceActor::create(&conn, &form)?;
+
+  info!("Instance actor created.");
+
+  Ok(())
+}
+
+/// Renames the automod users of communities from `automod_{community_id}`, which was a valid
+/// username, to a name which can't be registered.
+fn automod_user_names_2020_11_02(conn: &PgConnection) -> Result<(), LemmyError> {
+  use lemmy_db::schema::{community_settings, user_::dsl::*};
+
+  info!("Running automod_user_names_2020_11_02");
+
+  let automods = community_settings::table
+    .select((
+      community_settings::community_id,
+      community_settings::automod_user_id,
+    ))
+    .load::<(i32, Option<i32>)>(conn)?;
+  let mut renamed_users = 0;
+  for (community_id, automod_user_id) in automods {
+    let automod_user_id = match automod_user_id {
+      Some(automod_user_id) => automod_user_id,
+      None => continue,
+    };
+    let new_name = automod_user_name(community_id);
+    renamed_users += diesel::update(
+      user_
+        .find(automod_user_id)
+        .filter(name.eq(format!("automod_{}", community_id)))
+        .filter(local.eq(true))
+        .filter(password_encrypted.eq("")),
+    )
+    .set((
+      name.eq(&new_name),
+      actor_id.eq(make_apub_endpoint(EndpointType::User, &new_name).to_string()),
+    ))
+    .execute(conn)?;
+  }
+  info!("{} automod user rows updated.", renamed_users);
+
+  Ok(())
+}
@@ -0,0 +1,139 @@
+//! Response compression with gzip, brotli and zstd, negotiated with the Accept-Encoding header of
+//! the client. Which responses are compressed is set in the compression section of the config.
+use flate2::{write::GzEncoder, Compression};
+use lemmy_utils::settings::CompressionConfig;
+use std::io::{self, Write};
+use strum_macros::EnumString;
+
+#[derive(EnumString, Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+  #[strum(serialize = "zstd")]
+  Zstd,
+  #[strum(serialize = "br")]
+  Brotli,
+  #[strum(serialize = "gzip")]
+  Gzip,
+}
+
+impl Encoding {
+  /// As in the Accept-Encoding and Content-Encoding headers.
+  pub fn name(self) -> &'static str {
+    match self {
+      Encoding::Zstd => "zstd",
+      Encoding::Brotli => "br",
+      Encoding::Gzip => "gzip",
+    }
+  }
+}
+
+/// The configured encodings, in the order of preference of the server.
+pub fn configured_encodings(config: &CompressionConfig) -> Vec<Encoding> {
+  config
+    .algorithms
+    .split(',')
+    .filter_map(|a| a.trim().parse::<Encoding>().ok())
+    .collect()
+}
+
+/// Whether responses with this content type are compressed at all.
+pub fn is_compressible(config: &CompressionConfig, content_type: &str) -> bool {
+  config
+    .content_types
+    .split(',')
+    .map(|c| c.trim())
+    .any(|c| !c.is_empty() && content_type.starts_with(c))
+}
+
+/// The encoding which the client likes most, out of the ones the server supports. When the client
+/// likes several the same, the preference of the server decides.
+pub fn choose_encoding(accept_encoding: &str, supported: &[Encoding]) -> Option<Encoding> {
+  let mut best: Option<(Encoding, f32)> = None;
+  for encoding in supported {
+    let quality = accept_encoding
+      .split(',')
+      .filter_map(|part| {
+        let mut params = part.split(';');
+        let name = params.next()?.trim();
+        if name != encoding.name() && name != "*" {
+          return None;
+        }
+        let quality = params
+          .filter_map(|p| p.trim().strip_prefix("q="))
+          .filter_map(|q| q.parse::<f32>().ok())
+          .next()
+          .unwrap_or(1.0);
+        // An exact match wins over the wildcard
+        Some((name != "*", quality))
+      })
+      .max_by(|a, b| a.0.cmp(&b.0))
+      .map(|(_, quality)| quality)
+      .unwrap_or(0.0);
+    if quality > 0.0 && best.map_or(true, |(_, q)| quality > q) {
+      best = Some((*encoding, quality));
+    }
+  }
+  best.map(|(encoding, _)| encoding)
+}
+
+pub fn encode(bytes: &[u8], encoding: Encoding) -> Result<Vec<u8>, io::Error> {
+  match encoding {
+    Encoding::Gzip => {
+      let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+      encoder.write_all(bytes)?;
+      encoder.finish()
+    }
+    Encoding::Brotli => {
+      let mut output = Vec::new();
+      {
+        // Quality 5 is about as fast as gzip, and still smaller
+        let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, 5, 22);
+        encoder.write_all(bytes)?;
+      }
+      Ok(output)
+    }
+    Encoding::Zstd => zstd::encode_all(bytes, 3),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::compression::{choose_encoding, encode, Encoding};
+  use flate2::read::GzDecoder;
+  use std::io::Read;
+
+  #[test]
+  fn test_choose_encoding() {
+    let all = [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip];
+    assert_eq!(
+      Some(Encoding::Zstd),
+      choose_encoding("gzip, br, zstd", &all)
+    );
+    assert_eq!(Some(Encoding::Brotli), choose_encoding("gzip, br", &all));
+    assert_eq!(
+      Some(Encoding::Gzip),
+      choose_encoding("br;q=0.5, gzip;q=0.8", &all)
+    );
+    assert_eq!(Some(Encoding::Brotli), choose_encoding("*, zstd;q=0", &all));
+    assert_eq!(None, choose_encoding("deflate", &all));
+    assert_eq!(None, choose_encoding("br", &[Encoding::Gzip]));
+    assert_eq!(None, choose_encoding("", &all));
+  }
+
+  #[test]
+  fn test_encode() {
+    let text = "a response which repeats, ".repeat(100);
+    let gzipped = encode(text.as_bytes(), Encoding::Gzip).unwrap();
+    assert!(gzipped.len() < text.len());
+    let mut decoded = String::new();
+    GzDecoder::new(&gzipped[..])
+      .read_to_string(&mut decoded)
+      .unwrap();
+    assert_eq!(text, decoded);
+
+    let zstd = encode(text.as_bytes(), Encoding::Zstd).unwrap();
+    assert_eq!(text.as_bytes(), &zstd::decode_all(&zstd[..]).unwrap()[..]);
+
+    let brotli = encode(text.as_bytes(), Encoding::Brotli).unwrap();
+    assert!(brotli.len() < text.len());
+  }
+}
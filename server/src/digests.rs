@@ -0,0 +1,206 @@
+//! The digest job, which emails the subscribers of a community the top posts and the number of new
+//! members of the past week, and the users who get their community notifications as a digest the
+//! posts of the past day.
+use crate::LemmyContext;
+use lemmy_api_structs::{blocking, queue_email};
+use lemmy_db::{
+  community::{Community, CommunityFollower, CommunitySettings},
+  community_digest::{CommunityDigest, CommunityDigestSubscription},
+  community_notification::CommunityNotificationPost,
+  naive_now,
+  post_view::{PostQueryBuilder, PostView},
+  user::User_,
+  Crud,
+  ListingType,
+  SortType,
+};
+use lemmy_utils::{
+  email::{digest_unsubscribe_path, render_digest_template, DEFAULT_DIGEST_TEMPLATE},
+  settings::Settings,
+  utils::markdown_to_html,
+  LemmyError,
+};
+use log::error;
+use std::time::Duration;
+
+const DIGEST_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DIGEST_TOP_POSTS: i64 = 5;
+/// How long community notifications wait for their digest email, which then has all the pending
+/// notifications of the user.
+const NOTIFICATION_DIGEST_HOURS: i64 = 24;
+
+pub async fn run_digest_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(DIGEST_INTERVAL);
+  loop {
+    interval.tick().await;
+    if Settings::get().email.is_none() || Settings::get().maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = send_due_digests(&context).await {
+      error!("Couldn't send the community digests: {}", e);
+    }
+    if let Err(e) = send_notification_digests(&context).await {
+      error!("Couldn't send the notification digests: {}", e);
+    }
+  }
+}
+
+async fn send_due_digests(context: &LemmyContext) -> Result<(), LemmyError> {
+  let week_ago = naive_now() - chrono::Duration::days(7);
+  let due = blocking(context.pool(), move |conn| {
+    CommunityDigest::list_due(conn, week_ago)
+  })
+  .await??;
+  for community_id in due {
+    // Marked first, so that a digest which fails isn't retried every hour
+    blocking(context.pool(), move |conn| {
+      CommunityDigest::mark_sent(conn, community_id)
+    })
+    .await??;
+    if let Err(e) = send_digest(community_id, week_ago, context).await {
+      error!(
+        "Couldn't send the digest of community {}: {}",
+        community_id, e
+      );
+    }
+  }
+  Ok(())
+}
+
+async fn send_digest(
+  community_id: i32,
+  since: chrono::NaiveDateTime,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let (community, settings, new_members) =
+    blocking(context.pool(), move |conn| -> Result<_, LemmyError> {
+      Ok((
+        Community::read(conn, community_id)?,
+        CommunitySettings::read_for_community(conn, community_id)?,
+        CommunityFollower::count_since(conn, community_id, since)?,
+      ))
+    })
+    .await??;
+  let template = settings
+    .digest_template
+    .unwrap_or_else(|| DEFAULT_DIGEST_TEMPLATE.to_owned());
+  let subject = format!(
+    "{} - This week in {}",
+    Settings::get().hostname,
+    community.title
+  );
+
+  let subscriptions = blocking(context.pool(), move |conn| {
+    CommunityDigestSubscription::list_for_community(conn, community_id)
+  })
+  .await??;
+  for subscription in subscriptions {
+    let user_id = subscription.user_id;
+    let user = blocking(context.pool(), move |conn| User_::read(conn, user_id)).await??;
+    let (email, unsubscribe) = match (user.email, digest_unsubscribe_path(user.id, community_id)) {
+      (Some(email), Some(unsubscribe)) if !user.banned => (email, unsubscribe),
+      _ => continue,
+    };
+
+    // Listed the way the user would see them on the site, eg without nsfw posts if they don't
+    // want them
+    let show_nsfw = user.show_nsfw;
+    let posts = blocking(context.pool(), move |conn| {
+      PostQueryBuilder::create(conn)
+        .listing_type(ListingType::Community)
+        .sort(&SortType::TopWeek)
+        .for_community_id(community_id)
+        .my_user_id(user_id)
+        .show_nsfw(show_nsfw)
+        .limit(DIGEST_TOP_POSTS)
+        .list()
+    })
+    .await??;
+    let hostname = format!("https://{}", Settings::get().hostname);
+    let top_posts = posts
+      .iter()
+      .map(|p| format!("- [{}]({}/post/{})", p.name, hostname, p.id))
+      .collect::<Vec<String>>()
+      .join("\n");
+    let markdown = render_digest_template(&template, &community.title, &top_posts, new_members);
+
+    let html = format!(
+      "{}<br><a href=https://{}{}>Unsubscribe from this digest</a>",
+      markdown_to_html(&markdown),
+      Settings::get().hostname,
+      unsubscribe
+    );
+    let subject = subject.to_owned();
+    let user_name = user.name;
+    blocking(context.pool(), move |conn| {
+      queue_email(conn, &subject, &email, &user_name, &html, None)
+    })
+    .await?;
+  }
+  Ok(())
+}
+
+async fn send_notification_digests(context: &LemmyContext) -> Result<(), LemmyError> {
+  let pending_before = naive_now() - chrono::Duration::hours(NOTIFICATION_DIGEST_HOURS);
+  let user_ids = blocking(context.pool(), move |conn| {
+    CommunityNotificationPost::users_with_due_digest(conn, pending_before)
+  })
+  .await??;
+  for user_id in user_ids {
+    if let Err(e) = send_notification_digest(user_id, context).await {
+      error!(
+        "Couldn't send the notification digest of user {}: {}",
+        user_id, e
+      );
+    }
+  }
+  Ok(())
+}
+
+async fn send_notification_digest(user_id: i32, context: &LemmyContext) -> Result<(), LemmyError> {
+  // Taken first, so that a digest which fails isn't retried every hour
+  let (user, posts) = blocking(context.pool(), move |conn| -> Result<_, LemmyError> {
+    let notifications = CommunityNotificationPost::take_pending_emails(conn, user_id)?;
+    let posts = notifications
+      .iter()
+      .filter_map(|n| PostView::read(conn, n.post_id, Some(user_id)).ok())
+      .collect::<Vec<PostView>>();
+    Ok((User_::read(conn, user_id)?, posts))
+  })
+  .await??;
+  let email = match user.email {
+    Some(email) if !user.banned => email,
+    _ => return Ok(()),
+  };
+
+  // Posts which were deleted or removed in the meantime are left out
+  let posts = posts
+    .into_iter()
+    .filter(|p| !p.deleted && !p.removed)
+    .collect::<Vec<PostView>>();
+  if posts.is_empty() {
+    return Ok(());
+  }
+  let hostname = format!("https://{}", Settings::get().hostname);
+  let post_list = posts
+    .iter()
+    .map(|p| {
+      format!(
+        "<li><a href={}/post/{}>{}</a> in {}</li>",
+        hostname, p.id, p.name, p.community_name
+      )
+    })
+    .collect::<String>();
+  let subject = format!(
+    "{} - {} new posts in your communities",
+    Settings::get().hostname,
+    posts.len()
+  );
+  let html = format!("<h1>Community Notifications</h1><br><ul>{}</ul>", post_list);
+  let user_name = user.name;
+  blocking(context.pool(), move |conn| {
+    queue_email(conn, &subject, &email, &user_name, &html, None)
+  })
+  .await?;
+  Ok(())
+}
@@ -0,0 +1,75 @@
+//! The email outbox job, which sends the queued emails and retries those which failed.
+use crate::DbPool;
+use actix_web::{error::BlockingError, web};
+use anyhow::anyhow;
+use lemmy_api_structs::blocking;
+use lemmy_db::{email_outbox::EmailOutbox, naive_now};
+use lemmy_utils::{email::send_email, settings::Settings, LemmyError};
+use log::{error, warn};
+use std::time::Duration;
+
+const EMAIL_OUTBOX_INTERVAL: Duration = Duration::from_secs(30);
+const EMAILS_PER_RUN: i64 = 100;
+/// How long failed emails are kept for the admins to see.
+const FAILED_EMAIL_DAYS: i64 = 30;
+
+pub async fn run_email_outbox_job(pool: DbPool) {
+  let mut interval = actix_web::rt::time::interval(EMAIL_OUTBOX_INTERVAL);
+  loop {
+    interval.tick().await;
+    // The emails stay in the outbox until they can be sent
+    if Settings::get().email.is_none() || Settings::get().maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = send_due_emails(&pool).await {
+      error!("Couldn't send the queued emails: {}", e);
+    }
+  }
+}
+
+async fn send_due_emails(pool: &DbPool) -> Result<(), LemmyError> {
+  let due = blocking(pool, move |conn| {
+    EmailOutbox::list_due(conn, EMAILS_PER_RUN)
+  })
+  .await??;
+  for email in due {
+    // Sending blocks, so it's done on the thread pool, without holding on to a database connection
+    // while waiting for the SMTP server
+    let EmailOutbox {
+      id,
+      to_email,
+      to_name,
+      subject,
+      html,
+      reply_to,
+      ..
+    } = email;
+    let recipient = to_email.to_owned();
+    let sent =
+      web::block(move || send_email(&subject, &to_email, &to_name, &html, reply_to.as_deref()))
+        .await;
+    let error = match sent {
+      Ok(()) => None,
+      Err(BlockingError::Error(e)) => Some(e),
+      Err(BlockingError::Canceled) => {
+        return Err(anyhow!("Sending email {} was canceled", id).into())
+      }
+    };
+
+    blocking(pool, move |conn| match error {
+      None => EmailOutbox::sent(conn, id).map(|_| ()),
+      Some(e) => {
+        warn!("Couldn't send email {} to {}: {}", id, recipient, e);
+        EmailOutbox::failed(conn, id, &e.to_string(), e.permanent).map(|_| ())
+      }
+    })
+    .await??;
+  }
+
+  let failed_before = naive_now() - chrono::Duration::days(FAILED_EMAIL_DAYS);
+  blocking(pool, move |conn| {
+    EmailOutbox::prune_failed(conn, failed_before)
+  })
+  .await??;
+  Ok(())
+}
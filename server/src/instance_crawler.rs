@@ -0,0 +1,171 @@
+//! The instance crawler, which keeps the list of instances we federate with, and finds the
+//! software they run from their nodeinfo.
+use crate::{
+  request::{get_checked, read_body, RecvError},
+  routes::nodeinfo::NodeInfoSoftware,
+  LemmyContext,
+};
+use anyhow::anyhow;
+use lemmy_api_structs::blocking;
+use lemmy_db::{instance::Instance, naive_now};
+use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings, LemmyError};
+use log::{debug, error};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use url::Url;
+
+const CRAWL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// At most this many instances are crawled every hour.
+const CRAWL_BATCH_SIZE: i64 = 50;
+
+/// How often the software of an instance is looked up again.
+const RECRAWL_DAYS: i64 = 7;
+
+/// The nodeinfo schemas we understand, all 2.x versions have the software name and version.
+const NODEINFO_SCHEMA_PREFIX: &str = "http://nodeinfo.diaspora.software/ns/schema/2.";
+
+#[derive(Deserialize)]
+struct NodeInfoSoftwareOnly {
+  software: NodeInfoSoftware,
+}
+
+pub async fn run_instance_crawl_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(CRAWL_INTERVAL);
+  loop {
+    interval.tick().await;
+    let settings = Settings::get();
+    if !settings.federation.enabled || settings.maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = crawl_instances(&context).await {
+      error!("Couldn't crawl the linked instances: {}", e);
+    }
+  }
+}
+
+async fn crawl_instances(context: &LemmyContext) -> Result<(), LemmyError> {
+  let crawled_before = naive_now() - chrono::Duration::days(RECRAWL_DAYS);
+  let instances = blocking(context.pool(), move |conn| {
+    Instance::add_linked(conn)?;
+    Instance::list_uncrawled(conn, crawled_before, CRAWL_BATCH_SIZE)
+  })
+  .await??;
+
+  let settings = Settings::get();
+  for instance in instances {
+    let instance_id = instance.id;
+    // Instances we don't federate with don't get any requests from us
+    let software = if !settings.is_instance_allowed(&instance.domain) {
+      None
+    } else {
+      match fetch_software(context.client(), &instance.domain).await {
+        Ok(software) => Some(software),
+        Err(e) => {
+          debug!("Couldn't get the nodeinfo of {}: {}", instance.domain, e);
+          None
+        }
+      }
+    };
+    let updated = blocking(context.pool(), move |conn| match software {
+      Some(software) => {
+        Instance::update_software(conn, instance_id, &software.name, &software.version)
+      }
+      None => Instance::mark_crawled(conn, instance_id),
+    })
+    .await?;
+    // One instance with odd software mustn't keep the others from being crawled
+    if let Err(e) = updated {
+      error!("Couldn't update the software of {}: {}", instance.domain, e);
+      blocking(context.pool(), move |conn| {
+        Instance::mark_crawled(conn, instance_id)
+      })
+      .await??;
+    }
+  }
+
+  Ok(())
+}
+
+/// The software an instance reports in the nodeinfo linked from its /.well-known/nodeinfo.
+async fn fetch_software(client: &Client, domain: &str) -> Result<NodeInfoSoftware, LemmyError> {
+  let well_known_url = Url::parse(&format!(
+    "{}://{}/.well-known/nodeinfo",
+    get_apub_protocol_string(),
+    domain
+  ))?;
+  let well_known = fetch_json::<Value>(client, &well_known_url).await?;
+  let nodeinfo_url = match nodeinfo_href(&well_known) {
+    Some(href) => Url::parse(&href)?,
+    None => return Err(anyhow!("No nodeinfo 2.x link for {}", domain).into()),
+  };
+  let nodeinfo = fetch_json::<NodeInfoSoftwareOnly>(client, &nodeinfo_url).await?;
+  Ok(nodeinfo.software)
+}
+
+async fn fetch_json<T>(client: &Client, url: &Url) -> Result<T, LemmyError>
+where
+  T: for<'de> Deserialize<'de>,
+{
+  let response = get_checked(client, url, |r| r.header("Accept", "application/json")).await?;
+  let body = read_body(response).await?;
+  serde_json::from_slice(&body).map_err(|e| RecvError(e.to_string()).into())
+}
+
+/// The link to the newest nodeinfo 2.x schema. `links` is a list, but older Lemmy versions send a
+/// single link.
+fn nodeinfo_href(well_known: &Value) -> Option<String> {
+  let links = match &well_known["links"] {
+    Value::Array(links) => links.iter().collect::<Vec<&Value>>(),
+    link => vec![link],
+  };
+  links
+    .into_iter()
+    .filter_map(|link| Some((link["rel"].as_str()?, link["href"].as_str()?)))
+    .filter(|(rel, _)| rel.starts_with(NODEINFO_SCHEMA_PREFIX))
+    .max_by_key(|(rel, _)| rel.to_owned())
+    .map(|(_, href)| href.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::instance_crawler::nodeinfo_href;
+  use serde_json::json;
+
+  #[test]
+  fn test_nodeinfo_href() {
+    let listed = json!({
+      "links": [
+        {
+          "rel": "http://nodeinfo.diaspora.software/ns/schema/2.0",
+          "href": "https://a.example/nodeinfo/2.0.json"
+        },
+        {
+          "rel": "http://nodeinfo.diaspora.software/ns/schema/2.1",
+          "href": "https://a.example/nodeinfo/2.1.json"
+        },
+        {
+          "rel": "http://nodeinfo.diaspora.software/ns/schema/1.0",
+          "href": "https://a.example/nodeinfo/1.0.json"
+        }
+      ]
+    });
+    let single = json!({
+      "links": {
+        "rel": "http://nodeinfo.diaspora.software/ns/schema/2.0",
+        "href": "https://b.example/nodeinfo/2.0.json"
+      }
+    });
+    assert_eq!(
+      Some("https://a.example/nodeinfo/2.1.json".to_string()),
+      nodeinfo_href(&listed)
+    );
+    assert_eq!(
+      Some("https://b.example/nodeinfo/2.0.json".to_string()),
+      nodeinfo_href(&single)
+    );
+    assert_eq!(None, nodeinfo_href(&json!({})));
+  }
+}
@@ -6,7 +6,6 @@ extern crate lazy_static;
 extern crate actix;
 extern crate actix_web;
 extern crate base64;
-extern crate bcrypt;
 extern crate captcha;
 extern crate chrono;
 extern crate diesel;
@@ -21,16 +20,34 @@ extern crate serde_json;
 extern crate sha2;
 extern crate strum;
 
+pub mod actor_refresh;
+pub mod announcements;
+pub mod address_guard;
 pub mod api;
 pub mod apub;
+pub mod canonical_url;
 pub mod code_migrations;
+pub mod compression;
+pub mod digests;
+pub mod email_outbox;
+pub mod instance_crawler;
+pub mod plugins;
+pub mod post_views;
+pub mod push;
 pub mod request;
+pub mod response_cache;
 pub mod routes;
+pub mod scheduled_posts;
+pub mod spam;
+pub mod stats;
 pub mod version;
+pub mod wasm_plugins;
 pub mod websocket;
 
 use crate::{
-  request::{retry, RecvError},
+  apub::fetcher::FetchBudget,
+  request::{check_outbound_url, get_checked, link_preview_timeout, RecvError, RetryPolicy},
+  response_cache::ResponseCache,
   websocket::chat_server::ChatServer,
 };
 use actix::Addr;
@@ -42,13 +59,16 @@ use log::error;
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use reqwest::Client;
 use serde::Deserialize;
-use std::process::Command;
+use std::{process::Command, sync::Arc};
+use url::Url;
 
 pub struct LemmyContext {
   pub pool: DbPool,
   pub chat_server: Addr<ChatServer>,
   pub client: Client,
   pub activity_queue: QueueHandle,
+  pub response_cache: ResponseCache,
+  pub fetch_budget: Arc<FetchBudget>,
 }
 
 impl LemmyContext {
@@ -57,12 +77,24 @@ impl LemmyContext {
     chat_server: Addr<ChatServer>,
     client: Client,
     activity_queue: QueueHandle,
+    response_cache: ResponseCache,
   ) -> LemmyContext {
     LemmyContext {
       pool,
       chat_server,
       client,
       activity_queue,
+      response_cache,
+      fetch_budget: Arc::new(FetchBudget::default()),
+    }
+  }
+
+  /// A copy of the context with its own, limited budget for fetching remote objects, for
+  /// handling one incoming activity or search.
+  pub fn with_fetch_budget(&self) -> LemmyContext {
+    LemmyContext {
+      fetch_budget: Arc::new(FetchBudget::limited()),
+      ..self.clone()
     }
   }
   pub fn pool(&self) -> &DbPool {
@@ -77,6 +109,12 @@ impl LemmyContext {
   pub fn activity_queue(&self) -> &QueueHandle {
     &self.activity_queue
   }
+  pub fn response_cache(&self) -> &ResponseCache {
+    &self.response_cache
+  }
+  pub fn fetch_budget(&self) -> &FetchBudget {
+    &self.fetch_budget
+  }
 }
 
 impl Clone for LemmyContext {
@@ -86,6 +124,8 @@ impl Clone for LemmyContext {
       chat_server: self.chat_server.clone(),
       client: self.client.clone(),
       activity_queue: self.activity_queue.clone(),
+      response_cache: self.response_cache.clone(),
+      fetch_budget: self.fetch_budget.clone(),
     }
   }
 }
@@ -99,9 +139,18 @@ pub struct IframelyResponse {
 }
 
 pub async fn fetch_iframely(client: &Client, url: &str) -> Result<IframelyResponse, LemmyError> {
+  // iframely fetches the url for us, so it has to pass the same checks as our own requests
+  check_outbound_url(&Url::parse(url)?).await?;
   let fetch_url = format!("http://iframely/oembed?url={}", url);
 
-  let response = retry(|| client.get(&fetch_url).send()).await?;
+  let response = RetryPolicy::INTERACTIVE
+    .run(&fetch_url, || {
+      client
+        .get(&fetch_url)
+        .timeout(link_preview_timeout())
+        .send()
+    })
+    .await?;
 
   let res: IframelyResponse = response
     .json()
@@ -123,6 +172,7 @@ pub struct PictrsFile {
 }
 
 pub async fn fetch_pictrs(client: &Client, image_url: &str) -> Result<PictrsResponse, LemmyError> {
+  // This also checks the url, as pictrs downloads it for us
   is_image_content_type(client, image_url).await?;
 
   let fetch_url = format!(
@@ -130,7 +180,14 @@ pub async fn fetch_pictrs(client: &Client, image_url: &str) -> Result<PictrsResp
     utf8_percent_encode(image_url, NON_ALPHANUMERIC) // TODO this might not be needed
   );
 
-  let response = retry(|| client.get(&fetch_url).send()).await?;
+  let response = RetryPolicy::INTERACTIVE
+    .run(&fetch_url, || {
+      client
+        .get(&fetch_url)
+        .timeout(link_preview_timeout())
+        .send()
+    })
+    .await?;
 
   let response: PictrsResponse = response
     .json()
@@ -208,7 +265,10 @@ async fn fetch_iframely_and_pictrs_data(
 }
 
 pub async fn is_image_content_type(client: &Client, test: &str) -> Result<(), LemmyError> {
-  let response = retry(|| client.get(test).send()).await?;
+  let response = get_checked(client, &Url::parse(test)?, |r| {
+    r.timeout(link_preview_timeout())
+  })
+  .await?;
 
   if response
     .headers()
@@ -3,11 +3,12 @@ extern crate diesel_migrations;
 
 use actix::prelude::*;
 use actix_web::{
-  body::Body,
+  body::{Body, ResponseBody},
   dev::{Service, ServiceRequest, ServiceResponse},
   http::{
-    header::{CACHE_CONTROL, CONTENT_TYPE},
+    header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, VARY},
     HeaderValue,
+    StatusCode,
   },
   *,
 };
@@ -15,19 +16,42 @@ use diesel::{
   r2d2::{ConnectionManager, Pool},
   PgConnection,
 };
+use futures::StreamExt;
 use lazy_static::lazy_static;
 use lemmy_api_structs::blocking;
 use lemmy_db::get_database_url_from_env;
 use lemmy_rate_limit::{rate_limiter::RateLimiter, RateLimit};
 use lemmy_server::{
-  apub::activity_queue::create_activity_queue,
+  actor_refresh::run_actor_refresh_job,
+  announcements::run_announcement_job,
+  apub::{
+    activity_queue::{create_activity_queue, run_delivery_job},
+    fetcher::run_follower_sync_job,
+    inbox::received::run_received_activity_cleanup_job,
+  },
   code_migrations::run_advanced_migrations,
-  routes::*,
+  compression::{choose_encoding, configured_encodings, encode, is_compressible},
+  digests::run_digest_job,
+  email_outbox::run_email_outbox_job,
+  instance_crawler::run_instance_crawl_job,
+  plugins::reload_hooks,
+  post_views::run_post_view_flush_job,
+  request::build_client,
+  response_cache::ResponseCache,
+  routes::{session::use_session_cookie, sse::EVENT_STREAM_CONTENT_TYPE, *},
+  scheduled_posts::run_scheduled_posts_job,
+  stats::run_stats_job,
+  wasm_plugins::reload_wasm_plugins,
   websocket::chat_server::ChatServer,
   LemmyContext,
 };
-use lemmy_utils::{settings::Settings, LemmyError, CACHE_CONTROL_REGEX};
-use reqwest::Client;
+use lemmy_utils::{
+  apub::rewrite_links_for_onion,
+  settings::{IpPolicy, Settings},
+  LemmyError,
+  CACHE_CONTROL_REGEX,
+  CLIENT_CONTENT_TYPE_REGEX,
+};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -63,6 +87,9 @@ async fn main() -> Result<(), LemmyError> {
   })
   .await??;
 
+  reload_hooks(&pool).await?;
+  reload_wasm_plugins(&pool).await?;
+
   // Set up the rate limiter
   let rate_limiter = RateLimit {
     rate_limiter: Arc::new(Mutex::new(RateLimiter::default())),
@@ -73,37 +100,110 @@ async fn main() -> Result<(), LemmyError> {
     settings.bind, settings.port
   );
 
-  let activity_queue = create_activity_queue();
+  let client = build_client()?;
+  let activity_queue = create_activity_queue(client.clone(), pool.clone());
+  actix_web::rt::spawn(run_stats_job(pool.clone(), activity_queue.clone()));
+  actix_web::rt::spawn(run_email_outbox_job(pool.clone()));
+  actix_web::rt::spawn(run_post_view_flush_job(pool.clone()));
+  let response_cache = ResponseCache::default();
   let chat_server = ChatServer::startup(
     pool.clone(),
     rate_limiter.clone(),
-    Client::default(),
+    client.clone(),
     activity_queue.clone(),
+    response_cache.clone(),
   )
   .start();
+  actix_web::rt::spawn(run_actor_refresh_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_follower_sync_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_received_activity_cleanup_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_delivery_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_digest_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_instance_crawl_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_scheduled_posts_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
+  actix_web::rt::spawn(run_announcement_job(LemmyContext::create(
+    pool.clone(),
+    chat_server.to_owned(),
+    client.clone(),
+    activity_queue.to_owned(),
+    response_cache.to_owned(),
+  )));
 
   // Create Http server with websocket support
   HttpServer::new(move || {
     let context = LemmyContext::create(
       pool.clone(),
       chat_server.to_owned(),
-      Client::default(),
+      client.clone(),
       activity_queue.to_owned(),
+      response_cache.to_owned(),
     );
     let settings = Settings::get();
     let rate_limiter = rate_limiter.clone();
     App::new()
+      .wrap_fn(use_session_cookie)
       .wrap_fn(add_cache_headers)
-      .wrap(middleware::Logger::default())
+      .wrap_fn(rewrite_onion_links)
+      .wrap_fn(compress_response)
+      .wrap(request_logger(&settings))
       .data(context)
       // The routes
       .configure(|cfg| api::config(cfg, &rate_limiter))
       .configure(federation::config)
+      .configure(branding::config)
+      .configure(|cfg| csp_report::config(cfg, &rate_limiter))
+      .configure(digest::config)
       .configure(feeds::config)
       .configure(|cfg| images::config(cfg, &rate_limiter))
+      .configure(inbound_email::config)
       .configure(index::config)
       .configure(nodeinfo::config)
+      .configure(oembed::config)
+      .configure(permalinks::config)
       .configure(webfinger::config)
+      .configure(widget::config)
       // static files
       .service(actix_files::Files::new(
         "/static",
@@ -121,6 +221,15 @@ async fn main() -> Result<(), LemmyError> {
   Ok(())
 }
 
+/// The default logger writes the client address, which only happens if the config keeps it.
+fn request_logger(settings: &Settings) -> middleware::Logger {
+  if settings.ip_addresses.policy == IpPolicy::Full {
+    middleware::Logger::default()
+  } else {
+    middleware::Logger::new("\"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T")
+  }
+}
+
 fn add_cache_headers<S>(
   req: ServiceRequest,
   srv: &mut S,
@@ -132,7 +241,9 @@ where
   async move {
     let mut res = fut.await?;
     if let Some(content_type) = res.headers().get(CONTENT_TYPE) {
-      if CACHE_CONTROL_REGEX.is_match(content_type.to_str().unwrap()) {
+      let content_type = content_type.to_str().unwrap();
+      // Events are never cached
+      if CACHE_CONTROL_REGEX.is_match(content_type) && content_type != EVENT_STREAM_CONTENT_TYPE {
         let header_val = HeaderValue::from_static(&CACHE_CONTROL_VALUE);
         res.headers_mut().insert(CACHE_CONTROL, header_val);
       }
@@ -140,3 +251,110 @@ where
     Ok(res)
   }
 }
+
+/// Clients of the onion service get links to it. Federation endpoints are left alone, so that other
+/// instances only ever see the canonical hostname.
+fn rewrite_onion_links<S>(
+  req: ServiceRequest,
+  srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+  S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = Error>,
+{
+  let path = req.path();
+  let onion_hostname = if path.starts_with("/.well-known") || path.starts_with("/nodeinfo") {
+    None
+  } else {
+    Settings::get().get_onion_host(req.connection_info().host())
+  };
+  let fut = srv.call(req);
+  async move {
+    let mut res = fut.await?;
+    let onion_hostname = match onion_hostname {
+      Some(o) => o,
+      None => return Ok(res),
+    };
+    let is_client_content = res
+      .headers()
+      .get(CONTENT_TYPE)
+      .and_then(|c| c.to_str().ok())
+      .map(|c| CLIENT_CONTENT_TYPE_REGEX.is_match(c))
+      .unwrap_or(false);
+    if !is_client_content {
+      return Ok(res);
+    }
+
+    let mut body = res.take_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+      bytes.extend_from_slice(&chunk?);
+    }
+    let rewritten = rewrite_links_for_onion(&String::from_utf8_lossy(&bytes), &onion_hostname);
+    Ok(res.map_body(|_, _| ResponseBody::Body(Body::from(rewritten))))
+  }
+}
+
+/// Compresses responses with the encoding negotiated by compression::choose_encoding. This wraps
+/// the onion rewriting, so that it gets the final body.
+fn compress_response<S>(
+  req: ServiceRequest,
+  srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+  S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = Error>,
+{
+  let config = Settings::get().compression;
+  let encoding = req
+    .headers()
+    .get(ACCEPT_ENCODING)
+    .and_then(|a| a.to_str().ok())
+    .and_then(|a| choose_encoding(a, &configured_encodings(&config)));
+  let fut = srv.call(req);
+  async move {
+    let mut res = fut.await?;
+    let skipped_status = [
+      StatusCode::SWITCHING_PROTOCOLS,
+      StatusCode::NO_CONTENT,
+      StatusCode::PARTIAL_CONTENT,
+      StatusCode::NOT_MODIFIED,
+    ];
+    if !config.enabled
+      || skipped_status.contains(&res.status())
+      || res.headers().contains_key(CONTENT_ENCODING)
+    {
+      return Ok(res);
+    }
+    // Event streams don't end, so they can't be buffered
+    let is_compressible = res
+      .headers()
+      .get(CONTENT_TYPE)
+      .and_then(|c| c.to_str().ok())
+      .map(|c| is_compressible(&config, c) && c != EVENT_STREAM_CONTENT_TYPE)
+      .unwrap_or(false);
+    if !is_compressible {
+      return Ok(res);
+    }
+    // Caches must not give a compressed response to clients which can't read it
+    res
+      .headers_mut()
+      .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    let encoding = match encoding {
+      Some(e) => e,
+      None => return Ok(res),
+    };
+
+    let mut body = res.take_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+      bytes.extend_from_slice(&chunk?);
+    }
+    if bytes.len() < config.min_size {
+      return Ok(res.map_body(|_, _| ResponseBody::Body(Body::from(bytes))));
+    }
+    let compressed = web::block(move || encode(&bytes, encoding)).await?;
+    res
+      .headers_mut()
+      .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    Ok(res.map_body(|_, _| ResponseBody::Body(Body::from(compressed))))
+  }
+}
@@ -0,0 +1,223 @@
+//! Plugins are external services which admins register for hook points. At a hook point, every
+//! enabled plugin for it gets a POST with the payload, one after another, and answers with one of
+//!
+//! - `{"action": "allow"}` to go on with the payload as it is,
+//! - `{"action": "modify", "payload": ...}` to go on with a changed payload,
+//! - `{"action": "reject", "reason": ...}` to stop it.
+//!
+//! The hooks are kept in memory, so that checking them doesn't need the database.
+use crate::DbPool;
+use anyhow::anyhow;
+use lemmy_api_structs::blocking;
+use lemmy_db::plugin_hook::{HookFailurePolicy, HookPoint, PluginHook};
+use lemmy_utils::{APIError, LemmyError};
+use log::{error, info};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{str::FromStr, sync::RwLock, time::Duration};
+use url::Url;
+
+lazy_static! {
+  static ref HOOKS: RwLock<Vec<PluginHook>> = RwLock::new(Vec::new());
+}
+
+/// Sent with every call, so that plugins which handle several hook points can tell them apart.
+pub const HOOK_POINT_HEADER: &str = "X-Lemmy-Hook";
+
+/// Hex encoded HMAC-SHA256 of the body, with the secret of the plugin.
+pub const SIGNATURE_HEADER: &str = "X-Lemmy-Signature";
+
+#[derive(Serialize)]
+struct HookCall<'a, T> {
+  hook: String,
+  payload: &'a T,
+}
+
+/// Also what wasm plugins answer with.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub(crate) enum HookAnswer<T> {
+  Allow,
+  Modify { payload: T },
+  Reject { reason: Option<String> },
+}
+
+pub enum HookResult<T> {
+  Allowed(T),
+  Rejected,
+}
+
+/// The payload of PrePostCreate. Plugins can change everything except the ids.
+#[derive(Serialize, Deserialize)]
+pub struct PostHookPayload {
+  pub name: String,
+  pub url: Option<String>,
+  pub body: Option<String>,
+  pub nsfw: bool,
+  pub community_id: i32,
+  pub creator_id: i32,
+}
+
+/// The payload of PreRegister.
+#[derive(Serialize, Deserialize)]
+pub struct RegisterHookPayload {
+  pub username: String,
+  pub email: Option<String>,
+}
+
+/// The payload of PreFederationSend. Plugins can change the activity, and leave out inboxes.
+#[derive(Serialize, Deserialize)]
+pub struct FederationHookPayload {
+  pub activity: serde_json::Value,
+  pub inboxes: Vec<String>,
+}
+
+/// Hooks are called without the address guard, see `request::build_client`.
+pub(crate) fn is_hook_host(host: &str) -> bool {
+  HOOKS
+    .read()
+    .unwrap()
+    .iter()
+    .any(|hook| Url::parse(&hook.url).ok().as_ref().and_then(Url::host_str) == Some(host))
+}
+
+/// Reads the hooks again, after startup and whenever an admin changes them.
+pub async fn reload_hooks(pool: &DbPool) -> Result<(), LemmyError> {
+  let hooks = blocking(pool, move |conn| PluginHook::list_enabled(conn)).await??;
+  info!("Loaded {} plugin hooks", hooks.len());
+  *HOOKS.write().unwrap() = hooks;
+  Ok(())
+}
+
+/// Calls the plugins for the hook point. Fails if a plugin didn't answer properly, and its
+/// failure policy is Reject.
+pub async fn run_hooks<T>(
+  client: &Client,
+  hook_point: HookPoint,
+  payload: T,
+) -> Result<HookResult<T>, LemmyError>
+where
+  T: Serialize + DeserializeOwned,
+{
+  let hook_point_name = hook_point.to_string();
+  let hooks = HOOKS
+    .read()
+    .unwrap()
+    .iter()
+    .filter(|h| h.hook_point == hook_point_name)
+    .cloned()
+    .collect::<Vec<PluginHook>>();
+
+  let mut payload = payload;
+  for hook in hooks {
+    match call_hook(client, &hook, &hook_point_name, &payload).await {
+      Ok(HookAnswer::Allow) => (),
+      Ok(HookAnswer::Modify { payload: p }) => payload = p,
+      Ok(HookAnswer::Reject { reason }) => {
+        info!(
+          "Plugin {} rejected {}: {}",
+          hook.name,
+          hook_point_name,
+          reason.unwrap_or_default()
+        );
+        return Ok(HookResult::Rejected);
+      }
+      Err(e) => {
+        error!("Plugin {} failed at {}: {}", hook.name, hook_point_name, e);
+        let policy =
+          HookFailurePolicy::from_str(&hook.failure_policy).unwrap_or(HookFailurePolicy::Allow);
+        if policy == HookFailurePolicy::Reject {
+          return Err(anyhow!("Plugin {} failed", hook.name).into());
+        }
+      }
+    }
+  }
+  Ok(HookResult::Allowed(payload))
+}
+
+/// Like `run_hooks`, with the errors which api users get.
+pub async fn run_api_hooks<T>(
+  client: &Client,
+  hook_point: HookPoint,
+  payload: T,
+) -> Result<T, LemmyError>
+where
+  T: Serialize + DeserializeOwned,
+{
+  match run_hooks(client, hook_point, payload).await {
+    Ok(HookResult::Allowed(payload)) => Ok(payload),
+    Ok(HookResult::Rejected) => Err(APIError::err("rejected_by_plugin").into()),
+    Err(_e) => Err(APIError::err("plugin_unavailable").into()),
+  }
+}
+
+async fn call_hook<T>(
+  client: &Client,
+  hook: &PluginHook,
+  hook_point_name: &str,
+  payload: &T,
+) -> Result<HookAnswer<T>, LemmyError>
+where
+  T: Serialize + DeserializeOwned,
+{
+  let body = serde_json::to_string(&HookCall {
+    hook: hook_point_name.to_owned(),
+    payload,
+  })?;
+  let mut request = client
+    .post(&hook.url)
+    .timeout(Duration::from_millis(hook.timeout_ms as u64))
+    .header("Content-Type", "application/json")
+    .header(HOOK_POINT_HEADER, hook_point_name);
+  if let Some(secret) = &hook.secret {
+    request = request.header(SIGNATURE_HEADER, sign_body(secret, &body)?);
+  }
+
+  let response = request.body(body).send().await?.error_for_status()?;
+  Ok(response.json::<HookAnswer<T>>().await?)
+}
+
+fn sign_body(secret: &str, body: &str) -> Result<String, LemmyError> {
+  let key = PKey::hmac(secret.as_bytes())?;
+  let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+  signer.update(body.as_bytes())?;
+  Ok(
+    signer
+      .sign_to_vec()?
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::plugins::{sign_body, HookAnswer, PostHookPayload};
+
+  #[test]
+  fn test_hook_answers() {
+    let allow = serde_json::from_str::<HookAnswer<PostHookPayload>>(r#"{"action":"allow"}"#);
+    assert!(matches!(allow, Ok(HookAnswer::Allow)));
+
+    let modify = serde_json::from_str::<HookAnswer<PostHookPayload>>(
+      r#"{"action":"modify","payload":{"name":"Changed","url":null,"body":null,"nsfw":true,"community_id":1,"creator_id":2}}"#,
+    );
+    assert!(matches!(modify, Ok(HookAnswer::Modify { payload }) if payload.name == "Changed"));
+
+    let reject = serde_json::from_str::<HookAnswer<PostHookPayload>>(r#"{"action":"reject"}"#);
+    assert!(matches!(reject, Ok(HookAnswer::Reject { reason: None })));
+
+    let unknown = serde_json::from_str::<HookAnswer<PostHookPayload>>(r#"{"action":"maybe"}"#);
+    assert!(unknown.is_err());
+  }
+
+  #[test]
+  fn test_sign_body() {
+    // From RFC 4231, test case 2
+    assert_eq!(
+      "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+      sign_body("Jefe", "what do ya want for nothing?").unwrap()
+    );
+  }
+}
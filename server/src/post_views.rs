@@ -0,0 +1,179 @@
+//! Views of posts, and of comments through their context, are counted in memory, and the flush job
+//! adds them to the database every few minutes, so that reading doesn't write to the database.
+//! Besides the views, each post and comment has a HyperLogLog sketch of who viewed it, by user id
+//! or else by address, from which the unique views are estimated. Only hashes salted with the jwt
+//! secret go into the sketch. Views which weren't flushed yet are lost when the server stops.
+use crate::{api::get_user_from_jwt, DbPool};
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  comment_view_count::{CommentViewCount, CommentViewCountForm},
+  naive_now,
+  post_view_count::{PostViewCount, PostViewCountForm},
+};
+use lemmy_utils::{
+  hyperloglog::HyperLogLog,
+  settings::{IpPolicy, Settings},
+  LemmyError,
+};
+use log::{debug, error};
+use std::{
+  collections::HashMap,
+  sync::{Mutex, MutexGuard, PoisonError},
+  time::Duration,
+};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Each sketch takes a kilobyte, so views of others are dropped until the next flush.
+const MAX_PENDING_VIEWED: usize = 10_000;
+
+/// What the views are counted for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Viewed {
+  Post(i32),
+  /// Comments are viewed on their own through `GetCommentContext`, views of the post don't count.
+  Comment(i32),
+}
+
+#[derive(Default)]
+struct PendingViews {
+  views: i64,
+  visitors: HyperLogLog,
+}
+
+lazy_static! {
+  static ref PENDING_VIEWS: Mutex<HashMap<Viewed, PendingViews>> = Mutex::new(HashMap::new());
+}
+
+/// A panic while counting can at worst leave a view half counted, so the counts are used anyway
+/// instead of losing all views from then on.
+fn pending_views() -> MutexGuard<'static, HashMap<Viewed, PendingViews>> {
+  PENDING_VIEWS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Nothing is counted while the site is read only. The token is checked like for any request, so
+/// that revoked tokens and those of admins viewing the site as a user aren't counted. When
+/// addresses are disabled in the config, views of anonymous visitors are counted, but not as unique
+/// views.
+pub async fn count_view(viewed: Viewed, auth: Option<&str>, ip: &str, pool: &DbPool) {
+  let settings = Settings::get();
+  if settings.maintenance.read_only {
+    return;
+  }
+  let user_id = match auth {
+    Some(auth) => match get_user_from_jwt(auth, pool).await {
+      Ok(user) => Some(user.id),
+      Err(_) => return,
+    },
+    None => None,
+  };
+  let visitor = match user_id {
+    Some(user_id) => Some(format!("user {}", user_id)),
+    None if settings.ip_addresses.policy == IpPolicy::Disabled => None,
+    None => Some(format!("ip {}", ip)),
+  };
+
+  let mut pending = pending_views();
+  if pending.len() >= MAX_PENDING_VIEWED && !pending.contains_key(&viewed) {
+    return;
+  }
+  let pending_views = pending.entry(viewed).or_default();
+  pending_views.views += 1;
+  if let Some(visitor) = visitor {
+    pending_views
+      .visitors
+      .insert(&visitor, &settings.jwt_secret);
+  }
+}
+
+/// The kept views and visitors with those which weren't flushed yet, as views and unique views.
+pub fn current_views(viewed: Viewed, kept: Option<(i64, Vec<u8>)>) -> (i64, i64) {
+  let (kept_views, kept_visitors) = match kept {
+    Some((views, visitors)) => (views, HyperLogLog::from_registers(visitors)),
+    None => (0, None),
+  };
+  let pending = pending_views();
+  let pending = match pending.get(&viewed) {
+    Some(pending) => pending,
+    None => return (kept_views, kept_visitors.map_or(0, |v| v.count() as i64)),
+  };
+  let mut visitors = pending.visitors.to_owned();
+  if let Some(kept_visitors) = kept_visitors {
+    visitors.merge(&kept_visitors);
+  }
+  (kept_views + pending.views, visitors.count() as i64)
+}
+
+pub async fn run_post_view_flush_job(pool: DbPool) {
+  let mut interval = actix_web::rt::time::interval(FLUSH_INTERVAL);
+  loop {
+    interval.tick().await;
+    if let Err(e) = flush_views(&pool).await {
+      error!("Couldn't flush the views: {}", e);
+    }
+  }
+}
+
+/// This job is the only one writing the counts, so they can be merged outside of the database.
+async fn flush_views(pool: &DbPool) -> Result<(), LemmyError> {
+  let pending = std::mem::take(&mut *pending_views());
+  if pending.is_empty() || Settings::get().maintenance.read_only {
+    return Ok(());
+  }
+
+  let flushed = pending.len();
+  blocking(pool, move |conn| -> Result<(), LemmyError> {
+    for (viewed, pending) in pending {
+      let kept = match viewed {
+        Viewed::Post(post_id) => {
+          PostViewCount::read_for_post(conn, post_id)?.map(|kept| (kept.views, kept.visitors))
+        }
+        Viewed::Comment(comment_id) => CommentViewCount::read_for_comment(conn, comment_id)?
+          .map(|kept| (kept.views, kept.visitors)),
+      };
+      let mut visitors = pending.visitors;
+      let mut views = pending.views;
+      if let Some((kept_views, kept_visitors)) = kept {
+        views += kept_views;
+        if let Some(kept_visitors) = HyperLogLog::from_registers(kept_visitors) {
+          visitors.merge(&kept_visitors);
+        }
+      }
+      let unique_views = visitors.count() as i64;
+      let visitors = visitors.registers().to_vec();
+      let updated = naive_now();
+      let kept = match viewed {
+        Viewed::Post(post_id) => PostViewCount::upsert(
+          conn,
+          &PostViewCountForm {
+            post_id,
+            views,
+            unique_views,
+            visitors,
+            updated,
+          },
+        )
+        .map(|_| ()),
+        Viewed::Comment(comment_id) => CommentViewCount::upsert(
+          conn,
+          &CommentViewCountForm {
+            comment_id,
+            views,
+            unique_views,
+            visitors,
+            updated,
+          },
+        )
+        .map(|_| ()),
+      };
+      // The post or comment can be gone by now
+      if let Err(e) = kept {
+        debug!("Couldn't keep the views of {:?}: {}", viewed, e);
+      }
+    }
+    Ok(())
+  })
+  .await??;
+  debug!("Flushed the views of {} posts and comments", flushed);
+  Ok(())
+}
@@ -0,0 +1,128 @@
+//! Web pushes of notifications to the browsers of users, encrypted and signed with
+//! `lemmy_utils::web_push`. The VAPID key is read for every push, like the DKIM key, so that it can
+//! be replaced without a restart.
+use crate::{request::check_outbound_url, LemmyContext};
+use anyhow::anyhow;
+use lemmy_api_structs::blocking;
+use lemmy_db::push_subscription::PushSubscription;
+use lemmy_utils::{
+  settings::{Settings, WebPushConfig},
+  web_push::{encrypt_push_payload, vapid_authorization, vapid_public_key},
+  LemmyError,
+};
+use log::{error, warn};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::time::Duration;
+use url::Url;
+
+/// How long push services keep a push for a browser which is offline, in seconds.
+const PUSH_TTL: u32 = 60 * 60 * 24;
+
+/// What the service worker of the frontend gets to show the notification.
+#[derive(Serialize)]
+pub struct PushMessage {
+  pub title: String,
+  pub body: String,
+  pub url: String,
+}
+
+/// None if web push isn't configured.
+fn read_vapid_key() -> Result<Option<(WebPushConfig, Vec<u8>)>, LemmyError> {
+  match Settings::get().web_push {
+    Some(config) => {
+      let key = std::fs::read(&config.private_key_file)?;
+      Ok(Some((config, key)))
+    }
+    None => Ok(None),
+  }
+}
+
+/// The key which browsers subscribe with, None if web push isn't configured.
+pub fn push_public_key() -> Result<Option<String>, LemmyError> {
+  match read_vapid_key()? {
+    Some((_, key)) => Ok(Some(vapid_public_key(&key)?)),
+    None => Ok(None),
+  }
+}
+
+/// Pushes the message to all browsers of the users, in the background.
+pub fn send_pushes(user_ids: Vec<i32>, message: PushMessage, context: &LemmyContext) {
+  if user_ids.is_empty() || Settings::get().web_push.is_none() {
+    return;
+  }
+  let context = context.to_owned();
+  actix_web::rt::spawn(async move {
+    if let Err(e) = do_send_pushes(user_ids, message, &context).await {
+      error!("Couldn't send web pushes: {}", e);
+    }
+  });
+}
+
+async fn do_send_pushes(
+  user_ids: Vec<i32>,
+  message: PushMessage,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let (config, key) = match read_vapid_key()? {
+    Some(k) => k,
+    None => return Ok(()),
+  };
+  let payload = serde_json::to_vec(&message)?;
+  let subscriptions = blocking(context.pool(), move |conn| {
+    PushSubscription::list_for_users(conn, user_ids)
+  })
+  .await??;
+  for subscription in subscriptions {
+    if let Err(e) = send_push(&subscription, &payload, &config, &key, context).await {
+      warn!("Couldn't push to {}: {}", subscription.endpoint, e);
+    }
+  }
+  Ok(())
+}
+
+/// Subscriptions which the push service doesn't know anymore, eg because the user revoked the
+/// permission in the browser, are deleted.
+async fn send_push(
+  subscription: &PushSubscription,
+  payload: &[u8],
+  config: &WebPushConfig,
+  key: &[u8],
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let endpoint = Url::parse(&subscription.endpoint)?;
+  // The endpoint comes from the browser of the user, so it could point anywhere
+  check_outbound_url(&endpoint).await?;
+  let body = encrypt_push_payload(payload, &subscription.p256dh, &subscription.auth)?;
+  let authorization = vapid_authorization(
+    &endpoint,
+    &config.subject,
+    key,
+    chrono::Utc::now().timestamp(),
+  )?;
+  let timeout = Settings::get().outbound_requests.federation_timeout_seconds;
+  let response = context
+    .client()
+    .post(endpoint.as_str())
+    .header("Authorization", authorization)
+    .header("Content-Encoding", "aes128gcm")
+    .header("Content-Type", "application/octet-stream")
+    .header("TTL", PUSH_TTL)
+    .timeout(Duration::from_secs(timeout))
+    .body(body)
+    .send()
+    .await?;
+
+  match response.status() {
+    StatusCode::NOT_FOUND | StatusCode::GONE => {
+      let endpoint = subscription.endpoint.to_owned();
+      blocking(context.pool(), move |conn| {
+        PushSubscription::delete_for_endpoint(conn, &endpoint)
+      })
+      .await??;
+      Ok(())
+    }
+    status if status.is_success() => Ok(()),
+    status => Err(anyhow!("Push service answered {}", status).into()),
+  }
+}
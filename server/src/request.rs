@@ -1,7 +1,28 @@
-use anyhow::anyhow;
-use lemmy_utils::LemmyError;
-use std::future::Future;
+use crate::{address_guard::start_address_guard, plugins::is_hook_host, version::VERSION};
+use actix_web::rt::time::delay_for;
+use anyhow::{anyhow, Context};
+use lemmy_utils::{apub::get_apub_protocol_string, location_info, settings::Settings, LemmyError};
+use log::warn;
+use rand::{thread_rng, Rng};
+use reqwest::{
+  header::LOCATION,
+  redirect::Policy,
+  Client,
+  Proxy,
+  Request,
+  RequestBuilder,
+  Response,
+};
+use std::{
+  collections::HashMap,
+  future::Future,
+  net::{IpAddr, Ipv4Addr, SocketAddr},
+  sync::Mutex,
+  time::{Duration, Instant},
+};
 use thiserror::Error;
+use tokio::net::lookup_host;
+use url::Url;
 
 #[derive(Clone, Debug, Error)]
 #[error("Error sending request, {0}")]
@@ -11,33 +32,529 @@ struct SendError(pub String);
 #[error("Error receiving response, {0}")]
 pub struct RecvError(pub String);
 
-pub async fn retry<F, Fut, T>(f: F) -> Result<T, LemmyError>
+#[derive(Clone, Debug, Error)]
+#[error("Not sending requests to {0} for now, too many of them failed")]
+struct CircuitOpenError(pub String);
+
+/// Consecutive failed requests to a host after which its circuit breaker opens.
+const CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open circuit breaker blocks requests. After that, one request is let through, and
+/// the breaker closes if it succeeds or opens again if it fails.
+const CIRCUIT_OPEN_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct CircuitBreaker {
+  failures: u32,
+  opened_at: Option<Instant>,
+}
+
+/// Retries and circuit breaker trips for a host, since the stats job last collected them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RequestMetrics {
+  pub retries: i32,
+  pub circuit_breaker_trips: i32,
+}
+
+lazy_static! {
+  static ref CIRCUIT_BREAKERS: Mutex<HashMap<String, CircuitBreaker>> = Mutex::new(HashMap::new());
+  static ref REQUEST_METRICS: Mutex<HashMap<String, RequestMetrics>> = Mutex::new(HashMap::new());
+}
+
+/// How requests to other servers are retried. Timeouts and connection errors are retried with
+/// exponential backoff and full jitter, anything else fails right away. Every host has a circuit
+/// breaker, so that we stop sending requests to a host that keeps failing for a while.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+  pub max_attempts: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+  /// For requests that someone is waiting for, like fetching objects and link previews.
+  pub const INTERACTIVE: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    base_delay: Duration::from_millis(100),
+    max_delay: Duration::from_secs(1),
+  };
+
+  /// For requests from the activity queue, which retries failed jobs itself with a longer backoff.
+  pub const BACKGROUND: RetryPolicy = RetryPolicy {
+    max_attempts: 1,
+    base_delay: Duration::from_secs(0),
+    max_delay: Duration::from_secs(0),
+  };
+
+  /// Sends a request to url with f, retrying it as configured.
+  pub async fn run<F, Fut, T>(&self, url: &str, f: F) -> Result<T, LemmyError>
+  where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, reqwest::Error>>,
+  {
+    self.run_custom(url, || async { Ok(f().await) }).await
+  }
+
+  /// Like run, but f can fail before sending the request. These errors are returned right away.
+  pub async fn run_custom<F, Fut, T>(&self, url: &str, f: F) -> Result<T, LemmyError>
+  where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Result<T, reqwest::Error>, LemmyError>>,
+  {
+    let host = Url::parse(url)
+      .ok()
+      .and_then(|u| u.host_str().map(ToOwned::to_owned))
+      .unwrap_or_else(|| url.to_owned());
+    check_circuit(&host)?;
+
+    let mut attempt = 0;
+    loop {
+      attempt += 1;
+      match f().await? {
+        Ok(t) => {
+          record_success(&host);
+          return Ok(t);
+        }
+        Err(e) => {
+          // Connection errors are request errors, error statuses and bodies aren't retried
+          let retryable = e.is_timeout() || e.is_request();
+          if !retryable || attempt >= self.max_attempts {
+            if retryable {
+              record_failure(&host);
+            }
+            return Err(SendError(e.to_string()).into());
+          }
+        }
+      }
+      add_metrics(&host, |m| m.retries += 1);
+      delay_for(self.delay(attempt)).await;
+    }
+  }
+
+  /// A random delay between zero and the exponential backoff for the given attempt.
+  fn delay(&self, attempt: u32) -> Duration {
+    let backoff = self
+      .base_delay
+      .checked_mul(2u32.saturating_pow(attempt - 1))
+      .unwrap_or(self.max_delay)
+      .min(self.max_delay);
+    let millis = backoff.as_millis() as u64;
+    Duration::from_millis(thread_rng().gen_range(0, millis + 1))
+  }
+}
+
+/// Returns the request metrics per host, and starts counting from zero again.
+pub fn take_request_metrics() -> HashMap<String, RequestMetrics> {
+  match REQUEST_METRICS.lock() {
+    Ok(mut metrics) => std::mem::take(&mut *metrics),
+    Err(_) => HashMap::new(),
+  }
+}
+
+/// Adds metrics which were taken, but couldn't be saved, back for the next run of the stats job.
+pub fn restore_request_metrics(taken: HashMap<String, RequestMetrics>) {
+  if let Ok(mut metrics) = REQUEST_METRICS.lock() {
+    for (host, m) in taken {
+      let entry = metrics.entry(host).or_default();
+      entry.retries += m.retries;
+      entry.circuit_breaker_trips += m.circuit_breaker_trips;
+    }
+  }
+}
+
+fn add_metrics<F>(host: &str, f: F)
+where
+  F: FnOnce(&mut RequestMetrics),
+{
+  if let Ok(mut metrics) = REQUEST_METRICS.lock() {
+    f(metrics.entry(host.to_owned()).or_default());
+  }
+}
+
+fn check_circuit(host: &str) -> Result<(), LemmyError> {
+  let breakers = CIRCUIT_BREAKERS
+    .lock()
+    .map_err(|_| anyhow!("Lock poisoned"))?;
+  match breakers.get(host).and_then(|b| b.opened_at) {
+    Some(opened_at) if opened_at.elapsed() < CIRCUIT_OPEN_DURATION => {
+      Err(CircuitOpenError(host.to_owned()).into())
+    }
+    _ => Ok(()),
+  }
+}
+
+fn record_success(host: &str) {
+  if let Ok(mut breakers) = CIRCUIT_BREAKERS.lock() {
+    breakers.remove(host);
+  }
+}
+
+fn record_failure(host: &str) {
+  let opened = match CIRCUIT_BREAKERS.lock() {
+    Ok(mut breakers) => {
+      let breaker = breakers.entry(host.to_owned()).or_default();
+      breaker.failures += 1;
+      if breaker.failures >= CIRCUIT_FAILURE_THRESHOLD {
+        breaker.opened_at = Some(Instant::now());
+        true
+      } else {
+        false
+      }
+    }
+    Err(_) => false,
+  };
+  if opened {
+    warn!("Too many failed requests to {}, pausing requests", host);
+    add_metrics(host, |m| m.circuit_breaker_trips += 1);
+  }
+}
+
+#[derive(Clone, Debug, Error)]
+#[error("Refusing to connect to {0}, it's not a public address")]
+struct BlockedAddressError(pub String);
+
+/// The client for all outbound requests, configured by the outbound_requests settings. It's created
+/// once and shared, so that connections are reused. It doesn't follow redirects itself,
+/// get_checked does that after checking every target. Connections to other servers go through the
+/// address guard, which connects through the proxy of the config if there is one.
+///
+/// Requests use the federation timeout, link previews set link_preview_timeout on the request.
+pub fn build_client() -> Result<Client, LemmyError> {
+  let config = Settings::get().outbound_requests;
+  let mut builder = Client::builder()
+    .user_agent(user_agent())
+    .redirect(Policy::none())
+    .timeout(Duration::from_secs(config.federation_timeout_seconds))
+    .pool_max_idle_per_host(config.max_idle_connections_per_host);
+  // The guard connects through the proxy itself, but an unusable one should fail at startup
+  if let Some(proxy) = config.proxy {
+    let proxy = Url::parse(&proxy)?;
+    if !["http", "socks5", "socks5h"].contains(&proxy.scheme()) {
+      return Err(anyhow!("Unsupported proxy scheme {}", proxy.scheme()).into());
+    }
+  }
+  let guard = Url::parse(&format!("socks5h://{}", start_address_guard()?))?;
+  builder = builder.proxy(Proxy::custom(move |url| {
+    if is_internal_service(url) {
+      None
+    } else {
+      Some(guard.to_owned())
+    }
+  }));
+  Ok(builder.build()?)
+}
+
+/// The services which admins run next to Lemmy are usually on the internal network, so they're
+/// contacted without the address guard.
+fn is_internal_service(url: &Url) -> bool {
+  let host = match url.host_str() {
+    Some(host) => host,
+    None => return false,
+  };
+  let settings = Settings::get();
+  let host_of = |url: &str| Url::parse(url).ok()?.host_str().map(str::to_owned);
+  let service_hosts = [
+    Some("pictrs".to_owned()),
+    Some("iframely".to_owned()),
+    host_of(&settings.pictrs_url),
+    settings.spam.classifier_url.as_deref().and_then(host_of),
+  ];
+  is_allowed_host(host) || service_hosts.iter().flatten().any(|h| h == host) || is_hook_host(host)
+}
+
+/// Tells other servers which software and instance the requests come from.
+fn user_agent() -> String {
+  format!(
+    "Lemmy/{}; +{}://{}",
+    VERSION,
+    get_apub_protocol_string(),
+    Settings::get().hostname
+  )
+}
+
+pub fn link_preview_timeout() -> Duration {
+  Duration::from_secs(
+    Settings::get()
+      .outbound_requests
+      .link_preview_timeout_seconds,
+  )
+}
+
+/// Checks a url that users or other instances gave us before connecting to it, so that they can't
+/// make us send requests to the internal network. The host has to resolve to public addresses only,
+/// unless it's in the allowed_hosts setting.
+pub async fn check_outbound_url(url: &Url) -> Result<(), LemmyError> {
+  if url.scheme() != "http" && url.scheme() != "https" {
+    return Err(anyhow!("Invalid url scheme, {}", url).into());
+  }
+  let host = url.host_str().context(location_info!())?;
+  if is_allowed_host(host) {
+    return Ok(());
+  }
+
+  let port = url.port_or_known_default().context(location_info!())?;
+  // Ip addresses in brackets can't be resolved like that
+  let host = host.trim_start_matches('[').trim_end_matches(']');
+  resolve_checked(host, port).await?;
+  Ok(())
+}
+
+fn is_allowed_host(host: &str) -> bool {
+  let allowed_hosts = Settings::get().outbound_requests.allowed_hosts;
+  allowed_hosts.split(',').any(|h| h.trim() == host)
+}
+
+/// The address to connect to for the host, if all of its addresses are public, or if it's in the
+/// allowed_hosts setting. The address guard connects to the address this returns.
+pub(crate) async fn resolve_checked(host: &str, port: u16) -> Result<SocketAddr, LemmyError> {
+  let addrs = lookup_host((host, port))
+    .await?
+    .collect::<Vec<SocketAddr>>();
+  if !is_allowed_host(host) && addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+    return Err(BlockedAddressError(host.to_owned()).into());
+  }
+  Ok(
+    *addrs
+      .first()
+      .with_context(|| format!("Couldn't resolve {}", host))?,
+  )
+}
+
+/// Sends a GET request to a url from a user or another instance, checking it and every redirect
+/// with check_outbound_url.
+pub async fn get_checked<F>(client: &Client, url: &Url, build: F) -> Result<Response, LemmyError>
 where
-  F: Fn() -> Fut,
-  Fut: Future<Output = Result<T, reqwest::Error>>,
+  F: Fn(RequestBuilder) -> RequestBuilder,
 {
-  retry_custom(|| async { Ok((f)().await) }).await
+  get_checked_with(client, url, |r| Ok(build(r).build()?)).await
 }
 
-async fn retry_custom<F, Fut, T>(f: F) -> Result<T, LemmyError>
+/// Like get_checked, but building the request can fail, eg when it's signed. Every redirect and
+/// retry gets a newly built request.
+pub async fn get_checked_with<F>(
+  client: &Client,
+  url: &Url,
+  build: F,
+) -> Result<Response, LemmyError>
 where
-  F: Fn() -> Fut,
-  Fut: Future<Output = Result<Result<T, reqwest::Error>, LemmyError>>,
+  F: Fn(RequestBuilder) -> Result<Request, LemmyError>,
 {
-  let mut response = Err(anyhow!("connect timeout").into());
-
-  for _ in 0u8..3 {
-    match (f)().await? {
-      Ok(t) => return Ok(t),
-      Err(e) => {
-        if e.is_timeout() {
-          response = Err(SendError(e.to_string()).into());
-          continue;
+  let mut url = url.to_owned();
+  for _ in 0..=Settings::get().outbound_requests.max_redirects {
+    check_outbound_url(&url).await?;
+    let response = RetryPolicy::INTERACTIVE
+      .run_custom(url.as_str(), || async {
+        let request = build(client.get(url.as_str()))?;
+        Ok(client.execute(request).await)
+      })
+      .await?;
+    if !response.status().is_redirection() {
+      return Ok(response);
+    }
+    let location = response
+      .headers()
+      .get(LOCATION)
+      .context(location_info!())?
+      .to_str()?;
+    url = url.join(location)?;
+  }
+  Err(anyhow!("Too many redirects for {}", url).into())
+}
+
+/// Reads the body of a response, up to the max_response_bytes setting.
+pub async fn read_body(mut response: Response) -> Result<Vec<u8>, LemmyError> {
+  let max_bytes = Settings::get().outbound_requests.max_response_bytes;
+  let too_large = || anyhow!("Response is larger than {} bytes", max_bytes);
+  if response.content_length().unwrap_or(0) > max_bytes {
+    return Err(too_large().into());
+  }
+
+  let mut body = Vec::new();
+  while let Some(chunk) = response
+    .chunk()
+    .await
+    .map_err(|e| RecvError(e.to_string()))?
+  {
+    body.extend_from_slice(&chunk);
+    if body.len() as u64 > max_bytes {
+      return Err(too_large().into());
+    }
+  }
+  Ok(body)
+}
+
+/// Like the unstable IpAddr::is_global, false for private, loopback, link local and other reserved
+/// ranges.
+fn is_public_ip(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      let [a, b, c, _] = ip.octets();
+      !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Shared address space for carrier grade NAT, 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // Reserved, 240.0.0.0/4, and "this network", 0.0.0.0/8
+        || a >= 240
+        || a == 0)
+    }
+    IpAddr::V6(ip) => {
+      if let Some(ip) = ip.to_ipv4() {
+        // Only ipv4 mapped addresses, ::ffff:a.b.c.d, are routed like ipv4 ones
+        if ip.octets() != [0, 0, 0, 1] && ip.octets() != [0, 0, 0, 0] {
+          return is_public_ip(IpAddr::V4(ip));
         }
-        return Err(SendError(e.to_string()).into());
       }
+      let segments = ip.segments();
+      // NAT64, 64:ff9b::/96, and 6to4, 2002::/16, reach the ipv4 address in them
+      if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return is_public_ip(IpAddr::V4(embedded_ipv4(segments[6], segments[7])));
+      }
+      if segments[0] == 0x2002 {
+        return is_public_ip(IpAddr::V4(embedded_ipv4(segments[1], segments[2])));
+      }
+      !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link local, fe80::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+        // NAT64 for local use, 64:ff9b:1::/48
+        || segments[..3] == [0x64, 0xff9b, 1])
+    }
+  }
+}
+
+fn embedded_ipv4(high: u16, low: u16) -> Ipv4Addr {
+  let [a, b] = high.to_be_bytes();
+  let [c, d] = low.to_be_bytes();
+  Ipv4Addr::new(a, b, c, d)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::request::{
+    check_circuit,
+    check_outbound_url,
+    is_public_ip,
+    record_failure,
+    record_success,
+    restore_request_metrics,
+    take_request_metrics,
+    RequestMetrics,
+    RetryPolicy,
+    CIRCUIT_FAILURE_THRESHOLD,
+  };
+  use std::{collections::HashMap, time::Duration};
+  use url::Url;
+
+  #[test]
+  fn test_retry_delay() {
+    let policy = RetryPolicy::INTERACTIVE;
+    for attempt in 1..10 {
+      assert!(policy.delay(attempt) <= policy.max_delay);
     }
+    assert!(policy.delay(1) <= Duration::from_millis(100));
+  }
+
+  #[test]
+  fn test_circuit_breaker() {
+    let host = "circuit.example.com";
+    for _ in 1..CIRCUIT_FAILURE_THRESHOLD {
+      record_failure(host);
+      assert!(check_circuit(host).is_ok());
+    }
+    record_failure(host);
+    assert!(check_circuit(host).is_err());
+    record_success(host);
+    assert!(check_circuit(host).is_ok());
+  }
+
+  #[test]
+  fn test_restore_request_metrics() {
+    let host = "restore.example.com";
+    let mut taken = HashMap::new();
+    taken.insert(
+      host.to_owned(),
+      RequestMetrics {
+        retries: 2,
+        circuit_breaker_trips: 1,
+      },
+    );
+    restore_request_metrics(taken.clone());
+    restore_request_metrics(taken);
+    assert_eq!(
+      Some(&RequestMetrics {
+        retries: 4,
+        circuit_breaker_trips: 2,
+      }),
+      take_request_metrics().get(host)
+    );
   }
 
-  response
+  #[test]
+  fn test_check_outbound_url() {
+    actix_rt::System::new("test_check_outbound_url").block_on(async move {
+      for url in &[
+        "http://127.0.0.1:8536/",
+        "http://localhost/",
+        "https://10.0.0.1/",
+        "http://[::1]/",
+        "http://169.254.169.254/latest/meta-data/",
+        "file:///etc/passwd",
+      ] {
+        let url = Url::parse(url).unwrap();
+        assert!(check_outbound_url(&url).await.is_err(), "{}", url);
+      }
+      let public = Url::parse("https://93.184.216.34/").unwrap();
+      assert!(check_outbound_url(&public).await.is_ok());
+    });
+  }
+
+  #[test]
+  fn test_is_public_ip() {
+    for ip in &[
+      "1.1.1.1",
+      "93.184.216.34",
+      "2606:4700:4700::1111",
+      "64:ff9b::5db8:d822",
+      "2002:5db8:d822::1",
+    ] {
+      assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+    }
+    for ip in &[
+      "127.0.0.1",
+      "10.0.0.1",
+      "172.16.5.4",
+      "192.168.1.1",
+      "169.254.169.254",
+      "100.64.0.1",
+      "0.0.0.0",
+      "255.255.255.255",
+      "::1",
+      "::",
+      "fd00::1",
+      "fe80::1",
+      "::ffff:127.0.0.1",
+      "::ffff:192.168.1.1",
+      "64:ff9b::7f00:1",
+      "64:ff9b::a9fe:a9fe",
+      "64:ff9b:1::5db8:d822",
+      "2002:7f00:1::",
+      "2002:c0a8:101::1",
+    ] {
+      assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+    }
+  }
 }
@@ -0,0 +1,103 @@
+use actix_web::HttpResponse;
+use lemmy_utils::CommunityId;
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// How long a response is cached, unless something changes in its community before.
+const RESPONSE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Listings can be requested with any page and sort, this bounds the memory used for them.
+const MAX_CACHED_RESPONSES: usize = 1000;
+
+struct CachedResponse {
+  body: String,
+  content_type: &'static str,
+  community_id: Option<CommunityId>,
+  expires: Instant,
+}
+
+/// Responses which are the same for every anonymous client, like the front page, are cached for a
+/// short while so that traffic spikes don't all hit the database.
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+  responses: Arc<Mutex<HashMap<String, CachedResponse>>>,
+}
+
+impl ResponseCache {
+  pub fn get(&self, key: &str) -> Option<HttpResponse> {
+    let responses = self.responses.lock().unwrap();
+    responses
+      .get(key)
+      .filter(|r| r.expires > Instant::now())
+      .map(|r| {
+        HttpResponse::Ok()
+          .content_type(r.content_type)
+          .body(r.body.to_owned())
+      })
+  }
+
+  /// `community_id` is the community whose content is in the response, if there is one. Other
+  /// responses, like the front page, only expire.
+  pub fn insert(
+    &self,
+    key: String,
+    body: String,
+    content_type: &'static str,
+    community_id: Option<CommunityId>,
+  ) {
+    let now = Instant::now();
+    let mut responses = self.responses.lock().unwrap();
+    if responses.len() >= MAX_CACHED_RESPONSES {
+      responses.retain(|_, r| r.expires > now);
+      if responses.len() >= MAX_CACHED_RESPONSES {
+        return;
+      }
+    }
+    responses.insert(
+      key,
+      CachedResponse {
+        body,
+        content_type,
+        community_id,
+        expires: now + RESPONSE_CACHE_TTL,
+      },
+    );
+  }
+
+  pub fn remove(&self, key: &str) {
+    self.responses.lock().unwrap().remove(key);
+  }
+
+  /// Drops the responses with content of a community, after something in it changed.
+  pub fn invalidate_community(&self, community_id: CommunityId) {
+    self
+      .responses
+      .lock()
+      .unwrap()
+      .retain(|_, r| r.community_id != Some(community_id));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::response_cache::ResponseCache;
+
+  #[test]
+  fn test_invalidate_community() {
+    let cache = ResponseCache::default();
+    let json = "application/json";
+    cache.insert("/front".into(), "[]".into(), json, None);
+    cache.insert("/c/1".into(), "[]".into(), json, Some(1));
+    cache.insert("/c/2".into(), "[]".into(), json, Some(2));
+
+    cache.invalidate_community(1);
+
+    assert!(cache.get("/front").is_some());
+    assert!(cache.get("/c/1").is_none());
+    assert!(cache.get("/c/2").is_some());
+    assert!(cache.get("/unknown").is_none());
+  }
+}
@@ -1,7 +1,25 @@
-use crate::{api::Perform, LemmyContext};
+use crate::{
+  api::{claims::Claims, user::record_sign_in, Perform},
+  post_views::{count_view, Viewed},
+  routes::{
+    compact::{compact_response, wants_compact},
+    session::{
+      add_session_cookies,
+      remove_session_cookies,
+      session_cookie_jwt,
+      wants_cookie_session,
+    },
+  },
+  LemmyContext,
+};
 use actix_web::{error::ErrorBadRequest, *};
-use lemmy_api_structs::{comment::*, community::*, post::*, site::*, user::*};
+use lemmy_api_structs::{blocking, comment::*, community::*, post::*, site::*, user::*};
+use lemmy_db::sign_in_signal::SignInSignal;
 use lemmy_rate_limit::RateLimit;
+use lemmy_utils::{
+  settings::Settings,
+  utils::{check_not_read_only, client_fingerprint, get_ip},
+};
 use serde::Deserialize;
 
 pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
@@ -9,23 +27,150 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
     web::scope("/api/v1")
       // Websockets
       .service(web::resource("/ws").to(super::websocket::chat_route))
+      // Server-sent events, for clients which can't use websockets
+      .service(web::resource("/events").to(super::sse::events_route))
       // Site
       .service(
         web::scope("/site")
           .wrap(rate_limit.message())
+          .data(rate_limit.clone())
           .route("", web::get().to(route_get::<GetSite>))
           // Admin Actions
           .route("", web::post().to(route_post::<CreateSite>))
           .route("", web::put().to(route_post::<EditSite>))
           .route("/transfer", web::post().to(route_post::<TransferSite>))
           .route("/config", web::get().to(route_get::<GetSiteConfig>))
-          .route("/config", web::put().to(route_post::<SaveSiteConfig>)),
+          .route("/config", web::put().to(route_post::<SaveSiteConfig>))
+          .route("/spam", web::get().to(route_get::<ListSpamScores>))
+          .route("/stats", web::get().to(route_get::<GetInstanceStats>))
+          .route("/email/test", web::post().to(route_post::<SendTestEmail>))
+          .route("/email/outbox", web::get().to(route_get::<GetEmailOutbox>))
+          .route("/csp_reports", web::get().to(route_get::<ListCspReports>))
+          .route(
+            "/rotate_keys",
+            web::post().to(route_post::<RotateActorKeys>),
+          )
+          .route(
+            "/impersonate",
+            web::post().to(route_post::<ImpersonateUser>),
+          )
+          .route(
+            "/impersonations",
+            web::get().to(route_get::<GetImpersonationLog>),
+          )
+          .route(
+            "/duplicate_accounts",
+            web::get().to(route_get::<GetDuplicateAccounts>),
+          )
+          .route(
+            "/default_communities",
+            web::get().to(route_get::<GetDefaultCommunities>),
+          )
+          .route(
+            "/default_communities",
+            web::put().to(route_post::<SaveDefaultCommunities>),
+          )
+          .route("/branding", web::get().to(route_get::<GetSiteBranding>))
+          .route("/branding", web::put().to(route_post::<SaveSiteBranding>))
+          .route("/domain/list", web::get().to(route_get::<ListDomains>))
+          .route("/domain", web::get().to(route_get::<GetDomain>))
+          .route(
+            "/domain/policy",
+            web::put().to(route_post::<SetDomainPolicy>),
+          )
+          .route(
+            "/feature_flag/list",
+            web::get().to(route_get::<ListFeatureFlags>),
+          )
+          .route(
+            "/feature_flag",
+            web::put().to(route_post::<SaveFeatureFlag>),
+          )
+          .route(
+            "/feature_flag/delete",
+            web::post().to(route_post::<DeleteFeatureFlag>),
+          )
+          .route(
+            "/feature_flag/user",
+            web::put().to(route_post::<SetFeatureFlagUser>),
+          )
+          .route("/features", web::get().to(route_get::<GetEnabledFeatures>))
+          .route(
+            "/plugin_hook/list",
+            web::get().to(route_get::<ListPluginHooks>),
+          )
+          .route(
+            "/plugin_hook",
+            web::post().to(route_post::<CreatePluginHook>),
+          )
+          .route("/plugin_hook", web::put().to(route_post::<EditPluginHook>))
+          .route(
+            "/plugin_hook/delete",
+            web::post().to(route_post::<DeletePluginHook>),
+          )
+          .route(
+            "/wasm_plugin/list",
+            web::get().to(route_get::<ListWasmPlugins>),
+          )
+          .route(
+            "/wasm_plugin/enabled",
+            web::put().to(route_post::<SetWasmPluginEnabled>),
+          )
+          .route(
+            "/wasm_plugin/reload",
+            web::post().to(route_post::<ReloadWasmPlugins>),
+          )
+          .route(
+            "/maintenance",
+            web::get().to(route_get::<GetMaintenanceMode>),
+          )
+          .route(
+            "/maintenance",
+            web::put().to(route_post_when_read_only::<SaveMaintenanceMode>),
+          )
+          .route(
+            "/announcement/list",
+            web::get().to(route_get::<ListAnnouncements>),
+          )
+          .route(
+            "/announcement",
+            web::post().to(route_post::<CreateAnnouncement>),
+          )
+          .route(
+            "/announcement",
+            web::put().to(route_post::<EditAnnouncement>),
+          )
+          .route(
+            "/announcement/delete",
+            web::post().to(route_post::<DeleteAnnouncement>),
+          )
+          .route(
+            "/announcement/dismiss",
+            web::post().to(route_post::<DismissAnnouncement>),
+          )
+          .route(
+            "/rate_limit",
+            web::get().to(route_get::<GetRateLimitConfig>),
+          )
+          .route(
+            "/rate_limit",
+            web::put().to(route_post::<SaveRateLimitConfig>),
+          )
+          .route(
+            "/rate_limit/allowances",
+            web::get().to(get_rate_limit_allowances),
+          ),
       )
       .service(
         web::resource("/categories")
           .wrap(rate_limit.message())
           .route(web::get().to(route_get::<ListCategories>)),
       )
+      .service(
+        web::resource("/federated_instances")
+          .wrap(rate_limit.message())
+          .route(web::get().to(route_get::<GetFederatedInstances>)),
+      )
       .service(
         web::resource("/modlog")
           .wrap(rate_limit.message())
@@ -33,7 +178,7 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
       )
       .service(
         web::resource("/search")
-          .wrap(rate_limit.message())
+          .wrap(rate_limit.search())
           .route(web::get().to(route_get::<Search>)),
       )
       // Community
@@ -49,12 +194,87 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
           .route("", web::get().to(route_get::<GetCommunity>))
           .route("", web::put().to(route_post::<EditCommunity>))
           .route("/list", web::get().to(route_get::<ListCommunities>))
+          .route(
+            "/recommended",
+            web::get().to(route_get::<GetRecommendedCommunities>),
+          )
           .route("/follow", web::post().to(route_post::<FollowCommunity>))
+          .route(
+            "/notifications",
+            web::post().to(route_post::<SubscribeCommunityNotifications>),
+          )
+          .route(
+            "/digest",
+            web::post().to(route_post::<SubscribeCommunityDigest>),
+          )
           .route("/delete", web::post().to(route_post::<DeleteCommunity>))
           // Mod Actions
+          .route(
+            "/join_requests",
+            web::get().to(route_get::<GetCommunityJoinRequests>),
+          )
+          .route(
+            "/join_requests/review",
+            web::post().to(route_post::<ReviewCommunityJoinRequest>),
+          )
+          .route("/members", web::get().to(route_get::<GetCommunityMembers>))
+          .route(
+            "/members/remove",
+            web::post().to(route_post::<RemoveCommunityMember>),
+          )
+          .route(
+            "/removal_reason",
+            web::get().to(route_get::<GetRemovalReasons>),
+          )
+          .route(
+            "/removal_reason",
+            web::post().to(route_post::<CreateRemovalReason>),
+          )
+          .route(
+            "/removal_reason/delete",
+            web::post().to(route_post::<DeleteRemovalReason>),
+          )
+          .route(
+            "/post_tag",
+            web::get().to(route_get::<GetCommunityPostTags>),
+          )
+          .route(
+            "/post_tag",
+            web::post().to(route_post::<CreateCommunityPostTag>),
+          )
+          .route(
+            "/post_tag/delete",
+            web::post().to(route_post::<DeleteCommunityPostTag>),
+          )
           .route("/remove", web::post().to(route_post::<RemoveCommunity>))
           .route("/transfer", web::post().to(route_post::<TransferCommunity>))
           .route("/ban_user", web::post().to(route_post::<BanFromCommunity>))
+          .route(
+            "/bulk_remove",
+            web::post().to(route_post::<BulkRemoveFromCommunity>),
+          )
+          .route(
+            "/bulk_ban",
+            web::post().to(route_post::<BulkBanFromCommunity>),
+          )
+          .route("/bulk_action", web::get().to(route_get::<GetModBulkAction>))
+          .route("/stats", web::get().to(route_get::<GetCommunityStats>))
+          .route(
+            "/scheduled_post/list",
+            web::get().to(route_get::<ListScheduledPosts>),
+          )
+          .route(
+            "/scheduled_post",
+            web::post().to(route_post::<CreateScheduledPost>),
+          )
+          .route(
+            "/scheduled_post",
+            web::put().to(route_post::<EditScheduledPost>),
+          )
+          .route(
+            "/scheduled_post/delete",
+            web::post().to(route_post::<DeleteScheduledPost>),
+          )
           .route("/mod", web::post().to(route_post::<AddModToCommunity>)),
       )
       // Post
@@ -68,31 +288,71 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
       .service(
         web::scope("/post")
           .wrap(rate_limit.message())
-          .route("", web::get().to(route_get::<GetPost>))
+          .route("", web::get().to(get_post))
           .route("", web::put().to(route_post::<EditPost>))
           .route("/delete", web::post().to(route_post::<DeletePost>))
           .route("/remove", web::post().to(route_post::<RemovePost>))
           .route("/lock", web::post().to(route_post::<LockPost>))
+          .route(
+            "/close_comments",
+            web::post().to(route_post::<ClosePostComments>),
+          )
           .route("/sticky", web::post().to(route_post::<StickyPost>))
-          .route("/list", web::get().to(route_get::<GetPosts>))
+          .route(
+            "/distinguish",
+            web::post().to(route_post::<DistinguishPost>),
+          )
+          .route("/list", web::get().to(get_posts))
+          .route("/similar", web::get().to(route_get::<GetSimilarPosts>))
+          .route("/check_title", web::get().to(route_get::<CheckPostTitle>))
+          .route("/by_url", web::get().to(route_get::<GetPostsByUrl>))
+          .route("/crossposts", web::get().to(route_get::<GetPostCrossposts>))
+          .route(
+            "/edit_history",
+            web::get().to(route_get::<GetPostEditHistory>),
+          )
+          .route(
+            "/domain_approvals",
+            web::get().to(route_get::<ListDomainPostApprovals>),
+          )
+          .route(
+            "/approve_domain",
+            web::post().to(route_post::<ApproveDomainPost>),
+          )
           .route("/like", web::post().to(route_post::<CreatePostLike>))
           .route("/save", web::put().to(route_post::<SavePost>)),
       )
       // Comment
+      .service(
+        // Handle POST to /comment separately to add the comment() rate limitter
+        web::resource("/comment")
+          .guard(guard::Post())
+          .wrap(rate_limit.comment())
+          .route(web::post().to(route_post::<CreateComment>)),
+      )
       .service(
         web::scope("/comment")
           .wrap(rate_limit.message())
-          .route("", web::post().to(route_post::<CreateComment>))
           .route("", web::put().to(route_post::<EditComment>))
           .route("/delete", web::post().to(route_post::<DeleteComment>))
           .route("/remove", web::post().to(route_post::<RemoveComment>))
+          .route(
+            "/distinguish",
+            web::post().to(route_post::<DistinguishComment>),
+          )
+          .route("/pin", web::post().to(route_post::<PinComment>))
+          .route(
+            "/approve_mentions",
+            web::post().to(route_post::<ApproveMentions>),
+          )
           .route(
             "/mark_as_read",
             web::post().to(route_post::<MarkCommentAsRead>),
           )
           .route("/like", web::post().to(route_post::<CreateCommentLike>))
           .route("/save", web::put().to(route_post::<SaveComment>))
-          .route("/list", web::get().to(route_get::<GetComments>)),
+          .route("/list", web::get().to(route_get::<GetComments>))
+          .route("/context", web::get().to(get_comment_context)),
       )
       // Private Message
       .service(
@@ -117,7 +377,7 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
         web::resource("/user/register")
           .guard(guard::Post())
           .wrap(rate_limit.register())
-          .route(web::post().to(route_post::<Register>)),
+          .route(web::post().to(register)),
       )
       // User actions
       .service(
@@ -130,15 +390,53 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
             web::post().to(route_post::<MarkUserMentionAsRead>),
           )
           .route("/replies", web::get().to(route_get::<GetReplies>))
+          .route(
+            "/notifications/poll",
+            web::get().to(route_get::<GetNotifications>),
+          )
           .route(
             "/followed_communities",
             web::get().to(route_get::<GetFollowedCommunities>),
           )
-          .route("/join", web::post().to(route_post::<UserJoin>))
+          .route(
+            "/community_notifications",
+            web::get().to(route_get::<GetCommunityNotifications>),
+          )
+          .route(
+            "/community_notifications/posts",
+            web::get().to(route_get::<GetCommunityNotificationPosts>),
+          )
+          .route(
+            "/community_digests",
+            web::get().to(route_get::<GetCommunityDigests>),
+          )
+          .route("/mod_inbox", web::get().to(route_get::<GetModInbox>))
+          .route(
+            "/mod_inbox/counts",
+            web::get().to(route_get::<GetModInboxCounts>),
+          )
+          .route(
+            "/mod_inbox/resolve_report",
+            web::post().to(route_post::<ResolveReport>),
+          )
+          .route(
+            "/mod_inbox/resolve_vote_burst",
+            web::post().to(route_post::<ResolveVoteBurst>),
+          )
+          .route(
+            "/mod_inbox/vote_origins",
+            web::get().to(route_get::<GetVoteOrigins>),
+          )
+          .route("/report", web::post().to(route_post::<CreateReport>))
+          .route(
+            "/join",
+            web::post().to(route_post_when_read_only::<UserJoin>),
+          )
           // Admin action. I don't like that it's in /user
           .route("/ban", web::post().to(route_post::<BanUser>))
           // Account actions. I don't like that they're in /user maybe /accounts
-          .route("/login", web::post().to(route_post::<Login>))
+          .route("/login", web::post().to(login))
+          .route("/logout", web::post().to(logout))
           .route("/get_captcha", web::get().to(route_get::<GetCaptcha>))
           .route(
             "/delete_account",
@@ -148,9 +446,11 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
             "/password_reset",
             web::post().to(route_post::<PasswordReset>),
           )
+          .route("/password_change", web::post().to(password_change))
+          .route("/sign_ins", web::get().to(route_get::<GetSignIns>))
           .route(
-            "/password_change",
-            web::post().to(route_post::<PasswordChange>),
+            "/sign_ins/review",
+            web::post().to(route_post::<ReviewSignIn>),
           )
           // mark_all_as_read feels off being in this section as well
           .route(
@@ -160,6 +460,44 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
           .route(
             "/save_user_settings",
             web::put().to(route_post::<SaveUserSettings>),
+          )
+          .route(
+            "/saved_search",
+            web::get().to(route_get::<GetSavedSearches>),
+          )
+          .route(
+            "/saved_search",
+            web::post().to(route_post::<CreateSavedSearch>),
+          )
+          .route(
+            "/saved_search/delete",
+            web::post().to(route_post::<DeleteSavedSearch>),
+          )
+          .route(
+            "/saved_search/matches",
+            web::get().to(route_get::<GetSavedSearchMatches>),
+          )
+          .route("/mute_thread", web::post().to(route_post::<MuteThread>))
+          .route("/thread_mutes", web::get().to(route_get::<GetThreadMutes>))
+          .route(
+            "/push_public_key",
+            web::get().to(route_get::<GetPushPublicKey>),
+          )
+          .route(
+            "/push_subscription",
+            web::post().to(route_post::<SavePushSubscription>),
+          )
+          .route(
+            "/push_subscription/delete",
+            web::post().to(route_post::<DeletePushSubscription>),
+          )
+          .route(
+            "/ignore_vote_instance",
+            web::post().to(route_post::<IgnoreVoteInstance>),
+          )
+          .route(
+            "/ignored_vote_instances",
+            web::get().to(route_get::<GetIgnoredVoteInstances>),
           ),
       )
       // Admin Actions
@@ -172,6 +510,7 @@ pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
 }
 
 async fn perform<Request>(
+  req: HttpRequest,
   data: Request,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, Error>
@@ -182,27 +521,216 @@ where
   let res = data
     .perform(&context, None)
     .await
-    .map(|json| HttpResponse::Ok().json(json))
     .map_err(ErrorBadRequest)?;
+  if wants_compact(&req) {
+    let compact = compact_response(serde_json::to_value(res)?);
+    return Ok(HttpResponse::Ok().json(compact));
+  }
+  Ok(HttpResponse::Ok().json(res))
+}
+
+/// Keeps where the user signed up from, see `record_sign_in`.
+async fn register(
+  req: HttpRequest,
+  data: web::Json<Register>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  check_not_read_only()?;
+  sign_in(req, data.0, true, context).await
+}
+
+/// Keeps working while the site is read only, but then nothing is kept.
+async fn login(
+  req: HttpRequest,
+  data: web::Json<Login>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  sign_in(req, data.0, false, context).await
+}
+
+/// The user is logged out everywhere else, so the new token gets a sign in of its own.
+async fn password_change(
+  req: HttpRequest,
+  data: web::Json<PasswordChange>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  check_not_read_only()?;
+  sign_in(req, data.0, false, context).await
+}
+
+async fn sign_in<Request>(
+  req: HttpRequest,
+  data: Request,
+  registration: bool,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error>
+where
+  Request: Perform<Response = LoginResponse>,
+{
+  let ip = get_ip(&req.connection_info());
+  let mut res = data
+    .perform(&context, None)
+    .await
+    .map_err(ErrorBadRequest)?;
+  res.jwt = record_sign_in(
+    &res.jwt,
+    &ip,
+    client_fingerprint(req.headers()),
+    registration,
+    &context,
+  )
+  .await
+  .map_err(ErrorBadRequest)?;
+  if wants_cookie_session(&req) {
+    let mut builder = HttpResponse::Ok();
+    add_session_cookies(&mut builder, &res.jwt).map_err(ErrorBadRequest)?;
+    res.jwt = String::new();
+    return Ok(builder.json(res));
+  }
+  Ok(HttpResponse::Ok().json(res))
+}
+
+/// Ends a cookie session, see `routes::session`. The sign in of its token is revoked too, unless
+/// the site is read only, so that the token stops working wherever it was copied to.
+async fn logout(req: HttpRequest, context: web::Data<LemmyContext>) -> Result<HttpResponse, Error> {
+  if let Some(jwt) = session_cookie_jwt(&req) {
+    let claims = Claims::decode(&jwt).map_err(ErrorBadRequest)?.claims;
+    let read_only = Settings::get().maintenance.read_only;
+    if let Some(session_id) = claims.session_id.filter(|_| !read_only) {
+      blocking(context.pool(), move |conn| {
+        SignInSignal::review(conn, session_id, claims.id, true)
+      })
+      .await
+      .map_err(ErrorBadRequest)?
+      .map_err(ErrorBadRequest)?;
+    }
+  }
+  let mut builder = HttpResponse::Ok();
+  remove_session_cookies(&mut builder);
+  Ok(builder.finish())
+}
+
+/// Needs the rate limiter and the IP, so it isn't an api operation.
+async fn get_rate_limit_allowances(
+  req: HttpRequest,
+  rate_limit: web::Data<RateLimit>,
+) -> Result<HttpResponse, Error> {
+  let ip_addr = get_ip(&req.connection_info());
+  let allowances = rate_limit.allowances(&ip_addr).await;
+  Ok(HttpResponse::Ok().json(GetRateLimitAllowancesResponse { allowances }))
+}
+
+/// Counts the view once the post was found, see `post_views`.
+async fn get_post(
+  req: HttpRequest,
+  data: web::Query<GetPost>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let viewed = Viewed::Post(data.id);
+  let auth = data.auth.to_owned();
+  let pool = context.pool().to_owned();
+  let ip = get_ip(&req.connection_info());
+  let res = perform::<GetPost>(req, data.0, context).await?;
+  count_view(viewed, auth.as_deref(), &ip, &pool).await;
+  Ok(res)
+}
+
+/// Counts the view of the comment once it was found, like `get_post`.
+async fn get_comment_context(
+  req: HttpRequest,
+  data: web::Query<GetCommentContext>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let viewed = Viewed::Comment(data.comment_id);
+  let auth = data.auth.to_owned();
+  let pool = context.pool().to_owned();
+  let ip = get_ip(&req.connection_info());
+  let res = perform::<GetCommentContext>(req, data.0, context).await?;
+  count_view(viewed, auth.as_deref(), &ip, &pool).await;
   Ok(res)
 }
 
+/// Anonymous listings are the same for everyone, so they are cached for a short while.
+async fn get_posts(
+  req: HttpRequest,
+  data: web::Query<GetPosts>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  if data.auth.is_some() {
+    return perform::<GetPosts>(req, data.0, context).await;
+  }
+
+  // The compact header isn't in the uri
+  let compact = wants_compact(&req);
+  let cache_key = if compact {
+    format!("{}#compact", req.uri())
+  } else {
+    req.uri().to_string()
+  };
+  if let Some(res) = context.response_cache().get(&cache_key) {
+    return Ok(res);
+  }
+
+  let filters_community = data.community_id.is_some() || data.community_name.is_some();
+  let res = data
+    .0
+    .perform(&context, None)
+    .await
+    .map_err(ErrorBadRequest)?;
+  // Listings of a single community are dropped from the cache when something changes in it
+  let community_id = if filters_community {
+    res.posts.first().map(|p| p.community_id)
+  } else {
+    None
+  };
+  let body = if compact {
+    serde_json::to_string(&compact_response(serde_json::to_value(&res)?))?
+  } else {
+    serde_json::to_string(&res)?
+  };
+  context
+    .response_cache()
+    .insert(cache_key, body.to_owned(), "application/json", community_id);
+  Ok(
+    HttpResponse::Ok()
+      .content_type("application/json")
+      .body(body),
+  )
+}
+
 async fn route_get<'a, Data>(
+  req: HttpRequest,
   data: web::Query<Data>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, Error>
 where
   Data: Deserialize<'a> + Send + 'static + Perform,
 {
-  perform::<Data>(data.0, context).await
+  perform::<Data>(req, data.0, context).await
 }
 
+/// Posts write to the database, so they are refused while the site is read only.
 async fn route_post<'a, Data>(
+  req: HttpRequest,
+  data: web::Json<Data>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error>
+where
+  Data: Deserialize<'a> + Send + 'static + Perform,
+{
+  check_not_read_only()?;
+  perform::<Data>(req, data.0, context).await
+}
+
+/// For the few posts which keep working while the site is read only, see
+/// `UserOperation::allowed_when_read_only`.
+async fn route_post_when_read_only<'a, Data>(
+  req: HttpRequest,
   data: web::Json<Data>,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, Error>
 where
   Data: Deserialize<'a> + Send + 'static + Perform,
 {
-  perform::<Data>(data.0, context).await
+  perform::<Data>(req, data.0, context).await
 }
@@ -0,0 +1,69 @@
+//! Stable urls for the branding of the instance, so that pages and other sites can link to the
+//! logo or theme without knowing where the current one is stored.
+use crate::{api::site::read_site_branding, LemmyContext};
+use actix_web::{
+  error::ErrorBadRequest,
+  http::header::{HeaderValue, CACHE_CONTROL, LOCATION},
+  *,
+};
+use lemmy_api_structs::{blocking, site::SiteBrandingResponse};
+
+/// Admins can change the branding any time, so the redirects are only cached for a few minutes.
+const BRANDING_CACHE_CONTROL: &str = "public, max-age=300";
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg
+    .route("/branding/logo", web::get().to(get_logo))
+    .route("/branding/banner", web::get().to(get_banner))
+    .route("/branding/favicon", web::get().to(get_favicon))
+    .route("/branding/theme.css", web::get().to(get_theme_css));
+}
+
+async fn get_logo(context: web::Data<LemmyContext>) -> Result<HttpResponse, Error> {
+  Ok(redirect(read_branding(&context).await?.logo))
+}
+
+async fn get_banner(context: web::Data<LemmyContext>) -> Result<HttpResponse, Error> {
+  Ok(redirect(read_branding(&context).await?.banner))
+}
+
+async fn get_favicon(context: web::Data<LemmyContext>) -> Result<HttpResponse, Error> {
+  Ok(redirect(read_branding(&context).await?.favicon))
+}
+
+/// The colors as css variables, which themes can use. Colors that aren't set are left out, so that
+/// the theme's own values apply.
+async fn get_theme_css(context: web::Data<LemmyContext>) -> Result<HttpResponse, Error> {
+  let branding = read_branding(&context).await?;
+  let colors = [
+    ("--branding-primary", branding.primary_color),
+    ("--branding-secondary", branding.secondary_color),
+    ("--branding-background", branding.background_color),
+    ("--branding-text", branding.text_color),
+  ];
+  let variables = colors
+    .iter()
+    .filter_map(|(name, color)| color.as_ref().map(|c| format!("  {}: {};\n", name, c)))
+    .collect::<String>();
+  Ok(
+    HttpResponse::Ok()
+      .content_type("text/css")
+      .body(format!(":root {{\n{}}}\n", variables)),
+  )
+}
+
+async fn read_branding(context: &LemmyContext) -> Result<SiteBrandingResponse, Error> {
+  blocking(context.pool(), move |conn| read_site_branding(conn))
+    .await?
+    .map_err(ErrorBadRequest)
+}
+
+fn redirect(url: Option<String>) -> HttpResponse {
+  match url.and_then(|u| HeaderValue::from_str(&u).ok()) {
+    Some(url) => HttpResponse::Found()
+      .header(LOCATION, url)
+      .header(CACHE_CONTROL, BRANDING_CACHE_CONTROL)
+      .finish(),
+    None => HttpResponse::NotFound().finish(),
+  }
+}
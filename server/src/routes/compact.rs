@@ -0,0 +1,184 @@
+//! The compact mode of the http api, for clients on slow or metered connections. It is asked for
+//! with `?compact=true`, or with the `X-Lemmy-Compact: true` header, and changes responses so that
+//!
+//! - posts and other items in lists have no `body` or `embed_html`, and their `embed_description`
+//!   is cut to a preview,
+//! - the `creator_*` and `community_*` fields of items in lists are only sent once per creator and
+//!   community, in the `referenced_creators` and `referenced_communities` maps by id, with the
+//!   prefix taken off. Fields which depend on the item, like `creator_banned_from_community`,
+//!   stay on it.
+//!
+//! Single items, like the post of GetPost, stay as they are.
+use actix_web::{web, HttpRequest};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+pub const COMPACT_HEADER: &str = "X-Lemmy-Compact";
+
+/// In chars, not counting the ellipsis.
+const PREVIEW_LENGTH: usize = 200;
+
+/// Fields with a reference prefix which depend on the item, not only on the creator or community,
+/// like whether the creator is banned from the community of the item. They stay on the item.
+const ITEM_FIELDS: [&str; 1] = ["creator_banned_from_community"];
+
+#[derive(Deserialize)]
+struct CompactQuery {
+  compact: Option<bool>,
+}
+
+pub fn wants_compact(req: &HttpRequest) -> bool {
+  let header = req
+    .headers()
+    .get(COMPACT_HEADER)
+    .and_then(|h| h.to_str().ok())
+    .map_or(false, |h| h == "true" || h == "1");
+  let query = web::Query::<CompactQuery>::from_query(req.query_string())
+    .ok()
+    .and_then(|q| q.compact)
+    .unwrap_or(false);
+  header || query
+}
+
+pub fn compact_response(mut response: Value) -> Value {
+  let mut creators = Map::new();
+  let mut communities = Map::new();
+  if let Value::Object(fields) = &mut response {
+    for field in fields.values_mut() {
+      if let Value::Array(items) = field {
+        for item in items.iter_mut() {
+          if let Value::Object(item) = item {
+            compact_list_item(item, &mut creators, &mut communities);
+          }
+        }
+      }
+    }
+    if !creators.is_empty() {
+      fields.insert("referenced_creators".into(), Value::Object(creators));
+    }
+    if !communities.is_empty() {
+      fields.insert("referenced_communities".into(), Value::Object(communities));
+    }
+  }
+  response
+}
+
+fn compact_list_item(
+  item: &mut Map<String, Value>,
+  creators: &mut Map<String, Value>,
+  communities: &mut Map<String, Value>,
+) {
+  item.remove("body");
+  item.remove("embed_html");
+  if let Some(Value::String(description)) = item.get_mut("embed_description") {
+    truncate_preview(description);
+  }
+  move_to_references(item, "creator_", creators);
+  move_to_references(item, "community_", communities);
+}
+
+/// Moves the fields with the prefix, like `creator_name`, to the reference with the id in the
+/// `creator_id` field.
+fn move_to_references(
+  item: &mut Map<String, Value>,
+  prefix: &str,
+  references: &mut Map<String, Value>,
+) {
+  let id_field = format!("{}id", prefix);
+  let id = match item.get(&id_field) {
+    Some(Value::Number(id)) => id.to_string(),
+    _ => return,
+  };
+  let fields = item
+    .keys()
+    .filter(|k| k.starts_with(prefix) && **k != id_field && !ITEM_FIELDS.contains(&k.as_str()))
+    .cloned()
+    .collect::<Vec<String>>();
+  if fields.is_empty() {
+    return;
+  }
+
+  let mut reference = Map::new();
+  for field in fields {
+    if let Some(value) = item.remove(&field) {
+      reference.insert(field[prefix.len()..].to_owned(), value);
+    }
+  }
+  references.entry(id).or_insert(Value::Object(reference));
+}
+
+fn truncate_preview(text: &mut String) {
+  if let Some((end, _)) = text.char_indices().nth(PREVIEW_LENGTH) {
+    text.truncate(end);
+    text.push('…');
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::compact::compact_response;
+  use serde_json::json;
+
+  #[test]
+  fn test_compact_response() {
+    let response = json!({
+      "posts": [
+        {
+          "id": 1,
+          "name": "First",
+          "body": "A long body",
+          "embed_description": "x".repeat(300),
+          "creator_id": 5,
+          "creator_name": "alice",
+          "creator_banned_from_community": true,
+          "banned_from_community": true,
+          "community_id": 2,
+          "community_name": "main"
+        },
+        {
+          "id": 2,
+          "name": "Second",
+          "body": null,
+          "creator_id": 5,
+          "creator_name": "alice",
+          "creator_banned_from_community": false,
+          "banned_from_community": false,
+          "community_id": 3,
+          "community_name": "other"
+        }
+      ],
+      "community": { "id": 2, "name": "main", "description": "Stays" }
+    });
+
+    let compact = compact_response(response);
+    assert_eq!(
+      json!({
+        "id": 2,
+        "name": "Second",
+        "creator_id": 5,
+        "creator_banned_from_community": false,
+        "banned_from_community": false,
+        "community_id": 3
+      }),
+      compact["posts"][1]
+    );
+    assert_eq!(true, compact["posts"][0]["creator_banned_from_community"]);
+    assert_eq!(
+      201,
+      compact["posts"][0]["embed_description"]
+        .as_str()
+        .unwrap()
+        .chars()
+        .count()
+    );
+    assert_eq!(
+      json!({ "5": { "name": "alice" } }),
+      compact["referenced_creators"]
+    );
+    assert_eq!(
+      json!({ "2": { "name": "main" }, "3": { "name": "other" } }),
+      compact["referenced_communities"]
+    );
+    assert_eq!("Stays", compact["community"]["description"]);
+  }
+}
@@ -0,0 +1,266 @@
+//! Where browsers report violations of the content security policy of the front end. Admins who
+//! point the `report-uri` or `report-to` of their policy here can list the reports with
+//! ListCspReports, to see what their policy blocks before enforcing it.
+use crate::LemmyContext;
+use actix_web::{error::ErrorBadRequest, *};
+use lemmy_api_structs::blocking;
+use lemmy_db::csp_report::{CspReport, CspReportForm};
+use lemmy_rate_limit::RateLimit;
+use lemmy_utils::{settings::Settings, utils::truncate_to_length};
+use serde_json::Value;
+use url::Url;
+
+/// In bytes. Larger bodies are refused.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// The reporting api can send several reports at once, the others are dropped.
+const MAX_REPORTS_PER_REQUEST: usize = 10;
+/// In characters, longer fields are cut.
+const MAX_FIELD_LENGTH: usize = 500;
+/// Only the newest reports are kept.
+const MAX_CSP_REPORTS: i32 = 10_000;
+
+pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
+  cfg.service(
+    web::resource("/api/csp-report")
+      .wrap(rate_limit.message())
+      .app_data(web::PayloadConfig::new(MAX_BODY_SIZE))
+      .route(web::post().to(receive_csp_report)),
+  );
+}
+
+/// Accepted even if there's nothing to keep, so that browsers don't send it again. Nothing is kept
+/// while the site is read only.
+async fn receive_csp_report(
+  body: web::Bytes,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let reports = parse_csp_reports(&body);
+  if reports.is_empty() || Settings::get().maintenance.read_only {
+    return Ok(HttpResponse::NoContent().finish());
+  }
+
+  blocking(context.pool(), move |conn| {
+    for report in &reports {
+      CspReport::create(conn, report)?;
+    }
+    CspReport::delete_all_but_newest(conn, MAX_CSP_REPORTS)
+  })
+  .await?
+  .map_err(ErrorBadRequest)?;
+  Ok(HttpResponse::NoContent().finish())
+}
+
+/// Reports of `report-uri` come one at a time, with the `application/csp-report` content type,
+/// and those of `report-to` in lists with reports of other kinds, as `application/reports+json`.
+/// Both are read whatever the content type says.
+fn parse_csp_reports(body: &[u8]) -> Vec<CspReportForm> {
+  let value: Value = match serde_json::from_slice(body) {
+    Ok(value) => value,
+    Err(_) => return Vec::new(),
+  };
+  let reports: Vec<Value> = match value {
+    Value::Array(reports) => reports
+      .into_iter()
+      .filter(|r| r.get("type").and_then(Value::as_str) == Some("csp-violation"))
+      .filter_map(|r| r.get("body").cloned())
+      .collect(),
+    report => report.get("csp-report").cloned().into_iter().collect(),
+  };
+  reports
+    .iter()
+    .take(MAX_REPORTS_PER_REQUEST)
+    .filter_map(csp_report_form)
+    .collect()
+}
+
+/// The fields have dashes in `report-uri` reports, and are camel case in `report-to` ones.
+fn csp_report_form(report: &Value) -> Option<CspReportForm> {
+  let field = |dashed: &str, camel_case: &str| {
+    report
+      .get(dashed)
+      .or_else(|| report.get(camel_case))
+      .and_then(Value::as_str)
+      .filter(|f| !f.is_empty())
+      .map(|f| truncate_to_length(f, MAX_FIELD_LENGTH))
+  };
+  // The violated directive of older browsers has the sources of the policy after the name
+  let violated_directive = field("effective-directive", "effectiveDirective")
+    .or_else(|| field("violated-directive", "violatedDirective"))?
+    .split_whitespace()
+    .next()?
+    .to_owned();
+  let line_number = report
+    .get("line-number")
+    .or_else(|| report.get("lineNumber"))
+    .and_then(Value::as_i64)
+    .map(|l| l as i32);
+
+  Some(CspReportForm {
+    document_uri: without_query(&field("document-uri", "documentURL")?),
+    violated_directive,
+    blocked_uri: field("blocked-uri", "blockedURL").map(|u| without_query(&u)),
+    source_file: field("source-file", "sourceFile").map(|u| without_query(&u)),
+    line_number,
+    disposition: field("disposition", "disposition"),
+  })
+}
+
+/// Queries and fragments can have tokens in them. Blocked uris can also be keywords like `inline`,
+/// which are kept as they are.
+fn without_query(uri: &str) -> String {
+  match Url::parse(uri) {
+    Ok(mut url) => {
+      url.set_query(None);
+      url.set_fragment(None);
+      url.to_string()
+    }
+    Err(_) => uri.to_owned(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::csp_report::{parse_csp_reports, MAX_FIELD_LENGTH, MAX_REPORTS_PER_REQUEST};
+  use lemmy_db::csp_report::CspReportForm;
+  use serde_json::json;
+
+  #[test]
+  fn test_parse_report_uri_reports() {
+    let body = json!({
+      "csp-report": {
+        "document-uri": "https://lemmy.example/post/1?token=abc",
+        "violated-directive": "script-src 'self'",
+        "effective-directive": "script-src-elem",
+        "blocked-uri": "inline",
+        "source-file": "https://lemmy.example/static/app.js#main",
+        "line-number": 12,
+        "disposition": "report"
+      }
+    });
+    let expected = CspReportForm {
+      document_uri: "https://lemmy.example/post/1".into(),
+      violated_directive: "script-src-elem".into(),
+      blocked_uri: Some("inline".into()),
+      source_file: Some("https://lemmy.example/static/app.js".into()),
+      line_number: Some(12),
+      disposition: Some("report".into()),
+    };
+    assert_eq!(
+      vec![expected],
+      parse_csp_reports(body.to_string().as_bytes())
+    );
+
+    // Older browsers only send the violated directive, with the sources of the policy
+    let body = json!({
+      "csp-report": {
+        "document-uri": "https://lemmy.example/",
+        "violated-directive": "img-src 'self' data:"
+      }
+    });
+    let expected = CspReportForm {
+      document_uri: "https://lemmy.example/".into(),
+      violated_directive: "img-src".into(),
+      blocked_uri: None,
+      source_file: None,
+      line_number: None,
+      disposition: None,
+    };
+    assert_eq!(
+      vec![expected],
+      parse_csp_reports(body.to_string().as_bytes())
+    );
+  }
+
+  #[test]
+  fn test_parse_report_to_reports() {
+    let body = json!([
+      {
+        "type": "csp-violation",
+        "age": 10,
+        "url": "https://lemmy.example/?q=secret",
+        "body": {
+          "documentURL": "https://lemmy.example/?q=secret",
+          "effectiveDirective": "img-src",
+          "blockedURL": "https://tracker.example/pixel.gif?id=1",
+          "lineNumber": 3,
+          "disposition": "enforce"
+        }
+      },
+      {
+        "type": "deprecation",
+        "body": {
+          "documentURL": "https://lemmy.example/",
+          "effectiveDirective": "img-src"
+        }
+      }
+    ]);
+    let expected = CspReportForm {
+      document_uri: "https://lemmy.example/".into(),
+      violated_directive: "img-src".into(),
+      blocked_uri: Some("https://tracker.example/pixel.gif".into()),
+      source_file: None,
+      line_number: Some(3),
+      disposition: Some("enforce".into()),
+    };
+    assert_eq!(
+      vec![expected],
+      parse_csp_reports(body.to_string().as_bytes())
+    );
+
+    let report = json!({
+      "type": "csp-violation",
+      "body": {
+        "documentURL": "https://lemmy.example/",
+        "effectiveDirective": "img-src"
+      }
+    });
+    let body = json!(vec![report; MAX_REPORTS_PER_REQUEST + 5]);
+    assert_eq!(
+      MAX_REPORTS_PER_REQUEST,
+      parse_csp_reports(body.to_string().as_bytes()).len()
+    );
+  }
+
+  #[test]
+  fn test_parse_malformed_reports() {
+    let malformed = vec![
+      "".to_owned(),
+      "not json".to_owned(),
+      "{\"csp-report\": {\"document-uri\": ".to_owned(),
+      json!({}).to_string(),
+      json!([]).to_string(),
+      json!(null).to_string(),
+      json!("csp-report").to_string(),
+      json!({ "csp-report": "script-src" }).to_string(),
+      json!({ "csp-report": { "violated-directive": "script-src" } }).to_string(),
+      json!({ "csp-report": { "document-uri": "https://lemmy.example/" } }).to_string(),
+      json!({
+        "csp-report": { "document-uri": "https://lemmy.example/", "violated-directive": " " }
+      })
+      .to_string(),
+      json!({
+        "csp-report": { "document-uri": 1, "violated-directive": "script-src" }
+      })
+      .to_string(),
+      json!([{ "type": "csp-violation" }]).to_string(),
+      json!([{ "type": "csp-violation", "body": "img-src" }]).to_string(),
+      json!([{
+        "body": { "documentURL": "https://lemmy.example/", "effectiveDirective": "img-src" }
+      }])
+      .to_string(),
+    ];
+    for body in malformed {
+      assert!(parse_csp_reports(body.as_bytes()).is_empty(), "{}", body);
+    }
+
+    // Long fields are cut instead
+    let body = json!({
+      "csp-report": {
+        "document-uri": "a".repeat(MAX_FIELD_LENGTH * 2),
+        "violated-directive": "script-src"
+      }
+    });
+    let reports = parse_csp_reports(body.to_string().as_bytes());
+    assert_eq!(MAX_FIELD_LENGTH, reports[0].document_uri.chars().count());
+  }
+}
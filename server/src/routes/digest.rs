@@ -0,0 +1,52 @@
+//! The unsubscribe links of the weekly community digests, which work without logging in.
+use crate::LemmyContext;
+use actix_web::{error::ErrorBadRequest, *};
+use lemmy_api_structs::blocking;
+use lemmy_db::community_digest::CommunityDigestSubscription;
+use lemmy_utils::{email::check_digest_unsubscribe, utils::check_not_read_only};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg.service(
+    web::resource("/digest/unsubscribe/{user_id}/{community_id}/{signature}")
+      .route(web::get().to(confirm_unsubscribe))
+      .route(web::post().to(unsubscribe)),
+  );
+}
+
+/// Mail scanners follow the links of emails, so opening the link only asks to confirm.
+async fn confirm_unsubscribe(info: web::Path<(i32, i32, String)>) -> HttpResponse {
+  let (user_id, community_id, signature) = info.into_inner();
+  if !check_digest_unsubscribe(user_id, community_id, &signature) {
+    return HttpResponse::NotFound().finish();
+  }
+  HttpResponse::Ok()
+    .content_type("text/html; charset=utf-8")
+    .body(
+      "<!DOCTYPE html><html><body><form method=\"post\">\
+       <p>Stop getting the weekly digest of this community?</p>\
+       <button type=\"submit\">Unsubscribe</button></form></body></html>",
+    )
+}
+
+async fn unsubscribe(
+  info: web::Path<(i32, i32, String)>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let (user_id, community_id, signature) = info.into_inner();
+  if !check_digest_unsubscribe(user_id, community_id, &signature) {
+    return Ok(HttpResponse::NotFound().finish());
+  }
+  check_not_read_only()?;
+
+  blocking(context.pool(), move |conn| {
+    CommunityDigestSubscription::unsubscribe(conn, community_id, user_id)
+  })
+  .await?
+  .map_err(ErrorBadRequest)?;
+
+  Ok(
+    HttpResponse::Ok()
+      .content_type("text/plain; charset=utf-8")
+      .body("You won't get the digest of this community anymore."),
+  )
+}
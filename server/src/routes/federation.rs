@@ -1,7 +1,9 @@
 use crate::apub::{
+  activities::get_apub_activity,
   comment::get_apub_comment,
   community::*,
   inbox::{community_inbox::community_inbox, shared_inbox::shared_inbox, user_inbox::user_inbox},
+  instance::get_apub_instance_actor,
   post::get_apub_post,
   user::*,
   APUB_JSON_CONTENT_TYPE,
@@ -20,6 +22,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
       .service(
         web::scope("/")
           .guard(guard::Header("Accept", APUB_JSON_CONTENT_TYPE))
+          .route("/actor", web::get().to(get_apub_instance_actor))
           .route(
             "/c/{community_name}",
             web::get().to(get_apub_community_http),
@@ -28,13 +31,27 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             "/c/{community_name}/followers",
             web::get().to(get_apub_community_followers),
           )
+          .route(
+            "/c/{community_name}/moderators",
+            web::get().to(get_apub_community_moderators),
+          )
           .route(
             "/c/{community_name}/outbox",
             web::get().to(get_apub_community_outbox),
           )
           .route("/u/{user_name}", web::get().to(get_apub_user_http))
+          .route("/u/{user_name}/outbox", web::get().to(get_apub_user_outbox))
           .route("/post/{post_id}", web::get().to(get_apub_post))
-          .route("/comment/{comment_id}", web::get().to(get_apub_comment)),
+          .route("/comment/{comment_id}", web::get().to(get_apub_comment))
+          .route(
+            "/activities/{activity_id}",
+            web::get().to(get_apub_activity),
+          )
+          // Activity ids of older versions
+          .route(
+            "/activities/{kind}/{activity_id}",
+            web::get().to(get_apub_activity),
+          ),
       )
       // Inboxes dont work with the header guard for some reason.
       .service(
@@ -1,4 +1,4 @@
-use crate::{api::claims::Claims, LemmyContext};
+use crate::{api::claims::Claims, routes::oembed::escape_html, LemmyContext};
 use actix_web::{error::ErrorBadRequest, *};
 use anyhow::anyhow;
 use chrono::{DateTime, NaiveDateTime, Utc};
@@ -7,10 +7,13 @@ use lemmy_api_structs::blocking;
 use lemmy_db::{
   comment_view::{ReplyQueryBuilder, ReplyView},
   community::Community,
+  post_media::PostMedia,
   post_view::{PostQueryBuilder, PostView},
+  sign_in_signal::SignInSignal,
   site_view::SiteView,
   user::User_,
   user_mention_view::{UserMentionQueryBuilder, UserMentionView},
+  Crud,
   ListingType,
   SortType,
 };
@@ -65,7 +68,7 @@ fn get_feed_all_data(conn: &PgConnection, sort_type: &SortType) -> Result<String
     .sort(sort_type)
     .list()?;
 
-  let items = create_post_items(posts)?;
+  let items = create_post_items(conn, posts)?;
 
   let mut channel_builder = ChannelBuilder::default();
   channel_builder
@@ -136,7 +139,7 @@ fn get_feed_user(
     .for_creator_id(user.id)
     .list()?;
 
-  let items = create_post_items(posts)?;
+  let items = create_post_items(conn, posts)?;
 
   let mut channel_builder = ChannelBuilder::default();
   channel_builder
@@ -161,7 +164,7 @@ fn get_feed_community(
     .for_community_id(community.id)
     .list()?;
 
-  let items = create_post_items(posts)?;
+  let items = create_post_items(conn, posts)?;
 
   let mut channel_builder = ChannelBuilder::default();
   channel_builder
@@ -176,13 +179,30 @@ fn get_feed_community(
   Ok(channel_builder)
 }
 
+/// Feeds aren't counted in the impersonation log, so impersonation tokens don't work for them.
+/// Neither do revoked tokens.
+fn feed_user_id(conn: &PgConnection, jwt: &str) -> Result<i32, LemmyError> {
+  let claims = Claims::decode(jwt)?.claims;
+  if claims.impersonation_id.is_some() {
+    return Err(anyhow!("Impersonation tokens can't be used for feeds").into());
+  }
+  let revoked = match claims.session_id {
+    Some(session_id) => SignInSignal::is_revoked(conn, session_id)?,
+    None => User_::read(conn, claims.id)?.sessionless_tokens_revoked,
+  };
+  if revoked {
+    return Err(anyhow!("Revoked tokens can't be used for feeds").into());
+  }
+  Ok(claims.id)
+}
+
 fn get_feed_front(
   conn: &PgConnection,
   sort_type: &SortType,
   jwt: String,
 ) -> Result<ChannelBuilder, LemmyError> {
   let site_view = SiteView::read(&conn)?;
-  let user_id = Claims::decode(&jwt)?.claims.id;
+  let user_id = feed_user_id(&conn, &jwt)?;
 
   let posts = PostQueryBuilder::create(&conn)
     .listing_type(ListingType::Subscribed)
@@ -190,7 +210,7 @@ fn get_feed_front(
     .my_user_id(user_id)
     .list()?;
 
-  let items = create_post_items(posts)?;
+  let items = create_post_items(conn, posts)?;
 
   let mut channel_builder = ChannelBuilder::default();
   channel_builder
@@ -207,7 +227,7 @@ fn get_feed_front(
 
 fn get_feed_inbox(conn: &PgConnection, jwt: String) -> Result<ChannelBuilder, LemmyError> {
   let site_view = SiteView::read(&conn)?;
-  let user_id = Claims::decode(&jwt)?.claims.id;
+  let user_id = feed_user_id(&conn, &jwt)?;
 
   let sort = SortType::New;
 
@@ -297,8 +317,10 @@ fn build_item(
   Ok(i.build().map_err(|e| anyhow!(e))?)
 }
 
-fn create_post_items(posts: Vec<PostView>) -> Result<Vec<Item>, LemmyError> {
+fn create_post_items(conn: &PgConnection, posts: Vec<PostView>) -> Result<Vec<Item>, LemmyError> {
   let mut items: Vec<Item> = Vec::new();
+  let post_ids = posts.iter().map(|p| p.id).collect::<Vec<i32>>();
+  let media = PostMedia::list_for_posts(conn, &post_ids)?;
 
   for p in posts {
     let mut i = ItemBuilder::default();
@@ -344,7 +366,6 @@ fn create_post_items(posts: Vec<PostView>) -> Result<Vec<Item>, LemmyError> {
       i.link(url);
     }
 
-    // TODO add images
     let mut description = format!("submitted by <a href=\"{}\">{}</a> to <a href=\"{}\">{}</a><br>{} points | <a href=\"{}\">{} comments</a>",
     author_url,
     p.creator_name,
@@ -359,6 +380,19 @@ fn create_post_items(posts: Vec<PostView>) -> Result<Vec<Item>, LemmyError> {
       description.push_str(&html);
     }
 
+    let post_id = p.id;
+    for m in media.iter().filter(|m| m.post_id == post_id) {
+      description.push_str(&format!(
+        "<p><img src=\"{}\" alt=\"{}\">",
+        escape_html(&m.url),
+        escape_html(m.alt_text.as_deref().unwrap_or(""))
+      ));
+      if let Some(caption) = &m.caption {
+        description.push_str(&format!("<br>{}", escape_html(caption)));
+      }
+      description.push_str("</p>");
+    }
+
     i.description(description);
 
     items.push(i.build().map_err(|e| anyhow!(e))?);
@@ -2,7 +2,7 @@ use actix::clock::Duration;
 use actix_web::{body::BodyStream, http::StatusCode, *};
 use awc::Client;
 use lemmy_rate_limit::RateLimit;
-use lemmy_utils::settings::Settings;
+use lemmy_utils::{settings::Settings, utils::check_not_read_only};
 use serde::{Deserialize, Serialize};
 
 pub fn config(cfg: &mut web::ServiceConfig, rate_limit: &RateLimit) {
@@ -43,6 +43,7 @@ async fn upload(
   client: web::Data<Client>,
 ) -> Result<HttpResponse, Error> {
   // TODO: check auth and rate limit here
+  check_not_read_only()?;
 
   let mut res = client
     .request_from(format!("{}/image", Settings::get().pictrs_url), req.head())
@@ -0,0 +1,118 @@
+//! The hook for the mail server to pass on replies to notification emails, which become comments or
+//! private messages of the user the notification was sent to.
+use crate::{
+  api::{claims::Claims, Perform},
+  LemmyContext,
+};
+use actix_web::{error::ErrorBadRequest, *};
+use lemmy_api_structs::{blocking, comment::CreateComment, user::CreatePrivateMessage};
+use lemmy_db::{comment::Comment, user::User_, Crud};
+use lemmy_utils::{
+  email::{current_day, parse_reply_address, same_address, strip_quoted_reply, ReplyTarget},
+  settings::Settings,
+  utils::check_not_read_only,
+};
+use serde::Deserialize;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg.route("/api/v1/inbound_email", web::post().to(receive_email));
+}
+
+/// A reply, as the mail server passes it on.
+#[derive(Deserialize)]
+struct InboundEmail {
+  /// The reply address it was sent to
+  to: String,
+  from: String,
+  /// The plain text of the email, with the quoted notification below it
+  text: String,
+}
+
+async fn receive_email(
+  req: HttpRequest,
+  email: web::Json<InboundEmail>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let inbound_secret = match Settings::get().email.and_then(|e| e.inbound_secret) {
+    Some(secret) => secret,
+    None => return Ok(HttpResponse::NotFound().finish()),
+  };
+  let authorization = req
+    .headers()
+    .get("Authorization")
+    .and_then(|a| a.to_str().ok());
+  let expected = format!("Bearer {}", inbound_secret);
+  let authorized = authorization.map_or(false, |a| {
+    a.len() == expected.len() && openssl::memcmp::eq(a.as_bytes(), expected.as_bytes())
+  });
+  if !authorized {
+    return Ok(HttpResponse::Unauthorized().finish());
+  }
+  check_not_read_only()?;
+
+  let (target, user_id) = match parse_reply_address(&email.to, current_day()) {
+    Some(reply) => reply,
+    None => return Ok(HttpResponse::NotFound().finish()),
+  };
+  let user = blocking(context.pool(), move |conn| User_::read(conn, user_id))
+    .await?
+    .map_err(ErrorBadRequest)?;
+  // The signed address could have been forwarded, so it only works from the address of the user
+  let from_user = user
+    .email
+    .as_deref()
+    .map_or(false, |e| same_address(e, &email.from));
+  if !from_user {
+    return Ok(HttpResponse::Forbidden().finish());
+  }
+
+  let content = strip_quoted_reply(&email.text);
+  if content.is_empty() {
+    return Err(ErrorBadRequest("empty_reply"));
+  }
+  let auth = Claims::jwt(user, Settings::get().hostname).map_err(ErrorBadRequest)?;
+  match target {
+    ReplyTarget::Post(post_id) => {
+      let create_comment = CreateComment {
+        content,
+        parent_id: None,
+        post_id,
+        form_id: None,
+        auth,
+      };
+      create_comment
+        .perform(&context, None)
+        .await
+        .map_err(ErrorBadRequest)?;
+    }
+    ReplyTarget::Comment(comment_id) => {
+      let parent = blocking(context.pool(), move |conn| Comment::read(conn, comment_id))
+        .await?
+        .map_err(ErrorBadRequest)?;
+      let create_comment = CreateComment {
+        content,
+        parent_id: Some(parent.id),
+        post_id: parent.post_id,
+        form_id: None,
+        auth,
+      };
+      create_comment
+        .perform(&context, None)
+        .await
+        .map_err(ErrorBadRequest)?;
+    }
+    ReplyTarget::PrivateMessage(recipient_id) => {
+      let create_private_message = CreatePrivateMessage {
+        content,
+        recipient_id,
+        auth,
+      };
+      create_private_message
+        .perform(&context, None)
+        .await
+        .map_err(ErrorBadRequest)?;
+    }
+  }
+
+  Ok(HttpResponse::Ok().finish())
+}
@@ -1,6 +1,7 @@
+use crate::routes::oembed::oembed_discovery_link;
 use actix_files::NamedFile;
 use actix_web::*;
-use lemmy_utils::settings::Settings;
+use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
   cfg
@@ -16,7 +17,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     .route("/communities/page/{page}", web::get().to(index))
     .route("/communities", web::get().to(index))
     .route("/post/{id}/comment/{id2}", web::get().to(index))
-    .route("/post/{id}", web::get().to(index))
+    .route("/post/{id}", web::get().to(post_index))
     .route(
       "/c/{name}/data_type/{data_type}/sort/{sort}/page/{page}",
       web::get().to(index),
@@ -49,3 +50,28 @@ async fn index() -> Result<NamedFile, Error> {
     Settings::get().front_end_dir + "/index.html",
   )?)
 }
+
+/// Like index, but with the oEmbed discovery link of the post in the head, for sites that embed it.
+async fn post_index(req: HttpRequest) -> Result<HttpResponse, Error> {
+  let html = std::fs::read_to_string(Settings::get().front_end_dir + "/index.html")?;
+  let html = if Settings::get().oembed.enabled {
+    let page_url = format!(
+      "{}://{}{}",
+      get_apub_protocol_string(),
+      Settings::get().hostname,
+      req.path()
+    );
+    html.replacen(
+      "</head>",
+      &format!("{}</head>", oembed_discovery_link(&page_url)),
+      1,
+    )
+  } else {
+    html
+  };
+  Ok(
+    HttpResponse::Ok()
+      .content_type("text/html; charset=utf-8")
+      .body(html),
+  )
+}
@@ -1,8 +1,18 @@
 pub mod api;
+pub mod branding;
+pub mod compact;
+pub mod csp_report;
+pub mod digest;
 pub mod federation;
 pub mod feeds;
 pub mod images;
+pub mod inbound_email;
 pub mod index;
 pub mod nodeinfo;
+pub mod oembed;
+pub mod permalinks;
+pub mod session;
+pub mod sse;
 pub mod webfinger;
 pub mod websocket;
+pub mod widget;
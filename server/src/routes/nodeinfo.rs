@@ -1,14 +1,17 @@
-use crate::{version, LemmyContext};
+use crate::{apub::instance::instance_actor_id, version, LemmyContext};
 use actix_web::{body::Body, error::ErrorBadRequest, *};
 use anyhow::anyhow;
+use chrono::Duration;
 use lemmy_api_structs::blocking;
-use lemmy_db::site_view::SiteView;
+use lemmy_db::{naive_now, site_view::SiteView, user::User_, user_presence::UserPresence};
 use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings, LemmyError};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
+  // The .json path is the one older versions linked to
   cfg
+    .route("/nodeinfo/2.0", web::get().to(node_info))
     .route("/nodeinfo/2.0.json", web::get().to(node_info))
     .route("/.well-known/nodeinfo", web::get().to(node_info_well_known));
 }
@@ -18,7 +21,7 @@ async fn node_info_well_known() -> Result<HttpResponse<Body>, LemmyError> {
     links: NodeInfoWellKnownLinks {
       rel: Url::parse("http://nodeinfo.diaspora.software/ns/schema/2.0")?,
       href: Url::parse(&format!(
-        "{}://{}/nodeinfo/2.0.json",
+        "{}://{}/nodeinfo/2.0",
         get_apub_protocol_string(),
         Settings::get().hostname
       ))?,
@@ -28,11 +31,19 @@ async fn node_info_well_known() -> Result<HttpResponse<Body>, LemmyError> {
 }
 
 async fn node_info(context: web::Data<LemmyContext>) -> Result<HttpResponse, Error> {
-  let site_view = blocking(context.pool(), SiteView::read)
-    .await?
-    .map_err(|_| ErrorBadRequest(LemmyError::from(anyhow!("not_found"))))?;
+  let (site_view, total, active_month, active_halfyear) = blocking(context.pool(), |conn| {
+    Ok::<_, diesel::result::Error>((
+      SiteView::read(conn)?,
+      User_::count_local(conn)?,
+      UserPresence::count_active_since(conn, naive_now() - Duration::days(30))?,
+      UserPresence::count_active_since(conn, naive_now() - Duration::days(180))?,
+    ))
+  })
+  .await?
+  .map_err(|_| ErrorBadRequest(LemmyError::from(anyhow!("not_found"))))?;
 
-  let protocols = if Settings::get().federation.enabled {
+  let settings = Settings::get();
+  let protocols = if settings.federation.enabled {
     vec!["activitypub".to_string()]
   } else {
     vec![]
@@ -45,14 +56,31 @@ async fn node_info(context: web::Data<LemmyContext>) -> Result<HttpResponse, Err
       version: version::VERSION.to_string(),
     },
     protocols,
+    services: NodeInfoServices {
+      inbound: vec![],
+      outbound: vec![],
+    },
+    open_registrations: site_view.open_registration,
     usage: NodeInfoUsage {
       users: NodeInfoUsers {
-        total: site_view.number_of_users,
+        total,
+        active_month,
+        active_halfyear,
       },
       local_posts: site_view.number_of_posts,
       local_comments: site_view.number_of_comments,
       open_registrations: site_view.open_registration,
     },
+    metadata: NodeInfoMetadata {
+      node_name: site_view.name,
+      federation: NodeInfoFederation {
+        enabled: settings.federation.enabled,
+        mode: settings.get_federation_mode().as_str().to_string(),
+        require_signed_fetch: settings.federation.require_signed_fetch,
+        discoverable: settings.federation.discoverable,
+        instance_actor: instance_actor_id().map_err(ErrorBadRequest)?.to_string(),
+      },
+    },
   };
 
   Ok(HttpResponse::Ok().json(json))
@@ -70,11 +98,15 @@ pub struct NodeInfoWellKnownLinks {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct NodeInfo {
   pub version: String,
   pub software: NodeInfoSoftware,
   pub protocols: Vec<String>,
+  pub services: NodeInfoServices,
+  pub open_registrations: bool,
   pub usage: NodeInfoUsage,
+  pub metadata: NodeInfoMetadata,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -83,16 +115,44 @@ pub struct NodeInfoSoftware {
   pub version: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NodeInfoServices {
+  pub inbound: Vec<String>,
+  pub outbound: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeInfoUsage {
   pub users: NodeInfoUsers,
   pub local_posts: i64,
   pub local_comments: i64,
+  /// Older versions only had it here, instead of at the top
   pub open_registrations: bool,
 }
 
+/// Local users, and those active in the last 30 and 180 days.
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct NodeInfoUsers {
   pub total: i64,
+  pub active_month: i64,
+  pub active_halfyear: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfoMetadata {
+  pub node_name: String,
+  pub federation: NodeInfoFederation,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfoFederation {
+  pub enabled: bool,
+  pub mode: String,
+  pub require_signed_fetch: bool,
+  pub discoverable: bool,
+  pub instance_actor: String,
 }
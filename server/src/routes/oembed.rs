@@ -0,0 +1,248 @@
+//! oEmbed provider for posts, so that other sites can embed a post link as a snippet with its
+//! title, author, community, score and the start of its body. See https://oembed.com
+use crate::LemmyContext;
+use actix_web::{error::ErrorBadRequest, *};
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  community::CommunitySettings,
+  post_view::PostView,
+  site_view::SiteView,
+  user::User_,
+  Crud,
+};
+use lemmy_utils::{apub::get_apub_protocol_string, settings::Settings, LemmyError};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Embeds can change when the post is edited or voted on, consumers may keep them this long.
+const OEMBED_CACHE_AGE_SECONDS: i32 = 60 * 60;
+
+const DEFAULT_EMBED_WIDTH: i32 = 600;
+const DEFAULT_EMBED_HEIGHT: i32 = 200;
+
+#[derive(Deserialize)]
+pub struct OembedParams {
+  url: String,
+  format: Option<String>,
+  maxwidth: Option<i32>,
+  maxheight: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct OembedResponse {
+  #[serde(rename = "type")]
+  type_: &'static str,
+  version: &'static str,
+  title: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  author_name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  author_url: Option<String>,
+  provider_name: String,
+  provider_url: String,
+  cache_age: i32,
+  html: String,
+  width: i32,
+  height: i32,
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  if Settings::get().oembed.enabled {
+    cfg.route("/oembed", web::get().to(get_oembed));
+  }
+}
+
+/// The link to the oEmbed of a post page, which goes into its html so that other sites find it.
+pub fn oembed_discovery_link(page_url: &str) -> String {
+  let oembed_url = Url::parse_with_params(
+    &format!(
+      "{}://{}/oembed",
+      get_apub_protocol_string(),
+      Settings::get().hostname
+    ),
+    &[("url", page_url), ("format", "json")],
+  )
+  .map(|u| u.to_string())
+  .unwrap_or_default();
+  format!(
+    r#"<link rel="alternate" type="application/json+oembed" href="{}">"#,
+    escape_html(&oembed_url)
+  )
+}
+
+/// The embeds are the same for everyone, so they're cached like anonymous listings.
+async fn get_oembed(
+  req: HttpRequest,
+  info: web::Query<OembedParams>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  if info.format.as_deref().unwrap_or("json") != "json" {
+    return Ok(HttpResponse::NotImplemented().finish());
+  }
+  let post_id = match parse_post_id(&info.url) {
+    Some(id) => id,
+    None => return Ok(HttpResponse::NotFound().finish()),
+  };
+
+  let cache_key = req.uri().to_string();
+  if let Some(res) = context.response_cache().get(&cache_key) {
+    return Ok(res);
+  }
+
+  let info = info.into_inner();
+  let res = blocking(context.pool(), move |conn| {
+    build_oembed(conn, post_id, &info)
+  })
+  .await?
+  .map_err(ErrorBadRequest)?;
+  let (community_id, res) = match res {
+    Some(r) => r,
+    None => return Ok(HttpResponse::NotFound().finish()),
+  };
+
+  let body = serde_json::to_string(&res)?;
+  context.response_cache().insert(
+    cache_key,
+    body.to_owned(),
+    "application/json",
+    Some(community_id),
+  );
+  Ok(
+    HttpResponse::Ok()
+      .content_type("application/json")
+      .body(body),
+  )
+}
+
+/// Post urls of this instance, either the post page or its permalink.
+fn parse_post_id(url: &str) -> Option<i32> {
+  let url = Url::parse(url).ok()?;
+  if url.host_str()? != Settings::get().hostname {
+    return None;
+  }
+  let mut segments = url.path_segments()?;
+  let id = match (segments.next(), segments.next(), segments.next()) {
+    (Some("post"), Some(id), None) | (Some("p"), Some(id), None) => id,
+    _ => return None,
+  };
+  id.parse().ok()
+}
+
+/// Returns None for posts which aren't embedded, like the ones in private communities.
+fn build_oembed(
+  conn: &diesel::PgConnection,
+  post_id: i32,
+  params: &OembedParams,
+) -> Result<Option<(i32, OembedResponse)>, LemmyError> {
+  let post = match PostView::read(conn, post_id, None) {
+    Ok(p) => p,
+    Err(_) => return Ok(None),
+  };
+  let community_settings = CommunitySettings::read_for_community(conn, post.community_id)?;
+  let config = Settings::get().oembed;
+  if post.removed
+    || post.deleted
+    || post.community_removed
+    || post.community_deleted
+    || community_settings.private
+    || ((post.nsfw || post.community_nsfw) && !config.include_nsfw)
+  {
+    return Ok(None);
+  }
+
+  // Anonymous posts and users who don't want to be found aren't named. Views of anonymous posts
+  // have no creator to read.
+  let (author_name, author_url) =
+    if post.anonymous || !User_::read(conn, post.creator_id)?.discoverable {
+      (None, None)
+    } else {
+      (
+        Some(
+          post
+            .creator_preferred_username
+            .to_owned()
+            .unwrap_or_else(|| post.creator_name.to_owned()),
+        ),
+        Some(post.creator_actor_id.to_owned()),
+      )
+    };
+
+  let excerpt = post
+    .body
+    .as_deref()
+    .map(|b| b.chars().take(config.excerpt_length).collect::<String>())
+    .unwrap_or_default();
+  let score = if post.score_hidden {
+    String::new()
+  } else {
+    format!("{} points · ", post.score)
+  };
+  let author = match (&author_name, &author_url) {
+    (Some(name), Some(url)) => format!(
+      r#"by <a href="{}">{}</a> "#,
+      escape_html(url),
+      escape_html(name)
+    ),
+    _ => String::new(),
+  };
+  let html = format!(
+    r#"<blockquote class="lemmy-embed"><a href="{}">{}</a><p>{}</p><footer>{}{}in <a href="{}">{}</a></footer></blockquote>"#,
+    escape_html(&post.ap_id),
+    escape_html(&post.name),
+    escape_html(&excerpt),
+    score,
+    author,
+    escape_html(&post.community_actor_id),
+    escape_html(&post.community_name),
+  );
+
+  let provider_name = SiteView::read(conn)
+    .map(|s| s.name)
+    .unwrap_or_else(|_| Settings::get().hostname);
+  let res = OembedResponse {
+    type_: "rich",
+    version: "1.0",
+    title: post.name,
+    author_name,
+    author_url,
+    provider_name,
+    provider_url: format!(
+      "{}://{}",
+      get_apub_protocol_string(),
+      Settings::get().hostname
+    ),
+    cache_age: OEMBED_CACHE_AGE_SECONDS,
+    html,
+    width: params
+      .maxwidth
+      .map(|w| w.min(DEFAULT_EMBED_WIDTH))
+      .unwrap_or(DEFAULT_EMBED_WIDTH),
+    height: params
+      .maxheight
+      .map(|h| h.min(DEFAULT_EMBED_HEIGHT))
+      .unwrap_or(DEFAULT_EMBED_HEIGHT),
+  };
+  Ok(Some((post.community_id, res)))
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::oembed::escape_html;
+
+  #[test]
+  fn test_escape_html() {
+    assert_eq!(
+      "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;",
+      escape_html(r#"<a href="x">Tom & Jerry's</a>"#)
+    );
+  }
+}
@@ -0,0 +1,131 @@
+//! Short links for sharing posts and comments. They redirect to the canonical url, which is the
+//! page on the instance where the post or comment was created, so links to federated content lead
+//! to its origin.
+use crate::{
+  apub::comment::{comment_html_url, get_apub_comment, CommentQuery},
+  LemmyContext,
+};
+use actix_web::{
+  http::header::{ACCEPT, LOCATION},
+  *,
+};
+use lemmy_api_structs::blocking;
+use lemmy_db::{comment::Comment, post::Post, Crud};
+use lemmy_utils::{settings::Settings, LemmyError};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg
+    .route("/p/{post_id}", web::get().to(post_permalink))
+    // The id of a local comment, for people instead of for federation
+    .route("/comment/{comment_id}", web::get().to(comment_permalink));
+}
+
+async fn post_permalink(
+  post_id: web::Path<i32>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, LemmyError> {
+  let post_id = post_id.into_inner();
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await?;
+  Ok(match post {
+    Ok(post) => redirect(&post.ap_id),
+    Err(_) => HttpResponse::NotFound().finish(),
+  })
+}
+
+/// The comment url is also the ActivityPub id of local comments. The federation routes only take
+/// requests which accept nothing but ActivityPub json, so fetches which list more types end up
+/// here, and get the comment instead of the redirect.
+async fn comment_permalink(
+  request: HttpRequest,
+  info: web::Path<CommentQuery>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, LemmyError> {
+  let accept = request
+    .headers()
+    .get_all(ACCEPT)
+    .filter_map(|h| h.to_str().ok())
+    .collect::<Vec<&str>>()
+    .join(",");
+  if Settings::get().federation.enabled && accepts_activity_json(&accept) {
+    return get_apub_comment(request, info, context).await;
+  }
+
+  let comment_id = match info.comment_id.parse::<i32>() {
+    Ok(id) => id,
+    Err(_) => return Ok(HttpResponse::NotFound().finish()),
+  };
+  let comment = blocking(context.pool(), move |conn| Comment::read(conn, comment_id)).await?;
+  Ok(match comment {
+    Ok(comment) => redirect(comment_html_url(&comment)?.as_str()),
+    Err(_) => HttpResponse::NotFound().finish(),
+  })
+}
+
+/// Whether the Accept header lists ActivityPub json among its media types, as
+/// `application/activity+json`, or as `application/ld+json` without a profile or with the
+/// ActivityStreams one. Types with `q=0` are refused by the client, so they don't count.
+fn accepts_activity_json(accept: &str) -> bool {
+  accept.split(',').any(|media_range| {
+    let mut parts = media_range.split(';').map(str::trim);
+    let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let mut refused = false;
+    let mut profile = None;
+    for parameter in parts {
+      if let Some(i) = parameter.find('=') {
+        let name = parameter[..i].trim().to_ascii_lowercase();
+        let value = parameter[i + 1..].trim().trim_matches('"');
+        if name == "q" {
+          refused = value.parse::<f32>().map_or(false, |q| q == 0.0);
+        } else if name == "profile" {
+          profile = Some(value);
+        }
+      }
+    }
+
+    !refused
+      && match media_type.as_str() {
+        "application/activity+json" => true,
+        "application/ld+json" => profile.map_or(true, |p| {
+          p.split_whitespace()
+            .any(|p| p == "https://www.w3.org/ns/activitystreams")
+        }),
+        _ => false,
+      }
+  })
+}
+
+fn redirect(url: &str) -> HttpResponse {
+  HttpResponse::MovedPermanently()
+    .header(LOCATION, url)
+    .finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::permalinks::accepts_activity_json;
+
+  #[test]
+  fn test_accepts_activity_json() {
+    assert!(accepts_activity_json("application/activity+json"));
+    assert!(accepts_activity_json(
+      "application/activity+json, application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\""
+    ));
+    assert!(accepts_activity_json(
+      "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\", text/html;q=0.1"
+    ));
+    assert!(accepts_activity_json("Application/Activity+JSON; q=0.9"));
+    assert!(accepts_activity_json("application/ld+json"));
+
+    assert!(!accepts_activity_json(
+      "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+    ));
+    assert!(!accepts_activity_json("*/*"));
+    assert!(!accepts_activity_json(""));
+    assert!(!accepts_activity_json(
+      "application/activity+json;q=0, text/html"
+    ));
+    assert!(!accepts_activity_json(
+      "application/ld+json; profile=\"http://www.w3.org/ns/json-ld#compacted\""
+    ));
+  }
+}
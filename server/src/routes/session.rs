@@ -0,0 +1,315 @@
+//! Cookie sessions of the http api, for browser clients which don't want to keep their token where
+//! scripts can read it. They are asked for with the `X-Lemmy-Session: cookie` header when logging
+//! in or registering, and then
+//!
+//! - the token is in the HttpOnly `lemmy_session` cookie, and the `jwt` of the response is empty,
+//! - requests to the http api which have the cookie, but no `auth`, get its token as their `auth`,
+//! - requests other than GET which have the cookie need the `X-Lemmy-Csrf` header, with the value
+//!   of the `lemmy_csrf` cookie, which scripts of the site can read, but other sites can't.
+//!
+//! Other clients keep sending their token as `auth`, and the websocket always needs it.
+use actix_web::{
+  body::Body,
+  cookie::{Cookie, SameSite},
+  dev::{HttpResponseBuilder, Payload, Service, ServiceRequest, ServiceResponse},
+  error::PayloadError,
+  http::{header::CONTENT_LENGTH, uri::PathAndQuery, Method, Uri},
+  web::Bytes,
+  Error,
+  HttpMessage,
+  HttpRequest,
+  HttpResponse,
+};
+use futures::{
+  future::{ready, Either},
+  stream,
+  Future,
+  StreamExt,
+};
+use lemmy_utils::{settings::Settings, LemmyError};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde_json::Value;
+use url::form_urlencoded;
+
+pub const SESSION_HEADER: &str = "X-Lemmy-Session";
+pub const CSRF_HEADER: &str = "X-Lemmy-Csrf";
+/// Not `jwt`, which the ui sets itself for image uploads.
+const SESSION_COOKIE: &str = "lemmy_session";
+const CSRF_COOKIE: &str = "lemmy_csrf";
+/// In bytes, larger bodies are refused. The json extractor only takes much smaller ones anyway.
+const MAX_BODY_SIZE: usize = 1_000_000;
+
+pub fn wants_cookie_session(req: &HttpRequest) -> bool {
+  Settings::get().web_sessions.enabled
+    && req
+      .headers()
+      .get(SESSION_HEADER)
+      .and_then(|h| h.to_str().ok())
+      .map_or(false, |h| h == "cookie")
+}
+
+/// The csrf token for the session of the token. It is derived from the token, so that it doesn't
+/// have to be kept anywhere, and each sign in has its own.
+pub fn csrf_token(jwt: &str) -> Result<String, LemmyError> {
+  let key = PKey::hmac(Settings::get().jwt_secret.as_bytes())?;
+  let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+  signer.update(b"csrf:")?;
+  signer.update(jwt.as_bytes())?;
+  Ok(base64::encode_config(
+    signer.sign_to_vec()?,
+    base64::URL_SAFE_NO_PAD,
+  ))
+}
+
+fn session_cookie(name: &'static str, value: String, http_only: bool) -> Cookie<'static> {
+  Cookie::build(name, value)
+    .path("/")
+    .http_only(http_only)
+    .secure(Settings::get().web_sessions.secure_cookies)
+    .same_site(SameSite::Strict)
+    .finish()
+}
+
+pub fn add_session_cookies(res: &mut HttpResponseBuilder, jwt: &str) -> Result<(), LemmyError> {
+  res
+    .cookie(session_cookie(SESSION_COOKIE, jwt.to_owned(), true))
+    .cookie(session_cookie(CSRF_COOKIE, csrf_token(jwt)?, false));
+  Ok(())
+}
+
+pub fn remove_session_cookies(res: &mut HttpResponseBuilder) {
+  res
+    .del_cookie(&session_cookie(SESSION_COOKIE, String::new(), true))
+    .del_cookie(&session_cookie(CSRF_COOKIE, String::new(), false));
+}
+
+pub fn session_cookie_jwt<R: HttpMessage>(req: &R) -> Option<String> {
+  req
+    .cookie(SESSION_COOKIE)
+    .map(|c| c.value().to_owned())
+    .filter(|jwt| !jwt.is_empty())
+}
+
+/// The token in the session cookie of a request to the http api.
+fn session_jwt(req: &ServiceRequest) -> Option<String> {
+  let path = req.path();
+  if !path.starts_with("/api/v1/") || path.starts_with("/api/v1/ws") {
+    return None;
+  }
+  session_cookie_jwt(req)
+}
+
+/// Requests to the http api with a session cookie get its token as their auth. Those which may
+/// change something are refused without the csrf token.
+pub fn use_session_cookie<S>(
+  req: ServiceRequest,
+  srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+  S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = Error>,
+{
+  let jwt = if Settings::get().web_sessions.enabled {
+    session_jwt(&req)
+  } else {
+    None
+  };
+  apply_session_jwt(req, srv, jwt)
+}
+
+fn apply_session_jwt<S>(
+  mut req: ServiceRequest,
+  srv: &mut S,
+  jwt: Option<String>,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+  S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = Error>,
+{
+  let jwt = match jwt {
+    Some(jwt) => jwt,
+    None => return Either::Right(srv.call(req)),
+  };
+  if !needs_csrf_token(req.method()) {
+    if let Some(uri) = add_auth_to_query(req.uri(), &jwt) {
+      req.head_mut().uri = uri;
+    }
+    return Either::Right(srv.call(req));
+  }
+
+  if !is_csrf_token_valid(&req, &jwt) {
+    let res = HttpResponse::Forbidden()
+      .content_type("application/json")
+      .body(r#"{"error":"csrf_token_invalid"}"#);
+    return Either::Left(ready(Ok(req.into_response(res))));
+  }
+  let payload = add_auth_to_body(req.take_payload(), jwt);
+  req.set_payload(payload);
+  // The body gets longer
+  req.headers_mut().remove(CONTENT_LENGTH);
+  Either::Right(srv.call(req))
+}
+
+/// Reads don't need the csrf token, everything else does.
+fn needs_csrf_token(method: &Method) -> bool {
+  method != Method::GET && method != Method::HEAD
+}
+
+fn is_csrf_token_valid(req: &ServiceRequest, jwt: &str) -> bool {
+  let header = match req.headers().get(CSRF_HEADER).and_then(|h| h.to_str().ok()) {
+    Some(header) => header,
+    None => return false,
+  };
+  match csrf_token(jwt) {
+    Ok(token) => {
+      token.len() == header.len() && openssl::memcmp::eq(token.as_bytes(), header.as_bytes())
+    }
+    Err(_) => false,
+  }
+}
+
+/// The uri with the token as `auth`, if the query doesn't have one.
+fn add_auth_to_query(uri: &Uri, jwt: &str) -> Option<Uri> {
+  let query = uri.query().unwrap_or("");
+  if form_urlencoded::parse(query.as_bytes()).any(|(key, _)| key == "auth") {
+    return None;
+  }
+  let query = form_urlencoded::Serializer::for_suffix(query.to_owned(), 0)
+    .append_pair("auth", jwt)
+    .finish();
+  let path_and_query: PathAndQuery = format!("{}?{}", uri.path(), query).parse().ok()?;
+  let mut parts = uri.to_owned().into_parts();
+  parts.path_and_query = Some(path_and_query);
+  Uri::from_parts(parts).ok()
+}
+
+/// The json body with the token as `auth`, if it doesn't have one. The body is only read once the
+/// handler reads it.
+fn add_auth_to_body(mut payload: Payload, jwt: String) -> Payload {
+  let body = stream::once(async move {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = payload.next().await {
+      bytes.extend_from_slice(&chunk?);
+      if bytes.len() > MAX_BODY_SIZE {
+        return Err(PayloadError::Overflow);
+      }
+    }
+    let body = add_auth_to_json(&bytes, &jwt).unwrap_or(bytes);
+    Ok(Bytes::from(body))
+  });
+  Payload::Stream(Box::pin(body))
+}
+
+fn add_auth_to_json(body: &[u8], jwt: &str) -> Option<Vec<u8>> {
+  let mut value: Value = serde_json::from_slice(body).ok()?;
+  let object = value.as_object_mut()?;
+  if object.get("auth").map_or(false, |a| !a.is_null()) {
+    return None;
+  }
+  object.insert("auth".to_string(), Value::String(jwt.to_owned()));
+  serde_json::to_vec(&value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::session::*;
+  use actix_web::{http::StatusCode, test, web, App};
+
+  const JWT: &str = "session.token.value";
+
+  #[test]
+  fn test_csrf_token() {
+    let token = csrf_token(JWT).unwrap();
+    let req = test::TestRequest::default()
+      .header(CSRF_HEADER, token.as_str())
+      .to_srv_request();
+    assert!(is_csrf_token_valid(&req, JWT));
+    assert!(!is_csrf_token_valid(&req, "other.token.value"));
+
+    let req = test::TestRequest::default()
+      .header(CSRF_HEADER, "wrong")
+      .to_srv_request();
+    assert!(!is_csrf_token_valid(&req, JWT));
+
+    let req = test::TestRequest::default().to_srv_request();
+    assert!(!is_csrf_token_valid(&req, JWT));
+  }
+
+  #[test]
+  fn test_needs_csrf_token() {
+    assert!(!needs_csrf_token(&Method::GET));
+    assert!(!needs_csrf_token(&Method::HEAD));
+    assert!(needs_csrf_token(&Method::POST));
+    assert!(needs_csrf_token(&Method::PUT));
+  }
+
+  #[test]
+  fn test_add_auth_to_query() {
+    let uri: Uri = "/api/v1/post/list?sort=Hot".parse().unwrap();
+    let with_auth = add_auth_to_query(&uri, JWT).unwrap();
+    assert_eq!(
+      Some(format!("sort=Hot&auth={}", JWT).as_str()),
+      with_auth.query()
+    );
+
+    let uri: Uri = "/api/v1/post/list?auth=explicit".parse().unwrap();
+    assert!(add_auth_to_query(&uri, JWT).is_none());
+  }
+
+  #[test]
+  fn test_add_auth_to_json() {
+    let body = add_auth_to_json(br#"{"post_id":1}"#, JWT).unwrap();
+    let value: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(JWT, value["auth"]);
+
+    let body = add_auth_to_json(br#"{"post_id":1,"auth":null}"#, JWT).unwrap();
+    let value: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(JWT, value["auth"]);
+
+    assert!(add_auth_to_json(br#"{"post_id":1,"auth":"explicit"}"#, JWT).is_none());
+    assert!(add_auth_to_json(b"not json", JWT).is_none());
+  }
+
+  #[test]
+  fn test_session_cookie_needs_csrf_token() {
+    actix_rt::System::new("test_session_cookie_needs_csrf_token").block_on(async move {
+      // Whether sessions are enabled is up to the config, so the middleware is used without it
+      let mut app = test::init_service(
+        App::new()
+          .wrap_fn(|req, srv| {
+            let jwt = session_jwt(&req);
+            apply_session_jwt(req, srv, jwt)
+          })
+          .route(
+            "/api/v1/post/like",
+            web::post().to(|| HttpResponse::Ok().finish()),
+          ),
+      )
+      .await;
+      let cookie = Cookie::new(SESSION_COOKIE, JWT);
+
+      let req = test::TestRequest::post()
+        .uri("/api/v1/post/like")
+        .cookie(cookie.clone())
+        .set_json(&serde_json::json!({"post_id": 1}))
+        .to_request();
+      let res = test::call_service(&mut app, req).await;
+      assert_eq!(StatusCode::FORBIDDEN, res.status());
+
+      let req = test::TestRequest::post()
+        .uri("/api/v1/post/like")
+        .cookie(cookie)
+        .header(CSRF_HEADER, csrf_token(JWT).unwrap())
+        .set_json(&serde_json::json!({"post_id": 1}))
+        .to_request();
+      let res = test::call_service(&mut app, req).await;
+      assert_eq!(StatusCode::OK, res.status());
+
+      // Without the cookie, there is nothing to protect
+      let req = test::TestRequest::post()
+        .uri("/api/v1/post/like")
+        .set_json(&serde_json::json!({"post_id": 1, "auth": JWT}))
+        .to_request();
+      let res = test::call_service(&mut app, req).await;
+      assert_eq!(StatusCode::OK, res.status());
+    });
+  }
+}
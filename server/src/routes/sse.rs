@@ -0,0 +1,220 @@
+//! Server-sent events, for clients behind proxies which break websockets. A connection is a
+//! protocol version 2 websocket session which can't send requests: it joins the rooms given in the
+//! query, gets the same messages as a websocket in them, and is resumed with the Last-Event-ID
+//! header which browsers send when they reconnect.
+use crate::{
+  api::Perform,
+  websocket::{
+    chat_server::ChatServer,
+    messages::{
+      Connect,
+      Disconnect,
+      JoinCommunityRoom,
+      JoinPostRoom,
+      JoinUserRoom,
+      ResumeSession,
+      WSMessage,
+    },
+    UserOperation,
+  },
+  LemmyContext,
+};
+use actix::prelude::*;
+use actix_web::{error::ErrorBadRequest, http::header::CACHE_CONTROL, web::Bytes, *};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use lemmy_api_structs::user::UserJoin;
+use lemmy_utils::{apub::rewrite_links_for_onion, settings::Settings, utils::get_ip};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+
+pub const EVENT_STREAM_CONTENT_TYPE: &str = "text/event-stream";
+
+/// Proxies close connections which are quiet for too long
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+  /// Joins the user room, like UserJoin
+  auth: Option<String>,
+  /// Joins the community room, 0 for the front page
+  community_id: Option<i32>,
+  /// Joins the post room, instead of the community room
+  post_id: Option<i32>,
+}
+
+pub async fn events_route(
+  req: HttpRequest,
+  query: web::Query<EventsQuery>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let query = query.into_inner();
+  let user_id = match query.auth {
+    Some(auth) => Some(
+      UserJoin { auth }
+        .perform(&context, None)
+        .await
+        .map_err(ErrorBadRequest)?
+        .user_id,
+    ),
+    None => None,
+  };
+  let resume = req
+    .headers()
+    .get("Last-Event-ID")
+    .and_then(|l| l.to_str().ok())
+    .and_then(parse_last_event_id);
+
+  let (sender, receiver) = unbounded::<Result<Bytes, Error>>();
+  SseSession {
+    cs_addr: context.chat_server().to_owned(),
+    id: 0,
+    ip: get_ip(&req.connection_info()),
+    onion_hostname: Settings::get().get_onion_host(req.connection_info().host()),
+    sender,
+    resume,
+    resume_token: None,
+    user_id,
+    community_id: query.community_id,
+    post_id: query.post_id,
+  }
+  .start();
+
+  Ok(
+    HttpResponse::Ok()
+      .content_type(EVENT_STREAM_CONTENT_TYPE)
+      .header(CACHE_CONTROL, "no-cache")
+      .streaming(receiver),
+  )
+}
+
+/// Event ids are the resume token and the id of the event in the session, like `token:17`.
+fn parse_last_event_id(last_event_id: &str) -> Option<ResumeSession> {
+  let mut parts = last_event_id.splitn(2, ':');
+  let resume_token = parts.next()?.to_owned();
+  let last_event_id = parts.next()?.parse::<u64>().ok()?;
+  Some(ResumeSession {
+    resume_token,
+    last_event_id,
+  })
+}
+
+struct SseSession {
+  cs_addr: Addr<ChatServer>,
+  id: usize,
+  ip: String,
+  onion_hostname: Option<String>,
+  sender: UnboundedSender<Result<Bytes, Error>>,
+  /// Taken when the session registers with the ChatServer
+  resume: Option<ResumeSession>,
+  /// The current one, from the SessionStarted message
+  resume_token: Option<String>,
+  user_id: Option<i32>,
+  community_id: Option<i32>,
+  post_id: Option<i32>,
+}
+
+impl Actor for SseSession {
+  type Context = Context<Self>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    ctx.run_interval(KEEPALIVE_INTERVAL, |act, ctx| {
+      act.send(": keepalive\n\n".to_string(), ctx);
+    });
+
+    self
+      .cs_addr
+      .send(Connect {
+        addr: ctx.address().recipient(),
+        ip: self.ip.to_owned(),
+        // Events can't sign in
+        fingerprint: None,
+        v2: true,
+        resume: self.resume.take(),
+      })
+      .into_actor(self)
+      .then(|res, act, ctx| {
+        match res {
+          Ok(id) => {
+            act.id = id;
+            act.join_rooms();
+          }
+          _ => ctx.stop(),
+        }
+        actix::fut::ready(())
+      })
+      .wait(ctx);
+  }
+
+  fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+    self.cs_addr.do_send(Disconnect {
+      id: self.id,
+      ip: self.ip.to_owned(),
+    });
+    Running::Stop
+  }
+}
+
+impl Handler<WSMessage> for SseSession {
+  type Result = ();
+
+  fn handle(&mut self, msg: WSMessage, ctx: &mut Self::Context) {
+    let message = match &self.onion_hostname {
+      Some(onion_hostname) => rewrite_links_for_onion(&msg.0, onion_hostname),
+      None => msg.0,
+    };
+    let json: Value = serde_json::from_str(&message).unwrap_or_default();
+    if json["op"] == UserOperation::SessionStarted.to_string() {
+      self.resume_token = json["data"]["resume_token"].as_str().map(|t| t.to_owned());
+    }
+    let id_line = match (&self.resume_token, json["id"].as_u64()) {
+      (Some(resume_token), Some(event_id)) => format!("id: {}:{}\n", resume_token, event_id),
+      _ => String::new(),
+    };
+    self.send(format!("{}data: {}\n\n", id_line, message), ctx);
+  }
+}
+
+impl SseSession {
+  /// Rejoining the rooms of a resumed session only resets its count of new posts.
+  fn join_rooms(&self) {
+    if let Some(user_id) = self.user_id {
+      self.cs_addr.do_send(JoinUserRoom {
+        user_id,
+        id: self.id,
+      });
+    }
+    if let Some(post_id) = self.post_id {
+      self.cs_addr.do_send(JoinPostRoom {
+        post_id,
+        id: self.id,
+      });
+    } else if let Some(community_id) = self.community_id {
+      self.cs_addr.do_send(JoinCommunityRoom {
+        community_id,
+        id: self.id,
+      });
+    }
+  }
+
+  /// Stops once the client is gone.
+  fn send(&self, text: String, ctx: &mut Context<Self>) {
+    if self.sender.unbounded_send(Ok(Bytes::from(text))).is_err() {
+      ctx.stop();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::sse::parse_last_event_id;
+
+  #[test]
+  fn test_parse_last_event_id() {
+    let resume = parse_last_event_id("0b7a5f52-2c7e-4f3c-9e8b-0d6a4c1f2e3d:17").unwrap();
+    assert_eq!("0b7a5f52-2c7e-4f3c-9e8b-0d6a4c1f2e3d", resume.resume_token);
+    assert_eq!(17, resume.last_event_id);
+    assert!(parse_last_event_id("17").is_none());
+    assert!(parse_last_event_id("token:x").is_none());
+  }
+}
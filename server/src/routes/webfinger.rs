@@ -1,4 +1,7 @@
-use crate::LemmyContext;
+use crate::{
+  apub::instance::{instance_actor_id, instance_actor_webfinger},
+  LemmyContext,
+};
 use actix_web::{error::ErrorBadRequest, web::Query, *};
 use anyhow::anyhow;
 use lemmy_api_structs::blocking;
@@ -62,7 +65,11 @@ async fn get_webfinger_response(
     .map(|c| c.get(1))
     .flatten();
 
-  let url = if let Some(community_name) = community_regex_parsed {
+  let url = if info.resource == instance_actor_webfinger() {
+    instance_actor_id()
+      .map_err(|e| ErrorBadRequest(LemmyError::from(e)))?
+      .to_string()
+  } else if let Some(community_name) = community_regex_parsed {
     let community_name = community_name.as_str().to_owned();
     // Make sure the requested community exists.
     blocking(context.pool(), move |conn| {
@@ -1,15 +1,20 @@
 use crate::{
   websocket::{
     chat_server::ChatServer,
-    messages::{Connect, Disconnect, StandardMessage, WSMessage},
+    messages::{Connect, Disconnect, ResumeSession, StandardMessage, WSMessage},
   },
   LemmyContext,
 };
 use actix::prelude::*;
 use actix_web::*;
 use actix_web_actors::ws;
-use lemmy_utils::utils::get_ip;
+use lemmy_utils::{
+  apub::rewrite_links_for_onion,
+  settings::Settings,
+  utils::{client_fingerprint, get_ip},
+};
 use log::{debug, error, info};
+use serde::Deserialize;
 use std::time::{Duration, Instant};
 
 /// How often heartbeat pings are sent
@@ -17,18 +22,38 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+#[derive(Deserialize)]
+struct WebsocketQuery {
+  /// 2 for event ids, acknowledgements and resumable sessions
+  protocol: Option<u8>,
+  resume_token: Option<String>,
+  last_event_id: Option<u64>,
+}
+
 /// Entry point for our route
 pub async fn chat_route(
   req: HttpRequest,
   stream: web::Payload,
   context: web::Data<LemmyContext>,
 ) -> Result<HttpResponse, Error> {
+  let query = web::Query::<WebsocketQuery>::from_query(req.query_string())?.into_inner();
+  let resume = match (query.resume_token, query.last_event_id) {
+    (Some(resume_token), Some(last_event_id)) => Some(ResumeSession {
+      resume_token,
+      last_event_id,
+    }),
+    _ => None,
+  };
   ws::start(
     WSSession {
       cs_addr: context.chat_server().to_owned(),
       id: 0,
       hb: Instant::now(),
       ip: get_ip(&req.connection_info()),
+      fingerprint: client_fingerprint(req.headers()),
+      onion_hostname: Settings::get().get_onion_host(req.connection_info().host()),
+      v2: query.protocol == Some(2),
+      resume,
     },
     &req,
     stream,
@@ -40,6 +65,12 @@ struct WSSession {
   /// unique session id
   id: usize,
   ip: String,
+  fingerprint: Option<String>,
+  /// Set if the client connected through the onion service
+  onion_hostname: Option<String>,
+  v2: bool,
+  /// Taken when the session registers with the ChatServer
+  resume: Option<ResumeSession>,
   /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
   /// otherwise we drop connection.
   hb: Instant,
@@ -64,6 +95,9 @@ impl Actor for WSSession {
       .send(Connect {
         addr: addr.recipient(),
         ip: self.ip.to_owned(),
+        fingerprint: self.fingerprint.take(),
+        v2: self.v2,
+        resume: self.resume.take(),
       })
       .into_actor(self)
       .then(|res, act, ctx| {
@@ -93,7 +127,7 @@ impl Handler<WSMessage> for WSSession {
   type Result = ();
 
   fn handle(&mut self, msg: WSMessage, ctx: &mut Self::Context) {
-    ctx.text(msg.0);
+    ctx.text(self.localize(msg.0));
   }
 }
 
@@ -126,9 +160,11 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WSSession {
             msg: m,
           })
           .into_actor(self)
-          .then(|res, _, ctx| {
+          .then(|res, act, ctx| {
             match res {
-              Ok(Ok(res)) => ctx.text(res),
+              // Acknowledgements have no response
+              Ok(Ok(res)) if res.is_empty() => {}
+              Ok(Ok(res)) => ctx.text(act.localize(res)),
               Ok(Err(_)) => {}
               Err(e) => error!("{}", &e),
             }
@@ -146,6 +182,14 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WSSession {
 }
 
 impl WSSession {
+  /// Links in messages for clients of the onion service point to it, like in http responses.
+  fn localize(&self, text: String) -> String {
+    match &self.onion_hostname {
+      Some(onion_hostname) => rewrite_links_for_onion(&text, onion_hostname),
+      None => text,
+    }
+  }
+
   /// helper method that sends ping to client every second.
   ///
   /// also this method checks heartbeats from client
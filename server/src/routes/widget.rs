@@ -0,0 +1,196 @@
+//! Public json with the top posts of a community, for widgets on other websites. It doesn't need
+//! an account and isn't part of the api, so it can stay small and be cached for everyone.
+use crate::LemmyContext;
+use actix_web::{
+  error::ErrorBadRequest,
+  http::header::{HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, ORIGIN, VARY},
+  *,
+};
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  community::{Community, CommunitySettings},
+  post_view::{PostQueryBuilder, PostView},
+  ListingType,
+  SortType,
+};
+use lemmy_utils::{settings::Settings, LemmyError};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const DEFAULT_WIDGET_POSTS: i64 = 10;
+
+/// Browsers and proxies may keep a widget for a minute, our own cache drops it sooner on changes.
+const WIDGET_CACHE_CONTROL: &str = "public, max-age=60";
+
+#[derive(Deserialize)]
+pub struct WidgetParams {
+  sort: Option<String>,
+  limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct WidgetResponse {
+  community: WidgetCommunity,
+  posts: Vec<WidgetPost>,
+}
+
+#[derive(Serialize)]
+struct WidgetCommunity {
+  name: String,
+  title: String,
+  url: String,
+  icon: Option<String>,
+}
+
+#[derive(Serialize)]
+struct WidgetPost {
+  id: i32,
+  name: String,
+  /// The post on its instance
+  url: String,
+  /// The link of the post, if it has one
+  link: Option<String>,
+  thumbnail_url: Option<String>,
+  creator_name: Option<String>,
+  score: Option<i64>,
+  number_of_comments: i64,
+  published: chrono::NaiveDateTime,
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  if Settings::get().widget.enabled {
+    cfg.route(
+      "/widget/c/{community_name}.json",
+      web::get().to(get_community_widget),
+    );
+  }
+}
+
+async fn get_community_widget(
+  req: HttpRequest,
+  community_name: web::Path<String>,
+  info: web::Query<WidgetParams>,
+  context: web::Data<LemmyContext>,
+) -> Result<HttpResponse, Error> {
+  let allow_origin = allowed_origin(&req);
+  let cache_key = req.uri().to_string();
+  let mut res = match context.response_cache().get(&cache_key) {
+    Some(res) => res,
+    None => {
+      let sort =
+        SortType::from_str(info.sort.as_deref().unwrap_or("Hot")).map_err(ErrorBadRequest)?;
+      let limit = info
+        .limit
+        .unwrap_or(DEFAULT_WIDGET_POSTS)
+        .max(1)
+        .min(Settings::get().widget.max_posts);
+      let community_name = community_name.into_inner();
+      let widget = blocking(context.pool(), move |conn| {
+        build_widget(conn, &community_name, &sort, limit)
+      })
+      .await?
+      .map_err(ErrorBadRequest)?;
+      match widget {
+        Some((community_id, widget)) => {
+          let body = serde_json::to_string(&widget)?;
+          context.response_cache().insert(
+            cache_key,
+            body.to_owned(),
+            "application/json",
+            Some(community_id),
+          );
+          HttpResponse::Ok()
+            .content_type("application/json")
+            .body(body)
+        }
+        None => HttpResponse::NotFound().finish(),
+      }
+    }
+  };
+
+  let headers = res.headers_mut();
+  headers.insert(
+    CACHE_CONTROL,
+    HeaderValue::from_static(WIDGET_CACHE_CONTROL),
+  );
+  headers.insert(VARY, HeaderValue::from_static("Origin"));
+  if let Some(origin) = allow_origin {
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+  }
+  Ok(res)
+}
+
+/// The value for Access-Control-Allow-Origin, if the site which asks is allowed to load widgets.
+fn allowed_origin(req: &HttpRequest) -> Option<HeaderValue> {
+  let allowed_origins = Settings::get().widget.allowed_origins;
+  if allowed_origins.trim() == "*" {
+    return Some(HeaderValue::from_static("*"));
+  }
+  let origin = req.headers().get(ORIGIN)?;
+  let is_allowed = allowed_origins
+    .split(',')
+    .any(|o| Some(o.trim()) == origin.to_str().ok());
+  if is_allowed {
+    Some(origin.to_owned())
+  } else {
+    None
+  }
+}
+
+/// Returns None for communities which don't exist, or whose posts aren't public.
+fn build_widget(
+  conn: &diesel::PgConnection,
+  community_name: &str,
+  sort: &SortType,
+  limit: i64,
+) -> Result<Option<(i32, WidgetResponse)>, LemmyError> {
+  let community = match Community::read_from_name(conn, community_name) {
+    Ok(c) => c,
+    Err(_) => return Ok(None),
+  };
+  let settings = CommunitySettings::read_for_community(conn, community.id)?;
+  if community.removed || community.deleted || community.nsfw || settings.private {
+    return Ok(None);
+  }
+
+  let posts = PostQueryBuilder::create(conn)
+    .listing_type(ListingType::All)
+    .sort(sort)
+    .for_community_id(community.id)
+    .show_nsfw(false)
+    .limit(limit)
+    .list()?;
+
+  let widget = WidgetResponse {
+    community: WidgetCommunity {
+      name: community.name,
+      title: community.title,
+      url: community.actor_id,
+      icon: community.icon,
+    },
+    posts: posts.into_iter().map(widget_post).collect(),
+  };
+  Ok(Some((community.id, widget)))
+}
+
+fn widget_post(post: PostView) -> WidgetPost {
+  WidgetPost {
+    id: post.id,
+    name: post.name,
+    url: post.ap_id,
+    link: post.url,
+    thumbnail_url: post.thumbnail_url,
+    creator_name: if post.anonymous {
+      None
+    } else {
+      Some(post.creator_name)
+    },
+    score: if post.score_hidden {
+      None
+    } else {
+      Some(post.score)
+    },
+    number_of_comments: post.number_of_comments,
+    published: post.published,
+  }
+}
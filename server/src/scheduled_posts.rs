@@ -0,0 +1,204 @@
+//! The scheduled posts job, which creates the recurring posts that mods defined for their
+//! communities, as the automod user of the community.
+use crate::{
+  api::post::{send_community_notifications, send_saved_search_matches},
+  apub::{ApubLikeableType, ApubObjectType},
+  websocket::{messages::SendPost, UserOperation},
+  LemmyContext,
+};
+use anyhow::anyhow;
+use lemmy_api_structs::{blocking, post::PostResponse};
+use lemmy_db::{
+  community::CommunitySettings,
+  moderator::{ModStickyPost, ModStickyPostForm},
+  naive_now,
+  post::{Post, PostForm, PostLike, PostLikeForm},
+  post_view::PostView,
+  scheduled_post::ScheduledPost,
+  user::User_,
+  Crud,
+  Likeable,
+};
+use lemmy_utils::{
+  apub::{make_apub_endpoint, EndpointType},
+  schedule::{render_title_template, Schedule},
+  settings::Settings,
+  LemmyError,
+};
+use log::error;
+use std::time::Duration;
+
+const SCHEDULED_POSTS_INTERVAL: Duration = Duration::from_secs(60);
+
+pub async fn run_scheduled_posts_job(context: LemmyContext) {
+  let mut interval = actix_web::rt::time::interval(SCHEDULED_POSTS_INTERVAL);
+  loop {
+    interval.tick().await;
+    // Posts which are due during maintenance are created once it's over
+    if Settings::get().maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = create_due_posts(&context).await {
+      error!("Couldn't create the scheduled posts: {}", e);
+    }
+  }
+}
+
+async fn create_due_posts(context: &LemmyContext) -> Result<(), LemmyError> {
+  let due = blocking(context.pool(), move |conn| ScheduledPost::list_due(conn)).await??;
+  for scheduled_post in due {
+    // Moved to the next time first, so that a post which can't be created isn't tried again every
+    // minute. Times which were missed while the server was down are skipped.
+    let scheduled_post_id = scheduled_post.id;
+    let next_run =
+      Schedule::parse(&scheduled_post.schedule).and_then(|s| s.next_after(naive_now()));
+    blocking(context.pool(), move |conn| {
+      ScheduledPost::update_next_run(conn, scheduled_post_id, next_run)
+    })
+    .await??;
+
+    if let Err(e) = create_scheduled_post(&scheduled_post, context).await {
+      error!(
+        "Couldn't create scheduled post {}: {}",
+        scheduled_post_id, e
+      );
+    }
+  }
+  Ok(())
+}
+
+async fn create_scheduled_post(
+  scheduled_post: &ScheduledPost,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let community_id = scheduled_post.community_id;
+  let settings = blocking(context.pool(), move |conn| {
+    CommunitySettings::read_for_community(conn, community_id)
+  })
+  .await??;
+  let automod_user_id = settings
+    .automod_user_id
+    .ok_or_else(|| anyhow!("Community {} has no automod user", community_id))?;
+  let automod = blocking(context.pool(), move |conn| {
+    User_::read(conn, automod_user_id)
+  })
+  .await??;
+
+  let post_form = PostForm {
+    name: render_title_template(&scheduled_post.title_template, scheduled_post.next_run),
+    url: None,
+    body: scheduled_post.body.to_owned(),
+    community_id,
+    creator_id: automod.id,
+    removed: None,
+    deleted: None,
+    nsfw: false,
+    locked: None,
+    stickied: Some(scheduled_post.stickied),
+    updated: None,
+    embed_title: None,
+    embed_description: None,
+    embed_html: None,
+    thumbnail_url: None,
+    thumbnail_alt_text: None,
+    distinguished: None,
+    anonymous: None,
+    comments_closed: None,
+    ap_id: None,
+    local: true,
+    published: None,
+  };
+  let post = blocking(context.pool(), move |conn| {
+    let inserted_post = Post::create(conn, &post_form)?;
+    let apub_id = make_apub_endpoint(EndpointType::Post, &inserted_post.id.to_string()).to_string();
+    Post::update_ap_id(conn, inserted_post.id, apub_id)
+  })
+  .await??;
+
+  let scheduled_post_id = scheduled_post.id;
+  let post_id = post.id;
+  let automod_id = automod.id;
+  let stickied = scheduled_post.stickied;
+  blocking(context.pool(), move |conn| {
+    ScheduledPost::update_last_post(conn, scheduled_post_id, post_id)?;
+    PostLike::like(
+      conn,
+      &PostLikeForm {
+        post_id,
+        user_id: automod_id,
+        score: 1,
+      },
+    )?;
+    if stickied {
+      ModStickyPost::create(
+        conn,
+        &ModStickyPostForm {
+          mod_user_id: automod_id,
+          post_id,
+          stickied: Some(true),
+        },
+      )?;
+    }
+    Ok(()) as Result<(), LemmyError>
+  })
+  .await??;
+
+  post.send_create(&automod, context).await?;
+  post.send_like(&automod, context).await?;
+  send_post_to_websocket(post_id, UserOperation::CreatePost, context).await?;
+  send_saved_search_matches(post.to_owned(), context, true).await?;
+  send_community_notifications(post, context, true).await?;
+
+  // Only the newest one stays stickied, like a weekly thread
+  if let (true, Some(last_post_id)) = (stickied, scheduled_post.last_post_id) {
+    unsticky_last_post(last_post_id, &automod, context).await?;
+  }
+
+  Ok(())
+}
+
+async fn unsticky_last_post(
+  post_id: i32,
+  automod: &User_,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let post = blocking(context.pool(), move |conn| Post::read(conn, post_id)).await??;
+  if !post.stickied {
+    return Ok(());
+  }
+
+  let automod_id = automod.id;
+  let updated_post = blocking(context.pool(), move |conn| {
+    let updated_post = Post::update_stickied(conn, post_id, false)?;
+    ModStickyPost::create(
+      conn,
+      &ModStickyPostForm {
+        mod_user_id: automod_id,
+        post_id,
+        stickied: Some(false),
+      },
+    )?;
+    Ok(updated_post) as Result<Post, LemmyError>
+  })
+  .await??;
+
+  updated_post.send_update(automod, context).await?;
+  send_post_to_websocket(post_id, UserOperation::StickyPost, context).await
+}
+
+async fn send_post_to_websocket(
+  post_id: i32,
+  op: UserOperation,
+  context: &LemmyContext,
+) -> Result<(), LemmyError> {
+  let post_view = blocking(context.pool(), move |conn| {
+    PostView::read(conn, post_id, None)
+  })
+  .await??;
+  context.chat_server().do_send(SendPost {
+    op,
+    post: PostResponse { post: post_view },
+    websocket_id: None,
+  });
+  Ok(())
+}
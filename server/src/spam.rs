@@ -0,0 +1,320 @@
+use crate::{
+  request::{RecvError, RetryPolicy},
+  DbPool,
+  LemmyContext,
+};
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  naive_now,
+  spam_score::{SpamScore, SpamScoreForm},
+  user::User_,
+  Crud,
+};
+use lemmy_utils::{settings::Settings, LemmyError};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+/// Content with at least this many links is always checked for link density.
+const MIN_SPAMMY_LINKS: usize = 3;
+/// Shorter content isn't checked for duplicates.
+const MIN_DUPLICATE_CONTENT_LENGTH: usize = 30;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamContentType {
+  Post,
+  Comment,
+  PrivateMessage,
+}
+
+/// What the content was stored as, once it has been created.
+pub enum SpamTarget {
+  Post(i32),
+  Comment(i32),
+  PrivateMessage(i32),
+}
+
+pub struct SpamCandidate<'a> {
+  pub content_type: SpamContentType,
+  pub content: &'a str,
+  pub content_hash: String,
+  pub creator: &'a User_,
+}
+
+pub struct SpamSignal {
+  /// From 0 to 1
+  pub score: f32,
+  pub reason: String,
+}
+
+impl SpamSignal {
+  fn new(score: f32, reason: &str) -> Self {
+    SpamSignal {
+      score: score.max(0.0).min(1.0),
+      reason: reason.to_string(),
+    }
+  }
+}
+
+/// A stage of the spam scoring pipeline. Every classifier looks at the content independently, and
+/// returns a signal if it finds it suspicious.
+#[async_trait::async_trait(?Send)]
+pub trait SpamClassifier {
+  async fn classify(
+    &self,
+    candidate: &SpamCandidate<'_>,
+    context: &LemmyContext,
+  ) -> Result<Option<SpamSignal>, LemmyError>;
+}
+
+/// Flags content that is mostly made of links.
+pub struct LinkDensity;
+
+#[async_trait::async_trait(?Send)]
+impl SpamClassifier for LinkDensity {
+  async fn classify(
+    &self,
+    candidate: &SpamCandidate<'_>,
+    _context: &LemmyContext,
+  ) -> Result<Option<SpamSignal>, LemmyError> {
+    Ok(link_density_score(candidate.content).map(|s| SpamSignal::new(s, "link_density")))
+  }
+}
+
+/// Flags content of accounts that were created very recently.
+pub struct AccountAge;
+
+#[async_trait::async_trait(?Send)]
+impl SpamClassifier for AccountAge {
+  async fn classify(
+    &self,
+    candidate: &SpamCandidate<'_>,
+    _context: &LemmyContext,
+  ) -> Result<Option<SpamSignal>, LemmyError> {
+    let age = naive_now() - candidate.creator.published;
+    let score = if age < chrono::Duration::hours(1) {
+      0.4
+    } else if age < chrono::Duration::days(1) {
+      0.2
+    } else {
+      return Ok(None);
+    };
+    Ok(Some(SpamSignal::new(score, "account_age")))
+  }
+}
+
+/// Flags content that its creator already submitted during the last day. Short content like
+/// "thanks" is left alone, it's often repeated.
+pub struct DuplicateContent;
+
+#[async_trait::async_trait(?Send)]
+impl SpamClassifier for DuplicateContent {
+  async fn classify(
+    &self,
+    candidate: &SpamCandidate<'_>,
+    context: &LemmyContext,
+  ) -> Result<Option<SpamSignal>, LemmyError> {
+    if candidate.content.trim().chars().count() < MIN_DUPLICATE_CONTENT_LENGTH {
+      return Ok(None);
+    }
+    let creator_id = candidate.creator.id;
+    let content_hash = candidate.content_hash.to_owned();
+    let duplicates = blocking(context.pool(), move |conn| {
+      SpamScore::count_duplicates(conn, creator_id, &content_hash)
+    })
+    .await??;
+    if duplicates == 0 {
+      return Ok(None);
+    }
+    Ok(Some(SpamSignal::new(
+      0.3 * duplicates as f32,
+      "duplicate_content",
+    )))
+  }
+}
+
+/// Asks an external service for a score, see the `spam.classifier_url` setting.
+pub struct ExternalClassifier {
+  pub url: String,
+}
+
+#[derive(Serialize)]
+struct ExternalClassifierRequest<'a> {
+  content_type: SpamContentType,
+  content: &'a str,
+  creator_id: i32,
+  creator_published: chrono::NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+struct ExternalClassifierResponse {
+  score: f32,
+}
+
+#[async_trait::async_trait(?Send)]
+impl SpamClassifier for ExternalClassifier {
+  async fn classify(
+    &self,
+    candidate: &SpamCandidate<'_>,
+    context: &LemmyContext,
+  ) -> Result<Option<SpamSignal>, LemmyError> {
+    let request = ExternalClassifierRequest {
+      content_type: candidate.content_type,
+      content: candidate.content,
+      creator_id: candidate.creator.id,
+      creator_published: candidate.creator.published,
+    };
+    let response = RetryPolicy::INTERACTIVE
+      .run(&self.url, || {
+        context.client().post(&self.url).json(&request).send()
+      })
+      .await?;
+    let res: ExternalClassifierResponse = response
+      .json()
+      .await
+      .map_err(|e| RecvError(e.to_string()))?;
+    if res.score <= 0.0 {
+      return Ok(None);
+    }
+    Ok(Some(SpamSignal::new(res.score, "external_classifier")))
+  }
+}
+
+/// The classifiers enabled by the config.
+pub fn classifiers() -> Vec<Box<dyn SpamClassifier>> {
+  let mut classifiers: Vec<Box<dyn SpamClassifier>> = vec![
+    Box::new(LinkDensity),
+    Box::new(AccountAge),
+    Box::new(DuplicateContent),
+  ];
+  if let Some(url) = Settings::get().spam.classifier_url {
+    classifiers.push(Box::new(ExternalClassifier { url }));
+  }
+  classifiers
+}
+
+pub struct SpamVerdict {
+  pub score: f32,
+  pub reasons: Vec<String>,
+  pub content_hash: String,
+  pub creator_id: i32,
+  pub reported: bool,
+  pub removed: bool,
+}
+
+impl SpamVerdict {
+  /// Stores the score, so that admins can see it.
+  pub async fn save(self, target: SpamTarget, pool: &DbPool) -> Result<SpamScore, LemmyError> {
+    let (post_id, comment_id, private_message_id) = match target {
+      SpamTarget::Post(id) => (Some(id), None, None),
+      SpamTarget::Comment(id) => (None, Some(id), None),
+      SpamTarget::PrivateMessage(id) => (None, None, Some(id)),
+    };
+    let form = SpamScoreForm {
+      post_id,
+      comment_id,
+      private_message_id,
+      creator_id: self.creator_id,
+      score: self.score,
+      reasons: self.reasons,
+      content_hash: self.content_hash,
+      reported: self.reported,
+      removed: self.removed,
+    };
+    Ok(blocking(pool, move |conn| SpamScore::create(conn, &form)).await??)
+  }
+}
+
+/// Runs new local content through the spam classifiers. Returns None if spam scoring is disabled.
+///
+/// A failing classifier is logged and skipped, so that content can still be created if eg the
+/// external classifier is down.
+pub async fn score_content(
+  content_type: SpamContentType,
+  content: &str,
+  creator: &User_,
+  context: &LemmyContext,
+) -> Result<Option<SpamVerdict>, LemmyError> {
+  let config = Settings::get().spam;
+  if !config.enabled {
+    return Ok(None);
+  }
+
+  let candidate = SpamCandidate {
+    content_type,
+    content,
+    content_hash: SpamScore::hash_content(content),
+    creator,
+  };
+
+  let mut signals = Vec::new();
+  for classifier in classifiers() {
+    match classifier.classify(&candidate, context).await {
+      Ok(Some(signal)) => signals.push(signal),
+      Ok(None) => {}
+      Err(e) => error!("spam classifier err: {}", e),
+    }
+  }
+
+  let score = combine_scores(&signals);
+  Ok(Some(SpamVerdict {
+    score,
+    reasons: signals.into_iter().map(|s| s.reason).collect(),
+    content_hash: candidate.content_hash,
+    creator_id: creator.id,
+    reported: score >= config.report_threshold,
+    removed: score >= config.remove_threshold,
+  }))
+}
+
+/// The probability that at least one of the signals is right.
+fn combine_scores(signals: &[SpamSignal]) -> f32 {
+  1.0 - signals.iter().fold(1.0, |acc, s| acc * (1.0 - s.score))
+}
+
+fn link_density_score(content: &str) -> Option<f32> {
+  let words = content.split_whitespace().count();
+  let links = content
+    .split_whitespace()
+    .filter(|w| w.contains("http://") || w.contains("https://"))
+    .count();
+  if links == 0 {
+    return None;
+  }
+
+  let density = links as f32 / words as f32;
+  if links < MIN_SPAMMY_LINKS && density < 0.5 {
+    return None;
+  }
+  Some(density)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::spam::{combine_scores, link_density_score, SpamSignal};
+
+  #[test]
+  fn test_link_density_score() {
+    assert_eq!(None, link_density_score("no links at all"));
+    assert_eq!(
+      None,
+      link_density_score("have a look at https://example.com, it explains everything")
+    );
+    assert_eq!(Some(1.0), link_density_score("https://example.com"));
+    assert_eq!(
+      Some(0.5),
+      link_density_score("cheap https://a.com watches https://b.com here https://c.com")
+    );
+  }
+
+  #[test]
+  fn test_combine_scores() {
+    assert_eq!(0.0, combine_scores(&[]));
+    let signals = vec![
+      SpamSignal::new(0.5, "link_density"),
+      SpamSignal::new(0.5, "account_age"),
+    ];
+    assert_eq!(0.75, combine_scores(&signals));
+    assert_eq!(1.0, combine_scores(&[SpamSignal::new(3.0, "external")]));
+  }
+}
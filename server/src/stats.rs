@@ -0,0 +1,80 @@
+//! The stats job, which counts the daily instance stats for the admin dashboard.
+use crate::{
+  apub::activity_queue::{restore_outgoing_activity_counts, take_outgoing_activity_counts},
+  request::{restore_request_metrics, take_request_metrics},
+  DbPool,
+};
+use background_jobs::QueueHandle;
+use diesel::Connection;
+use lemmy_api_structs::blocking;
+use lemmy_db::{
+  activity_send_queue::ActivitySendQueue,
+  instance_stats::{InstancePeerStatsDaily, InstanceStatsDaily},
+};
+use lemmy_utils::{settings::Settings, LemmyError};
+use log::error;
+use std::{convert::TryFrom, time::Duration};
+
+const STATS_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Updates the stats of today right away, and then every hour.
+pub async fn run_stats_job(pool: DbPool, activity_queue: QueueHandle) {
+  let mut interval = actix_web::rt::time::interval(STATS_INTERVAL);
+  loop {
+    interval.tick().await;
+    // The counts keep adding up, and are saved once the site is writable again
+    if Settings::get().maintenance.read_only {
+      continue;
+    }
+    if let Err(e) = update_instance_stats(&pool, &activity_queue).await {
+      error!("Couldn't update the instance stats: {}", e);
+    }
+  }
+}
+
+async fn update_instance_stats(
+  pool: &DbPool,
+  activity_queue: &QueueHandle,
+) -> Result<(), LemmyError> {
+  let queue_stats = activity_queue.get_stats().await?;
+  // The workers only hold the activities being sent, the others wait in the send queue
+  let pending = blocking(pool, move |conn| ActivitySendQueue::count_pending(conn)).await??;
+  let pending = i32::try_from(pending).unwrap_or(i32::MAX);
+  let running = i32::try_from(queue_stats.running).unwrap_or(i32::MAX);
+  let failed = i32::try_from(queue_stats.dead.today()).unwrap_or(i32::MAX);
+  let outgoing = take_outgoing_activity_counts();
+  let request_metrics = take_request_metrics();
+
+  // In a single transaction, so that counts which are put back after an error aren't saved twice
+  let (outgoing_copy, request_metrics_copy) = (outgoing.clone(), request_metrics.clone());
+  let saved = blocking(pool, move |conn| {
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+      let today = InstanceStatsDaily::today(conn)?;
+      // Yesterday only gets its last counts after midnight
+      InstanceStatsDaily::refresh(conn, today.pred())?;
+      InstanceStatsDaily::refresh(conn, today)?;
+      InstanceStatsDaily::update_queue(conn, today, pending, running, failed)?;
+      for (domain, count) in outgoing_copy {
+        InstancePeerStatsDaily::add_activities_out(conn, today, &domain, count)?;
+      }
+      for (domain, m) in request_metrics_copy {
+        InstancePeerStatsDaily::add_request_failures(
+          conn,
+          today,
+          &domain,
+          m.retries,
+          m.circuit_breaker_trips,
+        )?;
+      }
+      Ok(())
+    })
+  })
+  .await
+  .and_then(|res| res.map_err(LemmyError::from));
+
+  if saved.is_err() {
+    restore_outgoing_activity_counts(outgoing);
+    restore_request_metrics(request_metrics);
+  }
+  saved
+}
@@ -0,0 +1,596 @@
+//! Wasm plugins are sandboxed modules which run inside the server, and can change or reject posts
+//! and comments when they are created. They are loaded from the plugin directory in the config,
+//! and only run when an admin turned them on. Without the `wasm-plugins` feature, no plugins are
+//! loaded.
+//!
+//! Modules can't import anything, so all they can do is compute. Version 1 of the guest ABI is
+//!
+//! - `memory`, the exported memory
+//! - `lemmy_abi_version() -> i32`, returns 1
+//! - `lemmy_alloc(len: i32) -> i32`, returns where the host can write `len` bytes of input
+//! - `lemmy_filter(ptr: i32, len: i32) -> i64`, gets the input json, and returns where the output
+//!   json is, as `(ptr << 32) | len`
+//!
+//! The input is `{"abi_version": 1, "kind": "post", "payload": {...}}`, where posts have the
+//! payload of the PrePostCreate hook of http plugins. The output is one of the answers of http
+//! plugins, like `{"action": "allow"}`. Every call gets a fresh instance, with the time and memory
+//! limits from the config, and the memory has to declare a maximum size within the limit. Modules
+//! are compiled once per thread. Plugins which trap, run out of time or answer with nonsense are
+//! skipped.
+use crate::{plugins::HookAnswer, DbPool};
+use actix_web::web;
+use lemmy_api_structs::{blocking, site::WasmPluginInfo};
+use lemmy_db::wasm_plugin::WasmPlugin;
+use lemmy_utils::{settings::Settings, APIError, LemmyError};
+use log::{error, info};
+use runtime::{call_filter, compile, CompiledModule};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf, sync::RwLock};
+
+pub const ABI_VERSION: i32 = 1;
+
+#[derive(Clone)]
+struct LoadedPlugin {
+  name: String,
+  module: CompiledModule,
+}
+
+lazy_static! {
+  static ref PLUGINS: RwLock<Vec<LoadedPlugin>> = RwLock::new(Vec::new());
+  /// Why enabled plugins couldn't be loaded, by name, for the admins.
+  static ref LOAD_ERRORS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+  Post,
+  Comment,
+}
+
+/// The payload for comments. Plugins can only change the content.
+#[derive(Serialize, Deserialize)]
+pub struct CommentFilterPayload {
+  pub content: String,
+  pub post_id: i32,
+  pub creator_id: i32,
+}
+
+#[derive(Serialize)]
+struct FilterCall<'a, T> {
+  abi_version: i32,
+  kind: ContentKind,
+  payload: &'a T,
+}
+
+/// The names of the modules in the plugin directory.
+pub fn list_module_names() -> Vec<String> {
+  let entries = match fs::read_dir(Settings::get().wasm_plugins.directory) {
+    Ok(e) => e,
+    Err(_e) => return Vec::new(),
+  };
+  let mut names = entries
+    .filter_map(|e| e.ok())
+    .map(|e| e.path())
+    .filter(|p| p.extension().map_or(false, |e| e == "wasm"))
+    .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+    .collect::<Vec<String>>();
+  names.sort();
+  names
+}
+
+/// Compiles the enabled plugins again, after startup and whenever an admin turns one on or off.
+pub async fn reload_wasm_plugins(pool: &DbPool) -> Result<(), LemmyError> {
+  let enabled = blocking(pool, move |conn| WasmPlugin::list_enabled_names(conn)).await??;
+  let (plugins, errors) = web::block(move || Ok::<_, LemmyError>(load_plugins(enabled))).await?;
+  for (name, e) in &errors {
+    error!("Couldn't load wasm plugin {}: {}", name, e);
+  }
+  info!("Loaded {} wasm plugins", plugins.len());
+  *PLUGINS.write().unwrap() = plugins;
+  *LOAD_ERRORS.write().unwrap() = errors;
+  Ok(())
+}
+
+fn load_plugins(enabled: Vec<String>) -> (Vec<LoadedPlugin>, HashMap<String, String>) {
+  let available = list_module_names();
+  let mut plugins = Vec::new();
+  let mut errors = HashMap::new();
+  for name in enabled {
+    if !available.contains(&name) {
+      errors.insert(name, "The module isn't in the plugin directory".to_string());
+      continue;
+    }
+    let path: PathBuf = [
+      Settings::get().wasm_plugins.directory,
+      format!("{}.wasm", name),
+    ]
+    .iter()
+    .collect();
+    match fs::read(path)
+      .map_err(LemmyError::from)
+      .and_then(|b| compile(&b))
+    {
+      Ok(module) => plugins.push(LoadedPlugin { name, module }),
+      Err(e) => {
+        errors.insert(name, e.to_string());
+      }
+    }
+  }
+  (plugins, errors)
+}
+
+/// All modules in the plugin directory, and the ones which are turned on without a module.
+pub fn list_plugin_infos(plugins: Vec<WasmPlugin>) -> Vec<WasmPluginInfo> {
+  let loaded = PLUGINS
+    .read()
+    .unwrap()
+    .iter()
+    .map(|p| p.name.to_owned())
+    .collect::<Vec<String>>();
+  let errors = LOAD_ERRORS.read().unwrap().clone();
+  let available = list_module_names();
+
+  let mut names = available.clone();
+  names.extend(
+    plugins
+      .iter()
+      .filter(|p| p.enabled)
+      .map(|p| p.name.to_owned()),
+  );
+  names.sort();
+  names.dedup();
+  names
+    .into_iter()
+    .map(|name| WasmPluginInfo {
+      enabled: plugins.iter().any(|p| p.name == name && p.enabled),
+      in_directory: available.contains(&name),
+      loaded: loaded.contains(&name),
+      error: errors.get(&name).cloned(),
+      name,
+    })
+    .collect()
+}
+
+/// Runs the loaded plugins one after another, each getting what the previous one answered.
+pub async fn run_content_filters<T>(kind: ContentKind, payload: T) -> Result<T, LemmyError>
+where
+  T: Serialize + DeserializeOwned,
+{
+  let plugins = PLUGINS.read().unwrap().clone();
+
+  let mut payload = payload;
+  for plugin in plugins {
+    let input = serde_json::to_vec(&FilterCall {
+      abi_version: ABI_VERSION,
+      kind,
+      payload: &payload,
+    })?;
+    let module = plugin.module.clone();
+    let answer = web::block(move || call_filter(&module, &input))
+      .await
+      .map_err(LemmyError::from)
+      .and_then(|output| Ok(serde_json::from_slice::<HookAnswer<T>>(&output)?));
+    match answer {
+      Ok(HookAnswer::Allow) => (),
+      Ok(HookAnswer::Modify { payload: p }) => payload = p,
+      Ok(HookAnswer::Reject { reason }) => {
+        info!(
+          "Wasm plugin {} rejected content: {}",
+          plugin.name,
+          reason.unwrap_or_default()
+        );
+        return Err(APIError::err("rejected_by_plugin").into());
+      }
+      Err(e) => error!("Wasm plugin {} failed: {}", plugin.name, e),
+    }
+  }
+  Ok(payload)
+}
+
+#[cfg(feature = "wasm-plugins")]
+mod runtime {
+  use super::ABI_VERSION;
+  use anyhow::{anyhow, Context};
+  use lemmy_utils::{location_info, settings::Settings, LemmyError};
+  use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+      atomic::{AtomicUsize, Ordering},
+      mpsc,
+      Arc,
+      Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+  };
+  use wasmtime::{
+    Config,
+    Engine,
+    ExternType,
+    Instance,
+    InterruptHandle,
+    LinearMemory,
+    MemoryCreator,
+    MemoryType,
+    Module,
+    Store,
+  };
+
+  /// Modules of this wasmtime version belong to a store, which can't be shared between threads,
+  /// so the checked bytes are kept, and each thread compiles them once into its own store.
+  #[derive(Clone)]
+  pub struct CompiledModule {
+    id: usize,
+    bytes: Arc<Vec<u8>>,
+  }
+
+  static NEXT_MODULE_ID: AtomicUsize = AtomicUsize::new(0);
+
+  /// Wasm pages are 64 KiB.
+  const PAGE_SIZE: usize = 64 * 1024;
+  const PAGES_PER_MB: u32 = 16;
+
+  /// The store of a thread, and the modules compiled into it. Instances live as long as their store,
+  /// so it is replaced once the memories of past calls add up to the memory limit, and after a call
+  /// failed.
+  struct ThreadStore {
+    store: Store,
+    modules: HashMap<usize, Module>,
+    retained_bytes: usize,
+  }
+
+  thread_local! {
+    static THREAD_STORE: RefCell<Option<ThreadStore>> = RefCell::new(None);
+  }
+
+  /// When a running plugin has to be interrupted.
+  struct Deadline {
+    at: Instant,
+    interrupt: InterruptHandle,
+    done: Arc<Mutex<bool>>,
+  }
+
+  lazy_static! {
+    static ref WATCHDOG: Mutex<mpsc::Sender<Deadline>> = Mutex::new(spawn_watchdog());
+  }
+
+  fn max_pages() -> u32 {
+    Settings::get().wasm_plugins.max_memory_mb as u32 * PAGES_PER_MB
+  }
+
+  /// Also checks that the module has the right ABI version, doesn't import anything, and has a
+  /// maximum size for its memory within the limit of the config.
+  pub fn compile(bytes: &[u8]) -> Result<CompiledModule, LemmyError> {
+    let store = new_store();
+    let module = Module::new(&store, bytes)?;
+    if module.imports().next().is_some() {
+      return Err(anyhow!("Plugins can't import anything").into());
+    }
+    let max_pages = max_pages();
+    for export in module.exports() {
+      if let ExternType::Memory(memory) = export.ty() {
+        match memory.limits().max() {
+          Some(max) if max <= max_pages => (),
+          _ => {
+            return Err(anyhow!("The memory needs a maximum of at most {} pages", max_pages).into())
+          }
+        }
+      }
+    }
+
+    let version = with_timeout(&store, || {
+      let instance = Instance::new(&module, &[])?;
+      Ok(instance
+        .get_func("lemmy_abi_version")
+        .context(location_info!())?
+        .get0::<i32>()?()?)
+    })?;
+    if version != ABI_VERSION {
+      return Err(anyhow!("Unsupported ABI version {}", version).into());
+    }
+    Ok(CompiledModule {
+      id: NEXT_MODULE_ID.fetch_add(1, Ordering::Relaxed),
+      bytes: Arc::new(bytes.to_vec()),
+    })
+  }
+
+  fn new_store() -> Store {
+    let mut config = Config::new();
+    config
+      .interruptable(true)
+      // Only dynamic memories without guard pages, which LimitedMemory can provide
+      .static_memory_maximum_size(0)
+      .dynamic_memory_guard_size(0)
+      .with_host_memory(Arc::new(LimitedMemoryCreator {
+        max_pages: max_pages(),
+      }));
+    Store::new(&Engine::new(&config))
+  }
+
+  /// Creates the memories of plugins, which can't grow beyond the limit of the config, whatever
+  /// maximum they declare, exported or not.
+  struct LimitedMemoryCreator {
+    max_pages: u32,
+  }
+
+  unsafe impl MemoryCreator for LimitedMemoryCreator {
+    fn new_memory(
+      &self,
+      ty: MemoryType,
+      reserved_size: Option<u64>,
+      _guard_size: u64,
+    ) -> Result<Box<dyn LinearMemory>, String> {
+      if reserved_size.is_some() {
+        return Err("Plugins only get dynamic memories".to_string());
+      }
+      let max_pages = ty
+        .limits()
+        .max()
+        .map_or(self.max_pages, |max| max.min(self.max_pages));
+      let memory = LimitedMemory {
+        pages: RefCell::new(Vec::new()),
+        max_pages,
+      };
+      match memory.grow(ty.limits().min()) {
+        Some(_) => Ok(Box::new(memory)),
+        None => Err(format!("The memory needs more than {} pages", max_pages)),
+      }
+    }
+  }
+
+  #[repr(align(65536))]
+  struct Page([u8; PAGE_SIZE]);
+
+  struct LimitedMemory {
+    pages: RefCell<Vec<Page>>,
+    max_pages: u32,
+  }
+
+  unsafe impl LinearMemory for LimitedMemory {
+    fn size(&self) -> u32 {
+      self.pages.borrow().len() as u32
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+      let mut pages = self.pages.borrow_mut();
+      let old_size = pages.len() as u32;
+      let new_size = old_size
+        .checked_add(delta)
+        .filter(|size| *size <= self.max_pages)?;
+      pages.resize_with(new_size as usize, || Page([0; PAGE_SIZE]));
+      Some(old_size)
+    }
+
+    fn as_ptr(&self) -> *mut u8 {
+      self.pages.borrow_mut().as_mut_ptr() as *mut u8
+    }
+  }
+
+  /// One thread interrupts all plugins which run out of time.
+  fn spawn_watchdog() -> mpsc::Sender<Deadline> {
+    let (sender, receiver) = mpsc::channel::<Deadline>();
+    thread::spawn(move || {
+      let mut deadlines: Vec<Deadline> = Vec::new();
+      loop {
+        let received = match deadlines.iter().map(|d| d.at).min() {
+          Some(next) => receiver.recv_timeout(next.saturating_duration_since(Instant::now())),
+          None => receiver
+            .recv()
+            .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+          Ok(deadline) => deadlines.push(deadline),
+          Err(mpsc::RecvTimeoutError::Timeout) => (),
+          Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        deadlines.retain(|d| match d.done.lock() {
+          Ok(done) if !*done => {
+            if d.at <= now {
+              d.interrupt.interrupt();
+            }
+            d.at > now
+          }
+          _ => false,
+        });
+      }
+    });
+    sender
+  }
+
+  /// Has the plugin interrupted once the time limit of the config is over. A plugin which ran out
+  /// of time failed, even if it returned just before the interrupt.
+  fn with_timeout<T, F>(store: &Store, run: F) -> Result<T, LemmyError>
+  where
+    F: FnOnce() -> Result<T, LemmyError>,
+  {
+    let at = Instant::now() + Duration::from_millis(Settings::get().wasm_plugins.timeout_ms);
+    let done = Arc::new(Mutex::new(false));
+    WATCHDOG
+      .lock()
+      .map_err(|_| anyhow!("The watchdog of wasm plugins panicked"))?
+      .send(Deadline {
+        at,
+        interrupt: store.interrupt_handle()?,
+        done: done.clone(),
+      })
+      .map_err(|_| anyhow!("The watchdog of wasm plugins stopped"))?;
+    let res = run();
+    if let Ok(mut done) = done.lock() {
+      *done = true;
+    }
+    if Instant::now() >= at {
+      return Err(anyhow!("The plugin ran out of time").into());
+    }
+    res
+  }
+
+  pub fn call_filter(module: &CompiledModule, input: &[u8]) -> Result<Vec<u8>, LemmyError> {
+    THREAD_STORE.with(|thread_store| {
+      let mut thread_store = thread_store.borrow_mut();
+      let max_bytes = max_pages() as usize * PAGE_SIZE;
+      if thread_store
+        .as_ref()
+        .map_or(true, |s| s.retained_bytes >= max_bytes)
+      {
+        *thread_store = Some(ThreadStore {
+          store: new_store(),
+          modules: HashMap::new(),
+          retained_bytes: 0,
+        });
+      }
+      let res = run_filter(
+        thread_store.as_mut().context(location_info!())?,
+        module,
+        input,
+      );
+      // The instance may have trapped, or the store still be interrupted
+      if res.is_err() {
+        *thread_store = None;
+      }
+      res
+    })
+  }
+
+  fn run_filter(
+    thread_store: &mut ThreadStore,
+    module: &CompiledModule,
+    input: &[u8],
+  ) -> Result<Vec<u8>, LemmyError> {
+    let store = thread_store.store.clone();
+    let compiled = match thread_store.modules.get(&module.id) {
+      Some(compiled) => compiled.clone(),
+      None => {
+        let compiled = Module::new(&store, module.bytes.as_slice())?;
+        thread_store.modules.insert(module.id, compiled.clone());
+        compiled
+      }
+    };
+
+    with_timeout(&store, || {
+      let instance = Instance::new(&compiled, &[])?;
+      let memory = instance.get_memory("memory").context(location_info!())?;
+      let alloc = instance
+        .get_func("lemmy_alloc")
+        .context(location_info!())?
+        .get1::<i32, i32>()?;
+      let filter = instance
+        .get_func("lemmy_filter")
+        .context(location_info!())?
+        .get2::<i32, i32, i64>()?;
+
+      let input_len = input.len() as i32;
+      let input_ptr = alloc(input_len)? as u32 as usize;
+      if input_ptr + input.len() > memory.data_size() {
+        return Err(anyhow!("The input is outside of the memory").into());
+      }
+      // Nothing else uses the memory while the plugin isn't running
+      unsafe {
+        memory.data_unchecked_mut()[input_ptr..input_ptr + input.len()].copy_from_slice(input)
+      };
+
+      let output = filter(input_ptr as i32, input_len)?;
+      thread_store.retained_bytes += memory.data_size();
+      let output_ptr = (output >> 32) as u32 as usize;
+      let output_len = output as u32 as usize;
+      if output_ptr + output_len > memory.data_size() {
+        return Err(anyhow!("The output is outside of the memory").into());
+      }
+      Ok(unsafe { memory.data_unchecked()[output_ptr..output_ptr + output_len].to_vec() })
+    })
+  }
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+mod runtime {
+  use anyhow::anyhow;
+  use lemmy_utils::LemmyError;
+
+  #[derive(Clone)]
+  pub struct CompiledModule;
+
+  pub fn compile(_bytes: &[u8]) -> Result<CompiledModule, LemmyError> {
+    Err(anyhow!("Lemmy was built without the wasm-plugins feature").into())
+  }
+
+  pub fn call_filter(_module: &CompiledModule, _input: &[u8]) -> Result<Vec<u8>, LemmyError> {
+    Err(anyhow!("Lemmy was built without the wasm-plugins feature").into())
+  }
+}
+
+#[cfg(all(test, feature = "wasm-plugins"))]
+mod tests {
+  use crate::wasm_plugins::runtime::{call_filter, compile};
+
+  const ALLOW_MODULE: &str = r#"
+    (module
+      (memory (export "memory") 1 16)
+      (data (i32.const 0) "{\"action\":\"allow\"}")
+      (func (export "lemmy_abi_version") (result i32) i32.const 1)
+      (func (export "lemmy_alloc") (param i32) (result i32) i32.const 1024)
+      (func (export "lemmy_filter") (param i32 i32) (result i64) i64.const 18))
+  "#;
+
+  const LOOP_MODULE: &str = r#"
+    (module
+      (memory (export "memory") 1 16)
+      (func (export "lemmy_abi_version") (result i32) i32.const 1)
+      (func (export "lemmy_alloc") (param i32) (result i32) i32.const 1024)
+      (func (export "lemmy_filter") (param i32 i32) (result i64)
+        (loop (br 0))
+        i64.const 0))
+  "#;
+
+  #[test]
+  fn test_call_filter() {
+    let module = compile(ALLOW_MODULE.as_bytes()).unwrap();
+    let output = call_filter(&module, br#"{"abi_version":1}"#).unwrap();
+    assert_eq!(br#"{"action":"allow"}"#.to_vec(), output);
+
+    // Runs out of time
+    let loop_module = compile(LOOP_MODULE.as_bytes()).unwrap();
+    assert!(call_filter(&loop_module, br#"{"abi_version":1}"#).is_err());
+
+    // The compiled module is reused, also after another plugin failed
+    let output = call_filter(&module, br#"{"abi_version":1}"#).unwrap();
+    assert_eq!(br#"{"action":"allow"}"#.to_vec(), output);
+  }
+
+  #[test]
+  fn test_compile_checks_module() {
+    let wrong_version =
+      ALLOW_MODULE.replace("(result i32) i32.const 1)", "(result i32) i32.const 2)");
+    assert!(compile(wrong_version.as_bytes()).is_err());
+
+    let with_import = r#"
+      (module
+        (import "env" "log" (func))
+        (func (export "lemmy_abi_version") (result i32) i32.const 1))
+    "#;
+    assert!(compile(with_import.as_bytes()).is_err());
+
+    let endless_start = r#"
+      (module
+        (func $start (loop (br 0)))
+        (start $start)
+        (func (export "lemmy_abi_version") (result i32) i32.const 1))
+    "#;
+    assert!(compile(endless_start.as_bytes()).is_err());
+
+    let unlimited_memory = ALLOW_MODULE.replace(
+      "(memory (export \"memory\") 1 16)",
+      "(memory (export \"memory\") 1)",
+    );
+    assert!(compile(unlimited_memory.as_bytes()).is_err());
+    let too_much_memory = ALLOW_MODULE.replace("1 16)", "1 65536)");
+    assert!(compile(too_much_memory.as_bytes()).is_err());
+    let too_much_hidden_memory = r#"
+      (module
+        (memory 65536)
+        (func (export "lemmy_abi_version") (result i32) i32.const 1))
+    "#;
+    assert!(compile(too_much_hidden_memory.as_bytes()).is_err());
+  }
+}
@@ -1,7 +1,16 @@
 use crate::{
+  post_views::Viewed,
+  response_cache::ResponseCache,
   websocket::{
-    handlers::{do_user_operation, to_json_string, Args},
+    handlers::{
+      do_sign_in_operation,
+      do_user_operation,
+      do_viewed_operation,
+      to_json_string,
+      Args,
+    },
     messages::*,
+    resume::{DetachedSession, EventLog},
     UserOperation,
   },
   LemmyContext,
@@ -13,10 +22,13 @@ use diesel::{
   r2d2::{ConnectionManager, Pool},
   PgConnection,
 };
+use futures::channel::oneshot;
 use lemmy_api_structs::{comment::*, community::*, post::*, site::*, user::*};
+use lemmy_db::community::hidden_private_communities;
 use lemmy_rate_limit::RateLimit;
 use lemmy_utils::{
   location_info,
+  utils::check_not_read_only,
   APIError,
   CommunityId,
   ConnectionId,
@@ -32,6 +44,7 @@ use serde_json::Value;
 use std::{
   collections::{HashMap, HashSet},
   str::FromStr,
+  time::Instant,
 };
 
 /// `ChatServer` manages chat rooms and responsible for coordinating chat
@@ -40,6 +53,9 @@ pub struct ChatServer {
   /// A map from generated random ID to session addr
   pub sessions: HashMap<ConnectionId, SessionInfo>,
 
+  /// Protocol version 2 sessions whose connection dropped, by resume token
+  pub(super) detached_sessions: HashMap<String, DetachedSession>,
+
   /// A map from post_id to set of connectionIDs
   pub post_rooms: HashMap<PostId, HashSet<ConnectionId>>,
 
@@ -50,6 +66,15 @@ pub struct ChatServer {
   /// sessions (IE clients)
   pub(super) user_rooms: HashMap<UserId, HashSet<ConnectionId>>,
 
+  /// Long polls waiting for a message to the user room
+  pub(super) user_room_waiters: HashMap<UserId, Vec<oneshot::Sender<()>>>,
+
+  /// How many new posts arrived for the listing a connection is viewing, since it was loaded
+  pub(super) new_post_counts: HashMap<ConnectionId, i64>,
+
+  /// Anyone can join a room, so the content of private communities isn't broadcast to them
+  pub(super) private_communities: HashSet<CommunityId>,
+
   pub(super) rng: ThreadRng,
 
   /// The DB Pool
@@ -65,11 +90,18 @@ pub struct ChatServer {
   client: Client,
 
   activity_queue: QueueHandle,
+
+  /// Dropped for a community when its content changes
+  pub(super) response_cache: ResponseCache,
 }
 
 pub struct SessionInfo {
   pub addr: Recipient<WSMessage>,
   pub ip: IPAddr,
+  /// Of the client which opened the websocket, see `client_fingerprint`
+  pub fingerprint: Option<String>,
+  /// Only for protocol version 2 sessions
+  pub event_log: Option<EventLog>,
 }
 
 /// `ChatServer` is an actor. It maintains list of connection client session.
@@ -81,18 +113,33 @@ impl ChatServer {
     rate_limiter: RateLimit,
     client: Client,
     activity_queue: QueueHandle,
+    response_cache: ResponseCache,
   ) -> ChatServer {
+    // Nobody logged out can see them, so these are all the private communities
+    let private_communities = pool
+      .get()
+      .ok()
+      .and_then(|conn| hidden_private_communities(&conn, None).ok())
+      .unwrap_or_default()
+      .into_iter()
+      .collect();
+
     ChatServer {
       sessions: HashMap::new(),
+      detached_sessions: HashMap::new(),
       post_rooms: HashMap::new(),
       community_rooms: HashMap::new(),
       user_rooms: HashMap::new(),
+      user_room_waiters: HashMap::new(),
+      new_post_counts: HashMap::new(),
+      private_communities,
       rng: rand::thread_rng(),
       pool,
       rate_limiter,
       captchas: Vec::new(),
       client,
       activity_queue,
+      response_cache,
     }
   }
 
@@ -122,6 +169,9 @@ impl ChatServer {
       .get_mut(&community_id)
       .context(location_info!())?
       .insert(id);
+
+    // Joining means the listing was just loaded, so nothing is new yet
+    self.new_post_counts.insert(id, 0);
     Ok(())
   }
 
@@ -175,7 +225,7 @@ impl ChatServer {
   }
 
   fn send_post_room_message<Response>(
-    &self,
+    &mut self,
     op: &UserOperation,
     response: &Response,
     post_id: PostId,
@@ -192,14 +242,14 @@ impl ChatServer {
             continue;
           }
         }
-        self.sendit(res_str, *id);
+        sendit(&mut self.sessions, res_str, *id);
       }
     }
     Ok(())
   }
 
   pub fn send_community_room_message<Response>(
-    &self,
+    &mut self,
     op: &UserOperation,
     response: &Response,
     community_id: CommunityId,
@@ -216,14 +266,14 @@ impl ChatServer {
             continue;
           }
         }
-        self.sendit(res_str, *id);
+        sendit(&mut self.sessions, res_str, *id);
       }
     }
     Ok(())
   }
 
   pub fn send_all_message<Response>(
-    &self,
+    &mut self,
     op: &UserOperation,
     response: &Response,
     websocket_id: Option<ConnectionId>,
@@ -232,19 +282,20 @@ impl ChatServer {
     Response: Serialize,
   {
     let res_str = &to_json_string(op, response)?;
-    for id in self.sessions.keys() {
+    let ids = self.sessions.keys().copied().collect::<Vec<ConnectionId>>();
+    for id in ids {
       if let Some(my_id) = websocket_id {
-        if *id == my_id {
+        if id == my_id {
           continue;
         }
       }
-      self.sendit(res_str, *id);
+      sendit(&mut self.sessions, res_str, id);
     }
     Ok(())
   }
 
   pub fn send_user_room_message<Response>(
-    &self,
+    &mut self,
     op: &UserOperation,
     response: &Response,
     recipient_id: UserId,
@@ -253,6 +304,12 @@ impl ChatServer {
   where
     Response: Serialize,
   {
+    if let Some(waiters) = self.user_room_waiters.remove(&recipient_id) {
+      for waiter in waiters {
+        let _ = waiter.send(());
+      }
+    }
+
     let res_str = &to_json_string(op, response)?;
     if let Some(sessions) = self.user_rooms.get(&recipient_id) {
       for id in sessions {
@@ -261,14 +318,14 @@ impl ChatServer {
             continue;
           }
         }
-        self.sendit(res_str, *id);
+        sendit(&mut self.sessions, res_str, *id);
       }
     }
     Ok(())
   }
 
   pub fn send_comment(
-    &self,
+    &mut self,
     user_operation: &UserOperation,
     comment: &CommentResponse,
     websocket_id: Option<ConnectionId>,
@@ -277,6 +334,23 @@ impl ChatServer {
     comment_reply_sent.comment.my_vote = None;
     comment_reply_sent.comment.user_id = None;
 
+    // Send it to the recipient(s) including the mentioned users
+    for recipient_id in &comment_reply_sent.recipient_ids {
+      self.send_user_room_message(
+        user_operation,
+        &comment_reply_sent,
+        *recipient_id,
+        websocket_id,
+      )?;
+    }
+
+    if self
+      .private_communities
+      .contains(&comment.comment.community_id)
+    {
+      return Ok(());
+    }
+
     let mut comment_post_sent = comment_reply_sent.clone();
     comment_post_sent.recipient_ids = Vec::new();
 
@@ -288,16 +362,6 @@ impl ChatServer {
       websocket_id,
     )?;
 
-    // Send it to the recipient(s) including the mentioned users
-    for recipient_id in &comment_reply_sent.recipient_ids {
-      self.send_user_room_message(
-        user_operation,
-        &comment_reply_sent,
-        *recipient_id,
-        websocket_id,
-      )?;
-    }
-
     // Send it to the community too
     self.send_community_room_message(user_operation, &comment_post_sent, 0, websocket_id)?;
     self.send_community_room_message(
@@ -311,17 +375,21 @@ impl ChatServer {
   }
 
   pub fn send_post(
-    &self,
+    &mut self,
     user_operation: &UserOperation,
     post: &PostResponse,
     websocket_id: Option<ConnectionId>,
   ) -> Result<(), LemmyError> {
     let community_id = post.post.community_id;
+    if self.private_communities.contains(&community_id) {
+      return Ok(());
+    }
 
     // Don't send my data with it
     let mut post_sent = post.clone();
     post_sent.post.my_vote = None;
     post_sent.post.user_id = None;
+    post_sent.post = post_sent.post.hide_creator();
 
     // Send it to /c/all and that community
     self.send_community_room_message(user_operation, &post_sent, 0, websocket_id)?;
@@ -333,10 +401,93 @@ impl ChatServer {
     Ok(())
   }
 
-  fn sendit(&self, message: &str, id: ConnectionId) {
-    if let Some(info) = self.sessions.get(&id) {
-      let _ = info.addr.do_send(WSMessage(message.to_owned()));
+  /// Tells the connections viewing the front page, or the community of a new post, how many new
+  /// posts they could load by refreshing.
+  pub fn send_new_posts_available(
+    &mut self,
+    community_id: CommunityId,
+    websocket_id: Option<ConnectionId>,
+  ) -> Result<(), LemmyError> {
+    if self.private_communities.contains(&community_id) {
+      return Ok(());
+    }
+
+    for room_id in &[0, community_id] {
+      let sessions = match self.community_rooms.get(room_id) {
+        Some(sessions) => sessions,
+        None => continue,
+      };
+      for id in sessions {
+        if Some(*id) == websocket_id {
+          continue;
+        }
+        let new_posts = self.new_post_counts.entry(*id).or_insert(0);
+        *new_posts += 1;
+        let response = NewPostsAvailableResponse {
+          community_id: *room_id,
+          new_posts: *new_posts,
+        };
+        let res_str = to_json_string(&UserOperation::NewPostsAvailable, &response)?;
+        sendit(&mut self.sessions, &res_str, *id);
+      }
+    }
+    Ok(())
+  }
+
+  /// Keeps what a protocol version 2 session was doing, so that it can be resumed.
+  pub(super) fn detach_session(&mut self, id: ConnectionId, event_log: EventLog) {
+    let in_room = |rooms: &HashMap<i32, HashSet<ConnectionId>>| {
+      rooms
+        .iter()
+        .find(|(_, sessions)| sessions.contains(&id))
+        .map(|(room_id, _)| *room_id)
+    };
+    let detached = DetachedSession {
+      user_id: in_room(&self.user_rooms),
+      community_id: in_room(&self.community_rooms),
+      post_id: in_room(&self.post_rooms),
+      new_posts: self.new_post_counts.get(&id).copied(),
+      event_log,
+      detached_at: Instant::now(),
+    };
+    self.detached_sessions.retain(|_, d| !d.is_expired());
+    self
+      .detached_sessions
+      .insert(detached.event_log.resume_token.to_owned(), detached);
+  }
+
+  /// Puts the new connection into the rooms of the dropped session, and returns the events which
+  /// the client missed. None if the session can't be resumed.
+  pub(super) fn resume_session(
+    &mut self,
+    id: ConnectionId,
+    resume: &ResumeSession,
+  ) -> Option<(EventLog, Vec<String>)> {
+    let detached = self.detached_sessions.remove(&resume.resume_token)?;
+    if detached.is_expired() {
+      return None;
+    }
+    let mut event_log = detached.event_log;
+    event_log.ack(resume.last_event_id);
+    let missed_events = event_log.events_after(resume.last_event_id)?;
+
+    if let Some(user_id) = detached.user_id {
+      self.user_rooms.entry(user_id).or_default().insert(id);
     }
+    if let Some(community_id) = detached.community_id {
+      self
+        .community_rooms
+        .entry(community_id)
+        .or_default()
+        .insert(id);
+    }
+    if let Some(post_id) = detached.post_id {
+      self.post_rooms.entry(post_id).or_default().insert(id);
+    }
+    if let Some(new_posts) = detached.new_posts {
+      self.new_post_counts.insert(id, new_posts);
+    }
+    Some((event_log, missed_events))
   }
 
   pub(super) fn parse_json_message(
@@ -348,13 +499,14 @@ impl ChatServer {
     let pool = self.pool.clone();
     let rate_limiter = self.rate_limiter.clone();
 
-    let ip: IPAddr = match self.sessions.get(&msg.id) {
-      Some(info) => info.ip.to_owned(),
-      None => "blank_ip".to_string(),
+    let (ip, fingerprint): (IPAddr, Option<String>) = match self.sessions.get(&msg.id) {
+      Some(info) => (info.ip.to_owned(), info.fingerprint.to_owned()),
+      None => ("blank_ip".to_string(), None),
     };
 
     let client = self.client.clone();
     let activity_queue = self.activity_queue.clone();
+    let response_cache = self.response_cache.clone();
     async move {
       let msg = msg;
       let json: Value = serde_json::from_str(&msg.msg)?;
@@ -364,26 +516,34 @@ impl ChatServer {
       })?;
 
       let user_operation: UserOperation = UserOperation::from_str(&op)?;
+      // Acknowledgements get no response
+      if let UserOperation::Ack = user_operation {
+        let ack: Ack = serde_json::from_str(data)?;
+        addr.do_send(AckEvents {
+          id: msg.id,
+          event_id: ack.event_id,
+        });
+        return Ok(String::new());
+      }
+      if !user_operation.allowed_when_read_only() {
+        check_not_read_only()?;
+      }
 
-      let context = LemmyContext {
-        pool,
-        chat_server: addr,
-        client,
-        activity_queue,
-      };
+      let context = LemmyContext::create(pool, addr, client, activity_queue, response_cache);
       let args = Args {
         context,
         rate_limiter,
         id: msg.id,
         ip,
+        fingerprint,
         op: user_operation.clone(),
         data,
       };
 
       match user_operation {
         // User ops
-        UserOperation::Login => do_user_operation::<Login>(args).await,
-        UserOperation::Register => do_user_operation::<Register>(args).await,
+        UserOperation::Login => do_sign_in_operation::<Login>(args, false).await,
+        UserOperation::Register => do_sign_in_operation::<Register>(args, true).await,
         UserOperation::GetCaptcha => do_user_operation::<GetCaptcha>(args).await,
         UserOperation::GetUserDetails => do_user_operation::<GetUserDetails>(args).await,
         UserOperation::GetReplies => do_user_operation::<GetReplies>(args).await,
@@ -396,9 +556,31 @@ impl ChatServer {
         UserOperation::MarkAllAsRead => do_user_operation::<MarkAllAsRead>(args).await,
         UserOperation::DeleteAccount => do_user_operation::<DeleteAccount>(args).await,
         UserOperation::PasswordReset => do_user_operation::<PasswordReset>(args).await,
-        UserOperation::PasswordChange => do_user_operation::<PasswordChange>(args).await,
+        UserOperation::PasswordChange => do_sign_in_operation::<PasswordChange>(args, false).await,
         UserOperation::UserJoin => do_user_operation::<UserJoin>(args).await,
         UserOperation::SaveUserSettings => do_user_operation::<SaveUserSettings>(args).await,
+        UserOperation::CreateSavedSearch => do_user_operation::<CreateSavedSearch>(args).await,
+        UserOperation::DeleteSavedSearch => do_user_operation::<DeleteSavedSearch>(args).await,
+        UserOperation::GetSavedSearches => do_user_operation::<GetSavedSearches>(args).await,
+        UserOperation::GetSavedSearchMatches => {
+          do_user_operation::<GetSavedSearchMatches>(args).await
+        }
+        UserOperation::MuteThread => do_user_operation::<MuteThread>(args).await,
+        UserOperation::GetThreadMutes => do_user_operation::<GetThreadMutes>(args).await,
+        UserOperation::GetPushPublicKey => do_user_operation::<GetPushPublicKey>(args).await,
+        UserOperation::SavePushSubscription => {
+          do_user_operation::<SavePushSubscription>(args).await
+        }
+        UserOperation::DeletePushSubscription => {
+          do_user_operation::<DeletePushSubscription>(args).await
+        }
+        UserOperation::GetNotifications => do_user_operation::<GetNotifications>(args).await,
+        UserOperation::IgnoreVoteInstance => do_user_operation::<IgnoreVoteInstance>(args).await,
+        UserOperation::GetIgnoredVoteInstances => {
+          do_user_operation::<GetIgnoredVoteInstances>(args).await
+        }
+        UserOperation::GetSignIns => do_user_operation::<GetSignIns>(args).await,
+        UserOperation::ReviewSignIn => do_user_operation::<ReviewSignIn>(args).await,
 
         // Private Message ops
         UserOperation::CreatePrivateMessage => {
@@ -420,14 +602,79 @@ impl ChatServer {
         UserOperation::GetSite => do_user_operation::<GetSite>(args).await,
         UserOperation::GetSiteConfig => do_user_operation::<GetSiteConfig>(args).await,
         UserOperation::SaveSiteConfig => do_user_operation::<SaveSiteConfig>(args).await,
+        UserOperation::ListSpamScores => do_user_operation::<ListSpamScores>(args).await,
+        UserOperation::GetInstanceStats => do_user_operation::<GetInstanceStats>(args).await,
+        UserOperation::SendTestEmail => do_user_operation::<SendTestEmail>(args).await,
+        UserOperation::GetEmailOutbox => do_user_operation::<GetEmailOutbox>(args).await,
+        UserOperation::ListCspReports => do_user_operation::<ListCspReports>(args).await,
+        UserOperation::RotateActorKeys => do_user_operation::<RotateActorKeys>(args).await,
+        UserOperation::ImpersonateUser => do_user_operation::<ImpersonateUser>(args).await,
+        UserOperation::GetImpersonationLog => do_user_operation::<GetImpersonationLog>(args).await,
+        UserOperation::GetDuplicateAccounts => {
+          do_user_operation::<GetDuplicateAccounts>(args).await
+        }
+        UserOperation::GetDefaultCommunities => {
+          do_user_operation::<GetDefaultCommunities>(args).await
+        }
+        UserOperation::SaveDefaultCommunities => {
+          do_user_operation::<SaveDefaultCommunities>(args).await
+        }
+        UserOperation::GetSiteBranding => do_user_operation::<GetSiteBranding>(args).await,
+        UserOperation::SaveSiteBranding => do_user_operation::<SaveSiteBranding>(args).await,
+        UserOperation::ListDomains => do_user_operation::<ListDomains>(args).await,
+        UserOperation::GetDomain => do_user_operation::<GetDomain>(args).await,
+        UserOperation::SetDomainPolicy => do_user_operation::<SetDomainPolicy>(args).await,
+        UserOperation::ListAnnouncements => do_user_operation::<ListAnnouncements>(args).await,
+        UserOperation::CreateAnnouncement => do_user_operation::<CreateAnnouncement>(args).await,
+        UserOperation::EditAnnouncement => do_user_operation::<EditAnnouncement>(args).await,
+        UserOperation::DeleteAnnouncement => do_user_operation::<DeleteAnnouncement>(args).await,
+        UserOperation::DismissAnnouncement => do_user_operation::<DismissAnnouncement>(args).await,
+        UserOperation::GetMaintenanceMode => do_user_operation::<GetMaintenanceMode>(args).await,
+        UserOperation::SaveMaintenanceMode => do_user_operation::<SaveMaintenanceMode>(args).await,
+        UserOperation::ListFeatureFlags => do_user_operation::<ListFeatureFlags>(args).await,
+        UserOperation::SaveFeatureFlag => do_user_operation::<SaveFeatureFlag>(args).await,
+        UserOperation::DeleteFeatureFlag => do_user_operation::<DeleteFeatureFlag>(args).await,
+        UserOperation::SetFeatureFlagUser => do_user_operation::<SetFeatureFlagUser>(args).await,
+        UserOperation::GetEnabledFeatures => do_user_operation::<GetEnabledFeatures>(args).await,
+        UserOperation::ListPluginHooks => do_user_operation::<ListPluginHooks>(args).await,
+        UserOperation::CreatePluginHook => do_user_operation::<CreatePluginHook>(args).await,
+        UserOperation::EditPluginHook => do_user_operation::<EditPluginHook>(args).await,
+        UserOperation::DeletePluginHook => do_user_operation::<DeletePluginHook>(args).await,
+        UserOperation::ListWasmPlugins => do_user_operation::<ListWasmPlugins>(args).await,
+        UserOperation::SetWasmPluginEnabled => {
+          do_user_operation::<SetWasmPluginEnabled>(args).await
+        }
+        UserOperation::ReloadWasmPlugins => do_user_operation::<ReloadWasmPlugins>(args).await,
+        UserOperation::GetRateLimitConfig => do_user_operation::<GetRateLimitConfig>(args).await,
+        UserOperation::SaveRateLimitConfig => do_user_operation::<SaveRateLimitConfig>(args).await,
+        // Needs the rate limiter and the IP, so it isn't an api operation
+        UserOperation::GetRateLimitAllowances => {
+          let allowances = args.rate_limiter.allowances(&args.ip).await;
+          to_json_string(
+            &user_operation,
+            &GetRateLimitAllowancesResponse { allowances },
+          )
+        }
+        // Only sent by the server, when new posts arrive
+        UserOperation::NewPostsAvailable | UserOperation::SessionStarted => {
+          Err(APIError::err("Unknown op type").into())
+        }
+        // Handled above
+        UserOperation::Ack => Ok(String::new()),
         UserOperation::Search => do_user_operation::<Search>(args).await,
         UserOperation::TransferCommunity => do_user_operation::<TransferCommunity>(args).await,
         UserOperation::TransferSite => do_user_operation::<TransferSite>(args).await,
         UserOperation::ListCategories => do_user_operation::<ListCategories>(args).await,
+        UserOperation::GetFederatedInstances => {
+          do_user_operation::<GetFederatedInstances>(args).await
+        }
 
         // Community ops
         UserOperation::GetCommunity => do_user_operation::<GetCommunity>(args).await,
         UserOperation::ListCommunities => do_user_operation::<ListCommunities>(args).await,
+        UserOperation::GetRecommendedCommunities => {
+          do_user_operation::<GetRecommendedCommunities>(args).await
+        }
         UserOperation::CreateCommunity => do_user_operation::<CreateCommunity>(args).await,
         UserOperation::EditCommunity => do_user_operation::<EditCommunity>(args).await,
         UserOperation::DeleteCommunity => do_user_operation::<DeleteCommunity>(args).await,
@@ -436,18 +683,84 @@ impl ChatServer {
         UserOperation::GetFollowedCommunities => {
           do_user_operation::<GetFollowedCommunities>(args).await
         }
+        UserOperation::GetCommunityJoinRequests => {
+          do_user_operation::<GetCommunityJoinRequests>(args).await
+        }
+        UserOperation::ReviewCommunityJoinRequest => {
+          do_user_operation::<ReviewCommunityJoinRequest>(args).await
+        }
+        UserOperation::GetCommunityMembers => do_user_operation::<GetCommunityMembers>(args).await,
+        UserOperation::GetModInbox => do_user_operation::<GetModInbox>(args).await,
+        UserOperation::GetModInboxCounts => do_user_operation::<GetModInboxCounts>(args).await,
+        UserOperation::CreateReport => do_user_operation::<CreateReport>(args).await,
+        UserOperation::ResolveReport => do_user_operation::<ResolveReport>(args).await,
+        UserOperation::ResolveVoteBurst => do_user_operation::<ResolveVoteBurst>(args).await,
+        UserOperation::GetVoteOrigins => do_user_operation::<GetVoteOrigins>(args).await,
+        UserOperation::RemoveCommunityMember => {
+          do_user_operation::<RemoveCommunityMember>(args).await
+        }
+        UserOperation::GetRemovalReasons => do_user_operation::<GetRemovalReasons>(args).await,
+        UserOperation::CreateRemovalReason => do_user_operation::<CreateRemovalReason>(args).await,
+        UserOperation::DeleteRemovalReason => do_user_operation::<DeleteRemovalReason>(args).await,
+        UserOperation::GetCommunityPostTags => {
+          do_user_operation::<GetCommunityPostTags>(args).await
+        }
+        UserOperation::CreateCommunityPostTag => {
+          do_user_operation::<CreateCommunityPostTag>(args).await
+        }
+        UserOperation::DeleteCommunityPostTag => {
+          do_user_operation::<DeleteCommunityPostTag>(args).await
+        }
+        UserOperation::SubscribeCommunityNotifications => {
+          do_user_operation::<SubscribeCommunityNotifications>(args).await
+        }
+        UserOperation::GetCommunityNotifications => {
+          do_user_operation::<GetCommunityNotifications>(args).await
+        }
+        UserOperation::GetCommunityNotificationPosts => {
+          do_user_operation::<GetCommunityNotificationPosts>(args).await
+        }
+        UserOperation::SubscribeCommunityDigest => {
+          do_user_operation::<SubscribeCommunityDigest>(args).await
+        }
+        UserOperation::GetCommunityDigests => do_user_operation::<GetCommunityDigests>(args).await,
         UserOperation::BanFromCommunity => do_user_operation::<BanFromCommunity>(args).await,
+        UserOperation::BulkRemoveFromCommunity => {
+          do_user_operation::<BulkRemoveFromCommunity>(args).await
+        }
+        UserOperation::BulkBanFromCommunity => {
+          do_user_operation::<BulkBanFromCommunity>(args).await
+        }
+        UserOperation::GetModBulkAction => do_user_operation::<GetModBulkAction>(args).await,
+        UserOperation::GetCommunityStats => do_user_operation::<GetCommunityStats>(args).await,
+        UserOperation::ListScheduledPosts => do_user_operation::<ListScheduledPosts>(args).await,
+        UserOperation::CreateScheduledPost => do_user_operation::<CreateScheduledPost>(args).await,
+        UserOperation::EditScheduledPost => do_user_operation::<EditScheduledPost>(args).await,
+        UserOperation::DeleteScheduledPost => do_user_operation::<DeleteScheduledPost>(args).await,
         UserOperation::AddModToCommunity => do_user_operation::<AddModToCommunity>(args).await,
 
         // Post ops
         UserOperation::CreatePost => do_user_operation::<CreatePost>(args).await,
-        UserOperation::GetPost => do_user_operation::<GetPost>(args).await,
+        UserOperation::GetPost => {
+          do_viewed_operation::<GetPost>(args, |d| (Viewed::Post(d.id), d.auth.to_owned())).await
+        }
         UserOperation::GetPosts => do_user_operation::<GetPosts>(args).await,
+        UserOperation::GetSimilarPosts => do_user_operation::<GetSimilarPosts>(args).await,
+        UserOperation::CheckPostTitle => do_user_operation::<CheckPostTitle>(args).await,
+        UserOperation::GetPostsByUrl => do_user_operation::<GetPostsByUrl>(args).await,
+        UserOperation::GetPostCrossposts => do_user_operation::<GetPostCrossposts>(args).await,
+        UserOperation::GetPostEditHistory => do_user_operation::<GetPostEditHistory>(args).await,
+        UserOperation::ListDomainPostApprovals => {
+          do_user_operation::<ListDomainPostApprovals>(args).await
+        }
+        UserOperation::ApproveDomainPost => do_user_operation::<ApproveDomainPost>(args).await,
         UserOperation::EditPost => do_user_operation::<EditPost>(args).await,
         UserOperation::DeletePost => do_user_operation::<DeletePost>(args).await,
         UserOperation::RemovePost => do_user_operation::<RemovePost>(args).await,
         UserOperation::LockPost => do_user_operation::<LockPost>(args).await,
+        UserOperation::ClosePostComments => do_user_operation::<ClosePostComments>(args).await,
         UserOperation::StickyPost => do_user_operation::<StickyPost>(args).await,
+        UserOperation::DistinguishPost => do_user_operation::<DistinguishPost>(args).await,
         UserOperation::CreatePostLike => do_user_operation::<CreatePostLike>(args).await,
         UserOperation::SavePost => do_user_operation::<SavePost>(args).await,
 
@@ -456,11 +769,31 @@ impl ChatServer {
         UserOperation::EditComment => do_user_operation::<EditComment>(args).await,
         UserOperation::DeleteComment => do_user_operation::<DeleteComment>(args).await,
         UserOperation::RemoveComment => do_user_operation::<RemoveComment>(args).await,
+        UserOperation::DistinguishComment => do_user_operation::<DistinguishComment>(args).await,
+        UserOperation::PinComment => do_user_operation::<PinComment>(args).await,
+        UserOperation::ApproveMentions => do_user_operation::<ApproveMentions>(args).await,
         UserOperation::MarkCommentAsRead => do_user_operation::<MarkCommentAsRead>(args).await,
         UserOperation::SaveComment => do_user_operation::<SaveComment>(args).await,
         UserOperation::GetComments => do_user_operation::<GetComments>(args).await,
+        UserOperation::GetCommentContext => {
+          do_viewed_operation::<GetCommentContext>(args, |d| {
+            (Viewed::Comment(d.comment_id), d.auth.to_owned())
+          })
+          .await
+        }
         UserOperation::CreateCommentLike => do_user_operation::<CreateCommentLike>(args).await,
       }
     }
   }
 }
+
+/// Protocol version 2 sessions get an event id with every message which isn't a response.
+fn sendit(sessions: &mut HashMap<ConnectionId, SessionInfo>, message: &str, id: ConnectionId) {
+  if let Some(info) = sessions.get_mut(&id) {
+    let message = match &mut info.event_log {
+      Some(event_log) => event_log.push(message),
+      None => message.to_owned(),
+    };
+    let _ = info.addr.do_send(WSMessage(message));
+  }
+}
@@ -1,26 +1,31 @@
 use crate::{
-  api::Perform,
+  api::{user::record_sign_in, Perform},
+  post_views::{count_view, Viewed},
   websocket::{
     chat_server::{ChatServer, SessionInfo},
     messages::*,
+    resume::EventLog,
     UserOperation,
   },
   LemmyContext,
 };
 use actix::{Actor, Context, Handler, ResponseFuture};
 use actix_web::web;
+use lemmy_api_structs::{site::SessionStartedResponse, user::LoginResponse};
 use lemmy_db::naive_now;
 use lemmy_rate_limit::RateLimit;
-use lemmy_utils::{ConnectionId, IPAddr, LemmyError};
+use lemmy_utils::{utils::loggable_ip, ConnectionId, IPAddr, LemmyError};
 use log::{error, info};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 pub(super) struct Args<'a> {
   pub(super) context: LemmyContext,
   pub(super) rate_limiter: RateLimit,
   pub(super) id: ConnectionId,
   pub(super) ip: IPAddr,
+  pub(super) fingerprint: Option<String>,
   pub(super) op: UserOperation,
   pub(super) data: &'a str,
 }
@@ -37,6 +42,7 @@ where
     ip,
     op,
     data,
+    ..
   } = args;
 
   let data = data.to_string();
@@ -54,10 +60,87 @@ where
     UserOperation::Register => rate_limiter.register().wrap(ip, fut).await,
     UserOperation::CreatePost => rate_limiter.post().wrap(ip, fut).await,
     UserOperation::CreateCommunity => rate_limiter.register().wrap(ip, fut).await,
+    UserOperation::CreateComment => rate_limiter.comment().wrap(ip, fut).await,
+    UserOperation::Search => rate_limiter.search().wrap(ip, fut).await,
     _ => rate_limiter.message().wrap(ip, fut).await,
   }
 }
 
+/// Like `do_user_operation`, but also keeps where the user signed up or logged in from, see
+/// `record_sign_in`.
+pub(super) async fn do_sign_in_operation<'a, 'b, Data>(
+  args: Args<'b>,
+  registration: bool,
+) -> Result<String, LemmyError>
+where
+  for<'de> Data: Deserialize<'de> + 'a,
+  Data: Perform<Response = LoginResponse>,
+{
+  let Args {
+    context,
+    rate_limiter,
+    id,
+    ip,
+    fingerprint,
+    op,
+    data,
+  } = args;
+
+  let data = data.to_string();
+  let client_ip = ip.to_owned();
+
+  let fut = async move {
+    let parsed_data: Data = serde_json::from_str(&data)?;
+    let context = web::Data::new(context);
+    let mut res = parsed_data.perform(&context, Some(id)).await?;
+    res.jwt = record_sign_in(&res.jwt, &client_ip, fingerprint, registration, &context).await?;
+    to_json_string(&op, &res)
+  };
+
+  if registration {
+    rate_limiter.register().wrap(ip, fut).await
+  } else {
+    rate_limiter.message().wrap(ip, fut).await
+  }
+}
+
+/// Like `do_user_operation`, but also counts the view of the post or comment, which `viewed` reads
+/// from the request together with its token, see `post_views`.
+pub(super) async fn do_viewed_operation<'a, 'b, Data>(
+  args: Args<'b>,
+  viewed: fn(&Data) -> (Viewed, Option<String>),
+) -> Result<String, LemmyError>
+where
+  for<'de> Data: Deserialize<'de> + 'a,
+  Data: Perform,
+{
+  let Args {
+    context,
+    rate_limiter,
+    id,
+    ip,
+    op,
+    data,
+    ..
+  } = args;
+
+  let data = data.to_string();
+  let client_ip = ip.to_owned();
+
+  let fut = async move {
+    let parsed_data: Data = serde_json::from_str(&data)?;
+    let pool = context.pool().to_owned();
+    let res = parsed_data
+      .perform(&web::Data::new(context), Some(id))
+      .await?;
+    let (viewed, auth) = viewed(&parsed_data);
+    count_view(viewed, auth.as_deref(), &client_ip, &pool).await;
+    to_json_string(&op, &res)
+  };
+
+  rate_limiter.message().wrap(ip, fut).await
+}
+
 /// Make actor from `ChatServer`
 impl Actor for ChatServer {
   /// We are going to use simple Context, we just need ability to communicate
@@ -74,13 +157,42 @@ impl Handler<Connect> for ChatServer {
   fn handle(&mut self, msg: Connect, _ctx: &mut Context<Self>) -> Self::Result {
     // register session with random id
     let id = self.rng.gen::<usize>();
-    info!("{} joined", &msg.ip);
+    info!("{} joined", loggable_ip(&msg.ip));
+
+    let event_log = if msg.v2 {
+      let resumed = msg
+        .resume
+        .as_ref()
+        .and_then(|resume| self.resume_session(id, resume));
+      let (mut event_log, missed_events) = match resumed {
+        Some((event_log, missed_events)) => (event_log, Some(missed_events)),
+        None => (EventLog::new(String::new()), None),
+      };
+      // A token can only be used once
+      event_log.resume_token = Uuid::new_v4().to_string();
+      let response = SessionStartedResponse {
+        resume_token: event_log.resume_token.to_owned(),
+        resumed: missed_events.is_some(),
+        last_event_id: event_log.last_event_id(),
+      };
+      if let Ok(res_str) = to_json_string(&UserOperation::SessionStarted, &response) {
+        let _ = msg.addr.do_send(WSMessage(res_str));
+      }
+      for event in missed_events.unwrap_or_default() {
+        let _ = msg.addr.do_send(WSMessage(event));
+      }
+      Some(event_log)
+    } else {
+      None
+    };
 
     self.sessions.insert(
       id,
       SessionInfo {
         addr: msg.addr,
         ip: msg.ip,
+        fingerprint: msg.fingerprint,
+        event_log,
       },
     );
 
@@ -94,7 +206,11 @@ impl Handler<Disconnect> for ChatServer {
 
   fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
     // Remove connections from sessions and all 3 scopes
-    if self.sessions.remove(&msg.id).is_some() {
+    if let Some(info) = self.sessions.remove(&msg.id) {
+      if let Some(event_log) = info.event_log {
+        self.detach_session(msg.id, event_log);
+      }
+
       for sessions in self.user_rooms.values_mut() {
         sessions.remove(&msg.id);
       }
@@ -106,6 +222,22 @@ impl Handler<Disconnect> for ChatServer {
       for sessions in self.community_rooms.values_mut() {
         sessions.remove(&msg.id);
       }
+
+      self.new_post_counts.remove(&msg.id);
+    }
+  }
+}
+
+impl Handler<AckEvents> for ChatServer {
+  type Result = ();
+
+  fn handle(&mut self, msg: AckEvents, _: &mut Context<Self>) {
+    let event_log = self
+      .sessions
+      .get_mut(&msg.id)
+      .and_then(|info| info.event_log.as_mut());
+    if let Some(event_log) = event_log {
+      event_log.ack(msg.event_id);
     }
   }
 }
@@ -164,6 +296,9 @@ where
   type Result = ();
 
   fn handle(&mut self, msg: SendCommunityRoomMessage<Response>, _: &mut Context<Self>) {
+    if msg.community_id != 0 {
+      self.response_cache.invalidate_community(msg.community_id);
+    }
     self
       .send_community_room_message(&msg.op, &msg.response, msg.community_id, msg.websocket_id)
       .ok();
@@ -174,7 +309,15 @@ impl Handler<SendPost> for ChatServer {
   type Result = ();
 
   fn handle(&mut self, msg: SendPost, _: &mut Context<Self>) {
+    self
+      .response_cache
+      .invalidate_community(msg.post.post.community_id);
     self.send_post(&msg.op, &msg.post, msg.websocket_id).ok();
+    if let UserOperation::CreatePost = msg.op {
+      self
+        .send_new_posts_available(msg.post.post.community_id, msg.websocket_id)
+        .ok();
+    }
   }
 }
 
@@ -182,6 +325,9 @@ impl Handler<SendComment> for ChatServer {
   type Result = ();
 
   fn handle(&mut self, msg: SendComment, _: &mut Context<Self>) {
+    self
+      .response_cache
+      .invalidate_community(msg.comment.comment.community_id);
     self
       .send_comment(&msg.op, &msg.comment, msg.websocket_id)
       .ok();
@@ -212,6 +358,18 @@ impl Handler<JoinPostRoom> for ChatServer {
   }
 }
 
+impl Handler<SetCommunityPrivate> for ChatServer {
+  type Result = ();
+
+  fn handle(&mut self, msg: SetCommunityPrivate, _: &mut Context<Self>) {
+    if msg.private {
+      self.private_communities.insert(msg.community_id);
+    } else {
+      self.private_communities.remove(&msg.community_id);
+    }
+  }
+}
+
 impl Handler<GetUsersOnline> for ChatServer {
   type Result = usize;
 
@@ -244,6 +402,28 @@ impl Handler<GetCommunityUsersOnline> for ChatServer {
   }
 }
 
+impl Handler<WaitForUserMessage> for ChatServer {
+  type Result = ();
+
+  fn handle(&mut self, msg: WaitForUserMessage, _: &mut Context<Self>) {
+    let waiters = self.user_room_waiters.entry(msg.user_id).or_default();
+    // Polls which timed out
+    waiters.retain(|w| !w.is_canceled());
+    waiters.push(msg.sender);
+  }
+}
+
+impl Handler<IsUserOnline> for ChatServer {
+  type Result = bool;
+
+  fn handle(&mut self, msg: IsUserOnline, _: &mut Context<Self>) -> Self::Result {
+    self
+      .user_rooms
+      .get(&msg.user_id)
+      .map_or(false, |sessions| !sessions.is_empty())
+  }
+}
+
 #[derive(Serialize)]
 struct WebsocketResponse<T> {
   op: String,
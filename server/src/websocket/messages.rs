@@ -1,5 +1,6 @@
 use crate::websocket::UserOperation;
 use actix::{prelude::*, Recipient};
+use futures::channel::oneshot;
 use lemmy_api_structs::{comment::CommentResponse, post::PostResponse};
 use lemmy_utils::{CommunityId, ConnectionId, IPAddr, PostId, UserId};
 use serde::{Deserialize, Serialize};
@@ -17,6 +18,17 @@ pub struct WSMessage(pub String);
 pub struct Connect {
   pub addr: Recipient<WSMessage>,
   pub ip: IPAddr,
+  pub fingerprint: Option<String>,
+  /// Protocol version 2 sessions get event ids, and can be resumed
+  pub v2: bool,
+  pub resume: Option<ResumeSession>,
+}
+
+#[derive(Deserialize)]
+pub struct ResumeSession {
+  pub resume_token: String,
+  /// The last event which the client got before the connection dropped
+  pub last_event_id: u64,
 }
 
 /// Session is disconnected
@@ -27,6 +39,14 @@ pub struct Disconnect {
   pub ip: IPAddr,
 }
 
+/// A protocol version 2 client got the events up to this one
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AckEvents {
+  pub id: ConnectionId,
+  pub event_id: u64,
+}
+
 /// The messages sent to websocket clients
 #[derive(Serialize, Deserialize, Message)]
 #[rtype(result = "Result<String, std::convert::Infallible>")]
@@ -100,6 +120,13 @@ pub struct JoinPostRoom {
   pub id: ConnectionId,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetCommunityPrivate {
+  pub community_id: CommunityId,
+  pub private: bool,
+}
+
 #[derive(Message)]
 #[rtype(usize)]
 pub struct GetUsersOnline;
@@ -116,6 +143,21 @@ pub struct GetCommunityUsersOnline {
   pub community_id: CommunityId,
 }
 
+/// The sender is resolved with the next message to the user room, for long polling.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WaitForUserMessage {
+  pub user_id: UserId,
+  pub sender: oneshot::Sender<()>,
+}
+
+/// A user is online if they have a websocket connection which joined their user room.
+#[derive(Message)]
+#[rtype(bool)]
+pub struct IsUserOnline {
+  pub user_id: UserId,
+}
+
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
 pub struct CaptchaItem {
@@ -1,6 +1,7 @@
 pub mod chat_server;
 pub mod handlers;
 pub mod messages;
+pub mod resume;
 
 #[derive(EnumString, ToString, Debug, Clone)]
 pub enum UserOperation {
@@ -9,7 +10,11 @@ pub enum UserOperation {
   GetCaptcha,
   CreateCommunity,
   CreatePost,
+  NewPostsAvailable,
+  SessionStarted,
+  Ack,
   ListCommunities,
+  GetRecommendedCommunities,
   ListCategories,
   GetPost,
   GetCommunity,
@@ -17,28 +22,69 @@ pub enum UserOperation {
   EditComment,
   DeleteComment,
   RemoveComment,
+  DistinguishComment,
+  PinComment,
+  ApproveMentions,
   MarkCommentAsRead,
   SaveComment,
   CreateCommentLike,
   GetPosts,
+  GetSimilarPosts,
+  CheckPostTitle,
+  GetPostsByUrl,
+  GetPostCrossposts,
+  GetPostEditHistory,
+  ListDomainPostApprovals,
+  ApproveDomainPost,
   CreatePostLike,
   EditPost,
   DeletePost,
   RemovePost,
   LockPost,
+  ClosePostComments,
   StickyPost,
+  DistinguishPost,
   SavePost,
   EditCommunity,
   DeleteCommunity,
   RemoveCommunity,
   FollowCommunity,
   GetFollowedCommunities,
+  GetCommunityJoinRequests,
+  ReviewCommunityJoinRequest,
+  GetCommunityMembers,
+  GetModInbox,
+  GetModInboxCounts,
+  CreateReport,
+  ResolveReport,
+  ResolveVoteBurst,
+  GetVoteOrigins,
+  RemoveCommunityMember,
+  GetRemovalReasons,
+  CreateRemovalReason,
+  DeleteRemovalReason,
+  GetCommunityPostTags,
+  CreateCommunityPostTag,
+  DeleteCommunityPostTag,
+  SubscribeCommunityNotifications,
+  GetCommunityNotifications,
+  GetCommunityNotificationPosts,
+  SubscribeCommunityDigest,
+  GetCommunityDigests,
   GetUserDetails,
   GetReplies,
   GetUserMentions,
   MarkUserMentionAsRead,
   GetModlog,
   BanFromCommunity,
+  BulkRemoveFromCommunity,
+  BulkBanFromCommunity,
+  GetModBulkAction,
+  GetCommunityStats,
+  ListScheduledPosts,
+  CreateScheduledPost,
+  EditScheduledPost,
+  DeleteScheduledPost,
   AddModToCommunity,
   CreateSite,
   EditSite,
@@ -60,6 +106,138 @@ pub enum UserOperation {
   GetPrivateMessages,
   UserJoin,
   GetComments,
+  GetCommentContext,
   GetSiteConfig,
   SaveSiteConfig,
+  ListSpamScores,
+  GetInstanceStats,
+  SendTestEmail,
+  GetEmailOutbox,
+  ListCspReports,
+  RotateActorKeys,
+  ImpersonateUser,
+  GetImpersonationLog,
+  GetDuplicateAccounts,
+  GetFederatedInstances,
+  GetDefaultCommunities,
+  SaveDefaultCommunities,
+  GetSiteBranding,
+  SaveSiteBranding,
+  ListDomains,
+  GetDomain,
+  SetDomainPolicy,
+  ListAnnouncements,
+  CreateAnnouncement,
+  EditAnnouncement,
+  DeleteAnnouncement,
+  DismissAnnouncement,
+  GetMaintenanceMode,
+  SaveMaintenanceMode,
+  ListFeatureFlags,
+  SaveFeatureFlag,
+  DeleteFeatureFlag,
+  SetFeatureFlagUser,
+  GetEnabledFeatures,
+  ListPluginHooks,
+  CreatePluginHook,
+  EditPluginHook,
+  DeletePluginHook,
+  ListWasmPlugins,
+  SetWasmPluginEnabled,
+  ReloadWasmPlugins,
+  GetRateLimitConfig,
+  SaveRateLimitConfig,
+  GetRateLimitAllowances,
+  CreateSavedSearch,
+  DeleteSavedSearch,
+  GetSavedSearches,
+  GetSavedSearchMatches,
+  MuteThread,
+  GetThreadMutes,
+  GetPushPublicKey,
+  SavePushSubscription,
+  DeletePushSubscription,
+  GetNotifications,
+  IgnoreVoteInstance,
+  GetIgnoredVoteInstances,
+  GetSignIns,
+  ReviewSignIn,
+}
+
+impl UserOperation {
+  /// Operations which keep working while the site is read only, because they don't write to the
+  /// database. Login is needed so that admins can turn read only mode off again.
+  pub fn allowed_when_read_only(&self) -> bool {
+    use UserOperation::*;
+    matches!(
+      self,
+      Login
+        | Ack
+        | GetCaptcha
+        | ListCommunities
+        | GetRecommendedCommunities
+        | ListCategories
+        | GetPost
+        | GetCommunity
+        | GetPosts
+        | GetSimilarPosts
+        | CheckPostTitle
+        | GetPostsByUrl
+        | GetPostCrossposts
+        | GetPostEditHistory
+        | ListDomainPostApprovals
+        | GetFollowedCommunities
+        | GetCommunityJoinRequests
+        | GetCommunityMembers
+        | GetModInbox
+        | GetModInboxCounts
+        | GetVoteOrigins
+        | GetRemovalReasons
+        | GetCommunityPostTags
+        | GetCommunityNotifications
+        | GetCommunityNotificationPosts
+        | GetCommunityDigests
+        | GetUserDetails
+        | GetReplies
+        | GetUserMentions
+        | GetModlog
+        | GetModBulkAction
+        | GetCommunityStats
+        | ListScheduledPosts
+        | GetSite
+        | Search
+        | GetPrivateMessages
+        | UserJoin
+        | GetComments
+        | GetCommentContext
+        | GetSiteConfig
+        | ListSpamScores
+        | GetInstanceStats
+        | GetEmailOutbox
+        | ListCspReports
+        | GetImpersonationLog
+        | GetDuplicateAccounts
+        | GetFederatedInstances
+        | GetDefaultCommunities
+        | GetSiteBranding
+        | ListDomains
+        | GetDomain
+        | ListAnnouncements
+        | GetMaintenanceMode
+        | ListFeatureFlags
+        | GetEnabledFeatures
+        | ListPluginHooks
+        | ListWasmPlugins
+        | SaveMaintenanceMode
+        | GetRateLimitConfig
+        | GetRateLimitAllowances
+        | GetSavedSearches
+        | GetSavedSearchMatches
+        | GetThreadMutes
+        | GetPushPublicKey
+        | GetNotifications
+        | GetSignIns
+        | GetIgnoredVoteInstances
+    )
+  }
 }
@@ -0,0 +1,129 @@
+//! Protocol version 2 of the websocket. Events which the server pushes, like new comments in a
+//! post room, get ids counting up from 1, and are kept until the client acknowledges them. When
+//! the connection drops, the session is kept for a while, so that a client which reconnects with
+//! its resume token gets the events it missed, and is back in the same rooms.
+use lemmy_utils::{CommunityId, PostId, UserId};
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+/// Older events are dropped, and a session which missed them can't be resumed.
+const MAX_UNACKED_EVENTS: usize = 500;
+/// How long a dropped session can be resumed.
+pub const RESUME_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+pub struct EventLog {
+  pub resume_token: String,
+  last_event_id: u64,
+  /// Sent but not acknowledged, the oldest first
+  unacked: VecDeque<(u64, String)>,
+  /// Events up to this id were dropped before they were acknowledged
+  dropped_up_to: u64,
+}
+
+impl EventLog {
+  pub fn new(resume_token: String) -> Self {
+    EventLog {
+      resume_token,
+      last_event_id: 0,
+      unacked: VecDeque::new(),
+      dropped_up_to: 0,
+    }
+  }
+
+  pub fn last_event_id(&self) -> u64 {
+    self.last_event_id
+  }
+
+  /// Gives the message the next event id, and keeps it until it's acknowledged.
+  pub fn push(&mut self, message: &str) -> String {
+    self.last_event_id += 1;
+    let event = with_event_id(message, self.last_event_id);
+    self
+      .unacked
+      .push_back((self.last_event_id, event.to_owned()));
+    if self.unacked.len() > MAX_UNACKED_EVENTS {
+      if let Some((id, _)) = self.unacked.pop_front() {
+        self.dropped_up_to = id;
+      }
+    }
+    event
+  }
+
+  /// Acknowledges the event and all the ones before it.
+  pub fn ack(&mut self, event_id: u64) {
+    while self
+      .unacked
+      .front()
+      .map_or(false, |(id, _)| *id <= event_id)
+    {
+      self.unacked.pop_front();
+    }
+  }
+
+  /// The events after the given one, or None if some of them were dropped.
+  pub fn events_after(&self, event_id: u64) -> Option<Vec<String>> {
+    if event_id < self.dropped_up_to || event_id > self.last_event_id {
+      return None;
+    }
+    Some(
+      self
+        .unacked
+        .iter()
+        .filter(|(id, _)| *id > event_id)
+        .map(|(_, event)| event.to_owned())
+        .collect(),
+    )
+  }
+}
+
+/// A dropped session, with the rooms it was in.
+pub struct DetachedSession {
+  pub event_log: EventLog,
+  pub user_id: Option<UserId>,
+  pub community_id: Option<CommunityId>,
+  pub post_id: Option<PostId>,
+  pub new_posts: Option<i64>,
+  pub detached_at: Instant,
+}
+
+impl DetachedSession {
+  pub fn is_expired(&self) -> bool {
+    self.detached_at.elapsed() > RESUME_WINDOW
+  }
+}
+
+/// Messages are json objects from to_json_string, so the id is put first into the object.
+fn with_event_id(message: &str, event_id: u64) -> String {
+  format!("{{\"id\":{},{}", event_id, &message[1..])
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::websocket::resume::{EventLog, MAX_UNACKED_EVENTS};
+
+  #[test]
+  fn test_event_log() {
+    let mut log = EventLog::new("token".into());
+    let first = log.push(r#"{"op":"CreateComment","data":{}}"#);
+    assert_eq!(r#"{"id":1,"op":"CreateComment","data":{}}"#, first);
+    log.push(r#"{"op":"EditComment","data":{}}"#);
+    log.push(r#"{"op":"DeleteComment","data":{}}"#);
+
+    assert_eq!(2, log.events_after(1).unwrap().len());
+    log.ack(2);
+    assert_eq!(
+      vec![r#"{"id":3,"op":"DeleteComment","data":{}}"#.to_string()],
+      log.events_after(0).unwrap()
+    );
+    assert_eq!(Some(vec![]), log.events_after(3));
+    assert_eq!(None, log.events_after(4));
+
+    for _ in 0..MAX_UNACKED_EVENTS + 1 {
+      log.push(r#"{"op":"CreatePost","data":{}}"#);
+    }
+    assert_eq!(None, log.events_after(3));
+    assert_eq!(MAX_UNACKED_EVENTS - 1, log.events_after(5).unwrap().len());
+  }
+}